@@ -32,5 +32,14 @@ fn main() {
 
     println!("cargo:rustc-link-lib=pam");
 
+    // the printf-like function for I/O plugins is variadic, which Rust cannot define
+    #[cfg(feature = "io-plugins")]
+    {
+        cc::Build::new()
+            .file("src/plugin/sudo_printf.c")
+            .compile("sudo_printf");
+        println!("cargo:rerun-if-changed=src/plugin/sudo_printf.c");
+    }
+
     println!("cargo:rustc-env=DEFAULT_PATH=\"/bin/:/usr/bin/:/usr/local/bin:/sbin/:/usr/sbin\"")
 }
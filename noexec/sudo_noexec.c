@@ -0,0 +1,120 @@
+/*
+ * sudo_noexec.so: keeps a command that sudo-rs runs with NOEXEC from running
+ * other programs. sudo-rs preloads it into the command (LD_PRELOAD, or
+ * DYLD_INSERT_LIBRARIES on macOS), from the path in `Path noexec` of
+ * /etc/sudo.conf.
+ *
+ * On Linux, a seccomp filter that makes execve and execveat fail with EACCES
+ * is installed when the library is loaded, which also covers system calls
+ * that are made directly. Where seccomp is not available, the exec family of
+ * functions (and those that use them) are replaced by versions that fail with
+ * EACCES instead.
+ *
+ * Statically linked commands do not load the library, and cannot be
+ * restricted this way.
+ *
+ * Build with: cc -shared -fPIC -O2 -o sudo_noexec.so sudo_noexec.c
+ */
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <spawn.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <unistd.h>
+
+#if defined(__linux__)
+#include <linux/audit.h>
+#include <linux/filter.h>
+#include <linux/seccomp.h>
+#include <stddef.h>
+#include <sys/prctl.h>
+#include <sys/syscall.h>
+
+#if defined(__x86_64__)
+#define NOEXEC_AUDIT_ARCH AUDIT_ARCH_X86_64
+#elif defined(__aarch64__)
+#define NOEXEC_AUDIT_ARCH AUDIT_ARCH_AARCH64
+#elif defined(__i386__)
+#define NOEXEC_AUDIT_ARCH AUDIT_ARCH_I386
+#endif
+
+#if defined(NOEXEC_AUDIT_ARCH) && defined(SECCOMP_MODE_FILTER)
+/* Make execve and execveat fail with EACCES; any system call made using a
+ * different architecture (or ABI) than our own kills the process. */
+__attribute__((constructor)) static void noexec_seccomp(void)
+{
+    struct sock_filter filter[] = {
+        BPF_STMT(BPF_LD | BPF_W | BPF_ABS, offsetof(struct seccomp_data, arch)),
+        BPF_JUMP(BPF_JMP | BPF_JEQ | BPF_K, NOEXEC_AUDIT_ARCH, 1, 0),
+        BPF_STMT(BPF_RET | BPF_K, SECCOMP_RET_KILL),
+        BPF_STMT(BPF_LD | BPF_W | BPF_ABS, offsetof(struct seccomp_data, nr)),
+#if defined(__x86_64__)
+        BPF_JUMP(BPF_JMP | BPF_JGE | BPF_K, 0x40000000, 0, 1),
+        BPF_STMT(BPF_RET | BPF_K, SECCOMP_RET_KILL),
+#endif
+        BPF_JUMP(BPF_JMP | BPF_JEQ | BPF_K, __NR_execve, 2, 0),
+#if defined(__NR_execveat)
+        BPF_JUMP(BPF_JMP | BPF_JEQ | BPF_K, __NR_execveat, 1, 0),
+#else
+        BPF_JUMP(BPF_JMP | BPF_JEQ | BPF_K, __NR_execve, 1, 0),
+#endif
+        BPF_STMT(BPF_RET | BPF_K, SECCOMP_RET_ALLOW),
+        BPF_STMT(BPF_RET | BPF_K, SECCOMP_RET_ERRNO | (EACCES & SECCOMP_RET_DATA)),
+    };
+    struct sock_fprog program = {
+        .len = sizeof(filter) / sizeof(filter[0]),
+        .filter = filter,
+    };
+
+    /* if this fails, the replaced functions below still apply */
+    if (prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) == 0)
+        prctl(PR_SET_SECCOMP, SECCOMP_MODE_FILTER, &program);
+}
+#endif
+#endif
+
+#define DENY(ret)       \
+    do {                \
+        errno = EACCES; \
+        return ret;     \
+    } while (0)
+
+int execl(const char *path, const char *arg, ...) { DENY(-1); }
+int execle(const char *path, const char *arg, ...) { DENY(-1); }
+int execlp(const char *file, const char *arg, ...) { DENY(-1); }
+int execv(const char *path, char *const argv[]) { DENY(-1); }
+int execve(const char *path, char *const argv[], char *const envp[]) { DENY(-1); }
+int execvp(const char *file, char *const argv[]) { DENY(-1); }
+int fexecve(int fd, char *const argv[], char *const envp[]) { DENY(-1); }
+
+#if defined(__GLIBC__) || defined(__FreeBSD__) || defined(__OpenBSD__)
+int execvpe(const char *file, char *const argv[], char *const envp[]) { DENY(-1); }
+#endif
+
+#if defined(__GLIBC__) && (__GLIBC__ > 2 || (__GLIBC__ == 2 && __GLIBC_MINOR__ >= 34))
+int execveat(int fd, const char *path, char *const argv[], char *const envp[], int flags)
+{
+    DENY(-1);
+}
+#endif
+
+#if defined(__APPLE__) || defined(__FreeBSD__)
+int execvP(const char *file, const char *search_path, char *const argv[]) { DENY(-1); }
+#endif
+
+int posix_spawn(pid_t *pid, const char *path, const posix_spawn_file_actions_t *file_actions,
+                const posix_spawnattr_t *attrp, char *const argv[], char *const envp[])
+{
+    return EACCES;
+}
+
+int posix_spawnp(pid_t *pid, const char *file, const posix_spawn_file_actions_t *file_actions,
+                 const posix_spawnattr_t *attrp, char *const argv[], char *const envp[])
+{
+    return EACCES;
+}
+
+int system(const char *command) { DENY(-1); }
+FILE *popen(const char *command, const char *type) { DENY(NULL); }
@@ -0,0 +1,323 @@
+//! Completion scripts for bash, zsh and fish, which are printed by the hidden `--completions`
+//! option. They are generated from the table of options, so that they match the options that
+//! sudo accepts.
+//!
+//! The arguments of `-u` and `-g` are completed with the users and groups of the system, and a
+//! command with the commands that `sudo -l` allows, or with any command if all of them are.
+
+use super::{Argument, SudoOption, SudoOptions};
+
+/// Print the absolute paths of the commands that `sudo -n -l` lists, or nothing if the invoking
+/// user may run any command (or none at all).
+const ALLOWED_COMMANDS: &str = r#"sudo -n -l 2>/dev/null | awk '
+    /^[[:space:]]/ {
+        sub(/^[[:space:]]*(\([^)]*\)[[:space:]]*)?/, "")
+        n = split($0, commands, /,[[:space:]]*/)
+        for (i = 1; i <= n; i++) {
+            split(commands[i], words, /[[:space:]]+/)
+            for (j = 1; words[j] ~ /:$/; j++) {}
+            if (words[j] == "ALL") all = 1
+            else if (words[j] ~ /^\//) found[words[j]] = 1
+        }
+    }
+    END { if (!all) for (command in found) print command }'"#;
+
+/// The completion script for `shell`.
+pub fn generate(shell: &str) -> Result<String, String> {
+    match shell {
+        "bash" => Ok(bash()),
+        "zsh" => Ok(zsh()),
+        "fish" => Ok(fish()),
+        _ => Err(format!(
+            "no completions for shell '{shell}', only for bash, zsh and fish"
+        )),
+    }
+}
+
+/// The options that are shown in completions.
+fn visible_options() -> impl Iterator<Item = &'static SudoOption> {
+    SudoOptions::SUDO_OPTIONS
+        .iter()
+        .filter(|option| !option.help.is_empty())
+}
+
+/// The ways of writing an option, e.g. `-u` and `--user`.
+fn spellings(option: &SudoOption) -> Vec<String> {
+    option
+        .short
+        .map(|short| format!("-{short}"))
+        .into_iter()
+        .chain([format!("--{}", option.long)])
+        .collect()
+}
+
+/// What the argument of an option is, to complete it with.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Value {
+    User,
+    Group,
+    Directory,
+    Host,
+    Other,
+}
+
+fn value(option: &SudoOption) -> Value {
+    match option.long {
+        "user" | "other-user" => Value::User,
+        "group" => Value::Group,
+        "chdir" | "chroot" => Value::Directory,
+        "host" => Value::Host,
+        _ => Value::Other,
+    }
+}
+
+/// The options that take their argument as the next argument.
+fn with_argument() -> impl Iterator<Item = &'static SudoOption> {
+    visible_options().filter(|option| option.argument == Argument::Required)
+}
+
+fn bash() -> String {
+    let options = visible_options()
+        .flat_map(spellings)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let arguments = [
+        (Value::User, r#"COMPREPLY=($(compgen -u -- "$cur"))"#),
+        (Value::Group, r#"COMPREPLY=($(compgen -g -- "$cur"))"#),
+        (Value::Directory, r#"COMPREPLY=($(compgen -d -- "$cur"))"#),
+        (
+            Value::Host,
+            r#"COMPREPLY=($(compgen -A hostname -- "$cur"))"#,
+        ),
+        (Value::Other, ":"),
+    ]
+    .iter()
+    .filter_map(|(kind, completion)| {
+        let patterns = with_argument()
+            .filter(|option| value(option) == *kind)
+            .flat_map(spellings)
+            .collect::<Vec<_>>();
+        (!patterns.is_empty())
+            .then(|| format!("        {}) {completion}; return ;;\n", patterns.join("|")))
+    })
+    .collect::<String>();
+    let skipped = with_argument()
+        .flat_map(spellings)
+        .collect::<Vec<_>>()
+        .join("|");
+
+    format!(
+        r#"# bash completion for sudo, generated by sudo-rs
+
+_sudo_rs_allowed_commands() {{
+    {ALLOWED_COMMANDS}
+}}
+
+_sudo_rs() {{
+    local cur=${{COMP_WORDS[COMP_CWORD]}} prev=${{COMP_WORDS[COMP_CWORD-1]}}
+    case $prev in
+{arguments}    esac
+
+    # once the command has started, complete it like that command
+    local i
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case ${{COMP_WORDS[i]}} in
+            {skipped}) ((i++)) ;;
+            -*|*=*) ;;
+            *)
+                if declare -F _command_offset >/dev/null; then
+                    _command_offset $i
+                else
+                    COMPREPLY=($(compgen -f -- "$cur"))
+                fi
+                return
+                ;;
+        esac
+    done
+
+    if [[ $cur == -* ]]; then
+        COMPREPLY=($(compgen -W "{options}" -- "$cur"))
+        return
+    fi
+
+    local allowed
+    allowed=$(_sudo_rs_allowed_commands)
+    if [[ -n $allowed ]]; then
+        COMPREPLY=($(compgen -W "$allowed" -- "$cur"))
+    else
+        COMPREPLY=($(compgen -c -- "$cur"))
+    fi
+}}
+
+complete -F _sudo_rs sudo
+"#
+    )
+}
+
+/// The action of zsh's `_arguments` that completes the argument of an option.
+fn zsh_action(option: &SudoOption) -> &'static str {
+    match value(option) {
+        Value::User => ":user:_users",
+        Value::Group => ":group:_groups",
+        Value::Directory => ":directory:_directories",
+        Value::Host => ":host:_hosts",
+        Value::Other if option.argument == Argument::Optional => "::variables: ",
+        Value::Other => ":value: ",
+    }
+}
+
+fn zsh() -> String {
+    let specs = visible_options()
+        .map(|option| {
+            let names = spellings(option);
+            let exclusion = format!("({})", names.join(" "));
+            let help = option.help.replace('\'', "'\\''");
+            names
+                .iter()
+                .map(|name| {
+                    let (suffix, action) = match option.argument {
+                        Argument::No => ("", ""),
+                        Argument::Required if name.starts_with("--") => ("=", zsh_action(option)),
+                        Argument::Required => ("+", zsh_action(option)),
+                        Argument::Optional if name.starts_with("--") => ("=-", zsh_action(option)),
+                        Argument::Optional => ("", ""),
+                    };
+                    format!("        '{exclusion}{name}{suffix}[{help}]{action}' \\\n")
+                })
+                .collect::<String>()
+        })
+        .collect::<String>();
+
+    format!(
+        r#"#compdef sudo
+# zsh completion for sudo, generated by sudo-rs
+
+_sudo_rs_commands() {{
+    local -a allowed
+    allowed=(${{(f)"$({ALLOWED_COMMANDS})"}})
+    if (( $#allowed )); then
+        _describe -t commands 'allowed command' allowed
+    else
+        _command_names -e
+    fi
+}}
+
+_sudo_rs() {{
+    _arguments -s -S \
+{specs}        '(-)1:command:_sudo_rs_commands' \
+        '*::arguments:_normal'
+}}
+
+_sudo_rs "$@"
+"#
+    )
+}
+
+fn fish() -> String {
+    let mut script = String::from("# fish completion for sudo, generated by sudo-rs\n\n");
+    script.push_str(&format!(
+        "function __sudo_rs_allowed_commands\n    {ALLOWED_COMMANDS}\nend\n\n"
+    ));
+
+    let skipped = with_argument()
+        .flat_map(spellings)
+        .map(|name| format!("'{name}'"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    script.push_str(&format!(
+        r#"# the arguments of the command line that come before the command itself
+function __sudo_rs_before_command
+    set -l tokens (commandline -opc)
+    set -l count 1
+    set -e tokens[1]
+    while set -q tokens[1]
+        switch $tokens[1]
+            case {skipped}
+                set count (math $count + 2)
+                set -e tokens[1..2]
+            case '-*' '*=*'
+                set count (math $count + 1)
+                set -e tokens[1]
+            case '*'
+                break
+        end
+    end
+    echo $count
+    set -q tokens[1]
+end
+
+function __sudo_rs_complete_command
+    # the argument of an option is completed by the option
+    switch (commandline -opc)[-1]
+        case {skipped}
+            return
+    end
+    set -l skip (__sudo_rs_before_command)
+    and begin
+        __fish_complete_subcommand --fcs-skip=$skip
+        return
+    end
+    set -l allowed (__sudo_rs_allowed_commands)
+    if set -q allowed[1]
+        printf '%s\n' $allowed
+    else
+        __fish_complete_command
+    end
+end
+
+complete -c sudo -f -a '(__sudo_rs_complete_command)'
+"#
+    ));
+
+    for option in visible_options() {
+        let mut line = String::from("complete -c sudo");
+        if let Some(short) = option.short {
+            line.push_str(&format!(" -s {short}"));
+        }
+        line.push_str(&format!(" -l {}", option.long));
+        let arguments = match value(option) {
+            Value::User => " -x -a '(__fish_complete_users)'",
+            Value::Group => " -x -a '(__fish_complete_groups)'",
+            Value::Directory => " -x -a '(__fish_complete_directories)'",
+            Value::Host => " -x -a '(__fish_print_hostnames)'",
+            Value::Other if option.argument == Argument::Required => " -x",
+            Value::Other => "",
+        };
+        line.push_str(arguments);
+        line.push_str(&format!(
+            " -n 'not __sudo_rs_before_command >/dev/null' -d '{}'\n",
+            option.help.replace('\'', "\\'")
+        ));
+        script.push_str(&line);
+    }
+
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::generate;
+
+    #[test]
+    fn covers_the_options() {
+        for shell in ["bash", "zsh", "fish"] {
+            let script = generate(shell).unwrap();
+            assert!(script.contains("preserve-env"), "{shell}");
+            assert!(script.contains("sudo -n -l"), "{shell}");
+            // the option itself is hidden
+            assert!(!script.contains("completions"), "{shell}");
+        }
+
+        assert!(generate("bash")
+            .unwrap()
+            .contains("-U|--other-user|-u|--user)"));
+        assert!(generate("zsh")
+            .unwrap()
+            .contains("'(-u --user)-u+[run command as specified user]:user:_users'"));
+        assert!(generate("fish")
+            .unwrap()
+            .contains("complete -c sudo -s g -l group -x -a '(__fish_complete_groups)'"));
+
+        assert!(generate("tcsh").is_err());
+    }
+}
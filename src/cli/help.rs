@@ -1,25 +1,44 @@
 pub const USAGE_MSG: &str = "\
-usage: sudo [-u user] [-g group] [-D directory] [-knS] [-i | -s] <command>
+usage: sudo [-u user] [-g group] [-D directory] [-p prompt] [-AEHkNnPS] [-i | -s] <command>
+       sudo -l [-U user] [-u user] [--why] [command]
        sudo -h | -K | -k | -V";
 
 const DESCRIPTOR: &str = "sudo - run commands as another user";
 
 const HELP_MSG: &str = "Options:
+  -A, --askpass                 use a helper program for password prompting
+      --check, --dry-run        report whether the command would be allowed, without running it
   -D, --chdir=directory         change the working directory before running command
+      --doctor                  check the installation of sudo for common problems
+  -E, --preserve-env            preserve user environment when running command
+      --preserve-env=list       preserve specific environment variables
   -g, --group=group             run command as the specified group name or ID
+  -H, --set-home                set HOME variable to target user's home dir
   -h, --help                    display help message and exit
   -i, --login                   run login shell as the target user; a command may also be
                                 specified
-  -K, --remove-timestamp        remove timestamp file completely
+      --json                    display the version information with --version, or
+                                report errors, as JSON
+  -K, --remove-timestamp        remove timestamp file completely, of another user with -u
   -k, --reset-timestamp         invalidate timestamp file
+  -l, --list                    list user's privileges or check a specific command; use twice
                                 for longer format
+  -N, --no-update               do not update the user's timestamp
   -n, --non-interactive         non-interactive mode, no prompts are used
+  -P, --preserve-groups         preserve group vector instead of setting to target's
+  -p, --prompt=prompt           use the specified password prompt
   -S, --stdin                   read password from standard input
   -s, --shell                   run shell as the target user; a command may also be specified
+      --stats                   report the time and memory that the command used
+  -U, --other-user=user         in list mode, display privileges for user
   -u, --user=user               run command (or edit file) as specified user name or ID
   -v, --validate                update user's timestamp without running a command
   -V, --version                 display version information and exit
-  --                            stop processing command line arguments";
+      --why                     with -l and a command, explain which rules and Defaults
+                                decide on the command
+  --                            stop processing command line arguments
+
+Long options may be abbreviated, as long as the abbreviation is unambiguous.";
 
 pub fn long_help_message() -> String {
     format!("{DESCRIPTOR}\n{USAGE_MSG}\n{HELP_MSG}")
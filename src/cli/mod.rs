@@ -2,6 +2,7 @@
 
 use std::path::PathBuf;
 
+pub mod completions;
 pub mod help;
 
 #[cfg(test)]
@@ -17,29 +18,45 @@ pub enum SudoAction {
     ResetTimestamp,
     Run(Vec<String>),
     List(Vec<String>),
+    Check(Vec<String>),
     Edit(Vec<PathBuf>),
+    /// Print the completion script for a shell.
+    Completions(String),
+    /// Check the installation of sudo for common problems.
+    Doctor,
 }
 
 #[derive(Debug, Default, PartialEq, Clone)]
 pub struct SudoOptions {
+    pub askpass: bool,
     pub background: bool,
     pub chroot: Option<PathBuf>,
     pub directory: Option<PathBuf>,
     pub group: Option<String>,
     pub host: Option<String>,
+    pub json: bool,
     pub login: bool,
+    pub no_update: bool,
     pub non_interactive: bool,
     pub other_user: Option<String>,
     pub preserve_env: Vec<String>,
+    pub preserve_env_all: bool,
     pub preserve_groups: bool,
+    pub prompt: Option<String>,
+    pub set_home: bool,
     pub shell: bool,
+    pub stats: bool,
     pub stdin: bool,
     pub user: Option<String>,
+    pub why: bool,
     // additional environment
     pub env_var_list: Vec<(String, String)>,
     // resulting action enum
     pub action: SudoAction,
     // actions
+    check: bool,
+    completions: Option<String>,
+    doctor: bool,
     edit: bool,
     help: bool,
     list: List,
@@ -66,19 +83,249 @@ enum SudoArg {
     Rest(Vec<String>),
 }
 
+/// Whether an option takes an argument.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Argument {
+    No,
+    Required,
+    /// Only with the `--option=value` syntax, like `--preserve-env=list`.
+    Optional,
+}
+
+struct SudoOption {
+    short: Option<char>,
+    long: &'static str,
+    argument: Argument,
+    /// What the option does, for shell completions; options without one are hidden.
+    help: &'static str,
+}
+
+const fn option(
+    short: Option<char>,
+    long: &'static str,
+    argument: Argument,
+    help: &'static str,
+) -> SudoOption {
+    SudoOption {
+        short,
+        long,
+        argument,
+        help,
+    }
+}
+
 impl SudoOptions {
-    const TAKES_ARGUMENT_SHORT: &'static [char] = &['D', 'E', 'g', 'h', 'R', 'U', 'u'];
-    const TAKES_ARGUMENT: &'static [&'static str] = &[
-        "chdir",
-        "preserve-env",
-        "group",
-        "host",
-        "chroot",
-        "other-user",
-        "user",
+    /// The options of sudo, with their short and long names like those of ogsudo.
+    const SUDO_OPTIONS: &'static [SudoOption] = &[
+        option(
+            Some('A'),
+            "askpass",
+            Argument::No,
+            "use a helper program for password prompting",
+        ),
+        option(
+            Some('b'),
+            "background",
+            Argument::No,
+            "run command in the background",
+        ),
+        option(
+            None,
+            "check",
+            Argument::No,
+            "report whether the command would be allowed",
+        ),
+        option(
+            Some('D'),
+            "chdir",
+            Argument::Required,
+            "change the working directory",
+        ),
+        option(
+            Some('R'),
+            "chroot",
+            Argument::Required,
+            "change the root directory",
+        ),
+        option(None, "completions", Argument::Required, ""),
+        option(
+            None,
+            "doctor",
+            Argument::No,
+            "check the installation of sudo for common problems",
+        ),
+        option(
+            None,
+            "dry-run",
+            Argument::No,
+            "report whether the command would be allowed",
+        ),
+        option(
+            Some('e'),
+            "edit",
+            Argument::No,
+            "edit files instead of running a command",
+        ),
+        option(
+            Some('g'),
+            "group",
+            Argument::Required,
+            "run command as the specified group",
+        ),
+        option(
+            Some('h'),
+            "help",
+            Argument::No,
+            "display help message and exit",
+        ),
+        option(None, "host", Argument::Required, "run command on host"),
+        option(
+            None,
+            "json",
+            Argument::No,
+            "with --version, display the version information as JSON",
+        ),
+        option(Some('l'), "list", Argument::No, "list user's privileges"),
+        option(
+            Some('i'),
+            "login",
+            Argument::No,
+            "run login shell as the target user",
+        ),
+        option(
+            Some('N'),
+            "no-update",
+            Argument::No,
+            "do not update the user's timestamp",
+        ),
+        option(
+            Some('n'),
+            "non-interactive",
+            Argument::No,
+            "non-interactive mode, no prompts are used",
+        ),
+        option(
+            Some('U'),
+            "other-user",
+            Argument::Required,
+            "in list mode, display privileges for user",
+        ),
+        option(
+            Some('E'),
+            "preserve-env",
+            Argument::Optional,
+            "preserve user environment",
+        ),
+        option(
+            Some('P'),
+            "preserve-groups",
+            Argument::No,
+            "preserve group vector",
+        ),
+        option(
+            Some('p'),
+            "prompt",
+            Argument::Required,
+            "use the specified password prompt",
+        ),
+        option(
+            Some('K'),
+            "remove-timestamp",
+            Argument::No,
+            "remove timestamp file completely",
+        ),
+        option(
+            Some('k'),
+            "reset-timestamp",
+            Argument::No,
+            "invalidate timestamp file",
+        ),
+        option(
+            Some('H'),
+            "set-home",
+            Argument::No,
+            "set HOME variable to target user's home dir",
+        ),
+        option(
+            Some('s'),
+            "shell",
+            Argument::No,
+            "run shell as the target user",
+        ),
+        option(
+            None,
+            "stats",
+            Argument::No,
+            "report the time and memory that the command used",
+        ),
+        option(
+            Some('S'),
+            "stdin",
+            Argument::No,
+            "read password from standard input",
+        ),
+        option(
+            Some('u'),
+            "user",
+            Argument::Required,
+            "run command as specified user",
+        ),
+        option(
+            Some('v'),
+            "validate",
+            Argument::No,
+            "update user's timestamp without running a command",
+        ),
+        option(
+            Some('V'),
+            "version",
+            Argument::No,
+            "display version information and exit",
+        ),
+        option(
+            None,
+            "why",
+            Argument::No,
+            "in list mode, explain which rules decide on the command",
+        ),
     ];
 
+    /// The long names of the options that sudo accepts, except for the hidden ones.
+    pub fn long_options() -> impl Iterator<Item = &'static str> {
+        Self::SUDO_OPTIONS
+            .iter()
+            .filter(|option| !option.help.is_empty())
+            .map(|option| option.long)
+    }
+
+    /// Find a long option by its name or, like `getopt_long`, by an unambiguous prefix of it.
+    fn find_long_option(name: &str) -> Result<&'static SudoOption, String> {
+        if let Some(option) = Self::SUDO_OPTIONS.iter().find(|o| o.long == name) {
+            return Ok(option);
+        }
+
+        let candidates = Self::SUDO_OPTIONS
+            .iter()
+            .filter(|o| !name.is_empty() && o.long.starts_with(name))
+            .collect::<Vec<_>>();
+        match candidates[..] {
+            [] => Err(format!("unrecognized option '--{name}'")),
+            [option] => Ok(option),
+            _ => Err(format!(
+                "option '--{name}' is ambiguous; possibilities: {}",
+                candidates
+                    .iter()
+                    .map(|o| format!("'--{}'", o.long))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            )),
+        }
+    }
+
     /// argument assignments and shorthand options preprocessing
+    ///
+    /// Options are parsed like `getopt_long` does for ogsudo, and problems with them are reported
+    /// in the same words.
     fn normalize_arguments<I>(iter: I) -> Result<Vec<SudoArg>, String>
     where
         I: IntoIterator<Item = String>,
@@ -94,49 +341,67 @@ impl SudoOptions {
                     break;
                 }
                 long_arg if long_arg.starts_with("--") => {
-                    if long_arg.contains('=') {
-                        // convert assignment to normal tokens
-                        let (key, value) = long_arg.split_once('=').unwrap();
-                        // only accept arguments when one is expected
-                        if !Self::TAKES_ARGUMENT.contains(&&key[2..]) {
-                            Err(format!("'{}' does not take any arguments", key))?;
-                        }
-                        processed.push(SudoArg::Argument(key.to_string(), value.to_string()));
-                    } else if Self::TAKES_ARGUMENT.contains(&&long_arg[2..]) {
-                        if let Some(next) = arg_iter.next() {
-                            processed.push(SudoArg::Argument(arg, next));
+                    let (name, value) = match long_arg[2..].split_once('=') {
+                        Some((name, value)) => (name, Some(value.to_string())),
+                        None => (&long_arg[2..], None),
+                    };
+                    let option = Self::find_long_option(name).map_err(|err| {
+                        // the whole argument is shown for an unknown option
+                        if err.starts_with("unrecognized") {
+                            format!("unrecognized option '{long_arg}'")
                         } else {
-                            Err(format!("'{}' expects an argument", &long_arg))?;
+                            err
+                        }
+                    })?;
+                    let flag = format!("--{}", option.long);
+
+                    match (option.argument, value) {
+                        (Argument::No, Some(_)) => {
+                            Err(format!("option '{flag}' doesn't allow an argument"))?;
+                        }
+                        (Argument::No | Argument::Optional, None) => {
+                            processed.push(SudoArg::Flag(flag));
+                        }
+                        (Argument::Required | Argument::Optional, Some(value)) => {
+                            processed.push(SudoArg::Argument(flag, value));
+                        }
+                        (Argument::Required, None) => {
+                            if let Some(next) = arg_iter.next() {
+                                processed.push(SudoArg::Argument(flag, next));
+                            } else {
+                                Err(format!("option '{flag}' requires an argument"))?;
+                            }
                         }
-                    } else {
-                        processed.push(SudoArg::Flag(arg));
                     }
                 }
-                short_arg if short_arg.starts_with('-') => {
+                short_arg if short_arg.starts_with('-') && short_arg.len() > 1 => {
                     // split combined shorthand options
-                    for (n, char) in short_arg.trim_start_matches('-').chars().enumerate() {
+                    for (n, char) in short_arg.char_indices().skip(1) {
                         let flag = format!("-{char}");
-                        // convert option argument to seperate segment
-                        if Self::TAKES_ARGUMENT_SHORT.contains(&char) {
-                            let rest = short_arg[(n + 2)..].trim().to_string();
-                            // assignment syntax is not accepted for shorthand arguments
-                            if rest.starts_with('=') {
-                                Err("invalid option '='")?;
-                            }
-                            if !rest.is_empty() {
-                                processed.push(SudoArg::Argument(flag, rest));
-                            } else if let Some(next) = arg_iter.next() {
-                                processed.push(SudoArg::Argument(flag, next));
-                            } else if char == 'h' {
-                                // short version of --help has no arguments
-                                processed.push(SudoArg::Flag(flag));
-                            } else {
-                                Err(format!("'-{}' expects an argument", char))?;
-                            }
-                            break;
-                        } else {
+                        let option = Self::SUDO_OPTIONS
+                            .iter()
+                            .find(|o| o.short == Some(char))
+                            .ok_or_else(|| format!("invalid option -- '{char}'"))?;
+
+                        // `-h` is `--help`, unless it is given a host like `--host`
+                        let takes_argument = option.argument == Argument::Required || char == 'h';
+                        if !takes_argument {
                             processed.push(SudoArg::Flag(flag));
+                            continue;
                         }
+
+                        // the rest of the options is the argument, or else the next one is
+                        let rest = &short_arg[n + char.len_utf8()..];
+                        if !rest.is_empty() {
+                            processed.push(SudoArg::Argument(flag, rest.to_string()));
+                        } else if let Some(next) = arg_iter.next() {
+                            processed.push(SudoArg::Argument(flag, next));
+                        } else if char == 'h' {
+                            processed.push(SudoArg::Flag(flag));
+                        } else {
+                            Err(format!("option requires an argument -- '{char}'"))?;
+                        }
+                        break;
                     }
                 }
                 env_var if SudoOptions::try_to_env_var(env_var).is_some() => {
@@ -175,8 +440,12 @@ impl SudoOptions {
 
     /// from the arguments resolve which action should be performed
     fn resolve_action(&mut self) {
-        if self.help {
+        if let Some(shell) = self.completions.take() {
+            self.action = SudoAction::Completions(shell);
+        } else if self.help {
             self.action = SudoAction::Help;
+        } else if self.doctor {
+            self.action = SudoAction::Doctor;
         } else if self.version {
             self.action = SudoAction::Version;
         } else if self.remove_timestamp {
@@ -185,6 +454,8 @@ impl SudoOptions {
             self.action = SudoAction::ResetTimestamp;
         } else if self.validate {
             self.action = SudoAction::Validate;
+        } else if self.check {
+            self.action = SudoAction::Check(std::mem::take(self.external_args.as_mut()));
         } else if self.list != List::None {
             self.action = SudoAction::List(std::mem::take(self.external_args.as_mut()));
         } else if self.edit {
@@ -202,14 +473,23 @@ impl SudoOptions {
         if self.remove_timestamp && self.reset_timestamp {
             Err("conflicting arguments '--remove-timestamp' and '--reset-timestamp'")?;
         }
+        if self.check && (self.list != List::None || self.edit) {
+            Err("'--check' cannot be combined with '--list' or '--edit'")?;
+        }
+        if self.why && !matches!(&self.action, SudoAction::List(args) if !args.is_empty()) {
+            Err("'--why' can only be used with '--list' and a command")?;
+        }
 
         // check arguments for validate action
         if matches!(self.action, SudoAction::Validate)
             && (self.background
                 || self.preserve_groups
+                || self.set_home
                 || self.login
                 || self.shell
+                || self.stats
                 || !self.preserve_env.is_empty()
+                || self.preserve_env_all
                 || self.other_user.is_some()
                 || self.directory.is_some()
                 || self.chroot.is_some())
@@ -225,10 +505,13 @@ impl SudoOptions {
 
             if self.background
                 || self.preserve_groups
+                || self.set_home
                 || self.login
                 || !valid_user_flag
                 || self.shell
+                || self.stats
                 || !self.preserve_env.is_empty()
+                || self.preserve_env_all
                 || self.directory.is_some()
                 || self.chroot.is_some()
             {
@@ -242,8 +525,10 @@ impl SudoOptions {
                 || self.preserve_groups
                 || self.login
                 || self.shell
+                || self.stats
                 || self.other_user.is_some()
-                || !self.preserve_env.is_empty())
+                || !self.preserve_env.is_empty()
+                || self.preserve_env_all)
         {
             Err("invalid argument found for '--edit'")?;
         }
@@ -265,15 +550,23 @@ impl SudoOptions {
         for arg in arg_iter {
             match arg {
                 SudoArg::Flag(flag) => match flag.as_str() {
+                    "-A" | "--askpass" => {
+                        options.askpass = true;
+                    }
                     "-b" | "--background" => {
                         options.background = true;
                     }
+                    "--check" | "--dry-run" => {
+                        options.check = true;
+                    }
+                    "-E" | "--preserve-env" => {
+                        options.preserve_env_all = true;
+                    }
                     "-e" | "--edit" => {
                         options.edit = true;
                     }
                     "-H" | "--set-home" => {
-                        // this option is ignored, since it is the default for sudo-rs; but accept
-                        // it for backwards compatibility reasons
+                        options.set_home = true;
                     }
                     "-h" | "--help" => {
                         options.help = true;
@@ -292,6 +585,9 @@ impl SudoOptions {
                         List::Once => options.list = List::Verbose,
                         List::Verbose => {}
                     },
+                    "-N" | "--no-update" => {
+                        options.no_update = true;
+                    }
                     "-n" | "--non-interactive" => {
                         options.non_interactive = true;
                     }
@@ -304,12 +600,24 @@ impl SudoOptions {
                     "-s" | "--shell" => {
                         options.shell = true;
                     }
+                    "--stats" => {
+                        options.stats = true;
+                    }
+                    "--json" => {
+                        options.json = true;
+                    }
+                    "--doctor" => {
+                        options.doctor = true;
+                    }
                     "-V" | "--version" => {
                         options.version = true;
                     }
                     "-v" | "--validate" => {
                         options.validate = true;
                     }
+                    "--why" => {
+                        options.why = true;
+                    }
                     _option => {
                         Err("invalid option provided")?;
                     }
@@ -318,15 +626,21 @@ impl SudoOptions {
                     "-D" | "--chdir" => {
                         options.directory = Some(PathBuf::from(value));
                     }
-                    "-E" | "--preserve-env" => {
-                        options.preserve_env = value.split(',').map(str::to_string).collect()
-                    }
+                    "--preserve-env" => options
+                        .preserve_env
+                        .extend(value.split(',').map(str::to_string)),
                     "-g" | "--group" => {
                         options.group = Some(value);
                     }
                     "-h" | "--host" => {
                         options.host = Some(value);
                     }
+                    "--completions" => {
+                        options.completions = Some(value);
+                    }
+                    "-p" | "--prompt" => {
+                        options.prompt = Some(value);
+                    }
                     "-R" | "--chroot" => {
                         options.chroot = Some(PathBuf::from(value));
                     }
@@ -364,6 +678,7 @@ impl SudoOptions {
         match self.action {
             SudoAction::Run(args) => args,
             SudoAction::List(args) => args,
+            SudoAction::Check(args) => args,
             _ => vec![],
         }
     }
@@ -10,14 +10,33 @@ fn short_preserve_env_with_var_fails() {
     assert!(cmd.is_err())
 }
 
-/// Passing '--preserve-env' with an argument fills 'preserve_env', 'short_preserve_env' stays 'false'
+/// Passing '--preserve-env' with an argument fills 'preserve_env', 'preserve_env_all' stays 'false'
 #[test]
 fn preserve_env_with_var() {
     let cmd = SudoOptions::try_parse_from(["sudo", "--preserve-env=some_argument"]).unwrap();
     assert_eq!(cmd.preserve_env, vec!["some_argument"]);
+    assert!(!cmd.preserve_env_all);
 }
 
-/// Passing '--preserve-env' with several arguments fills 'preserve_env', 'short_preserve_env' stays 'false'
+/// Passing '-E' or '--preserve-env' without an argument sets 'preserve_env_all', and does not
+/// take the command as its argument
+#[test]
+fn preserve_env_all() {
+    for flag in ["-E", "--preserve-env"] {
+        let cmd = SudoOptions::try_parse_from(["sudo", flag, "env"]).unwrap();
+        assert!(cmd.preserve_env_all);
+        assert!(cmd.preserve_env.is_empty());
+        assert_eq!(cmd.args(), ["env"]);
+    }
+
+    let cmd = SudoOptions::try_parse_from(["sudo", "-E", "--preserve-env=FOO", "env"]).unwrap();
+    assert!(cmd.preserve_env_all);
+    assert_eq!(cmd.preserve_env, ["FOO"]);
+
+    assert!(SudoOptions::try_parse_from(["sudo", "-E", "-v"]).is_err());
+}
+
+/// Passing '--preserve-env' with several arguments fills 'preserve_env', 'preserve_env_all' stays 'false'
 #[test]
 fn preserve_env_with_several_vars() {
     let cmd = SudoOptions::try_parse_from([
@@ -177,6 +196,17 @@ fn shorthand_without_argument() {
     assert!(cmd.is_err())
 }
 
+#[test]
+fn no_update() {
+    let cmd = SudoOptions::try_parse_from(["sudo", "-Nv"]).unwrap();
+    assert!(cmd.no_update);
+    assert_eq!(cmd.action, SudoAction::Validate);
+
+    let cmd = SudoOptions::try_parse_from(["sudo", "--no-update", "true"]).unwrap();
+    assert!(cmd.no_update);
+    assert_eq!(cmd.action, SudoAction::Run(vec!["true".to_string()]));
+}
+
 #[test]
 fn non_interactive() {
     let cmd = SudoOptions::try_parse_from(["sudo", "-n"]).unwrap();
@@ -195,6 +225,30 @@ fn preserve_groups() {
     assert!(cmd.preserve_groups);
 }
 
+#[test]
+fn set_home() {
+    let cmd = SudoOptions::try_parse_from(["sudo", "-H", "env"]).unwrap();
+    assert!(cmd.set_home);
+
+    let cmd = SudoOptions::try_parse_from(["sudo", "--set-home", "env"]).unwrap();
+    assert!(cmd.set_home);
+
+    let cmd = SudoOptions::try_parse_from(["sudo", "env"]).unwrap();
+    assert!(!cmd.set_home);
+}
+
+#[test]
+fn askpass() {
+    let cmd = SudoOptions::try_parse_from(["sudo", "-A", "true"]).unwrap();
+    assert!(cmd.askpass);
+
+    let cmd = SudoOptions::try_parse_from(["sudo", "--askpass", "-v"]).unwrap();
+    assert!(cmd.askpass);
+
+    let cmd = SudoOptions::try_parse_from(["sudo", "-S", "true"]).unwrap();
+    assert!(!cmd.askpass);
+}
+
 #[test]
 fn stdin() {
     let cmd = SudoOptions::try_parse_from(["sudo", "-S"]).unwrap();
@@ -273,6 +327,80 @@ fn other_user() {
     assert_eq!(cmd.other_user.as_deref(), Some("ferris"));
 }
 
+#[test]
+fn bundled_options() {
+    let cmd = SudoOptions::try_parse_from(["sudo", "-iu", "admin"]).unwrap();
+    assert!(cmd.login);
+    assert_eq!(cmd.user.as_deref(), Some("admin"));
+
+    let cmd = SudoOptions::try_parse_from(["sudo", "-HnuAdmin", "id"]).unwrap();
+    assert!(cmd.set_home && cmd.non_interactive);
+    assert_eq!(cmd.user.as_deref(), Some("Admin"));
+    assert_eq!(cmd.args(), ["id"]);
+
+    // everything after the option that takes an argument is that argument
+    let cmd = SudoOptions::try_parse_from(["sudo", "-u-s", "id"]).unwrap();
+    assert_eq!(cmd.user.as_deref(), Some("-s"));
+    assert!(!cmd.shell);
+}
+
+#[test]
+fn long_option_prefixes() {
+    let cmd = SudoOptions::try_parse_from(["sudo", "--us", "admin", "--log"]).unwrap();
+    assert_eq!(cmd.user.as_deref(), Some("admin"));
+    assert!(cmd.login);
+
+    let cmd = SudoOptions::try_parse_from(["sudo", "--grou=wheel", "--non", "id"]).unwrap();
+    assert_eq!(cmd.group.as_deref(), Some("wheel"));
+    assert!(cmd.non_interactive);
+
+    // an exact match wins over the options that it is a prefix of
+    let cmd = SudoOptions::try_parse_from(["sudo", "--check", "id"]).unwrap();
+    assert_eq!(cmd.action, SudoAction::Check(vec!["id".to_string()]));
+}
+
+#[test]
+fn error_messages() {
+    let error = |args: &[&str]| SudoOptions::try_parse_from(args.iter().copied()).unwrap_err();
+
+    assert_eq!(error(&["sudo", "-x"]), "invalid option -- 'x'");
+    assert_eq!(error(&["sudo", "-ix"]), "invalid option -- 'x'");
+    assert_eq!(error(&["sudo", "-E=FOO"]), "invalid option -- '='");
+    assert_eq!(
+        error(&["sudo", "-iu"]),
+        "option requires an argument -- 'u'"
+    );
+    assert_eq!(
+        error(&["sudo", "--wololo"]),
+        "unrecognized option '--wololo'"
+    );
+    assert_eq!(
+        error(&["sudo", "--wololo=1"]),
+        "unrecognized option '--wololo=1'"
+    );
+    assert_eq!(
+        error(&["sudo", "--user"]),
+        "option '--user' requires an argument"
+    );
+    assert_eq!(
+        error(&["sudo", "--log=yes"]),
+        "option '--login' doesn't allow an argument"
+    );
+    assert_eq!(
+        error(&["sudo", "--st"]),
+        "option '--st' is ambiguous; possibilities: '--stats' '--stdin'"
+    );
+}
+
+#[test]
+fn prompt() {
+    let cmd = SudoOptions::try_parse_from(["sudo", "-p", "pw for %u: ", "id"]).unwrap();
+    assert_eq!(cmd.prompt.as_deref(), Some("pw for %u: "));
+
+    let cmd = SudoOptions::try_parse_from(["sudo", "--prompt=", "id"]).unwrap();
+    assert_eq!(cmd.prompt.as_deref(), Some(""));
+}
+
 #[test]
 fn invalid_option() {
     let cmd = SudoOptions::try_parse_from(["sudo", "--wololo"]);
@@ -336,6 +464,13 @@ fn conflicting_arguments() {
     assert_eq!(cmd.action, SudoAction::ResetTimestamp);
 }
 
+#[test]
+fn remove_timestamp_of_other_user() {
+    let cmd = SudoOptions::try_parse_from(["sudo", "-K", "-u", "alice"]).unwrap();
+    assert_eq!(cmd.action, SudoAction::RemoveTimestamp);
+    assert_eq!(cmd.user.as_deref(), Some("alice"));
+}
+
 #[test]
 fn list() {
     let valid: &[&[_]] = &[
@@ -346,6 +481,7 @@ fn list() {
         &["sudo", "-l", "-U", "ferris", "true"],
         &["sudo", "-l", "-u", "ferris", "true"],
         &["sudo", "-l", "-u", "ferris", "-U", "root", "true"],
+        &["sudo", "-l", "--why", "true"],
     ];
 
     for args in valid {
@@ -356,6 +492,8 @@ fn list() {
     let invalid: &[&[_]] = &[
         &["sudo", "-l", "-u", "ferris"],
         &["sudo", "-l", "-u", "ferris", "-U", "root"],
+        &["sudo", "-l", "--why"],
+        &["sudo", "--why", "true"],
     ];
 
     for args in invalid {
@@ -364,6 +502,35 @@ fn list() {
     }
 }
 
+#[test]
+fn check() {
+    for flag in ["--check", "--dry-run"] {
+        let cmd = SudoOptions::try_parse_from(["sudo", flag, "-u", "ferris", "ls", "-l"]).unwrap();
+        assert_eq!(cmd.user.as_deref(), Some("ferris"));
+        assert_eq!(
+            cmd.action,
+            SudoAction::Check(vec!["ls".to_string(), "-l".to_string()])
+        );
+    }
+
+    let cmd = SudoOptions::try_parse_from(["sudo", "-i", "--check"]).unwrap();
+    assert_eq!(cmd.action, SudoAction::Check(vec![]));
+
+    assert!(SudoOptions::try_parse_from(["sudo", "--check", "-l", "ls"]).is_err());
+    assert!(SudoOptions::try_parse_from(["sudo", "--check=yes", "ls"]).is_err());
+}
+
+#[test]
+fn stats() {
+    let cmd = SudoOptions::try_parse_from(["sudo", "--stats", "make"]).unwrap();
+    assert!(cmd.stats);
+    assert_eq!(cmd.action, SudoAction::Run(vec!["make".to_string()]));
+
+    assert!(SudoOptions::try_parse_from(["sudo", "--stats", "-v"]).is_err());
+    assert!(SudoOptions::try_parse_from(["sudo", "--stats", "-l"]).is_err());
+    assert!(SudoOptions::try_parse_from(["sudo", "--stats=yes", "make"]).is_err());
+}
+
 #[test]
 fn validate() {
     let cmd = SudoOptions::try_parse_from(["sudo", "-v"]).unwrap();
@@ -373,6 +540,12 @@ fn validate() {
     assert_eq!(cmd.action, SudoAction::Validate);
 }
 
+#[test]
+fn doctor() {
+    let cmd = SudoOptions::try_parse_from(["sudo", "--doctor"]).unwrap();
+    assert_eq!(cmd.action, SudoAction::Doctor);
+}
+
 #[test]
 fn version() {
     let cmd = SudoOptions::try_parse_from(["sudo", "-V"]).unwrap();
@@ -380,4 +553,12 @@ fn version() {
 
     let cmd = SudoOptions::try_parse_from(["sudo", "--version"]).unwrap();
     assert_eq!(cmd.action, SudoAction::Version);
+
+    let cmd = SudoOptions::try_parse_from(["sudo", "-V", "--json"]).unwrap();
+    assert_eq!(cmd.action, SudoAction::Version);
+    assert!(cmd.json);
+
+    // otherwise it is about how errors are reported
+    let cmd = SudoOptions::try_parse_from(["sudo", "--json", "true"]).unwrap();
+    assert!(cmd.json);
 }
@@ -1,11 +1,18 @@
 use std::{
     fmt::Display,
-    path::{Path, PathBuf},
+    fs::{File, OpenOptions},
+    os::unix::fs::OpenOptionsExt,
+    path::PathBuf,
 };
 
 use crate::system::escape_os_str_lossy;
 
-use super::resolve::{canonicalize, resolve_path};
+use super::{
+    lookup::{lookup, Credentials, LookupError},
+    quote,
+    resolve::canonicalize,
+    Error,
+};
 
 #[derive(Debug, Default)]
 #[cfg_attr(test, derive(PartialEq))]
@@ -13,6 +20,7 @@ pub struct CommandAndArguments {
     pub(crate) command: PathBuf,
     pub(crate) arguments: Vec<String>,
     pub(crate) resolved: bool,
+    pub(crate) lookup_error: Option<LookupError>,
     pub(crate) arg0: Option<PathBuf>,
 }
 
@@ -29,38 +37,23 @@ impl Display for CommandAndArguments {
     }
 }
 
-// when -i and -s are used, the arguments given to sudo are escaped "except for alphanumerics, underscores, hyphens, and dollar signs."
-fn escaped(arguments: Vec<String>) -> String {
-    arguments
-        .into_iter()
-        .map(|arg| {
-            arg.chars()
-                .map(|c| match c {
-                    '_' | '-' | '$' => c.to_string(),
-                    c if c.is_alphanumeric() => c.to_string(),
-                    _ => ['\\', c].iter().collect(),
-                })
-                .collect()
-        })
-        .collect::<Vec<String>>()
-        .join(" ")
-}
-
-//checks whether the Path is actually describing a qualified path (i.e. contains "/")
-//or just specifying the name of a file (in which case we are going to resolve it via PATH)
-fn is_qualified(path: impl AsRef<Path>) -> bool {
-    path.as_ref().parent() != Some(Path::new(""))
-}
-
 impl CommandAndArguments {
-    pub fn build_from_args(shell: Option<PathBuf>, mut arguments: Vec<String>, path: &str) -> Self {
+    /// The command is looked up in `path` (if necessary) as the user with the given credentials.
+    pub fn build_from_args(
+        shell: Option<PathBuf>,
+        mut arguments: Vec<String>,
+        path: &str,
+        credentials: &Credentials,
+    ) -> Self {
         let mut resolved = true;
+        let mut lookup_error = None;
         let mut command;
         let mut arg0 = None;
         if let Some(chosen_shell) = shell {
             command = chosen_shell;
             if !arguments.is_empty() {
-                arguments = vec!["-c".to_string(), escaped(arguments)]
+                // the arguments given to sudo are escaped for the shell
+                arguments = vec!["-c".to_string(), quote::join(arguments)]
             }
         } else {
             command = arguments
@@ -74,10 +67,11 @@ impl CommandAndArguments {
             arg0 = Some(command.clone());
 
             // resolve the command, remembering errors (but not propagating them)
-            if !is_qualified(&command) {
-                match resolve_path(&command, path) {
-                    Some(qualified_path) => command = qualified_path,
-                    None => resolved = false,
+            match lookup(&command, path, credentials) {
+                Ok(qualified_path) => command = qualified_path,
+                Err(err) => {
+                    resolved = false;
+                    lookup_error = Some(err);
                 }
             }
 
@@ -93,30 +87,47 @@ impl CommandAndArguments {
             command,
             arguments,
             resolved,
+            lookup_error,
             arg0,
         }
     }
+
+    /// Open the command once it is resolved, so that it can be executed through this file later
+    /// on, whichever file its path refers to by then.
+    pub(crate) fn open(&self) -> Option<File> {
+        if !self.resolved {
+            return None;
+        }
+        // a FIFO that was put in its place in the meantime should not make sudo wait
+        let file = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&self.command)
+            .ok()?;
+
+        file.metadata().ok()?.is_file().then_some(file)
+    }
+
+    /// The error to report for a command that is not `resolved`.
+    pub(crate) fn not_found(&self) -> Error {
+        match &self.lookup_error {
+            Some(err) => Error::Lookup(err.clone()),
+            None => Error::CommandNotFound(self.command.clone()),
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{escaped, CommandAndArguments};
+    use super::CommandAndArguments;
+    use crate::common::lookup::{Credentials, LookupError};
+    use crate::common::resolve::canonicalize;
 
-    #[test]
-    fn test_escaped() {
-        let test = |src: &[&str], target: &str| {
-            assert_eq!(
-                &escaped(src.iter().map(|s| s.to_string()).collect()),
-                target
-            );
-        };
-        test(&["a", "b", "c"], "a b c");
-        test(&["a", "b c"], "a b\\ c");
-        test(&["a", "b-c"], "a b-c");
-        test(&["a", "b#c"], "a b\\#c");
-        test(&["1 2 3"], "1\\ 2\\ 3");
-        test(&["! @ $"], "\\!\\ \\@\\ $");
-    }
+    const ROOT: Credentials = Credentials {
+        uid: 0,
+        gid: 0,
+        groups: &[],
+    };
 
     #[test]
     fn test_build_command_and_args() {
@@ -124,12 +135,14 @@ mod test {
             CommandAndArguments::build_from_args(
                 None,
                 vec!["/usr/bin/fmt".into(), "hello".into()],
-                "/bin"
+                "/bin",
+                &ROOT
             ),
             CommandAndArguments {
                 command: "/usr/bin/fmt".into(),
                 arguments: vec!["hello".into()],
                 resolved: true,
+                lookup_error: None,
                 arg0: Some("/usr/bin/fmt".into()),
             }
         );
@@ -138,12 +151,14 @@ mod test {
             CommandAndArguments::build_from_args(
                 None,
                 vec!["fmt".into(), "hello".into()],
-                "/tmp:/usr/bin:/bin"
+                "/tmp:/usr/bin:/bin",
+                &ROOT
             ),
             CommandAndArguments {
                 command: "/usr/bin/fmt".into(),
                 arguments: vec!["hello".into()],
                 resolved: true,
+                lookup_error: None,
                 arg0: Some("fmt".into()),
             }
         );
@@ -152,12 +167,18 @@ mod test {
             CommandAndArguments::build_from_args(
                 None,
                 vec!["thisdoesnotexist".into(), "hello".into()],
-                ""
+                "",
+                &ROOT
             ),
             CommandAndArguments {
                 command: "thisdoesnotexist".into(),
                 arguments: vec!["hello".into()],
                 resolved: false,
+                // an empty PATH denotes the current directory
+                lookup_error: Some(LookupError::NotFound {
+                    command: "thisdoesnotexist".into(),
+                    searched: vec![std::env::current_dir().unwrap()],
+                }),
                 arg0: Some("thisdoesnotexist".into()),
             }
         );
@@ -166,28 +187,45 @@ mod test {
             CommandAndArguments::build_from_args(
                 Some("shell".into()),
                 vec!["ls".into(), "hello".into()],
-                "/bin"
+                "/bin",
+                &ROOT
             ),
             CommandAndArguments {
                 command: "shell".into(),
                 arguments: vec!["-c".into(), "ls hello".into()],
                 resolved: true,
+                lookup_error: None,
                 arg0: None,
             }
         );
     }
 
     #[test]
-    fn qualified_paths() {
-        use super::is_qualified;
-        assert!(is_qualified("foo/bar"));
-        assert!(is_qualified("a/b/bar"));
-        assert!(is_qualified("a/b//bar"));
-        assert!(is_qualified("/bar"));
-        assert!(is_qualified("/bar/"));
-        assert!(is_qualified("/bar/foo/"));
-        assert!(is_qualified("/"));
-        assert!(is_qualified("")); // don't try to resolve ""
-        assert!(!is_qualified("bar"));
+    fn relative_commands() {
+        // a path relative to the current directory, that leads to /usr/bin/env
+        let up = std::env::current_dir()
+            .unwrap()
+            .components()
+            .skip(1)
+            .map(|_| "..")
+            .collect::<Vec<_>>()
+            .join("/");
+        let relative = format!("./{up}/usr/bin/env");
+
+        // the policy is checked against the resolved path, but the command sees what was typed
+        let command = CommandAndArguments::build_from_args(None, vec![relative.clone()], "", &ROOT);
+        assert!(command.resolved);
+        assert_eq!(command.command, canonicalize("/usr/bin/env").unwrap());
+        assert_eq!(command.arg0, Some(relative.into()));
+    }
+
+    #[test]
+    fn opens_resolved_commands() {
+        let command = |name: &str| {
+            CommandAndArguments::build_from_args(None, vec![name.into()], "/usr/bin:/bin", &ROOT)
+        };
+
+        assert!(command("env").open().is_some());
+        assert!(command("thisisnotonyourfs").open().is_none());
     }
 }
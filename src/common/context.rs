@@ -1,9 +1,21 @@
 use crate::cli::{SudoAction, SudoOptions};
-use crate::system::{hostname, Group, Process, User};
+use crate::exec::OutputQuota;
+use crate::iolog::IoLogOptions;
+use crate::plugin::IoPlugin;
+use crate::system::{
+    capabilities::CapabilitySet, hostname, random_bytes, sandbox::SandboxProfile,
+    signal::SignalNumber, Group, Process, User,
+};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io;
+use std::os::fd::OwnedFd;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use super::{
     command::CommandAndArguments,
+    lookup::Credentials,
     resolve::{resolve_current_user, resolve_launch_and_shell, resolve_target_user_and_group},
     Error,
 };
@@ -17,14 +29,73 @@ pub struct Context {
     pub target_user: User,
     pub target_group: Group,
     pub stdin: bool,
+    /// Whether to ask for the password with the askpass helper, with `-A`.
+    pub askpass: bool,
+    /// The password prompt given with `-p`, with its escapes still in it.
+    pub prompt: Option<String>,
     pub non_interactive: bool,
     pub use_session_records: bool,
+    pub update_session_records: bool,
+    pub preserve_groups: bool,
+    /// Whether the invoking user asked to keep their whole environment, with `-E`.
+    pub preserve_env: bool,
+    /// The variables that the invoking user asked to set, on the command line or with
+    /// `--preserve-env=list`.
+    pub env_vars: Vec<(OsString, OsString)>,
+    pub set_home: bool,
+    pub stats: bool,
     // system
     pub hostname: String,
     pub current_user: User,
+    pub auth_user: User,
     pub process: Process,
+    /// The unique ID of this invocation of sudo, which is logged and exported to the command as
+    /// `SUDO_SESSION_ID`.
+    pub session_uuid: String,
+    /// The `SUDO_SESSION_ID` of the sudo that the invoking user runs under, if any, so that nested
+    /// invocations can be traced back.
+    pub parent_session_uuid: Option<String>,
     // policy
     pub use_pty: bool,
+    pub tty_signals: bool,
+    pub tty_flow_control: bool,
+    /// The signals that are forwarded to the command, or `None` if all of them are.
+    pub forward_signals: Option<Vec<SignalNumber>>,
+    pub pam_session: bool,
+    /// Whether sudo can execute the command in its own process, as nothing has to be done after
+    /// the command exited.
+    pub exec_in_place: bool,
+    pub pty_wait_for_eof: Duration,
+    /// How often the invoking user has to authenticate again while the command runs
+    /// (`session_reauth`).
+    pub session_reauth: Option<Duration>,
+    /// How long the command may go without input or output before it is hung up on
+    /// (`idle_timeout`).
+    pub idle_timeout: Option<Duration>,
+    pub output_quota: Option<OutputQuota>,
+    pub stay_setuid: bool,
+    pub capabilities: Option<CapabilitySet>,
+    pub sandbox: Option<SandboxProfile>,
+    /// Whether the command is kept from running other programs (`NOEXEC`).
+    pub noexec: bool,
+    /// The command, opened when it was resolved; it is executed through this file instead of by
+    /// its path if the policy says so (`fdexec`), and closed otherwise.
+    pub command_file: Option<File>,
+    /// How many invocations of sudo this one runs under, if that is logged (`nested_sudo`).
+    pub nested_sudo: usize,
+    pub cgroup: Option<String>,
+    pub pty_group: Option<String>,
+    pub log_input: bool,
+    pub log_output: bool,
+    pub mail: bool,
+    pub log_exit_status: bool,
+    pub sudoedit_follow: bool,
+    pub iolog: Option<IoLogOptions>,
+    pub tee_output: Option<PathBuf>,
+    /// The file descriptor in `SUDO_TEE_FD`, which gets a copy of the output of the command.
+    pub tee_fd: Option<OwnedFd>,
+    // plugins
+    pub io_plugins: Vec<IoPlugin>,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -35,54 +106,163 @@ pub enum LaunchType {
 }
 
 impl Context {
-    pub fn build_from_options(sudo_options: SudoOptions, path: String) -> Result<Context, Error> {
-        let hostname = hostname();
+    /// Build the context of a request; with `allow_unknown_id`, `-u #uid` and `-g #gid` may be ids
+    /// that are not in the user or group database.
+    pub fn build_from_options(
+        sudo_options: SudoOptions,
+        path: String,
+        allow_unknown_id: bool,
+    ) -> Result<Context, Error> {
+        // the policy is evaluated for another host with `sudo -l -h host`; commands can only be
+        // run on this one
+        let hostname = match (&sudo_options.action, &sudo_options.host) {
+            (SudoAction::List(_), Some(host)) => host.clone(),
+            (_, Some(_)) => Err(Error::Options(
+                "a host can only be specified when listing privileges".to_string(),
+            ))?,
+            (_, None) => hostname(),
+        };
         let current_user = resolve_current_user()?;
-        let (target_user, target_group) =
-            resolve_target_user_and_group(&sudo_options.user, &sudo_options.group, &current_user)?;
+        let (target_user, target_group) = resolve_target_user_and_group(
+            &sudo_options.user,
+            &sudo_options.group,
+            &current_user,
+            allow_unknown_id,
+        )?;
         let (launch, shell) = resolve_launch_and_shell(&sudo_options, &current_user, &target_user);
+        let set_home = sudo_options.set_home || launch == LaunchType::Login;
+        let credentials = Credentials::of(&target_user, &target_group);
+        let run = matches!(sudo_options.action, SudoAction::Run(_));
         let command = match sudo_options.action {
-            SudoAction::Run(args) => CommandAndArguments::build_from_args(shell, args, &path),
+            SudoAction::Run(args) | SudoAction::Check(args) => {
+                CommandAndArguments::build_from_args(shell, args, &path, &credentials)
+            }
             SudoAction::List(args) => {
                 if args.is_empty() {
                     // FIXME here and in the `_` arm, `Default` is being used as `Option::None`
                     Default::default()
                 } else {
-                    CommandAndArguments::build_from_args(shell, args, &path)
+                    CommandAndArguments::build_from_args(shell, args, &path, &credentials)
                 }
             }
             _ => Default::default(),
         };
+        let command_file = if run { command.open() } else { None };
+
+        // the variables of `--preserve-env=list` keep the value they have for the invoking user
+        let env_vars = sudo_options
+            .preserve_env
+            .iter()
+            .filter_map(|name| Some((name.into(), std::env::var_os(name)?)))
+            .chain(
+                sudo_options
+                    .env_var_list
+                    .into_iter()
+                    .map(|(name, value)| (name.into(), value.into())),
+            )
+            .collect();
 
         Ok(Context {
             hostname,
             command,
+            auth_user: current_user.clone(),
             current_user,
             target_user,
             target_group,
             use_session_records: !sudo_options.reset_timestamp,
+            update_session_records: !sudo_options.no_update,
             launch,
             chdir: sudo_options.directory,
             stdin: sudo_options.stdin,
+            askpass: sudo_options.askpass,
+            prompt: sudo_options.prompt,
             non_interactive: sudo_options.non_interactive,
+            preserve_groups: sudo_options.preserve_groups,
+            preserve_env: sudo_options.preserve_env_all,
+            env_vars,
+            set_home,
+            stats: sudo_options.stats,
             process: Process::new(),
+            session_uuid: random_uuid()?,
+            parent_session_uuid: std::env::var(SESSION_ID_VAR)
+                .ok()
+                .filter(|uuid| is_uuid(uuid)),
             use_pty: true,
+            tty_signals: false,
+            tty_flow_control: false,
+            forward_signals: None,
+            pam_session: true,
+            exec_in_place: false,
+            pty_wait_for_eof: Duration::ZERO,
+            session_reauth: None,
+            idle_timeout: None,
+            output_quota: None,
+            stay_setuid: false,
+            capabilities: None,
+            sandbox: None,
+            noexec: false,
+            command_file,
+            nested_sudo: 0,
+            cgroup: None,
+            pty_group: Some("tty".to_string()),
+            log_input: false,
+            log_output: false,
+            mail: false,
+            log_exit_status: false,
+            sudoedit_follow: false,
+            iolog: None,
+            tee_output: None,
+            tee_fd: None,
+            io_plugins: Vec::new(),
         })
     }
 }
 
+/// The variable in which commands get the ID of the invocation of sudo that started them.
+pub const SESSION_ID_VAR: &str = "SUDO_SESSION_ID";
+
+/// A random (version 4) UUID.
+fn random_uuid() -> io::Result<String> {
+    let mut bytes: [u8; 16] = random_bytes()?;
+    bytes[6] = bytes[6] & 0x0f | 0x40;
+    bytes[8] = bytes[8] & 0x3f | 0x80;
+
+    let hex = bytes
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    ))
+}
+
+/// Whether `text` is a UUID in the form that [random_uuid] writes; other values of
+/// `SUDO_SESSION_ID` are not trusted to end up in the logs.
+fn is_uuid(text: &str) -> bool {
+    text.len() == 36
+        && text.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => matches!(c, '0'..='9' | 'a'..='f'),
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{cli::SudoOptions, system::hostname};
     use std::collections::HashMap;
 
-    use super::Context;
+    use super::{is_uuid, random_uuid, Context};
 
     #[test]
     fn test_build_context() {
         let options = SudoOptions::try_parse_from(["sudo", "echo", "hello"]).unwrap();
         let path = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
-        let context = Context::build_from_options(options, path.to_string()).unwrap();
+        let context = Context::build_from_options(options, path.to_string(), false).unwrap();
 
         let mut target_environment = HashMap::new();
         target_environment.insert("SUDO_USER".to_string(), context.current_user.name.clone());
@@ -91,5 +271,30 @@ mod tests {
         assert_eq!(context.command.arguments, ["hello"]);
         assert_eq!(context.hostname, hostname());
         assert_eq!(context.target_user.uid, 0);
+        assert!(is_uuid(&context.session_uuid));
+    }
+
+    #[test]
+    fn generates_uuids() {
+        let uuid = random_uuid().unwrap();
+        assert!(is_uuid(&uuid));
+        assert_eq!(&uuid[14..15], "4");
+        assert_ne!(uuid, random_uuid().unwrap());
+
+        assert!(!is_uuid("not a uuid"));
+        assert!(!is_uuid("0123456789abcdef0123456789abcdef0123"));
+        assert!(!is_uuid("01234567-89AB-4def-8123-456789abcdef"));
+        assert!(is_uuid("01234567-89ab-4def-8123-456789abcdef"));
+    }
+
+    #[test]
+    fn test_list_for_other_host() {
+        let path = "/usr/bin:/bin";
+        let options = SudoOptions::try_parse_from(["sudo", "-l", "-h", "lilo"]).unwrap();
+        let context = Context::build_from_options(options, path.to_string(), false).unwrap();
+        assert_eq!(context.hostname, "lilo");
+
+        let options = SudoOptions::try_parse_from(["sudo", "-h", "lilo", "echo"]).unwrap();
+        assert!(Context::build_from_options(options, path.to_string(), false).is_err());
     }
 }
@@ -1,4 +1,10 @@
+use crate::common::i18n::tr;
+use crate::common::json;
+use crate::common::lookup::LookupError;
+use crate::common::style::{self, Style};
+use crate::exec::ExecFailure;
 use crate::pam::PamError;
+use crate::system::capabilities::CapabilitySet;
 use std::{borrow::Cow, fmt, path::PathBuf};
 
 #[derive(Debug)]
@@ -10,27 +16,73 @@ pub enum Error {
         hostname: String,
         other_user: Option<String>,
     },
-    SelfCheck,
+    /// The sudo binary at `path` is owned by `uid` and has `mode`, which does not make it
+    /// setuid root.
+    SelfCheck {
+        path: PathBuf,
+        uid: u32,
+        mode: u32,
+    },
+    /// sudo is installed setuid root, but does not run as root; the message says why.
+    Unprivileged(String),
     CommandNotFound(PathBuf),
     InvalidCommand(PathBuf),
+    Lookup(LookupError),
     ChDirNotAllowed {
         chdir: PathBuf,
         command: PathBuf,
     },
     UserNotFound(String),
     GroupNotFound(String),
+    InvalidShell {
+        username: String,
+        shell: PathBuf,
+    },
     Authentication(String),
     Configuration(String),
+    Plugin(String),
     Options(String),
     Pam(PamError),
     IoError(Option<PathBuf>, std::io::Error),
+    /// Preparing to execute the command failed, before the program itself was executed.
+    ExecFailure(ExecFailure),
     MaxAuthAttempts(usize),
+    /// Sudo was run by a command that this many other invocations of sudo run.
+    NestedSudo(usize),
+}
+
+/// What part of sudo an [`Error`] comes from, which is told in the JSON rendering of an error so
+/// that tools can act on it without parsing a message that may be translated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The command line was invalid, e.g. with a user that does not exist.
+    Usage,
+    /// The policy forbids what was asked, or it could not be loaded.
+    Policy,
+    /// The invoking user could not be authenticated.
+    Auth,
+    /// The command could not be found or executed.
+    Exec,
+    /// Anything else that went wrong on the system.
+    System,
+}
+
+impl ErrorKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorKind::Usage => "usage",
+            ErrorKind::Policy => "policy",
+            ErrorKind::Auth => "auth",
+            ErrorKind::Exec => "exec",
+            ErrorKind::System => "system",
+        }
+    }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Error::Silent => Ok(()),
+        let message = match self {
+            Error::Silent => return Ok(()),
             Error::NotAllowed {
                 username,
                 command,
@@ -38,45 +90,98 @@ impl fmt::Display for Error {
                 other_user,
             } => {
                 if let Some(other_user) = other_user {
-                    write!(
-                        f,
+                    tr!(
                         "Sorry, user {username} is not allowed to execute '{command}' as {other_user} on {hostname}.",
+                        username = username,
+                        command = command,
+                        other_user = other_user,
+                        hostname = hostname,
                     )
                 } else {
-                    write!(
-                        f,
+                    tr!(
                         "Sorry, user {username} may not run {command} on {hostname}.",
+                        username = username,
+                        command = command,
+                        hostname = hostname,
                     )
                 }
             }
-            Error::SelfCheck => {
-                write!(f, "sudo must be owned by uid 0 and have the setuid bit set")
+            Error::SelfCheck { path, uid, mode } => {
+                // changing the owner clears the setuid bit
+                let path = path.display();
+                let fix = if *uid != 0 {
+                    format!("chown root {path} && chmod u+s {path}")
+                } else {
+                    format!("chmod u+s {path}")
+                };
+                let message = tr!(
+                    "sudo must be owned by uid 0 and have the setuid bit set, but {path} is owned by uid {uid} and has mode {mode}; run `{fix}`",
+                    path = path,
+                    uid = uid,
+                    mode = format!("{:04o}", mode & 0o7777),
+                    fix = fix,
+                );
+                if cfg!(target_os = "linux") {
+                    let caps = CapabilitySet::INSTEAD_OF_SETUID;
+                    let fix = if *uid != 0 {
+                        format!("chown root {path} && setcap {caps}+ep {path}")
+                    } else {
+                        format!("setcap {caps}+ep {path}")
+                    };
+                    tr!(
+                        "{message}, or give it the capabilities it needs instead with `{fix}`",
+                        message = message,
+                        fix = fix,
+                    )
+                } else {
+                    message
+                }
+            }
+            Error::Unprivileged(reason) => {
+                tr!("sudo cannot run as root: {reason}", reason = reason)
             }
-            Error::CommandNotFound(p) => write!(f, "'{}': command not found", p.display()),
-            Error::InvalidCommand(p) => write!(f, "'{}': invalid command", p.display()),
-            Error::UserNotFound(u) => write!(f, "user '{u}' not found"),
-            Error::GroupNotFound(g) => write!(f, "group '{g}' not found"),
-            Error::Authentication(e) => write!(f, "authentication failed: {e}"),
-            Error::Configuration(e) => write!(f, "invalid configuration: {e}"),
-            Error::Options(e) => write!(f, "{e}"),
-            Error::Pam(e) => write!(f, "PAM error: {e}"),
+            Error::CommandNotFound(p) => tr!("'{path}': command not found", path = p.display()),
+            Error::InvalidCommand(p) => tr!("'{path}': invalid command", path = p.display()),
+            Error::Lookup(e) => e.to_string(),
+            Error::UserNotFound(u) => tr!("user '{user}' not found", user = u),
+            Error::GroupNotFound(g) => tr!("group '{group}' not found", group = g),
+            Error::InvalidShell { username, shell } => tr!(
+                "invalid shell for user {username}: {shell}",
+                username = username,
+                shell = shell.display()
+            ),
+            Error::Authentication(e) => tr!("authentication failed: {error}", error = e),
+            Error::Configuration(e) => tr!("invalid configuration: {error}", error = e),
+            Error::Plugin(e) => e.to_string(),
+            Error::Options(e) => e.to_string(),
+            Error::Pam(e) => tr!("PAM error: {error}", error = e),
             Error::IoError(location, e) => {
                 if let Some(path) = location {
-                    write!(f, "cannot execute '{}': {e}", path.display())
+                    tr!(
+                        "cannot execute '{path}': {error}",
+                        path = path.display(),
+                        error = e
+                    )
                 } else {
-                    write!(f, "IO error: {e}")
+                    tr!("IO error: {error}", error = e)
                 }
             }
+            Error::ExecFailure(failure) => failure.to_string(),
             Error::MaxAuthAttempts(num) => {
-                write!(f, "Maximum {num} incorrect authentication attempts")
+                tr!("Maximum {num} incorrect authentication attempts", num = num)
             }
-            Error::ChDirNotAllowed { chdir, command } => write!(
-                f,
-                "you are not allowed to use '--chdir {}' with '{}'",
-                chdir.display(),
-                command.display()
+            Error::NestedSudo(depth) => tr!(
+                "sudo may not be run by a command that sudo runs (depth {depth})",
+                depth = depth
             ),
-        }
+            Error::ChDirNotAllowed { chdir, command } => tr!(
+                "you are not allowed to use '--chdir {chdir}' with '{command}'",
+                chdir = chdir.display(),
+                command = command.display()
+            ),
+        };
+
+        f.write_str(&message)
     }
 }
 
@@ -88,7 +193,10 @@ impl From<PamError> for Error {
 
 impl From<std::io::Error> for Error {
     fn from(err: std::io::Error) -> Self {
-        Error::IoError(None, err)
+        match ExecFailure::of(&err) {
+            Some(failure) => Error::ExecFailure(failure),
+            None => Error::IoError(None, err),
+        }
     }
 }
 
@@ -97,6 +205,128 @@ impl Error {
         Self::Authentication(message.to_string())
     }
 
+    /// The exit status of sudo when it fails with this error: like ogsudo, 127 if the command
+    /// cannot be found, 126 if it was found but cannot be executed, and 1 otherwise (e.g. when
+    /// the policy forbids the command, or authentication failed).
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::CommandNotFound(_) => 127,
+            Error::InvalidCommand(_) => 126,
+            Error::Lookup(LookupError::NotFound { .. }) => 127,
+            Error::Lookup(_) => 126,
+            Error::IoError(Some(_), err) if err.kind() == std::io::ErrorKind::NotFound => 127,
+            Error::IoError(Some(_), _) => 126,
+            _ => 1,
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Options(_) | Error::UserNotFound(_) | Error::GroupNotFound(_) => {
+                ErrorKind::Usage
+            }
+            Error::NotAllowed { .. }
+            | Error::ChDirNotAllowed { .. }
+            | Error::Configuration(_)
+            | Error::NestedSudo(_) => ErrorKind::Policy,
+            Error::Authentication(_) | Error::Pam(_) | Error::MaxAuthAttempts(_) => ErrorKind::Auth,
+            Error::CommandNotFound(_)
+            | Error::InvalidCommand(_)
+            | Error::Lookup(_)
+            | Error::IoError(Some(_), _)
+            | Error::ExecFailure(_) => ErrorKind::Exec,
+            Error::Silent
+            | Error::SelfCheck { .. }
+            | Error::Unprivileged(_)
+            | Error::InvalidShell { .. }
+            | Error::Plugin(_)
+            | Error::IoError(None, _) => ErrorKind::System,
+        }
+    }
+
+    /// What the error is about, as fields of a JSON object with their values as JSON.
+    fn context(&self) -> Vec<(&'static str, String)> {
+        let path = |path: &PathBuf| json::string(&path.to_string_lossy());
+        let errno = |err: &std::io::Error| err.raw_os_error().map(|errno| errno.to_string());
+        match self {
+            Error::NotAllowed {
+                username,
+                command,
+                hostname,
+                other_user,
+            } => {
+                let mut fields = vec![
+                    ("user", json::string(username)),
+                    ("command", json::string(command)),
+                    ("host", json::string(hostname)),
+                ];
+                if let Some(other_user) = other_user {
+                    fields.push(("target_user", json::string(other_user)));
+                }
+                fields
+            }
+            Error::ChDirNotAllowed { chdir, command } => {
+                vec![("chdir", path(chdir)), ("command", path(command))]
+            }
+            Error::CommandNotFound(command) | Error::InvalidCommand(command) => {
+                vec![("command", path(command))]
+            }
+            Error::SelfCheck {
+                path: binary,
+                uid,
+                mode,
+            } => vec![
+                ("path", path(binary)),
+                ("uid", uid.to_string()),
+                ("mode", json::string(&format!("{:04o}", mode & 0o7777))),
+            ],
+            Error::UserNotFound(user) => vec![("user", json::string(user))],
+            Error::GroupNotFound(group) => vec![("group", json::string(group))],
+            Error::InvalidShell { username, shell } => {
+                vec![("user", json::string(username)), ("shell", path(shell))]
+            }
+            Error::IoError(location, err) => location
+                .iter()
+                .map(|command| ("command", path(command)))
+                .chain(errno(err).map(|errno| ("errno", errno)))
+                .collect(),
+            Error::ExecFailure(failure) => vec![("errno", failure.errno.to_string())],
+            Error::MaxAuthAttempts(attempts) => vec![("attempts", attempts.to_string())],
+            Error::NestedSudo(depth) => vec![("depth", depth.to_string())],
+            _ => Vec::new(),
+        }
+    }
+
+    /// The error as a JSON object, for `--json`: its kind, the message that is otherwise shown,
+    /// the exit status of sudo and what the error is about.
+    pub fn json(&self) -> String {
+        let mut fields = vec![
+            ("error", json::string(self.kind().as_str())),
+            ("message", json::string(&self.to_string())),
+            ("exit_code", self.exit_code().to_string()),
+        ];
+        fields.extend(self.context());
+        json::object(&fields)
+    }
+
+    /// Report the error to the user on the standard error, as a message that starts with the name
+    /// of `program`, or with `as_json` as a JSON object. A [`Silent`] error has been reported
+    /// already.
+    ///
+    /// [`Silent`]: Error::Silent
+    pub fn render(&self, program: &str, as_json: bool) {
+        if self.is_silent() {
+            return;
+        }
+
+        if as_json {
+            eprintln_ignore_io_error!("{}", self.json().trim_end());
+        } else {
+            let message = style::paint(Style::Error, &self.to_string(), &std::io::stderr());
+            eprintln_ignore_io_error!("{program}: {message}");
+        }
+    }
+
     /// Returns `true` if the error is [`Silent`].
     ///
     /// [`Silent`]: Error::Silent
@@ -105,3 +335,84 @@ impl Error {
         matches!(self, Self::Silent)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::path::PathBuf;
+
+    use super::{Error, ErrorKind};
+
+    #[test]
+    fn exit_codes() {
+        let command = || PathBuf::from("/usr/bin/foo");
+        let not_allowed = Error::NotAllowed {
+            username: "ferris".to_string(),
+            command: "/usr/bin/foo".into(),
+            hostname: "localhost".to_string(),
+            other_user: None,
+        };
+        assert_eq!(not_allowed.exit_code(), 1);
+        assert_eq!(Error::MaxAuthAttempts(3).exit_code(), 1);
+        assert_eq!(Error::auth("interaction required").exit_code(), 1);
+        assert_eq!(Error::Silent.exit_code(), 1);
+
+        assert_eq!(Error::CommandNotFound(command()).exit_code(), 127);
+        let not_found = io::Error::from_raw_os_error(libc::ENOENT);
+        assert_eq!(Error::IoError(Some(command()), not_found).exit_code(), 127);
+
+        assert_eq!(Error::InvalidCommand(command()).exit_code(), 126);
+        for errno in [libc::EACCES, libc::ENOEXEC] {
+            let not_executable = io::Error::from_raw_os_error(errno);
+            assert_eq!(
+                Error::IoError(Some(command()), not_executable).exit_code(),
+                126
+            );
+        }
+
+        // errors that are not about executing the command
+        let io_error = io::Error::from_raw_os_error(libc::ENOENT);
+        assert_eq!(Error::IoError(None, io_error).exit_code(), 1);
+    }
+
+    #[test]
+    fn renders_as_json() {
+        let not_allowed = Error::NotAllowed {
+            username: "ferris".to_string(),
+            command: "/usr/bin/foo".into(),
+            hostname: "localhost".to_string(),
+            other_user: Some("root".to_string()),
+        };
+        assert_eq!(not_allowed.kind(), ErrorKind::Policy);
+        let json = not_allowed.json();
+        assert!(
+            json.starts_with("{\n  \"error\": \"policy\",\n  \"message\": \"Sorry, user ferris")
+        );
+        assert!(json.contains("\"exit_code\": 1,\n"));
+        assert!(json.contains("\"target_user\": \"root\"\n}"));
+
+        let not_found = Error::IoError(
+            Some(PathBuf::from("/usr/bin/foo")),
+            io::Error::from_raw_os_error(libc::ENOENT),
+        );
+        assert_eq!(not_found.kind(), ErrorKind::Exec);
+        let json = not_found.json();
+        assert!(json.contains("\"exit_code\": 127,\n"));
+        assert!(json.contains(&format!("\"errno\": {}\n", libc::ENOENT)));
+
+        let self_check = Error::SelfCheck {
+            path: PathBuf::from("/usr/bin/sudo"),
+            uid: 1000,
+            mode: 0o100755,
+        };
+        assert_eq!(self_check.kind(), ErrorKind::System);
+        assert!(self_check.to_string().contains(
+            "/usr/bin/sudo is owned by uid 1000 and has mode 0755; \
+             run `chown root /usr/bin/sudo && chmod u+s /usr/bin/sudo`"
+        ));
+        assert!(self_check.json().contains("\"mode\": \"0755\"\n}"));
+
+        assert_eq!(Error::MaxAuthAttempts(3).kind(), ErrorKind::Auth);
+        assert_eq!(Error::Options(String::new()).kind(), ErrorKind::Usage);
+    }
+}
@@ -0,0 +1,317 @@
+//! Translation of the texts that are shown to users, using the message catalogs (`.mo` files) of
+//! GNU gettext, so that translations can be made with the usual tools.
+//!
+//! Texts are looked up by their English version; placeholders such as `{user}` are kept in the
+//! translation and are filled in afterwards, so translators can reorder them.
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::system::secure_open;
+
+/// Where the catalogs are installed, as `<locale>/LC_MESSAGES/sudo-rs.mo`.
+const LOCALE_DIR: &str = "/usr/share/locale";
+const DOMAIN: &str = "sudo-rs";
+
+const MAGIC: u32 = 0x950412de;
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+/// The translations of a single message catalog.
+#[derive(Default)]
+struct Catalog {
+    translations: HashMap<String, String>,
+}
+
+impl Catalog {
+    /// Parse a catalog in the `.mo` format, in either byte order.
+    fn parse(bytes: &[u8]) -> Option<Catalog> {
+        let word = |offset: usize, big_endian: bool| -> Option<u32> {
+            let bytes = bytes.get(offset..offset + 4)?.try_into().ok()?;
+            Some(if big_endian {
+                u32::from_be_bytes(bytes)
+            } else {
+                u32::from_le_bytes(bytes)
+            })
+        };
+        let big_endian = match word(0, false)? {
+            MAGIC => false,
+            magic if magic.swap_bytes() == MAGIC => true,
+            _ => return None,
+        };
+        let word = |offset: usize| word(offset, big_endian).map(|w| w as usize);
+        // a string is described by its length and offset in one of the two tables
+        let string = |table: usize, index: usize| -> Option<&str> {
+            let length = word(table + 8 * index)?;
+            let offset = word(table + 8 * index + 4)?;
+            std::str::from_utf8(bytes.get(offset..offset.checked_add(length)?)?).ok()
+        };
+
+        let count = word(8)?;
+        let originals = word(12)?;
+        let translated = word(16)?;
+
+        let mut translations = HashMap::new();
+        for index in 0..count {
+            let original = string(originals, index)?;
+            // only the singular form is used; messages with a context are not
+            let original = original.split('\0').next().unwrap_or_default();
+            let translation = string(translated, index)?;
+            let translation = translation.split('\0').next().unwrap_or_default();
+            // the empty message holds the header of the catalog
+            if original.is_empty() || original.contains('\x04') || translation.is_empty() {
+                continue;
+            }
+            translations.insert(original.to_string(), translation.to_string());
+        }
+
+        Some(Catalog { translations })
+    }
+
+    fn open(locale: &str) -> io::Result<Option<Catalog>> {
+        let path: PathBuf = [LOCALE_DIR, locale, "LC_MESSAGES", &format!("{DOMAIN}.mo")]
+            .iter()
+            .collect();
+        let mut bytes = Vec::new();
+        match secure_open(path, false) {
+            Ok(mut file) => file.read_to_end(&mut bytes)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        Ok(Catalog::parse(&bytes))
+    }
+}
+
+/// Whether a locale name may be used to find a catalog: like the variables in `env_check`, it
+/// must not contain a `/` or `%`, and in addition only the characters that locale names consist
+/// of are allowed, so it cannot refer to a file outside of the locale directory.
+fn is_safe_locale(locale: &str) -> bool {
+    !locale.is_empty()
+        && !locale.starts_with('.')
+        && locale.len() < 64
+        && locale
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '@' | '-'))
+}
+
+/// The locales to look for catalogs of, in order of preference, based on the environment of the
+/// invoking user in the way gettext does: `LANGUAGE` (a list) takes precedence over `LC_ALL`,
+/// `LC_MESSAGES` and `LANG`, unless the locale is `C`.
+fn locale_candidates(var: impl Fn(&str) -> Option<OsString>) -> Vec<String> {
+    let var = |name: &str| var(name).and_then(|value| value.into_string().ok());
+    let Some(locale) = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .filter_map(var)
+        .find(|value| !value.is_empty())
+    else {
+        return Vec::new();
+    };
+    if matches!(locale.as_str(), "C" | "POSIX") || locale.starts_with("C.") {
+        return Vec::new();
+    }
+
+    let mut preferred: Vec<String> = var("LANGUAGE")
+        .map(|languages| languages.split(':').map(str::to_string).collect())
+        .unwrap_or_default();
+    preferred.push(locale);
+
+    // `de_DE.UTF-8@euro` can also be found as `de_DE@euro`, `de_DE`, and `de`
+    let mut candidates = Vec::new();
+    for locale in preferred
+        .into_iter()
+        .filter(|locale| is_safe_locale(locale))
+    {
+        let (base, modifier) = match locale.split_once('@') {
+            Some((base, modifier)) => (base, Some(modifier)),
+            None => (locale.as_str(), None),
+        };
+        let language = base.split(['_', '.']).next().unwrap_or_default();
+        let territory = base.split('.').next().unwrap_or_default();
+        for name in [base, territory, language] {
+            if let Some(modifier) = modifier {
+                candidates.push(format!("{name}@{modifier}"));
+            }
+            candidates.push(name.to_string());
+        }
+    }
+    let mut seen = std::collections::HashSet::new();
+    candidates.retain(|name| !name.is_empty() && seen.insert(name.clone()));
+
+    candidates
+}
+
+/// Select the catalog for the locale of the invoking user; this should be done before anything
+/// is shown to the user, and only has an effect the first time.
+pub fn init_from_env() {
+    let catalog = locale_candidates(|name| std::env::var_os(name))
+        .iter()
+        .find_map(|locale| Catalog::open(locale).ok().flatten())
+        .unwrap_or_default();
+    let _ = CATALOG.set(catalog);
+}
+
+/// The translation of a text, or the text itself if it has not been translated.
+pub fn gettext(msgid: &'static str) -> &'static str {
+    CATALOG
+        .get()
+        .and_then(|catalog| catalog.translations.get(msgid))
+        .map_or(msgid, String::as_str)
+}
+
+/// Fill in the `{name}` placeholders of a (translated) text.
+pub fn substitute(template: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let placeholder = rest[start + 1..]
+            .find('}')
+            .map(|end| &rest[start + 1..start + 1 + end]);
+        match placeholder.and_then(|name| args.iter().find(|(arg, _)| *arg == name)) {
+            Some((name, value)) => {
+                result.push_str(&value.to_string());
+                rest = &rest[start + name.len() + 2..];
+            }
+            None => {
+                result.push('{');
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    result.push_str(rest);
+
+    result
+}
+
+/// Translate a text and fill in its placeholders, e.g.
+/// `tr!("user '{user}' not found", user = name)`.
+macro_rules! tr {
+    ($msgid:literal) => {
+        $crate::common::i18n::gettext($msgid)
+    };
+    ($msgid:literal, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::common::i18n::substitute(
+            $crate::common::i18n::gettext($msgid),
+            &[$((stringify!($name), &$value as &dyn std::fmt::Display)),+],
+        )
+    };
+}
+
+pub(crate) use tr;
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+
+    use super::{is_safe_locale, locale_candidates, substitute, Catalog, MAGIC};
+
+    /// Build a little-endian catalog with the given messages, sorted as msgfmt would.
+    fn catalog(messages: &[(&str, &str)]) -> Vec<u8> {
+        let header = 28;
+        let count = messages.len();
+        let mut strings = Vec::new();
+        let mut tables = [Vec::new(), Vec::new()];
+        let mut offset = header + 16 * count;
+        for (table, pick) in tables.iter_mut().zip([0, 1]) {
+            for message in messages {
+                let text = if pick == 0 { message.0 } else { message.1 };
+                table.extend((text.len() as u32).to_le_bytes());
+                table.extend((offset as u32).to_le_bytes());
+                strings.extend(text.as_bytes());
+                strings.push(0);
+                offset += text.len() + 1;
+            }
+        }
+
+        let mut bytes = Vec::new();
+        for word in [
+            MAGIC,
+            0,
+            count as u32,
+            header as u32,
+            (header + 8 * count) as u32,
+            0,
+            0,
+        ] {
+            bytes.extend(word.to_le_bytes());
+        }
+        bytes.extend(tables.concat());
+        bytes.extend(strings);
+        bytes
+    }
+
+    #[test]
+    fn parses_catalogs() {
+        let bytes = catalog(&[
+            ("", "Content-Type: text/plain; charset=UTF-8\n"),
+            (
+                "user '{user}' not found",
+                "Benutzer »{user}« nicht gefunden",
+            ),
+            ("one file\0{n} files", "eine Datei\0{n} Dateien"),
+        ]);
+        let catalog = Catalog::parse(&bytes).unwrap();
+        assert_eq!(catalog.translations.len(), 2);
+        assert_eq!(
+            catalog.translations["user '{user}' not found"],
+            "Benutzer »{user}« nicht gefunden"
+        );
+        assert_eq!(catalog.translations["one file"], "eine Datei");
+
+        assert!(Catalog::parse(b"not a catalog").is_none());
+        assert!(Catalog::parse(&bytes[..40]).is_none());
+    }
+
+    #[test]
+    fn selects_locales() {
+        let env = |vars: &'static [(&str, &str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| OsString::from(value))
+            }
+        };
+
+        assert_eq!(
+            locale_candidates(env(&[("LANG", "de_DE.UTF-8")])),
+            ["de_DE.UTF-8", "de_DE", "de"]
+        );
+        assert_eq!(
+            locale_candidates(env(&[("LANG", "de_DE.UTF-8"), ("LC_ALL", "nl_BE@euro")])),
+            ["nl_BE@euro", "nl_BE", "nl@euro", "nl"]
+        );
+        assert_eq!(
+            locale_candidates(env(&[("LANGUAGE", "fr:de"), ("LC_MESSAGES", "en_US")])),
+            ["fr", "de", "en_US", "en"]
+        );
+        assert!(locale_candidates(env(&[("LANGUAGE", "fr"), ("LANG", "C.UTF-8")])).is_empty());
+        assert!(locale_candidates(env(&[("LANG", "../../../tmp/x")])).is_empty());
+        assert!(locale_candidates(env(&[])).is_empty());
+    }
+
+    #[test]
+    fn checks_locales() {
+        assert!(is_safe_locale("sr_RS.UTF-8@latin"));
+        assert!(!is_safe_locale(""));
+        assert!(!is_safe_locale(".."));
+        assert!(!is_safe_locale("de/../../etc"));
+        assert!(!is_safe_locale("de%s"));
+    }
+
+    #[test]
+    fn substitutes_placeholders() {
+        assert_eq!(
+            substitute("{b} and {a}", &[("a", &1), ("b", &"two")]),
+            "two and 1"
+        );
+        assert_eq!(substitute("{unknown} {a", &[("a", &1)]), "{unknown} {a");
+        assert_eq!(
+            tr!("user '{user}' not found", user = "ferris"),
+            "user 'ferris' not found"
+        );
+    }
+}
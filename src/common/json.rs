@@ -0,0 +1,163 @@
+//! Writing JSON, for the few places where sudo produces it: the `log.json` file of an I/O log
+//! session and `sudo --version --json`, and reading the simple objects that the control socket of
+//! a session is sent.
+
+/// A string in JSON, with quotes.
+pub fn string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// An array of strings in JSON.
+pub fn string_array<'a>(items: impl IntoIterator<Item = &'a str>) -> String {
+    let items = items.into_iter().map(string).collect::<Vec<_>>().join(", ");
+    format!("[{items}]")
+}
+
+/// An object in JSON, with every field (whose value is JSON already) on a line of its own.
+pub fn object(fields: &[(&str, String)]) -> String {
+    let fields = fields
+        .iter()
+        .map(|(name, value)| format!("  {}: {value}", string(name)))
+        .collect::<Vec<_>>()
+        .join(",\n");
+    format!("{{\n{fields}\n}}\n")
+}
+
+/// The fields of a JSON object, with their values as JSON text; objects and arrays in it are kept
+/// as a whole.
+pub fn fields(text: &str) -> Option<Vec<(String, &str)>> {
+    let mut rest = text.trim().strip_prefix('{')?.trim_start();
+    let mut fields = Vec::new();
+    if let Some(after) = rest.strip_prefix('}') {
+        return after.trim().is_empty().then_some(fields);
+    }
+
+    loop {
+        let (name, after) = split_value(rest)?;
+        rest = after.trim_start().strip_prefix(':')?.trim_start();
+        let (value, after) = split_value(rest)?;
+        fields.push((unquote(name)?, value));
+
+        rest = after.trim_start();
+        match rest.strip_prefix(',') {
+            Some(after) => rest = after.trim_start(),
+            None => return rest.strip_prefix('}')?.trim().is_empty().then_some(fields),
+        }
+    }
+}
+
+/// Split the JSON value at the start of `text` from what follows it.
+fn split_value(text: &str) -> Option<(&str, &str)> {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => {
+                    in_string = false;
+                    if depth == 0 {
+                        return Some(text.split_at(i + 1));
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(text.split_at(i + 1));
+                }
+            }
+            ',' | '}' | ']' | ':' if depth == 0 => return (i > 0).then(|| text.split_at(i)),
+            c if c.is_whitespace() && depth == 0 => return (i > 0).then(|| text.split_at(i)),
+            _ => {}
+        }
+    }
+
+    (depth == 0 && !in_string && !text.is_empty()).then_some((text, ""))
+}
+
+/// The text of a JSON string, without its quotes and escapes.
+pub fn unquote(value: &str) -> Option<String> {
+    let value = value.strip_prefix('"')?.strip_suffix('"')?;
+    let mut text = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        text.push(match chars.next()? {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'u' => {
+                let code = chars.by_ref().take(4).collect::<String>();
+                char::from_u32(u32::from_str_radix(&code, 16).ok()?)?
+            }
+            c @ ('"' | '\\' | '/') => c,
+            _ => return None,
+        });
+    }
+
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_json() {
+        assert_eq!(string("say \"hi\"\n\u{1b}"), r#""say \"hi\"\n\u001b""#);
+        assert_eq!(string_array(["a", "b\\c"]), r#"["a", "b\\c"]"#);
+        assert_eq!(string_array([]), "[]");
+        assert_eq!(
+            object(&[("name", string("sudo-rs")), ("count", 2.to_string())]),
+            "{\n  \"name\": \"sudo-rs\",\n  \"count\": 2\n}\n"
+        );
+    }
+
+    #[test]
+    fn reads_json() {
+        let text = r#"{"jsonrpc": "2.0", "method":"say \"hi\"\u0021", "params": {"a": [1, "}"]}, "id": 7}"#;
+        let fields = fields(text).unwrap();
+        let names = fields
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["jsonrpc", "method", "params", "id"]);
+        assert_eq!(unquote(fields[1].1).unwrap(), "say \"hi\"!");
+        assert_eq!(fields[2].1, r#"{"a": [1, "}"]}"#);
+        assert_eq!(fields[3].1, "7");
+
+        assert_eq!(super::fields(" {} ").unwrap(), []);
+        assert!(super::fields("{\"a\": 1").is_none());
+        assert!(super::fields("{\"a\" 1}").is_none());
+        assert!(super::fields("[1]").is_none());
+        assert!(unquote("\"\\x\"").is_none());
+    }
+}
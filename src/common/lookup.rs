@@ -0,0 +1,394 @@
+//! Finding the executable of a command, and explaining why it could not be found.
+//!
+//! Unlike [super::resolve::resolve_path], a command is looked up with the credentials it will be
+//! executed with, so that a file the target user may not execute is reported as such instead of
+//! failing when it is executed.
+use std::{
+    env, fmt, fs, io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use crate::exec::{ExecFailure, ExecPhase};
+use crate::system::{
+    interface::{GroupId, UserId},
+    Group, User,
+};
+
+use super::{i18n::tr, Error};
+
+/// The credentials that a command will be executed with.
+pub struct Credentials<'a> {
+    pub uid: UserId,
+    pub gid: GroupId,
+    pub groups: &'a [GroupId],
+}
+
+impl<'a> Credentials<'a> {
+    pub fn of(user: &'a User, group: &Group) -> Self {
+        Credentials {
+            uid: user.uid,
+            gid: group.gid,
+            groups: &user.groups,
+        }
+    }
+
+    /// Whether a file may be executed; like `execve`, a file that is not a regular file may not.
+    fn may_execute(&self, metadata: &fs::Metadata) -> bool {
+        if !metadata.is_file() {
+            false
+        } else if self.uid == 0 {
+            // root may execute a file if anyone may
+            metadata.mode() & 0o111 != 0
+        } else {
+            self.has_execute_bit(metadata)
+        }
+    }
+
+    /// Whether a directory may be searched, i.e. whether the files in it may be reached.
+    fn may_search(&self, metadata: &fs::Metadata) -> bool {
+        self.uid == 0 || self.has_execute_bit(metadata)
+    }
+
+    /// Whether the execute bit that applies to these credentials is set.
+    fn has_execute_bit(&self, metadata: &fs::Metadata) -> bool {
+        let mode = metadata.mode();
+        if metadata.uid() == self.uid {
+            mode & 0o100 != 0
+        } else if metadata.gid() == self.gid || self.groups.contains(&metadata.gid()) {
+            mode & 0o010 != 0
+        } else {
+            mode & 0o001 != 0
+        }
+    }
+
+    /// Whether all directories that lead to `path` may be searched: those that are named in it,
+    /// and those that symbolic links in it lead to.
+    fn may_reach(&self, path: &Path) -> bool {
+        let named = match env::current_dir() {
+            Ok(dir) => dir.join(path),
+            Err(_) => path.to_path_buf(),
+        };
+        let resolved = fs::canonicalize(path).ok();
+
+        [Some(named), resolved].iter().flatten().all(|path| {
+            path.ancestors().skip(1).all(
+                |dir| !matches!(fs::metadata(dir), Ok(metadata) if !self.may_search(&metadata)),
+            )
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LookupError {
+    /// There is no such file, in any of the `searched` directories of the PATH.
+    NotFound {
+        command: PathBuf,
+        searched: Vec<PathBuf>,
+    },
+    /// The file exists, but the target user may not execute it (`EACCES`).
+    PermissionDenied(PathBuf),
+    /// The file exists, but is not in a format that can be executed (`ENOEXEC`).
+    NotExecutable(PathBuf),
+}
+
+impl fmt::Display for LookupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            LookupError::NotFound { command, searched } if searched.is_empty() => {
+                tr!("'{path}': command not found", path = command.display())
+            }
+            LookupError::NotFound { command, searched } => {
+                let searched = searched
+                    .iter()
+                    .map(|dir| dir.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(":");
+                tr!(
+                    "'{path}': command not found (searched {searched})",
+                    path = command.display(),
+                    searched = searched
+                )
+            }
+            LookupError::PermissionDenied(path) => {
+                tr!("'{path}': permission denied", path = path.display())
+            }
+            LookupError::NotExecutable(path) => {
+                tr!("'{path}': exec format error", path = path.display())
+            }
+        };
+
+        f.write_str(&message)
+    }
+}
+
+/// The error for a command that failed to execute, where possible in terms of the lookup.
+pub fn exec_error(command: PathBuf, err: io::Error) -> Error {
+    let err = match ExecFailure::of(&err) {
+        // the directory that could not be entered was already reported, along with its path
+        Some(failure) if failure.phase == ExecPhase::Chdir => return Error::Silent,
+        Some(failure) if failure.phase != ExecPhase::Exec => return Error::ExecFailure(failure),
+        Some(failure) => failure.os_error(),
+        None => err,
+    };
+
+    match err.raw_os_error() {
+        Some(libc::ENOENT) => Error::Lookup(LookupError::NotFound {
+            command,
+            searched: Vec::new(),
+        }),
+        Some(libc::EACCES) => Error::Lookup(LookupError::PermissionDenied(command)),
+        Some(libc::ENOEXEC) => Error::Lookup(LookupError::NotExecutable(command)),
+        _ => Error::IoError(Some(command), err),
+    }
+}
+
+/// Check whether a path can be executed with the given credentials; sudo itself may read any
+/// file, so the permissions of the file and of the directories leading to it are checked.
+fn check(path: &Path, credentials: &Credentials) -> Result<(), LookupError> {
+    match fs::metadata(path) {
+        Ok(metadata) if credentials.may_execute(&metadata) && credentials.may_reach(path) => Ok(()),
+        Ok(_) => Err(LookupError::PermissionDenied(path.to_path_buf())),
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+            Err(LookupError::PermissionDenied(path.to_path_buf()))
+        }
+        Err(_) => Err(LookupError::NotFound {
+            command: path.to_path_buf(),
+            searched: Vec::new(),
+        }),
+    }
+}
+
+//checks whether the Path is actually describing a qualified path (i.e. contains "/")
+//or just specifying the name of a file (in which case we are going to resolve it via PATH)
+fn is_qualified(path: impl AsRef<Path>) -> bool {
+    path.as_ref().parent() != Some(Path::new(""))
+}
+
+/// Find the executable of a command: a command that contains a `/` is used as is, others are
+/// searched in the directories of `path`.
+///
+/// To prevent command spoofing, the current directory (`.` or an empty entry) is searched last.
+/// A file that exists but cannot be executed is skipped, like `execvp` does, but is reported if no
+/// executable is found in the other directories.
+pub fn lookup(
+    command: &Path,
+    path: &str,
+    credentials: &Credentials,
+) -> Result<PathBuf, LookupError> {
+    if is_qualified(command) {
+        return check(command, credentials).map(|()| command.to_path_buf());
+    }
+
+    let mut searched: Vec<PathBuf> = path
+        .split(':')
+        .filter(|dir| !dir.is_empty() && *dir != ".")
+        .map(PathBuf::from)
+        .collect();
+    if path.split(':').any(|dir| dir.is_empty() || dir == ".") {
+        if let Ok(dir) = env::current_dir() {
+            searched.push(dir);
+        }
+    }
+
+    let mut first_error = None;
+    for dir in &searched {
+        let candidate = dir.join(command);
+        match check(&candidate, credentials) {
+            Ok(()) => return Ok(candidate),
+            Err(LookupError::NotFound { .. }) => {}
+            Err(err) => {
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+
+    Err(first_error.unwrap_or_else(|| LookupError::NotFound {
+        command: command.to_path_buf(),
+        searched,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    use std::path::PathBuf;
+
+    use super::{exec_error, is_qualified, lookup, Credentials, LookupError};
+    use crate::common::Error;
+    use crate::exec::{ExecFailure, ExecPhase};
+
+    const ROOT: Credentials = Credentials {
+        uid: 0,
+        gid: 0,
+        groups: &[],
+    };
+
+    #[test]
+    fn qualified_paths() {
+        assert!(is_qualified("foo/bar"));
+        assert!(is_qualified("a/b/bar"));
+        assert!(is_qualified("a/b//bar"));
+        assert!(is_qualified("/bar"));
+        assert!(is_qualified("/bar/"));
+        assert!(is_qualified("/bar/foo/"));
+        assert!(is_qualified("/"));
+        assert!(is_qualified("")); // don't try to resolve ""
+        assert!(!is_qualified("bar"));
+    }
+
+    #[test]
+    fn finds_commands() {
+        let path = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+        let found = lookup("sh".as_ref(), path, &ROOT).unwrap();
+        assert!(found.ends_with("sh") && found.is_absolute());
+        assert_eq!(
+            lookup("/bin/sh".as_ref(), "", &ROOT),
+            Ok(PathBuf::from("/bin/sh"))
+        );
+
+        assert_eq!(
+            lookup("thisisnotonyourfs".as_ref(), "/usr/bin:/bin", &ROOT),
+            Err(LookupError::NotFound {
+                command: "thisisnotonyourfs".into(),
+                searched: vec!["/usr/bin".into(), "/bin".into()],
+            })
+        );
+        assert_eq!(
+            lookup("/thisisnotonyourfs".as_ref(), "/bin", &ROOT),
+            Err(LookupError::NotFound {
+                command: "/thisisnotonyourfs".into(),
+                searched: vec![],
+            })
+        );
+        // a directory cannot be executed
+        assert_eq!(
+            lookup("/".as_ref(), "", &ROOT),
+            Err(LookupError::PermissionDenied("/".into()))
+        );
+    }
+
+    #[test]
+    fn checks_permissions() {
+        let dir = std::env::temp_dir().join(format!("sudo-rs-lookup-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("script");
+        fs::write(&script, "#!/bin/sh\n").unwrap();
+        let metadata = fs::metadata(&script).unwrap();
+        let (owner, group) = (metadata.uid(), metadata.gid());
+        let dirs = dir.to_str().unwrap();
+
+        let check = |mode: u32, credentials: &Credentials| {
+            fs::set_permissions(&script, fs::Permissions::from_mode(mode)).unwrap();
+            lookup("script".as_ref(), dirs, credentials)
+        };
+        let owner = Credentials {
+            uid: owner,
+            gid: u32::MAX - 1,
+            groups: &[],
+        };
+        let member = Credentials {
+            uid: u32::MAX - 1,
+            gid: u32::MAX - 1,
+            groups: &[group],
+        };
+        let other = Credentials {
+            uid: u32::MAX - 1,
+            gid: u32::MAX - 1,
+            groups: &[],
+        };
+
+        assert_eq!(check(0o700, &owner), Ok(script.clone()));
+        assert_eq!(check(0o070, &member), Ok(script.clone()));
+        assert_eq!(check(0o001, &other), Ok(script.clone()));
+        assert_eq!(check(0o001, &ROOT), Ok(script.clone()));
+        let denied = Err(LookupError::PermissionDenied(script.clone()));
+        // when running as root, the owner of the file is root, who may execute it anyway
+        if owner.uid != 0 {
+            assert_eq!(check(0o070, &owner), denied);
+        }
+        assert_eq!(check(0o770, &other), denied);
+        assert_eq!(check(0o666, &ROOT), denied);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn checks_directories_on_the_way() {
+        let dir = std::env::temp_dir().join(format!("sudo-rs-lookup-dirs-{}", std::process::id()));
+        let bin = dir.join("bin");
+        fs::create_dir_all(&bin).unwrap();
+        let script = bin.join("script");
+        fs::write(&script, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let link = dir.join("link");
+        std::os::unix::fs::symlink(&bin, &link).unwrap();
+
+        let other = Credentials {
+            uid: u32::MAX - 1,
+            gid: u32::MAX - 1,
+            groups: &[],
+        };
+        let check = |mode: u32, command: &PathBuf| {
+            fs::set_permissions(&bin, fs::Permissions::from_mode(mode)).unwrap();
+            lookup(command, "", &other)
+        };
+
+        assert_eq!(check(0o711, &script), Ok(script.clone()));
+        assert_eq!(
+            check(0o700, &script),
+            Err(LookupError::PermissionDenied(script.clone()))
+        );
+        // the directory is also reached through a symbolic link that leads into it
+        let linked = link.join("script");
+        assert_eq!(check(0o711, &linked), Ok(linked.clone()));
+        assert_eq!(
+            check(0o700, &linked),
+            Err(LookupError::PermissionDenied(linked.clone()))
+        );
+        // root may search any directory
+        assert_eq!(
+            lookup("script".as_ref(), bin.to_str().unwrap(), &ROOT),
+            Ok(script.clone())
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn explains_errors() {
+        let not_found = LookupError::NotFound {
+            command: "foo".into(),
+            searched: vec!["/usr/bin".into(), "/bin".into()],
+        };
+        assert_eq!(
+            not_found.to_string(),
+            "'foo': command not found (searched /usr/bin:/bin)"
+        );
+
+        let error = exec_error(
+            "/tmp/foo".into(),
+            std::io::Error::from_raw_os_error(libc::ENOEXEC),
+        );
+        assert_eq!(error.to_string(), "'/tmp/foo': exec format error");
+        assert_eq!(error.exit_code(), 126);
+        let error = exec_error(
+            "/tmp/foo".into(),
+            std::io::Error::from_raw_os_error(libc::EIO),
+        );
+        assert!(matches!(error, Error::IoError(Some(_), _)));
+
+        let failure = |phase| ExecFailure {
+            phase,
+            errno: libc::EACCES,
+        };
+        let error = exec_error("/tmp/foo".into(), failure(ExecPhase::Exec).into());
+        assert_eq!(error.to_string(), "'/tmp/foo': permission denied");
+        let error = exec_error("/tmp/foo".into(), failure(ExecPhase::Groups).into());
+        assert!(error
+            .to_string()
+            .starts_with("unable to change to the target user's groups: "));
+        assert_eq!(error.exit_code(), 1);
+    }
+}
@@ -0,0 +1,94 @@
+//! The texts that are shown to a user while authenticating. They are kept together so that a
+//! distribution can select a different set when building sudo-rs: by default the sudo-rs texts
+//! are used, and with the `classic-messages` feature the wording of ogsudo. The texts of either
+//! set are translated like all others, see [super::i18n].
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+use super::i18n::gettext;
+
+/// A set of texts; in the prompts, `{name}` is replaced by the name of the program and `{msg}`
+/// by the text that PAM asked to show.
+pub struct MessageSet {
+    /// Asks for input that may be shown while it is typed.
+    pub input_prompt: &'static str,
+    /// Asks for a password.
+    pub password_prompt: &'static str,
+    /// Shows an error reported by PAM.
+    pub error: &'static str,
+    /// Shows information from PAM.
+    pub info: &'static str,
+    /// Shown after a wrong password, when the user may try again.
+    pub bad_password: &'static str,
+}
+
+const DEFAULT: MessageSet = MessageSet {
+    input_prompt: "[{name}: input needed] {msg} ",
+    password_prompt: "[{name}: authenticate] {msg}",
+    error: "[{name} error] {msg}\n",
+    info: "[{name}] {msg}\n",
+    bad_password: "Authentication failed, try again.",
+};
+
+const CLASSIC: MessageSet = MessageSet {
+    input_prompt: "{msg} ",
+    password_prompt: "[{name}] {msg}",
+    error: "{msg}\n",
+    info: "{msg}\n",
+    bad_password: "Sorry, try again.",
+};
+
+/// The texts that were selected when sudo-rs was built.
+pub const MESSAGES: &MessageSet = if cfg!(feature = "classic-messages") {
+    &CLASSIC
+} else {
+    &DEFAULT
+};
+
+/// Shown instead of [MessageSet::bad_password] with `Defaults insults`.
+const INSULTS: &[&str] = &[
+    "Close, but no root shell.",
+    "Have you considered writing it down on a sticky note? Everyone else does.",
+    "I've seen better guesses from a random number generator.",
+    "Your keyboard called, it would like a more competent typist.",
+    "Have you tried turning your brain off and on again?",
+    "That was not it. Neither will the next one be, probably.",
+    "Are you sure you're allowed to be doing this?",
+    "Nice try. Really. No, not really.",
+];
+
+/// Fill in the placeholders of a prompt.
+pub fn fill(template: &str, name: &str, msg: &str) -> String {
+    template.replace("{name}", name).replace("{msg}", msg)
+}
+
+/// The message shown after a wrong password, which is a random insult if `insults` is set.
+pub fn bad_password(insults: bool) -> &'static str {
+    if insults {
+        // no need for a good source of randomness to pick an insult
+        let index = RandomState::new().build_hasher().finish() as usize % INSULTS.len();
+        gettext(INSULTS[index])
+    } else {
+        gettext(MESSAGES.bad_password)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bad_password, fill, INSULTS, MESSAGES};
+
+    #[test]
+    fn fills_placeholders() {
+        assert_eq!(
+            fill("[{name}: authenticate] {msg}", "sudo", "Password: "),
+            "[sudo: authenticate] Password: "
+        );
+        assert_eq!(fill("{msg}\n", "su", "{name}"), "{name}\n");
+    }
+
+    #[test]
+    fn insults_only_when_asked() {
+        assert_eq!(bad_password(false), MESSAGES.bad_password);
+        assert!(INSULTS.contains(&bad_password(true)));
+    }
+}
@@ -9,6 +9,12 @@ pub mod bin_serde;
 pub mod command;
 pub mod context;
 pub mod error;
+pub mod i18n;
+pub mod json;
+pub mod lookup;
+pub mod messages;
+pub mod quote;
 pub mod resolve;
+pub mod style;
 
 pub type Environment = HashMap<OsString, OsString>;
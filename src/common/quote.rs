@@ -0,0 +1,80 @@
+//! Quoting of arguments for a shell, used to turn the arguments of e.g. `sudo -s` or `sudo -i`
+//! into the single command string that is passed to the shell with `-c`.
+
+/// Escape an argument so that a shell reads it back as a single word with the same value.
+///
+/// Like ogsudo, every character except alphanumerics, underscores, hyphens, and dollar signs is
+/// escaped with a backslash. A backslash followed by a newline is a line continuation, so newlines
+/// are quoted instead, and an empty argument becomes `''` so it is not lost.
+pub fn escape(arg: &str) -> String {
+    if arg.is_empty() {
+        return "''".to_string();
+    }
+
+    let mut escaped = String::with_capacity(arg.len());
+    for c in arg.chars() {
+        match c {
+            '_' | '-' | '$' => escaped.push(c),
+            c if c.is_alphanumeric() => escaped.push(c),
+            '\n' => escaped.push_str("'\n'"),
+            c => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+        }
+    }
+
+    escaped
+}
+
+/// Escape all arguments and join them into a command line.
+pub fn join<S: AsRef<str>>(args: impl IntoIterator<Item = S>) -> String {
+    args.into_iter()
+        .map(|arg| escape(arg.as_ref()))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::join;
+
+    #[test]
+    fn test_escaped() {
+        let test = |src: &[&str], target: &str| {
+            assert_eq!(&join(src), target);
+        };
+        test(&["a", "b", "c"], "a b c");
+        test(&["a", "b c"], "a b\\ c");
+        test(&["a", "b-c"], "a b-c");
+        test(&["a", "b#c"], "a b\\#c");
+        test(&["1 2 3"], "1\\ 2\\ 3");
+        test(&["! @ $"], "\\!\\ \\@\\ $");
+        test(&["a", "", "b"], "a '' b");
+        test(&["a\nb"], "a'\n'b");
+    }
+
+    #[test]
+    fn survives_the_shell() {
+        let args = [
+            "it's",
+            "\"quoted\"",
+            "two  spaces",
+            "",
+            "new\nline",
+            "back\\slash",
+            "*",
+        ];
+        let output = Command::new("/bin/sh")
+            .arg("-c")
+            .arg(format!("printf '%s|' {}", join(args)))
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            args.map(|arg| format!("{arg}|")).concat()
+        );
+    }
+}
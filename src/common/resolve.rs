@@ -1,7 +1,9 @@
 use crate::cli::SudoOptions;
-use crate::system::{Group, User};
+use crate::system::{can_execute, Group, User};
 use std::{
-    env, fs, io,
+    env,
+    ffi::OsString,
+    fs, io,
     os::unix::prelude::MetadataExt,
     path::{Path, PathBuf},
     str::FromStr,
@@ -9,6 +11,9 @@ use std::{
 
 use super::{context::LaunchType, Error};
 
+const VALID_LOGIN_SHELLS_LIST: &str = "/etc/shells";
+const FALLBACK_LOGIN_SHELL: &str = "/bin/sh";
+
 #[derive(PartialEq, Debug)]
 enum NameOrId<'a, T: FromStr> {
     Name(&'a str),
@@ -51,16 +56,20 @@ pub(super) fn resolve_launch_and_shell(
     }
 }
 
+/// Resolve the target user and group of `-u` and `-g`; with `allow_unknown_id`, a `#<id>` that is
+/// not in the user or group database stands for a user or group that only has that id.
 pub(crate) fn resolve_target_user_and_group(
     target_user_name_or_id: &Option<String>,
     target_group_name_or_id: &Option<String>,
     current_user: &User,
+    allow_unknown_id: bool,
 ) -> Result<(User, Group), Error> {
     // resolve user name or #<id> to a user
     let mut target_user =
         match NameOrId::parse(target_user_name_or_id.as_deref().unwrap_or_default()) {
             Some(NameOrId::Name(name)) => User::from_name(name)?,
-            Some(NameOrId::Id(uid)) => User::from_uid(uid)?,
+            Some(NameOrId::Id(uid)) => User::from_uid(uid)?
+                .or_else(|| allow_unknown_id.then(|| User::unknown(uid, current_user.gid))),
             _ => None,
         };
 
@@ -68,7 +77,9 @@ pub(crate) fn resolve_target_user_and_group(
     let mut target_group =
         match NameOrId::parse(target_group_name_or_id.as_deref().unwrap_or_default()) {
             Some(NameOrId::Name(name)) => Group::from_name(name)?,
-            Some(NameOrId::Id(gid)) => Group::from_gid(gid)?,
+            Some(NameOrId::Id(gid)) => {
+                Group::from_gid(gid)?.or_else(|| allow_unknown_id.then(|| Group::unknown(gid)))
+            }
             _ => None,
         };
 
@@ -125,6 +136,20 @@ pub(crate) fn is_valid_executable(path: &PathBuf) -> bool {
     }
 }
 
+/// Check that a shell is listed in /etc/shells; if that file does not exist, only /bin/sh
+/// is considered to be a valid shell
+pub(crate) fn is_valid_shell(shell: &Path) -> bool {
+    let Some(pattern) = shell.as_os_str().to_str() else {
+        return false;
+    };
+
+    if let Ok(contents) = fs::read_to_string(VALID_LOGIN_SHELLS_LIST) {
+        contents.lines().any(|l| l == pattern)
+    } else {
+        FALLBACK_LOGIN_SHELL == pattern
+    }
+}
+
 /// Resolve a executable name based in the PATH environment variable
 /// When resolving a path, this code checks whether the target file is
 /// a regular file and has any executable bits set. It does not specifically
@@ -171,6 +196,49 @@ pub(crate) fn resolve_path(command: &Path, path: &str) -> Option<PathBuf> {
         })
 }
 
+/// Choose the editor for visudo and sudoedit.
+///
+/// The editor of the user (`SUDO_EDITOR`, `VISUAL` or `EDITOR`, looked up with `var`) is used if
+/// `env_editor` is set, or otherwise only if it is one of the colon-separated `editors`. If the
+/// user did not pick a usable editor, the first entry of `editors` that can be executed is used.
+/// Editors that do not resolve to an absolute path are never used.
+pub(crate) fn resolve_editor(
+    editors: &str,
+    env_editor: bool,
+    var: impl Fn(&str) -> Option<OsString>,
+) -> Option<PathBuf> {
+    let editors = editors
+        .split(':')
+        .map(Path::new)
+        .filter(|path| path.is_absolute())
+        .collect::<Vec<_>>();
+
+    let user_editor = ["SUDO_EDITOR", "VISUAL", "EDITOR"]
+        .into_iter()
+        .filter_map(&var)
+        .filter(|editor| !editor.is_empty())
+        .find_map(|editor| {
+            let editor = PathBuf::from(editor);
+            if editor.components().count() > 1 {
+                can_execute(&editor).then_some(editor)
+            } else {
+                let path = var("PATH")
+                    .and_then(|path| path.into_string().ok())
+                    .unwrap_or(env!("DEFAULT_PATH").to_string());
+                resolve_path(&editor, &path)
+            }
+        })
+        .filter(|editor| editor.is_absolute())
+        .filter(|editor| env_editor || editors.contains(&editor.as_path()));
+
+    user_editor.or_else(|| {
+        editors
+            .into_iter()
+            .find(|editor| can_execute(editor))
+            .map(Path::to_path_buf)
+    })
+}
+
 /// Resolve the use of a '~' that occurs in a PathBuf; based on the sudoers context
 pub(crate) fn expand_tilde_in_path(
     default_user: &str,
@@ -198,12 +266,14 @@ pub(crate) fn expand_tilde_in_path(
 
 #[cfg(test)]
 mod tests {
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
 
     use super::{
-        is_valid_executable, resolve_current_user, resolve_path, resolve_target_user_and_group,
-        NameOrId,
+        is_valid_executable, is_valid_shell, resolve_current_user, resolve_editor, resolve_path,
+        resolve_target_user_and_group, NameOrId,
     };
+    use crate::system::nss::{use_database, Database};
+    use crate::system::User;
 
     #[test]
     fn test_resolve_path() {
@@ -228,6 +298,53 @@ mod tests {
         assert_eq!(resolve_path(&PathBuf::from("thisisnotonyourfs"), "."), None);
     }
 
+    #[test]
+    fn test_resolve_editor() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |key: &str| {
+                vars.iter()
+                    .find(|(name, _)| *name == key)
+                    .map(|(_, value)| value.into())
+            }
+        };
+        let path = |path: &str| Some(PathBuf::from(path));
+
+        // the first editor in the list that exists
+        assert_eq!(
+            resolve_editor("/not/an/editor:/bin/sh:/bin/cat", true, env(&[])),
+            path("/bin/sh")
+        );
+        // relative entries are ignored
+        assert_eq!(resolve_editor("sh", true, env(&[])), None);
+
+        // the editor of the user comes first, and is searched in the PATH
+        let vars = env(&[("VISUAL", "cat"), ("EDITOR", "/bin/sh"), ("PATH", "/bin")]);
+        assert_eq!(resolve_editor("/bin/sh", true, vars), path("/bin/cat"));
+        let vars = env(&[("SUDO_EDITOR", "/bin/cat"), ("VISUAL", "/bin/sh")]);
+        assert_eq!(resolve_editor("/bin/sh", true, vars), path("/bin/cat"));
+        // but only if it can be found, and its path is absolute
+        let vars = env(&[("SUDO_EDITOR", "/not/an/editor")]);
+        assert_eq!(resolve_editor("/bin/sh", true, vars), path("/bin/sh"));
+        let vars = env(&[("EDITOR", "cat"), ("PATH", "bin")]);
+        assert_eq!(resolve_editor("/bin/sh", true, vars), path("/bin/sh"));
+
+        // without env_editor, the editor of the user has to be in the list
+        let vars = env(&[("EDITOR", "/bin/cat")]);
+        assert_eq!(resolve_editor("/bin/sh", false, vars), path("/bin/sh"));
+        let vars = env(&[("EDITOR", "/bin/cat")]);
+        assert_eq!(
+            resolve_editor("/bin/sh:/bin/cat", false, vars),
+            path("/bin/cat")
+        );
+    }
+
+    #[test]
+    fn test_is_valid_shell() {
+        assert!(is_valid_shell(&PathBuf::from("/bin/sh")));
+        assert!(!is_valid_shell(&PathBuf::from("/not/a/shell")));
+        assert!(!is_valid_shell(&PathBuf::from("sh")));
+    }
+
     #[test]
     fn test_name_or_id() {
         assert_eq!(NameOrId::<u32>::parse(""), None);
@@ -239,10 +356,16 @@ mod tests {
 
     #[test]
     fn test_resolve_target_user_and_group() {
+        let mut database = Database::default().with_user("root", 0);
+        if User::real_uid() != 0 {
+            database = database.with_user("ferris", User::real_uid());
+        }
+        use_database(database);
         let current_user = resolve_current_user().unwrap();
 
         // fallback to root
-        let (user, group) = resolve_target_user_and_group(&None, &None, &current_user).unwrap();
+        let (user, group) =
+            resolve_target_user_and_group(&None, &None, &current_user, false).unwrap();
         assert_eq!(user.name, "root");
         assert_eq!(group.name, "root");
 
@@ -251,6 +374,7 @@ mod tests {
             &Some("non_existing_ghost".to_string()),
             &None,
             &current_user,
+            false,
         );
         assert!(result.is_err());
 
@@ -259,12 +383,14 @@ mod tests {
             &None,
             &Some("non_existing_ghost".to_string()),
             &current_user,
+            false,
         );
         assert!(result.is_err());
 
         // fallback to current user when different group specified
         let (user, group) =
-            resolve_target_user_and_group(&None, &Some("root".to_string()), &current_user).unwrap();
+            resolve_target_user_and_group(&None, &Some("root".to_string()), &current_user, false)
+                .unwrap();
         assert_eq!(user.name, current_user.name);
         assert_eq!(group.name, "root");
 
@@ -273,10 +399,31 @@ mod tests {
             &Some(current_user.name.to_string()),
             &None,
             &current_user,
+            false,
         )
         .unwrap();
         assert_eq!(user.name, current_user.name);
         assert_eq!(group.gid, current_user.gid);
+
+        // ids that are in neither database only with runas_allow_unknown_id
+        let unknown = (
+            Some("#4000000001".to_string()),
+            Some("#4000000002".to_string()),
+        );
+        assert!(resolve_target_user_and_group(&unknown.0, &None, &current_user, false).is_err());
+        assert!(resolve_target_user_and_group(&None, &unknown.1, &current_user, false).is_err());
+        let (user, group) =
+            resolve_target_user_and_group(&unknown.0, &None, &current_user, true).unwrap();
+        assert_eq!((user.uid, user.name.as_str()), (4000000001, "#4000000001"));
+        assert_eq!(user.shell, Path::new("/bin/sh"));
+        assert_eq!(group.gid, current_user.gid);
+        let (user, group) =
+            resolve_target_user_and_group(&unknown.0, &unknown.1, &current_user, true).unwrap();
+        assert_eq!(user.uid, 4000000001);
+        assert_eq!(
+            (group.gid, group.name.as_str()),
+            (4000000002, "#4000000002")
+        );
     }
 }
 
@@ -0,0 +1,72 @@
+//! Styling of the text that sudo shows to the user: its error messages (such as the message that a
+//! command is not allowed) in bold red, and the headings in the output of `sudo -l` in bold.
+//!
+//! This is only done with `Defaults color`, and only for text that is written to a terminal while
+//! `NO_COLOR` is not set (see <https://no-color.org>); otherwise the text is left as it is.
+use std::ffi::OsString;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Use colors from now on if `color` is set, i.e. with `Defaults color`.
+pub fn use_color(color: bool) {
+    ENABLED.store(color, Ordering::Relaxed);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Style {
+    Error,
+    Heading,
+}
+
+impl Style {
+    /// The SGR parameters of the escape sequence that starts the style.
+    fn parameters(self) -> &'static str {
+        match self {
+            Style::Error => "1;31",
+            Style::Heading => "1",
+        }
+    }
+}
+
+/// Whether text that is written to a terminal is styled, given the value of `NO_COLOR`.
+fn colored(enabled: bool, terminal: bool, no_color: Option<OsString>) -> bool {
+    enabled && terminal && no_color.map_or(true, |value| value.is_empty())
+}
+
+/// `text` in `style`, if it is written to `stream` and colors are used.
+pub fn paint(style: Style, text: &str, stream: &impl IsTerminal) -> String {
+    if colored(
+        ENABLED.load(Ordering::Relaxed),
+        stream.is_terminal(),
+        std::env::var_os("NO_COLOR"),
+    ) {
+        format!("\x1b[{}m{text}\x1b[0m", style.parameters())
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn respects_no_color() {
+        assert!(colored(true, true, None));
+        assert!(colored(true, true, Some("".into())));
+        assert!(!colored(true, true, Some("1".into())));
+        assert!(!colored(true, false, None));
+        assert!(!colored(false, true, None));
+    }
+
+    #[test]
+    fn leaves_text_alone_without_color() {
+        assert_eq!(
+            paint(Style::Error, "not allowed", &std::io::stderr()),
+            "not allowed"
+        );
+        assert_eq!(Style::Heading.parameters(), "1");
+    }
+}
@@ -25,23 +25,71 @@ use settings_dsl::*;
 defaults! {
     always_query_group_plugin = false
     always_set_home           = false
+    set_home                  = false
     env_reset                 = true
+    setenv                    = false
     mail_badpass              = true
     match_group_by_gid        = false
     use_pty                   = true
+    runas_check_shell         = false
+    runaspw                   = false
+    stay_setuid               = false
     visiblepw                 = false
     env_editor                = true
+    insults                   = false
+    color                     = false
+    tty_signals               = false
+    tty_flow_control          = false
+    pam_session               = true
+    log_input                 = false
+    log_output                = false
+    mail_all_cmnds            = false
+    sudoedit_follow           = false
+    compress_io               = (cfg!(feature = "iolog-compression"))
+    iolog_flush               = false
+    log_exit_status           = false
+    noexec                    = false
+    intercept                 = false
+    fqdn                      = false
+    runas_allow_unknown_id    = false
 
     passwd_tries              = 3 [0..=1000]
+    pty_wait_for_eof          = 0 (!= 0) [0..=3600]
+    syslog_dedup_window       = 0 (!= 0) [0..=86400]
+    approval_timeout          = 300 [1..=86400]
+    syslog_port               = 514 [1..=65535]
+    maxseq                    = 2176782336 [1..=2176782336]
+    iolog_mode                = 0o600 [0..=0o777; radix: 8]
 
     secure_path               = None (!= None)
+    cgroup                    = None (!= None)
+    pty_group                 = (Some("tty")) (!= None)
+    runas_default             = "root"
+    editor                    = "/usr/bin/editor"
+    iolog_dir                 = "/var/log/sudo-io"
+    iolog_user                = "root" (!= "root")
+    iolog_group               = None (!= None)
+    iolog_recipient           = None (!= None)
+    tee_output                = None (!= None)
+    approval                  = None (!= None)
+    syslog_server             = None (!= None)
+    syslog_cabundle           = None (!= None)
     verifypw                  = "all" (!= "never") [all, always, any, never]
+    fdexec                    = "digest_only" (!= "never") [always, digest_only, never]
+    nested_sudo               = "ignore" (!= "ignore") [ignore, log, warn, deny]
+    output_quota_action       = "truncate" [truncate, terminate]
+    syslog_protocol           = "udp" [udp, tcp, tls]
 
     timestamp_timeout         = (15*60) (!= 0) {fractional_minutes}
+    session_reauth            = 0 (!= 0) {duration}
+    idle_timeout              = 0 (!= 0) {duration}
+    output_quota              = 0 (!= 0) {size}
 
     env_keep                  = ["COLORS", "DISPLAY", "HOSTNAME", "KRB5CCNAME", "LS_COLORS", "PATH",
                                  "PS1", "PS2", "XAUTHORITY", "XAUTHORIZATION", "XDG_CURRENT_DESKTOP"]
 
+    forward_signals           = ["ALL"]
+
     env_check                 = ["COLORTERM", "LANG", "LANGUAGE", "LC_*", "LINGUAS", "TERM", "TZ"]
 
     env_delete                = ["IFS", "CDPATH", "LOCALDOMAIN", "RES_OPTIONS", "HOSTALIASES",
@@ -63,6 +111,161 @@ fn fractional_minutes(input: &str) -> Option<i64> {
     }
 }
 
+/// A custom parser for a length of time in seconds, such as `30m` or `1h30m`, with `d`, `h`, `m`
+/// and `s` as units; a number without a unit is in minutes, as for timestamp_timeout.
+fn duration(input: &str) -> Option<i64> {
+    if input.bytes().all(|c| c.is_ascii_digit() || c == b'.') {
+        return fractional_minutes(input);
+    }
+
+    let mut seconds = 0i64;
+    let mut rest = input;
+    while !rest.is_empty() {
+        let (value, tail) = rest.split_at(rest.find(|c: char| !c.is_ascii_digit())?);
+        let mut tail = tail.chars();
+        let unit = match tail.next()? {
+            'd' => 24 * 60 * 60,
+            'h' => 60 * 60,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        seconds = seconds.checked_add(value.parse::<i64>().ok()?.checked_mul(unit)?)?;
+        rest = tail.as_str();
+    }
+
+    Some(seconds)
+}
+
+/// A custom parser for a number of bytes, which may end in `K`, `M` or `G` for kibibytes, mebibytes
+/// or gibibytes.
+fn size(input: &str) -> Option<i64> {
+    let (number, unit) = match input.char_indices().last()? {
+        (end, 'K' | 'k') => (&input[..end], 1 << 10),
+        (end, 'M') => (&input[..end], 1 << 20),
+        (end, 'G') => (&input[..end], 1 << 30),
+        _ => (input, 1),
+    };
+
+    number.parse::<i64>().ok()?.checked_mul(unit)
+}
+
+/// What a setting is and the values it accepts, which is used to explain how to write a setting
+/// in diagnostics, and is listed by `visudo -x schema`.
+pub struct Schema {
+    pub name: &'static str,
+    pub setting: SudoDefault,
+    bounds: Option<(i64, i64, u32)>,
+}
+
+/// The schema of a setting, if there is a setting called `name`.
+pub fn schema(name: &str) -> Option<Schema> {
+    let name = ALL_PARAMS.iter().find(|param| **param == name)?;
+
+    Some(Schema {
+        name,
+        setting: sudo_default(name)?,
+        bounds: integer_bounds(name),
+    })
+}
+
+/// The schemas of all settings, in the order in which they are defined.
+pub fn all_schemas() -> impl Iterator<Item = Schema> {
+    ALL_PARAMS.iter().filter_map(|name| schema(name))
+}
+
+impl Schema {
+    pub fn type_name(&self) -> &'static str {
+        match self.setting {
+            SudoDefault::Flag(_) => "flag",
+            SudoDefault::Integer(..) => "integer",
+            SudoDefault::Text(_) => "text",
+            SudoDefault::List(_) => "list",
+            SudoDefault::Enum(_) => "enum",
+        }
+    }
+
+    fn negatable(&self) -> bool {
+        match &self.setting {
+            SudoDefault::Flag(_) | SudoDefault::List(_) => true,
+            SudoDefault::Integer(OptTuple { negated, .. }, _) => negated.is_some(),
+            SudoDefault::Text(OptTuple { negated, .. }) => negated.is_some(),
+            SudoDefault::Enum(OptTuple { negated, .. }) => negated.is_some(),
+        }
+    }
+
+    /// The ways in which the setting can be written in a `Defaults` line.
+    pub fn syntax(&self) -> String {
+        let name = self.name;
+        let value = match &self.setting {
+            SudoDefault::Flag(_) => return format!("Defaults {name}, Defaults !{name}"),
+            SudoDefault::Integer(..) => match self.bounds {
+                Some((first, last, 8)) => format!("<octal number from {first:o} to {last:o}>"),
+                Some((first, last, _)) => format!("<number from {first} to {last}>"),
+                None => "<number>".to_string(),
+            },
+            SudoDefault::Text(_) => "\"<text>\"".to_string(),
+            SudoDefault::List(_) => {
+                return format!(
+                    "Defaults {name} = \"<item> ...\", Defaults {name} += \"<item> ...\", \
+                     Defaults {name} -= \"<item> ...\", Defaults !{name}"
+                )
+            }
+            SudoDefault::Enum(OptTuple { default, .. }) => default.possible_values.join("|"),
+        };
+
+        if self.negatable() {
+            format!("Defaults {name} = {value}, Defaults !{name}")
+        } else {
+            format!("Defaults {name} = {value}")
+        }
+    }
+
+    /// The value that the setting has if it is not set in the sudoers file.
+    pub fn default_value(&self) -> String {
+        match &self.setting {
+            SudoDefault::Flag(value) => value.to_string(),
+            SudoDefault::Integer(OptTuple { default, .. }, _) => match self.bounds {
+                Some((_, _, 8)) => format!("{default:o}"),
+                _ => default.to_string(),
+            },
+            SudoDefault::Text(OptTuple { default, .. }) => match default {
+                Some(text) => format!("\"{text}\""),
+                None => "(none)".to_string(),
+            },
+            SudoDefault::List(items) => format!("\"{}\"", items.join(" ")),
+            SudoDefault::Enum(OptTuple { default, .. }) => default.get().to_string(),
+        }
+    }
+}
+
+/// The setting whose name is closest to `name`, if there is one that is only a few typos away.
+pub fn similar_setting(name: &str) -> Option<&'static str> {
+    ALL_PARAMS
+        .iter()
+        .map(|candidate| (edit_distance(name, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The number of characters that have to be inserted, removed or replaced to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, x) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, y) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(x != *y);
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -89,20 +292,133 @@ mod test {
 
         test! { always_query_group_plugin => Flag(false) };
         test! { always_set_home => Flag(false) };
+        test! { set_home => Flag(false) };
         test! { env_reset => Flag(true) };
+        test! { setenv => Flag(false) };
         test! { mail_badpass => Flag(true) };
         test! { match_group_by_gid => Flag(false) };
         test! { use_pty => Flag(true) };
+        test! { runas_check_shell => Flag(false) };
+        test! { runaspw => Flag(false) };
+        test! { stay_setuid => Flag(false) };
         test! { visiblepw => Flag(false) };
         test! { env_editor => Flag(true) };
+        test! { insults => Flag(false) };
+        test! { color => Flag(false) };
+        test! { tty_signals => Flag(false) };
+        test! { tty_flow_control => Flag(false) };
+        test! { pam_session => Flag(true) };
+        test! { log_input => Flag(false) };
+        test! { log_output => Flag(false) };
+        test! { mail_all_cmnds => Flag(false) };
+        test! { sudoedit_follow => Flag(false) };
+        test! { compress_io => Flag(cfg!(feature = "iolog-compression")) };
+        test! { iolog_flush => Flag(false) };
+        test! { log_exit_status => Flag(false) };
+        test! { noexec => Flag(false) };
+        test! { intercept => Flag(false) };
+        test! { fqdn => Flag(false) };
+        test! { runas_allow_unknown_id => Flag(false) };
         test! { passwd_tries => Integer(OptTuple { default: 3, negated: None }, _) };
+        test! { pty_wait_for_eof => Integer(OptTuple { default: 0, negated: Some(0) }, _) };
+        test! { syslog_dedup_window => Integer(OptTuple { default: 0, negated: Some(0) }, _) };
+        test! { approval_timeout => Integer(OptTuple { default: 300, negated: None }, _) };
+        test! { syslog_port => Integer(OptTuple { default: 514, negated: None }, _) };
+        test! { maxseq => Integer(OptTuple { default: 2176782336, negated: None }, _) };
+        test! { iolog_mode => Integer(OptTuple { default: 0o600, negated: None }, _) };
+        test! { session_reauth => Integer(OptTuple { default: 0, negated: Some(0) }, _) };
+        test! { idle_timeout => Integer(OptTuple { default: 0, negated: Some(0) }, _) };
+        test! { output_quota => Integer(OptTuple { default: 0, negated: Some(0) }, _) };
         test! { secure_path => Text(OptTuple { default: None, negated: Some(None) }) };
+        test! { cgroup => Text(OptTuple { default: None, negated: Some(None) }) };
+        test! { pty_group => Text(OptTuple { default: Some("tty"), negated: Some(None) }) };
+        test! { runas_default => Text(OptTuple { default: Some("root"), negated: None }) };
+        test! { editor => Text(OptTuple { default: Some("/usr/bin/editor"), negated: None }) };
+        test! { iolog_dir => Text(OptTuple { default: Some("/var/log/sudo-io"), negated: None }) };
+        test! { iolog_user => Text(OptTuple { default: Some("root"), negated: Some(Some("root")) }) };
+        test! { iolog_group => Text(OptTuple { default: None, negated: Some(None) }) };
+        test! { iolog_recipient => Text(OptTuple { default: None, negated: Some(None) }) };
+        test! { tee_output => Text(OptTuple { default: None, negated: Some(None) }) };
+        test! { approval => Text(OptTuple { default: None, negated: Some(None) }) };
+        test! { syslog_server => Text(OptTuple { default: None, negated: Some(None) }) };
+        test! { syslog_cabundle => Text(OptTuple { default: None, negated: Some(None) }) };
         test! { env_keep => List(_) };
         test! { env_check => List(["COLORTERM", "LANG", "LANGUAGE", "LC_*", "LINGUAS", "TERM", "TZ"]) };
         test! { env_delete => List(_) };
+        test! { forward_signals => List(["ALL"]) };
         test! { verifypw => Enum(OptTuple { default: StrEnum { value: "all", possible_values: [_, "always", "any", _] }, negated: Some(StrEnum { value: "never", .. }) }) };
+        test! { fdexec => Enum(OptTuple { default: StrEnum { value: "digest_only", possible_values: ["always", _, "never"] }, negated: Some(StrEnum { value: "never", .. }) }) };
+        test! { nested_sudo => Enum(OptTuple { default: StrEnum { value: "ignore", possible_values: [_, "log", "warn", "deny"] }, negated: Some(StrEnum { value: "ignore", .. }) }) };
+        test! { output_quota_action => Enum(OptTuple { default: StrEnum { value: "truncate", possible_values: [_, "terminate"] }, negated: None }) };
+        test! { syslog_protocol => Enum(OptTuple { default: StrEnum { value: "udp", possible_values: [_, "tcp", "tls"] }, negated: None }) };
 
         let myenum = StrEnum::new("hello", &["hello", "goodbye"]).unwrap();
         assert!(&myenum as &str == "hello");
     }
+
+    #[test]
+    fn describes_settings() {
+        let syntax = |name| schema(name).unwrap().syntax();
+        assert_eq!(syntax("use_pty"), "Defaults use_pty, Defaults !use_pty");
+        assert_eq!(
+            syntax("passwd_tries"),
+            "Defaults passwd_tries = <number from 0 to 1000>"
+        );
+        assert_eq!(
+            syntax("iolog_mode"),
+            "Defaults iolog_mode = <octal number from 0 to 777>"
+        );
+        assert_eq!(
+            syntax("timestamp_timeout"),
+            "Defaults timestamp_timeout = <number>, Defaults !timestamp_timeout"
+        );
+        assert_eq!(syntax("editor"), "Defaults editor = \"<text>\"");
+        assert_eq!(
+            syntax("verifypw"),
+            "Defaults verifypw = all|always|any|never, Defaults !verifypw"
+        );
+        assert!(syntax("env_keep").contains("Defaults env_keep += \"<item> ...\""));
+
+        assert_eq!(schema("iolog_mode").unwrap().default_value(), "600");
+        assert_eq!(schema("secure_path").unwrap().default_value(), "(none)");
+        assert_eq!(schema("forward_signals").unwrap().type_name(), "list");
+        assert!(schema("env_kep").is_none());
+        assert_eq!(all_schemas().count(), ALL_PARAMS.len());
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(duration("30"), Some(30 * 60));
+        assert_eq!(duration("2.5"), Some(150));
+        assert_eq!(duration("30m"), Some(30 * 60));
+        assert_eq!(duration("1h30m"), Some(90 * 60));
+        assert_eq!(duration("1d12h"), Some(36 * 60 * 60));
+        assert_eq!(duration("45s"), Some(45));
+        assert_eq!(duration("m"), None);
+        assert_eq!(duration("10x"), None);
+        assert_eq!(duration("1h30"), None);
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(size("4096"), Some(4096));
+        assert_eq!(size("64K"), Some(64 * 1024));
+        assert_eq!(size("10M"), Some(10 * 1024 * 1024));
+        assert_eq!(size("2G"), Some(2 * 1024 * 1024 * 1024));
+        assert_eq!(size("G"), None);
+        assert_eq!(size("10T"), None);
+    }
+
+    #[test]
+    fn suggests_similar_settings() {
+        assert_eq!(edit_distance("env_keep", "env_keep"), 0);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+
+        assert_eq!(similar_setting("env_kep"), Some("env_keep"));
+        assert_eq!(similar_setting("env_keeep"), Some("env_keep"));
+        assert_eq!(similar_setting("secure-path"), Some("secure_path"));
+        assert_eq!(similar_setting("passwd_trys"), Some("passwd_tries"));
+        assert_eq!(similar_setting("lecture"), None);
+    }
 }
@@ -61,6 +61,14 @@ macro_rules! defaults {
             $(stringify!($name)),*
         ];
 
+        /// The range and radix of the integer settings that only accept some values.
+        fn integer_bounds(var: &str) -> Option<(i64, i64, u32)> {
+            match var {
+                $($(stringify!($name) => Some(($first, $last, 10$(*0 + $radix)?)),)?)*
+                _ => None,
+            }
+        }
+
         // because of the nature of radix and ranges, 'let mut result' is not always necessary, and
         // a radix of 10 can also not always be avoided (and for uniformity, I would also not avoid this
         // if this was hand-written code.
@@ -0,0 +1,734 @@
+#![forbid(unsafe_code)]
+
+//! Permissions in the syntax of `doas.conf` of OpenBSD, for systems that prefer the much simpler
+//! rules of doas over a sudoers file. Every rule permits or denies something to a user or to the
+//! members of a group:
+//!
+//! ```text
+//! permit persist keepenv :wheel
+//! permit nopass alice as root cmd systemctl args restart nginx
+//! deny bob
+//! ```
+//!
+//! As in doas, the last rule that matches a command decides whether it is allowed; commands are
+//! looked up in a fixed, safe `PATH`. Of the environment options, `setenv` can only keep variables
+//! of the invoking user: rules that set or remove variables are reported and left out.
+
+use std::collections::HashSet;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::sudoers::{Authorization, AuthorizationAllowed, Policy, PreJudgementPolicy, Request};
+use crate::system::interface::{UnixGroup, UnixUser};
+use crate::system::{secure_open, time::Duration};
+
+pub const DOAS_CONF_PATH: &str = "/etc/doas.conf";
+
+/// Where commands are looked up, which is also the `PATH` of the command.
+const SAFE_PATH: &str = "/bin:/sbin:/usr/bin:/usr/sbin:/usr/local/bin:/usr/local/sbin";
+
+/// The user that commands are run as without `-u`.
+const TARGET_DEFAULT: &str = "root";
+
+/// doas gives up after the first wrong password.
+const ALLOWED_ATTEMPTS: u16 = 1;
+
+/// How long a user with `persist` may run commands without authenticating again.
+const PERSIST_SECONDS: i64 = 5 * 60;
+
+/// The variables of the invoking user that are kept without `keepenv` or `setenv`.
+const KEPT_VARIABLES: [&str; 2] = ["DISPLAY", "TERM"];
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Options {
+    nopass: bool,
+    nolog: bool,
+    persist: bool,
+    keepenv: bool,
+    setenv: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Identity {
+    User(String),
+    Group(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rule {
+    permit: bool,
+    options: Options,
+    identity: Identity,
+    target: Option<String>,
+    command: Option<String>,
+    /// The exact arguments that the command must have, if they are given with `args`.
+    arguments: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default)]
+pub struct DoasConf {
+    rules: Vec<Rule>,
+}
+
+/// A problem with a rule, along with the line it starts on; the rule is left out.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Error(pub usize, pub String);
+
+/// What the rule that matched a command, if any, allows.
+#[derive(Debug, Default)]
+pub struct Judgement {
+    /// The options of the matching rule, if it permits the command.
+    options: Option<Options>,
+    env_keep: HashSet<String>,
+    env_check: HashSet<String>,
+    env_delete: HashSet<String>,
+}
+
+impl DoasConf {
+    /// Read the rules in `path`, which has to be owned by root and must not be writable by others.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<(DoasConf, Vec<Error>)> {
+        let mut text = String::new();
+        secure_open(path, false)?.read_to_string(&mut text)?;
+
+        Ok(Self::parse(&text))
+    }
+
+    pub fn parse(text: &str) -> (DoasConf, Vec<Error>) {
+        let mut conf = DoasConf::default();
+        let mut errors = Vec::new();
+
+        let lines = match tokenize(text) {
+            Ok(lines) => lines,
+            Err(error) => return (conf, vec![error]),
+        };
+        for (number, tokens) in lines {
+            match parse_rule(&tokens) {
+                Ok(rule) => conf.rules.push(rule),
+                Err(message) => errors.push(Error(number, message)),
+            }
+        }
+
+        (conf, errors)
+    }
+
+    /// Find what the last rule that matches the request of `am_user` allows.
+    pub fn check<User: UnixUser, Group: UnixGroup>(
+        &self,
+        am_user: &User,
+        request: Request<User, Group>,
+    ) -> Judgement {
+        let options = self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(am_user, &request))
+            .last()
+            .filter(|rule| rule.permit)
+            .map(|rule| rule.options.clone());
+
+        let env_keep = match &options {
+            Some(Options { keepenv: true, .. }) => HashSet::from(["*".to_string()]),
+            Some(Options { setenv, .. }) => KEPT_VARIABLES
+                .iter()
+                .map(|name| name.to_string())
+                .chain(setenv.iter().cloned())
+                .collect(),
+            None => HashSet::new(),
+        };
+
+        Judgement {
+            options,
+            env_keep,
+            env_check: HashSet::new(),
+            env_delete: HashSet::new(),
+        }
+    }
+}
+
+impl Rule {
+    fn matches<User: UnixUser, Group: UnixGroup>(
+        &self,
+        am_user: &User,
+        request: &Request<User, Group>,
+    ) -> bool {
+        let is_user = |user: &User, name: &str| {
+            user.has_name(name) || name.parse().is_ok_and(|uid| user.has_uid(uid))
+        };
+
+        let identity = match &self.identity {
+            Identity::User(name) => is_user(am_user, name),
+            Identity::Group(name) => {
+                am_user.in_group_by_name(name)
+                    || name.parse().is_ok_and(|gid| am_user.in_group_by_gid(gid))
+            }
+        };
+        let target = match &self.target {
+            Some(name) => is_user(request.user, name),
+            None => true,
+        };
+        let command = match &self.command {
+            Some(command) if command.contains('/') => request.command == Path::new(command),
+            // a command without a directory is one of those in the safe path
+            Some(command) => {
+                request.command.file_name() == Some(command.as_ref())
+                    && request
+                        .command
+                        .parent()
+                        .is_some_and(|dir| SAFE_PATH.split(':').any(|safe| dir == Path::new(safe)))
+            }
+            None => true,
+        };
+        let arguments = match &self.arguments {
+            Some(arguments) => request.arguments == arguments.as_slice(),
+            None => true,
+        };
+
+        identity && target && command && arguments
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Open,
+    Close,
+}
+
+/// Split `text` into the words of every rule, along with the line the rule starts on. A rule
+/// ends at the end of a line, unless the line ends with a backslash.
+fn tokenize(text: &str) -> Result<Vec<(usize, Vec<Token>)>, Error> {
+    let mut lines = Vec::new();
+    let mut tokens = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quoted = false;
+    let mut number = 1;
+    let mut start = 1;
+
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('\n') => {
+                    number += 1;
+                    if quoted {
+                        return Err(Error(number - 1, "unterminated quotes".to_string()));
+                    }
+                    tokens.extend(word.take().map(Token::Word));
+                }
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => return Err(Error(number, "unterminated escape".to_string())),
+            },
+            '"' => {
+                quoted = !quoted;
+                word.get_or_insert_with(String::new);
+            }
+            '\n' if quoted => return Err(Error(number, "unterminated quotes".to_string())),
+            _ if quoted => word.get_or_insert_with(String::new).push(c),
+            '#' => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+                number += 1;
+                tokens.extend(word.take().map(Token::Word));
+                if !tokens.is_empty() {
+                    lines.push((start, std::mem::take(&mut tokens)));
+                }
+                start = number;
+            }
+            '\n' => {
+                number += 1;
+                tokens.extend(word.take().map(Token::Word));
+                if !tokens.is_empty() {
+                    lines.push((start, std::mem::take(&mut tokens)));
+                }
+                start = number;
+            }
+            '{' | '}' => {
+                tokens.extend(word.take().map(Token::Word));
+                tokens.push(if c == '{' { Token::Open } else { Token::Close });
+            }
+            _ if c.is_whitespace() => tokens.extend(word.take().map(Token::Word)),
+            _ => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quoted {
+        return Err(Error(number, "unterminated quotes".to_string()));
+    }
+    tokens.extend(word.take().map(Token::Word));
+    if !tokens.is_empty() {
+        lines.push((start, tokens));
+    }
+
+    Ok(lines)
+}
+
+fn next_word<'a>(
+    tokens: &mut impl Iterator<Item = &'a Token>,
+    what: &str,
+) -> Result<String, String> {
+    match tokens.next() {
+        Some(Token::Word(word)) => Ok(word.clone()),
+        _ => Err(format!("syntax error: expected {what}")),
+    }
+}
+
+/// `action [options] identity [as target] [cmd command [args ...]]`
+fn parse_rule(tokens: &[Token]) -> Result<Rule, String> {
+    let mut tokens = tokens.iter().peekable();
+
+    let permit = match next_word(&mut tokens, "permit or deny")?.as_str() {
+        "permit" => true,
+        "deny" => false,
+        other => {
+            return Err(format!(
+                "syntax error: expected permit or deny, not {other}"
+            ))
+        }
+    };
+
+    let mut options = Options::default();
+    let identity = loop {
+        let word = next_word(&mut tokens, "an identity")?;
+        let option = match word.as_str() {
+            "nopass" => &mut options.nopass,
+            "nolog" => &mut options.nolog,
+            "persist" => &mut options.persist,
+            "keepenv" => &mut options.keepenv,
+            "setenv" => {
+                if tokens.next() != Some(&Token::Open) {
+                    return Err("syntax error: expected { after setenv".to_string());
+                }
+                loop {
+                    match tokens.next() {
+                        Some(Token::Close) => break,
+                        Some(Token::Word(name)) if name.contains('=') || name.starts_with('-') => {
+                            return Err(format!(
+                                "setenv: only keeping variables is supported: {name}"
+                            ));
+                        }
+                        Some(Token::Word(name)) => options.setenv.push(name.clone()),
+                        _ => return Err("syntax error: expected } after setenv".to_string()),
+                    }
+                }
+                continue;
+            }
+            _ => break word,
+        };
+        if !permit {
+            return Err(format!("{word} can only be used with permit"));
+        }
+        *option = true;
+    };
+    if !permit && !options.setenv.is_empty() {
+        return Err("setenv can only be used with permit".to_string());
+    }
+    if options.nopass && options.persist {
+        return Err("nopass and persist cannot be combined".to_string());
+    }
+    let identity = match identity.strip_prefix(':') {
+        Some(group) => Identity::Group(group.to_string()),
+        None => Identity::User(identity),
+    };
+
+    let mut rule = Rule {
+        permit,
+        options,
+        identity,
+        target: None,
+        command: None,
+        arguments: None,
+    };
+    while let Some(keyword) = tokens.next() {
+        match keyword {
+            Token::Word(keyword) if keyword == "as" && rule.target.is_none() => {
+                rule.target = Some(next_word(&mut tokens, "a user after as")?);
+            }
+            Token::Word(keyword) if keyword == "cmd" && rule.command.is_none() => {
+                rule.command = Some(next_word(&mut tokens, "a command after cmd")?);
+                if tokens.peek() == Some(&&Token::Word("args".to_string())) {
+                    tokens.next();
+                    let mut arguments = Vec::new();
+                    for argument in tokens.by_ref() {
+                        match argument {
+                            Token::Word(argument) => arguments.push(argument.clone()),
+                            _ => return Err("syntax error: unexpected brace".to_string()),
+                        }
+                    }
+                    rule.arguments = Some(arguments);
+                }
+            }
+            Token::Word(keyword) => return Err(format!("syntax error: unexpected {keyword}")),
+            _ => return Err("syntax error: unexpected brace".to_string()),
+        }
+    }
+
+    Ok(rule)
+}
+
+impl PreJudgementPolicy for DoasConf {
+    fn secure_path(&self) -> Option<String> {
+        Some(SAFE_PATH.to_string())
+    }
+
+    fn runas_default(&self) -> String {
+        TARGET_DEFAULT.to_string()
+    }
+
+    fn runaspw(&self) -> bool {
+        false
+    }
+
+    fn validate_authorization(
+        &self,
+        _invoking_user: &crate::system::User,
+        _hostname: &str,
+    ) -> Authorization {
+        Authorization::Allowed(AuthorizationAllowed {
+            must_authenticate: true,
+            allowed_attempts: ALLOWED_ATTEMPTS,
+            prior_validity: Duration::seconds(PERSIST_SECONDS),
+            insults: false,
+        })
+    }
+
+    fn color(&self) -> bool {
+        false
+    }
+
+    fn fqdn(&self) -> bool {
+        false
+    }
+
+    fn runas_allow_unknown_id(&self) -> bool {
+        false
+    }
+}
+
+impl Policy for Judgement {
+    fn authorization(&self) -> Authorization {
+        match &self.options {
+            Some(options) => Authorization::Allowed(AuthorizationAllowed {
+                must_authenticate: !options.nopass,
+                allowed_attempts: ALLOWED_ATTEMPTS,
+                prior_validity: Duration::seconds(if options.persist {
+                    PERSIST_SECONDS
+                } else {
+                    0
+                }),
+                insults: false,
+            }),
+            None => Authorization::Forbidden,
+        }
+    }
+
+    fn log_command(&self) -> bool {
+        !self.options.as_ref().is_some_and(|options| options.nolog)
+    }
+
+    fn env_keep(&self) -> &HashSet<String> {
+        &self.env_keep
+    }
+
+    fn env_check(&self) -> &HashSet<String> {
+        &self.env_check
+    }
+
+    fn env_delete(&self) -> &HashSet<String> {
+        &self.env_delete
+    }
+
+    fn secure_path(&self) -> Option<String> {
+        Some(SAFE_PATH.to_string())
+    }
+
+    fn use_pty(&self) -> bool {
+        false
+    }
+
+    fn tty_signals(&self) -> bool {
+        false
+    }
+
+    fn pam_session(&self) -> bool {
+        true
+    }
+
+    fn pty_wait_for_eof(&self) -> std::time::Duration {
+        std::time::Duration::ZERO
+    }
+
+    fn runas_check_shell(&self) -> bool {
+        false
+    }
+
+    fn stay_setuid(&self) -> bool {
+        false
+    }
+
+    fn set_home(&self) -> bool {
+        true
+    }
+
+    fn always_set_home(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{DoasConf, Error, Identity, Options, Rule};
+    use crate::sudoers::{Authorization, AuthorizationAllowed, Policy, Request};
+    use crate::system::interface::{GroupId, UnixGroup, UnixUser};
+    use crate::system::time::Duration;
+
+    struct Named(&'static str);
+
+    impl UnixUser for Named {
+        fn has_name(&self, name: &str) -> bool {
+            self.0 == name
+        }
+        fn has_uid(&self, uid: libc::uid_t) -> bool {
+            self.0 == "root" && uid == 0
+        }
+        fn in_group_by_name(&self, name: &str) -> bool {
+            self.0 == "alice" && name == "wheel"
+        }
+    }
+
+    impl UnixGroup for Named {
+        fn as_gid(&self) -> GroupId {
+            0
+        }
+        fn try_as_name(&self) -> Option<&str> {
+            Some(self.0)
+        }
+    }
+
+    fn judge(
+        conf: &DoasConf,
+        user: &str,
+        target: &str,
+        command: &str,
+        arguments: &[&str],
+    ) -> Authorization {
+        let user = match user {
+            "alice" => Named("alice"),
+            "bob" => Named("bob"),
+            _ => Named("root"),
+        };
+        let target = if target == "root" {
+            Named("root")
+        } else {
+            Named("bob")
+        };
+        let arguments = arguments
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>();
+        conf.check(
+            &user,
+            Request {
+                user: &target,
+                group: &Named("root"),
+                command: Path::new(command),
+                arguments: &arguments,
+            },
+        )
+        .authorization()
+    }
+
+    fn allowed(must_authenticate: bool, persist: bool) -> Authorization {
+        Authorization::Allowed(AuthorizationAllowed {
+            must_authenticate,
+            allowed_attempts: 1,
+            prior_validity: Duration::minutes(if persist { 5 } else { 0 }),
+            insults: false,
+        })
+    }
+
+    #[test]
+    fn parses_rules() {
+        let (conf, errors) = DoasConf::parse(
+            "# comment
+permit persist setenv { EDITOR LANG } :wheel
+permit nopass alice as root cmd \"/usr/bin/systemctl\" \\
+    args restart nginx # another comment
+
+deny bob cmd reboot args
+",
+        );
+
+        assert_eq!(errors, []);
+        assert_eq!(
+            conf.rules,
+            [
+                Rule {
+                    permit: true,
+                    options: Options {
+                        persist: true,
+                        setenv: vec!["EDITOR".to_string(), "LANG".to_string()],
+                        ..Default::default()
+                    },
+                    identity: Identity::Group("wheel".to_string()),
+                    target: None,
+                    command: None,
+                    arguments: None,
+                },
+                Rule {
+                    permit: true,
+                    options: Options {
+                        nopass: true,
+                        ..Default::default()
+                    },
+                    identity: Identity::User("alice".to_string()),
+                    target: Some("root".to_string()),
+                    command: Some("/usr/bin/systemctl".to_string()),
+                    arguments: Some(vec!["restart".to_string(), "nginx".to_string()]),
+                },
+                Rule {
+                    permit: false,
+                    options: Options::default(),
+                    identity: Identity::User("bob".to_string()),
+                    target: None,
+                    command: Some("reboot".to_string()),
+                    arguments: Some(vec![]),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_invalid_rules() {
+        let (conf, errors) = DoasConf::parse(
+            "permit
+allow alice
+permit nopass persist alice
+deny nopass bob
+permit setenv { PATH=/bin } alice
+permit alice as
+permit bob
+",
+        );
+
+        assert_eq!(
+            errors,
+            [
+                Error(1, "syntax error: expected an identity".to_string()),
+                Error(
+                    2,
+                    "syntax error: expected permit or deny, not allow".to_string()
+                ),
+                Error(3, "nopass and persist cannot be combined".to_string()),
+                Error(4, "nopass can only be used with permit".to_string()),
+                Error(
+                    5,
+                    "setenv: only keeping variables is supported: PATH=/bin".to_string()
+                ),
+                Error(6, "syntax error: expected a user after as".to_string()),
+            ]
+        );
+        assert_eq!(conf.rules.len(), 1);
+
+        let (_, errors) = DoasConf::parse("permit \"alice\n");
+        assert_eq!(errors, [Error(1, "unterminated quotes".to_string())]);
+    }
+
+    #[test]
+    fn last_matching_rule_decides() {
+        let (conf, errors) = DoasConf::parse(
+            "permit persist :wheel
+permit nopass alice as root cmd systemctl args restart nginx
+deny alice cmd /usr/bin/reboot
+permit nopass bob as bob
+",
+        );
+        assert_eq!(errors, []);
+
+        assert_eq!(
+            judge(&conf, "alice", "root", "/usr/bin/ls", &[]),
+            allowed(true, true)
+        );
+        assert_eq!(
+            judge(
+                &conf,
+                "alice",
+                "root",
+                "/usr/bin/systemctl",
+                &["restart", "nginx"]
+            ),
+            allowed(false, false)
+        );
+        // the arguments have to be the same
+        assert_eq!(
+            judge(
+                &conf,
+                "alice",
+                "root",
+                "/usr/bin/systemctl",
+                &["stop", "nginx"]
+            ),
+            allowed(true, true)
+        );
+        // a command without a directory has to be in the safe path
+        assert_eq!(
+            judge(
+                &conf,
+                "alice",
+                "bob",
+                "/tmp/systemctl",
+                &["restart", "nginx"]
+            ),
+            allowed(true, true)
+        );
+        assert_eq!(
+            judge(&conf, "alice", "root", "/usr/bin/reboot", &[]),
+            Authorization::Forbidden
+        );
+
+        assert_eq!(
+            judge(&conf, "bob", "bob", "/usr/bin/ls", &[]),
+            allowed(false, false)
+        );
+        assert_eq!(
+            judge(&conf, "bob", "root", "/usr/bin/ls", &[]),
+            Authorization::Forbidden
+        );
+        assert_eq!(
+            judge(&conf, "root", "root", "/usr/bin/ls", &[]),
+            Authorization::Forbidden
+        );
+    }
+
+    #[test]
+    fn keeps_variables() {
+        let (conf, _) = DoasConf::parse(
+            "permit nolog setenv { EDITOR } alice
+permit keepenv bob
+",
+        );
+        let check = |user| {
+            conf.check(
+                &Named(user),
+                Request {
+                    user: &Named("root"),
+                    group: &Named("root"),
+                    command: Path::new("/usr/bin/ls"),
+                    arguments: &[],
+                },
+            )
+        };
+
+        let alice = check("alice");
+        let mut keep = alice.env_keep().iter().cloned().collect::<Vec<_>>();
+        keep.sort();
+        assert_eq!(keep, ["DISPLAY", "EDITOR", "TERM"]);
+        assert!(!alice.log_command());
+
+        let bob = check("bob");
+        assert!(bob.env_keep().contains("*"));
+        assert!(bob.log_command());
+    }
+}
@@ -1,12 +1,17 @@
 use std::{
     collections::{hash_map::Entry, HashSet},
     ffi::{OsStr, OsString},
-    os::unix::prelude::OsStrExt,
+    fs,
+    os::unix::prelude::{MetadataExt, OsStrExt},
+    path::{Component, Path},
 };
 
-use crate::common::{CommandAndArguments, Context, Environment};
+use crate::common::{
+    context::{LaunchType, SESSION_ID_VAR},
+    CommandAndArguments, Context, Environment, Error,
+};
 use crate::sudoers::Policy;
-use crate::system::PATH_MAX;
+use crate::system::{interface::UserId, PATH_MAX};
 
 use super::wildcard_match::wildcard_match;
 
@@ -55,6 +60,7 @@ fn add_extra_env(
         context.current_user.gid.to_string().into(),
     );
     environment.insert("SUDO_USER".into(), context.current_user.name.clone().into());
+    environment.insert(SESSION_ID_VAR.into(), context.session_uuid.clone().into());
     // target user
     if let Entry::Vacant(entry) = environment.entry("MAIL".into()) {
         entry.insert(format!("{PATH_MAILDIR}/{}", context.target_user.name).into());
@@ -63,8 +69,11 @@ fn add_extra_env(
     environment.insert("SHELL".into(), context.target_user.shell.clone().into());
     // HOME' Set to the home directory of the target user if -i or -H are specified, env_reset or always_set_home are
     // set in sudoers, or when the -s option is specified and set_home is set in sudoers.
-    // Since we always want to do env_reset -> always set HOME
-    if let Entry::Vacant(entry) = environment.entry("HOME".into()) {
+    // Since we always want to do env_reset -> set HOME, unless it is preserved by env_keep and
+    // none of the other conditions apply
+    if context.set_home {
+        environment.insert("HOME".into(), context.target_user.home.clone().into());
+    } else if let Entry::Vacant(entry) = environment.entry("HOME".into()) {
         entry.insert(context.target_user.home.clone().into());
     }
 
@@ -106,8 +115,9 @@ fn add_extra_env(
         environment.insert("TERM".into(), "unknown".into());
     }
     // The SUDO_PS1 variable requires special treatment as the PS1 variable must be set in the
-    // target environment to the same value of SUDO_PS1 if the latter is set.
-    if let Some(sudo_ps1_value) = sudo_ps1 {
+    // target environment to the same value of SUDO_PS1 if the latter is set. A login shell sets
+    // up its own prompt, so it is left alone in that case.
+    if let Some(sudo_ps1_value) = sudo_ps1.filter(|_| context.launch != LaunchType::Login) {
         // set PS1 to the SUDO_PS1 value in the target environment
         environment.insert("PS1".into(), sudo_ps1_value);
     }
@@ -149,6 +159,21 @@ fn is_safe_tz(value: &[u8]) -> bool {
         && check_value.len() < PATH_MAX as usize
 }
 
+/// Variables that point to a file of the invoking user, such as the X11 authority file used to
+/// forward the display of GUI tools, or the socket of an ssh agent.
+const USER_FILE_VARS: &[&str] = &["XAUTHORITY", "SSH_AUTH_SOCK"];
+
+/// A variable from [USER_FILE_VARS] is only considered safe if it is an absolute path without
+/// any .. path elements, to an existing file that is owned by the invoking user; this prevents it
+/// from being used to have the target user open arbitrary files.
+fn is_safe_user_file(value: &OsStr, owner: UserId) -> bool {
+    let path = Path::new(value);
+
+    path.is_absolute()
+        && !path.components().any(|c| c == Component::ParentDir)
+        && fs::metadata(path).is_ok_and(|meta| meta.uid() == owner)
+}
+
 /// Check whether the needle exists in a haystack, in which the haystack is a list of patterns, possibly containing wildcards
 fn in_table(needle: &OsStr, haystack: &HashSet<String>) -> bool {
     haystack
@@ -156,22 +181,85 @@ fn in_table(needle: &OsStr, haystack: &HashSet<String>) -> bool {
         .any(|pattern| wildcard_match(needle.as_bytes(), pattern.as_bytes()))
 }
 
+/// How the environment of the command is made up from that of the invoking user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvMode {
+    /// Only the variables in env_keep and env_check are kept (`env_reset`).
+    Reset,
+    /// All variables are kept, except those in env_delete.
+    Keep,
+}
+
 /// Determine whether a specific environment variable should be kept
-fn should_keep(key: &OsStr, value: &OsStr, cfg: &impl Policy) -> bool {
+fn should_keep(key: &OsStr, value: &OsStr, cfg: &impl Policy, mode: EnvMode) -> bool {
     if value.as_bytes().starts_with("()".as_bytes()) {
         return false;
     }
 
-    if key == "TZ" {
-        return in_table(key, cfg.env_keep())
-            || (in_table(key, cfg.env_check()) && is_safe_tz(value.as_bytes()));
+    // like any other variable in env_check, TZ is checked even if it is also in env_keep or
+    // env_delete; it can only be kept unchecked by removing it from env_check
+    if in_table(key, cfg.env_check()) {
+        return if key == "TZ" {
+            is_safe_tz(value.as_bytes())
+        } else {
+            !value.as_bytes().iter().any(|c| *c == b'%' || *c == b'/')
+        };
     }
 
-    if in_table(key, cfg.env_check()) {
-        return !value.as_bytes().iter().any(|c| *c == b'%' || *c == b'/');
+    match mode {
+        EnvMode::Reset => in_table(key, cfg.env_keep()),
+        EnvMode::Keep => !in_table(key, cfg.env_delete()),
+    }
+}
+
+/// Decide how the environment of the command is made up, and whether the invoking user may ask
+/// for what they asked for, like ogsudo does:
+///
+/// - `-E` is only allowed with `SETENV` (which the command `ALL` implies) or `Defaults setenv`,
+///   and keeps the whole environment as if env_reset were off;
+/// - otherwise env_reset decides, and every variable that the user asks to set, on the command
+///   line or with `--preserve-env=list`, has to be allowed: with `SETENV` any variable is, without
+///   it only those that would be kept from the environment anyway, and never `PATH` when
+///   secure_path is set.
+pub fn decide_env_mode(
+    preserve_env: bool,
+    env_vars: &[(OsString, OsString)],
+    cfg: &impl Policy,
+) -> Result<EnvMode, Error> {
+    if preserve_env {
+        return if cfg.setenv() {
+            Ok(EnvMode::Keep)
+        } else {
+            Err(Error::Options(
+                "sorry, you are not allowed to preserve the environment".to_string(),
+            ))
+        };
+    }
+
+    let mode = if cfg.env_reset() {
+        EnvMode::Reset
+    } else {
+        EnvMode::Keep
+    };
+    if env_vars.is_empty() || cfg.setenv() {
+        return Ok(mode);
+    }
+
+    let forbidden = env_vars
+        .iter()
+        .filter(|(key, value)| {
+            (key == "PATH" && cfg.secure_path().is_some()) || !should_keep(key, value, cfg, mode)
+        })
+        .map(|(key, _)| key.to_string_lossy())
+        .collect::<Vec<_>>();
+    if !forbidden.is_empty() {
+        return Err(Error::Options(format!(
+            "sorry, you are not allowed to set the following environment variables: {}",
+            forbidden.join(", ")
+        )));
     }
 
-    in_table(key, cfg.env_keep())
+    Ok(mode)
 }
 
 /// Construct the final environment from the current one and a sudo context
@@ -182,17 +270,22 @@ fn should_keep(key: &OsStr, value: &OsStr, cfg: &impl Policy) -> bool {
 /// and the SUDO_* variables are set based on the invoking user.
 ///
 /// Additional variables, such as DISPLAY, PATH and TERM, are preserved from the invoking user's
-/// environment if permitted by the env_check, or env_keep options
+/// environment if permitted by the env_check, or env_keep options; with `-E` or without
+/// env_reset, all of them are except those in env_delete, see [decide_env_mode]
 ///
 /// If the PATH and TERM variables are not preserved from the user's environment, they will be set to default value
 ///
 /// Environment variables with a value beginning with ‘()’ are removed
+///
+/// The variables that the user asked to set come next, and override all others, except for
+/// the variables that the policy sets for the command
 pub fn get_target_environment(
     current_env: Environment,
     additional_env: Environment,
     context: &Context,
     settings: &impl Policy,
-) -> Environment {
+) -> Result<Environment, Error> {
+    let mode = decide_env_mode(context.preserve_env, &context.env_vars, settings)?;
     let mut environment = Environment::default();
 
     // retrieve SUDO_PS1 value to set a PS1 value as additional environment
@@ -202,29 +295,86 @@ pub fn get_target_environment(
     // env_keep list take precedence over those in the PAM environment
     environment.extend(additional_env);
 
+    environment.extend(current_env.into_iter().filter(|(key, value)| {
+        should_keep(key, value, settings, mode)
+            && (!USER_FILE_VARS.iter().any(|var| key == var)
+                || is_safe_user_file(value, context.current_user.uid))
+    }));
+
+    add_extra_env(context, settings, sudo_ps1, &mut environment);
+
+    environment.extend(context.env_vars.iter().cloned());
+
     environment.extend(
-        current_env
-            .into_iter()
-            .filter(|(key, value)| should_keep(key, value, settings)),
+        settings
+            .env_assignments()
+            .iter()
+            .map(|(name, value)| (name.into(), value.into())),
     );
 
-    add_extra_env(context, settings, sudo_ps1, &mut environment);
+    Ok(environment)
+}
+
+/// The variable that makes the dynamic linker load a library into the command first.
+#[cfg(not(target_os = "macos"))]
+const PRELOAD_VAR: &str = "LD_PRELOAD";
+#[cfg(target_os = "macos")]
+const PRELOAD_VAR: &str = "DYLD_INSERT_LIBRARIES";
+
+/// Load the `sudo_noexec.so` at `library` into the command, which keeps it from running other
+/// programs. Libraries that the environment already preloads are kept, after this one.
+pub fn preload_noexec(environment: &mut Environment, library: &Path) -> Result<(), Error> {
+    if !library.is_file() {
+        return Err(Error::Configuration(format!(
+            "cannot run the command with NOEXEC: {} does not exist",
+            library.display()
+        )));
+    }
+
+    let mut preload = OsString::from(library);
+    if let Some(existing) = environment.get(OsStr::new(PRELOAD_VAR)) {
+        if !existing.is_empty() {
+            preload.push(":");
+            preload.push(existing);
+        }
+    }
+    environment.insert(PRELOAD_VAR.into(), preload);
 
-    environment
+    // the functions of the library only replace those of libc in a flat namespace
+    #[cfg(target_os = "macos")]
+    environment.insert("DYLD_FORCE_FLAT_NAMESPACE".into(), "1".into());
+
+    Ok(())
 }
 
 #[cfg(test)]
-mod tests {
-    use super::{is_safe_tz, should_keep, PATH_ZONEINFO};
+pub(super) mod tests {
+    use super::{
+        decide_env_mode, is_safe_tz, is_safe_user_file, preload_noexec, should_keep, EnvMode,
+        PATH_MAX, PATH_ZONEINFO, PRELOAD_VAR,
+    };
     use crate::sudoers::Policy;
-    use std::{collections::HashSet, ffi::OsStr};
+    use std::{
+        collections::HashSet,
+        ffi::{OsStr, OsString},
+    };
 
-    struct TestConfiguration {
-        keep: HashSet<String>,
-        check: HashSet<String>,
+    #[derive(Default)]
+    pub(in crate::env) struct TestConfiguration {
+        pub(in crate::env) keep: HashSet<String>,
+        pub(in crate::env) check: HashSet<String>,
+        pub(in crate::env) delete: HashSet<String>,
+        pub(in crate::env) assignments: Vec<(String, String)>,
+        pub(in crate::env) setenv: bool,
+        pub(in crate::env) reset: bool,
+        pub(in crate::env) secure_path: Option<String>,
     }
 
     impl Policy for TestConfiguration {
+        fn env_assignments(&self) -> &[(String, String)] {
+            &self.assignments
+        }
+
         fn env_keep(&self) -> &HashSet<String> {
             &self.keep
         }
@@ -233,13 +383,53 @@ mod tests {
             &self.check
         }
 
+        fn env_delete(&self) -> &HashSet<String> {
+            &self.delete
+        }
+
+        fn setenv(&self) -> bool {
+            self.setenv
+        }
+
+        fn env_reset(&self) -> bool {
+            self.reset
+        }
+
         fn secure_path(&self) -> Option<String> {
-            None
+            self.secure_path.clone()
         }
 
         fn use_pty(&self) -> bool {
             true
         }
+
+        fn tty_signals(&self) -> bool {
+            false
+        }
+
+        fn pam_session(&self) -> bool {
+            true
+        }
+
+        fn pty_wait_for_eof(&self) -> std::time::Duration {
+            std::time::Duration::ZERO
+        }
+
+        fn runas_check_shell(&self) -> bool {
+            false
+        }
+
+        fn stay_setuid(&self) -> bool {
+            false
+        }
+
+        fn set_home(&self) -> bool {
+            false
+        }
+
+        fn always_set_home(&self) -> bool {
+            false
+        }
     }
 
     #[test]
@@ -247,11 +437,12 @@ mod tests {
         let config = TestConfiguration {
             keep: HashSet::from(["AAP".to_string(), "NOOT".to_string()]),
             check: HashSet::from(["MIES".to_string(), "TZ".to_string()]),
+            ..Default::default()
         };
 
         let check_should_keep = |key: &str, value: &str, expected: bool| {
             assert_eq!(
-                should_keep(OsStr::new(key), OsStr::new(value), &config),
+                should_keep(OsStr::new(key), OsStr::new(value), &config, EnvMode::Reset),
                 expected,
                 "{} should {}",
                 key,
@@ -268,6 +459,114 @@ mod tests {
         check_should_keep("MIES", "FOO%", false);
     }
 
+    #[test]
+    fn test_tz_in_both_lists_is_checked() {
+        let mut config = TestConfiguration {
+            keep: HashSet::from(["TZ".to_string()]),
+            check: HashSet::from(["TZ".to_string()]),
+            ..Default::default()
+        };
+
+        let keep_tz = |value: &str, config: &TestConfiguration| {
+            should_keep(OsStr::new("TZ"), OsStr::new(value), config, EnvMode::Reset)
+        };
+
+        assert!(keep_tz("Europe/Amsterdam", &config));
+        assert!(!keep_tz("/etc/localtime", &config));
+        assert!(!keep_tz("../Europe/Berlin", &config));
+
+        config.check.clear();
+        assert!(keep_tz("/etc/localtime", &config));
+    }
+
+    #[test]
+    fn test_filtering_without_env_reset() {
+        let config = TestConfiguration {
+            check: HashSet::from(["MIES".to_string()]),
+            delete: HashSet::from(["LD_*".to_string(), "MIES".to_string()]),
+            ..Default::default()
+        };
+        let keep = |key: &str, value: &str| {
+            should_keep(OsStr::new(key), OsStr::new(value), &config, EnvMode::Keep)
+        };
+
+        assert!(keep("AAP", "FOO"));
+        assert!(!keep("AAP", "()=foo"));
+        assert!(!keep("LD_PRELOAD", "/tmp/evil.so"));
+        // env_check takes precedence over env_delete
+        assert!(keep("MIES", "BAR"));
+        assert!(!keep("MIES", "FOO/BAR"));
+    }
+
+    #[test]
+    fn test_env_mode_matrix() {
+        use EnvMode::*;
+
+        const PRESERVE: &str = "sorry, you are not allowed to preserve the environment";
+        const SET: &str = "sorry, you are not allowed to set the following environment variables";
+
+        // -E, the variables to set, SETENV, env_reset, secure_path, and the outcome
+        type Case<'a> = (
+            bool,
+            &'a [(&'a str, &'a str)],
+            bool,
+            bool,
+            bool,
+            Result<EnvMode, String>,
+        );
+        #[rustfmt::skip]
+        let cases: &[Case] = &[
+            (false, &[], false, true, false, Ok(Reset)),
+            (false, &[], false, false, false, Ok(Keep)),
+            (false, &[], true, true, false, Ok(Reset)),
+            (false, &[], true, false, false, Ok(Keep)),
+            // -E needs SETENV, and turns env_reset off
+            (true, &[], true, true, false, Ok(Keep)),
+            (true, &[], true, false, false, Ok(Keep)),
+            (true, &[], false, true, false, Err(PRESERVE.to_string())),
+            (true, &[], false, false, false, Err(PRESERVE.to_string())),
+            (true, &[("AAP", "1")], false, true, false, Err(PRESERVE.to_string())),
+            // with env_reset, only variables in env_keep or env_check may be set
+            (false, &[("AAP", "1"), ("MIES", "2")], false, true, false, Ok(Reset)),
+            (false, &[("NOOT", "1")], false, true, false, Err(format!("{SET}: NOOT"))),
+            (false, &[("MIES", "a/b")], false, true, false, Err(format!("{SET}: MIES"))),
+            // without env_reset, any variable that is not in env_delete may be set
+            (false, &[("NOOT", "1")], false, false, false, Ok(Keep)),
+            (false, &[("LD_PRELOAD", "x"), ("NOOT", "1"), ("IFS", " ")], false, false, false,
+                Err(format!("{SET}: LD_PRELOAD, IFS"))),
+            // PATH may not override secure_path
+            (false, &[("PATH", "/tmp")], false, false, false, Ok(Keep)),
+            (false, &[("PATH", "/tmp")], false, false, true, Err(format!("{SET}: PATH"))),
+            (false, &[("AAP", "1"), ("PATH", "/tmp")], false, true, true, Err(format!("{SET}: PATH"))),
+            // unless SETENV allows any variable
+            (false, &[("LD_PRELOAD", "x"), ("PATH", "/tmp")], true, true, true, Ok(Reset)),
+            (true, &[("LD_PRELOAD", "x"), ("PATH", "/tmp")], true, true, true, Ok(Keep)),
+        ];
+
+        for (preserve_env, env_vars, setenv, reset, secure_path, expected) in cases {
+            let config = TestConfiguration {
+                keep: HashSet::from(["AAP".to_string(), "PATH".to_string()]),
+                check: HashSet::from(["MIES".to_string()]),
+                delete: HashSet::from(["LD_*".to_string(), "IFS".to_string()]),
+                setenv: *setenv,
+                reset: *reset,
+                secure_path: secure_path.then(|| "/usr/bin".to_string()),
+                ..Default::default()
+            };
+            let env_vars = env_vars
+                .iter()
+                .map(|(key, value)| (OsString::from(key), OsString::from(value)))
+                .collect::<Vec<_>>();
+
+            let result = decide_env_mode(*preserve_env, &env_vars, &config);
+            assert_eq!(
+                &result.map_err(|err| err.to_string()),
+                expected,
+                "-E: {preserve_env}, {env_vars:?}, SETENV: {setenv}, env_reset: {reset}, secure_path: {secure_path}"
+            );
+        }
+    }
+
     #[allow(clippy::useless_format)]
     #[allow(clippy::bool_assert_comparison)]
     #[test]
@@ -293,5 +592,36 @@ mod tests {
             is_safe_tz(format!("{PATH_ZONEINFO}/../Europe/London").as_bytes()),
             false
         );
+        assert_eq!(is_safe_tz(b"Europe/Amster dam"), false);
+        assert_eq!(is_safe_tz(b"Europe/Amsterdam\x07"), false);
+        assert_eq!(is_safe_tz("x".repeat(PATH_MAX as usize).as_bytes()), false);
+    }
+
+    #[test]
+    fn test_user_file_safety() {
+        let check = |path: &str, owner| is_safe_user_file(OsStr::new(path), owner);
+        let root_file = "/etc/passwd";
+
+        assert!(check(root_file, 0));
+        assert!(!check(root_file, 1000));
+        assert!(!check("/etc/../etc/passwd", 0));
+        assert!(!check("etc/passwd", 0));
+        assert!(!check("/does/not/exist", 0));
+    }
+
+    #[test]
+    fn test_preload_noexec() {
+        let library = std::env::current_exe().unwrap();
+        let mut environment = crate::common::Environment::default();
+        preload_noexec(&mut environment, &library).unwrap();
+        assert_eq!(environment[OsStr::new(PRELOAD_VAR)], library.as_os_str());
+
+        environment.insert(PRELOAD_VAR.into(), "/lib/libother.so".into());
+        preload_noexec(&mut environment, &library).unwrap();
+        let mut expected = OsString::from(&library);
+        expected.push(":/lib/libother.so");
+        assert_eq!(environment[OsStr::new(PRELOAD_VAR)], expected);
+
+        assert!(preload_noexec(&mut environment, "/does/not/exist.so".as_ref()).is_err());
     }
 }
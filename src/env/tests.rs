@@ -1,8 +1,11 @@
 use crate::cli::SudoOptions;
-use crate::common::{CommandAndArguments, Context, Environment};
+use crate::common::lookup::Credentials;
+use crate::common::{context::LaunchType, CommandAndArguments, Context, Environment};
 use crate::env::environment::get_target_environment;
+use crate::env::environment::tests::TestConfiguration;
 use crate::system::{Group, Process, User};
 use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
 
 const TESTS: &str = "
 > env
@@ -29,6 +32,7 @@ const TESTS: &str = "
     SHELL=/bin/bash
     SUDO_COMMAND=/usr/bin/env
     SUDO_GID=1000
+    SUDO_SESSION_ID=01234567-89ab-4def-8123-456789abcdef
     SUDO_UID=1000
     SUDO_USER=test
     HOME=/root
@@ -46,6 +50,7 @@ const TESTS: &str = "
     SHELL=/bin/sh
     SUDO_COMMAND=/usr/bin/env
     SUDO_GID=1000
+    SUDO_SESSION_ID=01234567-89ab-4def-8123-456789abcdef
     SUDO_UID=1000
     SUDO_USER=test
     HOME=/home/test
@@ -75,7 +80,17 @@ fn parse_env_commands(input: &str) -> Vec<(&str, Environment)> {
 
 fn create_test_context(sudo_options: &SudoOptions) -> Context {
     let path = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin".to_string();
-    let command = CommandAndArguments::build_from_args(None, sudo_options.clone().args(), &path);
+    let credentials = Credentials {
+        uid: 0,
+        gid: 0,
+        groups: &[],
+    };
+    let command = CommandAndArguments::build_from_args(
+        None,
+        sudo_options.clone().args(),
+        &path,
+        &credentials,
+    );
 
     let current_user = User {
         uid: 1000,
@@ -117,6 +132,7 @@ fn create_test_context(sudo_options: &SudoOptions) -> Context {
         hostname: "test-ubuntu".to_string(),
         command,
         current_user: current_user.clone(),
+        auth_user: current_user.clone(),
         target_user: if sudo_options.user.as_deref() == Some("test") {
             current_user
         } else {
@@ -127,13 +143,53 @@ fn create_test_context(sudo_options: &SudoOptions) -> Context {
         } else {
             root_group
         },
-        launch: crate::common::context::LaunchType::Direct,
+        launch: LaunchType::Direct,
         chdir: sudo_options.directory.clone(),
         stdin: sudo_options.stdin,
+        askpass: sudo_options.askpass,
+        prompt: sudo_options.prompt.clone(),
         non_interactive: sudo_options.non_interactive,
         process: Process::new(),
+        session_uuid: "01234567-89ab-4def-8123-456789abcdef".to_string(),
+        parent_session_uuid: None,
         use_session_records: false,
+        update_session_records: true,
         use_pty: true,
+        tty_signals: false,
+        tty_flow_control: false,
+        forward_signals: None,
+        pam_session: true,
+        exec_in_place: false,
+        cgroup: None,
+        pty_group: None,
+        log_input: false,
+        log_output: false,
+        mail: false,
+        log_exit_status: false,
+        sudoedit_follow: false,
+        iolog: None,
+        tee_output: None,
+        tee_fd: None,
+        pty_wait_for_eof: std::time::Duration::ZERO,
+        session_reauth: None,
+        idle_timeout: None,
+        output_quota: None,
+        stay_setuid: false,
+        capabilities: None,
+        sandbox: None,
+        noexec: false,
+        command_file: None,
+        nested_sudo: 0,
+        io_plugins: Vec::new(),
+        preserve_groups: false,
+        preserve_env: sudo_options.preserve_env_all,
+        env_vars: sudo_options
+            .env_var_list
+            .iter()
+            .map(|(name, value)| (name.into(), value.into()))
+            .collect(),
+        set_home: sudo_options.set_home,
+        stats: false,
     }
 }
 
@@ -155,7 +211,8 @@ fn test_environment_variable_filtering() {
         let settings = crate::sudoers::Judgement::default();
         let context = create_test_context(&options);
         let resulting_env =
-            get_target_environment(initial_env.clone(), HashMap::new(), &context, &settings);
+            get_target_environment(initial_env.clone(), HashMap::new(), &context, &settings)
+                .unwrap();
 
         let resulting_env = environment_to_set(resulting_env);
         let expected_env = environment_to_set(expected_env);
@@ -171,3 +228,67 @@ fn test_environment_variable_filtering() {
         );
     }
 }
+
+#[test]
+fn test_set_home_overrides_preserved_home() {
+    let initial_env = Environment::from([("HOME".into(), "/home/test".into())]);
+    let policy = TestConfiguration {
+        keep: HashSet::from(["HOME".to_string()]),
+        reset: true,
+        ..Default::default()
+    };
+
+    for (cmd, expected_home) in [("sudo env", "/home/test"), ("sudo -H env", "/root")] {
+        let options = SudoOptions::try_parse_from(cmd.split_whitespace()).unwrap();
+        let context = create_test_context(&options);
+        let resulting_env =
+            get_target_environment(initial_env.clone(), HashMap::new(), &context, &policy).unwrap();
+
+        assert_eq!(resulting_env[OsStr::new("HOME")], expected_home, "{cmd}");
+    }
+}
+
+#[test]
+fn test_env_assignments_override_filtering() {
+    let initial_env = Environment::from([
+        ("HOME".into(), "/home/test".into()),
+        ("http_proxy".into(), "http://elsewhere".into()),
+        ("LM_LICENSE_FILE".into(), "27000@elsewhere".into()),
+    ]);
+    let policy = TestConfiguration {
+        keep: HashSet::from(["HOME".to_string()]),
+        reset: true,
+        assignments: vec![
+            ("http_proxy".to_string(), "http://proxy:3128".to_string()),
+            ("HOME".to_string(), "/srv/tool".to_string()),
+        ],
+        ..Default::default()
+    };
+
+    let options = SudoOptions::try_parse_from(["sudo", "env"]).unwrap();
+    let context = create_test_context(&options);
+    let resulting_env =
+        get_target_environment(initial_env, HashMap::new(), &context, &policy).unwrap();
+
+    assert_eq!(resulting_env[OsStr::new("http_proxy")], "http://proxy:3128");
+    assert_eq!(resulting_env[OsStr::new("HOME")], "/srv/tool");
+    assert!(!resulting_env.contains_key(OsStr::new("LM_LICENSE_FILE")));
+}
+
+#[test]
+fn test_sudo_ps1_sets_ps1_unless_login_shell() {
+    let initial_env = Environment::from([("SUDO_PS1".into(), "# ".into())]);
+    let settings = crate::sudoers::Judgement::default();
+
+    let options = SudoOptions::try_parse_from(["sudo", "env"]).unwrap();
+    let mut context = create_test_context(&options);
+    let resulting_env =
+        get_target_environment(initial_env.clone(), HashMap::new(), &context, &settings).unwrap();
+    assert_eq!(resulting_env[OsStr::new("PS1")], "# ");
+    assert!(!resulting_env.contains_key(OsStr::new("SUDO_PS1")));
+
+    context.launch = LaunchType::Login;
+    let resulting_env =
+        get_target_environment(initial_env, HashMap::new(), &context, &settings).unwrap();
+    assert!(!resulting_env.contains_key(OsStr::new("PS1")));
+}
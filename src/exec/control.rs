@@ -0,0 +1,275 @@
+//! The control socket of a session, with the `control-socket` feature: while sudo waits for the
+//! command, it listens on `/run/sudo-rs/sessions/<pid of sudo>`, where tools that run as root can
+//! ask what the session is, and end it.
+//!
+//! Every connection carries a single JSON-RPC 2.0 request, which is answered before the
+//! connection is closed:
+//!
+//! - `describe` returns the invoking and target user, the command and its arguments, the process
+//!   ID of sudo and of the command, when the session started, and the terminal it runs on;
+//! - `terminate` sends `SIGTERM` to the command, as if sudo received it, and returns `true`.
+//!
+//! The directory is only accessible to root, and it is gone after a reboot, as `/run` is.
+use std::fs::{self, DirBuilder};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::common::json;
+use crate::log::dev_warn;
+use crate::system::{interface::ProcessId, term::current_tty_name};
+
+use super::RunOptions;
+
+/// The directory with the sockets of all sessions.
+const SESSIONS_DIR: &str = "/run/sudo-rs/sessions";
+
+/// The longest request that is read.
+const MAX_REQUEST: u64 = 4096;
+
+/// How long a client may take to send its request, as sudo does nothing else in the meantime.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// What is known about the session when it starts.
+struct SessionInfo {
+    user: String,
+    runas_user: String,
+    command: String,
+    arguments: Vec<String>,
+    tty: Option<String>,
+    sudo_pid: ProcessId,
+    started: SystemTime,
+}
+
+pub(super) struct ControlSocket {
+    listener: UnixListener,
+    path: PathBuf,
+    info: SessionInfo,
+}
+
+impl ControlSocket {
+    /// Listen for requests about the session of `options`, if sudo was built with the
+    /// `control-socket` feature; the command is run anyway if this fails.
+    pub(super) fn open(options: &impl RunOptions) -> Option<Self> {
+        if !cfg!(feature = "control-socket") {
+            return None;
+        }
+
+        let info = SessionInfo {
+            user: options.requesting_user().name.clone(),
+            runas_user: options.user().name.clone(),
+            command: options.command().ok()?.to_string_lossy().into_owned(),
+            arguments: options.arguments().clone(),
+            tty: current_tty_name()
+                .ok()
+                .map(|tty| tty.to_string_lossy().into_owned()),
+            sudo_pid: options.pid(),
+            started: SystemTime::now(),
+        };
+
+        let path = Path::new(SESSIONS_DIR).join(info.sudo_pid.to_string());
+        match Self::bind(&path) {
+            Ok(listener) => Some(Self {
+                listener,
+                path,
+                info,
+            }),
+            Err(err) => {
+                dev_warn!("cannot create control socket {}: {err}", path.display());
+                None
+            }
+        }
+    }
+
+    fn bind(path: &Path) -> io::Result<UnixListener> {
+        let dir = Path::new(SESSIONS_DIR);
+        DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+        let meta = fs::symlink_metadata(dir)?;
+        if !meta.is_dir() || meta.uid() != 0 || meta.mode() & 0o077 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{SESSIONS_DIR} must be a directory that only root can access"),
+            ));
+        }
+
+        // left behind by an earlier sudo with the same process ID, which cannot be running now
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+
+        let listener = UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        Ok(listener)
+    }
+
+    /// Answer a request from a client that connected, given the process ID of the command if it
+    /// is running. Returns whether the client asked to terminate the session.
+    pub(super) fn serve(&self, command_pid: Option<ProcessId>) -> bool {
+        let stream = match self.listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                if err.kind() != io::ErrorKind::WouldBlock {
+                    dev_warn!("cannot accept connection to control socket: {err}");
+                }
+                return false;
+            }
+        };
+
+        match self.answer(&stream, command_pid) {
+            Ok(terminate) => terminate,
+            Err(err) => {
+                dev_warn!("cannot answer request on control socket: {err}");
+                false
+            }
+        }
+    }
+
+    fn answer(&self, mut stream: &UnixStream, command_pid: Option<ProcessId>) -> io::Result<bool> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+
+        let mut request = String::new();
+        BufReader::new(stream.take(MAX_REQUEST)).read_line(&mut request)?;
+        let (response, terminate) = self.respond(&request, command_pid);
+        stream.write_all(response.as_bytes())?;
+
+        Ok(terminate)
+    }
+
+    /// The response to a JSON-RPC request, and whether it asks to terminate the session.
+    fn respond(&self, request: &str, command_pid: Option<ProcessId>) -> (String, bool) {
+        let Some(fields) = json::fields(request) else {
+            return (error_response("null", -32700, "Parse error"), false);
+        };
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| *value)
+        };
+        let id = field("id").unwrap_or("null");
+
+        match field("method").and_then(json::unquote).as_deref() {
+            Some("describe") => (result_response(id, &self.describe(command_pid)), false),
+            Some("terminate") => (result_response(id, "true"), true),
+            Some(_) => (error_response(id, -32601, "Method not found"), false),
+            None => (error_response(id, -32600, "Invalid Request"), false),
+        }
+    }
+
+    fn describe(&self, command_pid: Option<ProcessId>) -> String {
+        let info = &self.info;
+        let started = info
+            .started
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        json::object(&[
+            ("user", json::string(&info.user)),
+            ("runas_user", json::string(&info.runas_user)),
+            ("command", json::string(&info.command)),
+            (
+                "arguments",
+                json::string_array(info.arguments.iter().map(String::as_str)),
+            ),
+            ("sudo_pid", info.sudo_pid.to_string()),
+            (
+                "pid",
+                command_pid.map_or("null".to_string(), |pid| pid.to_string()),
+            ),
+            ("start_time", started.to_string()),
+            (
+                "tty",
+                info.tty.as_deref().map_or("null".to_string(), json::string),
+            ),
+        ])
+    }
+}
+
+fn result_response(id: &str, result: &str) -> String {
+    json::object(&[
+        ("jsonrpc", json::string("2.0")),
+        ("id", id.to_string()),
+        ("result", result.trim_end().replace('\n', "\n  ")),
+    ])
+}
+
+fn error_response(id: &str, code: i32, message: &str) -> String {
+    let error = format!(
+        "{{\"code\": {code}, \"message\": {}}}",
+        json::string(message)
+    );
+    json::object(&[
+        ("jsonrpc", json::string("2.0")),
+        ("id", id.to_string()),
+        ("error", error),
+    ])
+}
+
+impl AsRawFd for ControlSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_requests() {
+        let path = std::env::temp_dir().join(format!("sudo-rs-control-{}", std::process::id()));
+        let control = ControlSocket {
+            listener: UnixListener::bind(&path).unwrap(),
+            path,
+            info: SessionInfo {
+                user: "alice".to_string(),
+                runas_user: "root".to_string(),
+                command: "/usr/bin/top".to_string(),
+                arguments: vec!["-b".to_string()],
+                tty: None,
+                sudo_pid: 10,
+                started: UNIX_EPOCH + Duration::from_secs(1700000000),
+            },
+        };
+
+        let (describe, terminate) = control.respond(
+            r#"{"jsonrpc": "2.0", "method": "describe", "id": 1}"#,
+            Some(11),
+        );
+        assert!(!terminate);
+        assert!(describe.starts_with("{\n  \"jsonrpc\": \"2.0\",\n  \"id\": 1,\n"));
+        assert!(describe.contains("\"user\": \"alice\""));
+        assert!(describe.contains("\"arguments\": [\"-b\"]"));
+        assert!(describe.contains("\"pid\": 11"));
+        assert!(describe.contains("\"start_time\": 1700000000"));
+        assert!(describe.contains("\"tty\": null"));
+
+        let (response, terminate) = control.respond(
+            r#"{"jsonrpc": "2.0", "method": "terminate", "id": "x"}"#,
+            None,
+        );
+        assert!(terminate);
+        assert!(response.contains("\"result\": true"));
+
+        let (response, terminate) = control.respond(r#"{"method": "reboot", "id": 2}"#, None);
+        assert!(!terminate);
+        assert!(response.contains("\"code\": -32601"));
+        assert!(control.respond("not json", None).0.contains("-32700"));
+
+        let path = control.path.clone();
+        drop(control);
+        assert!(!path.exists());
+    }
+}
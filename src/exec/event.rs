@@ -6,7 +6,7 @@ use std::{
 
 use libc::{c_short, pollfd, POLLIN, POLLOUT};
 
-use crate::{cutils::cerr, log::dev_debug};
+use crate::{log::dev_debug, system::poll::poll};
 
 pub(super) trait Process: Sized {
     /// IO Events that this process should handle.
@@ -177,7 +177,7 @@ impl<T: Process> EventRegistry<T> {
         }
 
         // FIXME: we should set either a timeout or use ppoll when available.
-        cerr(unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, -1) })?;
+        poll(&mut fds, None)?;
 
         // Remove the ids that correspond to file descriptors that were not ready.
         for (i, fd) in fds.iter().enumerate().rev() {
@@ -0,0 +1,152 @@
+//! What went wrong when a command could not be executed: the step that failed, along with the
+//! error of the system call, which is passed from the process that tried to execute the command to
+//! the one that reports it.
+use std::{ffi::c_int, fmt, io, mem::size_of};
+
+use crate::common::bin_serde::DeSerialize;
+
+/// The steps of executing a command, in the order in which they are taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecPhase {
+    /// Starting a new session for the command, with `setsid`.
+    Session,
+    /// Making the pty the controlling terminal of that session.
+    ControllingTerminal,
+    /// Giving the command its standard streams and closing all other file descriptors.
+    FileDescriptors,
+    /// Moving the command to its cgroup.
+    Cgroup,
+    /// Restricting what the command may do, e.g. with `Defaults sandbox`.
+    Restrictions,
+    /// Setting the supplementary groups of the target user, with `setgroups`.
+    Groups,
+    /// Switching to the user and group IDs of the target user.
+    User,
+    /// Changing to the working directory of the command.
+    Chdir,
+    /// Executing the program.
+    Exec,
+}
+
+impl ExecPhase {
+    /// All phases, in the order of their discriminants.
+    const ALL: [ExecPhase; 9] = [
+        ExecPhase::Session,
+        ExecPhase::ControllingTerminal,
+        ExecPhase::FileDescriptors,
+        ExecPhase::Cgroup,
+        ExecPhase::Restrictions,
+        ExecPhase::Groups,
+        ExecPhase::User,
+        ExecPhase::Chdir,
+        ExecPhase::Exec,
+    ];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            ExecPhase::Session => "unable to start a new session",
+            ExecPhase::ControllingTerminal => "unable to set the controlling terminal",
+            ExecPhase::FileDescriptors => "unable to set up the file descriptors of the command",
+            ExecPhase::Cgroup => "unable to move the command to its cgroup",
+            ExecPhase::Restrictions => "unable to restrict the command",
+            ExecPhase::Groups => "unable to change to the target user's groups",
+            ExecPhase::User => "unable to change to the target user",
+            ExecPhase::Chdir => "unable to change to the working directory",
+            ExecPhase::Exec => "unable to execute the command",
+        }
+    }
+}
+
+/// Executing a command failed at `phase`, with the error number `errno`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExecFailure {
+    pub phase: ExecPhase,
+    pub errno: c_int,
+}
+
+impl ExecFailure {
+    /// The failure of `phase` with `err`, which is expected to be an error of the system.
+    pub(super) fn new(phase: ExecPhase, err: &io::Error) -> Self {
+        Self {
+            phase,
+            errno: err.raw_os_error().unwrap_or(libc::EIO),
+        }
+    }
+
+    /// Returns a function that turns an error into the failure of `phase`.
+    pub(super) fn at(phase: ExecPhase) -> impl Fn(io::Error) -> Self {
+        move |err| Self::new(phase, &err)
+    }
+
+    /// The failure that `err` was made from, if any.
+    pub fn of(err: &io::Error) -> Option<Self> {
+        err.get_ref()?.downcast_ref::<Self>().copied()
+    }
+
+    /// The error of the system that caused the failure.
+    pub fn os_error(&self) -> io::Error {
+        io::Error::from_raw_os_error(self.errno)
+    }
+}
+
+impl fmt::Display for ExecFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.phase.as_str(), self.os_error())
+    }
+}
+
+impl std::error::Error for ExecFailure {}
+
+impl From<ExecFailure> for io::Error {
+    fn from(failure: ExecFailure) -> Self {
+        io::Error::new(failure.os_error().kind(), failure)
+    }
+}
+
+const ERRNO_LEN: usize = size_of::<c_int>();
+
+impl DeSerialize for ExecFailure {
+    type Bytes = [u8; ERRNO_LEN + 1];
+
+    fn serialize(&self) -> Self::Bytes {
+        let mut buf = [0; ERRNO_LEN + 1];
+        buf[..ERRNO_LEN].copy_from_slice(&self.errno.to_ne_bytes());
+        buf[ERRNO_LEN] = self.phase as u8;
+        buf
+    }
+
+    fn deserialize(buf: Self::Bytes) -> Self {
+        Self {
+            phase: ExecPhase::ALL
+                .get(buf[ERRNO_LEN] as usize)
+                .copied()
+                .unwrap_or(ExecPhase::Exec),
+            errno: c_int::from_ne_bytes(buf[..ERRNO_LEN].try_into().unwrap()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passes_failures_on() {
+        for phase in ExecPhase::ALL {
+            let failure = ExecFailure {
+                phase,
+                errno: libc::EPERM,
+            };
+            assert_eq!(ExecFailure::deserialize(failure.serialize()), failure);
+        }
+
+        let failure = ExecFailure::new(ExecPhase::Groups, &io::Error::from_raw_os_error(1));
+        let err = io::Error::from(failure);
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(ExecFailure::of(&err), Some(failure));
+        assert!(err
+            .to_string()
+            .starts_with("unable to change to the target user's groups: "));
+        assert_eq!(ExecFailure::of(&io::Error::from_raw_os_error(1)), None);
+    }
+}
@@ -1,8 +1,17 @@
+use std::fs::File;
 use std::io::{self, ErrorKind};
-use std::path::PathBuf;
+use std::os::fd::{AsFd, BorrowedFd};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use crate::common::{context::LaunchType, Context};
-use crate::system::{Group, User};
+use crate::iolog::IoLogOptions;
+use crate::plugin::IoPlugin;
+use crate::system::{
+    capabilities::CapabilitySet, sandbox::SandboxProfile, signal::SignalNumber, Group, User,
+};
+
+use super::OutputQuota;
 
 pub trait RunOptions {
     fn command(&self) -> io::Result<&PathBuf>;
@@ -15,6 +24,26 @@ pub trait RunOptions {
     fn group(&self) -> &Group;
     fn pid(&self) -> i32;
     fn use_pty(&self) -> bool;
+    fn exec_in_place(&self) -> bool;
+    fn tty_signals(&self) -> bool;
+    fn tty_flow_control(&self) -> bool;
+    fn forward_signals(&self) -> Option<&[SignalNumber]>;
+    fn pty_wait_for_eof(&self) -> Duration;
+    fn session_reauth(&self) -> Option<Duration>;
+    fn idle_timeout(&self) -> Option<Duration>;
+    fn output_quota(&self) -> Option<OutputQuota>;
+    fn preserve_groups(&self) -> bool;
+    fn stay_setuid(&self) -> bool;
+    fn command_file(&self) -> Option<&File>;
+    fn capabilities(&self) -> Option<CapabilitySet>;
+    fn sandbox(&self) -> Option<&SandboxProfile>;
+    fn cgroup(&self) -> Option<&str>;
+    fn pty_group(&self) -> Option<&str>;
+    fn io_plugins(&self) -> &[IoPlugin];
+    fn iolog(&self) -> Option<&IoLogOptions>;
+    fn tee_output(&self) -> Option<&Path>;
+    fn tee_fd(&self) -> Option<BorrowedFd<'_>>;
+    fn session_uuid(&self) -> Option<&str>;
 }
 
 impl RunOptions for Context {
@@ -61,4 +90,84 @@ impl RunOptions for Context {
     fn use_pty(&self) -> bool {
         self.use_pty
     }
+
+    fn exec_in_place(&self) -> bool {
+        self.exec_in_place
+    }
+
+    fn tty_signals(&self) -> bool {
+        self.tty_signals
+    }
+
+    fn tty_flow_control(&self) -> bool {
+        self.tty_flow_control
+    }
+
+    fn forward_signals(&self) -> Option<&[SignalNumber]> {
+        self.forward_signals.as_deref()
+    }
+
+    fn pty_wait_for_eof(&self) -> Duration {
+        self.pty_wait_for_eof
+    }
+
+    fn session_reauth(&self) -> Option<Duration> {
+        self.session_reauth
+    }
+
+    fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout
+    }
+
+    fn output_quota(&self) -> Option<OutputQuota> {
+        self.output_quota
+    }
+
+    fn preserve_groups(&self) -> bool {
+        self.preserve_groups
+    }
+
+    fn stay_setuid(&self) -> bool {
+        self.stay_setuid
+    }
+
+    fn command_file(&self) -> Option<&File> {
+        self.command_file.as_ref()
+    }
+
+    fn capabilities(&self) -> Option<CapabilitySet> {
+        self.capabilities
+    }
+
+    fn sandbox(&self) -> Option<&SandboxProfile> {
+        self.sandbox.as_ref()
+    }
+
+    fn cgroup(&self) -> Option<&str> {
+        self.cgroup.as_deref()
+    }
+
+    fn pty_group(&self) -> Option<&str> {
+        self.pty_group.as_deref()
+    }
+
+    fn io_plugins(&self) -> &[IoPlugin] {
+        &self.io_plugins
+    }
+
+    fn iolog(&self) -> Option<&IoLogOptions> {
+        self.iolog.as_ref()
+    }
+
+    fn tee_output(&self) -> Option<&Path> {
+        self.tee_output.as_deref()
+    }
+
+    fn tee_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.tee_fd.as_ref().map(|fd| fd.as_fd())
+    }
+
+    fn session_uuid(&self) -> Option<&str> {
+        Some(&self.session_uuid)
+    }
 }
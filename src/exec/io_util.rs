@@ -1,4 +1,6 @@
-use std::io;
+use std::{io, time::Duration};
+
+use crate::log::{dev_info, user_message};
 
 /// Return `true` if the IO error is an interruption.
 pub(super) fn was_interrupted(err: &io::Error) -> bool {
@@ -18,3 +20,148 @@ pub(super) fn retry_while_interrupted<T>(mut f: impl FnMut() -> io::Result<T>) -
         }
     }
 }
+
+/// How long to wait before trying again after a transient error.
+const RETRY_DELAY: Duration = Duration::from_millis(10);
+
+/// Call `f` up to `attempts` times while it fails with an interruption or with `EIO`, which a
+/// terminal reports e.g. while its other side is being set up.
+pub(super) fn retry_transient<T>(
+    attempts: usize,
+    mut f: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let mut attempt = 1;
+    loop {
+        match f() {
+            Err(err)
+                if attempt < attempts
+                    && (was_interrupted(&err) || err.raw_os_error() == Some(libc::EIO)) =>
+            {
+                dev_info!("retrying after transient error: {err}");
+                attempt += 1;
+                std::thread::sleep(RETRY_DELAY);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Describe an IO error the way `strerror` would, i.e. without the "(os error N)" suffix that the
+/// `Display` implementation of [`io::Error`] adds.
+pub(super) fn describe_error(err: &io::Error) -> String {
+    let message = err.to_string();
+    match err.raw_os_error() {
+        Some(code) => message
+            .strip_suffix(&format!(" (os error {code})"))
+            .map(str::to_owned)
+            .unwrap_or(message),
+        None => message,
+    }
+}
+
+/// A message about a failed system call that can be shown to the user after `fork`, when it can no
+/// longer be formatted. It is prepared for each of the `errors` the system call is known to fail
+/// with; other errors are not described.
+pub(super) struct ErrorMessage {
+    messages: Vec<(i32, Vec<u8>)>,
+    other: Vec<u8>,
+}
+
+impl ErrorMessage {
+    pub(super) fn new(what: &str, errors: &[i32]) -> Self {
+        let messages = errors
+            .iter()
+            .map(|&code| {
+                let err = io::Error::from_raw_os_error(code);
+                let message = user_message(format_args!("{what}: {}", describe_error(&err)));
+                (code, message)
+            })
+            .collect();
+
+        Self {
+            messages,
+            other: user_message(format_args!("{what}")),
+        }
+    }
+
+    fn get(&self, err: &io::Error) -> &[u8] {
+        self.messages
+            .iter()
+            .find(|(code, _)| Some(*code) == err.raw_os_error())
+            .map_or(&self.other, |(_, message)| message)
+    }
+
+    /// Write the message for `err` to stderr; this only calls async-signal-safe functions.
+    pub(super) fn show(&self, err: &io::Error) {
+        let mut message = self.get(err);
+        while !message.is_empty() {
+            // SAFETY: `message` is valid for reads of `message.len()` bytes.
+            match unsafe {
+                libc::write(libc::STDERR_FILENO, message.as_ptr().cast(), message.len())
+            } {
+                -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+                written if written > 0 => message = &message[written as usize..],
+                _ => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::{describe_error, retry_transient, ErrorMessage};
+
+    #[test]
+    fn retries_transient_errors() {
+        let mut calls = 0;
+        let result = retry_transient(3, || {
+            calls += 1;
+            match calls {
+                1 => Err(io::Error::from_raw_os_error(libc::EINTR)),
+                2 => Err(io::Error::from_raw_os_error(libc::EIO)),
+                _ => Ok(calls),
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        let mut calls = 0;
+        let result: io::Result<()> = retry_transient(3, || {
+            calls += 1;
+            Err(io::Error::from_raw_os_error(libc::EIO))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 3);
+
+        let mut calls = 0;
+        let result: io::Result<()> = retry_transient(3, || {
+            calls += 1;
+            Err(io::Error::from_raw_os_error(libc::ENOTTY))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn describes_os_errors_without_code() {
+        let err = io::Error::from_raw_os_error(libc::ENOENT);
+        assert_eq!(describe_error(&err), "No such file or directory");
+
+        let err = io::Error::new(io::ErrorKind::Other, "custom error");
+        assert_eq!(describe_error(&err), "custom error");
+    }
+
+    #[test]
+    fn prepares_error_messages() {
+        let message = ErrorMessage::new("unable to go to /x", &[libc::ENOENT]);
+        assert_eq!(
+            message.get(&io::Error::from_raw_os_error(libc::ENOENT)),
+            b"unable to go to /x: No such file or directory\n"
+        );
+        assert_eq!(
+            message.get(&io::Error::from_raw_os_error(libc::EPERM)),
+            b"unable to go to /x\n"
+        );
+    }
+}
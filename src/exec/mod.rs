@@ -1,69 +1,121 @@
+mod control;
 mod event;
+mod failure;
 mod interface;
 mod io_util;
 mod no_pty;
+mod prepared;
+mod tee;
 mod use_pty;
 
 use std::{
     borrow::Cow,
-    env,
-    ffi::{c_int, OsStr},
+    ffi::{c_int, OsString},
     io,
-    os::unix::ffi::OsStrExt,
-    os::unix::process::CommandExt,
-    process::Command,
+    os::unix::ffi::{OsStrExt, OsStringExt},
     time::Duration,
 };
 
+use crate::system::kill;
 use crate::{
     common::Environment,
     log::dev_warn,
     system::{
-        _exit,
+        _exit, disable_core_dumps,
         interface::ProcessId,
-        killpg,
-        signal::{consts::*, signal_name},
-        wait::{Wait, WaitError, WaitOptions},
+        signal::{consts::*, signal_name, SignalHandler, SignalHandlerBehavior, SignalSet},
+        wait::{ResourceUsage, Wait, WaitError, WaitOptions},
+        ChildProcess,
     },
 };
 use crate::{
-    exec::no_pty::exec_no_pty,
-    log::dev_info,
-    system::{set_target_user, signal::SignalNumber, term::UserTerm},
+    exec::no_pty::{exec_in_place, exec_no_pty},
+    iolog::{IoLog, IoLogOptions, Session},
+    log::{dev_info, user_warn},
+    system::{
+        cgroup::Cgroup,
+        identity::Identity,
+        sandbox::InjectionFilter,
+        signal::SignalNumber,
+        term::{current_tty_name, UserTerm},
+    },
 };
-use crate::{log::user_error, system::kill};
 
+pub use failure::{ExecFailure, ExecPhase};
 pub use interface::RunOptions;
+pub(crate) use tee::{take_inherited_fd, TEE_FD_VAR};
 
 use self::{
+    control::ControlSocket,
     event::{EventRegistry, Process},
     io_util::was_interrupted,
+    prepared::PreparedCommand,
+    tee::Tee,
     use_pty::{exec_pty, SIGCONT_BG, SIGCONT_FG},
 };
 
+/// Asks the invoking user to authenticate again while the command runs, and returns whether they
+/// did (`session_reauth`).
+pub type Reauthenticate = Box<dyn FnMut() -> bool>;
+
+/// How much of the output of a command running in a pty is logged, and what happens once it wrote
+/// more (`output_quota` and `output_quota_action`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputQuota {
+    pub bytes: u64,
+    /// End the session, instead of only no longer logging the output.
+    pub terminate: bool,
+}
+
 /// Based on `ogsudo`s `exec_pty` function.
 ///
 /// Returns the [`ExitReason`] of the command and a function that restores the default handler for
-/// signals once its called.
-pub fn run_command(options: &impl RunOptions, env: Environment) -> io::Result<ExecOutput> {
-    match run_command_internal(options, env)? {
-        ProcessOutput::SudoExit { output } => Ok(output),
+/// signals once its called. If `options` asks for it, `reauth` is called every so often while the
+/// command runs in a pty.
+pub fn run_command(
+    options: &impl RunOptions,
+    env: Environment,
+    reauth: Option<Reauthenticate>,
+) -> io::Result<ExecOutput> {
+    // The cgroup is created here, so it can be removed once the command exited.
+    let cgroup = match options.cgroup() {
+        Some(parent) => Some(Cgroup::create(parent, options.pid()).map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("cannot create cgroup below {parent}: {err}"),
+            )
+        })?),
+        None => None,
+    };
+
+    let output = match run_command_internal(options, env, cgroup.as_ref(), reauth) {
+        Ok(ProcessOutput::SudoExit { output }) => Ok(output),
         // We call `_exit` instead of `exit` to avoid flushing the parent's IO streams by accident.
-        ProcessOutput::ChildExit => _exit(1),
+        Ok(ProcessOutput::ChildExit) => _exit(1),
+        Err(err) => Err(err),
+    };
+
+    if let Some(cgroup) = cgroup {
+        let path = cgroup.path().to_owned();
+        if let Err(err) = cgroup.remove() {
+            dev_info!("cannot remove cgroup {}: {err}", path.display());
+        }
     }
+
+    output
 }
 
-fn run_command_internal(options: &impl RunOptions, env: Environment) -> io::Result<ProcessOutput> {
+fn run_command_internal(
+    options: &impl RunOptions,
+    env: Environment,
+    cgroup: Option<&Cgroup>,
+    reauth: Option<Reauthenticate>,
+) -> io::Result<ProcessOutput> {
     // FIXME: should we pipe the stdio streams?
     let qualified_path = options.command()?;
-    let mut command = Command::new(qualified_path);
-    // reset env and set filtered environment
-    command.args(options.arguments()).env_clear().envs(env);
     // set the arg0 to the requested string
     // TODO: this mechanism could perhaps also be used to set the arg0 for login shells, as below
-    if let Some(arg0) = options.arg0() {
-        command.arg0(arg0);
-    }
+    let mut arg0 = options.arg0().map(|arg0| arg0.as_os_str().to_owned());
     // Decide if the pwd should be changed. `--chdir` takes precedence over `-i`.
     let path = options.chdir().cloned().or_else(|| {
         options.is_login().then(|| {
@@ -73,56 +125,184 @@ fn run_command_internal(options: &impl RunOptions, env: Environment) -> io::Resu
                 .map(|osstr| osstr.as_bytes().to_vec())
                 .unwrap_or_else(Vec::new);
             process_name.insert(0, b'-');
-            command.arg0(OsStr::from_bytes(&process_name));
+            arg0 = Some(OsString::from_vec(process_name));
 
             options.user().home.clone()
         })
     });
 
     // set target user and groups
-    set_target_user(
-        &mut command,
-        options.user().clone(),
-        options.group().clone(),
-    );
+    let preserved_groups = options
+        .preserve_groups()
+        .then(|| options.requesting_user().groups.as_slice());
+    let mut identity = Identity::new(options.user(), options.group(), preserved_groups)
+        .with_capabilities(options.capabilities(), options.requesting_user());
+    if options.stay_setuid() {
+        identity = identity.keep_real_user(options.requesting_user());
+    }
+    // everything the command needs is prepared here, as only system calls can be made after `fork`
+    let mut command = PreparedCommand::new(
+        qualified_path,
+        arg0.as_deref(),
+        options.arguments(),
+        env,
+        identity,
+    )?;
+
+    // execute the program that was checked, even if its path refers to another file by now
+    if let Some(file) = options.command_file() {
+        command.exec_through_fd(file)?;
+    }
+
+    if let Some(cgroup) = cgroup {
+        command.cgroup(cgroup)?;
+    }
 
     // change current directory if necessary.
     if let Some(path) = path {
-        let is_chdir = options.chdir().is_some();
-
-        unsafe {
-            command.pre_exec(move || {
-                if let Err(err) = env::set_current_dir(&path) {
-                    user_error!("unable to change directory to {}: {}", path.display(), err);
-                    if is_chdir {
-                        return Err(err);
-                    }
-                }
+        command.chdir(&path, options.chdir().is_some())?;
+    }
 
-                Ok(())
-            });
-        }
+    // whatever terminals the command gets hold of, it cannot make them read input it made up
+    match InjectionFilter::new() {
+        Ok(filter) => command.injection_filter(filter),
+        Err(err) => dev_info!("cannot keep the command from typing into terminals: {err}"),
     }
 
-    if options.use_pty() {
+    // restrict the command to its sandbox, after everything else that needs privileges is done
+    if let Some(profile) = options.sandbox() {
+        command.sandbox(profile.prepare()?);
+    }
+
+    // I/O plugins, the I/O log and the tees can only see the terminal of the command if it runs
+    // in a pty
+    if options.use_pty()
+        || !options.io_plugins().is_empty()
+        || options.iolog().is_some()
+        || options.tee_output().is_some()
+        || options.tee_fd().is_some()
+    {
         match UserTerm::open() {
-            Ok(user_tty) => exec_pty(options.pid(), command, user_tty),
+            Ok(user_tty) => {
+                let iolog = match options.iolog() {
+                    Some(iolog_options) => Some(create_iolog(options, iolog_options, &user_tty)?),
+                    None => None,
+                };
+
+                exec_pty(
+                    options.pid(),
+                    command,
+                    user_tty,
+                    options.tty_signals(),
+                    options.tty_flow_control(),
+                    options.forward_signals(),
+                    options.pty_wait_for_eof(),
+                    options.pty_group(),
+                    options.io_plugins(),
+                    iolog,
+                    open_tees(options),
+                    ControlSocket::open(options),
+                    options.session_reauth().zip(reauth),
+                    options.idle_timeout(),
+                    options.output_quota(),
+                )
+            }
             Err(err) => {
                 dev_info!("Could not open user's terminal, not allocating a pty: {err}");
-                exec_no_pty(options.pid(), command)
+                if reauth.is_some() {
+                    user_warn!("the session cannot be authenticated again without a terminal");
+                }
+                if options.idle_timeout().is_some() {
+                    dev_info!("idle_timeout does not apply without a terminal");
+                }
+                if options.output_quota().is_some() {
+                    dev_info!("output_quota does not apply without a terminal");
+                }
+                exec_no_pty(
+                    options.pid(),
+                    command,
+                    options.forward_signals(),
+                    ControlSocket::open(options),
+                )
             }
         }
+    } else if options.exec_in_place() && cgroup.is_none() {
+        dev_info!("executing the command in place of sudo");
+        exec_in_place(command)
     } else {
-        exec_no_pty(options.pid(), command)
+        exec_no_pty(
+            options.pid(),
+            command,
+            options.forward_signals(),
+            ControlSocket::open(options),
+        )
     }
 }
 
+/// Start the I/O log of the command, which runs in a pty on `user_tty`.
+fn create_iolog(
+    options: &impl RunOptions,
+    iolog_options: &IoLogOptions,
+    user_tty: &UserTerm,
+) -> io::Result<IoLog> {
+    let size = user_tty.get_size()?;
+    let session = Session {
+        user: &options.requesting_user().name,
+        runas_user: &options.user().name,
+        runas_group: &options.group().name,
+        tty: current_tty_name().ok(),
+        session_id: crate::system::Process::session_id(),
+        login_uid: crate::system::Process::login_uid(),
+        uuid: options.session_uuid(),
+        cwd: std::env::current_dir().ok(),
+        command: options.command()?,
+        arguments: options.arguments(),
+        lines: size.rows(),
+        cols: size.cols(),
+    };
+
+    let iolog = IoLog::create(iolog_options, &session).map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!(
+                "cannot create I/O log in {}: {err}",
+                iolog_options.dir.display()
+            ),
+        )
+    })?;
+    dev_info!("logging I/O to {}", iolog.path().display());
+
+    Ok(iolog)
+}
+
+/// Open the sinks that get a copy of the output of the command. A sink that cannot be opened is
+/// left out, as it is not needed to run the command.
+fn open_tees(options: &impl RunOptions) -> Vec<Tee> {
+    let mut tees = Vec::new();
+    if let Some(path) = options.tee_output() {
+        match Tee::open(path) {
+            Ok(tee) => tees.push(tee),
+            Err(err) => user_warn!("cannot copy output to {}: {err}", path.display()),
+        }
+    }
+    if let Some(fd) = options.tee_fd() {
+        match Tee::inherit(fd) {
+            Ok(tee) => tees.push(tee),
+            Err(err) => user_warn!("cannot copy output to {TEE_FD_VAR}: {err}"),
+        }
+    }
+
+    tees
+}
+
 /// The output of a command's execution.
 pub struct ExecOutput {
     /// The exit reason of the executed command,
     pub command_exit_reason: ExitReason,
     /// A function to restore the signal handlers that were modified to execute the command.
     pub restore_signal_handlers: Box<dyn FnOnce()>,
+    /// The resources that the command used, if they are known.
+    pub resource_usage: Option<ResourceUsage>,
 }
 
 enum ProcessOutput {
@@ -139,15 +319,96 @@ pub enum ExitReason {
     Signal(i32),
 }
 
+impl ExitReason {
+    /// Exit the current process in the same way as the command did. If the command was terminated
+    /// by a signal, the signal is sent to the current process as well, so its parent sees the
+    /// same wait status as if it had run the command itself.
+    ///
+    /// This should be called after the signal handlers have been restored.
+    pub fn exit_process(self) -> ! {
+        match self {
+            ExitReason::Code(code) => std::process::exit(code),
+            ExitReason::Signal(signal) => {
+                if !matches!(signal, SIGKILL | SIGSTOP) {
+                    // the signal may have been ignored or blocked when we were started
+                    if let Ok(handler) =
+                        SignalHandler::register(signal, SignalHandlerBehavior::Default)
+                    {
+                        handler.forget();
+                    }
+                    if let Ok(set) = SignalSet::single(signal) {
+                        let _ = set.unblock();
+                    }
+                    // the command may have dumped core, but we should not
+                    let _ = disable_core_dumps();
+                }
+                let _ = kill(crate::system::Process::process_id(), signal);
+
+                // the default action of some signals is to do nothing
+                std::process::exit(128 + signal)
+            }
+        }
+    }
+}
+
 // Kill the process with increasing urgency.
 //
 // Based on `terminate_command`.
-fn terminate_process(pid: ProcessId, use_killpg: bool) {
-    let kill_fn = if use_killpg { killpg } else { kill };
-    kill_fn(pid, SIGHUP).ok();
-    kill_fn(pid, SIGTERM).ok();
+fn terminate_process(process: ChildProcess, use_killpg: bool) {
+    let kill_fn = if use_killpg {
+        ChildProcess::killpg
+    } else {
+        ChildProcess::kill
+    };
+    kill_fn(&process, SIGHUP).ok();
+    kill_fn(&process, SIGTERM).ok();
     std::thread::sleep(Duration::from_secs(2));
-    kill_fn(pid, SIGKILL).ok();
+    kill_fn(&process, SIGKILL).ok();
+}
+
+/// Whether a signal is meant to terminate the command.
+fn is_termination_signal(signal: SignalNumber) -> bool {
+    matches!(
+        signal,
+        SIGINT | SIGQUIT | SIGTERM | SIGHUP | SIGALRM | SIGKILL
+    )
+}
+
+/// The signals for the command that arrive before it is executed.
+///
+/// Until then, the child process would handle a signal with the actions of sudo instead of the
+/// ones the command sets up, so other signals than termination signals are held back until the
+/// command is running. A termination signal is delivered right away, which aborts the command
+/// before it is executed.
+struct HeldSignals {
+    signals: Vec<SignalNumber>,
+    released: bool,
+}
+
+impl HeldSignals {
+    fn new() -> Self {
+        Self {
+            signals: Vec::new(),
+            released: false,
+        }
+    }
+
+    /// Hold back a signal if the command is not running yet, returning whether it was held.
+    fn hold(&mut self, signal: SignalNumber) -> bool {
+        if self.released || is_termination_signal(signal) {
+            return false;
+        }
+        if !self.signals.contains(&signal) {
+            self.signals.push(signal);
+        }
+        true
+    }
+
+    /// The command is running: return the signals that were held back, in order of arrival.
+    fn release(&mut self) -> Vec<SignalNumber> {
+        self.released = true;
+        std::mem::take(&mut self.signals)
+    }
 }
 
 trait HandleSigchld: Process {
@@ -156,6 +417,9 @@ trait HandleSigchld: Process {
     fn on_exit(&mut self, exit_code: c_int, registry: &mut EventRegistry<Self>);
     fn on_term(&mut self, signal: SignalNumber, registry: &mut EventRegistry<Self>);
     fn on_stop(&mut self, signal: SignalNumber, registry: &mut EventRegistry<Self>);
+    /// Called with the resources that the child used, right before it is reported as exited or
+    /// terminated.
+    fn on_resource_usage(&mut self, _usage: ResourceUsage) {}
 }
 
 fn handle_sigchld<T: HandleSigchld>(
@@ -180,6 +444,9 @@ fn handle_sigchld<T: HandleSigchld>(
             Ok((_pid, status)) => break status,
         }
     };
+    if let Some(usage) = status.resource_usage() {
+        handler.on_resource_usage(usage);
+    }
     if let Some(exit_code) = status.exit_status() {
         dev_info!("{child_pid} ({child_name}) exited with status code {exit_code}");
         handler.on_exit(exit_code, registry)
@@ -202,6 +469,11 @@ fn handle_sigchld<T: HandleSigchld>(
     }
 }
 
+/// Whether `signal` is one of the signals in `forward_signals` that are forwarded to the command.
+fn is_forwarded(forward_signals: Option<&[SignalNumber]>, signal: SignalNumber) -> bool {
+    forward_signals.map_or(true, |signals| signals.contains(&signal))
+}
+
 fn signal_fmt(signal: SignalNumber) -> Cow<'static, str> {
     match signal_name(signal) {
         name @ Cow::Owned(_) => match signal {
@@ -224,3 +496,25 @@ const fn cond_fmt<'a>(cond: bool, true_s: &'a str, false_s: &'a str) -> &'a str
 const fn opt_fmt(cond: bool, s: &str) -> &str {
     cond_fmt(cond, s, "")
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::system::signal::consts::*;
+
+    use super::HeldSignals;
+
+    #[test]
+    fn holds_signals_until_executed() {
+        let mut held = HeldSignals::new();
+        assert!(held.hold(SIGUSR1));
+        assert!(held.hold(SIGWINCH));
+        assert!(held.hold(SIGUSR1));
+        // termination signals abort the command right away
+        assert!(!held.hold(SIGTERM));
+        assert!(!held.hold(SIGINT));
+        assert_eq!(held.release(), [SIGUSR1, SIGWINCH]);
+
+        assert!(!held.hold(SIGUSR2));
+        assert!(held.release().is_empty());
+    }
+}
@@ -1,10 +1,13 @@
-use std::{ffi::c_int, io, os::unix::process::CommandExt, process::Command};
+use std::{ffi::c_int, io};
 
 use super::{
+    control::ControlSocket,
     event::PollEvent,
-    event::{EventRegistry, Process, StopReason},
+    event::{EventHandle, EventRegistry, Process, StopReason},
+    failure::{ExecFailure, ExecPhase},
     io_util::was_interrupted,
-    terminate_process, ExitReason, HandleSigchld, ProcessOutput,
+    prepared::PreparedCommand,
+    terminate_process, ExitReason, HandleSigchld, HeldSignals, ProcessOutput,
 };
 use crate::{
     common::bin_serde::BinPipe,
@@ -14,19 +17,24 @@ use crate::{
     },
 };
 use crate::{
-    exec::{handle_sigchld, opt_fmt, signal_fmt},
+    exec::{handle_sigchld, is_forwarded, opt_fmt, signal_fmt},
     log::{dev_error, dev_info, dev_warn},
     system::{
-        fork, getpgid, getpgrp,
+        _exit, fork, getpgid, getpgrp,
         interface::ProcessId,
         kill, killpg,
         term::{Terminal, UserTerm},
-        wait::WaitOptions,
-        FileCloser, ForkResult,
+        wait::{ResourceUsage, WaitOptions},
+        ChildProcess, FileCloser, ForkResult,
     },
 };
 
-pub(super) fn exec_no_pty(sudo_pid: ProcessId, mut command: Command) -> io::Result<ProcessOutput> {
+pub(super) fn exec_no_pty(
+    sudo_pid: ProcessId,
+    command: PreparedCommand,
+    forward_signals: Option<&[SignalNumber]>,
+    control: Option<ControlSocket>,
+) -> io::Result<ProcessOutput> {
     // FIXME (ogsudo): Initialize the policy plugin's session here.
 
     // Block all the signals until we are done setting up the signal handlers so we don't miss
@@ -49,38 +57,50 @@ pub(super) fn exec_no_pty(sudo_pid: ProcessId, mut command: Command) -> io::Resu
     // Don't close the error pipe as we need it to retrieve the error code if the command execution
     // fails.
     file_closer.except(&errpipe_tx);
+    command.keep_open(&mut file_closer);
 
-    let ForkResult::Parent(command_pid) = fork().map_err(|err| {
+    // SAFETY: the child only calls async-signal-safe functions until it executes the command.
+    let ForkResult::Parent(command_pid) = unsafe { fork() }.map_err(|err| {
         dev_warn!("unable to fork command process: {err}");
         err
     })?
     else {
-        file_closer.close_the_universe()?;
+        let failure = match file_closer.close_the_universe() {
+            Ok(()) => {
+                // Restore the signal mask now that the handlers have been setup.
+                if let Some(set) = original_set {
+                    set.set_mask().ok();
+                }
 
-        // Restore the signal mask now that the handlers have been setup.
-        if let Some(set) = original_set {
-            if let Err(err) = set.set_mask() {
-                dev_warn!("cannot restore signal mask: {err}");
+                command.exec()
             }
-        }
+            Err(err) => ExecFailure::new(ExecPhase::FileDescriptors, &err),
+        };
 
-        let err = command.exec();
+        // If `exec` returns, it means that executing the command failed. Send the failure to the
+        // parent using the pipe.
+        errpipe_tx.write(&failure).ok();
 
-        dev_warn!("failed to execute command: {err}");
-        // If `exec` returns, it means that executing the command failed. Send the error to the
-        // monitor using the pipe.
-        if let Some(error_code) = err.raw_os_error() {
-            errpipe_tx.write(&error_code).ok();
-        }
-
-        return Ok(ProcessOutput::ChildExit);
+        // Exit right away instead of returning, which would free memory.
+        _exit(1);
     };
 
     dev_info!("executed command with pid {command_pid}");
 
+    // The child's end of the pipe is closed when the command is executed, so we can tell when
+    // that happened.
+    drop(errpipe_tx);
+
     let mut registry = EventRegistry::new();
 
-    let mut closure = ExecClosure::new(command_pid, sudo_pid, errpipe_rx, &mut registry)?;
+    let mut closure = ExecClosure::new(
+        command_pid,
+        sudo_pid,
+        errpipe_rx,
+        forward_signals.map(<[_]>::to_vec),
+        control,
+        &mut registry,
+    )?;
 
     // Restore the signal mask now that the handlers have been setup.
     if let Some(set) = original_set {
@@ -98,32 +118,65 @@ pub(super) fn exec_no_pty(sudo_pid: ProcessId, mut command: Command) -> io::Resu
         output: crate::exec::ExecOutput {
             command_exit_reason,
             restore_signal_handlers: Box::new(move || drop(closure.signal_handlers)),
+            resource_usage: closure.resource_usage,
         },
     })
 }
 
+/// Execute the command in the sudo process itself instead of a child process, which is only
+/// possible if nothing has to be done after the command exited. This only returns if executing
+/// the command failed.
+pub(super) fn exec_in_place(command: PreparedCommand) -> io::Result<ProcessOutput> {
+    let mut file_closer = FileCloser::new();
+    command.keep_open(&mut file_closer);
+    file_closer
+        .close_the_universe()
+        .map_err(ExecFailure::at(ExecPhase::FileDescriptors))?;
+
+    Err(command.exec().into())
+}
+
 struct ExecClosure {
     command_pid: Option<ProcessId>,
     sudo_pid: ProcessId,
     parent_pgrp: ProcessId,
-    errpipe_rx: BinPipe<i32>,
+    errpipe_rx: BinPipe<ExecFailure>,
+    errpipe_handle: EventHandle,
+    held_signals: HeldSignals,
+    /// The signals that are forwarded to the command, or `None` if all of them are.
+    forward_signals: Option<Vec<SignalNumber>>,
+    resource_usage: Option<ResourceUsage>,
+    control: Option<ControlSocket>,
     signal_stream: &'static SignalStream,
     signal_handlers: [SignalHandler; ExecClosure::SIGNALS.len()],
 }
 
 impl ExecClosure {
+    #[cfg(target_os = "linux")]
     const SIGNALS: [SignalNumber; 12] = [
         SIGINT, SIGQUIT, SIGTSTP, SIGTERM, SIGHUP, SIGALRM, SIGPIPE, SIGUSR1, SIGUSR2, SIGCHLD,
         SIGCONT, SIGWINCH,
     ];
+    #[cfg(not(target_os = "linux"))]
+    const SIGNALS: [SignalNumber; 13] = [
+        SIGINT, SIGQUIT, SIGTSTP, SIGTERM, SIGHUP, SIGALRM, SIGPIPE, SIGUSR1, SIGUSR2, SIGCHLD,
+        SIGCONT, SIGWINCH, SIGINFO,
+    ];
 
     fn new(
         command_pid: ProcessId,
         sudo_pid: ProcessId,
-        errpipe_rx: BinPipe<i32>,
+        errpipe_rx: BinPipe<ExecFailure>,
+        forward_signals: Option<Vec<SignalNumber>>,
+        control: Option<ControlSocket>,
         registry: &mut EventRegistry<Self>,
     ) -> io::Result<Self> {
-        registry.register_event(&errpipe_rx, PollEvent::Readable, |_| ExecEvent::ErrPipe);
+        let errpipe_handle =
+            registry.register_event(&errpipe_rx, PollEvent::Readable, |_| ExecEvent::ErrPipe);
+
+        if let Some(control) = &control {
+            registry.register_event(control, PollEvent::Readable, |_| ExecEvent::Control);
+        }
 
         let signal_stream = SignalStream::init()?;
 
@@ -134,6 +187,11 @@ impl ExecClosure {
         Ok(Self {
             command_pid: Some(command_pid),
             errpipe_rx,
+            errpipe_handle,
+            held_signals: HeldSignals::new(),
+            forward_signals,
+            resource_usage: None,
+            control,
             sudo_pid,
             parent_pgrp: getpgrp(),
             signal_stream,
@@ -260,20 +318,52 @@ impl ExecClosure {
                     return;
                 }
 
-                if signal == SIGALRM {
-                    terminate_process(command_pid, false);
-                } else {
-                    kill(command_pid, signal).ok();
+                if !is_forwarded(self.forward_signals.as_deref(), signal) {
+                    dev_info!("not forwarding {} to the command", signal_fmt(signal));
+                    return;
                 }
+
+                if self.held_signals.hold(signal) {
+                    dev_info!(
+                        "holding back {} until the command is executed",
+                        signal_fmt(signal)
+                    );
+                    return;
+                }
+
+                forward_signal(signal, command_pid);
+            }
+        }
+    }
+
+    /// The command was executed, so it can receive the signals that were held back.
+    fn on_exec(&mut self, registry: &mut EventRegistry<Self>) {
+        // The pipe stays readable once it is closed.
+        self.errpipe_handle.ignore(registry);
+
+        for signal in self.held_signals.release() {
+            if let Some(command_pid) = self.command_pid {
+                dev_info!("sending held back {} to command", signal_fmt(signal));
+                forward_signal(signal, command_pid);
             }
         }
     }
 }
 
+fn forward_signal(signal: SignalNumber, command_pid: ProcessId) {
+    if signal == SIGALRM {
+        // the command has not been waited for yet, so its ID cannot have been reused
+        terminate_process(ChildProcess::new(command_pid), false);
+    } else {
+        kill(command_pid, signal).ok();
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum ExecEvent {
     Signal,
     ErrPipe,
+    Control,
 }
 
 impl Process for ExecClosure {
@@ -284,13 +374,28 @@ impl Process for ExecClosure {
     fn on_event(&mut self, event: Self::Event, registry: &mut EventRegistry<Self>) {
         match event {
             ExecEvent::Signal => self.on_signal(registry),
+            ExecEvent::Control => {
+                let Some(control) = &self.control else {
+                    return;
+                };
+                if control.serve(self.command_pid) {
+                    if let Some(command_pid) = self.command_pid {
+                        dev_info!("terminating the command as asked on the control socket");
+                        forward_signal(SIGTERM, command_pid);
+                    }
+                }
+            }
             ExecEvent::ErrPipe => {
                 match self.errpipe_rx.read() {
                     Err(err) if was_interrupted(&err) => { /* Retry later */ }
+                    Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                        self.on_exec(registry)
+                    }
                     Err(err) => registry.set_break(err),
-                    Ok(error_code) => {
-                        // Received error code from the command, forward it to the parent.
-                        registry.set_break(io::Error::from_raw_os_error(error_code));
+                    Ok(failure) => {
+                        // Received the failure from the command, forward it to the parent.
+                        dev_warn!("failed to execute command: {failure}");
+                        registry.set_break(failure.into());
                     }
                 }
             }
@@ -314,4 +419,8 @@ impl HandleSigchld for ExecClosure {
     fn on_stop(&mut self, signal: SignalNumber, _registry: &mut EventRegistry<Self>) {
         self.suspend_parent(signal);
     }
+
+    fn on_resource_usage(&mut self, usage: ResourceUsage) {
+        self.resource_usage = Some(usage);
+    }
 }
@@ -0,0 +1,398 @@
+//! A command that is prepared to be executed in a child process.
+//!
+//! Between `fork` and `exec`, the child process of a multithreaded process may only call
+//! async-signal-safe functions: it must not allocate or take locks, which rules out the logging
+//! macros and [`std::process::Command::exec`]. Everything the child needs is therefore prepared
+//! before forking, so that the child only has to make system calls.
+use std::{
+    ffi::{c_char, CStr, CString, OsStr},
+    fs::File,
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    os::unix::{ffi::OsStrExt, fs::FileExt},
+    path::Path,
+    ptr,
+};
+
+use crate::{
+    common::Environment,
+    cutils::cerr,
+    system::{
+        cgroup::Cgroup,
+        identity::Identity,
+        sandbox::{InjectionFilter, Sandbox},
+        signal::{consts::SIGPIPE, SignalHandler, SignalHandlerBehavior},
+        FileCloser,
+    },
+};
+
+use super::failure::{ExecFailure, ExecPhase};
+use super::io_util::ErrorMessage;
+
+/// A null-terminated array of C strings, as `execve` takes them.
+struct CStringArray {
+    strings: Vec<CString>,
+    pointers: Vec<*const c_char>,
+}
+
+impl CStringArray {
+    fn new(strings: Vec<CString>) -> Self {
+        let pointers = strings
+            .iter()
+            .map(|string| string.as_ptr())
+            .chain([ptr::null()])
+            .collect();
+
+        Self { strings, pointers }
+    }
+
+    fn as_ptr(&self) -> *const *const c_char {
+        debug_assert_eq!(self.pointers.len(), self.strings.len() + 1);
+        self.pointers.as_ptr()
+    }
+}
+
+fn c_string(bytes: impl AsRef<[u8]>) -> io::Result<CString> {
+    CString::new(bytes.as_ref()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "nul byte found in provided data",
+        )
+    })
+}
+
+/// The directory the command is started in.
+struct Chdir {
+    path: CString,
+    /// Whether the command must not be executed if the directory cannot be entered.
+    required: bool,
+    error: ErrorMessage,
+}
+
+/// The errors `chdir` can fail with, so their messages can be prepared.
+const CHDIR_ERRORS: &[i32] = &[
+    libc::EACCES,
+    libc::EIO,
+    libc::ELOOP,
+    libc::ENAMETOOLONG,
+    libc::ENOENT,
+    libc::ENOMEM,
+    libc::ENOTDIR,
+];
+
+pub(super) struct PreparedCommand {
+    program: CString,
+    /// The program, if it is executed through this file descriptor instead of by its path.
+    program_fd: Option<OwnedFd>,
+    args: CStringArray,
+    env: CStringArray,
+    /// The file descriptors that become the stdin, stdout and stderr of the command; if there is
+    /// none, the command inherits the one of sudo.
+    stdio: [Option<OwnedFd>; 3],
+    identity: Identity,
+    /// The `cgroup.procs` file of the cgroup the command is moved to.
+    cgroup: Option<CString>,
+    chdir: Option<Chdir>,
+    sandbox: Option<Sandbox>,
+    injection_filter: Option<InjectionFilter>,
+}
+
+impl PreparedCommand {
+    /// Prepare `program` to be executed with `args` in the environment `env` as `identity`. The
+    /// name of the program (`argv[0]`) is `arg0`, or the program itself.
+    pub(super) fn new(
+        program: &Path,
+        arg0: Option<&OsStr>,
+        args: &[String],
+        env: Environment,
+        identity: Identity,
+    ) -> io::Result<Self> {
+        let arg0 = arg0.unwrap_or(program.as_os_str());
+        let args = [c_string(arg0.as_bytes())]
+            .into_iter()
+            .chain(args.iter().map(c_string))
+            .collect::<io::Result<_>>()?;
+        let env = env
+            .into_iter()
+            .map(|(name, value)| c_string([name.as_bytes(), b"=", value.as_bytes()].concat()))
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self {
+            program: c_string(program.as_os_str().as_bytes())?,
+            program_fd: None,
+            args: CStringArray::new(args),
+            env: CStringArray::new(env),
+            stdio: [None, None, None],
+            identity,
+            cgroup: None,
+            chdir: None,
+            sandbox: None,
+            injection_filter: None,
+        })
+    }
+
+    /// Set the stdin of the command, `None` lets it inherit the one of sudo.
+    pub(super) fn stdin(&mut self, fd: Option<OwnedFd>) {
+        self.stdio[0] = fd;
+    }
+
+    /// Set the stdout of the command, `None` lets it inherit the one of sudo.
+    pub(super) fn stdout(&mut self, fd: Option<OwnedFd>) {
+        self.stdio[1] = fd;
+    }
+
+    /// Set the stderr of the command, `None` lets it inherit the one of sudo.
+    pub(super) fn stderr(&mut self, fd: Option<OwnedFd>) {
+        self.stdio[2] = fd;
+    }
+
+    /// Move the command to `cgroup`, before switching to the target user.
+    pub(super) fn cgroup(&mut self, cgroup: &Cgroup) -> io::Result<()> {
+        self.cgroup = Some(c_string(cgroup.procs().as_os_str().as_bytes())?);
+
+        Ok(())
+    }
+
+    /// Start the command in `path`, after switching to the target user. If this fails, the user
+    /// is told so, and the command is only executed anyway if the directory is not `required`.
+    pub(super) fn chdir(&mut self, path: &Path, required: bool) -> io::Result<()> {
+        self.chdir = Some(Chdir {
+            path: c_string(path.as_os_str().as_bytes())?,
+            required,
+            error: ErrorMessage::new(
+                &format!("unable to change directory to {}", path.display()),
+                CHDIR_ERRORS,
+            ),
+        });
+
+        Ok(())
+    }
+
+    /// Restrict the command to `sandbox`, after everything else that needs privileges is done.
+    pub(super) fn sandbox(&mut self, sandbox: Sandbox) {
+        self.sandbox = Some(sandbox);
+    }
+
+    /// Keep the command from typing into terminals with `filter`, which is applied while the
+    /// process is still privileged, so that the command can still execute setuid programs.
+    pub(super) fn injection_filter(&mut self, filter: InjectionFilter) {
+        self.injection_filter = Some(filter);
+    }
+
+    /// Execute the program through `file`, which it was opened as when it was resolved, instead
+    /// of by its path, so that the file that is executed is the one that was checked even if its
+    /// path is made to point to another file in the meantime (`Defaults fdexec`).
+    ///
+    /// macOS has no `fexecve`, so there the program is still executed by its path.
+    pub(super) fn exec_through_fd(&mut self, file: &File) -> io::Result<()> {
+        if cfg!(target_os = "macos") {
+            return Ok(());
+        }
+
+        let file = file.try_clone()?;
+        if !file.metadata()?.is_file() {
+            // this is what `execve` fails with as well
+            return Err(io::Error::from_raw_os_error(libc::EACCES));
+        }
+
+        // the interpreter of a script opens it as `/dev/fd/N`, so it has to stay open for it
+        let mut magic = [0; 2];
+        if file.read_exact_at(&mut magic, 0).is_ok() && &magic == b"#!" {
+            // SAFETY: the file descriptor is valid while `file` is alive.
+            cerr(unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETFD, 0) })?;
+        }
+
+        self.program_fd = Some(file.into());
+
+        Ok(())
+    }
+
+    /// Keep the file descriptors that the command needs open when closing all others.
+    pub(super) fn keep_open(&self, file_closer: &mut FileCloser) {
+        for fd in self.stdio.iter().flatten() {
+            file_closer.except(fd);
+        }
+        if let Some(fd) = &self.program_fd {
+            file_closer.except(fd);
+        }
+        if let Some(ruleset) = self.sandbox.as_ref().and_then(Sandbox::ruleset) {
+            file_closer.except(ruleset);
+        }
+    }
+
+    /// Execute the command in the current process, which only returns if that failed.
+    ///
+    /// This only calls async-signal-safe functions, so it can be used after `fork`.
+    pub(super) fn exec(&self) -> ExecFailure {
+        if let Err(failure) = self.setup() {
+            return failure;
+        }
+
+        // SAFETY: all pointers point to null-terminated strings and null-terminated arrays of
+        // them, which are valid until `self` is dropped, and the file descriptor is valid while
+        // `self.program_fd` is alive.
+        match &self.program_fd {
+            #[cfg(not(target_os = "macos"))]
+            Some(fd) => unsafe {
+                libc::fexecve(fd.as_raw_fd(), self.args.as_ptr(), self.env.as_ptr())
+            },
+            _ => unsafe {
+                libc::execve(self.program.as_ptr(), self.args.as_ptr(), self.env.as_ptr())
+            },
+        };
+
+        ExecFailure::new(ExecPhase::Exec, &io::Error::last_os_error())
+    }
+
+    fn setup(&self) -> Result<(), ExecFailure> {
+        for (target, fd) in self.stdio.iter().enumerate() {
+            let Some(fd) = fd else {
+                continue;
+            };
+            if fd.as_raw_fd() == target as i32 {
+                // `dup2` would leave close-on-exec set
+                // SAFETY: the file descriptor is valid while `fd` is alive.
+                cerr(unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, 0) })
+                    .map_err(ExecFailure::at(ExecPhase::FileDescriptors))?;
+            } else {
+                // SAFETY: both file descriptors are valid, and the standard streams are not
+                // owned by anything else in the child.
+                cerr(unsafe { libc::dup2(fd.as_raw_fd(), target as i32) })
+                    .map_err(ExecFailure::at(ExecPhase::FileDescriptors))?;
+            }
+        }
+
+        // Rust programs ignore SIGPIPE, but the command should get the default action back.
+        SignalHandler::register(SIGPIPE, SignalHandlerBehavior::Default)
+            .map_err(ExecFailure::at(ExecPhase::FileDescriptors))?
+            .forget();
+
+        if let Some(procs) = &self.cgroup {
+            join_cgroup(procs).map_err(ExecFailure::at(ExecPhase::Cgroup))?;
+        }
+
+        if let Some(filter) = &self.injection_filter {
+            filter
+                .apply()
+                .map_err(ExecFailure::at(ExecPhase::Restrictions))?;
+        }
+
+        self.identity
+            .switch_groups()
+            .map_err(ExecFailure::at(ExecPhase::Groups))?;
+        self.identity
+            .switch_user()
+            .map_err(ExecFailure::at(ExecPhase::User))?;
+
+        if let Some(chdir) = &self.chdir {
+            // SAFETY: `chdir.path` is a null-terminated string.
+            if unsafe { libc::chdir(chdir.path.as_ptr()) } == -1 {
+                let err = io::Error::last_os_error();
+                chdir.error.show(&err);
+                // a login shell starts in the current directory if the home directory of the
+                // target user cannot be entered; an explicit `--chdir` must always succeed
+                if chdir.required {
+                    return Err(ExecFailure::new(ExecPhase::Chdir, &err));
+                }
+            }
+        }
+
+        if let Some(sandbox) = &self.sandbox {
+            sandbox
+                .apply()
+                .map_err(ExecFailure::at(ExecPhase::Restrictions))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Move the current process to a cgroup, given its `cgroup.procs` file.
+fn join_cgroup(procs: &CStr) -> io::Result<()> {
+    // SAFETY: `procs` is a null-terminated string.
+    let fd = cerr(unsafe { libc::open(procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) })?;
+    // SAFETY: the file descriptor was just opened, and is not owned by anything else.
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    // writing 0 moves the process that writes it
+    // SAFETY: the buffer is valid for the length that is passed.
+    cerr(unsafe { libc::write(fd.as_raw_fd(), b"0".as_ptr().cast(), 1) })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, fs::File, path::Path};
+
+    use super::{CStringArray, PreparedCommand};
+    use crate::system::nss::{use_database, Database};
+    use crate::system::{identity::Identity, Group, User};
+
+    #[test]
+    fn marshals_arguments_and_environment() {
+        use_database(Database::default().with_user("root", 0));
+        let user = User::from_uid(0).unwrap().unwrap();
+        let group = Group::from_gid(0).unwrap().unwrap();
+        let identity = Identity::new(&user, &group, None);
+        let env = [("PATH".into(), "/bin".into())].into_iter().collect();
+
+        let command = PreparedCommand::new(
+            Path::new("/bin/sh"),
+            Some(OsStr::new("-sh")),
+            &["-c".to_string(), "echo hi".to_string()],
+            env,
+            identity.clone(),
+        )
+        .unwrap();
+        assert_eq!(command.program.to_bytes(), b"/bin/sh");
+        let strings = |array: &CStringArray| {
+            array
+                .strings
+                .iter()
+                .map(|s| s.to_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(strings(&command.args), ["-sh", "-c", "echo hi"]);
+        assert_eq!(strings(&command.env), ["PATH=/bin"]);
+        assert!(command.args.pointers.last().unwrap().is_null());
+
+        let nul = PreparedCommand::new(
+            Path::new("/bin/sh"),
+            None,
+            &["a\0b".to_string()],
+            Default::default(),
+            identity,
+        );
+        assert!(nul.is_err());
+    }
+
+    #[test]
+    fn keeps_the_opened_program() {
+        use_database(Database::default().with_user("root", 0));
+        let user = User::from_uid(0).unwrap().unwrap();
+        let group = Group::from_gid(0).unwrap().unwrap();
+        let identity = Identity::new(&user, &group, None);
+        let command = |program: &str| {
+            PreparedCommand::new(
+                Path::new(program),
+                None,
+                &[],
+                Default::default(),
+                identity.clone(),
+            )
+            .unwrap()
+        };
+
+        let mut program = command("/bin/sh");
+        program
+            .exec_through_fd(&File::open("/bin/sh").unwrap())
+            .unwrap();
+        assert_eq!(program.program_fd.is_some(), cfg!(not(target_os = "macos")));
+
+        if cfg!(not(target_os = "macos")) {
+            let dir = File::open("/").unwrap();
+            assert!(command("/").exec_through_fd(&dir).is_err());
+        }
+    }
+}
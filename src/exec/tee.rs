@@ -0,0 +1,131 @@
+//! Passing a copy of everything the command writes to its terminal to other files, so that a
+//! session can be followed live by a review tool, without an I/O log.
+//!
+//! A sink is either a file or FIFO set with `Defaults tee_output`, or a file descriptor that the
+//! invoking user passes in `SUDO_TEE_FD`. Output that the FIFO of `tee_output` cannot take right
+//! away is left out, so that a reader that does not keep up does not slow the command down. A sink
+//! that fails is not written to anymore.
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    os::{
+        fd::{BorrowedFd, OwnedFd, RawFd},
+        unix::fs::OpenOptionsExt,
+    },
+    path::Path,
+};
+
+use crate::{cutils::cerr, log::user_warn};
+
+/// The environment variable with the file descriptor of a sink of the invoking user.
+pub(crate) const TEE_FD_VAR: &str = "SUDO_TEE_FD";
+
+/// Take a copy of the file descriptor in `SUDO_TEE_FD`, if it is set, which has to be open for
+/// writing.
+///
+/// This has to be called before sudo opens any file itself, as the file descriptor could refer to
+/// one of those files otherwise.
+pub(crate) fn take_inherited_fd() -> io::Result<Option<OwnedFd>> {
+    let Some(value) = std::env::var_os(TEE_FD_VAR) else {
+        return Ok(None);
+    };
+    let fd = value
+        .to_str()
+        .and_then(|value| value.parse::<RawFd>().ok())
+        // the standard streams already show the output
+        .filter(|fd| *fd > libc::STDERR_FILENO)
+        .ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "not a file descriptor above 2")
+        })?;
+
+    let flags = cerr(unsafe { libc::fcntl(fd, libc::F_GETFL) })?;
+    if flags & libc::O_ACCMODE == libc::O_RDONLY {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "not open for writing",
+        ));
+    }
+
+    // SAFETY: `fcntl` just showed that `fd` is open, and it is only borrowed to duplicate it
+    let fd = unsafe { BorrowedFd::borrow_raw(fd) }.try_clone_to_owned()?;
+
+    Ok(Some(fd))
+}
+
+pub(super) struct Tee {
+    file: File,
+    name: String,
+    failed: bool,
+}
+
+impl Tee {
+    /// Open the file or FIFO at `path` for appending, without waiting for a FIFO to be read.
+    pub(super) fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .append(true)
+            .custom_flags(libc::O_NONBLOCK | libc::O_NOCTTY)
+            .open(path)?;
+
+        Ok(Self {
+            file,
+            name: path.display().to_string(),
+            failed: false,
+        })
+    }
+
+    /// Use a file descriptor that was taken with [take_inherited_fd].
+    pub(super) fn inherit(fd: BorrowedFd) -> io::Result<Self> {
+        Ok(Self {
+            file: fd.try_clone_to_owned()?.into(),
+            name: TEE_FD_VAR.to_string(),
+            failed: false,
+        })
+    }
+
+    pub(super) fn write(&mut self, data: &[u8]) {
+        if self.failed {
+            return;
+        }
+
+        match self.file.write_all(data) {
+            Ok(()) => {}
+            // the reader does not keep up, which must not slow the command down
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+            Err(err) => {
+                user_warn!("not copying output to {} anymore: {err}", self.name);
+                self.failed = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::CString, fs, os::unix::ffi::OsStrExt};
+
+    use super::Tee;
+
+    #[test]
+    fn appends_to_file() {
+        let dir = std::env::temp_dir().join(format!("sudo-rs-tee-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("output");
+        fs::write(&path, b"earlier\n").unwrap();
+        let mut tee = Tee::open(&path).unwrap();
+        tee.write(b"hello ");
+        tee.write(b"world\n");
+        assert_eq!(fs::read(&path).unwrap(), b"earlier\nhello world\n");
+
+        // a FIFO that nobody reads from is not waited for
+        let fifo = dir.join("fifo");
+        let fifo_path = CString::new(fifo.as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(fifo_path.as_ptr(), 0o600) }, 0);
+        assert_eq!(
+            Tee::open(&fifo).err().and_then(|err| err.raw_os_error()),
+            Some(libc::ENXIO)
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
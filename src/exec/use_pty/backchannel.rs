@@ -3,12 +3,13 @@ use std::{
     io,
     mem::size_of,
     os::fd::{AsRawFd, RawFd},
+    time::Duration,
 };
 
 use crate::{
     common::bin_serde::{BinPipe, DeSerialize},
-    exec::signal_fmt,
-    system::interface::ProcessId,
+    exec::{signal_fmt, ExecFailure},
+    system::{interface::ProcessId, wait::ResourceUsage},
 };
 
 use super::CommandStatus;
@@ -19,7 +20,10 @@ type MonitorData = c_int;
 
 const PREFIX_LEN: usize = size_of::<Prefix>();
 const PARENT_DATA_LEN: usize = size_of::<ParentData>();
+/// The user time and system time in microseconds and the maximum resident set size.
+const RESOURCE_USAGE_LEN: usize = 3 * size_of::<u64>();
 const MONITOR_DATA_LEN: usize = size_of::<MonitorData>();
+const EXEC_FAILURE_LEN: usize = size_of::<<ExecFailure as DeSerialize>::Bytes>();
 
 pub(super) struct BackchannelPair {
     pub(super) parent: ParentBackchannel,
@@ -53,19 +57,26 @@ impl BackchannelPair {
 
 pub(super) enum ParentMessage {
     IoError(c_int),
+    /// Executing the command failed, in the monitor or in the process of the command.
+    ExecFailure(ExecFailure),
     CommandStatus(CommandStatus),
     CommandPid(ProcessId),
     ShortRead,
+    /// The resources that the command used, which is sent right before its exit status.
+    ResourceUsage(ResourceUsage),
 }
 
 impl ParentMessage {
-    const LEN: usize = PREFIX_LEN + PARENT_DATA_LEN;
+    // every message has the length of the longest one
+    const LEN: usize = PREFIX_LEN + RESOURCE_USAGE_LEN;
     const IO_ERROR: Prefix = 0;
     const CMD_STAT_EXIT: Prefix = 1;
     const CMD_STAT_TERM: Prefix = 2;
     const CMD_STAT_STOP: Prefix = 3;
     const CMD_PID: Prefix = 4;
     const SHORT_READ: Prefix = 5;
+    const RESOURCE_USAGE: Prefix = 6;
+    const EXEC_FAILURE: Prefix = 7;
 
     fn from_parts(prefix: Prefix, data: ParentData) -> Self {
         match prefix {
@@ -87,6 +98,8 @@ impl ParentMessage {
             ParentMessage::CommandStatus(CommandStatus::Stop(_)) => Self::CMD_STAT_STOP,
             ParentMessage::CommandPid(_) => Self::CMD_PID,
             ParentMessage::ShortRead => Self::SHORT_READ,
+            ParentMessage::ResourceUsage(_) => Self::RESOURCE_USAGE,
+            ParentMessage::ExecFailure(_) => Self::EXEC_FAILURE,
         };
 
         let data = match self {
@@ -96,7 +109,9 @@ impl ParentMessage {
                 | CommandStatus::Term(data)
                 | CommandStatus::Stop(data) => *data,
             },
-            ParentMessage::ShortRead => 0,
+            ParentMessage::ShortRead
+            | ParentMessage::ResourceUsage(_)
+            | ParentMessage::ExecFailure(_) => 0,
         };
 
         (prefix, data)
@@ -107,6 +122,10 @@ impl TryFrom<io::Error> for ParentMessage {
     type Error = io::Error;
 
     fn try_from(err: io::Error) -> Result<Self, Self::Error> {
+        if let Some(failure) = ExecFailure::of(&err) {
+            return Ok(Self::ExecFailure(failure));
+        }
+
         err.raw_os_error()
             .map(Self::IoError)
             .or_else(|| (err.kind() == io::ErrorKind::UnexpectedEof).then_some(Self::ShortRead))
@@ -130,7 +149,22 @@ impl DeSerialize for ParentMessage {
         let (prefix, data) = self.to_parts();
 
         prefix_buf.copy_from_slice(&prefix.to_ne_bytes());
-        data_buf.copy_from_slice(&data.to_ne_bytes());
+        match self {
+            ParentMessage::ResourceUsage(usage) => {
+                let fields = [
+                    usage.user_time.as_micros() as u64,
+                    usage.system_time.as_micros() as u64,
+                    usage.max_rss,
+                ];
+                for (field_buf, field) in data_buf.chunks_exact_mut(size_of::<u64>()).zip(fields) {
+                    field_buf.copy_from_slice(&field.to_ne_bytes());
+                }
+            }
+            ParentMessage::ExecFailure(failure) => {
+                data_buf[..EXEC_FAILURE_LEN].copy_from_slice(&failure.serialize());
+            }
+            _ => data_buf[..PARENT_DATA_LEN].copy_from_slice(&data.to_ne_bytes()),
+        }
         buf
     }
 
@@ -138,7 +172,24 @@ impl DeSerialize for ParentMessage {
         let (prefix_buf, data_buf) = buf.split_at(PREFIX_LEN);
 
         let prefix = Prefix::from_ne_bytes(prefix_buf.try_into().unwrap());
-        let data = MonitorData::from_ne_bytes(data_buf.try_into().unwrap());
+        if prefix == Self::RESOURCE_USAGE {
+            let mut fields = data_buf
+                .chunks_exact(size_of::<u64>())
+                .map(|field_buf| u64::from_ne_bytes(field_buf.try_into().unwrap()));
+            let mut field = || fields.next().unwrap();
+
+            return ParentMessage::ResourceUsage(ResourceUsage {
+                user_time: Duration::from_micros(field()),
+                system_time: Duration::from_micros(field()),
+                max_rss: field(),
+            });
+        }
+        if prefix == Self::EXEC_FAILURE {
+            return ParentMessage::ExecFailure(ExecFailure::deserialize(
+                data_buf[..EXEC_FAILURE_LEN].try_into().unwrap(),
+            ));
+        }
+        let data = ParentData::from_ne_bytes(data_buf[..PARENT_DATA_LEN].try_into().unwrap());
 
         ParentMessage::from_parts(prefix, data)
     }
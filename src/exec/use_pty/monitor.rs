@@ -1,6 +1,6 @@
-use std::{ffi::c_int, io, os::unix::process::CommandExt, process::Command};
+use std::{ffi::c_int, io};
 
-use crate::exec::{opt_fmt, signal_fmt};
+use crate::exec::{opt_fmt, signal_fmt, ExecFailure, ExecPhase, HeldSignals};
 use crate::system::signal::{
     consts::*, register_handlers, SignalHandler, SignalHandlerBehavior, SignalNumber, SignalSet,
     SignalStream,
@@ -8,7 +8,7 @@ use crate::system::signal::{
 use crate::{
     common::bin_serde::BinPipe,
     exec::{
-        event::{EventRegistry, Process},
+        event::{EventHandle, EventRegistry, Process},
         io_util::{retry_while_interrupted, was_interrupted},
         use_pty::backchannel::{MonitorBackchannel, MonitorMessage, ParentMessage},
     },
@@ -25,28 +25,30 @@ use crate::{
 use crate::{
     exec::{handle_sigchld, terminate_process, HandleSigchld},
     system::{
-        fork, getpgid, getpgrp,
+        _exit, fork, getpgid, getpgrp,
         interface::ProcessId,
-        kill, setpgid, setsid,
+        kill, killpg, setpgid, setsid,
         term::{PtyFollower, Terminal},
-        wait::{Wait, WaitError, WaitOptions},
-        ForkResult,
+        wait::{ResourceUsage, Wait, WaitError, WaitOptions},
+        ChildProcess, ForkResult,
     },
 };
 
 use super::CommandStatus;
+use crate::exec::prepared::PreparedCommand;
 
 // FIXME: This should return `io::Result<!>` but `!` is not stable yet.
 pub(super) fn exec_monitor(
     pty_follower: PtyFollower,
-    command: Command,
+    command: PreparedCommand,
     foreground: bool,
     backchannel: &mut MonitorBackchannel,
     mut file_closer: FileCloser,
     original_set: Option<SignalSet>,
 ) -> io::Result<ProcessOutput> {
-    // SIGTTIN and SIGTTOU are ignored here but the docs state that it shouldn't
-    // be possible to receive them in the first place. Investigate
+    // The monitor is not in the foreground process group of the pty when the command is, so it
+    // would be stopped by SIGTTOU when it changes the foreground process group back. The command
+    // gets the default actions back before it is executed.
     match SignalHandler::register(SIGTTIN, SignalHandlerBehavior::Ignore) {
         Ok(handler) => handler.forget(),
         Err(err) => dev_warn!("cannot set handler for SIGTTIN: {err}"),
@@ -59,16 +61,16 @@ pub(super) fn exec_monitor(
     // Start a new terminal session with the monitor as the leader.
     setsid().map_err(|err| {
         dev_warn!("cannot start a new session: {err}");
-        err
+        ExecFailure::new(ExecPhase::Session, &err)
     })?;
 
     // Set the follower side of the pty as the controlling terminal for the session.
     pty_follower.make_controlling_terminal().map_err(|err| {
         dev_warn!("cannot set the controlling terminal: {err}");
-        err
+        ExecFailure::new(ExecPhase::ControllingTerminal, &err)
     })?;
 
-    // Use a pipe to get the IO error if `exec_command` fails.
+    // Use a pipe to get the failure if `exec_command` fails.
     let (mut errpipe_tx, errpipe_rx) = BinPipe::pair()?;
 
     // Don't close the error pipe as we need it to retrieve the error code if the command execution
@@ -87,24 +89,27 @@ pub(super) fn exec_monitor(
 
     // FIXME (ogsudo): Some extra config happens here if selinux is available.
 
-    let ForkResult::Parent(command_pid) = fork().map_err(|err| {
+    // SAFETY: the child only calls async-signal-safe functions until it executes the command.
+    let ForkResult::Parent(command_pid) = unsafe { fork() }.map_err(|err| {
         dev_warn!("unable to fork command process: {err}");
         err
     })?
     else {
         drop(errpipe_rx);
 
-        let err = exec_command(command, foreground, pty_follower, file_closer, original_set);
-        dev_warn!("failed to execute command: {err}");
-        // If `exec_command` returns, it means that executing the command failed. Send the error to
-        // the monitor using the pipe.
-        if let Some(error_code) = err.raw_os_error() {
-            errpipe_tx.write(&error_code).ok();
-        }
+        let failure = exec_command(command, foreground, pty_follower, file_closer, original_set);
+        // If `exec_command` returns, it means that executing the command failed. Send the failure
+        // to the monitor using the pipe.
+        errpipe_tx.write(&failure).ok();
 
-        return Ok(ProcessOutput::ChildExit);
+        // Exit right away instead of returning, which would free memory.
+        _exit(1);
     };
 
+    // The child's end of the pipe is closed when the command is executed, so we can tell when
+    // that happened.
+    drop(errpipe_tx);
+
     // Send the command's PID to the parent.
     if let Err(err) = backchannel.send(&ParentMessage::CommandPid(command_pid)) {
         dev_warn!("cannot send command PID to parent: {err}");
@@ -145,7 +150,8 @@ pub(super) fn exec_monitor(
 
     // Terminate the command if it's not terminated.
     if let Some(command_pid) = closure.command_pid {
-        terminate_process(command_pid, true);
+        // the command has not been waited for yet, so its ID cannot have been reused
+        terminate_process(ChildProcess::new(command_pid), true);
 
         loop {
             match command_pid.wait(WaitOptions::new()) {
@@ -178,6 +184,15 @@ pub(super) fn exec_monitor(
             }
         },
         StopReason::Exit(command_status) => {
+            // The parent stops reading once it has the exit status, so this has to come first.
+            if let Some(usage) = closure.resource_usage {
+                if let Err(err) = closure
+                    .backchannel
+                    .send(&ParentMessage::ResourceUsage(usage))
+                {
+                    dev_warn!("cannot send message over backchannel: {err}")
+                }
+            }
             if let Err(err) = closure.backchannel.send(&command_status.into()) {
                 dev_warn!("cannot send message over backchannel: {err}")
             }
@@ -189,14 +204,14 @@ pub(super) fn exec_monitor(
     Ok(ProcessOutput::ChildExit)
 }
 
-// FIXME: This should return `io::Result<!>` but `!` is not stable yet.
+// FIXME: This should return `Result<!, ExecFailure>` but `!` is not stable yet.
 fn exec_command(
-    mut command: Command,
+    command: PreparedCommand,
     foreground: bool,
     pty_follower: PtyFollower,
     file_closer: FileCloser,
     original_set: Option<SignalSet>,
-) -> io::Error {
+) -> ExecFailure {
     // FIXME (ogsudo): Do any additional configuration that needs to be run after `fork` but before `exec`
     let command_pid = std::process::id() as ProcessId;
 
@@ -214,14 +229,21 @@ fn exec_command(
     drop(pty_follower);
 
     if let Err(err) = file_closer.close_the_universe() {
-        return err;
+        return ExecFailure::new(ExecPhase::FileDescriptors, &err);
+    }
+
+    // Ignored signals stay ignored after `exec`, so restore the default actions that were
+    // overridden for the monitor; the command must be stopped when it accesses the pty from the
+    // background, so sudo can be suspended in turn.
+    for signal in [SIGTTIN, SIGTTOU] {
+        if let Ok(handler) = SignalHandler::register(signal, SignalHandlerBehavior::Default) {
+            handler.forget();
+        }
     }
 
     // Restore the signal mask now that the handlers have been setup.
     if let Some(set) = original_set {
-        if let Err(err) = set.set_mask() {
-            dev_warn!("cannot restore signal mask: {err}");
-        }
+        set.set_mask().ok();
     }
 
     command.exec()
@@ -235,7 +257,11 @@ struct MonitorClosure<'a> {
     command_pgrp: ProcessId,
     monitor_pgrp: ProcessId,
     pty_follower: PtyFollower,
-    errpipe_rx: BinPipe<i32>,
+    errpipe_rx: BinPipe<ExecFailure>,
+    errpipe_handle: EventHandle,
+    held_signals: HeldSignals,
+    /// The resources that the command used, once it terminated.
+    resource_usage: Option<ResourceUsage>,
     backchannel: &'a mut MonitorBackchannel,
     signal_stream: &'static SignalStream,
     _signal_handlers: [SignalHandler; MonitorClosure::SIGNALS.len()],
@@ -249,7 +275,7 @@ impl<'a> MonitorClosure<'a> {
     fn new(
         command_pid: ProcessId,
         pty_follower: PtyFollower,
-        errpipe_rx: BinPipe<i32>,
+        errpipe_rx: BinPipe<ExecFailure>,
         backchannel: &'a mut MonitorBackchannel,
         registry: &mut EventRegistry<Self>,
     ) -> io::Result<Self> {
@@ -257,7 +283,7 @@ impl<'a> MonitorClosure<'a> {
         let monitor_pgrp = getpgrp();
 
         // Register the callback to receive the IO error if the command fails to execute.
-        registry.register_event(&errpipe_rx, PollEvent::Readable, |_| {
+        let errpipe_handle = registry.register_event(&errpipe_rx, PollEvent::Readable, |_| {
             MonitorEvent::ReadableErrPipe
         });
 
@@ -287,6 +313,9 @@ impl<'a> MonitorClosure<'a> {
             monitor_pgrp,
             pty_follower,
             errpipe_rx,
+            errpipe_handle,
+            held_signals: HeldSignals::new(),
+            resource_usage: None,
             backchannel,
             signal_stream,
             _signal_handlers: signal_handlers,
@@ -322,18 +351,32 @@ impl<'a> MonitorClosure<'a> {
     fn read_errpipe(&mut self, registry: &mut EventRegistry<Self>) {
         match self.errpipe_rx.read() {
             Err(err) if was_interrupted(&err) => { /* Retry later */ }
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => self.on_exec(registry),
             Err(err) => registry.set_break(err),
-            Ok(error_code) => {
-                // Received error code from the command, forward it to the parent.
+            Ok(failure) => {
+                // Received the failure from the command, forward it to the parent.
+                dev_warn!("failed to execute command: {failure}");
                 self.backchannel
-                    .send(&ParentMessage::IoError(error_code))
+                    .send(&ParentMessage::ExecFailure(failure))
                     .ok();
             }
         }
     }
 
+    /// The command was executed, so it can receive the signals that were held back.
+    fn on_exec(&mut self, registry: &mut EventRegistry<Self>) {
+        // The pipe stays readable once it is closed.
+        self.errpipe_handle.ignore(registry);
+
+        for signal in self.held_signals.release() {
+            if let Some(command_pid) = self.command_pid {
+                self.send_signal(signal, command_pid, false);
+            }
+        }
+    }
+
     /// Send a signal to the command.
-    fn send_signal(&self, signal: c_int, command_pid: ProcessId, from_parent: bool) {
+    fn send_signal(&mut self, signal: c_int, command_pid: ProcessId, from_parent: bool) {
         dev_info!(
             "sending {}{} to command",
             signal_fmt(signal),
@@ -342,7 +385,7 @@ impl<'a> MonitorClosure<'a> {
         // FIXME: We should call `killpg` instead of `kill`.
         match signal {
             SIGALRM => {
-                terminate_process(command_pid, false);
+                terminate_process(ChildProcess::new(command_pid), false);
             }
             SIGCONT_FG => {
                 // Continue with the command as the foreground process group
@@ -352,7 +395,8 @@ impl<'a> MonitorClosure<'a> {
                         self.command_pgrp
                     );
                 }
-                kill(command_pid, SIGCONT).ok();
+                // A job control signal stops the whole process group of the command.
+                killpg(self.command_pgrp, SIGCONT).ok();
             }
             SIGCONT_BG => {
                 // Continue with the monitor as the foreground process group
@@ -362,7 +406,13 @@ impl<'a> MonitorClosure<'a> {
                         self.monitor_pgrp
                     );
                 }
-                kill(command_pid, SIGCONT).ok();
+                killpg(self.command_pgrp, SIGCONT).ok();
+            }
+            signal if self.held_signals.hold(signal) => {
+                dev_info!(
+                    "holding back {} until the command is executed",
+                    signal_fmt(signal)
+                );
             }
             signal => {
                 // Send the signal to the command.
@@ -473,4 +523,8 @@ impl<'a> HandleSigchld for MonitorClosure<'a> {
             .send(&CommandStatus::Stop(signal).into())
             .ok();
     }
+
+    fn on_resource_usage(&mut self, usage: ResourceUsage) {
+        self.resource_usage = Some(usage);
+    }
 }
@@ -1,40 +1,67 @@
+use std::cell::RefCell;
 use std::collections::VecDeque;
 use std::ffi::c_int;
+use std::fs::File;
 use std::io;
-use std::process::{Command, Stdio};
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 use crate::exec::event::{EventHandle, EventRegistry, PollEvent, Process, StopReason};
 use crate::exec::use_pty::monitor::exec_monitor;
 use crate::exec::use_pty::SIGCONT_FG;
 use crate::exec::{
-    cond_fmt, handle_sigchld, opt_fmt, signal_fmt, terminate_process, ExecOutput, HandleSigchld,
-    ProcessOutput,
+    cond_fmt, handle_sigchld, is_forwarded, is_termination_signal, opt_fmt, signal_fmt,
+    terminate_process, ExecOutput, HandleSigchld, OutputQuota, ProcessOutput, Reauthenticate,
 };
 use crate::exec::{
-    io_util::retry_while_interrupted,
+    io_util::{retry_transient, retry_while_interrupted},
     use_pty::backchannel::{BackchannelPair, MonitorMessage, ParentBackchannel, ParentMessage},
     ExitReason,
 };
-use crate::log::{dev_error, dev_info, dev_warn};
+use crate::log::{auth_info, auth_warn, dev_error, dev_info, dev_warn, user_warn};
 use crate::system::signal::{
     consts::*, register_handlers, SignalHandler, SignalHandlerBehavior, SignalNumber, SignalSet,
     SignalStream,
 };
-use crate::system::term::{Pty, PtyFollower, PtyLeader, TermSize, Terminal, UserTerm};
-use crate::system::wait::WaitOptions;
-use crate::system::{chown, fork, getpgrp, kill, killpg, FileCloser, ForkResult, Group, User};
+use crate::system::term::{
+    set_pty_owner, Pty, PtyLeader, TermSettings, TermSize, Terminal, UserTerm,
+};
+use crate::system::timer::IntervalTimer;
+use crate::system::wait::{ResourceUsage, WaitOptions};
+use crate::system::{
+    fork, getpgrp, kill, killpg, pipe, ChildProcess, FileCloser, ForkResult, Group, User,
+};
 use crate::system::{getpgid, interface::ProcessId};
 
-use super::pipe::Pipe;
+use super::pipe::{Pipe, Relay};
 use super::{CommandStatus, SIGCONT_BG};
+use crate::exec::control::ControlSocket;
+use crate::exec::prepared::PreparedCommand;
+use crate::exec::tee::Tee;
+use crate::iolog::IoLog;
+use crate::plugin::IoPlugin;
 
+#[allow(clippy::too_many_arguments)]
 pub(in crate::exec) fn exec_pty(
     sudo_pid: ProcessId,
-    mut command: Command,
+    mut command: PreparedCommand,
     user_tty: UserTerm,
+    tty_signals: bool,
+    tty_flow_control: bool,
+    forward_signals: Option<&[SignalNumber]>,
+    pty_wait_for_eof: Duration,
+    pty_group: Option<&str>,
+    io_plugins: &[IoPlugin],
+    iolog: Option<IoLog>,
+    mut tees: Vec<Tee>,
+    control: Option<ControlSocket>,
+    reauth: Option<(Duration, Reauthenticate)>,
+    idle_timeout: Option<Duration>,
+    output_quota: Option<OutputQuota>,
 ) -> io::Result<ProcessOutput> {
     // Allocate a pseudoterminal.
-    let pty = get_pty()?;
+    let pty = get_pty(pty_group)?;
 
     // Create backchannels to communicate with the monitor.
     let mut backchannels = BackchannelPair::new().map_err(|err| {
@@ -42,7 +69,11 @@ pub(in crate::exec) fn exec_pty(
         err
     })?;
 
-    // We don't want to receive SIGTTIN/SIGTTOU
+    // Ignore SIGTTIN and SIGTTOU so sudo is not stopped when it accesses the user's terminal while
+    // it is in the background: reading fails with `EIO` instead, which is when we stop relaying
+    // input until we are in the foreground again. These signals cannot be caught instead, as the
+    // kernel would restart the read and send the signal again, forever. The command gets the
+    // default actions back before it is executed, so it is still stopped by them.
     match SignalHandler::register(SIGTTIN, SignalHandlerBehavior::Ignore) {
         Ok(handler) => handler.forget(),
         Err(err) => dev_warn!("cannot set handler for SIGTTIN: {err}"),
@@ -62,15 +93,13 @@ pub(in crate::exec) fn exec_pty(
     let mut file_closer = FileCloser::new();
 
     // Set all the IO streams for the command to the follower side of the pty.
-    let mut clone_follower = || -> io::Result<PtyFollower> {
+    let clone_follower = || -> io::Result<Option<OwnedFd>> {
         let follower = pty.follower.try_clone().map_err(|err| {
             dev_error!("cannot clone pty follower: {err}");
             err
         })?;
-        // Don't close these as we will need them so they are dupped inside `Command::exec`.
-        file_closer.except(&follower);
 
-        Ok(follower)
+        Ok(Some(follower.into()))
     };
 
     command.stdin(clone_follower()?);
@@ -88,6 +117,61 @@ pub(in crate::exec) fn exec_pty(
         ParentEvent::Pty,
     );
 
+    // Show the I/O plugins, the I/O log and the tees everything the command writes to its
+    // terminal, up to the output quota
+    let iolog = iolog.map(|iolog| Rc::new(RefCell::new(iolog)));
+    if !io_plugins.is_empty() || iolog.is_some() || !tees.is_empty() {
+        let io_plugins = io_plugins.to_vec();
+        let iolog = iolog.clone();
+        let mut logged = 0;
+        tty_pipe.log_right(move |output| {
+            let mut log = |output: &[u8]| {
+                for plugin in &io_plugins {
+                    match plugin.log_ttyout(output) {
+                        Ok(true) => {}
+                        Ok(false) => dev_warn!("{}: output rejected by I/O plugin", plugin.name()),
+                        Err(err) => dev_warn!("{err}"),
+                    }
+                }
+                if let Some(iolog) = &iolog {
+                    iolog.borrow_mut().log_ttyout(output);
+                }
+                for tee in &mut tees {
+                    tee.write(output);
+                }
+            };
+
+            let Some(quota) = output_quota else {
+                return log(output);
+            };
+            let (output, exceeded) = within_quota(output, &mut logged, quota.bytes);
+            if !output.is_empty() {
+                log(output);
+            }
+            if exceeded {
+                let consequence = if quota.terminate {
+                    "the session is terminated"
+                } else {
+                    auth_warn!(
+                        "the command wrote more than {} bytes of output, no longer logging it",
+                        quota.bytes
+                    );
+                    "the rest of the output is not logged"
+                };
+                log(format!(
+                    "\r\n[sudo: the output quota of {} bytes was exceeded, {consequence}]\r\n",
+                    quota.bytes
+                )
+                .as_bytes());
+            }
+        });
+    }
+
+    // and everything that is typed into it
+    if let Some(iolog) = iolog.clone() {
+        tty_pipe.log_left(move |input| iolog.borrow_mut().log_ttyin(input));
+    }
+
     let user_tty = tty_pipe.left_mut();
 
     // Check if we are the foreground process
@@ -115,7 +199,7 @@ pub(in crate::exec) fn exec_pty(
     if !io::stdin().is_terminal() {
         dev_info!("stdin is not a terminal, command will inherit it");
         pipeline = true;
-        command.stdin(Stdio::inherit());
+        command.stdin(None);
 
         if foreground && parent_pgrp != sudo_pid {
             // If sudo is not the process group leader and stdin is not a terminal we might be
@@ -128,22 +212,68 @@ pub(in crate::exec) fn exec_pty(
     if !io::stdout().is_terminal() {
         dev_info!("stdout is not a terminal, command will inherit it");
         pipeline = true;
-        command.stdout(Stdio::inherit());
+        command.stdout(None);
     }
 
+    // What the command writes to a stderr that is not a terminal would not be logged if it
+    // inherited it, so it goes through a pipe instead when the I/O log or plugins want to see it.
+    let mut stderr_relay = None;
     if !io::stderr().is_terminal() {
-        dev_info!("stderr is not a terminal, command will inherit it");
-        command.stderr(Stdio::inherit());
+        let logged = !io_plugins.is_empty()
+            || iolog
+                .as_ref()
+                .is_some_and(|iolog| iolog.borrow().logs_stderr());
+        match logged.then(|| relay_stderr(&mut registry)).transpose() {
+            Ok(Some((stderr, mut relay))) => {
+                dev_info!("stderr is not a terminal, relaying it to be logged");
+                let io_plugins = io_plugins.to_vec();
+                let iolog = iolog.clone();
+                relay.log(move |output| {
+                    for plugin in &io_plugins {
+                        match plugin.log_stderr(output) {
+                            Ok(true) => {}
+                            Ok(false) => {
+                                dev_warn!("{}: output rejected by I/O plugin", plugin.name())
+                            }
+                            Err(err) => dev_warn!("{err}"),
+                        }
+                    }
+                    if let Some(iolog) = &iolog {
+                        iolog.borrow_mut().log_stderr(output);
+                    }
+                });
+                command.stderr(Some(stderr));
+                stderr_relay = Some(relay);
+            }
+            result => {
+                if let Err(err) = result {
+                    dev_warn!("cannot create pipe for stderr: {err}");
+                }
+                dev_info!("stderr is not a terminal, command will inherit it");
+                command.stderr(None);
+            }
+        }
     }
 
+    // Don't close the streams of the command, they are dupped when it is executed.
+    command.keep_open(&mut file_closer);
+
     // Copy terminal settings from `/dev/tty` to the pty.
-    if let Err(err) = user_tty.copy_to(&pty.follower) {
-        dev_error!("cannot copy terminal settings to pty: {err}");
-        foreground = false;
-    }
+    let tty_settings = match copy_settings(user_tty, &pty.follower) {
+        Ok(settings) => Some(settings),
+        Err(err) => {
+            dev_error!("cannot copy terminal settings to pty: {err}");
+            foreground = false;
+            None
+        }
+    };
 
     // Start in raw mode unless we're part of a pipeline or backgrounded.
-    if foreground && !pipeline && !exec_bg && user_tty.set_raw_mode(false).is_ok() {
+    if foreground
+        && !pipeline
+        && !exec_bg
+        && user_tty.set_raw_mode(tty_signals, tty_flow_control).is_ok()
+    {
         term_raw = true;
     }
 
@@ -162,14 +292,19 @@ pub(in crate::exec) fn exec_pty(
         }
     };
 
-    let ForkResult::Parent(monitor_pid) = fork().map_err(|err| {
+    // SAFETY: sudo does not run any other threads, so the monitor is free to do anything after
+    // `fork`, like running its own event loop.
+    let ForkResult::Parent(monitor_pid) = unsafe { fork() }.map_err(|err| {
         dev_error!("cannot fork monitor process: {err}");
         err
     })?
     else {
         // Close the file descriptors that we don't access
         drop(tty_pipe);
+        drop(stderr_relay);
         drop(backchannels.parent);
+        // the socket belongs to the parent, which removes it when the session ends
+        std::mem::forget(control);
 
         // If `exec_monitor` returns, it means we failed to execute the command somehow.
         match exec_monitor(
@@ -204,6 +339,8 @@ pub(in crate::exec) fn exec_pty(
     // Close the file descriptors that we don't access
     drop(pty.follower);
     drop(backchannels.monitor);
+    // including the write end of the pipe for stderr, so it reaches the end of file
+    drop(command);
 
     // Send green light to the monitor after closing the follower.
     retry_while_interrupted(|| backchannels.parent.send(&MonitorMessage::ExecCommand)).map_err(
@@ -219,9 +356,20 @@ pub(in crate::exec) fn exec_pty(
         parent_pgrp,
         backchannels.parent,
         tty_pipe,
+        stderr_relay,
         tty_size,
+        tty_settings,
         foreground,
         term_raw,
+        tty_signals,
+        tty_flow_control,
+        forward_signals.map(<[_]>::to_vec),
+        control,
+        reauth,
+        idle_timeout,
+        output_quota
+            .filter(|quota| quota.terminate)
+            .map(|quota| quota.bytes),
         &mut registry,
     )?;
 
@@ -238,6 +386,32 @@ pub(in crate::exec) fn exec_pty(
     // Flush the terminal
     closure.tty_pipe.flush_left().ok();
 
+    // Processes that the command started in the background may still write to the pty.
+    if exit_reason.is_ok() && !pty_wait_for_eof.is_zero() {
+        match closure.tty_pipe.drain_right(pty_wait_for_eof) {
+            Ok(true) => dev_info!("all processes closed the pty"),
+            Ok(false) => dev_info!("timed out waiting for the pty to be closed"),
+            Err(err) => dev_warn!("cannot relay the output of the pty: {err}"),
+        }
+    }
+
+    if let Some(relay) = &mut closure.stderr_relay {
+        if let Err(err) = relay.drain(pty_wait_for_eof) {
+            dev_warn!("cannot relay stderr: {err}");
+        }
+    }
+
+    if let Some(command) = closure.command {
+        closure.report_leftover_processes(command.pid);
+    }
+
+    if let Some(iolog) = iolog {
+        let mut iolog = iolog.borrow_mut();
+        if let Err(err) = iolog.finish() {
+            auth_warn!("cannot write I/O log {}: {err}", iolog.path().display());
+        }
+    }
+
     // Restore the terminal settings
     if closure.term_raw {
         // Only restore the terminal if sudo is the foreground process.
@@ -255,28 +429,153 @@ pub(in crate::exec) fn exec_pty(
         output: ExecOutput {
             command_exit_reason: exit_reason?,
             restore_signal_handlers: Box::new(move || drop(closure.signal_handlers)),
+            resource_usage: closure.resource_usage,
         },
     })
 }
 
-fn get_pty() -> io::Result<Pty> {
-    let tty_gid = Group::from_name("tty")
-        .unwrap_or(None)
-        .map(|group| group.gid);
+/// The maximum number of signals that can wait to be sent to the monitor.
+const MAX_QUEUED_SIGNALS: usize = 32;
+
+/// Add a signal to the messages for the monitor, returning whether it was added.
+///
+/// Termination signals are sent before any other signal, and are not added if they are already
+/// waiting to be sent. Other signals are not added if they are the same as the last one, or if the
+/// queue is full, unless they resume the command.
+fn queue_signal(queue: &mut VecDeque<MonitorMessage>, signal: SignalNumber) -> bool {
+    let message = MonitorMessage::Signal(signal);
+
+    if is_termination_signal(signal) {
+        let position = queue
+            .iter()
+            .position(|msg| !matches!(msg, MonitorMessage::Signal(s) if is_termination_signal(*s)))
+            .unwrap_or(queue.len());
+        if queue.range(..position).any(|msg| *msg == message) {
+            return false;
+        }
+        queue.insert(position, message);
+    } else {
+        let resumes = matches!(signal, SIGCONT_FG | SIGCONT_BG);
+        if queue.back() == Some(&message) || (queue.len() >= MAX_QUEUED_SIGNALS && !resumes) {
+            return false;
+        }
+        queue.push_back(message);
+    }
+
+    true
+}
+
+/// The part of `output` that may still be logged when `logged` bytes of output came before it,
+/// and whether it is the part that exceeds `quota`.
+fn within_quota<'a>(output: &'a [u8], logged: &mut u64, quota: u64) -> (&'a [u8], bool) {
+    let before = *logged;
+    *logged = before.saturating_add(output.len() as u64);
+    if before >= quota {
+        return (&[], before == quota && *logged > quota);
+    }
+
+    let allowed = (quota - before).min(output.len() as u64) as usize;
+    (&output[..allowed], *logged > quota)
+}
+
+/// How often copying the settings of the user's terminal to the pty is attempted.
+const COPY_SETTINGS_ATTEMPTS: usize = 3;
+
+/// How often to check whether the size or the settings of the user's terminal changed.
+const SYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often to check whether the session is idle, at most.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long before an idle session ends the user is warned, at most.
+const IDLE_WARN_BEFORE: Duration = Duration::from_secs(60);
+
+/// How long a command that was hung up on for being idle may take to exit before it is killed.
+const IDLE_KILL_AFTER: Duration = Duration::from_secs(10);
+
+/// Ends the session once nothing went through the pty for a while (`idle_timeout`).
+struct IdleTimeout {
+    timeout: Duration,
+    warn_before: Duration,
+    timer: IntervalTimer,
+    handle: EventHandle,
+    state: IdleState,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IdleState {
+    Active,
+    /// The user was told that the session is about to end.
+    Warned,
+    /// The command was hung up on at this time.
+    HungUp(Instant),
+}
+
+/// Copy the settings of the user's terminal to the pty, and log the ones that the pty did not take
+/// over. This returns the settings of the user's terminal at the time.
+fn copy_settings(user_tty: &UserTerm, pty: &impl AsRawFd) -> io::Result<TermSettings> {
+    retry_transient(COPY_SETTINGS_ATTEMPTS, || user_tty.copy_to(pty))?;
+    let settings = TermSettings::of(user_tty)?;
+
+    match TermSettings::of(pty) {
+        Ok(pty_settings) => {
+            let differences = settings.differences(&pty_settings);
+            if !differences.is_empty() {
+                dev_warn!(
+                    "pty differs from the user's terminal in {}",
+                    differences.join(", ")
+                );
+            }
+        }
+        Err(err) => dev_warn!("cannot get the settings of the pty: {err}"),
+    }
+
+    Ok(settings)
+}
+
+/// Allocate a pty that belongs to the invoking user, and that members of `group` may write to.
+fn get_pty(group: Option<&str>) -> io::Result<Pty> {
+    let gid = group.and_then(|name| match Group::from_name(name) {
+        Ok(Some(group)) => Some(group.gid),
+        _ => {
+            dev_warn!("group {name} not found, only the invoking user may use the pty");
+            None
+        }
+    });
 
     let pty = Pty::open().map_err(|err| {
         dev_error!("cannot allocate pty: {err}");
         err
     })?;
 
-    chown(&pty.path, User::effective_uid(), tty_gid).map_err(|err| {
-        dev_error!("cannot change owner for pty: {err}");
+    set_pty_owner(&pty.follower, User::effective_uid(), gid).map_err(|err| {
+        dev_error!(
+            "cannot change owner for pty {}: {err}",
+            pty.path.to_string_lossy()
+        );
         err
     })?;
 
     Ok(pty)
 }
 
+/// Create a pipe for the stderr of the command, returning its write end and a relay from its read
+/// end to the stderr of sudo.
+fn relay_stderr(
+    registry: &mut EventRegistry<ParentClosure>,
+) -> io::Result<(OwnedFd, Relay<File, File>)> {
+    let (read, write) = pipe()?;
+    let stderr = io::stderr().as_fd().try_clone_to_owned()?;
+    let relay = Relay::new(
+        File::from(read),
+        File::from(stderr),
+        registry,
+        ParentEvent::Stderr,
+    );
+
+    Ok((write, relay))
+}
+
 struct ParentClosure {
     // The monitor PID.
     //
@@ -284,23 +583,56 @@ struct ParentClosure {
     monitor_pid: Option<ProcessId>,
     sudo_pid: ProcessId,
     parent_pgrp: ProcessId,
-    command_pid: Option<ProcessId>,
+    /// The command, which is not a child of this process but of the monitor.
+    command: Option<ChildProcess>,
     tty_pipe: Pipe<UserTerm, PtyLeader>,
+    /// Relays the stderr of the command to the one of sudo, if that is not a terminal and logged.
+    stderr_relay: Option<Relay<File, File>>,
+    /// The size of the user's terminal when it was last given to the pty.
     tty_size: TermSize,
+    /// The settings of the user's terminal when they were last copied to the pty.
+    tty_settings: Option<TermSettings>,
+    /// Expires when it is time to check whether the size or the settings of the user's terminal
+    /// changed.
+    sync_timer: Option<IntervalTimer>,
     foreground: bool,
     term_raw: bool,
+    /// Whether the user's terminal generates signals for Ctrl-C, Ctrl-Z, etc. while it is in raw
+    /// mode, instead of passing these keys to the pty.
+    tty_signals: bool,
+    /// Whether the user's terminal handles Ctrl-S and Ctrl-Q while it is in raw mode, instead of
+    /// passing these keys to the pty.
+    tty_flow_control: bool,
+    /// The signals that are forwarded to the command, or `None` if all of them are.
+    forward_signals: Option<Vec<SignalNumber>>,
+    /// The resources that the command used, as reported by the monitor.
+    resource_usage: Option<ResourceUsage>,
     backchannel: ParentBackchannel,
     message_queue: VecDeque<MonitorMessage>,
     backchannel_write_handle: EventHandle,
+    control: Option<ControlSocket>,
+    /// Expires when the invoking user has to authenticate again, and asks them to.
+    reauth: Option<(IntervalTimer, EventHandle, Reauthenticate)>,
+    /// Whether the invoking user has to authenticate again as soon as sudo is in the foreground.
+    reauth_pending: bool,
+    idle: Option<IdleTimeout>,
+    /// How many bytes of output the command may write before it is hung up on.
+    output_limit: Option<u64>,
     signal_stream: &'static SignalStream,
     signal_handlers: [SignalHandler; ParentClosure::SIGNALS.len()],
 }
 
 impl ParentClosure {
+    #[cfg(target_os = "linux")]
     const SIGNALS: [SignalNumber; 11] = [
         SIGINT, SIGQUIT, SIGTSTP, SIGTERM, SIGHUP, SIGALRM, SIGUSR1, SIGUSR2, SIGCHLD, SIGCONT,
         SIGWINCH,
     ];
+    #[cfg(not(target_os = "linux"))]
+    const SIGNALS: [SignalNumber; 12] = [
+        SIGINT, SIGQUIT, SIGTSTP, SIGTERM, SIGHUP, SIGALRM, SIGUSR1, SIGUSR2, SIGCHLD, SIGCONT,
+        SIGWINCH, SIGINFO,
+    ];
 
     #[allow(clippy::too_many_arguments)]
     fn new(
@@ -308,10 +640,19 @@ impl ParentClosure {
         sudo_pid: ProcessId,
         parent_pgrp: ProcessId,
         mut backchannel: ParentBackchannel,
-        tty_pipe: Pipe<UserTerm, PtyLeader>,
+        mut tty_pipe: Pipe<UserTerm, PtyLeader>,
+        stderr_relay: Option<Relay<File, File>>,
         tty_size: TermSize,
+        tty_settings: Option<TermSettings>,
         foreground: bool,
         term_raw: bool,
+        tty_signals: bool,
+        tty_flow_control: bool,
+        forward_signals: Option<Vec<SignalNumber>>,
+        control: Option<ControlSocket>,
+        reauth: Option<(Duration, Reauthenticate)>,
+        idle_timeout: Option<Duration>,
+        output_limit: Option<u64>,
         registry: &mut EventRegistry<Self>,
     ) -> io::Result<Self> {
         // Enable nonblocking assertions as we will poll this inside the event loop.
@@ -330,18 +671,89 @@ impl ParentClosure {
 
         let signal_handlers = register_handlers(Self::SIGNALS)?;
 
+        if let Some(control) = &control {
+            registry.register_event(control, PollEvent::Readable, |_| ParentEvent::Control);
+        }
+
+        // Other processes may change the settings of the user's terminal while it is not in raw
+        // mode, e.g. with `stty`, which the command should see as well, and `SIGWINCH` only
+        // reaches sudo if it is in the foreground when the terminal is resized.
+        let sync_timer = match IntervalTimer::new(SYNC_INTERVAL) {
+            Ok(timer) => {
+                registry.register_event(&timer, PollEvent::Readable, |_| ParentEvent::Sync);
+                Some(timer)
+            }
+            Err(err) => {
+                dev_warn!("cannot create timer to check the user's terminal: {err}");
+                None
+            }
+        };
+
+        // The invoking user has to authenticate again every so often; without a timer, the command
+        // is not run at all, as it could run for longer.
+        let reauth = match reauth {
+            Some((interval, authenticate)) => {
+                let timer = IntervalTimer::new(interval)?;
+                let handle =
+                    registry.register_event(&timer, PollEvent::Readable, |_| ParentEvent::Reauth);
+                Some((timer, handle, authenticate))
+            }
+            None => None,
+        };
+
+        // Without a timer, the command is not run at all, as it would never end while idle.
+        let idle = match idle_timeout {
+            Some(timeout) => {
+                let warn_before = IDLE_WARN_BEFORE.min(timeout / 2);
+                let timer = IntervalTimer::new(IDLE_CHECK_INTERVAL.min(warn_before))?;
+                let handle =
+                    registry.register_event(&timer, PollEvent::Readable, |_| ParentEvent::Idle);
+                Some(IdleTimeout {
+                    timeout,
+                    warn_before,
+                    timer,
+                    handle,
+                    state: IdleState::Active,
+                })
+            }
+            None => None,
+        };
+
+        // Reading from the user's terminal would fail until we are in the foreground.
+        if !foreground {
+            tty_pipe.pause_reading_left(registry);
+        }
+
+        // The command has a terminal of its own, so no one has to open the user's terminal while
+        // it is in the foreground, but processes that would like to read what is typed into it.
+        if let Err(err) = tty_pipe.left_mut().set_exclusive(foreground) {
+            dev_warn!("cannot put the terminal in exclusive mode: {err}");
+        }
+
         Ok(Self {
             monitor_pid: Some(monitor_pid),
             sudo_pid,
             parent_pgrp,
-            command_pid: None,
+            command: None,
             tty_pipe,
+            stderr_relay,
             tty_size,
+            tty_settings,
+            sync_timer,
             foreground,
             term_raw,
+            tty_signals,
+            tty_flow_control,
+            forward_signals,
+            resource_usage: None,
             backchannel,
             message_queue: VecDeque::new(),
             backchannel_write_handle,
+            control,
+            reauth,
+            reauth_pending: false,
+            idle,
+            output_limit,
             signal_stream,
             signal_handlers,
         })
@@ -382,7 +794,7 @@ impl ParentClosure {
                     // executing.
                     ParentMessage::CommandPid(pid) => {
                         dev_info!("received command PID ({pid}) from monitor");
-                        self.command_pid = pid.into();
+                        self.command = Some(ChildProcess::new(pid));
                     }
                     ParentMessage::CommandStatus(status) => {
                         // The command terminated or the monitor was not able to spawn it. We should stop
@@ -415,10 +827,18 @@ impl ParentClosure {
                         dev_info!("received error ({code}) for monitor: {err}");
                         registry.set_break(err);
                     }
+                    ParentMessage::ExecFailure(failure) => {
+                        dev_info!("received failure for monitor: {failure}");
+                        registry.set_break(failure.into());
+                    }
                     ParentMessage::ShortRead => {
                         dev_info!("received short read error for monitor");
                         registry.set_break(io::ErrorKind::UnexpectedEof.into());
                     }
+                    ParentMessage::ResourceUsage(usage) => {
+                        dev_info!("received resource usage of command: {usage:?}");
+                        self.resource_usage = Some(usage);
+                    }
                 }
             }
         }
@@ -431,12 +851,13 @@ impl ParentClosure {
     /// - is in the process group of the command and either sudo or the command is the leader.
     fn is_self_terminating(&self, signaler_pid: ProcessId) -> bool {
         if signaler_pid != 0 {
-            if Some(signaler_pid) == self.command_pid {
+            let command_pid = self.command.map(|command| command.pid);
+            if Some(signaler_pid) == command_pid {
                 return true;
             }
 
             if let Ok(signaler_pgrp) = getpgid(signaler_pid) {
-                if Some(signaler_pgrp) == self.command_pid || signaler_pgrp == self.sudo_pid {
+                if Some(signaler_pgrp) == command_pid || signaler_pgrp == self.sudo_pid {
                     return true;
                 }
             }
@@ -449,8 +870,11 @@ impl ParentClosure {
     ///
     /// The signal message will be sent once the backchannel is ready to be written.
     fn schedule_signal(&mut self, signal: c_int, registry: &mut EventRegistry<Self>) {
+        if !queue_signal(&mut self.message_queue, signal) {
+            dev_info!("not scheduling {} for monitor again", signal_fmt(signal));
+            return;
+        }
         dev_info!("scheduling message with {} for monitor", signal_fmt(signal));
-        self.message_queue.push_back(MonitorMessage::Signal(signal));
 
         // Start polling the backchannel for writing if not already.
         self.backchannel_write_handle.resume(registry);
@@ -511,7 +935,12 @@ impl ParentClosure {
                     signal_fmt(signal)
                 );
                 if !self.term_raw {
-                    if self.tty_pipe.left_mut().set_raw_mode(false).is_ok() {
+                    if self
+                        .tty_pipe
+                        .left_mut()
+                        .set_raw_mode(self.tty_signals, self.tty_flow_control)
+                        .is_ok()
+                    {
                         self.term_raw = true;
                     }
                     // Resume command in the foreground
@@ -523,6 +952,11 @@ impl ParentClosure {
         // Stop polling the terminals.
         self.tty_pipe.ignore_events(registry);
 
+        // The shell of the user may need to open the terminal while the command is suspended.
+        if let Err(err) = self.tty_pipe.left_mut().set_exclusive(false) {
+            dev_warn!("cannot take the terminal out of exclusive mode: {err}");
+        }
+
         if self.term_raw {
             match self.tty_pipe.left_mut().restore(false) {
                 Ok(()) => self.term_raw = false,
@@ -542,14 +976,14 @@ impl ParentClosure {
             || killpg(self.parent_pgrp, signal).is_err()
         {
             dev_error!("no parent to suspend, terminating command");
-            if let Some(command_pid) = self.command_pid.take() {
-                terminate_process(command_pid, true);
+            if let Some(command) = self.command.take() {
+                terminate_process(command, true);
             }
         }
 
         drop(signal_handler);
 
-        if self.command_pid.is_none() || self.resume_terminal().is_err() {
+        if self.command.is_none() || self.resume_terminal(registry).is_err() {
             return None;
         }
 
@@ -573,18 +1007,20 @@ impl ParentClosure {
     }
 
     /// Restore the terminal when sudo resumes after receving `SIGCONT`.
-    fn resume_terminal(&mut self) -> io::Result<()> {
+    fn resume_terminal(&mut self, registry: &mut EventRegistry<Self>) -> io::Result<()> {
         self.check_foreground()?;
 
         // Update the pty settings based on the user's tty.
-        self.tty_pipe
-            .left()
-            .copy_to(self.tty_pipe.right())
-            .map_err(|err| {
+        let settings =
+            copy_settings(self.tty_pipe.left(), self.tty_pipe.right()).map_err(|err| {
                 dev_error!("cannot copy terminal settings to pty: {err}");
                 err
             })?;
-        // FIXME: sync the terminal size here.
+        self.tty_settings = Some(settings);
+        // The terminal may have been resized while the command was suspended.
+        if let Err(err) = self.sync_size() {
+            dev_warn!("cannot resize terminal: {err}");
+        }
         dev_info!(
             "parent is in {} ({} -> {})",
             cond_fmt(self.foreground, "foreground", "background"),
@@ -592,9 +1028,19 @@ impl ParentClosure {
             cond_fmt(self.foreground, "raw", "cooked"),
         );
 
+        if let Err(err) = self.tty_pipe.left_mut().set_exclusive(self.foreground) {
+            dev_warn!("cannot change the exclusive mode of the terminal: {err}");
+        }
+
         if self.foreground {
-            // We're in the foreground, set tty to raw mode.
-            if self.tty_pipe.left_mut().set_raw_mode(false).is_ok() {
+            // We're in the foreground, relay input from the tty again and set it to raw mode.
+            self.tty_pipe.resume_reading_left(registry);
+            if self
+                .tty_pipe
+                .left_mut()
+                .set_raw_mode(self.tty_signals, self.tty_flow_control)
+                .is_ok()
+            {
                 self.term_raw = true;
             }
         } else {
@@ -602,9 +1048,132 @@ impl ParentClosure {
             self.term_raw = false;
         }
 
+        if self.reauth_pending {
+            self.reauthenticate(registry);
+        }
+
         Ok(())
     }
 
+    /// Ask the invoking user to authenticate again, once sudo is in the foreground, while the
+    /// output of the command is not relayed. The command is hung up on if they fail to.
+    fn reauthenticate(&mut self, registry: &mut EventRegistry<Self>) {
+        if let Some((timer, ..)) = &self.reauth {
+            timer.acknowledge().ok();
+        }
+        self.reauth_pending = true;
+        if self.check_foreground().is_err() || !self.foreground {
+            dev_info!("parent is in the background, authenticating again once it is not");
+            return;
+        }
+        let Some((timer, mut handle, mut authenticate)) = self.reauth.take() else {
+            return;
+        };
+        self.reauth_pending = false;
+
+        // the password is read from the user's terminal as it usually is
+        let authenticated = self.with_cooked_terminal(|| {
+            user_warn!("the session has to be authenticated again");
+            authenticate()
+        });
+
+        if authenticated {
+            auth_info!("session authenticated again");
+            // the time it took does not count
+            timer.acknowledge().ok();
+            self.reauth = Some((timer, handle, authenticate));
+        } else {
+            auth_warn!("session could not be authenticated again, hanging up on the command");
+            handle.ignore(registry);
+            self.schedule_signal(SIGHUP, registry);
+        }
+    }
+
+    /// Run `f` with the user's terminal out of raw mode, after everything the command wrote to it
+    /// so far, so that sudo can talk to the user.
+    fn with_cooked_terminal<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        self.tty_pipe.flush_left().ok();
+        let term_raw = self.term_raw;
+        if term_raw {
+            match self.tty_pipe.left_mut().restore(false) {
+                Ok(()) => self.term_raw = false,
+                Err(err) => dev_warn!("cannot restore terminal settings: {err}"),
+            }
+        }
+        let result = f();
+        if term_raw
+            && self
+                .tty_pipe
+                .left_mut()
+                .set_raw_mode(self.tty_signals, self.tty_flow_control)
+                .is_ok()
+        {
+            self.term_raw = true;
+        }
+
+        result
+    }
+
+    /// Warn the user when the session is about to end because nothing went through the pty for
+    /// too long, and end it once it has. The command is killed if it is still there a while
+    /// after being hung up on.
+    fn check_idle(&mut self, registry: &mut EventRegistry<Self>) {
+        let Some(idle) = &mut self.idle else {
+            return;
+        };
+        idle.timer.acknowledge().ok();
+        let idle_for = self.tty_pipe.idle_for();
+        let timeout = idle.timeout;
+
+        match idle.state {
+            IdleState::HungUp(since) => {
+                if since.elapsed() >= IDLE_KILL_AFTER {
+                    auth_warn!("the command did not exit after being hung up on, killing it");
+                    if let Some(mut idle) = self.idle.take() {
+                        idle.handle.ignore(registry);
+                    }
+                    self.schedule_signal(SIGKILL, registry);
+                }
+            }
+            _ if idle_for >= timeout => {
+                auth_warn!(
+                    "session was idle for {} seconds, hanging up on the command",
+                    timeout.as_secs()
+                );
+                idle.state = IdleState::HungUp(Instant::now());
+                self.schedule_signal(SIGHUP, registry);
+            }
+            IdleState::Active if idle_for + idle.warn_before >= timeout => {
+                idle.state = IdleState::Warned;
+                let remaining = timeout.saturating_sub(idle_for).as_secs_f64().ceil() as u64;
+                // only a terminal in the foreground can be written to
+                if self.check_foreground().is_ok() && self.foreground {
+                    self.with_cooked_terminal(|| {
+                        user_warn!(
+                            "the session ends in {remaining} seconds unless there is input or output"
+                        )
+                    });
+                }
+            }
+            IdleState::Warned if idle_for + idle.warn_before < timeout => {
+                idle.state = IdleState::Active;
+            }
+            IdleState::Active | IdleState::Warned => {}
+        }
+    }
+
+    /// Hang up on the command once it wrote more output than it may.
+    fn check_output_limit(&mut self, registry: &mut EventRegistry<Self>) {
+        let Some(limit) = self.output_limit else {
+            return;
+        };
+        if self.tty_pipe.bytes_read_right() > limit {
+            auth_warn!("the command wrote more than {limit} bytes of output, hanging up on it");
+            self.output_limit = None;
+            self.schedule_signal(SIGHUP, registry);
+        }
+    }
+
     fn on_signal(&mut self, registry: &mut EventRegistry<Self>) {
         let info = match self.signal_stream.recv() {
             Ok(info) => info,
@@ -629,21 +1198,121 @@ impl ParentClosure {
         match info.signal() {
             SIGCHLD => handle_sigchld(self, registry, "monitor", monitor_pid),
             SIGCONT => {
-                self.resume_terminal().ok();
-            }
-            SIGWINCH => {
-                if let Err(err) = self.handle_sigwinch() {
-                    dev_warn!("cannot resize terminal: {}", err);
-                }
+                self.resume_terminal(registry).ok();
             }
+            SIGWINCH => self.sync_terminal(),
             // Skip the signal if it was sent by the user and it is self-terminating.
             _ if info.is_user_signaled() && self.is_self_terminating(info.pid()) => {}
+            signal if !is_forwarded(self.forward_signals.as_deref(), signal) => {
+                dev_info!("not forwarding {} to the command", signal_fmt(signal));
+            }
             // FIXME: check `send_command_status`
             signal => self.schedule_signal(signal, registry),
         }
     }
 
-    fn handle_sigwinch(&mut self) -> io::Result<()> {
+    /// Stop relaying input from the user's terminal if reading from it failed because sudo was
+    /// moved to the background; [Self::resume_terminal] resumes it once we are in the foreground.
+    fn on_tty_error(&mut self, err: io::Error, registry: &mut EventRegistry<Self>) {
+        if err.raw_os_error() != Some(libc::EIO) {
+            return;
+        }
+
+        if self.tty_hung_up(registry) {
+            return;
+        }
+
+        if self.check_foreground().is_err() || !self.foreground {
+            dev_info!("parent is in the background, not reading from the user's terminal");
+            self.tty_pipe.pause_reading_left(registry);
+        }
+    }
+
+    /// Reading from the user's terminal reached the end of file, either because Ctrl-D was typed at
+    /// the start of a line while it is not in raw mode, which ends the input of the command as
+    /// well, or because it was hung up, after which it is not read from anymore.
+    fn on_tty_eof(&mut self, registry: &mut EventRegistry<Self>) {
+        if self.tty_hung_up(registry) {
+            return;
+        }
+
+        let eof = TermSettings::of(self.tty_pipe.right())
+            .ok()
+            .or(self.tty_settings)
+            .and_then(|settings| settings.eof_char());
+        self.tty_pipe.continue_left(eof, registry);
+    }
+
+    /// Check whether the user's terminal was hung up, in which case it cannot be read from or
+    /// written to anymore. The output of the command is still logged, but goes nowhere else.
+    fn tty_hung_up(&mut self, registry: &mut EventRegistry<Self>) -> bool {
+        // A terminal that was hung up cannot be inspected anymore.
+        if TermSettings::of(self.tty_pipe.left()).is_ok() {
+            return false;
+        }
+
+        dev_info!("the user's terminal was hung up, not relaying to it anymore");
+        self.tty_pipe.pause_reading_left(registry);
+        self.tty_pipe.stop_writing_left(registry);
+        true
+    }
+
+    /// Bring the pty in line with the user's terminal, which is done on `SIGWINCH` and every
+    /// [`SYNC_INTERVAL`], so that the command never sees a stale size.
+    fn sync_terminal(&mut self) {
+        if let Some(timer) = &self.sync_timer {
+            timer.acknowledge().ok();
+        }
+        if let Err(err) = self.sync_size() {
+            dev_warn!("cannot resize terminal: {err}");
+        }
+        self.sync_settings();
+    }
+
+    /// Copy the settings of the user's terminal to the pty again if they changed since the last
+    /// time. While the user's terminal is in raw mode, it has the settings that sudo gave it.
+    fn sync_settings(&mut self) {
+        if self.term_raw {
+            return;
+        }
+
+        let settings = match TermSettings::of(self.tty_pipe.left()) {
+            Ok(settings) => settings,
+            Err(err) => {
+                dev_warn!("cannot get the settings of the user's terminal: {err}");
+                return;
+            }
+        };
+        if self.tty_settings == Some(settings) {
+            return;
+        }
+
+        dev_info!("settings of the user's terminal changed, updating the pty");
+        match copy_settings(self.tty_pipe.left(), self.tty_pipe.right()) {
+            Ok(settings) => self.tty_settings = Some(settings),
+            Err(err) => dev_warn!("cannot copy terminal settings to pty: {err}"),
+        }
+    }
+
+    /// Log the processes that are still in the process group of the command after it exited.
+    fn report_leftover_processes(&self, command_pgrp: ProcessId) {
+        match crate::system::Process::group_members(command_pgrp) {
+            Ok(pids) if pids.is_empty() => {}
+            Ok(pids) => {
+                let pids = pids
+                    .iter()
+                    .map(ProcessId::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                auth_info!("processes of the command are still running after it exited: {pids}");
+            }
+            Err(err) => dev_info!("cannot list the processes of the command: {err}"),
+        }
+    }
+
+    /// Give the pty the size of the user's terminal if it changed since the last time, and tell
+    /// the command about it.
+    fn sync_size(&mut self) -> io::Result<()> {
         let new_size = self.tty_pipe.left().get_size()?;
 
         if new_size != self.tty_size {
@@ -651,8 +1320,8 @@ impl ParentClosure {
             // Set the pty size.
             self.tty_pipe.right().set_size(&new_size)?;
             // Send SIGWINCH to the command.
-            if let Some(command_pid) = self.command_pid {
-                killpg(command_pid, SIGWINCH).ok();
+            if let Some(command) = self.command {
+                command.killpg(SIGWINCH).ok();
             }
             // Update the terminal size.
             self.tty_size = new_size;
@@ -686,7 +1355,12 @@ enum ParentEvent {
     Signal,
     Tty(PollEvent),
     Pty(PollEvent),
+    Stderr(PollEvent),
     Backchannel(PollEvent),
+    Sync,
+    Control,
+    Reauth,
+    Idle,
 }
 
 impl Process for ParentClosure {
@@ -698,15 +1372,43 @@ impl Process for ParentClosure {
         match event {
             ParentEvent::Signal => self.on_signal(registry),
             ParentEvent::Tty(poll_event) => {
-                self.tty_pipe.on_left_event(poll_event, registry).ok();
+                match self.tty_pipe.on_left_event(poll_event, registry) {
+                    Err(err) => self.on_tty_error(err, registry),
+                    Ok(()) if self.tty_pipe.left_at_eof() => self.on_tty_eof(registry),
+                    Ok(()) => {}
+                }
             }
             ParentEvent::Pty(poll_event) => {
                 self.tty_pipe.on_right_event(poll_event, registry).ok();
+                self.check_output_limit(registry);
+            }
+            ParentEvent::Stderr(poll_event) => {
+                let Some(relay) = &mut self.stderr_relay else {
+                    return;
+                };
+                if let Err(err) = relay.on_event(poll_event, registry) {
+                    // the command gets an error when it writes to its stderr from now on
+                    dev_warn!("cannot relay stderr: {err}");
+                    relay.ignore_events(registry);
+                    self.stderr_relay = None;
+                }
             }
             ParentEvent::Backchannel(poll_event) => match poll_event {
                 PollEvent::Readable => self.on_message_received(registry),
                 PollEvent::Writable => self.check_message_queue(registry),
             },
+            ParentEvent::Sync => self.sync_terminal(),
+            ParentEvent::Reauth => self.reauthenticate(registry),
+            ParentEvent::Idle => self.check_idle(registry),
+            ParentEvent::Control => {
+                let Some(control) = &self.control else {
+                    return;
+                };
+                if control.serve(self.command.map(|command| command.pid)) {
+                    dev_info!("terminating the command as asked on the control socket");
+                    self.schedule_signal(SIGTERM, registry);
+                }
+            }
         }
     }
 }
@@ -729,3 +1431,67 @@ impl HandleSigchld for ParentClosure {
         self.tty_pipe.resume_events(registry);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::{queue_signal, within_quota, MonitorMessage, MAX_QUEUED_SIGNALS, SIGCONT_FG};
+    use crate::system::signal::consts::*;
+
+    fn signals(queue: &VecDeque<MonitorMessage>) -> Vec<i32> {
+        queue
+            .iter()
+            .map(|msg| match msg {
+                MonitorMessage::Signal(signal) => *signal,
+                MonitorMessage::ExecCommand => 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn coalesces_signals() {
+        let mut queue = VecDeque::new();
+        assert!(queue_signal(&mut queue, SIGUSR1));
+        assert!(!queue_signal(&mut queue, SIGUSR1));
+        assert!(queue_signal(&mut queue, SIGUSR2));
+        assert!(queue_signal(&mut queue, SIGUSR1));
+        assert!(queue_signal(&mut queue, SIGINT));
+        assert!(queue_signal(&mut queue, SIGTERM));
+        assert!(!queue_signal(&mut queue, SIGINT));
+        assert_eq!(
+            signals(&queue),
+            [SIGINT, SIGTERM, SIGUSR1, SIGUSR2, SIGUSR1]
+        );
+    }
+
+    #[test]
+    fn enforces_output_quota() {
+        let mut logged = 0;
+        assert_eq!(within_quota(b"abc", &mut logged, 5), (&b"abc"[..], false));
+        assert_eq!(within_quota(b"de", &mut logged, 5), (&b"de"[..], false));
+        assert_eq!(within_quota(b"fg", &mut logged, 5), (&b""[..], true));
+        assert_eq!(within_quota(b"hi", &mut logged, 5), (&b""[..], false));
+
+        let mut logged = 0;
+        assert_eq!(
+            within_quota(b"abcdef", &mut logged, 4),
+            (&b"abcd"[..], true)
+        );
+        assert_eq!(within_quota(b"g", &mut logged, 4), (&b""[..], false));
+    }
+
+    #[test]
+    fn limits_the_queue() {
+        let mut queue = VecDeque::new();
+        for i in 0..MAX_QUEUED_SIGNALS {
+            queue_signal(&mut queue, if i % 2 == 0 { SIGUSR1 } else { SIGUSR2 });
+        }
+        assert!(!queue_signal(&mut queue, SIGTSTP));
+        assert!(queue_signal(&mut queue, SIGCONT_FG));
+        assert!(queue_signal(&mut queue, SIGHUP));
+        assert_eq!(queue.len(), MAX_QUEUED_SIGNALS + 2);
+        assert_eq!(signals(&queue)[0], SIGHUP);
+        assert_eq!(signals(&queue).last(), Some(&SIGCONT_FG));
+    }
+}
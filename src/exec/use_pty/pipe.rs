@@ -2,9 +2,14 @@ use std::{
     io::{self, Read, Write},
     marker::PhantomData,
     os::fd::AsRawFd,
+    time::{Duration, Instant},
 };
 
 use crate::exec::event::{EventHandle, EventRegistry, PollEvent, Process};
+use crate::system::poll::poll;
+
+/// A function that is shown the bytes that go through a pipe.
+type Log = Box<dyn FnMut(&[u8])>;
 
 // A pipe able to stream data bidirectionally between two read-write types.
 pub(super) struct Pipe<L, R> {
@@ -12,6 +17,14 @@ pub(super) struct Pipe<L, R> {
     right: R,
     buffer_lr: Buffer<L, R>,
     buffer_rl: Buffer<R, L>,
+    /// Called with all the bytes that are read from the left side.
+    log_left: Option<Log>,
+    /// Called with all the bytes that are read from the right side.
+    log_right: Option<Log>,
+    /// When bytes were last read from either side.
+    last_activity: Instant,
+    /// How many bytes were read from the right side.
+    read_right: u64,
 }
 
 impl<L: Read + Write + AsRawFd, R: Read + Write + AsRawFd> Pipe<L, R> {
@@ -36,9 +49,33 @@ impl<L: Read + Write + AsRawFd, R: Read + Write + AsRawFd> Pipe<L, R> {
             ),
             left,
             right,
+            log_left: None,
+            log_right: None,
+            last_activity: Instant::now(),
+            read_right: 0,
         }
     }
 
+    /// Pass all the bytes that are read from the left side of the pipe to `log` as well.
+    pub(super) fn log_left(&mut self, log: impl FnMut(&[u8]) + 'static) {
+        self.log_left = Some(Box::new(log));
+    }
+
+    /// Pass all the bytes that are read from the right side of the pipe to `log` as well.
+    pub(super) fn log_right(&mut self, log: impl FnMut(&[u8]) + 'static) {
+        self.log_right = Some(Box::new(log));
+    }
+
+    /// How long it has been since bytes were last read from either side of the pipe.
+    pub(super) fn idle_for(&self) -> Duration {
+        self.last_activity.elapsed()
+    }
+
+    /// How many bytes were read from the right side of the pipe so far.
+    pub(super) fn bytes_read_right(&self) -> u64 {
+        self.read_right
+    }
+
     /// Get a reference to the left side of the pipe.
     pub(super) fn left(&self) -> &L {
         &self.left
@@ -64,12 +101,62 @@ impl<L: Read + Write + AsRawFd, R: Read + Write + AsRawFd> Pipe<L, R> {
 
     /// Resume the poll events of this pipe
     pub(super) fn resume_events<T: Process>(&mut self, registry: &mut EventRegistry<T>) {
-        self.buffer_lr.read_handle.resume(registry);
+        if self.buffer_lr.can_read() {
+            self.buffer_lr.read_handle.resume(registry);
+        }
         self.buffer_lr.write_handle.resume(registry);
-        self.buffer_rl.read_handle.resume(registry);
+        if self.buffer_rl.can_read() {
+            self.buffer_rl.read_handle.resume(registry);
+        }
         self.buffer_rl.write_handle.resume(registry);
     }
 
+    /// Stop reading from the left side of the pipe until [Pipe::resume_reading_left] is called.
+    /// The bytes that were already read are still written to the right side.
+    pub(super) fn pause_reading_left<T: Process>(&mut self, registry: &mut EventRegistry<T>) {
+        self.buffer_lr.read_paused = true;
+        self.buffer_lr.read_handle.ignore(registry);
+    }
+
+    /// Resume reading from the left side of the pipe.
+    pub(super) fn resume_reading_left<T: Process>(&mut self, registry: &mut EventRegistry<T>) {
+        if self.buffer_lr.read_paused {
+            self.buffer_lr.read_paused = false;
+            if self.buffer_lr.can_read() {
+                self.buffer_lr.read_handle.resume(registry);
+            }
+        }
+    }
+
+    /// Stop writing to the left side of the pipe, which cannot be written to anymore. What is read
+    /// from the right side is still passed to the log, but dropped afterwards.
+    pub(super) fn stop_writing_left<T: Process>(&mut self, registry: &mut EventRegistry<T>) {
+        self.buffer_rl.discard(registry);
+    }
+
+    /// Whether the left side of the pipe reached the end of file, after which it is not read
+    /// anymore until [Pipe::continue_left] is called.
+    pub(super) fn left_at_eof(&self) -> bool {
+        self.buffer_lr.eof
+    }
+
+    /// Read from the left side of the pipe again after it reached the end of file, which is a
+    /// passing condition for a terminal. The end of file is passed on to the right side as
+    /// `eof`, after the bytes that were read before it.
+    pub(super) fn continue_left<T: Process>(
+        &mut self,
+        eof: Option<u8>,
+        registry: &mut EventRegistry<T>,
+    ) {
+        if let Some(eof) = eof {
+            self.buffer_lr.push(eof, registry);
+        }
+        self.buffer_lr.eof = false;
+        if self.buffer_lr.can_read() {
+            self.buffer_lr.read_handle.resume(registry);
+        }
+    }
+
     /// Handle a poll event for the left side of the pipe.
     pub(super) fn on_left_event<T: Process>(
         &mut self,
@@ -77,7 +164,16 @@ impl<L: Read + Write + AsRawFd, R: Read + Write + AsRawFd> Pipe<L, R> {
         registry: &mut EventRegistry<T>,
     ) -> io::Result<()> {
         match poll_event {
-            PollEvent::Readable => self.buffer_lr.read(&mut self.left, registry),
+            PollEvent::Readable => {
+                let read = self.buffer_lr.read(&mut self.left, registry)?;
+                if !read.is_empty() {
+                    self.last_activity = Instant::now();
+                }
+                if let Some(log) = &mut self.log_left {
+                    log(read);
+                }
+                Ok(())
+            }
             PollEvent::Writable => self.buffer_rl.write(&mut self.left, registry),
         }
     }
@@ -89,7 +185,17 @@ impl<L: Read + Write + AsRawFd, R: Read + Write + AsRawFd> Pipe<L, R> {
         registry: &mut EventRegistry<T>,
     ) -> io::Result<()> {
         match poll_event {
-            PollEvent::Readable => self.buffer_rl.read(&mut self.right, registry),
+            PollEvent::Readable => {
+                let read = self.buffer_rl.read(&mut self.right, registry)?;
+                if !read.is_empty() {
+                    self.last_activity = Instant::now();
+                    self.read_right += read.len() as u64;
+                }
+                if let Some(log) = &mut self.log_right {
+                    log(read);
+                }
+                Ok(())
+            }
             PollEvent::Writable => self.buffer_lr.write(&mut self.right, registry),
         }
     }
@@ -98,6 +204,153 @@ impl<L: Read + Write + AsRawFd, R: Read + Write + AsRawFd> Pipe<L, R> {
     pub(super) fn flush_left(&mut self) -> io::Result<()> {
         self.buffer_rl.flush(&mut self.left)
     }
+
+    /// Write everything that is read from the right side to the left side, without using the
+    /// event loop, until the right side reaches the end of file or `timeout` passed. Return
+    /// whether the end of file was reached.
+    ///
+    /// Reading from a pty leader fails with `EIO` once no process has the follower open anymore,
+    /// which is also considered the end of file.
+    pub(super) fn drain_right(&mut self, timeout: Duration) -> io::Result<bool> {
+        self.flush_left()?;
+        drain(
+            &mut self.right,
+            &mut self.left,
+            &mut self.log_right,
+            &mut self.read_right,
+            timeout,
+        )
+    }
+}
+
+/// A pipe that streams data in one direction only, from a source that is read to a sink that is
+/// written.
+pub(super) struct Relay<R, W> {
+    source: R,
+    sink: W,
+    buffer: Buffer<R, W>,
+    /// Called with all the bytes that are read from the source.
+    log: Option<Log>,
+}
+
+impl<R: Read + AsRawFd, W: Write + AsRawFd> Relay<R, W> {
+    /// Create a new relay from `source` to `sink` and register them to be polled.
+    pub fn new<T: Process>(
+        source: R,
+        sink: W,
+        registry: &mut EventRegistry<T>,
+        f: fn(PollEvent) -> T::Event,
+    ) -> Self {
+        Self {
+            buffer: Buffer::new(
+                registry.register_event(&source, PollEvent::Readable, f),
+                registry.register_event(&sink, PollEvent::Writable, f),
+                registry,
+            ),
+            source,
+            sink,
+            log: None,
+        }
+    }
+
+    /// Pass all the bytes that are read from the source to `log` as well.
+    pub(super) fn log(&mut self, log: impl FnMut(&[u8]) + 'static) {
+        self.log = Some(Box::new(log));
+    }
+
+    /// Stop the poll events of this relay.
+    pub(super) fn ignore_events<T: Process>(&mut self, registry: &mut EventRegistry<T>) {
+        self.buffer.read_handle.ignore(registry);
+        self.buffer.write_handle.ignore(registry);
+    }
+
+    /// Handle a poll event for the source, when it is readable, or the sink, when it is writable.
+    pub(super) fn on_event<T: Process>(
+        &mut self,
+        poll_event: PollEvent,
+        registry: &mut EventRegistry<T>,
+    ) -> io::Result<()> {
+        match poll_event {
+            PollEvent::Readable => {
+                let read = self.buffer.read(&mut self.source, registry)?;
+                if let Some(log) = &mut self.log {
+                    log(read);
+                }
+                Ok(())
+            }
+            PollEvent::Writable => self.buffer.write(&mut self.sink, registry),
+        }
+    }
+
+    /// Write everything that is read from the source to the sink, without using the event loop,
+    /// until the source reaches the end of file or `timeout` passed. Return whether the end of
+    /// file was reached.
+    pub(super) fn drain(&mut self, timeout: Duration) -> io::Result<bool> {
+        self.buffer.flush(&mut self.sink)?;
+        drain(
+            &mut self.source,
+            &mut self.sink,
+            &mut self.log,
+            &mut 0,
+            timeout,
+        )
+    }
+}
+
+/// Write everything that is read from `from` to `to`, passing it to `log` as well and counting it
+/// in `read`, until `from` reaches the end of file or `timeout` passed. Return whether the end of
+/// file was reached.
+fn drain(
+    from: &mut (impl Read + AsRawFd),
+    to: &mut impl Write,
+    log: &mut Option<Log>,
+    read: &mut u64,
+    timeout: Duration,
+) -> io::Result<bool> {
+    let deadline = Instant::now() + timeout;
+    let mut buffer = [0; BUFSIZE];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match poll_readable(from, remaining) {
+            Ok(true) => {}
+            Ok(false) => return Ok(false),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+
+        let len = match from.read(&mut buffer) {
+            Ok(0) => return Ok(true),
+            Ok(len) => len,
+            // a pty leader that no process has the follower of open anymore
+            Err(err) if err.raw_os_error() == Some(libc::EIO) => return Ok(true),
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock
+                ) =>
+            {
+                continue
+            }
+            Err(err) => return Err(err),
+        };
+        *read += len as u64;
+        if let Some(log) = log {
+            log(&buffer[..len]);
+        }
+        to.write_all(&buffer[..len])?;
+    }
+}
+
+/// Wait until `fd` can be read, returning `false` if `timeout` passed first.
+fn poll_readable(fd: &impl AsRawFd, timeout: Duration) -> io::Result<bool> {
+    let mut pollfd = libc::pollfd {
+        fd: fd.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let ready = poll(std::slice::from_mut(&mut pollfd), Some(timeout))?;
+
+    Ok(ready > 0)
 }
 
 /// The size of the internal buffer of the pipe.
@@ -114,6 +367,12 @@ struct Buffer<R, W> {
     read_handle: EventHandle,
     /// The handle for the event of the writer.
     write_handle: EventHandle,
+    /// Whether reading was paused, so it should not be resumed when there is room in the buffer.
+    read_paused: bool,
+    /// Whether the reader reached the end of file, which it would stay readable at forever.
+    eof: bool,
+    /// Whether the writer is gone, so the bytes that are read are dropped right away.
+    discarding: bool,
     marker: PhantomData<(R, W)>,
 }
 
@@ -133,6 +392,9 @@ impl<R: Read, W: Write> Buffer<R, W> {
             end: 0,
             read_handle,
             write_handle,
+            read_paused: false,
+            eof: false,
+            discarding: false,
             marker: PhantomData,
         }
     }
@@ -142,6 +404,11 @@ impl<R: Read, W: Write> Buffer<R, W> {
         self.start == self.end
     }
 
+    /// Return true if the reader can be read from when there is room in the buffer.
+    fn can_read(&self) -> bool {
+        !self.read_paused && !self.eof
+    }
+
     /// Return true if the buffer is full.
     fn is_full(&self) -> bool {
         // FIXME: This doesn't really mean that the buffer is full but it cannot be used for writes
@@ -149,18 +416,18 @@ impl<R: Read, W: Write> Buffer<R, W> {
         self.end == BUFSIZE
     }
 
-    /// Read bytes into the buffer.
+    /// Read bytes into the buffer, returning the bytes that were read.
     ///
     /// Calling this function will block until `read` is ready to be read.
     fn read<T: Process>(
         &mut self,
         read: &mut R,
         registry: &mut EventRegistry<T>,
-    ) -> io::Result<()> {
+    ) -> io::Result<&[u8]> {
         // Don't read if the buffer is full.
         if self.is_full() {
             self.read_handle.ignore(registry);
-            return Ok(());
+            return Ok(&[]);
         }
 
         // This is the remaining free section that follows the busy section of the buffer.
@@ -171,13 +438,22 @@ impl<R: Read, W: Write> Buffer<R, W> {
 
         // Mark the `len` bytes after the busy section as busy too.
         self.end += len;
+        let read = self.end - len..self.end;
 
-        // If we read something, the buffer is not empty anymore and we can resume writing.
-        if len > 0 {
+        if len == 0 {
+            // The reader stays readable at the end of file, so it is not polled anymore.
+            self.eof = true;
+            self.read_handle.ignore(registry);
+        } else if self.discarding {
+            // Nothing is written anymore, so the whole buffer is free again.
+            self.start = 0;
+            self.end = 0;
+        } else {
+            // We read something, the buffer is not empty anymore and we can resume writing.
             self.write_handle.resume(registry);
         }
 
-        Ok(())
+        Ok(&self.buffer[read])
     }
 
     /// Write bytes from the buffer.
@@ -210,13 +486,34 @@ impl<R: Read, W: Write> Buffer<R, W> {
         }
 
         // If we wrote something, the buffer is not full anymore and we can resume reading.
-        if len > 0 {
+        if len > 0 && self.can_read() {
             self.read_handle.resume(registry);
         }
 
         Ok(())
     }
 
+    /// Drop the contents of the buffer and everything that is read from now on, as the writer is
+    /// gone.
+    fn discard<T: Process>(&mut self, registry: &mut EventRegistry<T>) {
+        self.discarding = true;
+        self.start = 0;
+        self.end = 0;
+        self.write_handle.ignore(registry);
+        if self.can_read() {
+            self.read_handle.resume(registry);
+        }
+    }
+
+    /// Add `byte` to the buffer, as if it was read, unless the buffer is full.
+    fn push<T: Process>(&mut self, byte: u8, registry: &mut EventRegistry<T>) {
+        if !self.is_full() {
+            self.buffer[self.end] = byte;
+            self.end += 1;
+            self.write_handle.resume(registry);
+        }
+    }
+
     /// Flush this buffer, ensuring that all the contents of its internal buffer are written.
     fn flush(&mut self, write: &mut W) -> io::Result<()> {
         // This is the busy section of the buffer.
@@ -0,0 +1,111 @@
+//! Compressing the streams of I/O logs with gzip (`compress_io`), the way the original sudo does
+//! with zlib, so that `sudoreplay` can read them.
+use std::{
+    fs::File,
+    io::{self, Write},
+    mem::ManuallyDrop,
+};
+
+use flate2::{write::GzEncoder, Compression};
+
+use super::stream::Sink;
+
+/// Compresses what is written to it into a single gzip member, which it writes to another sink.
+pub(super) struct GzipSink {
+    /// Dropping the encoder would end the data, which only [GzipSink::finish] may do.
+    encoder: ManuallyDrop<GzEncoder<Box<dyn Sink>>>,
+    finished: bool,
+}
+
+impl GzipSink {
+    pub(super) fn new(sink: Box<dyn Sink>) -> Self {
+        Self {
+            encoder: ManuallyDrop::new(GzEncoder::new(sink, Compression::default())),
+            finished: false,
+        }
+    }
+}
+
+impl Write for GzipSink {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.encoder.write(data)
+    }
+
+    /// Make everything that was written so far decompressible from the file, like
+    /// `Z_SYNC_FLUSH` of zlib does.
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder.flush()
+    }
+}
+
+impl Sink for GzipSink {
+    fn file(&self) -> &File {
+        self.encoder.get_ref().file()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.encoder.try_finish()?;
+        self.encoder.get_mut().finish()?;
+        self.finished = true;
+
+        Ok(())
+    }
+}
+
+impl Drop for GzipSink {
+    fn drop(&mut self) {
+        // the data was ended already, so the encoder has nothing left to write; otherwise it is
+        // leaked, as another process may still be writing to the same file
+        if self.finished {
+            // SAFETY: the encoder is not used after this.
+            unsafe { ManuallyDrop::drop(&mut self.encoder) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Seek};
+
+    use flate2::read::MultiGzDecoder;
+
+    use super::*;
+
+    /// Decompress as much of the contents of `file` as can be decompressed.
+    fn decompress(file: &mut File) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        file.rewind().unwrap();
+        file.read_to_end(&mut compressed).unwrap();
+
+        let mut decompressed = Vec::new();
+        let _ = MultiGzDecoder::new(&compressed[..]).read_to_end(&mut decompressed);
+        decompressed
+    }
+
+    #[test]
+    fn compresses() {
+        let path = std::env::temp_dir().join(format!("sudo-rs-gzip-{}", std::process::id()));
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        let mut reader = file.try_clone().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let text = b"the quick brown fox jumps over the lazy dog; ".repeat(1000);
+        let mut sink = GzipSink::new(Box::new(file));
+        sink.write_all(&text[..100]).unwrap();
+        // what was flushed can be read while the command still runs, as with `iolog_flush`
+        sink.flush().unwrap();
+        assert_eq!(decompress(&mut reader), &text[..100]);
+
+        sink.write_all(&text[100..]).unwrap();
+        sink.finish().unwrap();
+        let length = reader.metadata().unwrap().len();
+        assert!(length < text.len() as u64 / 10);
+        assert_eq!(decompress(&mut reader), text);
+    }
+}
@@ -0,0 +1,127 @@
+//! Encrypting the streams of I/O logs to the age public key of `iolog_recipient`, so that what was
+//! typed into a session, passwords included, can only be read by the holder of the matching
+//! identity, e.g. with `age -d -i key.txt ttyin`, and not by everyone who can read the logs.
+//!
+//! age encrypts in chunks of 64 KiB, and a chunk can only be written once it is full or the
+//! stream ends. Until then, `iolog_flush` cannot make what was logged readable.
+use std::{
+    fs::File,
+    io::{self, Write},
+    iter,
+};
+
+use age::stream::StreamWriter;
+
+use super::stream::Sink;
+
+/// The public key that the streams are encrypted to.
+pub(super) struct Recipient(age::x25519::Recipient);
+
+impl Recipient {
+    pub(super) fn parse(text: &str) -> io::Result<Self> {
+        text.parse().map(Recipient).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid iolog_recipient {text}: {err}"),
+            )
+        })
+    }
+
+    /// Encrypt what is written to the sink to this recipient before it ends up in `file`.
+    pub(super) fn sink(&self, file: File) -> io::Result<Box<dyn Sink>> {
+        Ok(Box::new(AgeSink::new(file, self)?))
+    }
+}
+
+/// Encrypts what is written to it into a single age file.
+struct AgeSink {
+    /// Taken when the last chunk is written, after which nothing can be written anymore.
+    writer: Option<StreamWriter<File>>,
+    /// The file that the writer writes to, which the writer does not give access to.
+    file: File,
+}
+
+impl AgeSink {
+    fn new(file: File, recipient: &Recipient) -> io::Result<Self> {
+        let encryptor =
+            age::Encryptor::with_recipients(iter::once(&recipient.0 as &dyn age::Recipient))
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+
+        Ok(Self {
+            file: file.try_clone()?,
+            writer: Some(encryptor.wrap_output(file)?),
+        })
+    }
+
+    fn writer(&mut self) -> io::Result<&mut StreamWriter<File>> {
+        self.writer
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "the stream was finished"))
+    }
+}
+
+impl Write for AgeSink {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.writer()?.write(data)
+    }
+
+    /// This only writes out the chunks that are full.
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer()?.flush()
+    }
+}
+
+impl Sink for AgeSink {
+    fn file(&self) -> &File {
+        &self.file
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(writer) = self.writer.take() {
+            writer.finish()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Seek};
+
+    use age::secrecy::ExposeSecret;
+
+    use super::*;
+
+    #[test]
+    fn encrypts() {
+        let identity = age::x25519::Identity::generate();
+        let recipient = Recipient::parse(&identity.to_public().to_string()).unwrap();
+        assert!(Recipient::parse(identity.to_string().expose_secret()).is_err());
+
+        let path = std::env::temp_dir().join(format!("sudo-rs-age-{}", std::process::id()));
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        let mut reader = file.try_clone().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let text = b"password\n".repeat(10000);
+        let mut sink = recipient.sink(file).unwrap();
+        sink.write_all(&text).unwrap();
+        sink.finish().unwrap();
+        assert!(sink.write_all(b"more").is_err());
+
+        let mut encrypted = Vec::new();
+        reader.rewind().unwrap();
+        reader.read_to_end(&mut encrypted).unwrap();
+        assert!(!encrypted.windows(9).any(|window| window == b"password\n"));
+
+        let decrypted = age::decrypt(&identity, &encrypted).unwrap();
+        assert_eq!(decrypted, text);
+    }
+}
@@ -0,0 +1,706 @@
+//! Logging what is typed into and written by a command in its terminal, in the format of the
+//! original sudo, so that sessions can be replayed with `sudoreplay`.
+//!
+//! Every session gets a directory below `iolog_dir`, named after a sequence number in base 36
+//! that is kept in the `seq` file: the session `00001A` is logged in `00/00/1A`. That directory
+//! contains:
+//!
+//! * `log`: when and by whom the command was run, in the legacy format of the original sudo;
+//! * `log.json`: the same as a JSON object, along with the session ID and the login uid of the
+//!   user, which audit records can be correlated with;
+//! * `timing`: for every piece of input or output, its kind, its delay since the previous one
+//!   (on the monotonic clock, so that replaying is not thrown off by adjustments of the wall
+//!   clock), and its length;
+//! * `ttyin` and `ttyout`: what was typed into, and written to, the terminal of the command;
+//! * `stderr`: what the command wrote to its standard error, when that was redirected away from
+//!   the terminal, which is logged along with the output.
+//!
+//! With `compress_io`, all files except `log` and `log.json` are compressed with gzip, if sudo was
+//! built with the `iolog-compression` feature. With `iolog_recipient`, they are then encrypted to
+//! that age public key, if sudo was built with the `iolog-encryption` feature; without it, no
+//! session is logged, and so no command is run, while `iolog_recipient` is set. The timing file is
+//! made read-only once the command exited, which marks the session as complete.
+//!
+//! The files and the directories that are created for them belong to `iolog_user` and
+//! `iolog_group`, with the permissions of `iolog_mode`, so that a collector that does not run as
+//! root can be allowed to read them.
+use std::{
+    ffi::OsString,
+    fs::{DirBuilder, File, Permissions},
+    io::{self, Read, Seek, Write},
+    os::unix::fs::{DirBuilderExt, PermissionsExt},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use crate::{
+    common::json,
+    log::auth_warn,
+    system::{
+        file::{Chown, FileLock},
+        interface::{GroupId, ProcessId, UserId},
+        open_no_symlinks,
+        time::{SystemTime, Timestamps},
+        Group, User,
+    },
+};
+
+use self::stream::Stream;
+
+#[cfg(feature = "iolog-compression")]
+mod compress;
+#[cfg(feature = "iolog-encryption")]
+mod encrypt;
+mod stream;
+
+#[cfg(feature = "iolog-encryption")]
+use self::encrypt::Recipient;
+#[cfg(not(feature = "iolog-encryption"))]
+use self::without_encryption::Recipient;
+
+/// The largest sequence number, as it has to fit in six base 36 digits.
+pub(crate) const MAX_SEQ: u64 = 36u64.pow(6);
+
+/// Which streams are logged, where, and how.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IoLogOptions {
+    /// The directory with the sequence file and the logs of all sessions (`iolog_dir`).
+    pub dir: PathBuf,
+    /// The sequence number wraps around to zero when it reaches this value (`maxseq`).
+    pub maxseq: u64,
+    /// Compress the streams with gzip (`compress_io`, which is only on by default with the
+    /// `iolog-compression` feature); without that feature, they are never compressed.
+    pub compress: bool,
+    /// Write every piece of input and output to the log immediately (`iolog_flush`).
+    pub flush: bool,
+    /// Log what is typed into the terminal (`LOG_INPUT`).
+    pub input: bool,
+    /// Log what the command writes to the terminal (`LOG_OUTPUT`).
+    pub output: bool,
+    /// The owner of the files and directories (`iolog_user`).
+    pub user: String,
+    /// The group of the files and directories, instead of the primary group of the owner
+    /// (`iolog_group`).
+    pub group: Option<String>,
+    /// The permissions of the files, of which only the read and write bits are used
+    /// (`iolog_mode`).
+    pub mode: u32,
+    /// The age public key that the streams are encrypted to (`iolog_recipient`).
+    pub recipient: Option<String>,
+}
+
+/// Without the `iolog-encryption` feature, no recipient can be parsed, so there never is one.
+#[cfg(not(feature = "iolog-encryption"))]
+mod without_encryption {
+    use std::{fs::File, io};
+
+    use super::stream::Sink;
+
+    pub(super) enum Recipient {}
+
+    impl Recipient {
+        pub(super) fn parse(_text: &str) -> io::Result<Self> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "iolog_recipient is set, but sudo was built without support for encrypting I/O logs",
+            ))
+        }
+
+        pub(super) fn sink(&self, _file: File) -> io::Result<Box<dyn Sink>> {
+            match *self {}
+        }
+    }
+}
+
+/// Who owns the files and directories of the I/O logs, and who may access them.
+struct Ownership {
+    uid: UserId,
+    gid: GroupId,
+    file_mode: u32,
+    dir_mode: u32,
+}
+
+impl Ownership {
+    fn resolve(options: &IoLogOptions) -> io::Result<Self> {
+        let not_found = |what: &str, name: &str| {
+            io::Error::new(io::ErrorKind::NotFound, format!("unknown {what} {name}"))
+        };
+
+        let user =
+            User::from_name(&options.user)?.ok_or_else(|| not_found("user", &options.user))?;
+        let gid = match &options.group {
+            Some(name) => {
+                Group::from_name(name)?
+                    .ok_or_else(|| not_found("group", name))?
+                    .gid
+            }
+            None => user.gid,
+        };
+
+        // the owner can always read and write the files
+        let file_mode = options.mode & 0o666 | 0o600;
+        // and directories can be searched by those who can read or write the files in them
+        let dir_mode = [0o700, 0o070, 0o007]
+            .into_iter()
+            .filter(|class| file_mode & class != 0)
+            .fold(file_mode, |mode, class| mode | class & 0o111);
+
+        Ok(Self {
+            uid: user.uid,
+            gid,
+            file_mode,
+            dir_mode,
+        })
+    }
+
+    /// Give `file` this ownership and `mode`, regardless of the umask.
+    fn apply(&self, file: &File, mode: u32) -> io::Result<()> {
+        file.chown(self.uid, self.gid)?;
+        file.set_permissions(Permissions::from_mode(mode))
+    }
+
+    /// Create the directory `path`, as well as its parents that do not exist yet.
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        if path.is_dir() {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            self.create_dir_all(parent)?;
+        }
+
+        match DirBuilder::new().mode(self.dir_mode).create(path) {
+            Ok(()) => self.apply(&File::open(path)?, self.dir_mode),
+            // created in the meantime by another sudo process
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists && path.is_dir() => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Open the file at `path` for writing, creating it if it does not exist, and truncating it
+    /// if indicated.
+    fn open_file(&self, path: &Path, truncate: bool) -> io::Result<File> {
+        // the directories of the log are owned by its owner, and no one else may swap them out
+        let truncate = if truncate { libc::O_TRUNC } else { 0 };
+        let file = open_no_symlinks(
+            path,
+            libc::O_RDWR | libc::O_CREAT | truncate,
+            self.file_mode,
+            &[self.uid],
+        )?;
+        self.apply(&file, self.file_mode)?;
+
+        Ok(file)
+    }
+}
+
+/// What is recorded about a session in its `log` and `log.json` files.
+pub(crate) struct Session<'a> {
+    pub user: &'a str,
+    pub runas_user: &'a str,
+    pub runas_group: &'a str,
+    pub tty: Option<OsString>,
+    pub session_id: ProcessId,
+    pub login_uid: Option<UserId>,
+    /// The ID of the invocation of sudo (`SUDO_SESSION_ID`).
+    pub uuid: Option<&'a str>,
+    pub cwd: Option<PathBuf>,
+    pub command: &'a Path,
+    pub arguments: &'a [String],
+    pub lines: u16,
+    pub cols: u16,
+}
+
+/// The kinds of events in the timing file.
+#[derive(Clone, Copy)]
+enum Event {
+    Stderr = 2,
+    TtyIn = 3,
+    TtyOut = 4,
+}
+
+/// The I/O log of a single session.
+pub(crate) struct IoLog {
+    path: PathBuf,
+    ownership: Ownership,
+    timing: Stream,
+    ttyin: Option<Stream>,
+    ttyout: Option<Stream>,
+    stderr: Option<Stream>,
+    last_event: Instant,
+    failed: bool,
+}
+
+impl IoLog {
+    /// Start logging a new session in the directory of `options`, which is created if needed.
+    pub(crate) fn create(options: &IoLogOptions, session: &Session) -> io::Result<Self> {
+        #[cfg(not(feature = "iolog-compression"))]
+        if options.compress {
+            auth_warn!(
+                "compress_io is set, but sudo was built without support for compressing I/O logs"
+            );
+        }
+        let recipient = options
+            .recipient
+            .as_deref()
+            .map(Recipient::parse)
+            .transpose()?;
+        let ownership = Ownership::resolve(options)?;
+        ownership.create_dir_all(&options.dir)?;
+
+        let mut seq = ownership.open_file(&options.dir.join("seq"), false)?;
+        let id = next_session_id(&mut seq, options.maxseq)?;
+        let path = options.dir.join(session_path(&id));
+        ownership.create_dir_all(&path)?;
+
+        let start = Timestamps::now()?;
+        let mut log = ownership.open_file(&path.join("log"), true)?;
+        log.write_all(&legacy_log(session, &start))?;
+        let mut log = ownership.open_file(&path.join("log.json"), true)?;
+        log.write_all(&json_log(session, &start))?;
+
+        let stream = |name: &str| -> io::Result<Stream> {
+            Stream::new(
+                ownership.open_file(&path.join(name), true)?,
+                options.compress,
+                options.flush,
+                recipient.as_ref(),
+            )
+        };
+
+        Ok(Self {
+            timing: stream("timing")?,
+            ttyin: options.input.then(|| stream("ttyin")).transpose()?,
+            ttyout: options.output.then(|| stream("ttyout")).transpose()?,
+            stderr: options.output.then(|| stream("stderr")).transpose()?,
+            path,
+            ownership,
+            last_event: Instant::now(),
+            failed: false,
+        })
+    }
+
+    /// The directory with the logs of this session.
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether what the command writes to its standard error is logged.
+    pub(crate) fn logs_stderr(&self) -> bool {
+        self.stderr.is_some()
+    }
+
+    /// Log bytes that were typed into the terminal.
+    pub(crate) fn log_ttyin(&mut self, data: &[u8]) {
+        self.log(Event::TtyIn, data)
+    }
+
+    /// Log bytes that the command wrote to its terminal.
+    pub(crate) fn log_ttyout(&mut self, data: &[u8]) {
+        self.log(Event::TtyOut, data)
+    }
+
+    /// Log bytes that the command wrote to its standard error, which is not its terminal.
+    pub(crate) fn log_stderr(&mut self, data: &[u8]) {
+        self.log(Event::Stderr, data)
+    }
+
+    fn log(&mut self, event: Event, data: &[u8]) {
+        let stream = match event {
+            Event::TtyIn => &mut self.ttyin,
+            Event::TtyOut => &mut self.ttyout,
+            Event::Stderr => &mut self.stderr,
+        };
+        let Some(stream) = stream else {
+            return;
+        };
+        if self.failed || data.is_empty() {
+            return;
+        }
+
+        let now = Instant::now();
+        let delay = now - self.last_event;
+        self.last_event = now;
+
+        let timing = format!(
+            "{} {}.{:09} {}\n",
+            event as u8,
+            delay.as_secs(),
+            delay.subsec_nanos(),
+            data.len()
+        );
+        let result = stream
+            .write(data)
+            .and_then(|()| self.timing.write(timing.as_bytes()));
+        if let Err(err) = result {
+            // the command keeps running, as it would without an I/O log
+            auth_warn!("cannot write I/O log {}: {err}", self.path.display());
+            self.failed = true;
+        }
+    }
+
+    /// Write everything that is still buffered and mark the session as complete, after which
+    /// nothing is logged anymore.
+    pub(crate) fn finish(&mut self) -> io::Result<()> {
+        self.failed = true;
+        for stream in [&mut self.ttyin, &mut self.ttyout, &mut self.stderr]
+            .into_iter()
+            .flatten()
+        {
+            stream.finish()?;
+        }
+        self.timing.finish()?;
+        let read_only = self.ownership.file_mode & !0o222;
+        self.timing
+            .file()
+            .set_permissions(Permissions::from_mode(read_only))
+    }
+}
+
+/// Take the next number from the sequence file, which holds the last number that was used, in
+/// six base 36 digits.
+fn next_session_id(file: &mut File, maxseq: u64) -> io::Result<String> {
+    let lock = FileLock::exclusive(file, false)?;
+
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    let last = u64::from_str_radix(contents.trim_end(), 36).ok();
+    let limit = maxseq.min(MAX_SEQ);
+    let id = match last {
+        Some(last) if last + 1 < limit => last + 1,
+        _ => 0,
+    };
+    let id = to_base36(id);
+
+    file.rewind()?;
+    file.set_len(0)?;
+    writeln!(file, "{id}")?;
+    lock.unlock()?;
+
+    Ok(id)
+}
+
+fn to_base36(mut id: u64) -> String {
+    const DIGITS: &[u8; 36] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+    let mut digits = [b'0'; 6];
+    for digit in digits.iter_mut().rev() {
+        *digit = DIGITS[(id % 36) as usize];
+        id /= 36;
+    }
+
+    digits.iter().map(|&digit| digit as char).collect()
+}
+
+/// The directory of a session relative to `iolog_dir`, as the original sudo names it.
+fn session_path(id: &str) -> PathBuf {
+    [&id[0..2], &id[2..4], &id[4..6]].iter().collect()
+}
+
+/// The contents of the `log` file: the start time, the users and terminal, the working directory
+/// and the command, on three lines.
+fn legacy_log(session: &Session, start: &Timestamps) -> Vec<u8> {
+    let start = start.realtime.seconds();
+    let tty = session
+        .tty
+        .as_ref()
+        .map(|tty| tty.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "unknown".to_string());
+    let cwd = session
+        .cwd
+        .as_ref()
+        .map(|cwd| cwd.display().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let command = std::iter::once(session.command.display().to_string())
+        .chain(session.arguments.iter().cloned())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!(
+        "{start}:{}:{}:{}:{tty}:{}:{}\n{cwd}\n{command}\n",
+        session.user, session.runas_user, session.runas_group, session.lines, session.cols
+    )
+    .into_bytes()
+}
+
+/// A point in time in JSON.
+fn json_time(time: &SystemTime) -> String {
+    format!(
+        "{{\"seconds\": {}, \"nanoseconds\": {}}}",
+        time.seconds(),
+        time.nanoseconds()
+    )
+}
+
+/// The contents of the `log.json` file: what the `log` file records, with the session, the
+/// login uid of the user and the ID of the invocation of sudo, as a JSON object. The start time is given on every clock, so that it can
+/// be related to other logs even if the wall clock was adjusted.
+fn json_log(session: &Session, start: &Timestamps) -> Vec<u8> {
+    let mut fields = vec![
+        ("timestamp", json_time(&start.realtime)),
+        ("monotonic", json_time(&start.monotonic)),
+    ];
+    if let Some(boottime) = &start.boottime {
+        fields.push(("boottime", json_time(boottime)));
+    }
+    fields.extend([
+        ("submituser", json::string(session.user)),
+        ("runuser", json::string(session.runas_user)),
+        ("rungroup", json::string(session.runas_group)),
+    ]);
+    if let Some(tty) = &session.tty {
+        fields.push(("ttyname", json::string(&tty.to_string_lossy())));
+    }
+    fields.push(("sessionid", session.session_id.to_string()));
+    if let Some(login_uid) = session.login_uid {
+        fields.push(("loginuid", login_uid.to_string()));
+    }
+    if let Some(uuid) = session.uuid {
+        fields.push(("uuid", json::string(uuid)));
+    }
+    if let Some(cwd) = &session.cwd {
+        fields.push(("submitcwd", json::string(&cwd.to_string_lossy())));
+    }
+    fields.push(("command", json::string(&session.command.to_string_lossy())));
+    let argv = std::iter::once(session.command.to_string_lossy().into_owned())
+        .chain(session.arguments.iter().cloned())
+        .collect::<Vec<_>>();
+    fields.push((
+        "runargv",
+        json::string_array(argv.iter().map(String::as_str)),
+    ));
+    fields.push(("lines", session.lines.to_string()));
+    fields.push(("columns", session.cols.to_string()));
+
+    json::object(&fields).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, fs::OpenOptions, os::unix::fs::PermissionsExt, path::Path};
+
+    use super::*;
+
+    /// Remove a directory that a test created, if it exists.
+    fn remove_test_dir(dir: &Path) {
+        if dir.exists() {
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    fn next_id(seq: &Path, maxseq: u64) -> io::Result<String> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(seq)?;
+        next_session_id(&mut file, maxseq)
+    }
+
+    fn options(dir: &Path) -> IoLogOptions {
+        IoLogOptions {
+            dir: dir.to_owned(),
+            maxseq: MAX_SEQ,
+            compress: false,
+            flush: false,
+            input: false,
+            output: true,
+            // the tests may not be able to give files to another user
+            user: User::real().unwrap().unwrap().name,
+            group: None,
+            mode: 0o600,
+            recipient: None,
+        }
+    }
+
+    fn session<'a>(arguments: &'a [String]) -> Session<'a> {
+        Session {
+            user: "ferris",
+            runas_user: "root",
+            runas_group: "wheel",
+            tty: Some("/dev/pts/3".into()),
+            session_id: 4242,
+            login_uid: Some(1000),
+            uuid: Some("01234567-89ab-4def-8123-456789abcdef"),
+            cwd: Some("/home/ferris".into()),
+            command: Path::new("/usr/bin/vi"),
+            arguments,
+            lines: 24,
+            cols: 80,
+        }
+    }
+
+    fn start() -> Timestamps {
+        Timestamps {
+            realtime: SystemTime::new(1700000000, 0),
+            monotonic: SystemTime::new(5000, 250),
+            boottime: Some(SystemTime::new(5100, 250)),
+        }
+    }
+
+    #[test]
+    fn numbers_sessions() {
+        assert_eq!(to_base36(0), "000000");
+        assert_eq!(to_base36(36 + 10), "00001A");
+        assert_eq!(to_base36(MAX_SEQ - 1), "ZZZZZZ");
+        assert_eq!(session_path("00001A"), Path::new("00/00/1A"));
+
+        let dir = std::env::temp_dir().join(format!("sudo-rs-iolog-seq-{}", std::process::id()));
+        remove_test_dir(&dir);
+        fs::create_dir(&dir).unwrap();
+        let seq = dir.join("seq");
+
+        assert_eq!(next_id(&seq, MAX_SEQ).unwrap(), "000000");
+        assert_eq!(next_id(&seq, MAX_SEQ).unwrap(), "000001");
+        assert_eq!(fs::read_to_string(&seq).unwrap(), "000001\n");
+        assert_eq!(next_id(&seq, 2).unwrap(), "000000");
+
+        fs::write(&seq, "ZZZZZZ\n").unwrap();
+        assert_eq!(next_id(&seq, u64::MAX).unwrap(), "000000");
+        fs::write(&seq, "garbage\n").unwrap();
+        assert_eq!(next_id(&seq, MAX_SEQ).unwrap(), "000000");
+
+        remove_test_dir(&dir);
+    }
+
+    #[test]
+    fn derives_permissions() {
+        let modes = |mode| {
+            let ownership = Ownership::resolve(&IoLogOptions {
+                mode,
+                ..options(Path::new("/var/log/sudo-io"))
+            })
+            .unwrap();
+            (ownership.file_mode, ownership.dir_mode)
+        };
+
+        assert_eq!(modes(0o600), (0o600, 0o700));
+        assert_eq!(modes(0o640), (0o640, 0o750));
+        assert_eq!(modes(0o004), (0o604, 0o705));
+        assert_eq!(modes(0), (0o600, 0o700));
+        assert_eq!(modes(0o4777), (0o666, 0o777));
+    }
+
+    #[test]
+    fn writes_legacy_log() {
+        let arguments = ["-R".to_string(), "/etc/hosts".to_string()];
+        assert_eq!(
+            String::from_utf8(legacy_log(&session(&arguments), &start())).unwrap(),
+            "1700000000:ferris:root:wheel:/dev/pts/3:24:80\n\
+             /home/ferris\n\
+             /usr/bin/vi -R /etc/hosts\n"
+        );
+    }
+
+    #[test]
+    fn writes_json_log() {
+        let arguments = ["-R".to_string(), "say \"hi\"\n".to_string()];
+        assert_eq!(
+            String::from_utf8(json_log(&session(&arguments), &start())).unwrap(),
+            r#"{
+  "timestamp": {"seconds": 1700000000, "nanoseconds": 0},
+  "monotonic": {"seconds": 5000, "nanoseconds": 250},
+  "boottime": {"seconds": 5100, "nanoseconds": 250},
+  "submituser": "ferris",
+  "runuser": "root",
+  "rungroup": "wheel",
+  "ttyname": "/dev/pts/3",
+  "sessionid": 4242,
+  "loginuid": 1000,
+  "uuid": "01234567-89ab-4def-8123-456789abcdef",
+  "submitcwd": "/home/ferris",
+  "command": "/usr/bin/vi",
+  "runargv": ["/usr/bin/vi", "-R", "say \"hi\"\n"],
+  "lines": 24,
+  "columns": 80
+}
+"#
+        );
+    }
+
+    #[test]
+    fn refuses_an_unusable_recipient() {
+        let dir = std::env::temp_dir().join(format!("sudo-rs-iolog-age-{}", std::process::id()));
+        let options = IoLogOptions {
+            recipient: Some("age1notakey".to_string()),
+            ..options(&dir)
+        };
+
+        // the session is not logged in the clear instead
+        assert!(IoLog::create(&options, &session(&[])).is_err());
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn logs_sessions() {
+        let dir = std::env::temp_dir().join(format!("sudo-rs-iolog-{}", std::process::id()));
+        remove_test_dir(&dir);
+        let options = IoLogOptions {
+            mode: 0o640,
+            ..options(&dir)
+        };
+
+        let mut log = IoLog::create(&options, &session(&[])).unwrap();
+        assert_eq!(log.path(), dir.join("00/00/00"));
+        log.log_ttyin(b"secret\n");
+        log.log_ttyout(b"hello ");
+        log.log_stderr(b"oops\n");
+        log.log_ttyout(b"world\n");
+        log.finish().unwrap();
+
+        let path = dir.join("00/00/00");
+        assert!(!path.join("ttyin").exists());
+        assert_eq!(
+            fs::read_to_string(path.join("ttyout")).unwrap(),
+            "hello world\n"
+        );
+        assert_eq!(fs::read_to_string(path.join("stderr")).unwrap(), "oops\n");
+        let timing = fs::read_to_string(path.join("timing")).unwrap();
+        let events: Vec<(&str, &str)> = timing
+            .lines()
+            .map(|line| {
+                let fields: Vec<&str> = line.split(' ').collect();
+                assert_eq!(fields.len(), 3);
+                (fields[0], fields[2])
+            })
+            .collect();
+        assert_eq!(events, [("4", "6"), ("2", "5"), ("4", "6")]);
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&path.join("timing")), 0o440);
+        assert_eq!(mode(&path.join("ttyout")), 0o640);
+        assert_eq!(mode(&dir.join("seq")), 0o640);
+        assert_eq!(mode(&path), 0o750);
+        assert_eq!(mode(&dir), 0o750);
+
+        remove_test_dir(&dir);
+    }
+
+    #[cfg(all(feature = "iolog-compression", feature = "iolog-encryption"))]
+    #[test]
+    fn compresses_before_encrypting() {
+        use flate2::read::GzDecoder;
+
+        let dir = std::env::temp_dir().join(format!("sudo-rs-iolog-gz-age-{}", std::process::id()));
+        remove_test_dir(&dir);
+        let identity = age::x25519::Identity::generate();
+        let options = IoLogOptions {
+            compress: true,
+            recipient: Some(identity.to_public().to_string()),
+            ..options(&dir)
+        };
+
+        let mut log = IoLog::create(&options, &session(&[])).unwrap();
+        log.log_ttyout(b"hello world\n");
+        log.finish().unwrap();
+
+        let encrypted = fs::read(dir.join("00/00/00/ttyout")).unwrap();
+        let compressed = age::decrypt(&identity, &encrypted).unwrap();
+        let mut text = String::new();
+        GzDecoder::new(&compressed[..])
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "hello world\n");
+
+        remove_test_dir(&dir);
+    }
+}
@@ -0,0 +1,96 @@
+//! Writing a stream of input or output to its file in the I/O log, as the files of the log are
+//! laid out by the parent module: compressed by the `compress` module if indicated, and then
+//! encrypted by the `encrypt` module if there is a recipient.
+use std::{
+    fs::File,
+    io::{self, Write},
+};
+
+use super::Recipient;
+
+/// How much output is collected before it is written to the log, unless `iolog_flush` is set.
+const BUFFER_SIZE: usize = 16 * 1024;
+
+/// What a stream is written to: its file, or something that compresses or encrypts what is
+/// written to it before it ends up in that file.
+pub(super) trait Sink: Write {
+    /// The file that is written to in the end.
+    fn file(&self) -> &File;
+
+    /// Write out everything that is still held back, after which nothing can be written anymore.
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+impl Sink for File {
+    fn file(&self) -> &File {
+        self
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A file that a stream is logged to, which may be compressed and encrypted.
+///
+/// This does not write anything when it is dropped, as the monitor process has a copy of it.
+pub(super) struct Stream {
+    sink: Box<dyn Sink>,
+    pending: Vec<u8>,
+    flush: bool,
+}
+
+impl Stream {
+    pub(super) fn new(
+        file: File,
+        compress: bool,
+        flush: bool,
+        recipient: Option<&Recipient>,
+    ) -> io::Result<Self> {
+        let sink: Box<dyn Sink> = match recipient {
+            Some(recipient) => recipient.sink(file)?,
+            None => Box::new(file),
+        };
+        let sink: Box<dyn Sink> = match compress {
+            #[cfg(feature = "iolog-compression")]
+            true => Box::new(super::compress::GzipSink::new(sink)),
+            _ => sink,
+        };
+
+        Ok(Self {
+            sink,
+            pending: Vec::new(),
+            flush,
+        })
+    }
+
+    /// The file that the stream is logged to.
+    pub(super) fn file(&self) -> &File {
+        self.sink.file()
+    }
+
+    pub(super) fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.pending.extend_from_slice(data);
+        if self.flush || self.pending.len() >= BUFFER_SIZE {
+            self.write_pending()?;
+        }
+
+        Ok(())
+    }
+
+    fn write_pending(&mut self) -> io::Result<()> {
+        self.sink.write_all(&self.pending)?;
+        self.pending.clear();
+        // everything that was logged can be read from the file right away
+        if self.flush {
+            self.sink.flush()?;
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn finish(&mut self) -> io::Result<()> {
+        self.write_pending()?;
+        self.sink.finish()
+    }
+}
@@ -4,10 +4,15 @@ pub(crate) mod cli;
 pub(crate) mod common;
 pub(crate) mod cutils;
 pub(crate) mod defaults;
+pub(crate) mod doas;
 pub(crate) mod env;
 pub(crate) mod exec;
+pub(crate) mod iolog;
 pub(crate) mod log;
 pub(crate) mod pam;
+pub(crate) mod plugin;
+#[cfg(feature = "policy-api")]
+pub mod policy;
 pub(crate) mod sudoers;
 pub(crate) mod system;
 
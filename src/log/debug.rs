@@ -0,0 +1,209 @@
+//! Debug output as configured by a `Debug` line in sudo.conf, e.g.
+//!
+//! ```text
+//! Debug sudo /var/log/sudo_debug all@info,exec@debug
+//! ```
+//!
+//! The flags are a comma separated list of `subsystem@priority` pairs, using the subsystem and
+//! priority names of ogsudo, so the same line can be used for both.
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::system::secure_open_for_append;
+
+/// The subsystems debug output can be selected for, along with the modules they cover; the first
+/// matching module prefix decides the subsystem, and everything else belongs to `main`.
+const SUBSYSTEMS: &[(&str, &str)] = &[
+    ("args", "cli"),
+    ("conv", "pam"),
+    ("defaults", "defaults"),
+    ("env", "env"),
+    ("pty", "exec::use_pty"),
+    ("exec", "exec"),
+    ("parser", "sudoers"),
+    ("util", "system"),
+    ("util", "cutils"),
+];
+
+const MAIN: &str = "main";
+
+/// Translate a priority of ogsudo to a log level; ogsudo considers `debug` to be more verbose
+/// than `trace`, so these are swapped.
+fn parse_priority(priority: &str) -> Option<LevelFilter> {
+    Some(match priority {
+        "crit" | "err" => LevelFilter::Error,
+        "warn" => LevelFilter::Warn,
+        "notice" | "diag" | "info" => LevelFilter::Info,
+        "trace" => LevelFilter::Debug,
+        "debug" => LevelFilter::Trace,
+        _ => return None,
+    })
+}
+
+/// The subsystem that a module of this crate belongs to.
+fn subsystem(module_path: &str) -> &'static str {
+    let module = module_path
+        .split_once("::")
+        .map_or("", |(_crate_name, module)| module);
+
+    SUBSYSTEMS
+        .iter()
+        .find(|(_, prefix)| {
+            module
+                .strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+        })
+        .map_or(MAIN, |(name, _)| name)
+}
+
+/// The maximum level of debug output for every subsystem.
+#[derive(Debug, PartialEq, Eq)]
+struct DebugFlags(Vec<(&'static str, LevelFilter)>);
+
+impl DebugFlags {
+    fn parse(flags: &str) -> Result<Self, String> {
+        let mut levels = Vec::new();
+
+        for flag in flags.split(',') {
+            let Some((name, priority)) = flag.split_once('@') else {
+                return Err(format!("invalid debug flag: {flag}"));
+            };
+            let level =
+                parse_priority(priority).ok_or_else(|| format!("invalid priority: {priority}"))?;
+
+            if name == "all" {
+                levels.clear();
+                levels.push((MAIN, level));
+                levels.extend(SUBSYSTEMS.iter().map(|(name, _)| (*name, level)));
+            } else if let Some(name) = SUBSYSTEMS
+                .iter()
+                .map(|(name, _)| *name)
+                .chain([MAIN])
+                .find(|known| *known == name)
+            {
+                levels.retain(|(existing, _)| *existing != name);
+                levels.push((name, level));
+            }
+            // subsystems of ogsudo that we do not have are ignored
+        }
+
+        Ok(Self(levels))
+    }
+
+    fn level(&self, subsystem: &str) -> LevelFilter {
+        self.0
+            .iter()
+            .find(|(name, _)| *name == subsystem)
+            .map_or(LevelFilter::Off, |(_, level)| *level)
+    }
+}
+
+/// Writes debug output of the selected subsystems to a file.
+pub struct DebugLogger {
+    program: &'static str,
+    flags: DebugFlags,
+    file: Mutex<File>,
+}
+
+impl DebugLogger {
+    pub fn open(program: &'static str, path: &Path, flags: &str) -> Result<Self, String> {
+        let flags = DebugFlags::parse(flags)?;
+        let file = secure_open_for_append(path, 0o600)
+            .map_err(|err| format!("unable to open {}: {err}", path.display()))?;
+
+        Ok(Self {
+            program,
+            flags,
+            file: Mutex::new(file),
+        })
+    }
+
+    fn write(&self, record: &Record) -> io::Result<()> {
+        let line = format!(
+            "{}[{}] {} {}\n",
+            self.program,
+            std::process::id(),
+            level_name(record.level()),
+            record.args()
+        );
+
+        self.file
+            .lock()
+            .map_err(|_| io::Error::from(io::ErrorKind::Other))?
+            .write_all(line.as_bytes())
+    }
+}
+
+fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::Error => "err",
+        Level::Warn => "warn",
+        Level::Info => "info",
+        Level::Debug => "trace",
+        Level::Trace => "debug",
+    }
+}
+
+impl Log for DebugLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // the module of a record is not part of its metadata, so this is an upper bound
+        metadata.level()
+            <= self
+                .flags
+                .0
+                .iter()
+                .map(|(_, level)| *level)
+                .max()
+                .unwrap_or(LevelFilter::Off)
+    }
+
+    fn log(&self, record: &Record) {
+        let subsystem = subsystem(record.module_path().unwrap_or_default());
+        if record.level() <= self.flags.level(subsystem) {
+            // there is nowhere to report a failure to write debug output
+            let _ = self.write(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use log::LevelFilter;
+
+    use super::{subsystem, DebugFlags};
+
+    #[test]
+    fn maps_modules_to_subsystems() {
+        assert_eq!(subsystem("sudo_rs::exec::use_pty::monitor"), "pty");
+        assert_eq!(subsystem("sudo_rs::exec::no_pty"), "exec");
+        assert_eq!(subsystem("sudo_rs::sudoers::policy"), "parser");
+        assert_eq!(subsystem("sudo_rs::envy"), "main");
+        assert_eq!(subsystem("sudo_rs::sudo::pipeline"), "main");
+    }
+
+    #[test]
+    fn parses_flags() {
+        let flags = DebugFlags::parse("all@info,exec@debug,plugin@warn,pty@err").unwrap();
+
+        assert_eq!(flags.level("main"), LevelFilter::Info);
+        assert_eq!(flags.level("exec"), LevelFilter::Trace);
+        assert_eq!(flags.level("pty"), LevelFilter::Error);
+
+        let flags = DebugFlags::parse("exec@trace").unwrap();
+        assert_eq!(flags.level("exec"), LevelFilter::Debug);
+        assert_eq!(flags.level("main"), LevelFilter::Off);
+
+        assert!(DebugFlags::parse("all").is_err());
+        assert!(DebugFlags::parse("all@loud").is_err());
+    }
+}
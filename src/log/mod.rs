@@ -1,11 +1,19 @@
 #![allow(unused_macros)]
 use self::simple_logger::SimpleLogger;
 use self::syslog::Syslog;
+pub use debug::DebugLogger;
 pub use log::Level;
+pub use remote::{send_syslog_to, SyslogProtocol, SyslogServer};
 use std::ops::Deref;
+use std::sync::OnceLock;
+pub use syslog::fold_repeated_messages;
 
+mod debug;
+mod remote;
 mod simple_logger;
 mod syslog;
+#[cfg(feature = "syslog-tls")]
+mod tls;
 
 // TODO: logger_macro has an allow_unused that should be removed
 macro_rules! logger_macro {
@@ -35,19 +43,19 @@ logger_macro!(user_debug is Debug to "sudo::user");
 logger_macro!(user_trace is Trace to "sudo::user");
 
 // TODO: dev_logger_macro has an allow_unused that should be removed
+// these are always compiled in; whether they end up anywhere is decided at runtime, by the
+// `dev` feature or by a `Debug` line in sudo.conf
 macro_rules! dev_logger_macro {
     ($name:ident is $rule_level:ident to $target:expr, $d:tt) => {
         macro_rules! $name {
             ($d($d arg:tt)+) => {
-                if std::cfg!(feature = "dev") {
-                    (::log::log!(
-                        target: $target,
-                        $crate::log::Level::$rule_level,
-                        "{}: {}",
-                        std::panic::Location::caller(),
-                        format_args!($d($d arg)+)
-                    ));
-                }
+                (::log::log!(
+                    target: $target,
+                    $crate::log::Level::$rule_level,
+                    "{}: {}",
+                    std::panic::Location::caller(),
+                    format_args!($d($d arg)+)
+                ))
             };
         }
 
@@ -65,12 +73,23 @@ dev_logger_macro!(dev_info is Info to "sudo::dev");
 dev_logger_macro!(dev_debug is Debug to "sudo::dev");
 dev_logger_macro!(dev_trace is Trace to "sudo::dev");
 
+/// The prefix of the messages for the user, as passed to [SudoLogger::new].
+static USER_PREFIX: OnceLock<&'static str> = OnceLock::new();
+
+/// Format a message for the user like `user_error!` would show it, so that it can be written out
+/// later without formatting it, e.g. by a child process after `fork`.
+pub fn user_message(args: std::fmt::Arguments) -> Vec<u8> {
+    let prefix = USER_PREFIX.get().copied().unwrap_or_default();
+    format!("{prefix}{args}\n").into_bytes()
+}
+
 #[derive(Default)]
 pub struct SudoLogger(Vec<(String, Box<dyn log::Log>)>);
 
 impl SudoLogger {
     pub fn new(prefix: &'static str) -> Self {
         let mut logger: Self = Default::default();
+        let _ = USER_PREFIX.set(prefix);
 
         logger.add_logger("sudo::auth", Syslog);
 
@@ -89,6 +108,11 @@ impl SudoLogger {
         logger
     }
 
+    /// Also send all messages of sudo to `logger`, which selects what it writes out itself.
+    pub fn add_debug_logger(&mut self, logger: DebugLogger) {
+        self.add_logger("sudo", logger)
+    }
+
     pub fn into_global_logger(self) {
         log::set_boxed_logger(Box::new(self))
             .map(|()| log::set_max_level(log::LevelFilter::Trace))
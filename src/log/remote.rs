@@ -0,0 +1,254 @@
+//! Sending the system log to a syslog collector on another host with `Defaults syslog_server`,
+//! for systems without a syslog daemon of their own, such as minimal containers.
+//!
+//! Messages are formatted as described in RFC 5424 and sent over UDP, or over TCP with the
+//! octet counting framing of RFC 6587. With the `syslog-tls` feature, they can also be sent over
+//! TLS as RFC 5425 describes, with the same framing; the certificate of the collector has to be
+//! issued for its host name by one of the authorities in `syslog_cabundle`, or else in the
+//! certificate bundle of the system. The collector is connected to when the first message is
+//! logged; when it cannot be reached, that message and all later ones go to the local system
+//! log instead.
+use std::io::{self, Write};
+use std::mem;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::system::hostname;
+use crate::system::time::{SystemTime, Timestamps};
+
+/// How long connecting to, or sending a message to, the collector may take.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyslogProtocol {
+    Udp,
+    Tcp,
+    Tls,
+}
+
+/// A syslog collector, see [send_syslog_to].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyslogServer {
+    pub host: String,
+    pub port: u16,
+    pub protocol: SyslogProtocol,
+    /// The certificate authorities that the certificate of the collector is checked against
+    /// over TLS, instead of those of the system (`syslog_cabundle`).
+    pub cabundle: Option<PathBuf>,
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    #[cfg(feature = "syslog-tls")]
+    Tls(Box<super::tls::TlsStream>),
+}
+
+/// The collector that messages are sent to; set by [send_syslog_to].
+static SERVER: OnceLock<SyslogServer> = OnceLock::new();
+
+/// The connection to the collector, once it has been tried; `None` if it could not be reached or
+/// a message could not be sent.
+static CONNECTION: OnceLock<Mutex<Option<Connection>>> = OnceLock::new();
+
+/// From now on, send the messages for the system log to `server`.
+pub fn send_syslog_to(server: SyslogServer) {
+    let _ = SERVER.set(server);
+}
+
+/// Send `message` to the collector, if there is one; returns whether it was sent.
+pub(super) fn send(priority: libc::c_int, message: &str) -> bool {
+    let Some(server) = SERVER.get() else {
+        return false;
+    };
+    let mut connection = CONNECTION
+        .get_or_init(|| Mutex::new(connect(server).ok()))
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+    let Some(active) = connection.as_mut() else {
+        return false;
+    };
+
+    let record = format_record(
+        priority,
+        Timestamps::now().ok().map(|now| now.realtime),
+        &hostname(),
+        std::process::id(),
+        message,
+    );
+    if transmit(active, &record).is_err() {
+        *connection = None;
+        return false;
+    }
+
+    true
+}
+
+fn connect(server: &SyslogServer) -> io::Result<Connection> {
+    let mut last_error = io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{}: no address found", server.host),
+    );
+    for address in (server.host.as_str(), server.port).to_socket_addrs()? {
+        match connect_to(address, server) {
+            Ok(connection) => return Ok(connection),
+            Err(err) => last_error = err,
+        }
+    }
+
+    Err(last_error)
+}
+
+fn connect_to(address: SocketAddr, server: &SyslogServer) -> io::Result<Connection> {
+    match server.protocol {
+        SyslogProtocol::Udp => {
+            let local = if address.is_ipv4() {
+                "0.0.0.0:0"
+            } else {
+                "[::]:0"
+            };
+            let socket = UdpSocket::bind(local)?;
+            socket.connect(address)?;
+            Ok(Connection::Udp(socket))
+        }
+        SyslogProtocol::Tcp => {
+            let stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+            stream.set_write_timeout(Some(TIMEOUT))?;
+            Ok(Connection::Tcp(stream))
+        }
+        #[cfg(feature = "syslog-tls")]
+        SyslogProtocol::Tls => {
+            let stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+            // the handshake reads from the collector as well
+            stream.set_read_timeout(Some(TIMEOUT))?;
+            stream.set_write_timeout(Some(TIMEOUT))?;
+            Ok(Connection::Tls(Box::new(super::tls::connect(
+                stream, server,
+            )?)))
+        }
+        #[cfg(not(feature = "syslog-tls"))]
+        SyslogProtocol::Tls => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "sudo was built without support for TLS",
+        )),
+    }
+}
+
+/// Send a record: a datagram of its own over UDP, or preceded by its length over TCP.
+fn transmit(connection: &mut Connection, record: &str) -> io::Result<()> {
+    match connection {
+        Connection::Udp(socket) => socket.send(record.as_bytes()).map(|_| ()),
+        Connection::Tcp(stream) => {
+            stream.write_all(format!("{} {record}", record.len()).as_bytes())
+        }
+        #[cfg(feature = "syslog-tls")]
+        Connection::Tls(stream) => {
+            super::tls::send(stream, format!("{} {record}", record.len()).as_bytes())
+        }
+    }
+}
+
+/// A syslog record for the auth facility; fields that are not known are left out with `-`.
+fn format_record(
+    priority: libc::c_int,
+    now: Option<SystemTime>,
+    hostname: &str,
+    pid: u32,
+    message: &str,
+) -> String {
+    let timestamp = now.and_then(timestamp);
+    let timestamp = timestamp.as_deref().unwrap_or("-");
+    let hostname = if hostname.is_empty() { "-" } else { hostname };
+
+    format!(
+        "<{}>1 {timestamp} {hostname} sudo {pid} - - {message}",
+        priority | libc::LOG_AUTH
+    )
+}
+
+/// `now` in UTC, with microseconds, e.g. `2003-10-11T22:14:15.003000Z`.
+fn timestamp(now: SystemTime) -> Option<String> {
+    let seconds = now.seconds() as libc::time_t;
+    // SAFETY: an all-zero `tm` is valid, it only holds numbers and a null pointer
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    // SAFETY: both pointers are to values that live through the call
+    if unsafe { libc::gmtime_r(&seconds, &mut tm) }.is_null() {
+        return None;
+    }
+
+    Some(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec,
+        now.nanoseconds() / 1000
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn formats_records() {
+        let now = SystemTime::new(1065910455, 3_000_999);
+        assert_eq!(
+            format_record(libc::LOG_WARNING, Some(now), "host", 42, "hello"),
+            "<36>1 2003-10-11T22:14:15.003000Z host sudo 42 - - hello"
+        );
+        assert_eq!(
+            format_record(libc::LOG_INFO, None, "", 42, "hello"),
+            "<38>1 - - sudo 42 - - hello"
+        );
+    }
+
+    #[test]
+    fn sends_over_udp() {
+        let collector = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server = SyslogServer {
+            host: "127.0.0.1".to_string(),
+            port: collector.local_addr().unwrap().port(),
+            protocol: SyslogProtocol::Udp,
+            cabundle: None,
+        };
+
+        let mut connection = connect(&server).unwrap();
+        transmit(&mut connection, "<38>1 - - sudo 42 - - hello").unwrap();
+
+        let mut buf = [0; 64];
+        let len = collector.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"<38>1 - - sudo 42 - - hello");
+    }
+
+    #[test]
+    fn sends_over_tcp() {
+        let collector = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = SyslogServer {
+            host: "127.0.0.1".to_string(),
+            port: collector.local_addr().unwrap().port(),
+            protocol: SyslogProtocol::Tcp,
+            cabundle: None,
+        };
+
+        let mut connection = connect(&server).unwrap();
+        transmit(&mut connection, "<38>1 - - sudo 42 - - hello").unwrap();
+        transmit(&mut connection, "<38>1 - - sudo 42 - - bye").unwrap();
+        drop(connection);
+
+        let mut received = String::new();
+        let (mut stream, _) = collector.accept().unwrap();
+        stream.read_to_string(&mut received).unwrap();
+        assert_eq!(
+            received,
+            "27 <38>1 - - sudo 42 - - hello25 <38>1 - - sudo 42 - - bye"
+        );
+    }
+}
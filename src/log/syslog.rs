@@ -1,9 +1,94 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, Write};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+
 use log::{Level, Log, Metadata};
 
-use crate::system::syslog;
+use crate::system::file::FileLock;
+use crate::system::time::SystemTime;
+use crate::system::{secure_open_cookie_file, syslog};
 
 pub struct Syslog;
 
+/// How long (in seconds) a message that is logged again and again is folded into a count, and the
+/// user whose messages are folded; set by [fold_repeated_messages].
+static FOLDING: OnceLock<(i64, String)> = OnceLock::new();
+
+/// From now on, fold messages that are the same as the previous message that was logged for
+/// `user` within `window` into a "last message repeated N times" message, so that a
+/// script that runs sudo in a loop does not flood the system log. The previous message is kept
+/// in a file, as every message is logged by another invocation of sudo.
+pub fn fold_repeated_messages(window: Duration, user: &str) {
+    if let Ok(window @ 1..) = i64::try_from(window.as_secs()) {
+        let _ = FOLDING.set((window, user.to_string()));
+    }
+}
+
+/// What to do with a message that could be a repetition of the previous message.
+#[derive(Debug, PartialEq, Eq)]
+enum Folded {
+    /// The message is the same as the previous one, and is only counted.
+    Repeated,
+    /// The message is logged, after the number of times the previous message was repeated.
+    Log { repeats: u64 },
+}
+
+/// Compare `message` with the previous message in `file`, which is first logged at the time on
+/// its first line, followed by the number of repetitions since.
+fn fold(file: &mut File, message: &str, now: SystemTime, window: i64) -> io::Result<Folded> {
+    let _lock = FileLock::exclusive(file, false)?;
+    let mut contents = String::new();
+    file.rewind()?;
+    file.read_to_string(&mut contents)?;
+
+    let previous = contents.split_once('\n').and_then(|(header, previous)| {
+        let (since, repeats) = header.split_once(' ')?;
+        Some((
+            since.parse::<i64>().ok()?,
+            repeats.parse::<u64>().ok()?,
+            previous,
+        ))
+    });
+    let (folded, since, repeats) = match previous {
+        Some((since, repeats, previous))
+            if previous == message && now.seconds() - since < window =>
+        {
+            (Folded::Repeated, since, repeats + 1)
+        }
+        Some((_, repeats, _)) => (Folded::Log { repeats }, now.seconds(), 0),
+        None => (Folded::Log { repeats: 0 }, now.seconds(), 0),
+    };
+
+    file.set_len(0)?;
+    file.rewind()?;
+    write!(file, "{since} {repeats}\n{message}")?;
+
+    Ok(folded)
+}
+
+/// Whether `message` is to be logged, and how often the previous message was repeated before it.
+fn fold_message(priority: libc::c_int, message: &str) -> Folded {
+    let Some((window, user)) = FOLDING.get() else {
+        return Folded::Log { repeats: 0 };
+    };
+    let path = PathBuf::from_iter(["/run/sudo-rs/log", user]);
+    let message = format!("{priority} {message}");
+
+    // if the previous message cannot be read, nothing is lost by logging this one
+    SystemTime::now()
+        .and_then(|now| fold(&mut secure_open_cookie_file(&path)?, &message, now, *window))
+        .unwrap_or(Folded::Log { repeats: 0 })
+}
+
+/// Send `message` to the syslog collector if there is one, or else to the local system log.
+fn write_message(priority: libc::c_int, message: &str) {
+    if !super::remote::send(priority, message) {
+        syslog(priority, libc::LOG_AUTH, message);
+    }
+}
+
 impl Log for Syslog {
     fn enabled(&self, metadata: &Metadata) -> bool {
         metadata.level() <= log::max_level() && metadata.level() <= log::STATIC_MAX_LEVEL
@@ -19,13 +104,21 @@ impl Log for Syslog {
         };
 
         let mut message = format!("{}", record.args());
+        match fold_message(priority, &message) {
+            Folded::Repeated => return,
+            Folded::Log { repeats: 0 } => {}
+            Folded::Log { repeats } => {
+                write_message(priority, &format!("last message repeated {repeats} times"))
+            }
+        }
+
         let mut message_len = message.bytes().len();
 
         let mut end: usize = 960;
         let mut start: usize = 0;
 
         if message_len <= 960 {
-            syslog(priority, libc::LOG_AUTH, &message);
+            write_message(priority, &message);
             return;
         }
 
@@ -56,7 +149,7 @@ impl Log for Syslog {
                 end += 6;
             }
 
-            syslog(priority, libc::LOG_AUTH, &message[start..end]);
+            write_message(priority, &message[start..end]);
 
             start = end;
             end += 960;
@@ -70,8 +163,9 @@ impl Log for Syslog {
 
 #[cfg(test)]
 mod tests {
-    use super::Syslog;
-    use log::Log;
+    use std::fs::{self, OpenOptions};
+
+    use super::*;
 
     #[test]
     fn can_write_to_syslog() {
@@ -84,6 +178,32 @@ mod tests {
         logger.log(&record);
     }
 
+    #[test]
+    fn folds_repeated_messages() {
+        let path = std::env::temp_dir().join(format!("sudo-rs-syslog-{}", std::process::id()));
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        let at = |seconds| SystemTime::new(seconds, 0);
+        let mut fold = |message, now| fold(&mut file, message, at(now), 60).unwrap();
+
+        assert_eq!(fold("ls", 100), Folded::Log { repeats: 0 });
+        assert_eq!(fold("ls", 110), Folded::Repeated);
+        assert_eq!(fold("ls", 159), Folded::Repeated);
+        // the repeats are reported along with the next message that is logged
+        assert_eq!(fold("cat", 160), Folded::Log { repeats: 2 });
+        assert_eq!(fold("cat", 161), Folded::Repeated);
+        // which includes the same message, after the window has passed
+        assert_eq!(fold("cat", 220), Folded::Log { repeats: 1 });
+        assert_eq!(fold("ls", 221), Folded::Log { repeats: 0 });
+
+        fs::remove_file(&path).unwrap();
+    }
+
     #[test]
     fn can_truncate_syslog() {
         let logger = Syslog;
@@ -1,3 +1,7 @@
+use std::path::PathBuf;
+
+use crate::common::i18n::gettext;
+use crate::common::messages::{fill, MESSAGES};
 use crate::cutils::string_from_ptr;
 
 use super::sys::*;
@@ -126,48 +130,85 @@ where
 pub struct CLIConverser {
     pub(super) name: String,
     pub(super) use_stdin: bool,
+    pub(super) use_askpass: bool,
+    /// The askpass helper program, if one is configured.
+    pub(super) askpass: Option<PathBuf>,
+    /// The password prompt to show instead of that of PAM.
+    pub(super) prompt: Option<String>,
     pub(super) no_interact: bool,
 }
 
-use rpassword::Terminal;
+use rpassword::{PromptTarget, Terminal};
 
 impl CLIConverser {
+    fn target(&self) -> std::io::Result<PromptTarget> {
+        PromptTarget::choose(
+            self.use_stdin,
+            self.use_askpass,
+            self.askpass.as_deref(),
+            Terminal::tty_present(),
+            std::env::var_os("DISPLAY").is_some(),
+        )
+    }
+
+    /// Where to show messages; those of PAM are shown on standard error with the askpass helper.
     fn open(&self) -> std::io::Result<Terminal> {
-        if self.use_stdin {
-            Terminal::open_stdie()
-        } else {
-            Terminal::open_tty()
+        match self.target()? {
+            PromptTarget::Tty => Terminal::open_tty(),
+            PromptTarget::Stdin | PromptTarget::Askpass(_) => Terminal::open_stdie(),
         }
     }
-}
 
-impl SequentialConverser for CLIConverser {
-    fn handle_normal_prompt(&self, msg: &str) -> PamResult<PamBuffer> {
+    /// Show `prompt` and read the answer, which is hidden if it is `secret`.
+    fn ask(&self, prompt: &str, secret: bool) -> PamResult<PamBuffer> {
         if self.no_interact {
             return Err(PamError::InteractionRequired);
         }
+        if let PromptTarget::Askpass(path) = self.target()? {
+            return Ok(rpassword::askpass(&path, prompt)?);
+        }
+
         let mut tty = self.open()?;
-        tty.prompt(&format!("[{}: input needed] {msg} ", self.name))?;
-        Ok(tty.read_cleartext()?)
+        let _cooked = tty.cooked();
+        tty.prompt(prompt)?;
+        if secret {
+            Ok(tty.read_password()?)
+        } else {
+            Ok(tty.read_cleartext()?)
+        }
+    }
+}
+
+impl SequentialConverser for CLIConverser {
+    fn handle_normal_prompt(&self, msg: &str) -> PamResult<PamBuffer> {
+        self.ask(
+            &fill(gettext(MESSAGES.input_prompt), &self.name, msg),
+            false,
+        )
     }
 
     fn handle_hidden_prompt(&self, msg: &str) -> PamResult<PamBuffer> {
-        if self.no_interact {
-            return Err(PamError::InteractionRequired);
+        match &self.prompt {
+            // like ogsudo, only the usual prompt for a password is replaced, not e.g. that of
+            // a one-time password
+            Some(prompt) if msg.trim_end() == "Password:" => self.ask(prompt, true),
+            _ => self.ask(
+                &fill(gettext(MESSAGES.password_prompt), &self.name, msg),
+                true,
+            ),
         }
-        let mut tty = self.open()?;
-        tty.prompt(&format!("[{}: authenticate] {msg}", self.name))?;
-        Ok(tty.read_password()?)
     }
 
     fn handle_error(&self, msg: &str) -> PamResult<()> {
         let mut tty = self.open()?;
-        Ok(tty.prompt(&format!("[{} error] {msg}\n", self.name))?)
+        let _cooked = tty.cooked();
+        Ok(tty.prompt(&fill(gettext(MESSAGES.error), &self.name, msg))?)
     }
 
     fn handle_info(&self, msg: &str) -> PamResult<()> {
         let mut tty = self.open()?;
-        Ok(tty.prompt(&format!("[{}] {msg}\n", self.name))?)
+        let _cooked = tty.cooked();
+        Ok(tty.prompt(&fill(gettext(MESSAGES.info), &self.name, msg))?)
     }
 }
 
@@ -175,6 +216,8 @@ impl SequentialConverser for CLIConverser {
 pub(super) struct ConverserData<C> {
     pub(super) converser: C,
     pub(super) panicked: bool,
+    /// Why the last conversation failed, which PAM only reports as a conversation error.
+    pub(super) error: Option<PamError>,
 }
 
 /// This function implements the conversation function of `pam_conv`.
@@ -222,11 +265,8 @@ pub(super) unsafe extern "C" fn converse<C: Converser>(
 
         // send the conversation of to the Rust part
         let app_data = unsafe { &mut *(appdata_ptr as *mut ConverserData<C>) };
-        if app_data
-            .converser
-            .handle_conversation(&mut conversation)
-            .is_err()
-        {
+        if let Err(err) = app_data.converser.handle_conversation(&mut conversation) {
+            app_data.error = Some(err);
             return PamErrorType::ConversationError;
         }
 
@@ -395,6 +435,7 @@ mod test {
         let mut hello = Box::pin(ConverserData {
             converser: "tux".to_string(),
             panicked: false,
+            error: None,
         });
         let cookie = PamConvBorrow::new(hello.as_mut());
         let pam_conv = cookie.borrow();
@@ -3,6 +3,7 @@ use std::{
     ffi::{CStr, CString, OsStr, OsString},
     os::raw::c_char,
     os::unix::prelude::OsStrExt,
+    path::PathBuf,
     ptr::NonNull,
 };
 
@@ -21,6 +22,8 @@ mod securemem;
 pub mod sys;
 
 pub use converse::{CLIConverser, Converser};
+#[cfg(feature = "io-plugins")]
+pub(crate) use rpassword::Terminal;
 
 pub struct PamContext<C: Converser> {
     data_ptr: *mut ConverserData<C>,
@@ -55,6 +58,7 @@ impl<C: Converser> PamContextBuilder<C> {
             let data_ptr = Box::into_raw(Box::new(ConverserData {
                 converser,
                 panicked: false,
+                error: None,
             }));
 
             let mut pamh = std::ptr::null_mut();
@@ -162,7 +166,11 @@ impl<C: Converser> PamContext<C> {
         flags |= self.silent_flag();
         flags |= self.disallow_null_auth_token_flag();
 
-        pam_err(unsafe { pam_authenticate(self.pamh, flags) })?;
+        self.take_conversation_error();
+        if let Err(err) = pam_err(unsafe { pam_authenticate(self.pamh, flags) }) {
+            // why the conversation failed, if it did, says more than that authentication did
+            return Err(self.take_conversation_error().unwrap_or(err));
+        }
 
         if self.has_panicked() {
             panic!("Panic during pam authentication");
@@ -329,6 +337,11 @@ impl<C: Converser> PamContext<C> {
         Ok(res)
     }
 
+    /// Why the last conversation failed, if it did.
+    fn take_conversation_error(&mut self) -> Option<PamError> {
+        unsafe { (*self.data_ptr).error.take() }
+    }
+
     /// Check if anything panicked since the last call.
     pub fn has_panicked(&self) -> bool {
         unsafe { (*self.data_ptr).panicked }
@@ -345,11 +358,34 @@ impl PamContext<CLIConverser> {
         PamContextBuilder::default().converser(CLIConverser {
             name: name.to_owned(),
             use_stdin,
+            use_askpass: false,
+            askpass: None,
+            prompt: None,
             no_interact,
         })
     }
 }
 
+impl PamContextBuilder<CLIConverser> {
+    /// Prompt with the helper program `askpass` when there is no terminal, or always if
+    /// `use_askpass` is set.
+    pub fn askpass(mut self, askpass: Option<PathBuf>, use_askpass: bool) -> Self {
+        if let Some(converser) = &mut self.converser {
+            converser.askpass = askpass;
+            converser.use_askpass = use_askpass;
+        }
+        self
+    }
+
+    /// Show `prompt` instead of the usual password prompt of PAM.
+    pub fn prompt(mut self, prompt: Option<String>) -> Self {
+        if let Some(converser) = &mut self.converser {
+            converser.prompt = prompt;
+        }
+        self
+    }
+}
+
 impl<C: Converser> Drop for PamContext<C> {
     fn drop(&mut self) {
         // data_ptr's pointee is de-allocated in this scope
@@ -10,32 +10,44 @@
 /// - the "HiddenInput" struct and implementation, with changes:
 ///   * replaced occurences of explicit 'i32' and 'c_int' with RawFd
 ///   * open the TTY ourselves to mitigate Linux CVE-2023-2002
+///   * hide the input on the terminal that it is read from
 /// - the general idea of a "SafeString" type that clears its memory
 ///   (although much more robust than in the original code)
 ///
 use std::io::{self, Error, ErrorKind, Read};
-use std::os::fd::{AsRawFd, RawFd};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 use std::{fs, mem};
 
-use libc::{tcsetattr, termios, ECHO, ECHONL, TCSANOW};
+use libc::{
+    tcsetattr, termios, ECHO, ECHOE, ECHOK, ECHONL, ICANON, ICRNL, IEXTEN, ONLCR, OPOST, TCSADRAIN,
+    TCSANOW,
+};
 
 use crate::cutils::cerr;
+use crate::log::auth_warn;
+use crate::system::{term::verify_tty, User};
 
 use super::securemem::PamBuffer;
 
 pub struct HiddenInput {
-    tty: fs::File,
+    tty: OwnedFd,
     term_orig: termios,
 }
 
 impl HiddenInput {
-    fn new() -> io::Result<Option<HiddenInput>> {
-        // control ourselves that we are really talking to a TTY
-        // mitigates: https://marc.info/?l=oss-security&m=168164424404224
-        let Ok(tty) = fs::File::open("/dev/tty") else {
+    /// Stop echoing what is typed on `input` until this is dropped, if it is a terminal.
+    fn new(input: BorrowedFd) -> io::Result<Option<HiddenInput>> {
+        // SAFETY: `isatty` only looks at the file descriptor
+        if unsafe { libc::isatty(input.as_raw_fd()) } == 0 {
             // if we have nothing to show, we have nothing to hide
             return Ok(None);
-        };
+        }
+        // the input is read from this terminal, so it also has to be hidden there, and not on
+        // e.g. `/dev/tty` if standard input is another terminal
+        let tty = input.try_clone_to_owned()?;
         let fd = tty.as_raw_fd();
 
         // Make two copies of the terminal settings. The first one will be modified
@@ -66,6 +78,54 @@ impl Drop for HiddenInput {
     }
 }
 
+/// Takes a terminal out of raw mode until this is dropped, so that lines are written and read the
+/// usual way: with `use_pty`, the user's terminal is in raw mode while the command runs, in which
+/// the messages of a conversation, like the instructions of a one-time password module that span
+/// several lines, would not start at the beginning of a line, and what is typed would not show.
+pub struct CookedMode {
+    tty: OwnedFd,
+    term_orig: termios,
+}
+
+impl CookedMode {
+    /// Put `tty` in cooked mode if it is a terminal that is not; if its mode cannot be changed, it
+    /// is used as it is.
+    fn new(tty: BorrowedFd) -> Option<CookedMode> {
+        // SAFETY: `isatty` only looks at the file descriptor
+        if unsafe { libc::isatty(tty.as_raw_fd()) } == 0 {
+            return None;
+        }
+
+        let term_orig = safe_tcgetattr(tty.as_raw_fd()).ok()?;
+        let mut term = term_orig;
+        // undo what `cfmakeraw` does to the input, output and line handling, except for signals,
+        // which sudo relays to the command whichever mode the terminal is in
+        term.c_iflag |= ICRNL;
+        term.c_oflag |= OPOST | ONLCR;
+        term.c_lflag |= ICANON | ECHO | ECHOE | ECHOK | IEXTEN;
+        if (term.c_iflag, term.c_oflag, term.c_lflag)
+            == (term_orig.c_iflag, term_orig.c_oflag, term_orig.c_lflag)
+        {
+            return None;
+        }
+
+        let tty = tty.try_clone_to_owned().ok()?;
+        // SAFETY: `term` is a valid termios structure, taken from this terminal
+        cerr(unsafe { tcsetattr(tty.as_raw_fd(), TCSADRAIN, &term) }).ok()?;
+
+        Some(CookedMode { tty, term_orig })
+    }
+}
+
+impl Drop for CookedMode {
+    fn drop(&mut self) {
+        // what was written is shown before the terminal goes back to raw mode
+        unsafe {
+            tcsetattr(self.tty.as_raw_fd(), TCSADRAIN, &self.term_orig);
+        }
+    }
+}
+
 fn safe_tcgetattr(fd: RawFd) -> io::Result<termios> {
     let mut term = mem::MaybeUninit::<termios>::uninit();
     cerr(unsafe { ::libc::tcgetattr(fd, term.as_mut_ptr()) })?;
@@ -100,6 +160,80 @@ fn write_unbuffered(sink: &mut impl io::Write, text: &str) -> io::Result<()> {
     sink.flush()
 }
 
+/// Ask for input with the helper program at `path`, which gets the prompt as its argument and
+/// writes the answer to its standard output, like `ssh-askpass`. It runs as the invoking user, as
+/// it is a program of their choosing.
+pub fn askpass(path: &Path, prompt: &str) -> io::Result<PamBuffer> {
+    let mut helper = Command::new(path)
+        .arg(prompt)
+        .uid(User::real_uid())
+        .gid(User::real_gid())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    let mut output = helper
+        .stdout
+        .take()
+        .expect("the output of the helper is piped");
+    let input = read_unbuffered(&mut output);
+    drop(output);
+    let status = helper.wait()?;
+    if !status.success() {
+        return Err(Error::new(
+            ErrorKind::Other,
+            format!("{} failed: {status}", path.display()),
+        ));
+    }
+
+    input
+}
+
+/// Where a prompt is shown and the answer to it is read from.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PromptTarget {
+    /// The controlling terminal, `/dev/tty`.
+    Tty,
+    /// Standard input, with the prompt on standard error.
+    Stdin,
+    /// The askpass helper program.
+    Askpass(PathBuf),
+}
+
+impl PromptTarget {
+    /// Decide where to prompt: with the askpass helper if `use_askpass` (`-A`), on standard input
+    /// if `use_stdin` (`-S`), or else on the terminal. Without a terminal, the askpass helper is
+    /// used if there is one and a display to show it on, like ogsudo does.
+    pub fn choose(
+        use_stdin: bool,
+        use_askpass: bool,
+        askpass: Option<&Path>,
+        tty_present: bool,
+        display_present: bool,
+    ) -> io::Result<Self> {
+        let askpass = askpass.map(Path::to_path_buf);
+        if use_askpass {
+            return askpass.map(PromptTarget::Askpass).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    "no askpass program specified, try setting SUDO_ASKPASS",
+                )
+            });
+        }
+
+        match askpass {
+            _ if use_stdin => Ok(PromptTarget::Stdin),
+            _ if tty_present => Ok(PromptTarget::Tty),
+            Some(askpass) if display_present => Ok(PromptTarget::Askpass(askpass)),
+            _ => Err(Error::new(
+                ErrorKind::NotFound,
+                "a terminal is required to read the password; either use the -S option to read \
+                 from standard input or configure an askpass helper",
+            )),
+        }
+    }
+}
+
 /// A data structure representing either /dev/tty or /dev/stdin+stderr
 pub enum Terminal<'a> {
     Tty(fs::File),
@@ -109,12 +243,17 @@ pub enum Terminal<'a> {
 impl Terminal<'_> {
     /// Open the current TTY for user communication
     pub fn open_tty() -> io::Result<Self> {
-        Ok(Terminal::Tty(
-            fs::OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open("/dev/tty")?,
-        ))
+        let tty = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")?;
+
+        if let Err(err) = verify_tty(&tty, User::real_uid()) {
+            auth_warn!("refusing to prompt on the terminal: {err}");
+            return Err(err);
+        }
+
+        Ok(Terminal::Tty(tty))
     }
 
     /// Open standard input and standard error for user communication
@@ -122,11 +261,19 @@ impl Terminal<'_> {
         Ok(Terminal::StdIE(io::stdin().lock(), io::stderr().lock()))
     }
 
+    /// Whether `/dev/tty` can be used to prompt on.
+    pub fn tty_present() -> bool {
+        fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .is_ok()
+    }
+
     /// Reads input with TTY echo disabled
     pub fn read_password(&mut self) -> io::Result<PamBuffer> {
-        let mut input = self.source();
-        let _hide_input = HiddenInput::new()?;
-        read_unbuffered(&mut input)
+        let _hide_input = HiddenInput::new(self.source_fd())?;
+        read_unbuffered(&mut self.source())
     }
 
     /// Reads input with TTY echo enabled
@@ -134,12 +281,24 @@ impl Terminal<'_> {
         read_unbuffered(&mut self.source())
     }
 
+    /// Take the terminal out of raw mode until the result is dropped, see [CookedMode].
+    pub fn cooked(&self) -> Option<CookedMode> {
+        CookedMode::new(self.source_fd())
+    }
+
     /// Display information
     pub fn prompt(&mut self, text: &str) -> io::Result<()> {
         write_unbuffered(&mut self.sink(), text)
     }
 
     // boilerplate reduction functions
+    fn source_fd(&self) -> BorrowedFd<'_> {
+        match self {
+            Terminal::StdIE(x, _) => x.as_fd(),
+            Terminal::Tty(x) => x.as_fd(),
+        }
+    }
+
     fn source(&mut self) -> &mut dyn io::Read {
         match self {
             Terminal::StdIE(x, _) => x,
@@ -157,7 +316,10 @@ impl Terminal<'_> {
 
 #[cfg(test)]
 mod test {
-    use super::{read_unbuffered, write_unbuffered};
+    use std::io::ErrorKind;
+    use std::path::Path;
+
+    use super::{askpass, read_unbuffered, write_unbuffered, CookedMode, PromptTarget};
 
     #[test]
     fn miri_test_read() {
@@ -181,10 +343,97 @@ mod test {
         assert!(read_unbuffered(&mut "a".repeat(512).as_bytes()).is_err());
     }
 
+    #[test]
+    fn cooked_mode() {
+        use crate::system::term::{Pty, TermSettings};
+        use libc::{ECHO, ICANON, ISIG, OPOST};
+        use std::io::{Read, Write};
+        use std::os::fd::{AsRawFd, BorrowedFd};
+
+        let mut pty = Pty::open().unwrap();
+        // SAFETY: the follower is open as long as `pty` is
+        let follower = unsafe { BorrowedFd::borrow_raw(pty.follower.as_raw_fd()) };
+        let flags = || {
+            let term = super::safe_tcgetattr(follower.as_raw_fd()).unwrap();
+            (term.c_oflag & OPOST, term.c_lflag & (ICANON | ECHO | ISIG))
+        };
+        assert!(CookedMode::new(follower).is_none());
+
+        let mut raw = super::safe_tcgetattr(follower.as_raw_fd()).unwrap();
+        unsafe { libc::cfmakeraw(&mut raw) };
+        unsafe { libc::tcsetattr(follower.as_raw_fd(), libc::TCSANOW, &raw) };
+        let raw = TermSettings::of(&pty.follower).unwrap();
+
+        let guard = CookedMode::new(follower).unwrap();
+        // signals are left as they were
+        assert_eq!(flags(), (OPOST, ICANON | ECHO));
+        let mut tty = std::fs::File::from(follower.try_clone_to_owned().unwrap());
+        tty.write_all(b"Enter the code\nsent to your phone\n")
+            .unwrap();
+        let mut shown = [0; 36];
+        pty.leader.read_exact(&mut shown).unwrap();
+        assert_eq!(&shown, b"Enter the code\r\nsent to your phone\r\n");
+
+        drop(guard);
+        assert_eq!(TermSettings::of(&pty.follower).unwrap(), raw);
+    }
+
     #[test]
     fn miri_test_write() {
         let mut data = Vec::new();
         write_unbuffered(&mut data, "prompt").unwrap();
         assert_eq!(std::str::from_utf8(&data).unwrap(), "prompt");
     }
+
+    #[test]
+    fn prompt_target() {
+        use PromptTarget::*;
+
+        let helper = Path::new("/usr/libexec/ssh-askpass");
+        let choose = |use_stdin, use_askpass, askpass: Option<&Path>, tty, display| {
+            PromptTarget::choose(use_stdin, use_askpass, askpass, tty, display)
+                .map_err(|err| err.kind())
+        };
+
+        // -S, -A, askpass helper, /dev/tty, DISPLAY, and the outcome
+        #[rustfmt::skip]
+        let cases = [
+            (false, false, None, true, false, Ok(Tty)),
+            (false, false, Some(helper), true, true, Ok(Tty)),
+            (false, false, None, false, true, Err(ErrorKind::NotFound)),
+            (false, false, Some(helper), false, false, Err(ErrorKind::NotFound)),
+            (false, false, Some(helper), false, true, Ok(Askpass(helper.into()))),
+            (true, false, None, true, false, Ok(Stdin)),
+            (true, false, None, false, false, Ok(Stdin)),
+            (true, false, Some(helper), false, true, Ok(Stdin)),
+            (false, true, Some(helper), true, false, Ok(Askpass(helper.into()))),
+            (true, true, Some(helper), false, false, Ok(Askpass(helper.into()))),
+            (false, true, None, true, true, Err(ErrorKind::NotFound)),
+        ];
+
+        for (use_stdin, use_askpass, askpass, tty, display, expected) in cases {
+            assert_eq!(
+                choose(use_stdin, use_askpass, askpass, tty, display),
+                expected,
+                "-S: {use_stdin}, -A: {use_askpass}, {askpass:?}, tty: {tty}, DISPLAY: {display}"
+            );
+        }
+    }
+
+    #[test]
+    fn runs_askpass_helper() {
+        // `echo` answers with the prompt it gets
+        let password = askpass(Path::new("/bin/echo"), "hunter2").unwrap();
+        assert_eq!(
+            password
+                .iter()
+                .map(|&b| b as char)
+                .take_while(|&x| x != '\0')
+                .collect::<String>(),
+            "hunter2"
+        );
+
+        // a helper that fails, e.g. because the dialog was cancelled, gives no password
+        assert!(askpass(Path::new("/bin/false"), "Password: ").is_err());
+    }
 }
@@ -0,0 +1,170 @@
+//! The approval plugin that is enabled with `Defaults approval`, which lets another program
+//! decide whether a command may be run, e.g. by asking a second person to approve it.
+//!
+//! `approval` names either a Unix socket, which sudo connects to, or a program, which sudo runs
+//! as root with a socket as its stdin and stdout (its stderr is the one of sudo, so it can tell
+//! the user what is going on). Sudo writes the request as a JSON object and closes its side for
+//! writing; the first line that comes back is the decision: `allow`, or `deny` followed by the
+//! reason. Anything else, or no decision within `approval_timeout` seconds, rejects the command.
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::Shutdown;
+use std::os::fd::OwnedFd;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use crate::common::{json, Context, Environment};
+
+use super::ApprovalPlugin;
+
+/// The longest decision that is read.
+const MAX_REPLY: u64 = 4096;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalApproval {
+    target: PathBuf,
+    timeout: Duration,
+}
+
+impl ExternalApproval {
+    pub fn new(target: PathBuf, timeout: Duration) -> Self {
+        Self { target, timeout }
+    }
+
+    /// Send `request` to the socket or program, and return the first line of its reply.
+    fn ask(&self, request: &str) -> io::Result<String> {
+        if !self.target.is_absolute() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not an absolute path",
+            ));
+        }
+
+        let (stream, mut helper) = if fs::metadata(&self.target)?.file_type().is_socket() {
+            (UnixStream::connect(&self.target)?, None)
+        } else {
+            let (stream, theirs) = UnixStream::pair()?;
+            let helper = Command::new(&self.target)
+                .env_clear()
+                .env("PATH", "/usr/bin:/bin:/usr/sbin:/sbin")
+                .stdin(Stdio::from(OwnedFd::from(theirs.try_clone()?)))
+                .stdout(Stdio::from(OwnedFd::from(theirs)))
+                .spawn()?;
+            (stream, Some(helper))
+        };
+
+        let result = exchange(&stream, request, self.timeout);
+        if let Some(helper) = &mut helper {
+            // a helper that did not decide in time is not waited for
+            if result.is_err() {
+                let _ = helper.kill();
+            }
+            let _ = helper.wait();
+        }
+
+        result
+    }
+}
+
+/// Write `request` to `stream` and read the first line of the reply, waiting at most `timeout`
+/// for every part of it.
+fn exchange(mut stream: &UnixStream, request: &str, timeout: Duration) -> io::Result<String> {
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    stream.write_all(request.as_bytes())?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut reply = String::new();
+    BufReader::new(stream.take(MAX_REPLY)).read_line(&mut reply)?;
+
+    Ok(reply)
+}
+
+/// What is sent to the socket or program.
+fn request(context: &Context) -> String {
+    let command = &context.command;
+    json::object(&[
+        ("user", json::string(&context.current_user.name)),
+        ("runas_user", json::string(&context.target_user.name)),
+        ("runas_group", json::string(&context.target_group.name)),
+        ("host", json::string(&context.hostname)),
+        ("command", json::string(&command.command.to_string_lossy())),
+        (
+            "arguments",
+            json::string_array(command.arguments.iter().map(String::as_str)),
+        ),
+    ])
+}
+
+/// The decision in the first line of the reply.
+fn decision(reply: &str) -> Result<(), String> {
+    match reply.trim_end_matches('\n') {
+        "allow" => Ok(()),
+        "deny" => Err("not approved".to_string()),
+        "" => Err("no decision was made".to_string()),
+        reply => match reply.strip_prefix("deny ") {
+            Some(reason) => Err(format!("not approved: {reason}")),
+            None => Err(format!("unexpected decision: {reply}")),
+        },
+    }
+}
+
+impl ApprovalPlugin for ExternalApproval {
+    fn name(&self) -> &str {
+        "approval"
+    }
+
+    fn check(&mut self, context: &Context, _env: &Environment) -> Result<(), String> {
+        let reply = self
+            .ask(&request(context))
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
+                    format!("no decision within {} seconds", self.timeout.as_secs())
+                }
+                _ => format!("cannot ask {} for approval: {err}", self.target.display()),
+            })?;
+
+        decision(&reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    #[test]
+    fn reads_decisions() {
+        assert_eq!(decision("allow\n"), Ok(()));
+        assert_eq!(
+            decision("deny outside of the change window\n"),
+            Err("not approved: outside of the change window".to_string())
+        );
+        assert_eq!(decision("deny"), Err("not approved".to_string()));
+        assert!(decision("").is_err());
+        assert!(decision("allowed\n").is_err());
+    }
+
+    #[test]
+    fn asks_a_program() {
+        let helper = std::env::temp_dir().join(format!("sudo-rs-approval-{}", std::process::id()));
+        fs::write(
+            &helper,
+            "#!/bin/sh\ngrep -q '\"user\": \"alice\"' && echo allow || echo deny\n",
+        )
+        .unwrap();
+        fs::set_permissions(&helper, fs::Permissions::from_mode(0o700)).unwrap();
+
+        let approval = ExternalApproval::new(helper.clone(), Duration::from_secs(10));
+        let allowed = approval.ask("{\n  \"user\": \"alice\"\n}\n");
+        let denied = approval.ask("{\n  \"user\": \"mallory\"\n}\n");
+        fs::remove_file(&helper).unwrap();
+
+        assert_eq!(allowed.unwrap(), "allow\n");
+        assert_eq!(denied.unwrap(), "deny\n");
+    }
+}
@@ -0,0 +1,40 @@
+//! The audit plugin that is compiled in with the `bsm-audit` feature, which writes what became of
+//! every command to the BSM audit trail.
+use std::ffi::OsStr;
+
+use crate::common::Context;
+use crate::exec::ExitReason;
+use crate::log::auth_warn;
+use crate::system::bsm_audit::{write_record, Outcome};
+
+use super::AuditPlugin;
+
+pub(super) struct BsmAudit;
+
+fn audit(context: &Context, outcome: Outcome) {
+    let command = &context.command;
+    let argv = std::iter::once(command.command.as_os_str())
+        .chain(command.arguments.iter().map(OsStr::new))
+        .collect::<Vec<_>>();
+    if let Err(e) = write_record(&argv, outcome) {
+        auth_warn!("Could not write audit record: {e}");
+    }
+}
+
+impl AuditPlugin for BsmAudit {
+    fn accept(&mut self, context: &Context) {
+        audit(context, Outcome::Accepted);
+    }
+
+    fn reject(&mut self, context: &Context, reason: &str) {
+        audit(context, Outcome::Rejected(reason));
+    }
+
+    fn exit(&mut self, context: &Context, reason: &ExitReason) {
+        let status = match reason {
+            ExitReason::Code(code) => *code,
+            ExitReason::Signal(signal) => 128 + signal,
+        };
+        audit(context, Outcome::Exited(status));
+    }
+}
@@ -0,0 +1,95 @@
+//! Plugins that are declared in sudo.conf; the sudoers policy itself is built in.
+//!
+//! Besides I/O plugins that use the C plugin API of ogsudo, which are only loaded when sudo-rs is
+//! built with the `io-plugins` feature, sites that build their own sudo-rs
+//! can compile in approval and audit plugins that implement [ApprovalPlugin] and [AuditPlugin],
+//! e.g. to require a ticket number for every command. The approval of another program can also be
+//! required with `Defaults approval`, see [ExternalApproval].
+mod approval;
+#[cfg(all(feature = "bsm-audit", any(target_os = "macos", target_os = "freebsd")))]
+mod bsm_audit;
+#[cfg(feature = "io-plugins")]
+mod sudo_plugin;
+
+pub use approval::ExternalApproval;
+#[cfg(feature = "io-plugins")]
+pub use sudo_plugin::IoPlugin;
+#[cfg(not(feature = "io-plugins"))]
+pub use without_io_plugins::IoPlugin;
+
+use crate::common::{Context, Environment};
+use crate::exec::ExitReason;
+
+/// Decides whether a command that is allowed by the policy may actually be run.
+pub trait ApprovalPlugin {
+    fn name(&self) -> &str;
+
+    /// Called after the user has been authenticated, with the environment the command will get;
+    /// an error rejects the command and explains why.
+    fn check(&mut self, context: &Context, env: &Environment) -> Result<(), String>;
+}
+
+/// Is told about every command sudo is asked to run, and what became of it.
+pub trait AuditPlugin {
+    /// The command is about to be run.
+    fn accept(&mut self, _context: &Context) {}
+
+    /// The command was rejected by the policy or by an approval plugin.
+    fn reject(&mut self, _context: &Context, _reason: &str) {}
+
+    /// The command has finished.
+    fn exit(&mut self, _context: &Context, _reason: &ExitReason) {}
+}
+
+/// The approval plugins that are compiled in; add your own here.
+pub fn builtin_approval_plugins() -> Vec<Box<dyn ApprovalPlugin>> {
+    Vec::new()
+}
+
+/// The audit plugins that are compiled in; add your own here.
+pub fn builtin_audit_plugins() -> Vec<Box<dyn AuditPlugin>> {
+    vec![
+        #[cfg(all(feature = "bsm-audit", any(target_os = "macos", target_os = "freebsd")))]
+        Box::new(bsm_audit::BsmAudit),
+    ]
+}
+
+/// Without the `io-plugins` feature, no I/O plugin can be loaded, so there never is one.
+#[cfg(not(feature = "io-plugins"))]
+mod without_io_plugins {
+    use std::ffi::c_int;
+    use std::path::Path;
+
+    use crate::common::{Context, Environment};
+
+    #[derive(Debug, Clone)]
+    pub enum IoPlugin {}
+
+    impl IoPlugin {
+        pub fn load(symbol: &str, _path: &Path, _options: &[String]) -> Result<IoPlugin, String> {
+            Err(format!(
+                "{symbol}: sudo was built without support for I/O plugins"
+            ))
+        }
+
+        pub fn name(&self) -> &str {
+            match *self {}
+        }
+
+        pub fn open(&self, _context: &Context, _env: &Environment) -> Result<bool, String> {
+            match *self {}
+        }
+
+        pub fn log_ttyout(&self, _buf: &[u8]) -> Result<bool, String> {
+            match *self {}
+        }
+
+        pub fn log_stderr(&self, _buf: &[u8]) -> Result<bool, String> {
+            match *self {}
+        }
+
+        pub fn close(&self, _status: c_int, _error: c_int) {
+            match *self {}
+        }
+    }
+}
@@ -0,0 +1,469 @@
+//! Loading of I/O plugins that were written against the C plugin API of ogsudo (`sudo_plugin.h`).
+//!
+//! Only the part of the API that is needed to show a plugin the session and the terminal output
+//! of the command is implemented: the `open`, `close`, `log_ttyout` and `log_stderr` entry
+//! points.
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, c_uint, c_void, CStr, CString, OsStr};
+use std::fmt;
+use std::io::Write;
+use std::os::fd::AsRawFd;
+use std::os::unix::prelude::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::rc::Rc;
+
+use crate::common::{Context, Environment};
+use crate::pam::Terminal;
+use crate::system::{hostname, secure_open_no_symlinks};
+
+/// Where plugins that are not given with an absolute path are found.
+const PLUGIN_DIR: &str = "/usr/libexec/sudo";
+
+const SUDO_API_VERSION_MAJOR: c_uint = 1;
+const SUDO_API_VERSION: c_uint = SUDO_API_VERSION_MAJOR << 16 | 17;
+
+const SUDO_POLICY_PLUGIN: c_uint = 1;
+const SUDO_IO_PLUGIN: c_uint = 2;
+const SUDO_AUDIT_PLUGIN: c_uint = 3;
+const SUDO_APPROVAL_PLUGIN: c_uint = 4;
+
+const SUDO_CONV_PROMPT_ECHO_OFF: c_int = 0x0001;
+const SUDO_CONV_PROMPT_ECHO_ON: c_int = 0x0002;
+const SUDO_CONV_ERROR_MSG: c_int = 0x0003;
+const SUDO_CONV_INFO_MSG: c_int = 0x0004;
+const SUDO_CONV_PROMPT_MASK: c_int = 0x0005;
+
+#[repr(C)]
+struct ConvMessage {
+    msg_type: c_int,
+    timeout: c_int,
+    msg: *const c_char,
+}
+
+#[repr(C)]
+struct ConvReply {
+    reply: *mut c_char,
+}
+
+type ConversationFn =
+    unsafe extern "C" fn(c_int, *const ConvMessage, *mut ConvReply, *mut c_void) -> c_int;
+type PrintfFn = unsafe extern "C" fn(c_int, *const c_char, ...) -> c_int;
+type LogFn = unsafe extern "C" fn(*const c_char, c_uint, *mut *const c_char) -> c_int;
+type OpenFn = unsafe extern "C" fn(
+    c_uint,
+    ConversationFn,
+    PrintfFn,
+    *const *const c_char,
+    *const *const c_char,
+    *const *const c_char,
+    c_int,
+    *const *const c_char,
+    *const *const c_char,
+    *const *const c_char,
+    *mut *const c_char,
+) -> c_int;
+
+/// The start of `struct io_plugin`; the members that follow were added in later versions of the
+/// API, and are not used.
+#[repr(C)]
+struct RawIoPlugin {
+    plugin_type: c_uint,
+    version: c_uint,
+    open: Option<OpenFn>,
+    close: Option<unsafe extern "C" fn(c_int, c_int)>,
+    show_version: Option<unsafe extern "C" fn(c_int) -> c_int>,
+    log_ttyin: Option<LogFn>,
+    log_ttyout: Option<LogFn>,
+    _log_stdin: Option<LogFn>,
+    _log_stdout: Option<LogFn>,
+    log_stderr: Option<LogFn>,
+}
+
+/// The conversation function that is passed to plugins, to show messages to the user or to
+/// ask for input.
+unsafe extern "C" fn conversation(
+    num_msgs: c_int,
+    msgs: *const ConvMessage,
+    replies: *mut ConvReply,
+    _callback: *mut c_void,
+) -> c_int {
+    for i in 0..num_msgs.max(0) as usize {
+        // SAFETY: the plugin passes `num_msgs` messages, and as many replies
+        let msg = unsafe { &*msgs.add(i) };
+        let text = if msg.msg.is_null() {
+            String::new()
+        } else {
+            unsafe { CStr::from_ptr(msg.msg) }
+                .to_string_lossy()
+                .into_owned()
+        };
+
+        match msg.msg_type & 0xff {
+            SUDO_CONV_ERROR_MSG => {
+                let _ = std::io::stderr().write_all(text.as_bytes());
+            }
+            SUDO_CONV_INFO_MSG => {
+                let _ = std::io::stdout().write_all(text.as_bytes());
+            }
+            msg_type @ (SUDO_CONV_PROMPT_ECHO_OFF
+            | SUDO_CONV_PROMPT_ECHO_ON
+            | SUDO_CONV_PROMPT_MASK) => {
+                let Ok(mut terminal) = Terminal::open_tty().or_else(|_| Terminal::open_stdie())
+                else {
+                    return -1;
+                };
+                if terminal.prompt(&text).is_err() {
+                    return -1;
+                }
+                let input = if msg_type == SUDO_CONV_PROMPT_ECHO_ON {
+                    terminal.read_cleartext()
+                } else {
+                    terminal.read_password()
+                };
+                let (Ok(input), false) = (input, replies.is_null()) else {
+                    return -1;
+                };
+                // the buffer was allocated with `calloc`, so the plugin can `free` it
+                unsafe { (*replies.add(i)).reply = input.leak().as_ptr().cast() };
+            }
+            _ => return -1,
+        }
+    }
+
+    0
+}
+
+extern "C" {
+    /// The printf-like function that is passed to plugins; it is variadic, so it is written in
+    /// C, in `sudo_printf.c`.
+    fn sudo_rs_plugin_printf(msg_type: c_int, fmt: *const c_char, ...) -> c_int;
+}
+
+/// A NULL terminated array of strings, such as the settings that are passed to a plugin.
+struct CStringArray {
+    _strings: Vec<CString>,
+    pointers: Vec<*const c_char>,
+}
+
+impl CStringArray {
+    fn new<T: Into<Vec<u8>>>(items: impl IntoIterator<Item = T>) -> Self {
+        // strings with NUL bytes cannot be passed, but these are not valid in the first place
+        let strings: Vec<CString> = items
+            .into_iter()
+            .filter_map(|item| CString::new(item).ok())
+            .collect();
+        let pointers = strings
+            .iter()
+            .map(|s| s.as_ptr())
+            .chain([ptr::null()])
+            .collect();
+
+        Self {
+            _strings: strings,
+            pointers,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.pointers.len() - 1
+    }
+
+    fn as_ptr(&self) -> *const *const c_char {
+        self.pointers.as_ptr()
+    }
+}
+
+fn key_value(key: &str, value: impl AsRef<OsStr>) -> Vec<u8> {
+    let mut item = format!("{key}=").into_bytes();
+    item.extend_from_slice(value.as_ref().as_bytes());
+    item
+}
+
+struct LoadedPlugin {
+    raw: &'static RawIoPlugin,
+    path: PathBuf,
+    options: CStringArray,
+    /// The information passed to `open`; plugins may keep pointers to it until they are closed.
+    session: RefCell<Vec<CStringArray>>,
+}
+
+/// An I/O plugin; cloning it results in another handle to the same plugin.
+#[derive(Clone)]
+pub struct IoPlugin {
+    name: String,
+    inner: Rc<LoadedPlugin>,
+}
+
+impl fmt::Debug for IoPlugin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IoPlugin")
+            .field("name", &self.name)
+            .field("path", &self.inner.path)
+            .finish()
+    }
+}
+
+impl IoPlugin {
+    /// Load the plugin that is exported as `symbol` by the shared object at `path`, which is
+    /// relative to the plugin directory unless it is absolute.
+    pub fn load(symbol: &str, path: &Path, options: &[String]) -> Result<IoPlugin, String> {
+        let path = Path::new(PLUGIN_DIR).join(path);
+
+        // the plugin will run with root privileges, so neither it nor the directories leading to
+        // it may be writable by anyone else, which also keeps them from being replaced
+        let file = secure_open_no_symlinks(&path, true)
+            .map_err(|err| format!("{}: {err}", path.display()))?;
+
+        // load the file that was checked, rather than whatever the path refers to by now
+        #[cfg(target_os = "linux")]
+        let load_path = PathBuf::from(format!("/proc/self/fd/{}", file.as_raw_fd()));
+        #[cfg(not(target_os = "linux"))]
+        let load_path = path.clone();
+        let c_path = CString::new(load_path.into_os_string().into_vec())
+            .map_err(|_| format!("{}: invalid path", path.display()))?;
+        let c_symbol = CString::new(symbol).map_err(|_| format!("{symbol}: invalid symbol"))?;
+
+        // SAFETY: both strings are valid and NUL terminated; the library is never unloaded,
+        // so the symbol remains valid for the rest of the process.
+        let raw = unsafe {
+            let handle = libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+            if handle.is_null() {
+                return Err(format!("unable to load {}", path.display()));
+            }
+            libc::dlsym(handle, c_symbol.as_ptr()) as *const RawIoPlugin
+        };
+        drop(file);
+        // SAFETY: the symbol of a plugin refers to one of the plugin structures of sudo_plugin.h,
+        // which all start with the type and the version
+        let Some(raw) = (unsafe { raw.as_ref() }) else {
+            return Err(format!(
+                "unable to find symbol {symbol} in {}",
+                path.display()
+            ));
+        };
+
+        if raw.version >> 16 != SUDO_API_VERSION_MAJOR {
+            return Err(format!(
+                "{symbol}: incompatible plugin API version {}.{}",
+                raw.version >> 16,
+                raw.version & 0xffff
+            ));
+        }
+        match raw.plugin_type {
+            SUDO_IO_PLUGIN => {}
+            // the policy is always decided by the built-in sudoers policy
+            SUDO_POLICY_PLUGIN => {
+                return Err(format!(
+                    "{symbol}: only I/O plugins can be loaded, not policy plugins"
+                ))
+            }
+            SUDO_AUDIT_PLUGIN => {
+                return Err(format!(
+                    "{symbol}: only I/O plugins can be loaded, not audit plugins"
+                ))
+            }
+            SUDO_APPROVAL_PLUGIN => {
+                return Err(format!(
+                    "{symbol}: only I/O plugins can be loaded, not approval plugins"
+                ))
+            }
+            other => return Err(format!("{symbol}: unknown plugin type {other}")),
+        }
+
+        Ok(IoPlugin {
+            name: symbol.to_string(),
+            inner: Rc::new(LoadedPlugin {
+                raw,
+                path,
+                options: CStringArray::new(options.iter().map(String::as_str)),
+                session: RefCell::new(Vec::new()),
+            }),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Tell the plugin about the command that is about to be run; returns whether the plugin
+    /// accepts that the command is run.
+    pub fn open(&self, context: &Context, env: &Environment) -> Result<bool, String> {
+        let Some(open) = self.inner.raw.open else {
+            return Ok(true);
+        };
+
+        let settings = CStringArray::new([
+            key_value("progname", "sudo"),
+            key_value("plugin_path", &self.inner.path),
+        ]);
+
+        let process = &context.process;
+        let mut user_info = vec![
+            key_value("user", &context.current_user.name),
+            key_value("uid", context.current_user.uid.to_string()),
+            key_value("gid", context.current_user.gid.to_string()),
+            key_value("host", hostname()),
+            key_value("pid", process.pid.to_string()),
+            key_value("pgid", process.group_id.to_string()),
+            key_value("sid", process.session_id.to_string()),
+        ];
+        if let Some(ppid) = process.parent_pid {
+            user_info.push(key_value("ppid", ppid.to_string()));
+        }
+        if let Ok(cwd) = std::env::current_dir() {
+            user_info.push(key_value("cwd", cwd));
+        }
+        if let Ok(tty) = crate::system::term::current_tty_name() {
+            user_info.push(key_value("tty", tty));
+        }
+        let user_info = CStringArray::new(user_info);
+
+        let mut command_info = vec![
+            key_value("command", &context.command.command),
+            key_value("runas_user", &context.target_user.name),
+            key_value("runas_uid", context.target_user.uid.to_string()),
+            key_value("runas_gid", context.target_group.gid.to_string()),
+            key_value("use_pty", if context.use_pty { "true" } else { "false" }),
+        ];
+        if let Some(chdir) = &context.chdir {
+            command_info.push(key_value("cwd", chdir));
+        }
+        // the names that the sudoers policy of the original sudo uses for `LOG_INPUT`,
+        // `LOG_OUTPUT` and `FOLLOW`, so existing I/O plugins know what to log
+        if context.log_input {
+            for name in ["iolog_ttyin", "iolog_stdin"] {
+                command_info.push(key_value(name, "true"));
+            }
+        }
+        if context.log_output {
+            for name in ["iolog_ttyout", "iolog_stdout", "iolog_stderr"] {
+                command_info.push(key_value(name, "true"));
+            }
+        }
+        if context.sudoedit_follow {
+            command_info.push(key_value("sudoedit_follow", "true"));
+        }
+        let command_info = CStringArray::new(command_info);
+
+        let argv = CStringArray::new(
+            [context.command.command.as_os_str().as_bytes().to_vec()]
+                .into_iter()
+                .chain(
+                    context
+                        .command
+                        .arguments
+                        .iter()
+                        .map(|arg| arg.clone().into()),
+                ),
+        );
+        let user_env = CStringArray::new(env.iter().map(|(key, value)| {
+            let mut item = key.as_bytes().to_vec();
+            item.push(b'=');
+            item.extend_from_slice(value.as_bytes());
+            item
+        }));
+
+        let mut errstr = ptr::null();
+        // SAFETY: all arrays are NULL terminated and are kept alive until the plugin is closed
+        let result = unsafe {
+            open(
+                SUDO_API_VERSION,
+                conversation,
+                sudo_rs_plugin_printf,
+                settings.as_ptr(),
+                user_info.as_ptr(),
+                command_info.as_ptr(),
+                argv.len() as c_int,
+                argv.as_ptr(),
+                user_env.as_ptr(),
+                self.inner.options.as_ptr(),
+                &mut errstr,
+            )
+        };
+
+        self.inner
+            .session
+            .borrow_mut()
+            .extend([settings, user_info, command_info, argv, user_env]);
+
+        match result {
+            1 => Ok(true),
+            0 => Ok(false),
+            _ => Err(self.error_message(errstr, "error initializing I/O plugin")),
+        }
+    }
+
+    /// Show the plugin output of the command; returns whether the plugin accepts it.
+    pub fn log_ttyout(&self, buf: &[u8]) -> Result<bool, String> {
+        self.log(self.inner.raw.log_ttyout, buf)
+    }
+
+    /// Show the plugin what the command wrote to its standard error, when that is not its
+    /// terminal; returns whether the plugin accepts it.
+    pub fn log_stderr(&self, buf: &[u8]) -> Result<bool, String> {
+        self.log(self.inner.raw.log_stderr, buf)
+    }
+
+    fn log(&self, log: Option<LogFn>, buf: &[u8]) -> Result<bool, String> {
+        let Some(log) = log else {
+            return Ok(true);
+        };
+
+        let mut errstr = ptr::null();
+        let mut accepted = true;
+        for chunk in buf.chunks(c_uint::MAX as usize) {
+            // SAFETY: the plugin reads `chunk.len()` bytes from a valid pointer
+            match unsafe { log(chunk.as_ptr().cast(), chunk.len() as c_uint, &mut errstr) } {
+                1 => {}
+                0 => accepted = false,
+                _ => return Err(self.error_message(errstr, "error logging output")),
+            }
+        }
+
+        Ok(accepted)
+    }
+
+    /// Tell the plugin that the command has finished, with `status` in the format of `wait`.
+    pub fn close(&self, status: c_int, error: c_int) {
+        if let Some(close) = self.inner.raw.close {
+            // SAFETY: this only passes integers
+            unsafe { close(status, error) };
+        }
+    }
+
+    fn error_message(&self, errstr: *const c_char, default: &str) -> String {
+        let name = &self.name;
+        if errstr.is_null() {
+            format!("{name}: {default}")
+        } else {
+            // SAFETY: a plugin that sets `errstr` points it to a NUL terminated string
+            let message = unsafe { CStr::from_ptr(errstr) }.to_string_lossy();
+            format!("{name}: {message}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::CStringArray;
+
+    #[test]
+    fn builds_null_terminated_arrays() {
+        let array = CStringArray::new(["progname=sudo", "bad\0string", "plugin_path=io.so"]);
+
+        assert_eq!(array.len(), 2);
+        let pointers = &array.pointers;
+        assert_eq!(
+            unsafe { CStr::from_ptr(pointers[0]) }.to_bytes(),
+            b"progname=sudo"
+        );
+        assert_eq!(
+            unsafe { CStr::from_ptr(pointers[1]) }.to_bytes(),
+            b"plugin_path=io.so"
+        );
+        assert!(pointers[2].is_null());
+    }
+}
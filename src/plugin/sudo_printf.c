@@ -0,0 +1,48 @@
+/*
+ * The printf-like function that sudo-rs passes to I/O plugins, as `sudo_printf`
+ * in sudo_plugin.h. It is variadic, which Rust cannot define, so it is written
+ * in C: the message is formatted with vasprintf and written to the standard
+ * error for SUDO_CONV_ERROR_MSG, and to the standard output otherwise.
+ */
+
+#define _GNU_SOURCE
+
+#include <errno.h>
+#include <stdarg.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <unistd.h>
+
+#define SUDO_CONV_ERROR_MSG 0x0003
+
+int sudo_rs_plugin_printf(int msg_type, const char *fmt, ...)
+{
+    char *text = NULL;
+    va_list ap;
+    int len;
+
+    if (fmt == NULL)
+        return 0;
+
+    va_start(ap, fmt);
+    len = vasprintf(&text, fmt, ap);
+    va_end(ap);
+    if (len < 0)
+        return -1;
+
+    int fd = (msg_type & 0xff) == SUDO_CONV_ERROR_MSG ? STDERR_FILENO : STDOUT_FILENO;
+    size_t done = 0;
+    while (done < (size_t)len) {
+        ssize_t written = write(fd, text + done, (size_t)len - done);
+        if (written < 0) {
+            if (errno == EINTR)
+                continue;
+            free(text);
+            return -1;
+        }
+        done += (size_t)written;
+    }
+
+    free(text);
+    return len;
+}
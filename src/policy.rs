@@ -0,0 +1,357 @@
+#![forbid(unsafe_code)]
+
+//! The sudoers parser and evaluator of sudo-rs, for tools that need to know what a sudoers file
+//! means to sudo, such as linters or tools that generate rules and want to verify them.
+//!
+//! This is only available with the `policy-api` feature. The types in this module are a thin
+//! layer over the ones sudo itself uses, so a decision is made exactly like sudo would make it.
+//!
+//! ```no_run
+//! use sudo_rs::policy::{Account, Decision, Group, Query, Sudoers};
+//!
+//! let (sudoers, errors) = Sudoers::open("/etc/sudoers")?;
+//! for error in &errors {
+//!     eprintln!("{error}");
+//! }
+//!
+//! let ferris = Account::new("ferris", 1000).with_groups(vec![Group::new("wheel", 10)]);
+//! // without an explicit group, the command runs with the primary group of the target user
+//! let root_group = Group::new("root", 0);
+//! let root = Account::new("root", 0).with_groups(vec![root_group.clone()]);
+//! let query = Query {
+//!     user: &ferris,
+//!     host: "localhost",
+//!     runas_user: &root,
+//!     runas_group: &root_group,
+//!     command: "/usr/bin/apt".as_ref(),
+//!     arguments: &["update".to_string()],
+//! };
+//! if let Decision::Allowed(allowed) = sudoers.check(&query) {
+//!     println!("allowed, password needed: {}", allowed.must_authenticate);
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    defaults::{sudo_default, SudoDefault, ALL_PARAMS},
+    sudoers::{self, Authorization, DirChange, Policy, Request},
+    system::interface::{GroupId, UnixGroup, UnixUser, UserId},
+};
+
+/// A parsed sudoers file, including the files it includes.
+pub struct Sudoers {
+    inner: sudoers::Sudoers,
+}
+
+/// A syntax error in a sudoers file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The file that has the error, which may be an included file.
+    pub file: Option<PathBuf>,
+    /// The line and column where the error starts, counting from 1, if known.
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file.display())?;
+        }
+        match self.position {
+            Some((line, column)) => write!(f, "{line}:{column}: {}", self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl Sudoers {
+    /// Parse the sudoers file at `path`. Like sudo, this refuses files that are writable by
+    /// others than their owner. Syntax errors do not make this fail: the rest of the file is
+    /// still used, as sudo does.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<(Self, Vec<ParseError>)> {
+        let (inner, errors) = sudoers::Sudoers::open(path)?;
+        Ok(Self::new(inner, errors))
+    }
+
+    /// Parse sudoers rules from `reader`; relative paths of included files are resolved as if
+    /// they appeared in the file at `path`.
+    pub fn read(
+        reader: impl io::Read,
+        path: impl AsRef<Path>,
+    ) -> io::Result<(Self, Vec<ParseError>)> {
+        let (inner, errors) = sudoers::Sudoers::read(reader, path)?;
+        Ok(Self::new(inner, errors))
+    }
+
+    fn new(inner: sudoers::Sudoers, errors: Vec<sudoers::Error>) -> (Self, Vec<ParseError>) {
+        let errors = errors
+            .into_iter()
+            .map(|error| ParseError {
+                file: error.source,
+                position: error.location.map(|range| range.start),
+                message: error.message,
+            })
+            .collect();
+
+        (Self { inner }, errors)
+    }
+
+    /// Decide whether `query.user` may run a command.
+    pub fn check(&self, query: &Query) -> Decision {
+        let judgement = self.inner.check(
+            query.user,
+            query.host,
+            Request {
+                user: query.runas_user,
+                group: query.runas_group,
+                command: query.command,
+                arguments: query.arguments,
+            },
+        );
+
+        let Authorization::Allowed(auth) = judgement.authorization() else {
+            return Decision::Denied;
+        };
+
+        Decision::Allowed(Allowed {
+            must_authenticate: auth.must_authenticate,
+            cwd: match judgement.chdir() {
+                DirChange::Any => Some("*".to_string()),
+                DirChange::Strict(path) => path.map(|path| path.display().to_string()),
+            },
+            capabilities: judgement.capabilities().map(|caps| caps.to_string()),
+            sandbox: judgement.sandbox().map(str::to_string),
+        })
+    }
+
+    /// The value of every setting after applying the `Defaults` of the file, in alphabetical
+    /// order.
+    pub fn defaults(&self) -> impl Iterator<Item = (&'static str, DefaultValue)> + '_ {
+        let settings = self.inner.settings();
+        let mut names = ALL_PARAMS.to_vec();
+        names.sort_unstable();
+
+        names.into_iter().filter_map(move |name| {
+            let value = match sudo_default(name)? {
+                SudoDefault::Flag(_) => DefaultValue::Flag(settings.flags.contains(name)),
+                SudoDefault::Integer(..) => DefaultValue::Integer(settings.int_value[name]),
+                SudoDefault::Text(_) => {
+                    DefaultValue::Text(settings.str_value[name].as_deref().map(str::to_string))
+                }
+                SudoDefault::Enum(_) => {
+                    DefaultValue::Text(Some(settings.enum_value[name].get().to_string()))
+                }
+                SudoDefault::List(_) => {
+                    let mut items: Vec<String> = settings.list[name].iter().cloned().collect();
+                    items.sort_unstable();
+                    DefaultValue::List(items)
+                }
+            };
+
+            Some((name, value))
+        })
+    }
+}
+
+/// The value of a setting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DefaultValue {
+    Flag(bool),
+    Integer(i64),
+    /// A text, or `None` if it was negated (e.g. `Defaults !secure_path`).
+    Text(Option<String>),
+    List(Vec<String>),
+}
+
+/// A user, as far as the sudoers rules are concerned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Account {
+    pub name: String,
+    pub uid: UserId,
+    pub groups: Vec<Group>,
+}
+
+impl Account {
+    pub fn new(name: impl Into<String>, uid: UserId) -> Self {
+        Self {
+            name: name.into(),
+            uid,
+            groups: Vec::new(),
+        }
+    }
+
+    /// Set the groups the user is a member of, including their primary group.
+    pub fn with_groups(self, groups: Vec<Group>) -> Self {
+        Self { groups, ..self }
+    }
+}
+
+impl UnixUser for Account {
+    fn has_name(&self, name: &str) -> bool {
+        self.name == name
+    }
+
+    fn has_uid(&self, uid: UserId) -> bool {
+        self.uid == uid
+    }
+
+    fn is_root(&self) -> bool {
+        self.uid == 0
+    }
+
+    fn in_group_by_name(&self, name: &str) -> bool {
+        self.groups.iter().any(|group| group.name == name)
+    }
+
+    fn in_group_by_gid(&self, gid: GroupId) -> bool {
+        self.groups.iter().any(|group| group.gid == gid)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    pub name: String,
+    pub gid: GroupId,
+}
+
+impl Group {
+    pub fn new(name: impl Into<String>, gid: GroupId) -> Self {
+        Self {
+            name: name.into(),
+            gid,
+        }
+    }
+}
+
+impl UnixGroup for Group {
+    fn as_gid(&self) -> GroupId {
+        self.gid
+    }
+
+    fn try_as_name(&self) -> Option<&str> {
+        Some(&self.name)
+    }
+}
+
+/// A question for the sudoers rules: may `user` run `command` with `arguments` as `runas_user`
+/// and `runas_group` on `host`?
+pub struct Query<'a> {
+    pub user: &'a Account,
+    pub host: &'a str,
+    pub runas_user: &'a Account,
+    pub runas_group: &'a Group,
+    /// The absolute path of the command.
+    pub command: &'a Path,
+    pub arguments: &'a [String],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Allowed(Allowed),
+    Denied,
+}
+
+/// What the rule that allows a command says about running it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Allowed {
+    /// Whether the user has to authenticate first (`PASSWD`).
+    pub must_authenticate: bool,
+    /// The directory the command runs in (`CWD`), where `*` means the user may choose.
+    pub cwd: Option<String>,
+    /// The capabilities the command may keep (`CAPS`).
+    pub capabilities: Option<String>,
+    /// The sandbox profile the command runs in (`SANDBOX`).
+    pub sandbox: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Account, Allowed, Decision, DefaultValue, Group, Query, Sudoers};
+
+    fn sudoers(text: &str) -> Sudoers {
+        let (sudoers, errors) = Sudoers::read(text.as_bytes(), "/etc/sudoers").unwrap();
+        assert!(errors.is_empty(), "{errors:?}");
+        sudoers
+    }
+
+    #[test]
+    fn checks_commands() {
+        let sudoers = sudoers(
+            "ferris ALL=(root) NOPASSWD: /usr/bin/apt update\n\
+             %wheel ALL=(ALL) CWD=* ALL\n",
+        );
+        let root_group = Group::new("root", 0);
+        let root = Account::new("root", 0).with_groups(vec![root_group.clone()]);
+        let ferris = Account::new("ferris", 1000);
+        let query = |user: &Account, command: &str, arguments: &[String]| {
+            let query = Query {
+                user,
+                host: "server",
+                runas_user: &root,
+                runas_group: &root_group,
+                command: command.as_ref(),
+                arguments,
+            };
+            sudoers.check(&query)
+        };
+
+        let update = ["update".to_string()];
+        assert_eq!(
+            query(&ferris, "/usr/bin/apt", &update),
+            Decision::Allowed(Allowed {
+                must_authenticate: false,
+                cwd: None,
+                capabilities: None,
+                sandbox: None,
+            })
+        );
+        assert_eq!(query(&ferris, "/usr/bin/apt", &[]), Decision::Denied);
+
+        let admin = Account::new("admin", 1001).with_groups(vec![Group::new("wheel", 10)]);
+        let Decision::Allowed(allowed) = query(&admin, "/bin/sh", &[]) else {
+            panic!("members of wheel may run anything");
+        };
+        assert!(allowed.must_authenticate);
+        assert_eq!(allowed.cwd.as_deref(), Some("*"));
+    }
+
+    #[test]
+    fn lists_defaults() {
+        let sudoers = sudoers("Defaults !use_pty, passwd_tries=5, secure_path=/bin\n");
+        let value = |name| {
+            sudoers
+                .defaults()
+                .find(|(setting, _)| *setting == name)
+                .map(|(_, value)| value)
+                .unwrap()
+        };
+
+        assert_eq!(value("use_pty"), DefaultValue::Flag(false));
+        assert_eq!(value("env_reset"), DefaultValue::Flag(true));
+        assert_eq!(value("passwd_tries"), DefaultValue::Integer(5));
+        assert_eq!(
+            value("secure_path"),
+            DefaultValue::Text(Some("/bin".into()))
+        );
+        assert_eq!(value("verifypw"), DefaultValue::Text(Some("all".into())));
+        assert!(
+            matches!(value("env_keep"), DefaultValue::List(list) if list.contains(&"PATH".to_string()))
+        );
+
+        let names: Vec<_> = sudoers.defaults().map(|(name, _)| name).collect();
+        assert!(names.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn reports_syntax_errors() {
+        let (_, errors) = Sudoers::read("ferris ALL=(root\n".as_bytes(), "/etc/sudoers").unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].position.map(|(line, _)| line), Some(1));
+        assert_eq!(errors[0].file.as_deref(), Some("/etc/sudoers".as_ref()));
+    }
+}
@@ -8,6 +8,7 @@ pub struct SuOptions {
     pub supp_group: Vec<String>,
     pub login: bool,
     pub preserve_environment: bool,
+    pub pty: bool,
     pub shell: Option<PathBuf>,
     pub whitelist_environment: Vec<String>,
     pub arguments: Vec<String>,
@@ -23,6 +24,7 @@ impl Default for SuOptions {
             supp_group: vec![],
             login: false,
             preserve_environment: false,
+            pty: false,
             shell: None,
             whitelist_environment: vec![],
             arguments: vec![],
@@ -122,7 +124,10 @@ impl SuOptions {
             short: 'P',
             long: "pty",
             takes_argument: false,
-            set: &|_sudo_options, _| Ok(()),
+            set: &|sudo_options, _| {
+                sudo_options.pty = true;
+                Ok(())
+            },
         },
         SuOption {
             short: 's',
@@ -307,6 +312,7 @@ mod tests {
     fn it_parses_combined_options() {
         let expected = SuOptions {
             login: true,
+            pty: true,
             ..Default::default()
         };
 
@@ -318,6 +324,7 @@ mod tests {
     fn it_parses_combined_options_and_arguments() {
         let expected = SuOptions {
             login: true,
+            pty: true,
             shell: Some("/bin/bash".into()),
             ..Default::default()
         };
@@ -335,6 +342,7 @@ mod tests {
         assert_eq!(
             SuOptions {
                 user: "ferris".to_string(),
+                pty: true,
                 ..Default::default()
             },
             parse(&["-P", "ferris"])
@@ -355,6 +363,7 @@ mod tests {
         let expected = SuOptions {
             user: "ferris".to_string(),
             arguments: vec!["script.sh".to_string()],
+            pty: true,
             ..Default::default()
         };
 
@@ -423,7 +432,10 @@ mod tests {
 
     #[test]
     fn it_parses_pty() {
-        let expected = SuOptions::default();
+        let expected = SuOptions {
+            pty: true,
+            ..Default::default()
+        };
         assert_eq!(expected, parse(&["-P"]));
         assert_eq!(expected, parse(&["--pty"]));
     }
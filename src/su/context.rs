@@ -1,21 +1,26 @@
 use std::{
     env,
     ffi::OsString,
-    fs, io,
+    fs::File,
+    io,
+    os::fd::BorrowedFd,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
-use crate::common::resolve::{is_valid_executable, resolve_current_user};
+use crate::common::resolve::{is_valid_executable, is_valid_shell, resolve_current_user};
 use crate::common::{error::Error, Environment};
-use crate::exec::RunOptions;
+use crate::exec::{OutputQuota, RunOptions};
+use crate::iolog::IoLogOptions;
 use crate::log::user_warn;
-use crate::system::{Group, Process, User};
+use crate::plugin::IoPlugin;
+use crate::system::{
+    capabilities::CapabilitySet, sandbox::SandboxProfile, signal::SignalNumber, term::Terminal,
+    Group, Process, User,
+};
 
 use super::cli::SuOptions;
 
-const VALID_LOGIN_SHELLS_LIST: &str = "/etc/shells";
-const FALLBACK_LOGIN_SHELL: &str = "/bin/sh";
-
 const PATH_MAILDIR: &str = env!("PATH_MAILDIR");
 const PATH_DEFAULT: &str = env!("SU_PATH_DEFAULT");
 const PATH_DEFAULT_ROOT: &str = env!("SU_PATH_DEFAULT_ROOT");
@@ -32,23 +37,16 @@ pub(crate) struct SuContext {
     pub(crate) process: Process,
 }
 
-/// check that a shell is not restricted / exists in /etc/shells
-fn is_restricted(shell: &Path) -> bool {
-    if let Some(pattern) = shell.as_os_str().to_str() {
-        if let Ok(contents) = fs::read_to_string(VALID_LOGIN_SHELLS_LIST) {
-            return !contents.lines().any(|l| l == pattern);
-        } else {
-            return FALLBACK_LOGIN_SHELL != pattern;
-        }
-    }
-
-    true
-}
-
 impl SuContext {
-    pub(crate) fn from_env(options: SuOptions) -> Result<SuContext, Error> {
+    pub(crate) fn from_env(mut options: SuOptions) -> Result<SuContext, Error> {
         let process = crate::system::Process::new();
 
+        // --preserve-environment has no effect on a login shell
+        if options.login && options.preserve_environment {
+            user_warn!("ignoring --preserve-environment, it's mutually exclusive with --login");
+            options.preserve_environment = false;
+        }
+
         // resolve environment, reset if this is a login
         let mut environment = if options.login {
             Environment::default()
@@ -93,20 +91,17 @@ impl SuContext {
         let mut group =
             Group::from_gid(user.gid)?.ok_or_else(|| Error::GroupNotFound(user.gid.to_string()))?;
 
-        if !options.supp_group.is_empty() || !options.group.is_empty() {
+        // when supplementary groups are given, they replace the groups of the target user
+        if !options.supp_group.is_empty() {
             user.groups.clear();
         }
 
-        for group_name in options.group.iter() {
-            let primary_group = Group::from_name(group_name)?
+        // the last --group argument is the primary group
+        if let Some(group_name) = options.group.last() {
+            group = Group::from_name(group_name)?
                 .ok_or_else(|| Error::GroupNotFound(group_name.to_owned()))?;
-
-            // last argument is the primary group
-            group = primary_group.clone();
-            user.groups.push(primary_group.gid);
         }
 
-        // add additional group if current user is root
         for (index, group_name) in options.supp_group.iter().enumerate() {
             let supp_group = Group::from_name(group_name)?
                 .ok_or_else(|| Error::GroupNotFound(group_name.to_owned()))?;
@@ -141,7 +136,7 @@ impl SuContext {
         // this user's entry in /etc/passwd is not listed in /etc/shells),
         // then the --shell option or the $SHELL environment variable won't be
         // taken into account, unless su is called by root.
-        if is_restricted(user_shell.as_path()) && !is_current_root {
+        if !is_valid_shell(user_shell.as_path()) && !is_current_root {
             user_warn!(
                 "using restricted shell {}",
                 user_shell.as_os_str().to_string_lossy()
@@ -157,9 +152,11 @@ impl SuContext {
             return Err(Error::InvalidCommand(command));
         }
 
-        // pass command to shell
+        // pass command to shell, any additional arguments are passed along as positional parameters
         let arguments = if let Some(command) = &options.command {
-            vec!["-c".to_owned(), command.to_owned()]
+            let mut arguments = vec!["-c".to_owned(), command.to_owned()];
+            arguments.extend(options.arguments.iter().cloned());
+            arguments
         } else {
             options.arguments.clone()
         };
@@ -242,7 +239,90 @@ impl RunOptions for SuContext {
     }
 
     fn use_pty(&self) -> bool {
-        true
+        // a login session gets its own pseudoterminal whenever su is run from a terminal, so the
+        // target user cannot inject input into the terminal of the invoking user
+        self.options.pty || (self.options.login && io::stdin().is_terminal())
+    }
+
+    fn exec_in_place(&self) -> bool {
+        // the PAM session of su has to be closed after the command exited
+        false
+    }
+
+    fn tty_signals(&self) -> bool {
+        false
+    }
+
+    fn tty_flow_control(&self) -> bool {
+        false
+    }
+
+    fn forward_signals(&self) -> Option<&[SignalNumber]> {
+        None
+    }
+
+    fn pty_wait_for_eof(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    fn session_reauth(&self) -> Option<Duration> {
+        None
+    }
+
+    fn idle_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    fn output_quota(&self) -> Option<OutputQuota> {
+        None
+    }
+
+    fn preserve_groups(&self) -> bool {
+        false
+    }
+
+    fn stay_setuid(&self) -> bool {
+        false
+    }
+
+    fn command_file(&self) -> Option<&File> {
+        None
+    }
+
+    fn capabilities(&self) -> Option<CapabilitySet> {
+        None
+    }
+
+    fn sandbox(&self) -> Option<&SandboxProfile> {
+        None
+    }
+
+    fn cgroup(&self) -> Option<&str> {
+        None
+    }
+
+    fn pty_group(&self) -> Option<&str> {
+        Some("tty")
+    }
+
+    fn io_plugins(&self) -> &[IoPlugin] {
+        &[]
+    }
+
+    fn iolog(&self) -> Option<&IoLogOptions> {
+        None
+    }
+
+    fn tee_output(&self) -> Option<&Path> {
+        None
+    }
+
+    fn tee_fd(&self) -> Option<BorrowedFd<'_>> {
+        None
+    }
+
+    fn session_uuid(&self) -> Option<&str> {
+        None
     }
 }
 
@@ -268,6 +348,25 @@ mod tests {
         assert_eq!(context.user.name, "root");
     }
 
+    #[test]
+    fn command_with_arguments() {
+        let options = get_options(&["-c", "echo $0 $1", "root", "foo", "bar"]);
+        let context = SuContext::from_env(options).unwrap();
+
+        assert_eq!(context.arguments, ["-c", "echo $0 $1", "foo", "bar"]);
+    }
+
+    #[test]
+    fn login_ignores_preserve_environment() {
+        let options = get_options(&["-l", "-p", "root"]);
+        let context = SuContext::from_env(options).unwrap();
+
+        assert!(!context.options.preserve_environment);
+        assert!(context
+            .environment
+            .contains_key(std::ffi::OsStr::new("HOME")));
+    }
+
     #[test]
     fn group_as_non_root() {
         let options = get_options(&["-g", "root"]);
@@ -1,10 +1,10 @@
-use crate::common::error::Error;
-use crate::exec::{ExecOutput, ExitReason, RunOptions};
-use crate::log::user_warn;
+use crate::common::{error::Error, messages, quote};
+use crate::exec::{ExecOutput, RunOptions};
+use crate::log::{dev_info, user_warn};
 use crate::pam::{CLIConverser, PamContext, PamError, PamErrorType};
 use crate::system::term::current_tty_name;
 
-use std::{env, process};
+use std::env;
 
 use cli::{SuAction, SuOptions};
 use context::SuContext;
@@ -59,7 +59,7 @@ fn authenticate(
                 if max_tries == 0 {
                     return Err(Error::MaxAuthAttempts(current_try));
                 } else {
-                    user_warn!("Authentication failed, try again.");
+                    user_warn!("{}", messages::bad_password(false));
                 }
             }
 
@@ -94,13 +94,18 @@ fn run(options: SuOptions) -> Result<(), Error> {
     let mut environment = context.environment.clone();
     environment.extend(pam.env()?);
 
-    let pid = context.process.pid;
+    dev_info!(
+        "running {} {}",
+        context.command()?.display(),
+        quote::join(context.arguments())
+    );
 
     // run command and return corresponding exit code
     let ExecOutput {
         command_exit_reason,
         restore_signal_handlers,
-    } = crate::exec::run_command(&context, environment)?;
+        ..
+    } = crate::exec::run_command(&context, environment, None)?;
 
     // closing the pam session is best effort, if any error occurs we cannot
     // do anything with it
@@ -109,17 +114,11 @@ fn run(options: SuOptions) -> Result<(), Error> {
     // Run any clean-up code before this line.
     restore_signal_handlers();
 
-    match command_exit_reason {
-        ExitReason::Code(code) => process::exit(code),
-        ExitReason::Signal(signal) => {
-            crate::system::kill(pid, signal)?;
-        }
-    }
-
-    Ok(())
+    command_exit_reason.exit_process()
 }
 
 pub fn main() {
+    crate::common::i18n::init_from_env();
     crate::log::SudoLogger::new("su: ").into_global_logger();
 
     let su_options = match SuOptions::from_env() {
@@ -139,20 +138,11 @@ pub fn main() {
             eprintln_ignore_io_error!("su-rs {VERSION}");
             std::process::exit(0);
         }
-        SuAction::Run => match run(su_options) {
-            Err(Error::CommandNotFound(c)) => {
-                eprintln_ignore_io_error!("su: {}", Error::CommandNotFound(c));
-                std::process::exit(127);
+        SuAction::Run => {
+            if let Err(error) = run(su_options) {
+                error.render("su", false);
+                std::process::exit(error.exit_code());
             }
-            Err(Error::InvalidCommand(c)) => {
-                eprintln_ignore_io_error!("su: {}", Error::InvalidCommand(c));
-                std::process::exit(126);
-            }
-            Err(error) => {
-                eprintln_ignore_io_error!("su: {error}");
-                std::process::exit(1);
-            }
-            _ => {}
-        },
+        }
     };
 }
@@ -0,0 +1,381 @@
+//! Loading of the front-end configuration in `/etc/sudo.conf`.
+//!
+//! This file uses the same syntax as the one of ogsudo, so distributions can ship their
+//! customary sudo.conf. Every line holds a single directive:
+//!
+//! ```text
+//! Path askpass /usr/libexec/ssh-askpass
+//! Path noexec /usr/libexec/sudo/sudo_noexec.so
+//! Path sudoers_cache /var/cache/sudo-rs/sudoers
+//! Set disable_coredump false
+//! Set group_source dynamic
+//! Set sudoers_backups 3
+//! Set rootless_container true
+//! Set policy doas
+//! Debug sudo /var/log/sudo_debug all@info
+//! Plugin sudoers_policy sudoers.so
+//! ```
+//!
+//! Unknown directives and settings are ignored, so a file that was written for ogsudo can be
+//! used as is; invalid values of known settings are reported and leave the default in place.
+//! Only I/O plugins are loaded, as the sudoers policy is built in; a policy plugin other than
+//! `sudoers_policy` is reported, and sudo then refuses to run.
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::system::{getgroups, interface::GroupId, max_groups, secure_open};
+
+pub const SUDO_CONF_PATH: &str = "/etc/sudo.conf";
+
+/// The library that keeps commands from running other programs, unless `Path noexec` says
+/// otherwise.
+pub const NOEXEC_PATH: &str = "/usr/libexec/sudo/sudo_noexec.so";
+
+/// The most backups of the sudoers file that `Set sudoers_backups` may ask visudo to keep.
+const MAX_SUDOERS_BACKUPS: u32 = 100;
+
+/// Where the supplementary groups of the invoking user are taken from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GroupSource {
+    /// The groups of the sudo process, i.e. those that the kernel knows about.
+    Static,
+    /// The groups in the group database.
+    Dynamic,
+    /// The groups of the sudo process, unless there are as many of them as the kernel allows;
+    /// in that case the list may have been truncated, and the group database is used instead.
+    #[default]
+    Adaptive,
+}
+
+impl GroupSource {
+    /// The supplementary groups of the invoking user, or `None` if they should be looked up in
+    /// the group database.
+    pub fn process_groups(self) -> io::Result<Option<Vec<GroupId>>> {
+        let groups = match self {
+            GroupSource::Dynamic => return Ok(None),
+            GroupSource::Static => getgroups()?,
+            GroupSource::Adaptive => {
+                let groups = getgroups()?;
+                if groups.len() >= max_groups() {
+                    return Ok(None);
+                }
+                groups
+            }
+        };
+
+        Ok(Some(groups))
+    }
+}
+
+/// Where the permissions of users are read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyFormat {
+    /// The sudoers file.
+    Sudoers,
+    /// The much simpler rules in `/etc/doas.conf`.
+    Doas,
+}
+
+/// doas.conf is the default when sudo-rs is built with the `doas` feature.
+impl Default for PolicyFormat {
+    fn default() -> Self {
+        if cfg!(feature = "doas") {
+            PolicyFormat::Doas
+        } else {
+            PolicyFormat::Sudoers
+        }
+    }
+}
+
+/// A `Debug program path flags` line; the flags are kept as they are written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugEntry {
+    pub program: String,
+    pub path: PathBuf,
+    pub flags: String,
+}
+
+/// A `Plugin symbol path [arguments]` line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginEntry {
+    pub symbol: String,
+    pub path: PathBuf,
+    pub arguments: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SudoConf {
+    // paths
+    pub askpass: Option<PathBuf>,
+    pub noexec: Option<PathBuf>,
+    pub sudoers_cache: Option<PathBuf>,
+    // settings
+    pub disable_coredump: bool,
+    pub group_source: GroupSource,
+    pub policy: PolicyFormat,
+    /// How many earlier versions of the sudoers file visudo keeps, as `/etc/sudoers.1` and on.
+    pub sudoers_backups: u32,
+    /// Whether root in a user namespace other than the initial one, e.g. in a rootless container,
+    /// may run sudo when it is not installed setuid root.
+    pub rootless_container: bool,
+    pub debug: Vec<DebugEntry>,
+    pub plugins: Vec<PluginEntry>,
+}
+
+impl Default for SudoConf {
+    fn default() -> Self {
+        Self {
+            askpass: None,
+            noexec: None,
+            sudoers_cache: None,
+            disable_coredump: true,
+            group_source: GroupSource::default(),
+            policy: PolicyFormat::default(),
+            sudoers_backups: 0,
+            rootless_container: false,
+            debug: Vec::new(),
+            plugins: Vec::new(),
+        }
+    }
+}
+
+/// A problem with a line in sudo.conf, along with its line number.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Warning(pub usize, pub String);
+
+impl SudoConf {
+    /// Read the configuration from `path`; a missing file results in the default configuration.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<(SudoConf, Vec<Warning>)> {
+        let mut text = String::new();
+        match secure_open(path, false) {
+            Ok(mut file) => file.read_to_string(&mut text)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err),
+        };
+
+        Ok(Self::parse(&text))
+    }
+
+    pub fn parse(text: &str) -> (SudoConf, Vec<Warning>) {
+        let mut conf = SudoConf::default();
+        let mut warnings = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            if let Err(message) = conf.parse_line(strip_comment(line)) {
+                warnings.push(Warning(number + 1, message));
+            }
+        }
+
+        (conf, warnings)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let mut words = line.split_whitespace();
+        let Some(directive) = words.next() else {
+            return Ok(());
+        };
+
+        match directive {
+            "Path" => {
+                let (Some(name), value) = (words.next(), words.next()) else {
+                    return Err("missing name in Path directive".to_string());
+                };
+                let path = match value {
+                    // an empty value resets the path to its default
+                    None => None,
+                    Some(value) if value.starts_with('/') => Some(PathBuf::from(value)),
+                    Some(value) => {
+                        return Err(format!("{name}: {value} must be a fully qualified path"))
+                    }
+                };
+                match name {
+                    "askpass" => self.askpass = path,
+                    "noexec" => self.noexec = path,
+                    "sudoers_cache" => self.sudoers_cache = path,
+                    _ => (),
+                }
+            }
+            "Set" => {
+                let (Some(name), Some(value)) = (words.next(), words.next()) else {
+                    return Err("missing name or value in Set directive".to_string());
+                };
+                let invalid = || format!("invalid value for {name}: {value}");
+                match name {
+                    "disable_coredump" => {
+                        self.disable_coredump = match value {
+                            "true" => true,
+                            "false" => false,
+                            _ => return Err(invalid()),
+                        }
+                    }
+                    "rootless_container" => {
+                        self.rootless_container = match value {
+                            "true" => true,
+                            "false" => false,
+                            _ => return Err(invalid()),
+                        }
+                    }
+                    "group_source" => {
+                        self.group_source = match value {
+                            "static" => GroupSource::Static,
+                            "dynamic" => GroupSource::Dynamic,
+                            "adaptive" => GroupSource::Adaptive,
+                            _ => return Err(invalid()),
+                        }
+                    }
+                    "policy" => {
+                        self.policy = match value {
+                            "sudoers" => PolicyFormat::Sudoers,
+                            "doas" => PolicyFormat::Doas,
+                            _ => return Err(invalid()),
+                        }
+                    }
+                    "sudoers_backups" => {
+                        self.sudoers_backups = value
+                            .parse()
+                            .ok()
+                            .filter(|&count| count <= MAX_SUDOERS_BACKUPS)
+                            .ok_or_else(invalid)?
+                    }
+                    _ => (),
+                }
+            }
+            "Debug" => {
+                let (Some(program), Some(path), Some(flags)) =
+                    (words.next(), words.next(), words.next())
+                else {
+                    return Err("a Debug directive needs a program, a path and flags".to_string());
+                };
+                self.debug.push(DebugEntry {
+                    program: program.to_string(),
+                    path: path.into(),
+                    flags: flags.to_string(),
+                });
+            }
+            "Plugin" => {
+                let (Some(symbol), Some(path)) = (words.next(), words.next()) else {
+                    return Err("a Plugin directive needs a symbol name and a path".to_string());
+                };
+                self.plugins.push(PluginEntry {
+                    symbol: symbol.to_string(),
+                    path: path.into(),
+                    arguments: words.map(str::to_string).collect(),
+                });
+                // the entry is kept, so that sudo refuses to run rather than ignore the policy
+                if symbol.ends_with("_policy") && symbol != "sudoers_policy" {
+                    return Err(format!(
+                        "{symbol}: only I/O plugins can be loaded, not policy plugins"
+                    ));
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
+
+/// A comment starts with a '#' at the start of a line or after white space.
+fn strip_comment(line: &str) -> &str {
+    let mut previous = ' ';
+    for (index, c) in line.char_indices() {
+        if c == '#' && previous.is_whitespace() {
+            return &line[..index];
+        }
+        previous = c;
+    }
+
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{DebugEntry, GroupSource, PluginEntry, PolicyFormat, SudoConf, Warning};
+
+    #[test]
+    fn parses_directives() {
+        let (conf, warnings) = SudoConf::parse(
+            "# sudo.conf as shipped by a distribution
+Path askpass /usr/libexec/ssh-askpass # for sudo -A
+Path noexec /usr/libexec/sudo/sudo_noexec.so
+Path sesh /usr/libexec/sudo/sesh
+Path sudoers_cache /var/cache/sudo-rs/sudoers
+Set disable_coredump false
+Set group_source static
+Set policy doas
+Set sudoers_backups 3
+Set rootless_container true
+Set probe_interfaces false
+
+Debug sudo /var/log/sudo_debug all@info,exec@debug
+Plugin sudoers_policy sudoers.so sudoers_file=/etc/sudoers#1
+Unknown directive
+",
+        );
+
+        assert_eq!(warnings, []);
+        assert_eq!(
+            conf,
+            SudoConf {
+                askpass: Some("/usr/libexec/ssh-askpass".into()),
+                noexec: Some("/usr/libexec/sudo/sudo_noexec.so".into()),
+                sudoers_cache: Some("/var/cache/sudo-rs/sudoers".into()),
+                disable_coredump: false,
+                group_source: GroupSource::Static,
+                policy: PolicyFormat::Doas,
+                sudoers_backups: 3,
+                rootless_container: true,
+                debug: vec![DebugEntry {
+                    program: "sudo".to_string(),
+                    path: "/var/log/sudo_debug".into(),
+                    flags: "all@info,exec@debug".to_string(),
+                }],
+                plugins: vec![PluginEntry {
+                    symbol: "sudoers_policy".to_string(),
+                    path: PathBuf::from("sudoers.so"),
+                    arguments: vec!["sudoers_file=/etc/sudoers#1".to_string()],
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn reports_invalid_values() {
+        let (conf, warnings) = SudoConf::parse(
+            "Set disable_coredump maybe
+Set group_source dynamic
+Path askpass ssh-askpass
+Debug sudo
+Set policy sudo
+Set sudoers_backups 1000
+Plugin ldap_policy ldap.so
+",
+        );
+
+        assert_eq!(
+            warnings,
+            [
+                Warning(1, "invalid value for disable_coredump: maybe".to_string()),
+                Warning(
+                    3,
+                    "askpass: ssh-askpass must be a fully qualified path".to_string()
+                ),
+                Warning(
+                    4,
+                    "a Debug directive needs a program, a path and flags".to_string()
+                ),
+                Warning(5, "invalid value for policy: sudo".to_string()),
+                Warning(6, "invalid value for sudoers_backups: 1000".to_string()),
+                Warning(
+                    7,
+                    "ldap_policy: only I/O plugins can be loaded, not policy plugins".to_string()
+                ),
+            ]
+        );
+        assert!(conf.disable_coredump);
+        assert_eq!(conf.group_source, GroupSource::Dynamic);
+        assert_eq!(conf.askpass, None);
+    }
+}
@@ -0,0 +1,207 @@
+//! `sudo --doctor`: check the installation of sudo for the problems that most often keep it from
+//! working, and say how to fix each of them.
+//!
+//! This runs before sudo checks that it is installed setuid root, as that is one of the problems it
+//! looks for; without it, files that only root may read are reported as unreadable.
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use crate::common::Error;
+use crate::sudoers::Sudoers;
+use crate::system::Group;
+
+use super::{candidate_sudoers_file, has_capabilities_instead_of_setuid, unmapped_owner_problem};
+
+/// The directory with the session records of the users.
+const TIMESTAMP_DIR: &str = "/run/sudo-rs/ts";
+
+/// The places where PAM looks for the configuration of a service.
+const PAM_DIRS: &[&str] = &["/etc/pam.d", "/usr/lib/pam.d", "/usr/share/pam.d"];
+
+/// The outcome of a single check.
+#[derive(Debug, PartialEq, Eq)]
+enum Finding {
+    Ok(String),
+    /// What is wrong, and what to do about it.
+    Problem(String),
+}
+
+/// A problem with the owner or permissions of a file that only root may change: `path` has to be
+/// owned by root, and may have no other permissions than those in `allowed`.
+fn ownership_problem(path: &Path, uid: u32, mode: u32, allowed: u32) -> Option<String> {
+    let path = path.display();
+    if uid != 0 {
+        Some(format!(
+            "{path} is not owned by root; run `chown root {path}`"
+        ))
+    } else if mode & 0o7777 & !allowed != 0 {
+        Some(format!(
+            "{path} has mode {:04o}; run `chmod {allowed:o} {path}`",
+            mode & 0o7777
+        ))
+    } else {
+        None
+    }
+}
+
+fn check_binary() -> Finding {
+    let path = match std::env::current_exe() {
+        Ok(path) => path,
+        Err(err) => return Finding::Problem(format!("cannot find the sudo binary: {err}")),
+    };
+    let shown = path.display();
+    match fs::metadata(&path) {
+        Err(err) => Finding::Problem(format!("cannot inspect {shown}: {err}")),
+        Ok(meta) if meta.uid() != 0 => Finding::Problem(
+            unmapped_owner_problem(&path, meta.uid()).unwrap_or_else(|| {
+                format!("{shown} is not owned by root; run `chown root {shown}`")
+            }),
+        ),
+        Ok(meta) if meta.mode() & 0o4000 == 0 && has_capabilities_instead_of_setuid() => {
+            Finding::Ok(format!(
+                "{shown} is owned by root and has the capabilities it needs"
+            ))
+        }
+        Ok(meta) if meta.mode() & 0o4000 == 0 => {
+            Finding::Problem(format!("{shown} is not setuid; run `chmod u+s {shown}`"))
+        }
+        Ok(_) => Finding::Ok(format!("{shown} is owned by root and setuid")),
+    }
+}
+
+fn check_sudoers(path: &Path) -> Vec<Finding> {
+    let shown = path.display();
+    let meta = match fs::metadata(path) {
+        Ok(meta) => meta,
+        Err(err) => return vec![Finding::Problem(format!("cannot inspect {shown}: {err}"))],
+    };
+    if let Some(problem) = ownership_problem(path, meta.uid(), meta.mode(), 0o440) {
+        return vec![Finding::Problem(problem)];
+    }
+
+    match Sudoers::open(path) {
+        Err(err) => vec![Finding::Problem(format!("cannot read {shown}: {err}"))],
+        Ok((_, errors)) if errors.is_empty() => {
+            vec![Finding::Ok(format!("{shown} is safe and has no errors"))]
+        }
+        Ok((_, errors)) => errors
+            .into_iter()
+            .map(|error| {
+                let source = error.source.as_deref().unwrap_or(path).display();
+                let location = match error.location {
+                    Some(range) => format!("{source}:{}:{}", range.start.0, range.start.1),
+                    None => source.to_string(),
+                };
+                Finding::Problem(format!(
+                    "{location}: {}; run `visudo` to fix it",
+                    error.message
+                ))
+            })
+            .collect(),
+    }
+}
+
+fn check_tty_group() -> Finding {
+    match Group::from_name("tty") {
+        Ok(Some(_)) => Finding::Ok("the group tty exists".to_string()),
+        Ok(None) => Finding::Problem(
+            "the group tty does not exist, so the terminals that sudo gives to commands cannot be \
+             written to by others; create it with `groupadd --system tty`, or choose another \
+             group with `Defaults pty_group`"
+                .to_string(),
+        ),
+        Err(err) => Finding::Problem(format!("cannot look up the group tty: {err}")),
+    }
+}
+
+fn check_pam_service(service: &str) -> Finding {
+    let found = PAM_DIRS
+        .iter()
+        .map(|dir| Path::new(dir).join(service))
+        .find(|path| path.exists());
+    match found {
+        Some(path) => Finding::Ok(format!(
+            "the PAM service {service} is configured in {}",
+            path.display()
+        )),
+        None if Path::new("/etc/pam.conf").exists() => Finding::Ok(format!(
+            "the PAM service {service} may be configured in /etc/pam.conf"
+        )),
+        None => Finding::Problem(format!(
+            "the PAM service {service} is not configured, so PAM falls back to the `other` \
+             service, which usually denies everyone; create /etc/pam.d/{service}"
+        )),
+    }
+}
+
+fn check_timestamp_dir() -> Finding {
+    let path = Path::new(TIMESTAMP_DIR);
+    match fs::metadata(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            Finding::Ok(format!("{TIMESTAMP_DIR} is created when it is needed"))
+        }
+        Err(err) => Finding::Problem(format!("cannot inspect {TIMESTAMP_DIR}: {err}")),
+        Ok(meta) => match ownership_problem(path, meta.uid(), meta.mode(), 0o700) {
+            Some(problem) => Finding::Problem(problem),
+            None => Finding::Ok(format!("{TIMESTAMP_DIR} is only accessible to root")),
+        },
+    }
+}
+
+/// Run every check and show its outcome; finding any problem is an error.
+pub(super) fn run() -> Result<(), Error> {
+    let mut findings = vec![check_binary()];
+    findings.extend(check_sudoers(candidate_sudoers_file()));
+    findings.push(check_tty_group());
+    findings.push(check_pam_service("sudo"));
+    findings.push(check_pam_service("sudo-i"));
+    findings.push(check_timestamp_dir());
+
+    let mut problems = 0;
+    for finding in &findings {
+        match finding {
+            Finding::Ok(message) => println_ignore_io_error!("ok:      {message}"),
+            Finding::Problem(message) => {
+                problems += 1;
+                println_ignore_io_error!("problem: {message}");
+            }
+        }
+    }
+
+    if problems == 0 {
+        Ok(())
+    } else {
+        println_ignore_io_error!("{problems} problem(s) found");
+        Err(Error::Silent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_unsafe_permissions() {
+        let path = Path::new("/etc/sudoers");
+        assert_eq!(ownership_problem(path, 0, 0o100440, 0o440), None);
+        assert_eq!(ownership_problem(path, 0, 0o100400, 0o440), None);
+        assert_eq!(
+            ownership_problem(path, 1000, 0o100440, 0o440).unwrap(),
+            "/etc/sudoers is not owned by root; run `chown root /etc/sudoers`"
+        );
+        assert_eq!(
+            ownership_problem(path, 0, 0o100664, 0o440).unwrap(),
+            "/etc/sudoers has mode 0664; run `chmod 440 /etc/sudoers`"
+        );
+        assert!(ownership_problem(Path::new("/run/sudo-rs/ts"), 0, 0o40777, 0o700).is_some());
+    }
+
+    #[test]
+    fn checks_sudoers() {
+        let findings = check_sudoers(Path::new("/nonexistent/sudoers"));
+        assert!(matches!(&findings[..], [Finding::Problem(message)]
+            if message.starts_with("cannot inspect /nonexistent/sudoers")));
+    }
+}
@@ -1,19 +1,32 @@
 #![forbid(unsafe_code)]
 
-use crate::cli::{help, SudoAction, SudoOptions};
-use crate::common::{resolve::resolve_current_user, Context, Error};
-use crate::log::dev_info;
+use crate::cli::{completions, help, SudoAction, SudoOptions};
+use crate::common::resolve::{resolve_current_user, resolve_target_user_and_group};
+use crate::common::{i18n, Context, Error};
+use crate::doas::{DoasConf, DOAS_CONF_PATH};
+use crate::exec::{self, TEE_FD_VAR};
+use crate::log::{auth_info, dev_info, user_warn, DebugLogger, SudoLogger};
+use crate::pam::CLIConverser;
+use crate::plugin::{builtin_approval_plugins, builtin_audit_plugins, IoPlugin};
+use crate::system::capabilities::CapabilitySet;
 use crate::system::timestamp::RecordScope;
-use crate::system::{time::Duration, timestamp::SessionRecordFile, Process};
+use crate::system::userns::{is_on_nosuid_mount, no_new_privs, overflow_uid, UidMap};
+use crate::system::{
+    disable_core_dumps, time::Duration, timestamp::SessionRecordFile, Process, User,
+};
+use conf::{PolicyFormat, SudoConf, Warning, SUDO_CONF_PATH};
 use pam::PamAuthenticator;
 use pipeline::{Pipeline, PolicyPlugin};
 use std::os::unix::fs::MetadataExt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::{env, fs};
 
-mod diagnostic;
+pub(crate) mod conf;
+pub(crate) mod diagnostic;
+mod doctor;
 mod pam;
 mod pipeline;
+mod version;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -29,7 +42,10 @@ fn candidate_sudoers_file() -> &'static Path {
 }
 
 #[derive(Default)]
-pub(crate) struct SudoersPolicy {}
+pub(crate) struct SudoersPolicy {
+    /// The cache of the parsed sudoers file, if it is enabled in sudo.conf.
+    sudoers_cache: Option<PathBuf>,
+}
 
 impl PolicyPlugin for SudoersPolicy {
     type PreJudgementPolicy = crate::sudoers::Sudoers;
@@ -38,11 +54,20 @@ impl PolicyPlugin for SudoersPolicy {
     fn init(&mut self) -> Result<Self::PreJudgementPolicy, Error> {
         let sudoers_path = candidate_sudoers_file();
 
-        let (sudoers, syntax_errors) = crate::sudoers::Sudoers::open(sudoers_path)
-            .map_err(|e| Error::Configuration(format!("{e}")))?;
+        let (sudoers, syntax_errors) = match &self.sudoers_cache {
+            Some(cache) => crate::sudoers::Sudoers::open_cached(sudoers_path, cache),
+            None => crate::sudoers::Sudoers::open(sudoers_path),
+        }
+        .map_err(|e| Error::Configuration(format!("{e}")))?;
 
-        for crate::sudoers::Error(pos, error) in syntax_errors {
-            diagnostic::diagnostic!("{error}", sudoers_path @ pos);
+        for crate::sudoers::Error {
+            source,
+            location,
+            message,
+        } in syntax_errors
+        {
+            let path = source.as_deref().unwrap_or(sudoers_path);
+            diagnostic::diagnostic!("{message}", path @ location);
         }
 
         Ok(sudoers)
@@ -66,18 +91,156 @@ impl PolicyPlugin for SudoersPolicy {
     }
 }
 
+/// The rules in doas.conf, which are used instead of the sudoers file with `Set policy doas` in
+/// sudo.conf.
+pub(crate) struct DoasPolicy;
+
+impl PolicyPlugin for DoasPolicy {
+    type PreJudgementPolicy = DoasConf;
+    type Policy = crate::doas::Judgement;
+
+    fn init(&mut self) -> Result<Self::PreJudgementPolicy, Error> {
+        let (conf, errors) = DoasConf::open(DOAS_CONF_PATH)
+            .map_err(|e| Error::Configuration(format!("{DOAS_CONF_PATH}: {e}")))?;
+
+        for crate::doas::Error(line, message) in errors {
+            diagnostic::diagnostic!("{DOAS_CONF_PATH}:{line}: {message}");
+        }
+
+        Ok(conf)
+    }
+
+    fn judge(
+        &mut self,
+        pre: Self::PreJudgementPolicy,
+        context: &Context,
+    ) -> Result<Self::Policy, Error> {
+        // doas only switches to a user along with the group of that user, there is no `-g`
+        if context.target_group.gid != context.target_user.gid {
+            return Ok(Default::default());
+        }
+
+        Ok(pre.check(
+            &context.current_user,
+            crate::sudoers::Request {
+                user: &context.target_user,
+                group: &context.target_group,
+                command: &context.command.command,
+                arguments: &context.command.arguments,
+            },
+        ))
+    }
+}
+
 fn sudo_process() -> Result<(), Error> {
-    crate::log::SudoLogger::new("sudo: ").into_global_logger();
+    // before any file is opened, which the file descriptor could refer to otherwise
+    let tee_fd = exec::take_inherited_fd();
+
+    i18n::init_from_env();
+
+    // completion scripts are generated when packaging, before sudo is installed setuid
+    if let Ok(SudoOptions {
+        action: SudoAction::Completions(shell),
+        ..
+    }) = SudoOptions::from_env()
+    {
+        let script = completions::generate(&shell).map_err(Error::Options)?;
+        println_ignore_io_error!("{script}");
+        return Ok(());
+    }
+
+    let conf = load_sudo_conf();
+
+    let mut logger = SudoLogger::new("sudo: ");
+    // the sudoers policy is built in, so its debug output goes along with that of sudo itself
+    for entry in conf.debug.iter() {
+        if !matches!(entry.program.as_str(), "sudo" | "sudoers.so") {
+            continue;
+        }
+        match DebugLogger::open("sudo", &entry.path, &entry.flags) {
+            Ok(debug_logger) => logger.add_debug_logger(debug_logger),
+            Err(err) => diagnostic::diagnostic!("{SUDO_CONF_PATH}: {err}"),
+        }
+    }
+    logger.into_global_logger();
 
     dev_info!("development logs are enabled");
 
-    self_check()?;
+    // the installation is checked before sudo refuses to run because of a problem with it
+    if let Ok(SudoOptions {
+        action: SudoAction::Doctor,
+        ..
+    }) = SudoOptions::from_env()
+    {
+        return doctor::run();
+    }
 
-    let pipeline = Pipeline {
-        policy: SudoersPolicy::default(),
-        authenticator: PamAuthenticator::new_cli(),
-    };
+    self_check(&conf)?;
+
+    if conf.disable_coredump {
+        disable_core_dumps()?;
+    }
+
+    let tee_fd = tee_fd.unwrap_or_else(|err| {
+        user_warn!("ignoring {TEE_FD_VAR}: {err}");
+        None
+    });
+
+    let io_plugins = load_io_plugins(&conf)?;
+    match conf.policy {
+        PolicyFormat::Sudoers => {
+            let policy = SudoersPolicy {
+                sudoers_cache: conf.sudoers_cache.clone(),
+            };
+            run_action(
+                &conf,
+                pipeline(policy, &conf, io_plugins, tee_fd),
+                Pipeline::run_list,
+            )
+        }
+        PolicyFormat::Doas => run_action(
+            &conf,
+            pipeline(DoasPolicy, &conf, io_plugins, tee_fd),
+            |_, _| {
+                Err(Error::Options(
+                    "listing privileges is not supported with doas.conf".to_string(),
+                ))
+            },
+        ),
+    }
+}
 
+/// A pipeline that authenticates the invoking user on the command line.
+type CliPipeline<Policy> = Pipeline<Policy, PamAuthenticator<CLIConverser>>;
+
+fn pipeline<Policy: PolicyPlugin>(
+    policy: Policy,
+    conf: &SudoConf,
+    io_plugins: Vec<IoPlugin>,
+    tee_fd: Option<std::os::fd::OwnedFd>,
+) -> CliPipeline<Policy> {
+    Pipeline {
+        policy,
+        authenticator: PamAuthenticator::new_cli(conf.askpass.clone()),
+        group_source: conf.group_source,
+        io_plugins,
+        approval_plugins: builtin_approval_plugins(),
+        audit_plugins: builtin_audit_plugins(),
+        tee_fd,
+        noexec: conf
+            .noexec
+            .clone()
+            .unwrap_or_else(|| conf::NOEXEC_PATH.into()),
+    }
+}
+
+/// Carry out what the command line asks for; `list` lists the privileges of a user, which
+/// depends on the policy.
+fn run_action<Policy: PolicyPlugin>(
+    conf: &SudoConf,
+    pipeline: CliPipeline<Policy>,
+    list: fn(CliPipeline<Policy>, SudoOptions) -> Result<(), Error>,
+) -> Result<(), Error> {
     // parse cli options
     match SudoOptions::from_env() {
         Ok(options) => match options.action {
@@ -86,16 +249,16 @@ fn sudo_process() -> Result<(), Error> {
                 std::process::exit(0);
             }
             SudoAction::Version => {
-                eprintln_ignore_io_error!("sudo-rs {VERSION}");
+                version::print_version(VERSION, conf, options.json);
                 std::process::exit(0);
             }
-            SudoAction::RemoveTimestamp => {
-                let user = resolve_current_user()?;
-                let mut record_file =
-                    SessionRecordFile::open_for_user(&user.name, Duration::seconds(0))?;
-                record_file.reset()?;
-                Ok(())
+            SudoAction::Completions(_) => {
+                unreachable!("completion scripts are printed before anything else is done")
             }
+            SudoAction::Doctor => {
+                unreachable!("the installation is checked before sudo checks itself")
+            }
+            SudoAction::RemoveTimestamp => remove_session_records(&options.user),
             SudoAction::ResetTimestamp => {
                 if let Some(scope) = RecordScope::for_process(&Process::new()) {
                     let user = resolve_current_user()?;
@@ -115,31 +278,186 @@ fn sudo_process() -> Result<(), Error> {
                     pipeline.run(options)
                 }
             }
-            SudoAction::List(_) => pipeline.run_list(options),
+            SudoAction::List(_) => list(pipeline, options),
+            SudoAction::Check(ref cmd) => {
+                if cmd.is_empty() && !options.shell && !options.login {
+                    eprintln_ignore_io_error!("{}", help::USAGE_MSG);
+                    std::process::exit(1);
+                } else {
+                    pipeline.run_check(options)
+                }
+            }
             SudoAction::Edit(_) => {
                 unimplemented!();
             }
         },
         Err(e) => {
-            eprintln_ignore_io_error!("{e}\n{}", help::USAGE_MSG);
+            // in the words of ogsudo, for scripts that look for them
+            eprintln_ignore_io_error!("sudo: {e}\n{}", help::USAGE_MSG);
             std::process::exit(1);
         }
     }
 }
 
-fn self_check() -> Result<(), Error> {
+/// Remove every session record of the invoking user, or with `-u` of another user, which only
+/// root may do: this is how an administrator makes a user authenticate again right away.
+fn remove_session_records(other_user: &Option<String>) -> Result<(), Error> {
+    let current_user = resolve_current_user()?;
+    let user = if other_user.is_some() {
+        let (user, _) = resolve_target_user_and_group(other_user, &None, &current_user, false)?;
+        if user.uid != current_user.uid && current_user.uid != 0 {
+            return Err(Error::Options(
+                "only root can remove the session records of other users".to_string(),
+            ));
+        }
+        user
+    } else {
+        current_user
+    };
+
+    let mut record_file = SessionRecordFile::open_for_user(&user.name, Duration::seconds(0))?;
+    record_file.reset()?;
+    if other_user.is_some() {
+        auth_info!("removed the session records of user {}", user.name);
+    }
+
+    Ok(())
+}
+
+/// Load sudo.conf; problems with it are reported, but do not prevent sudo from running.
+fn load_sudo_conf() -> SudoConf {
+    let conf = match SudoConf::open(SUDO_CONF_PATH) {
+        Ok((conf, warnings)) => {
+            for Warning(line, message) in warnings {
+                diagnostic::diagnostic!("{SUDO_CONF_PATH}:{line}: {message}");
+            }
+            conf
+        }
+        Err(err) => {
+            diagnostic::diagnostic!("unable to load {SUDO_CONF_PATH}: {err}");
+            SudoConf::default()
+        }
+    };
+
+    conf
+}
+
+/// Load the I/O plugins declared in sudo.conf; the sudoers plugins are built in.
+///
+/// A plugin that cannot be loaded is fatal, as it may be required for e.g. auditing.
+fn load_io_plugins(conf: &SudoConf) -> Result<Vec<IoPlugin>, Error> {
+    conf.plugins
+        .iter()
+        .filter(|plugin| {
+            !matches!(
+                plugin.symbol.as_str(),
+                "sudoers_policy" | "sudoers_io" | "sudoers_audit"
+            )
+        })
+        .map(|plugin| {
+            IoPlugin::load(&plugin.symbol, &plugin.path, &plugin.arguments).map_err(Error::Plugin)
+        })
+        .collect()
+}
+
+/// The problem with a sudo binary at `path` that is owned by `uid` in a user namespace, if that
+/// owner is not mapped into the namespace, so that the binary cannot be setuid root there.
+fn unmapped_owner_problem(path: &Path, uid: u32) -> Option<String> {
+    if uid != overflow_uid() || UidMap::current().is_none() {
+        return None;
+    }
+
+    let mut problem = format!(
+        "{} is owned by a user that is not mapped into this user namespace, so it cannot be \
+         setuid root here; install sudo inside the container",
+        path.display()
+    );
+    if User::real_uid() == 0 {
+        problem.push_str(", or set `Set rootless_container true` in /etc/sudo.conf");
+    }
+
+    Some(problem)
+}
+
+/// Whether sudo was given the capabilities it needs with `setcap`, instead of the setuid bit.
+///
+/// This looks at the binary rather than at the capabilities of this process, which may also have
+/// been inherited from whoever started sudo.
+fn has_capabilities_instead_of_setuid() -> bool {
+    CapabilitySet::of_file(Path::new("/proc/self/exe"))
+        .is_ok_and(|caps| caps.includes(CapabilitySet::INSTEAD_OF_SETUID))
+}
+
+fn self_check(conf: &SudoConf) -> Result<(), Error> {
     const ROOT: u32 = 0;
     const SETUID_BIT: u32 = 0o4000;
 
     let path = env::current_exe().map_err(|e| Error::IoError(None, e))?;
-    let metadata = fs::metadata(path).map_err(|e| Error::IoError(None, e))?;
+    let metadata = fs::metadata(&path).map_err(|e| Error::IoError(None, e))?;
+
+    // root in a rootless container has nothing to gain from the setuid bit
+    if conf.rootless_container
+        && UidMap::current().is_some()
+        && User::real_uid() == ROOT
+        && User::effective_uid() == ROOT
+    {
+        return Ok(());
+    }
 
     let owned_by_root = metadata.uid() == ROOT;
     let setuid_bit_is_set = metadata.mode() & SETUID_BIT != 0;
-    if owned_by_root && setuid_bit_is_set {
-        Ok(())
+
+    // sudo can be given the capabilities it needs instead of the setuid bit; as they make it
+    // root in name only, these are all the privileges it has
+    if owned_by_root && !setuid_bit_is_set && has_capabilities_instead_of_setuid() {
+        if !CapabilitySet::permitted()
+            .is_ok_and(|caps| caps.includes(CapabilitySet::INSTEAD_OF_SETUID))
+        {
+            return Err(Error::Unprivileged(ineffective_privileges(
+                &path,
+                "file capabilities",
+            )));
+        }
+        return CapabilitySet::become_root().map_err(|e| Error::IoError(None, e));
+    }
+
+    if !(owned_by_root && setuid_bit_is_set) {
+        return Err(match unmapped_owner_problem(&path, metadata.uid()) {
+            Some(problem) => Error::Unprivileged(problem),
+            None => Error::SelfCheck {
+                path,
+                uid: metadata.uid(),
+                mode: metadata.mode(),
+            },
+        });
+    }
+
+    if User::effective_uid() != ROOT {
+        return Err(Error::Unprivileged(ineffective_privileges(
+            &path,
+            "setuid bit",
+        )));
+    }
+
+    Ok(())
+}
+
+/// Why the `privileges` of the sudo binary at `path` were not granted when it was started.
+fn ineffective_privileges(path: &Path, privileges: &str) -> String {
+    if no_new_privs() {
+        format!(
+            "it was started with the no_new_privs flag set, which keeps its {privileges} from \
+             taking effect; this flag is set by e.g. `--security-opt no-new-privileges` of \
+             container runtimes and `NoNewPrivileges=` of systemd"
+        )
+    } else if is_on_nosuid_mount(path) {
+        format!(
+            "{} is on a file system that is mounted nosuid, which keeps its {privileges} from \
+             taking effect",
+            path.display()
+        )
     } else {
-        Err(Error::SelfCheck)
+        format!("its {privileges} did not take effect")
     }
 }
 
@@ -147,10 +465,9 @@ pub fn main() {
     match sudo_process() {
         Ok(()) => (),
         Err(error) => {
-            if !error.is_silent() {
-                diagnostic::diagnostic!("{error}");
-            }
-            std::process::exit(1);
+            let as_json = SudoOptions::from_env().is_ok_and(|options| options.json);
+            error.render("sudo-rs", as_json);
+            std::process::exit(error.exit_code());
         }
     }
 }
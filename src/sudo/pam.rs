@@ -1,8 +1,10 @@
 use std::collections::HashMap;
 use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 
 use crate::common::context::LaunchType;
-use crate::common::{error::Error, Context};
+use crate::common::{error::Error, messages, Context};
+use crate::exec::Reauthenticate;
 use crate::log::{dev_info, user_warn};
 use crate::pam::{CLIConverser, Converser, PamContext, PamError, PamErrorType, PamResult};
 use crate::system::term::current_tty_name;
@@ -27,39 +29,86 @@ impl<C: Converser> PamAuthenticator<C> {
     }
 }
 
+/// The environment variable with the askpass helper program of the invoking user.
+const ASKPASS_VAR: &str = "SUDO_ASKPASS";
+
 impl PamAuthenticator<CLIConverser> {
-    pub fn new_cli() -> PamAuthenticator<CLIConverser> {
-        PamAuthenticator::new(|context| {
+    /// Authenticate on the terminal, or with the helper program in `SUDO_ASKPASS` or else
+    /// `askpass`, the one of sudo.conf.
+    pub fn new_cli(askpass: Option<PathBuf>) -> PamAuthenticator<CLIConverser> {
+        PamAuthenticator::new(move |context| {
             init_pam(
                 matches!(context.launch, LaunchType::Login),
                 matches!(context.launch, LaunchType::Shell),
                 context.stdin,
                 context.non_interactive,
-                &context.current_user.name,
+                askpass_helper(std::env::var_os(ASKPASS_VAR), askpass.as_deref()),
+                context.askpass,
+                context.prompt.as_deref().map(|prompt| {
+                    expand_prompt(
+                        prompt,
+                        &context.hostname,
+                        &context.auth_user.name,
+                        &context.current_user.name,
+                        &context.target_user.name,
+                    )
+                }),
+                &context.auth_user.name,
                 &context.current_user.name,
             )
         })
     }
 }
 
-impl<C: Converser> AuthPlugin for PamAuthenticator<C> {
+impl<C: Converser + 'static> AuthPlugin for PamAuthenticator<C> {
     fn init(&mut self, context: &Context) -> Result<(), Error> {
         self.pam = Some((self.builder)(context)?);
         Ok(())
     }
 
-    fn authenticate(&mut self, non_interactive: bool, max_tries: u16) -> Result<(), Error> {
+    fn authenticate(
+        &mut self,
+        non_interactive: bool,
+        max_tries: u16,
+        insults: bool,
+    ) -> Result<(), Error> {
         let pam = self
             .pam
             .as_mut()
             .expect("Pam must be initialized before authenticate");
 
-        attempt_authenticate(pam, non_interactive, max_tries)?;
+        attempt_authenticate(pam, non_interactive, max_tries, insults)?;
 
         Ok(())
     }
 
-    fn pre_exec(&mut self, target_user: &str) -> Result<HashMap<OsString, OsString>, Error> {
+    fn reauthenticator(
+        &self,
+        context: &Context,
+        max_tries: u16,
+        insults: bool,
+    ) -> Result<Reauthenticate, Error> {
+        // a PAM handle of its own, as the one that opened the session now belongs to the target
+        // user
+        let mut pam = (self.builder)(context)?;
+        let non_interactive = context.non_interactive;
+
+        Ok(Box::new(move || {
+            match attempt_authenticate(&mut pam, non_interactive, max_tries, insults) {
+                Ok(()) => true,
+                Err(err) => {
+                    user_warn!("{err}");
+                    false
+                }
+            }
+        }))
+    }
+
+    fn pre_exec(
+        &mut self,
+        target_user: &str,
+        open_session: bool,
+    ) -> Result<HashMap<OsString, OsString>, Error> {
         let pam = self
             .pam
             .as_mut()
@@ -84,7 +133,9 @@ impl<C: Converser> AuthPlugin for PamAuthenticator<C> {
             }
         }
 
-        pam.open_session()?;
+        if open_session {
+            pam.open_session()?;
+        }
 
         let env_vars = pam.env()?;
 
@@ -103,16 +154,74 @@ impl<C: Converser> AuthPlugin for PamAuthenticator<C> {
     }
 }
 
+/// The askpass helper: that of the invoking user, or else that of sudo.conf. As the helper is
+/// started by a path, it has to be an absolute one.
+fn askpass_helper(user_askpass: Option<OsString>, askpass: Option<&Path>) -> Option<PathBuf> {
+    match user_askpass.map(PathBuf::from) {
+        Some(path) if path.is_absolute() => Some(path),
+        Some(path) => {
+            user_warn!(
+                "ignoring {ASKPASS_VAR}: {} is not an absolute path",
+                path.display()
+            );
+            askpass.map(Path::to_path_buf)
+        }
+        None => askpass.map(Path::to_path_buf),
+    }
+}
+
+/// Expand the escapes in a prompt given with `-p`, like ogsudo does: `%H` is the host name,
+/// `%h` the host name without its domain, `%p` the user whose password is asked for, `%u` the
+/// invoking user, `%U` the target user and `%%` is a single `%`.
+fn expand_prompt(
+    prompt: &str,
+    hostname: &str,
+    auth_user: &str,
+    current_user: &str,
+    target_user: &str,
+) -> String {
+    let mut expanded = String::with_capacity(prompt.len());
+    let mut chars = prompt.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('H') => expanded.push_str(hostname),
+            Some('h') => expanded.push_str(hostname.split('.').next().unwrap_or(hostname)),
+            Some('p') => expanded.push_str(auth_user),
+            Some('u') => expanded.push_str(current_user),
+            Some('U') => expanded.push_str(target_user),
+            Some('%') => expanded.push('%'),
+            // other escapes are left as they are
+            Some(other) => {
+                expanded.push('%');
+                expanded.push(other);
+            }
+            None => expanded.push('%'),
+        }
+    }
+
+    expanded
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn init_pam(
     is_login_shell: bool,
     is_shell: bool,
     use_stdin: bool,
     non_interactive: bool,
+    askpass: Option<PathBuf>,
+    use_askpass: bool,
+    prompt: Option<String>,
     auth_user: &str,
     requesting_user: &str,
 ) -> PamResult<PamContext<CLIConverser>> {
     let service_name = if is_login_shell { "sudo-i" } else { "sudo" };
     let mut pam = PamContext::builder_cli("sudo", use_stdin, non_interactive)
+        .askpass(askpass, use_askpass)
+        .prompt(prompt)
         .service_name(service_name)
         .build()?;
     pam.mark_silent(!is_shell && !is_login_shell);
@@ -132,6 +241,7 @@ pub fn attempt_authenticate<C: Converser>(
     pam: &mut PamContext<C>,
     non_interactive: bool,
     mut max_tries: u16,
+    insults: bool,
 ) -> Result<(), Error> {
     let mut current_try = 0;
     loop {
@@ -153,10 +263,19 @@ pub fn attempt_authenticate<C: Converser>(
                 } else if non_interactive {
                     return Err(Error::Authentication("interaction required".to_string()));
                 } else {
-                    user_warn!("Authentication failed, try again.");
+                    user_warn!("{}", messages::bad_password(insults));
                 }
             }
 
+            Err(PamError::InteractionRequired) => {
+                return Err(Error::Authentication("interaction required".to_string()));
+            }
+
+            // the password could not be asked for, e.g. as there is no terminal
+            Err(PamError::IoError(err)) => {
+                return Err(Error::Authentication(err.to_string()));
+            }
+
             // there was another pam error, return the error
             Err(e) => {
                 return Err(e.into());
@@ -166,3 +285,20 @@ pub fn attempt_authenticate<C: Converser>(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::expand_prompt;
+
+    #[test]
+    fn expands_prompt_escapes() {
+        let expand = |prompt| expand_prompt(prompt, "box.example.org", "root", "ferris", "www");
+
+        assert_eq!(expand("Password: "), "Password: ");
+        assert_eq!(
+            expand("[sudo] %p@%h (%H) for %u as %U: "),
+            "[sudo] root@box (box.example.org) for ferris as www: "
+        );
+        assert_eq!(expand("100%% sure? %x %"), "100% sure? %x %");
+    }
+}
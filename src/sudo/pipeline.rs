@@ -1,19 +1,36 @@
 use std::ffi::OsStr;
-use std::process::exit;
+use std::fs;
+use std::io;
+use std::os::fd::OwnedFd;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::time::Instant;
 
 use crate::cli::SudoOptions;
-use crate::common::{resolve::expand_tilde_in_path, Context, Environment, Error};
+use crate::common::lookup::exec_error;
+use crate::common::resolve::{expand_tilde_in_path, is_valid_shell};
+use crate::common::{context::LaunchType, style, Context, Environment, Error};
 use crate::env::environment;
-use crate::exec::{ExecOutput, ExitReason};
-use crate::log::{auth_info, auth_warn};
+use crate::exec::{ExecOutput, ExitReason, Reauthenticate};
+use crate::log::{auth_info, auth_warn, fold_repeated_messages, send_syslog_to, user_warn};
+use crate::plugin::{ApprovalPlugin, AuditPlugin, IoPlugin};
+use crate::sudo::conf::GroupSource;
 use crate::sudo::Duration;
-use crate::sudoers::{Authorization, AuthorizationAllowed, DirChange, Policy, PreJudgementPolicy};
+use crate::sudoers::{
+    Authorization, AuthorizationAllowed, DirChange, NestedSudo, Policy, PreJudgementPolicy,
+};
 use crate::system::interface::UserId;
+use crate::system::sandbox::SandboxProfile;
+use crate::system::signal::{signal_name, signal_number};
 use crate::system::term::current_tty_name;
 use crate::system::timestamp::{RecordScope, SessionRecordFile, TouchResult};
-use crate::system::{escape_os_str_lossy, Process};
+use crate::system::wait::ResourceUsage;
+use crate::system::{dns, escape_os_str_lossy, Process, User};
 
+mod check;
 mod list;
+mod nested;
+mod plugins;
 
 pub trait PolicyPlugin {
     type PreJudgementPolicy: PreJudgementPolicy;
@@ -29,54 +46,160 @@ pub trait PolicyPlugin {
 
 pub trait AuthPlugin {
     fn init(&mut self, context: &Context) -> Result<(), Error>;
-    fn authenticate(&mut self, non_interactive: bool, max_tries: u16) -> Result<(), Error>;
-    fn pre_exec(&mut self, target_user: &str) -> Result<Environment, Error>;
+    fn authenticate(
+        &mut self,
+        non_interactive: bool,
+        max_tries: u16,
+        insults: bool,
+    ) -> Result<(), Error>;
+    /// Something that authenticates the invoking user again while the command runs, the way
+    /// `authenticate` did.
+    fn reauthenticator(
+        &self,
+        context: &Context,
+        max_tries: u16,
+        insults: bool,
+    ) -> Result<Reauthenticate, Error>;
+    fn pre_exec(&mut self, target_user: &str, open_session: bool) -> Result<Environment, Error>;
     fn cleanup(&mut self);
 }
 
 pub struct Pipeline<Policy: PolicyPlugin, Auth: AuthPlugin> {
     pub policy: Policy,
     pub authenticator: Auth,
+    pub group_source: GroupSource,
+    pub io_plugins: Vec<IoPlugin>,
+    pub approval_plugins: Vec<Box<dyn ApprovalPlugin>>,
+    pub audit_plugins: Vec<Box<dyn AuditPlugin>>,
+    /// The file descriptor in `SUDO_TEE_FD`, taken when sudo started.
+    pub tee_fd: Option<OwnedFd>,
+    /// The library that is preloaded into commands that may not run other programs.
+    pub noexec: PathBuf,
 }
 
 impl<Policy: PolicyPlugin, Auth: AuthPlugin> Pipeline<Policy, Auth> {
     pub fn run(mut self, cmd_opts: SudoOptions) -> Result<(), Error> {
         let pre = self.policy.init()?;
-        let mut context = build_context(cmd_opts, &pre)?;
+        let mut context = build_context(cmd_opts, &pre, self.group_source)?;
 
         let policy = self.policy.judge(pre, &context)?;
+
+        // fold repeated messages in the system log if indicated
+        fold_repeated_messages(policy.syslog_dedup_window(), &context.current_user.name);
+        // and send them to a syslog collector instead of the local system log if indicated
+        if let Some(server) = policy.syslog_server() {
+            send_syslog_to(server);
+        }
+
         let authorization = policy.authorization();
 
-        match authorization {
+        let reauth_with = match authorization {
             Authorization::Forbidden => {
+                #[cfg(feature = "linux-audit")]
+                audit_command(&context, false);
+                for plugin in &mut self.audit_plugins {
+                    plugin.reject(&context, "command not allowed");
+                }
+
                 return Err(Error::auth(&format!(
                     "I'm sorry {}. I'm afraid I can't do that",
                     context.current_user.name
                 )));
             }
             Authorization::Allowed(auth) => {
+                // a command that may be run without a password is not asked for one later either
+                let reauth_with = auth
+                    .must_authenticate
+                    .then_some((auth.allowed_attempts, auth.insults));
                 self.apply_policy_to_context(&mut context, &policy)?;
                 self.auth_and_update_record_file(&context, auth)?;
+                reauth_with
             }
-        }
+        };
 
-        let additional_env = self.authenticator.pre_exec(&context.target_user.name)?;
+        let additional_env = self
+            .authenticator
+            .pre_exec(&context.target_user.name, context.pam_session)?;
 
         // build environment
         let current_env = std::env::vars_os().collect();
-        let target_env =
-            environment::get_target_environment(current_env, additional_env, &context, &policy);
+        let mut target_env =
+            environment::get_target_environment(current_env, additional_env, &context, &policy)?;
 
-        let pid = context.process.pid;
+        // preload the library that does so into the command
+        if context.noexec {
+            environment::preload_noexec(&mut target_env, &self.noexec)?;
+        }
+
+        // ask the socket or program that approves commands first if indicated
+        if let Some(approval) = policy.approval() {
+            self.approval_plugins.insert(0, Box::new(approval));
+        }
+
+        // every approval plugin has to approve the command before it is run
+        plugins::approve(
+            &mut self.approval_plugins,
+            &mut self.audit_plugins,
+            &context,
+            &target_env,
+        )?;
+
+        // every I/O plugin has to accept the command before it is run
+        for plugin in &self.io_plugins {
+            if !plugin.open(&context, &target_env).map_err(Error::Plugin)? {
+                return Err(Error::Plugin(format!(
+                    "{}: command rejected by I/O plugin",
+                    plugin.name()
+                )));
+            }
+        }
+        context.io_plugins = std::mem::take(&mut self.io_plugins);
+        context.tee_fd = self.tee_fd.take();
+
+        // ask the invoking user to authenticate again every so often while the command runs if
+        // indicated
+        let reauth = match (context.session_reauth, reauth_with) {
+            (Some(_), Some((max_tries, insults))) => Some(
+                self.authenticator
+                    .reauthenticator(&context, max_tries, insults)?,
+            ),
+            _ => {
+                context.session_reauth = None;
+                None
+            }
+        };
+
+        // without a PAM session to close, an I/O log to complete, plugins that want to know the
+        // exit status of the command, a control socket to serve, a session to authenticate again,
+        // or an idle session or output quota to enforce, sudo does not have to wait for the
+        // command, and can become the command instead
+        context.exec_in_place = !context.pam_session
+            && context.session_reauth.is_none()
+            && context.idle_timeout.is_none()
+            && context.output_quota.is_none()
+            && !cfg!(feature = "control-socket")
+            && context.iolog.is_none()
+            && context.io_plugins.is_empty()
+            && self.audit_plugins.is_empty()
+            && context.forward_signals.is_none()
+            && !context.log_exit_status
+            && !context.stats;
 
         // run command and return corresponding exit code
+        let started = Instant::now();
         let exec_result = if context.command.resolved {
-            log_command_execution(&context);
+            if policy.log_command() {
+                log_command_execution(&context);
+            }
+            #[cfg(feature = "linux-audit")]
+            audit_command(&context, true);
 
-            crate::exec::run_command(&context, target_env)
-                .map_err(|io_error| Error::IoError(Some(context.command.command), io_error))
+            plugins::run_audited(&mut self.audit_plugins, &context, || {
+                crate::exec::run_command(&context, target_env, reauth)
+                    .map_err(|io_error| exec_error(context.command.command.clone(), io_error))
+            })
         } else {
-            Err(Error::CommandNotFound(context.command.command))
+            Err(context.command.not_found())
         };
 
         self.authenticator.cleanup();
@@ -84,26 +207,44 @@ impl<Policy: PolicyPlugin, Auth: AuthPlugin> Pipeline<Policy, Auth> {
         let ExecOutput {
             command_exit_reason,
             restore_signal_handlers,
-        } = exec_result?;
+            resource_usage,
+        } = match exec_result {
+            Ok(output) => output,
+            Err(err) => {
+                for plugin in &context.io_plugins {
+                    plugin.close(0, libc::EIO);
+                }
+                return Err(err);
+            }
+        };
 
-        // Run any clean-up code before this line.
-        restore_signal_handlers();
+        // the plugins get the exit status in the format of `wait`
+        let status = match command_exit_reason {
+            ExitReason::Code(code) => (code & 0xff) << 8,
+            ExitReason::Signal(signal) => signal & 0x7f,
+        };
+        for plugin in &context.io_plugins {
+            plugin.close(status, 0);
+        }
 
-        match command_exit_reason {
-            ExitReason::Code(code) => exit(code),
-            ExitReason::Signal(signal) => {
-                crate::system::kill(pid, signal)?;
-            }
+        if context.log_exit_status {
+            log_command_exit(&context, &command_exit_reason, resource_usage.as_ref());
+        }
+        if context.stats {
+            print_stats(started.elapsed(), resource_usage.as_ref());
         }
 
-        Ok(())
+        // Run any clean-up code before this line.
+        restore_signal_handlers();
+
+        command_exit_reason.exit_process()
     }
 
     pub fn run_validate(mut self, cmd_opts: SudoOptions) -> Result<(), Error> {
         let pre = self.policy.init()?;
-        let context = build_context(cmd_opts, &pre)?;
+        let context = build_context(cmd_opts, &pre, self.group_source)?;
 
-        match pre.validate_authorization() {
+        match pre.validate_authorization(&context.current_user, &context.hostname) {
             Authorization::Forbidden => {
                 return Err(Error::auth(&format!(
                     "I'm sorry {}. I'm afraid I can't do that",
@@ -125,23 +266,32 @@ impl<Policy: PolicyPlugin, Auth: AuthPlugin> Pipeline<Policy, Auth> {
             must_authenticate,
             prior_validity,
             allowed_attempts,
+            insults,
         }: AuthorizationAllowed,
     ) -> Result<(), Error> {
         let scope = RecordScope::for_process(&Process::new());
         let mut auth_status = determine_auth_status(
             must_authenticate,
             context.use_session_records,
+            context.update_session_records,
             scope,
-            context.current_user.uid,
+            context.auth_user.uid,
             &context.current_user.name,
             prior_validity,
         );
         self.authenticator.init(context)?;
         if auth_status.must_authenticate {
-            self.authenticator
-                .authenticate(context.non_interactive, allowed_attempts)?;
+            let result =
+                self.authenticator
+                    .authenticate(context.non_interactive, allowed_attempts, insults);
+            #[cfg(feature = "linux-audit")]
+            audit_authentication(context, result.is_ok());
+            result?;
+            if !context.update_session_records {
+                return Ok(());
+            }
             if let (Some(record_file), Some(scope)) = (&mut auth_status.record_file, scope) {
-                match record_file.create(scope, context.current_user.uid) {
+                match record_file.create(scope, context.auth_user.uid) {
                     Ok(_) => (),
                     Err(e) => {
                         auth_warn!("Could not update session record file with new record: {e}");
@@ -168,7 +318,7 @@ impl<Policy: PolicyPlugin, Auth: AuthPlugin> Pipeline<Policy, Auth> {
                         command: context.command.command.clone(),
                     });
                 } else {
-                    context.chdir = optdir.map(std::path::PathBuf::from)
+                    context.chdir = optdir.map(PathBuf::from)
                 }
             }
         }
@@ -178,20 +328,201 @@ impl<Policy: PolicyPlugin, Auth: AuthPlugin> Pipeline<Policy, Auth> {
             context.chdir = Some(expand_tilde_in_path(&context.target_user.name, dir)?)
         }
 
+        // only run commands as users with a shell listed in /etc/shells if indicated
+        if policy.runas_check_shell() && !is_valid_shell(&context.target_user.shell) {
+            return Err(Error::InvalidShell {
+                username: context.target_user.name.clone(),
+                shell: context.target_user.shell.clone(),
+            });
+        }
+
+        // refuse or report being run by a command that another sudo runs if indicated; sudo
+        // that runs itself in a loop is refused anyway
+        let depth = nested::nesting_depth();
+        if depth > nested::MAX_NESTING {
+            return Err(Error::NestedSudo(depth));
+        }
+        if depth > 0 {
+            match policy.nested_sudo() {
+                NestedSudo::Ignore => {}
+                NestedSudo::Log => context.nested_sudo = depth,
+                NestedSudo::Warn => {
+                    user_warn!("sudo is run by a command that sudo runs (depth {depth})");
+                    context.nested_sudo = depth;
+                }
+                NestedSudo::Deny => return Err(Error::NestedSudo(depth)),
+            }
+        }
+
         // override the default pty behaviour if indicated
         if !policy.use_pty() {
             context.use_pty = false
         }
 
+        // override a preserved HOME with the home directory of the target user if indicated
+        if policy.always_set_home() || (policy.set_home() && context.launch == LaunchType::Shell) {
+            context.set_home = true;
+        }
+
+        // keep the real user id of the invoking user if indicated
+        context.stay_setuid = policy.stay_setuid();
+
+        // let the user's terminal turn Ctrl-C and Ctrl-Z into signals for sudo if indicated
+        context.tty_signals = policy.tty_signals();
+
+        // let the user's terminal handle Ctrl-S and Ctrl-Q itself if indicated
+        context.tty_flow_control = policy.tty_flow_control();
+
+        // don't open a PAM session for the command if indicated
+        context.pam_session = policy.pam_session();
+
+        // ask the invoking user to authenticate again while the command runs if indicated
+        context.session_reauth = policy.session_reauth();
+
+        // hang up on the command once it has been idle for too long if indicated
+        context.idle_timeout = policy.idle_timeout();
+
+        // log only so much of the output of the command if indicated
+        context.output_quota = policy.output_quota();
+
+        // keep relaying the output of processes that the command left behind if indicated
+        context.pty_wait_for_eof = policy.pty_wait_for_eof();
+
+        // only grant the capabilities listed by the matching rule, if any
+        context.capabilities = policy.capabilities();
+
+        // let another group than `tty` write to the pty of the command if indicated
+        context.pty_group = policy.pty_group().map(str::to_string);
+
+        // the tags of the matching rule, or the defaults, for the I/O plugins
+        context.log_input = policy.log_input();
+        context.log_output = policy.log_output();
+        context.mail = policy.mail();
+        context.sudoedit_follow = policy.sudoedit_follow();
+
+        // keep the command from running other programs if indicated; the programs that a command
+        // with INTERCEPT runs cannot be checked against the policy yet, so it may not run any
+        context.noexec = policy.noexec() || policy.intercept();
+
+        // execute the command through the file it was opened as when it was resolved if
+        // indicated, provided that this is the file that the policy allowed
+        if policy.fdexec() && context.command.resolved {
+            check_command_file(context)?;
+        } else {
+            context.command_file = None;
+        }
+
+        // log the exit status and resource usage of the command if indicated
+        context.log_exit_status = policy.log_exit_status();
+
+        // log the input and output of the command if indicated
+        context.iolog = policy.iolog();
+
+        // copy the output of the command to a file or FIFO if indicated
+        context.tee_output = policy.tee_output().map(PathBuf::from);
+
+        // only forward some of the signals that sudo receives to the command if indicated
+        context.forward_signals = policy.forward_signals().map(|names| {
+            names
+                .iter()
+                .filter_map(|name| {
+                    let signal = signal_number(name);
+                    if signal.is_none() {
+                        auth_warn!("forward_signals: unknown signal {name}");
+                    }
+                    signal
+                })
+                .collect()
+        });
+
+        // run the command in a cgroup of its own if indicated
+        context.cgroup = policy.cgroup().map(str::to_string);
+
+        // restrict the command to the sandbox profile named by the matching rule, if any
+        if let Some(name) = policy.sandbox() {
+            let profile = SandboxProfile::load(name).map_err(|err| {
+                Error::Configuration(format!("cannot load sandbox profile '{name}': {err}"))
+            })?;
+            context.sandbox = Some(profile);
+        }
+
         Ok(())
     }
 }
 
-fn build_context(cmd_opts: SudoOptions, pre: &dyn PreJudgementPolicy) -> Result<Context, Error> {
+fn build_context(
+    mut cmd_opts: SudoOptions,
+    pre: &dyn PreJudgementPolicy,
+    group_source: GroupSource,
+) -> Result<Context, Error> {
     let secure_path: String = pre
         .secure_path()
         .unwrap_or_else(|| std::env::var("PATH").unwrap_or_default());
-    Context::build_from_options(cmd_opts, secure_path)
+
+    // without -u and -g, commands are run as the runas_default user
+    let runas_default = pre.runas_default();
+    if cmd_opts.user.is_none() && cmd_opts.group.is_none() {
+        cmd_opts.user = Some(runas_default.clone());
+    }
+
+    // show error messages and headings in color if indicated
+    style::use_color(pre.color());
+
+    let mut context =
+        Context::build_from_options(cmd_opts, secure_path, pre.runas_allow_unknown_id())?;
+
+    // with fqdn, the host is known by its fully qualified name, which `%h` in the prompt and
+    // rules with a short host name still match the first part of
+    if pre.fqdn() {
+        context.hostname = dns::fully_qualified(&context.hostname);
+    }
+
+    // with `Set group_source` in sudo.conf, the groups of the invoking user can be those of the
+    // sudo process instead of those in the group database
+    if let Some(groups) = group_source.process_groups()? {
+        context.current_user.groups = groups;
+    }
+
+    // with runaspw, the password of the runas_default user is asked instead of the invoking user's
+    if pre.runaspw() {
+        context.auth_user = resolve_runas_default(&runas_default)?;
+    }
+
+    Ok(context)
+}
+
+fn resolve_runas_default(runas_default: &str) -> Result<User, Error> {
+    let user = match runas_default.strip_prefix('#') {
+        Some(uid) => uid.parse().ok().map(User::from_uid).transpose()?.flatten(),
+        None => User::from_name(runas_default)?,
+    };
+
+    user.ok_or_else(|| Error::UserNotFound(runas_default.to_string()))
+}
+
+/// Check that the command was opened as the file that its path refers to, which is the one that
+/// the policy allowed, before it is executed through that file.
+fn check_command_file(context: &Context) -> Result<(), Error> {
+    let path = &context.command.command;
+    let error = |msg| {
+        Error::IoError(
+            Some(path.clone()),
+            io::Error::new(io::ErrorKind::Other, msg),
+        )
+    };
+
+    let Some(file) = &context.command_file else {
+        return Err(error("it could not be opened to be executed"));
+    };
+    let opened = file
+        .metadata()
+        .map_err(|err| Error::IoError(Some(path.clone()), err))?;
+    let allowed = fs::metadata(path).map_err(|err| Error::IoError(Some(path.clone()), err))?;
+    if (opened.dev(), opened.ino()) != (allowed.dev(), allowed.ino()) {
+        return Err(error("it was replaced while it was checked"));
+    }
+
+    Ok(())
 }
 
 /// This should determine what the authentication status for the given record
@@ -199,6 +530,7 @@ fn build_context(cmd_opts: SudoOptions, pre: &dyn PreJudgementPolicy) -> Result<
 fn determine_auth_status(
     must_policy_authenticate: bool,
     use_session_records: bool,
+    update_session_records: bool,
     record_for: Option<RecordScope>,
     auth_uid: UserId,
     current_user: &str,
@@ -209,7 +541,12 @@ fn determine_auth_status(
     } else if let (true, Some(record_for)) = (use_session_records, record_for) {
         match SessionRecordFile::open_for_user(current_user, prior_validity) {
             Ok(mut sr) => {
-                match sr.touch(record_for, auth_uid) {
+                let found = if update_session_records {
+                    sr.touch(record_for, auth_uid)
+                } else {
+                    sr.peek(record_for, auth_uid)
+                };
+                match found {
                     // if a record was found and updated within the timeout, we do not need to authenticate
                     Ok(TouchResult::Updated { .. }) => AuthStatus::new(false, Some(sr)),
                     Ok(TouchResult::NotFound | TouchResult::Outdated { .. }) => {
@@ -247,11 +584,67 @@ impl<'a> AuthStatus<'a> {
 }
 
 fn log_command_execution(context: &Context) {
+    auth_info!("{}", command_log_entry(context));
+}
+
+/// Log how the command exited, and the resources that it used if they are known.
+fn log_command_exit(context: &Context, reason: &ExitReason, usage: Option<&ResourceUsage>) {
+    let status = match reason {
+        ExitReason::Code(code) => format!("EXIT={code}"),
+        ExitReason::Signal(signal) => format!("SIGNAL={}", signal_name(*signal)),
+    };
+    let usage = usage
+        .map(|usage| {
+            format!(
+                " ; USERTIME={:.3} ; SYSTIME={:.3} ; MAXRSS={}",
+                usage.user_time.as_secs_f64(),
+                usage.system_time.as_secs_f64(),
+                usage.max_rss
+            )
+        })
+        .unwrap_or_default();
+
+    auth_info!("{} ; {status}{usage}", command_log_entry(context));
+}
+
+/// Report the time and memory that the command used, for `--stats`.
+fn print_stats(elapsed: std::time::Duration, usage: Option<&ResourceUsage>) {
+    match usage {
+        Some(usage) => eprintln_ignore_io_error!(
+            "sudo: {:.3}s real, {:.3}s user, {:.3}s system, {} KB maximum resident set size",
+            elapsed.as_secs_f64(),
+            usage.user_time.as_secs_f64(),
+            usage.system_time.as_secs_f64(),
+            usage.max_rss
+        ),
+        None => eprintln_ignore_io_error!("sudo: {:.3}s real", elapsed.as_secs_f64()),
+    }
+}
+
+fn command_log_entry(context: &Context) -> String {
     let tty_info = if let Ok(tty_name) = current_tty_name() {
         format!("TTY={} ;", escape_os_str_lossy(&tty_name))
     } else {
         String::from("")
     };
+    let session_info = match Process::login_uid() {
+        Some(login_uid) => format!(
+            "SESSIONID={} ; LOGINUID={login_uid} ;",
+            context.process.session_id
+        ),
+        None => format!("SESSIONID={} ;", context.process.session_id),
+    };
+    let uuid_info = match &context.parent_session_uuid {
+        Some(parent) => format!(
+            "SUDO_SESSION_ID={} ; PARENT_SUDO_SESSION_ID={parent} ;",
+            context.session_uuid
+        ),
+        None => format!("SUDO_SESSION_ID={} ;", context.session_uuid),
+    };
+    let nested_info = match context.nested_sudo {
+        0 => String::new(),
+        depth => format!("NESTED={depth} ;"),
+    };
     let pwd = escape_os_str_lossy(
         std::env::current_dir()
             .as_ref()
@@ -259,12 +652,57 @@ fn log_command_execution(context: &Context) {
             .unwrap_or_else(|_| OsStr::new("unknown")),
     );
     let user = context.target_user.name.escape_debug().collect::<String>();
-    auth_info!(
-        "{} : {} PWD={} ; USER={} ; COMMAND={}",
+    format!(
+        "{} : {} {} {} {} PWD={} ; USER={} ; COMMAND={}",
         &context.current_user.name,
         tty_info,
+        session_info,
+        uuid_info,
+        nested_info,
         pwd,
         user,
         &context.command
-    );
+    )
+}
+
+/// Report whether the command in `context` is allowed to the Linux audit subsystem.
+#[cfg(feature = "linux-audit")]
+fn audit_command(context: &Context, allowed: bool) {
+    use crate::system::linux_audit::{send_record, AuditRecord};
+    use std::ffi::OsString;
+
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let mut cmd = OsString::from(&context.command.command);
+    for arg in &context.command.arguments {
+        cmd.push(" ");
+        cmd.push(arg);
+    }
+    let tty = current_tty_name().ok();
+
+    let fields = [
+        ("cwd", cwd.as_os_str()),
+        ("cmd", cmd.as_os_str()),
+        ("sudo_session", OsStr::new(&context.session_uuid)),
+    ];
+    if let Err(e) = send_record(AuditRecord::UserCmd, &fields, tty.as_deref(), allowed) {
+        auth_warn!("Could not send audit record: {e}");
+    }
+}
+
+/// Report the outcome of authenticating the user in `context` to the Linux audit subsystem.
+#[cfg(feature = "linux-audit")]
+fn audit_authentication(context: &Context, success: bool) {
+    use crate::system::linux_audit::{send_record, AuditRecord};
+
+    let exe = std::env::current_exe().unwrap_or_default();
+    let tty = current_tty_name().ok();
+
+    let fields = [
+        ("acct", OsStr::new(&context.auth_user.name)),
+        ("exe", exe.as_os_str()),
+        ("sudo_session", OsStr::new(&context.session_uuid)),
+    ];
+    if let Err(e) = send_record(AuditRecord::UserAuth, &fields, tty.as_deref(), success) {
+        auth_warn!("Could not send audit record: {e}");
+    }
 }
@@ -0,0 +1,85 @@
+use crate::{
+    cli::SudoOptions,
+    common::Error,
+    sudoers::{Authorization, DirChange, Policy},
+};
+
+use super::{build_context, AuthPlugin, Pipeline, PolicyPlugin};
+
+impl<Policy: PolicyPlugin, Auth: AuthPlugin> Pipeline<Policy, Auth> {
+    /// Decide whether a command may be run like [Pipeline::run] does, including authenticating
+    /// the invoking user, but report the outcome instead of running the command.
+    ///
+    /// The report is meant to be read by tools that check the rules they generate, so it is not
+    /// translated. sudo exits with status 1 if the command is not allowed.
+    pub fn run_check(mut self, cmd_opts: SudoOptions) -> Result<(), Error> {
+        let pre = self.policy.init()?;
+        let mut context = build_context(cmd_opts, &pre, self.group_source)?;
+        let policy = self.policy.judge(pre, &context)?;
+
+        let command = std::iter::once(context.command.command.display().to_string())
+            .chain(context.command.arguments.iter().cloned())
+            .collect::<Vec<_>>()
+            .join(" ");
+        println_ignore_io_error!("command: {command}");
+        println_ignore_io_error!(
+            "runas: {}:{}",
+            context.target_user.name,
+            context.target_group.name
+        );
+
+        let auth = match policy.authorization() {
+            Authorization::Forbidden => {
+                println_ignore_io_error!("result: denied");
+                return Err(Error::Silent);
+            }
+            Authorization::Allowed(auth) => auth,
+        };
+        let must_authenticate = auth.must_authenticate;
+
+        self.apply_policy_to_context(&mut context, &policy)?;
+        self.auth_and_update_record_file(&context, auth)?;
+
+        if !context.command.resolved {
+            return Err(context.command.not_found());
+        }
+
+        println_ignore_io_error!("result: allowed");
+        println_ignore_io_error!("tags: {}", tags(&policy, must_authenticate).join(" "));
+
+        Ok(())
+    }
+}
+
+/// The tags of the rule that allows the command, written the way they are in sudoers.
+fn tags(policy: &impl Policy, must_authenticate: bool) -> Vec<String> {
+    let mut tags = vec![if must_authenticate {
+        "PASSWD".to_string()
+    } else {
+        "NOPASSWD".to_string()
+    }];
+
+    match policy.chdir() {
+        DirChange::Any => tags.push("CWD=*".to_string()),
+        DirChange::Strict(Some(path)) => tags.push(format!("CWD={}", path.display())),
+        DirChange::Strict(None) => {}
+    }
+    if let Some(caps) = policy.capabilities() {
+        tags.push(format!("CAPS={caps}"));
+    }
+    if let Some(sandbox) = policy.sandbox() {
+        tags.push(format!("SANDBOX={sandbox}"));
+    }
+    let switches = [
+        (policy.sudoedit_follow(), "FOLLOW"),
+        (policy.log_input(), "LOG_INPUT"),
+        (policy.log_output(), "LOG_OUTPUT"),
+        (policy.mail(), "MAIL"),
+        (policy.setenv(), "SETENV"),
+    ];
+    for (_, name) in switches.into_iter().filter(|(enabled, _)| *enabled) {
+        tags.push(name.to_string());
+    }
+
+    tags
+}
@@ -2,11 +2,15 @@ use std::{borrow::Cow, ops::ControlFlow, path::Path};
 
 use crate::{
     cli::{SudoAction, SudoOptions},
-    common::{Context, Error},
+    common::{
+        i18n::tr,
+        style::{self, Style},
+        Context, Error,
+    },
     pam::CLIConverser,
     sudo::{pam::PamAuthenticator, SudoersPolicy},
     sudoers::{Authorization, ListRequest, Policy, Request, Sudoers},
-    system::User,
+    system::{Group, User},
 };
 
 use super::{Pipeline, PolicyPlugin};
@@ -14,6 +18,7 @@ use super::{Pipeline, PolicyPlugin};
 impl Pipeline<SudoersPolicy, PamAuthenticator<CLIConverser>> {
     pub(in crate::sudo) fn run_list(mut self, cmd_opts: SudoOptions) -> Result<(), Error> {
         let verbose_list_mode = cmd_opts.verbose_list_mode();
+        let why = cmd_opts.why;
         let other_user = cmd_opts
             .other_user
             .as_ref()
@@ -29,10 +34,10 @@ impl Pipeline<SudoersPolicy, PamAuthenticator<CLIConverser>> {
         };
 
         let sudoers = self.policy.init()?;
-        let context = super::build_context(cmd_opts, &sudoers)?;
+        let context = super::build_context(cmd_opts, &sudoers, self.group_source)?;
 
         if original_command.is_some() && !context.command.resolved {
-            return Err(Error::CommandNotFound(context.command.command));
+            return Err(context.command.not_found());
         }
 
         if self
@@ -43,17 +48,27 @@ impl Pipeline<SudoersPolicy, PamAuthenticator<CLIConverser>> {
         }
 
         if let Some(other_user) = &other_user {
-            check_other_users_list_perms(other_user, &context, &sudoers, &original_command)?;
+            if check_other_users_list_perms(other_user, &context, &sudoers, &original_command)?
+                .is_break()
+            {
+                return Ok(());
+            }
         }
 
-        if let Some(original_command) = original_command {
+        if original_command.is_some() && why {
+            explain_sudo_command(&context, &other_user, &sudoers)?;
+        } else if let Some(original_command) = original_command {
             check_sudo_command_perms(&original_command, &context, &other_user, &sudoers)?;
         } else {
             let invoking_user = other_user.as_ref().unwrap_or(&context.current_user);
+            let heading = tr!(
+                "User {user} may run the following commands on {hostname}:",
+                user = invoking_user.name,
+                hostname = context.hostname
+            );
             println_ignore_io_error!(
-                "User {} may run the following commands on {}:",
-                invoking_user.name,
-                context.hostname
+                "{}",
+                style::paint(Style::Heading, &heading, &std::io::stdout())
             );
 
             let matching_entries = sudoers.matching_entries(invoking_user, &context.hostname);
@@ -78,12 +93,36 @@ impl Pipeline<SudoersPolicy, PamAuthenticator<CLIConverser>> {
         original_command: &Option<String>,
         other_user: &Option<User>,
     ) -> Result<ControlFlow<(), ()>, Error> {
-        let list_request = ListRequest {
-            target_user: &context.target_user,
-            target_group: &context.target_group,
+        let judgement = match other_user {
+            // listing the privileges of someone else takes a rule for the `list` pseudo-command
+            Some(other_user)
+                if other_user.uid != context.current_user.uid && context.current_user.uid != 0 =>
+            {
+                let other_group = Group::from_gid(other_user.gid)?
+                    .ok_or_else(|| Error::GroupNotFound(format!("#{}", other_user.gid)))?;
+                let list_request = ListRequest {
+                    target_user: &context.target_user,
+                    target_group: &context.target_group,
+                };
+                sudoers.check_list_other_permission(
+                    &context.current_user,
+                    &context.hostname,
+                    (other_user, &other_group),
+                    list_request,
+                )
+            }
+            _ => {
+                let list_request = ListRequest {
+                    target_user: &context.target_user,
+                    target_group: &context.target_group,
+                };
+                sudoers.check_list_permission(
+                    &context.current_user,
+                    &context.hostname,
+                    list_request,
+                )
+            }
         };
-        let judgement =
-            sudoers.check_list_permission(&context.current_user, &context.hostname, list_request);
         match judgement.authorization() {
             Authorization::Allowed(auth) => {
                 self.auth_and_update_record_file(context, auth)?;
@@ -97,9 +136,12 @@ impl Pipeline<SudoersPolicy, PamAuthenticator<CLIConverser>> {
                     }
 
                     println_ignore_io_error!(
-                        "User {} is not allowed to run sudo on {}.",
-                        other_user.as_ref().unwrap_or(&context.current_user).name,
-                        context.hostname
+                        "{}",
+                        tr!(
+                            "User {user} is not allowed to run sudo on {hostname}.",
+                            user = other_user.as_ref().unwrap_or(&context.current_user).name,
+                            hostname = context.hostname
+                        )
                     );
 
                     // this branch does not result in exit code 1 but no further information should
@@ -129,7 +171,7 @@ fn check_other_users_list_perms(
     context: &Context,
     sudoers: &Sudoers,
     original_command: &Option<String>,
-) -> Result<(), Error> {
+) -> Result<ControlFlow<(), ()>, Error> {
     let list_request = ListRequest {
         target_user: &context.target_user,
         target_group: &context.target_group,
@@ -137,6 +179,19 @@ fn check_other_users_list_perms(
     let judgement = sudoers.check_list_permission(other_user, &context.hostname, list_request);
 
     if let Authorization::Forbidden = judgement.authorization() {
+        // someone who may list the privileges of a user is told that there are none
+        if original_command.is_none() {
+            println_ignore_io_error!(
+                "{}",
+                tr!(
+                    "User {user} is not allowed to run sudo on {hostname}.",
+                    user = other_user.name,
+                    hostname = context.hostname
+                )
+            );
+            return Ok(ControlFlow::Break(()));
+        }
+
         return Err(Error::NotAllowed {
             username: context.current_user.name.clone(),
             command: format_list_command(original_command),
@@ -145,7 +200,7 @@ fn check_other_users_list_perms(
         });
     }
 
-    Ok(())
+    Ok(ControlFlow::Continue(()))
 }
 
 fn check_sudo_command_perms(
@@ -194,3 +249,27 @@ fn format_list_command(original_command: &Option<String>) -> Cow<'static, str> {
         "list".into()
     }
 }
+
+/// Show how the rules decide on the command, for `sudo --list --why`.
+fn explain_sudo_command(
+    context: &Context,
+    other_user: &Option<User>,
+    sudoers: &Sudoers,
+) -> Result<(), Error> {
+    let user = other_user.as_ref().unwrap_or(&context.current_user);
+
+    let request = Request {
+        user: &context.target_user,
+        group: &context.target_group,
+        command: &context.command.command,
+        arguments: &context.command.arguments,
+    };
+
+    let explanation = sudoers.explain(user, &context.hostname, request);
+    println_ignore_io_error!("{explanation}");
+
+    match explanation.deciding_rule() {
+        Some((_, true)) => Ok(()),
+        _ => Err(Error::Silent),
+    }
+}
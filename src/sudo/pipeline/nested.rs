@@ -0,0 +1,68 @@
+//! Finding out whether sudo was run by a command that another sudo runs, e.g. with `sudo sudo -i`,
+//! or by a script that calls sudo and is itself run with sudo.
+//!
+//! The variables that sudo sets for the command are easily lost or faked, so the processes above
+//! this one are looked at as well: every run of processes with the same name as this one counts as
+//! one invocation of sudo, as both sudo and its monitor are there when the command runs in a pty.
+use crate::system::{Process, WithProcess};
+
+/// Beyond this many invocations, sudo is refused whatever the policy says, as it is most likely
+/// running itself in a loop.
+pub(super) const MAX_NESTING: usize = 8;
+
+/// How many processes above this one are looked at.
+const MAX_ANCESTORS: usize = 256;
+
+/// How many invocations of sudo this one runs under.
+pub(super) fn nesting_depth() -> usize {
+    let from_env =
+        std::env::var_os("SUDO_COMMAND").is_some() && std::env::var_os("SUDO_UID").is_some();
+    let from_ancestry = Process::name_of(WithProcess::Current)
+        .map(|name| count_invocations(ancestor_names(), &name))
+        .unwrap_or(0);
+
+    from_ancestry.max(usize::from(from_env))
+}
+
+/// The names of the processes above this one, up to the first process, or up to the first one
+/// that cannot be inspected.
+fn ancestor_names() -> impl Iterator<Item = String> {
+    let mut next = Process::parent_id();
+    std::iter::from_fn(move || {
+        let pid = next.filter(|&pid| pid > 1)?;
+        next = Process::parent_of(WithProcess::Other(pid)).ok();
+        Process::name_of(WithProcess::Other(pid)).ok()
+    })
+    .take(MAX_ANCESTORS)
+}
+
+/// The number of runs of `name` in `names`.
+fn count_invocations(names: impl Iterator<Item = String>, name: &str) -> usize {
+    let mut count = 0;
+    let mut previous = false;
+    for ancestor in names {
+        let is_sudo = ancestor == name;
+        if is_sudo && !previous {
+            count += 1;
+        }
+        previous = is_sudo;
+    }
+
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_invocations() {
+        let count =
+            |names: &[&str]| count_invocations(names.iter().map(|name| name.to_string()), "sudo");
+        assert_eq!(count(&["bash", "sshd", "systemd"]), 0);
+        assert_eq!(count(&["sudo", "bash"]), 1);
+        assert_eq!(count(&["sudo", "sudo", "bash"]), 1);
+        assert_eq!(count(&["sh", "sudo", "sudo", "sudo", "bash"]), 1);
+        assert_eq!(count(&["sudo", "sh", "sudo", "sh", "sudo"]), 3);
+    }
+}
@@ -0,0 +1,162 @@
+//! Telling the approval and audit plugins of [crate::plugin] about a command while it is decided
+//! on and run.
+use crate::common::{Context, Environment, Error};
+use crate::exec::ExecOutput;
+use crate::plugin::{ApprovalPlugin, AuditPlugin};
+
+/// Ask every approval plugin whether the command may be run; the first one that rejects it stops
+/// the command, and the audit plugins are told why.
+pub(super) fn approve(
+    approval_plugins: &mut [Box<dyn ApprovalPlugin>],
+    audit_plugins: &mut [Box<dyn AuditPlugin>],
+    context: &Context,
+    env: &Environment,
+) -> Result<(), Error> {
+    for plugin in approval_plugins {
+        if let Err(reason) = plugin.check(context, env) {
+            for audit_plugin in audit_plugins.iter_mut() {
+                audit_plugin.reject(context, &reason);
+            }
+            return Err(Error::Plugin(format!("{}: {reason}", plugin.name())));
+        }
+    }
+
+    Ok(())
+}
+
+/// Run the command with `run`, telling the audit plugins that it is about to run and, once it
+/// has, how it ended.
+pub(super) fn run_audited(
+    audit_plugins: &mut [Box<dyn AuditPlugin>],
+    context: &Context,
+    run: impl FnOnce() -> Result<ExecOutput, Error>,
+) -> Result<ExecOutput, Error> {
+    for plugin in audit_plugins.iter_mut() {
+        plugin.accept(context);
+    }
+
+    let output = run()?;
+    for plugin in audit_plugins.iter_mut() {
+        plugin.exit(context, &output.command_exit_reason);
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::cli::SudoOptions;
+    use crate::exec::ExitReason;
+
+    /// What the plugins were asked and told, in order.
+    type Events = Rc<RefCell<Vec<String>>>;
+
+    /// Approves a command only if a ticket number was given for it.
+    struct Ticket {
+        given: bool,
+        events: Events,
+    }
+
+    impl ApprovalPlugin for Ticket {
+        fn name(&self) -> &str {
+            "ticket"
+        }
+
+        fn check(&mut self, _context: &Context, _env: &Environment) -> Result<(), String> {
+            self.events.borrow_mut().push("check".to_string());
+            if self.given {
+                Ok(())
+            } else {
+                Err("no ticket number".to_string())
+            }
+        }
+    }
+
+    struct Recorder(Events);
+
+    impl AuditPlugin for Recorder {
+        fn accept(&mut self, _context: &Context) {
+            self.0.borrow_mut().push("accept".to_string());
+        }
+
+        fn reject(&mut self, _context: &Context, reason: &str) {
+            self.0.borrow_mut().push(format!("reject: {reason}"));
+        }
+
+        fn exit(&mut self, _context: &Context, reason: &ExitReason) {
+            let event = match reason {
+                ExitReason::Code(code) => format!("exit: {code}"),
+                ExitReason::Signal(signal) => format!("signal: {signal}"),
+            };
+            self.0.borrow_mut().push(event);
+        }
+    }
+
+    /// Run a command the way the pipeline does, with a ticket number or without; returns whether
+    /// the command was run and what the plugins were asked and told.
+    fn run(given: bool) -> (bool, Vec<String>) {
+        let events = Events::default();
+        let mut approval_plugins: Vec<Box<dyn ApprovalPlugin>> = vec![Box::new(Ticket {
+            given,
+            events: events.clone(),
+        })];
+        let mut audit_plugins: Vec<Box<dyn AuditPlugin>> = vec![Box::new(Recorder(events.clone()))];
+
+        let options = SudoOptions::try_parse_from(["sudo", "true"]).unwrap();
+        let context =
+            Context::build_from_options(options, "/usr/bin:/bin".to_string(), false).unwrap();
+        let result = approve(
+            &mut approval_plugins,
+            &mut audit_plugins,
+            &context,
+            &Environment::new(),
+        )
+        .and_then(|()| {
+            run_audited(&mut audit_plugins, &context, || {
+                events.borrow_mut().push("run".to_string());
+                Ok(ExecOutput {
+                    command_exit_reason: ExitReason::Code(0),
+                    restore_signal_handlers: Box::new(|| ()),
+                    resource_usage: None,
+                })
+            })
+        });
+
+        if let Err(err) = &result {
+            assert_eq!(err.to_string(), "ticket: no ticket number");
+        }
+        let events = events.borrow().clone();
+        (result.is_ok(), events)
+    }
+
+    #[test]
+    fn rejection_stops_the_command() {
+        assert_eq!(
+            run(false),
+            (
+                false,
+                vec!["check".into(), "reject: no ticket number".into()]
+            )
+        );
+    }
+
+    #[test]
+    fn tells_about_the_command_in_order() {
+        assert_eq!(
+            run(true),
+            (
+                true,
+                vec![
+                    "check".into(),
+                    "accept".into(),
+                    "run".into(),
+                    "exit: 0".into()
+                ]
+            )
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! What `sudo --version` shows: the version of sudo-rs, the features it was built with and the
+//! policy and plugins that sudo.conf selects, so that the capabilities of the sudo on a machine
+//! can be inventoried. With `--json`, the same is written as a JSON object.
+use crate::cli::SudoOptions;
+use crate::common::json;
+
+use super::conf::{PolicyFormat, SudoConf};
+
+/// The cargo features that sudo-rs was built with.
+fn features() -> Vec<&'static str> {
+    [
+        ("linux-audit", cfg!(feature = "linux-audit")),
+        ("bsm-audit", cfg!(feature = "bsm-audit")),
+        ("classic-messages", cfg!(feature = "classic-messages")),
+        ("doas", cfg!(feature = "doas")),
+        ("policy-api", cfg!(feature = "policy-api")),
+        ("io-plugins", cfg!(feature = "io-plugins")),
+        ("iolog-compression", cfg!(feature = "iolog-compression")),
+        ("iolog-encryption", cfg!(feature = "iolog-encryption")),
+        ("sudoers-digest", cfg!(feature = "sudoers-digest")),
+        ("syslog-tls", cfg!(feature = "syslog-tls")),
+        ("dev", cfg!(feature = "dev")),
+    ]
+    .into_iter()
+    .filter_map(|(feature, enabled)| enabled.then_some(feature))
+    .collect()
+}
+
+/// What is reported about this sudo.
+struct VersionInfo {
+    version: &'static str,
+    features: Vec<&'static str>,
+    policy: &'static str,
+    io_plugins: Vec<String>,
+    options: Vec<&'static str>,
+}
+
+impl VersionInfo {
+    fn new(version: &'static str, conf: &SudoConf) -> Self {
+        VersionInfo {
+            version,
+            features: features(),
+            policy: match conf.policy {
+                PolicyFormat::Sudoers => "sudoers",
+                PolicyFormat::Doas => "doas",
+            },
+            io_plugins: conf
+                .plugins
+                .iter()
+                // the sudoers plugins of ogsudo are built in
+                .filter(|plugin| {
+                    !matches!(
+                        plugin.symbol.as_str(),
+                        "sudoers_policy" | "sudoers_io" | "sudoers_audit"
+                    )
+                })
+                .map(|plugin| format!("{} ({})", plugin.symbol, plugin.path.display()))
+                .collect(),
+            options: SudoOptions::long_options().collect(),
+        }
+    }
+
+    fn text(&self) -> String {
+        let list = |items: &[&str]| {
+            if items.is_empty() {
+                "none".to_string()
+            } else {
+                items.join(", ")
+            }
+        };
+        let io_plugins = self
+            .io_plugins
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        let options = self
+            .options
+            .iter()
+            .map(|option| format!("--{option}"))
+            .collect::<Vec<_>>();
+
+        format!(
+            "sudo-rs {}\n\
+             Features: {}\n\
+             Policy: {}\n\
+             Authentication: pam\n\
+             I/O plugins: {}\n\
+             Options: {}",
+            self.version,
+            list(&self.features),
+            self.policy,
+            list(&io_plugins),
+            options.join(" ")
+        )
+    }
+
+    fn json(&self) -> String {
+        json::object(&[
+            ("version", json::string(self.version)),
+            (
+                "features",
+                json::string_array(self.features.iter().copied()),
+            ),
+            ("policy", json::string(self.policy)),
+            ("authentication", json::string("pam")),
+            (
+                "io_plugins",
+                json::string_array(self.io_plugins.iter().map(String::as_str)),
+            ),
+            ("options", json::string_array(self.options.iter().copied())),
+        ])
+    }
+}
+
+/// Show the version and capabilities of sudo: as text on stderr, like before, or as JSON on
+/// stdout so that it can be piped into a JSON parser.
+pub(super) fn print_version(version: &'static str, conf: &SudoConf, as_json: bool) {
+    let info = VersionInfo::new(version, conf);
+    if as_json {
+        println_ignore_io_error!("{}", info.json().trim_end());
+    } else {
+        eprintln_ignore_io_error!("{}", info.text());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sudo::conf::PluginEntry;
+
+    #[test]
+    fn describes_sudo() {
+        let conf = SudoConf {
+            plugins: vec![PluginEntry {
+                symbol: "sample_io".to_string(),
+                path: "/usr/lib/sample_io.so".into(),
+                arguments: Vec::new(),
+            }],
+            ..Default::default()
+        };
+        let info = VersionInfo::new("1.2.3", &conf);
+
+        let text = info.text();
+        assert!(text.starts_with("sudo-rs 1.2.3\n"));
+        assert!(text.contains("\nI/O plugins: sample_io (/usr/lib/sample_io.so)\n"));
+        assert!(text.contains("--preserve-env --preserve-groups"));
+        assert!(!text.contains("--completions"));
+
+        let json = info.json();
+        assert!(json.starts_with("{\n  \"version\": \"1.2.3\",\n"));
+        assert!(json.contains("\"io_plugins\": [\"sample_io (/usr/lib/sample_io.so)\"]"));
+        assert!(json.contains("\"authentication\": \"pam\""));
+    }
+}
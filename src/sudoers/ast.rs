@@ -68,6 +68,18 @@ pub enum Authenticate {
 pub struct Tag {
     pub authenticate: Authenticate,
     pub cwd: Option<ChDir>,
+    pub caps: Option<CapabilitySet>,
+    pub sandbox: Option<String>,
+    /// The variables that are set for the command, whatever the environment settings say.
+    pub env: Vec<(String, String)>,
+    // for these, `None` means that the `Defaults` decide
+    pub mail: Option<bool>,
+    pub log_input: Option<bool>,
+    pub log_output: Option<bool>,
+    pub follow: Option<bool>,
+    pub setenv: Option<bool>,
+    pub noexec: Option<bool>,
+    pub intercept: Option<bool>,
 }
 
 impl Tag {
@@ -97,10 +109,13 @@ pub enum Directive {
     CmndAlias(Defs<Command>),
     RunasAlias(Defs<UserSpecifier>),
     Defaults(Vec<(String, ConfigValue)>),
+    HostDefaults(SpecList<Hostname>, Vec<(String, ConfigValue)>),
+    CmndDefaults(SpecList<Command>, Vec<(String, ConfigValue)>),
 }
 
 pub type TextEnum = crate::defaults::StrEnum<'static>;
 
+#[derive(Clone)]
 pub enum ConfigValue {
     Flag(bool),
     Text(Option<Box<str>>),
@@ -109,6 +124,7 @@ pub enum ConfigValue {
     Enum(TextEnum),
 }
 
+#[derive(Clone)]
 pub enum Mode {
     Add,
     Set,
@@ -265,7 +281,42 @@ impl Parse for RunAs {
 struct MetaOrTag(Meta<Modifier>);
 
 /// A `Modifier` is something that updates the `Tag`.
-pub type Modifier = Box<dyn Fn(&mut Tag)>;
+pub enum Modifier {
+    Authenticate(Authenticate),
+    Cwd(ChDir),
+    Caps(CapabilitySet),
+    Sandbox(String),
+    Env(String, String),
+    Mail(bool),
+    LogInput(bool),
+    LogOutput(bool),
+    Follow(bool),
+    Setenv(bool),
+    Noexec(bool),
+    Intercept(bool),
+}
+
+impl Modifier {
+    pub fn apply(&self, tag: &mut Tag) {
+        match self {
+            Modifier::Authenticate(authenticate) => tag.authenticate = *authenticate,
+            Modifier::Cwd(path) => tag.cwd = Some(path.clone()),
+            Modifier::Caps(caps) => tag.caps = Some(*caps),
+            Modifier::Sandbox(name) => tag.sandbox = Some(name.clone()),
+            Modifier::Env(name, value) => {
+                tag.env.retain(|(existing, _)| existing != name);
+                tag.env.push((name.clone(), value.clone()));
+            }
+            Modifier::Mail(mail) => tag.mail = Some(*mail),
+            Modifier::LogInput(log) => tag.log_input = Some(*log),
+            Modifier::LogOutput(log) => tag.log_output = Some(*log),
+            Modifier::Follow(follow) => tag.follow = Some(*follow),
+            Modifier::Setenv(setenv) => tag.setenv = Some(*setenv),
+            Modifier::Noexec(noexec) => tag.noexec = Some(*noexec),
+            Modifier::Intercept(intercept) => tag.intercept = Some(*intercept),
+        }
+    }
+}
 
 // note: at present, "ALL" can be distinguished from a tag using a lookup of 1, since no tag starts with an "A"; but this feels like hanging onto
 // the parseability by a thread (although the original sudo also has some ugly parts, like 'sha224' being an illegal user name).
@@ -276,18 +327,47 @@ impl Parse for MetaOrTag {
         use Meta::*;
         let AliasName(keyword) = try_nonterminal(stream)?;
 
-        let mut switch = |modifier: fn(&mut Tag)| {
+        let mut switch = |modifier| {
             expect_syntax(':', stream)?;
-            make(Box::new(modifier))
+            make(modifier)
         };
 
-        let result: Modifier = match keyword.as_str() {
-            "PASSWD" => switch(|tag| tag.authenticate = Authenticate::Passwd)?,
-            "NOPASSWD" => switch(|tag| tag.authenticate = Authenticate::Nopasswd)?,
+        let result = match keyword.as_str() {
+            "PASSWD" => switch(Modifier::Authenticate(Authenticate::Passwd))?,
+            "NOPASSWD" => switch(Modifier::Authenticate(Authenticate::Nopasswd))?,
+            "MAIL" => switch(Modifier::Mail(true))?,
+            "NOMAIL" => switch(Modifier::Mail(false))?,
+            "LOG_INPUT" => switch(Modifier::LogInput(true))?,
+            "NOLOG_INPUT" => switch(Modifier::LogInput(false))?,
+            "LOG_OUTPUT" => switch(Modifier::LogOutput(true))?,
+            "NOLOG_OUTPUT" => switch(Modifier::LogOutput(false))?,
+            "FOLLOW" => switch(Modifier::Follow(true))?,
+            "NOFOLLOW" => switch(Modifier::Follow(false))?,
+            "SETENV" => switch(Modifier::Setenv(true))?,
+            "NOSETENV" => switch(Modifier::Setenv(false))?,
+            "NOEXEC" => switch(Modifier::Noexec(true))?,
+            "EXEC" => switch(Modifier::Noexec(false))?,
+            "INTERCEPT" => switch(Modifier::Intercept(true))?,
+            "NOINTERCEPT" => switch(Modifier::Intercept(false))?,
             "CWD" => {
                 expect_syntax('=', stream)?;
-                let path: ChDir = expect_nonterminal(stream)?;
-                Box::new(move |tag| tag.cwd = Some(path.clone()))
+                Modifier::Cwd(expect_nonterminal(stream)?)
+            }
+            "CAPS" => {
+                expect_syntax('=', stream)?;
+                Modifier::Caps(expect_nonterminal(stream)?)
+            }
+            "SANDBOX" => {
+                expect_syntax('=', stream)?;
+                let ProfileName(name) = expect_nonterminal(stream)?;
+                Modifier::Sandbox(name)
+            }
+            "ENV" => {
+                expect_syntax('=', stream)?;
+                expect_syntax('"', stream)?;
+                let EnvAssignment(name, value) = expect_nonterminal(stream)?;
+                expect_syntax('"', stream)?;
+                Modifier::Env(name, value)
             }
             "ALL" => return make(MetaOrTag(All)),
             alias => return make(MetaOrTag(Alias(alias.to_string()))),
@@ -297,6 +377,9 @@ impl Parse for MetaOrTag {
     }
 }
 
+/// The pseudo-command that allows listing the privileges of the users that it may be run as.
+pub const LIST_COMMAND: &str = "list";
+
 /// grammar:
 /// ```text
 /// commandspec = [tag modifiers]*, command
@@ -322,8 +405,13 @@ impl Parse for CommandSpec {
             if keyword == "sudoedit" {
                 // note: special behaviour of forward slashes in wildcards, tread carefully
                 unrecoverable!(pos = start_pos, stream, "sudoedit is not yet supported");
-            } else if keyword == "list" {
-                unrecoverable!(pos = start_pos, stream, "list is not yet supported");
+            } else if keyword == LIST_COMMAND {
+                // a pattern without a slash never matches the path of an actual command
+                let list = CommandPath::new(LIST_COMMAND.to_string()).expect("valid pattern");
+                return make(CommandSpec(
+                    tags,
+                    Qualified::Allow(Meta::Only((list, None))),
+                ));
             } else if keyword.starts_with("sha") {
                 unrecoverable!(
                     pos = start_pos,
@@ -396,6 +484,8 @@ impl Many for (Option<RunAs>, CommandSpec) {}
 /// sudo = permissionspec
 ///      | Keyword_Alias identifier = identifier_list
 ///      | Defaults (name [+-]?= ...)+
+///      | Defaults@host_list (name [+-]?= ...)+
+///      | Defaults!cmnd_list (name [+-]?= ...)+
 /// ```
 /// There is a syntactical ambiguity in the sudoer Directive and Permission specifications, so we
 /// have to parse them 'together' and do a delayed decision on which category we are in.
@@ -439,7 +529,7 @@ impl Parse for Sudo {
         if let Some(users) = maybe(try_nonterminal::<SpecList<_>>(stream))? {
             // element 1 always exists (parse_list fails on an empty list)
             let key = &users[0];
-            if let Some(directive) = maybe(get_directive(key, stream))? {
+            if let Some(directive) = maybe(get_directive(key, start_pos, stream))? {
                 if users.len() != 1 {
                     unrecoverable!(pos = start_pos, stream, "invalid user name list");
                 }
@@ -487,8 +577,8 @@ fn parse_include(stream: &mut impl CharStream) -> Parsed<Sudo> {
     make(result)
 }
 
-use crate::defaults::sudo_default;
 use crate::defaults::SudoDefault as Setting;
+use crate::defaults::{schema, similar_setting, sudo_default};
 
 /// grammar:
 /// ```text
@@ -522,6 +612,7 @@ impl<T> Many for Def<T> {
 
 fn get_directive(
     perhaps_keyword: &Spec<UserSpecifier>,
+    start_pos: (usize, usize),
     stream: &mut impl CharStream,
 ) -> Parsed<Directive> {
     use super::ast::Directive::*;
@@ -537,11 +628,39 @@ fn get_directive(
         "Host_Alias" => make(HostAlias(expect_nonterminal(stream)?)),
         "Cmnd_Alias" | "Cmd_Alias" => make(CmndAlias(expect_nonterminal(stream)?)),
         "Runas_Alias" => make(RunasAlias(expect_nonterminal(stream)?)),
-        "Defaults" => make(Defaults(expect_nonterminal(stream)?)),
+        "Defaults" => {
+            if is_syntax('@', stream)? {
+                let hosts = expect_nonterminal(stream)?;
+                make(HostDefaults(hosts, expect_nonterminal(stream)?))
+            } else if stream.get_pos() == (start_pos.0, start_pos.1 + keyword.len())
+                && is_syntax('!', stream)?
+            {
+                // `Defaults!/bin/ls` has no whitespace before the `!`, unlike `Defaults !setting`
+                let commands: SpecList<DefaultsCommand> = expect_nonterminal(stream)?;
+                let commands = commands
+                    .into_iter()
+                    .map(|spec| match spec {
+                        Allow(meta) => Allow(defaults_command(meta)),
+                        Forbid(meta) => Forbid(defaults_command(meta)),
+                    })
+                    .collect();
+                make(CmndDefaults(commands, expect_nonterminal(stream)?))
+            } else {
+                make(Defaults(expect_nonterminal(stream)?))
+            }
+        }
         _ => reject(),
     }
 }
 
+fn defaults_command(meta: Meta<DefaultsCommand>) -> Meta<Command> {
+    match meta {
+        Meta::All => Meta::All,
+        Meta::Only(DefaultsCommand(command)) => Meta::Only(command),
+        Meta::Alias(alias) => Meta::Alias(alias),
+    }
+}
+
 /// grammar:
 /// ```text
 /// parameter = name [+-]?= ...
@@ -581,7 +700,12 @@ impl Parse for (String, ConfigValue) {
         let list_items = |mode: Mode, name: String, cfg: Setting, stream: &mut _| {
             expect_syntax('=', stream)?;
             if !matches!(cfg, Setting::List(_)) {
-                unrecoverable!(pos = id_pos, stream, "{name} is not a list parameter");
+                unrecoverable!(
+                    pos = id_pos,
+                    stream,
+                    "{name} is not a list parameter{}",
+                    expected_syntax(&name)
+                );
             }
 
             make((name, ConfigValue::List(mode, parse_vars(stream)?)))
@@ -619,18 +743,29 @@ impl Parse for (String, ConfigValue) {
                     },
                     _checker,
                 )) => ConfigValue::Num(val),
-                None => unrecoverable!(pos = value_pos, stream, "unknown setting: '{name}'"),
+                None => unrecoverable!(
+                    pos = value_pos,
+                    stream,
+                    "unknown setting: '{name}'{}",
+                    did_you_mean(&name)
+                ),
                 _ => unrecoverable!(
                     pos = value_pos,
                     stream,
-                    "'{name}' cannot be used in a boolean context"
+                    "'{name}' cannot be used in a boolean context{}",
+                    expected_syntax(&name)
                 ),
             };
             make((name, value))
         } else {
             let DefaultName(name) = try_nonterminal(stream)?;
             let Some(cfg) = sudo_default(&name) else {
-                unrecoverable!(pos = id_pos, stream, "unknown setting: '{name}'");
+                unrecoverable!(
+                    pos = id_pos,
+                    stream,
+                    "unknown setting: '{name}'{}",
+                    did_you_mean(&name)
+                );
             };
 
             if is_syntax('+', stream)? {
@@ -641,7 +776,11 @@ impl Parse for (String, ConfigValue) {
                 let value_pos = stream.get_pos();
                 match cfg {
                     Setting::Flag(_) => {
-                        unrecoverable!(stream, "can't assign to boolean setting '{name}'")
+                        unrecoverable!(
+                            stream,
+                            "can't assign to boolean setting '{name}'{}",
+                            expected_syntax(&name)
+                        )
                     }
                     Setting::Integer(_, checker) => {
                         let Numeric(denotation) = expect_nonterminal(stream)?;
@@ -651,7 +790,8 @@ impl Parse for (String, ConfigValue) {
                             unrecoverable!(
                                 pos = value_pos,
                                 stream,
-                                "'{denotation}' is not a valid value for {name}"
+                                "'{denotation}' is not a valid value for {name}{}",
+                                expected_syntax(&name)
                             );
                         }
                     }
@@ -669,7 +809,8 @@ impl Parse for (String, ConfigValue) {
                             unrecoverable!(
                                 pos = value_pos,
                                 stream,
-                                "'{text}' is not a valid value for {name}"
+                                "'{text}' is not a valid value for {name}{}",
+                                expected_syntax(&name)
                             );
                         };
                         make((name, ConfigValue::Enum(value)))
@@ -677,7 +818,12 @@ impl Parse for (String, ConfigValue) {
                 }
             } else {
                 if !matches!(cfg, Setting::Flag(_)) {
-                    unrecoverable!(pos = id_pos, stream, "'{name}' is not a boolean setting");
+                    unrecoverable!(
+                        pos = id_pos,
+                        stream,
+                        "'{name}' is not a boolean setting{}",
+                        expected_syntax(&name)
+                    );
                 }
                 make((name, ConfigValue::Flag(true)))
             }
@@ -686,3 +832,19 @@ impl Parse for (String, ConfigValue) {
 }
 
 impl Many for (String, ConfigValue) {}
+
+/// A hint for an unknown setting that is probably a misspelling of a known one.
+fn did_you_mean(name: &str) -> String {
+    match similar_setting(name) {
+        Some(similar) => format!(", did you mean '{similar}'?"),
+        None => String::new(),
+    }
+}
+
+/// The accepted syntax of a setting, for when it is written in the wrong way.
+fn expected_syntax(name: &str) -> String {
+    match schema(name) {
+        Some(schema) => format!("; expected: {}", schema.syntax()),
+        None => String::new(),
+    }
+}
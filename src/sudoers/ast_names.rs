@@ -39,6 +39,10 @@ mod names {
         const DESCRIPTION: &'static str = "path to binary (or sudoedit)";
     }
 
+    impl UserFriendly for tokens::DefaultsCommand {
+        const DESCRIPTION: &'static str = tokens::Command::DESCRIPTION;
+    }
+
     impl UserFriendly
         for (
             SpecList<tokens::Hostname>,
@@ -101,6 +105,18 @@ mod names {
         const DESCRIPTION: &'static str = "directory or '*'";
     }
 
+    impl UserFriendly for tokens::EnvAssignment {
+        const DESCRIPTION: &'static str = "environment variable assignment";
+    }
+
+    impl UserFriendly for tokens::ProfileName {
+        const DESCRIPTION: &'static str = "sandbox profile";
+    }
+
+    impl UserFriendly for tokens::CapabilitySet {
+        const DESCRIPTION: &'static str = "list of capabilities";
+    }
+
     impl UserFriendly for (String, ConfigValue) {
         const DESCRIPTION: &'static str = "parameter";
     }
@@ -310,37 +310,103 @@ impl<T: Parse + Many + UserFriendly> Parse for Vec<T> {
 }
 
 /// Entry point utility function; parse a `Vec<T>` but with fatal error recovery per line
+#[cfg(test)]
 pub fn parse_lines<T, Stream: CharStream>(stream: &mut Stream) -> Vec<Parsed<T>>
 where
     T: Parse + UserFriendly,
 {
-    let mut result = Vec::new();
+    Lines::new(stream).collect()
+}
+
+/// The lines of a stream, parsed one at a time as the iterator advances, with fatal error
+/// recovery per line. If the stream ends early because of an error, that is the last item.
+pub struct Lines<T, Stream> {
+    stream: Stream,
+    pending: Option<Parsed<T>>,
+    done: bool,
+    /// The line on which the last item started.
+    line: usize,
+}
+
+impl<T, Stream: CharStream> Lines<T, Stream> {
+    pub fn new(stream: Stream) -> Self {
+        Lines {
+            stream,
+            pending: None,
+            done: false,
+            line: 0,
+        }
+    }
+
+    /// The items along with the line on which they start.
+    pub fn numbered(&mut self) -> impl Iterator<Item = (usize, Parsed<T>)> + '_
+    where
+        T: Parse + UserFriendly,
+    {
+        std::iter::from_fn(|| {
+            let item = self.next()?;
+            Some((self.line, item))
+        })
+    }
+
+    fn stream_error(&mut self) -> Option<Parsed<T>> {
+        let error = self.stream.take_error()?;
+        self.done = true;
+        let stream = &mut self.stream;
+        let error = |stream: &mut Stream| unrecoverable!(stream, "{error}");
+        Some(error(stream))
+    }
+}
+
+impl<T, Stream> Iterator for Lines<T, Stream>
+where
+    T: Parse + UserFriendly,
+    Stream: CharStream,
+{
+    type Item = Parsed<T>;
 
     // this will terminate; if the inner accept_if is an error, either a character will be consumed
     // by the second accept_if (making progress), or the end of the stream will have been reacherd
     // (which will cause the next iteration to fall through)
+    fn next(&mut self) -> Option<Parsed<T>> {
+        if let Some(item) = self.pending.take() {
+            return Some(item);
+        }
+        if self.done {
+            return None;
+        }
 
-    while LeadingWhitespace::parse(stream).is_ok() {
+        let stream = &mut self.stream;
+        if LeadingWhitespace::parse(stream).is_err() {
+            self.done = true;
+            return self.stream_error();
+        }
+        self.line = stream.get_pos().0;
         let item = expect_nonterminal(stream);
         let parsed_item_ok = item.is_ok();
-        result.push(item);
 
         let _ = maybe(Comment::parse(stream));
         if accept_if(|c| c == '\n', stream).is_none() {
-            if parsed_item_ok {
+            let garbage = parsed_item_ok.then(|| {
                 let msg = if stream.peek().is_none() {
                     "missing line terminator at end of file"
                 } else {
                     "garbage at end of line"
                 };
                 let error = |stream: &mut Stream| unrecoverable!(stream, "{msg}");
-                result.push(error(stream));
-            }
+                error(stream)
+            });
             while accept_if(|c| c != '\n', stream).is_some() {}
+
+            // a line that was cut short by an error in the stream is not used at all
+            if let Some(error) = self.stream_error() {
+                return Some(error);
+            }
+            self.pending = garbage;
         }
-    }
 
-    result
+        Some(item)
+    }
 }
 
 #[cfg(test)]
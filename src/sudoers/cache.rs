@@ -0,0 +1,911 @@
+//! A binary cache of a parsed sudoers file, for hosts where the sudoers file includes so many
+//! other files that parsing them noticeably slows down every invocation of sudo.
+//!
+//! The cache is enabled with `Path sudoers_cache /var/cache/sudo-rs/sudoers` in sudo.conf. It
+//! is written by visudo, and by sudo if it finds that the cache is missing or out of date. Along
+//! with the parsed policy, it records the inode, size and times of change of every file it was
+//! read from and of every directory that was included, so that checking whether the cache is up
+//! to date only takes a `stat` of each of them. Writing to a file, replacing it, or changing its
+//! owner or permissions changes these, and so does adding, removing or renaming a file in an
+//! included directory; the sudoers file is then parsed again. The files are opened with the same
+//! checks as when they are parsed.
+//!
+//! The rules are stored with their command paths as they were written, and the symbolic links
+//! in those paths are resolved again when the cache is read, just like when sudoers is parsed.
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, Metadata, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+use crate::defaults::{sudo_default, SudoDefault};
+use crate::system::{capabilities::CapabilitySet, secure_open, secure_open_no_symlinks};
+
+use super::ast::*;
+use super::tokens::*;
+use super::{AliasTable, CommandSettings, DefaultsLine, HostSettings, Origin, Settings, Sudoers};
+
+/// Identifies the format of the cache; a cache that was written by another version of sudo-rs
+/// is never used, so the format can change freely.
+const MAGIC: &[u8] = concat!("sudo-rs sudoers cache ", env!("CARGO_PKG_VERSION"), "\n").as_bytes();
+
+/// What identifies a version of a file or directory: it changes when the file is written to,
+/// replaced or renamed over, or has its owner or permissions changed.
+#[derive(Clone, Copy, Default, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+pub(super) struct Stamp {
+    device: u64,
+    inode: u64,
+    size: u64,
+    modified: (i64, i64),
+    changed: (i64, i64),
+}
+
+impl Stamp {
+    pub fn of(metadata: &Metadata) -> Self {
+        Stamp {
+            device: metadata.dev(),
+            inode: metadata.ino(),
+            size: metadata.size(),
+            modified: (metadata.mtime(), metadata.mtime_nsec()),
+            changed: (metadata.ctime(), metadata.ctime_nsec()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+pub(super) enum SourceKind {
+    Sudoers,
+    Include,
+    Directory,
+}
+
+/// A file or directory that a policy was read from, as it was just before it was read.
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub(super) struct Source {
+    pub path: PathBuf,
+    pub kind: SourceKind,
+    pub stamp: Stamp,
+}
+
+impl Source {
+    /// Whether the source is still the one that the policy was read from.
+    pub(super) fn is_unchanged(&self) -> bool {
+        let current = match self.kind {
+            SourceKind::Sudoers => {
+                secure_open_no_symlinks(&self.path, false).and_then(|file| file.metadata())
+            }
+            SourceKind::Include => {
+                secure_open_no_symlinks(&self.path, true).and_then(|file| file.metadata())
+            }
+            SourceKind::Directory => fs::metadata(&self.path),
+        };
+
+        matches!(current, Ok(metadata) if Stamp::of(&metadata) == self.stamp)
+    }
+}
+
+/// The policy in the cache at `cache`, if it was read from the sudoers file at `path` and none of
+/// the files it was read from have changed since.
+pub(super) fn load(cache: &Path, path: &Path) -> Option<Sudoers> {
+    let mut contents = Vec::new();
+    secure_open(cache, true)
+        .and_then(|mut file| file.read_to_end(&mut contents))
+        .ok()?;
+
+    let sudoers = decode(&contents)?;
+    let sources = &sudoers.sources;
+    if sources.first().map(|source| source.path.as_path()) != Some(path)
+        || !sources.iter().all(Source::is_unchanged)
+    {
+        return None;
+    }
+
+    Some(sudoers)
+}
+
+/// The contents of a cache of `sudoers`.
+pub(super) fn encode(sudoers: &Sudoers) -> Vec<u8> {
+    let mut contents = MAGIC.to_vec();
+    sudoers.sources.encode(&mut contents);
+    sudoers.encode(&mut contents);
+
+    contents
+}
+
+/// Read back a policy from [encode], or `None` if `contents` is not a valid cache.
+pub(super) fn decode(contents: &[u8]) -> Option<Sudoers> {
+    let mut input = contents.strip_prefix(MAGIC)?;
+    let sources = Vec::<Source>::decode(&mut input)?;
+    let sudoers = Sudoers::decode(&mut input)?;
+    if !input.is_empty() {
+        return None;
+    }
+
+    Some(Sudoers { sources, ..sudoers })
+}
+
+/// Write `sudoers` to the cache at `cache`, replacing the cache atomically so that a concurrent
+/// sudo never sees half of it.
+pub(super) fn store(cache: &Path, sudoers: &Sudoers) -> io::Result<()> {
+    let contents = encode(sudoers);
+
+    let mut temporary = cache.as_os_str().to_owned();
+    temporary.push(format!(".{}", std::process::id()));
+    let temporary = PathBuf::from(temporary);
+
+    // a file that is left over from an earlier attempt is replaced, but never followed if it is
+    // a symbolic link
+    let _ = fs::remove_file(&temporary);
+    let write = || -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&temporary)?;
+        file.write_all(&contents)?;
+        file.sync_all()?;
+        fs::rename(&temporary, cache)
+    };
+
+    write().map_err(|err| {
+        let _ = fs::remove_file(&temporary);
+        err
+    })
+}
+
+/// A type that can be written to the cache and read back.
+trait Cache: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+    fn decode(input: &mut &[u8]) -> Option<Self>;
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if input.len() < len {
+        return None;
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+
+    Some(bytes)
+}
+
+/// Write the variant of an enum.
+fn tag(out: &mut Vec<u8>, variant: u8) {
+    out.push(variant)
+}
+
+/// Write a byte string, preceded by its length.
+fn bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    bytes.len().encode(out);
+    out.extend_from_slice(bytes);
+}
+
+impl Cache for u8 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self)
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(take(input, 1)?[0])
+    }
+}
+
+impl Cache for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        u8::from(*self).encode(out)
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        match u8::decode(input)? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl Cache for u64 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes())
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(u64::from_le_bytes(take(input, 8)?.try_into().ok()?))
+    }
+}
+
+impl Cache for u32 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        u64::from(*self).encode(out)
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        u64::decode(input)?.try_into().ok()
+    }
+}
+
+impl Cache for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out)
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        u64::decode(input)?.try_into().ok()
+    }
+}
+
+impl Cache for i64 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out)
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(u64::decode(input)? as i64)
+    }
+}
+
+impl Cache for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        bytes(out, self.as_bytes())
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        let len = usize::decode(input)?;
+        String::from_utf8(take(input, len)?.to_vec()).ok()
+    }
+}
+
+impl Cache for Box<str> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        bytes(out, self.as_bytes())
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(String::decode(input)?.into_boxed_str())
+    }
+}
+
+impl Cache for PathBuf {
+    fn encode(&self, out: &mut Vec<u8>) {
+        bytes(out, self.as_os_str().as_bytes())
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        let len = usize::decode(input)?;
+        let bytes = take(input, len)?;
+        Some(PathBuf::from(std::ffi::OsStr::from_bytes(bytes)))
+    }
+}
+
+impl<T: Cache> Cache for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            None => tag(out, 0),
+            Some(value) => {
+                tag(out, 1);
+                value.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        match u8::decode(input)? {
+            0 => Some(None),
+            1 => Some(Some(T::decode(input)?)),
+            _ => None,
+        }
+    }
+}
+
+impl<A: Cache, B: Cache> Cache for (A, B) {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some((A::decode(input)?, B::decode(input)?))
+    }
+}
+
+impl<T: Cache> Cache for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        for item in self {
+            item.encode(out);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        let len = usize::decode(input)?;
+        // every item takes at least a byte, which bounds the allocation by the size of the cache
+        let mut items = Vec::with_capacity(len.min(input.len()));
+        for _ in 0..len {
+            items.push(T::decode(input)?);
+        }
+
+        Some(items)
+    }
+}
+
+impl<T: Cache> Cache for Box<[T]> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        for item in self.iter() {
+            item.encode(out);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(Vec::decode(input)?.into_boxed_slice())
+    }
+}
+
+impl Cache for HashSet<String> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        for item in self {
+            item.encode(out);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(Vec::decode(input)?.into_iter().collect())
+    }
+}
+
+impl<V: Cache> Cache for HashMap<String, V> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        for (key, value) in self {
+            key.encode(out);
+            value.encode(out);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(Vec::<(String, V)>::decode(input)?.into_iter().collect())
+    }
+}
+
+impl Cache for Stamp {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.device.encode(out);
+        self.inode.encode(out);
+        self.size.encode(out);
+        self.modified.encode(out);
+        self.changed.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(Stamp {
+            device: u64::decode(input)?,
+            inode: u64::decode(input)?,
+            size: u64::decode(input)?,
+            modified: <(i64, i64)>::decode(input)?,
+            changed: <(i64, i64)>::decode(input)?,
+        })
+    }
+}
+
+impl Cache for Source {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.path.encode(out);
+        tag(
+            out,
+            match self.kind {
+                SourceKind::Sudoers => 0,
+                SourceKind::Include => 1,
+                SourceKind::Directory => 2,
+            },
+        );
+        self.stamp.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(Source {
+            path: PathBuf::decode(input)?,
+            kind: match u8::decode(input)? {
+                0 => SourceKind::Sudoers,
+                1 => SourceKind::Include,
+                2 => SourceKind::Directory,
+                _ => return None,
+            },
+            stamp: Stamp::decode(input)?,
+        })
+    }
+}
+
+impl<T: Cache> Cache for Qualified<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let (variant, item) = match self {
+            Qualified::Allow(item) => (0, item),
+            Qualified::Forbid(item) => (1, item),
+        };
+        tag(out, variant);
+        item.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        match u8::decode(input)? {
+            0 => Some(Qualified::Allow(T::decode(input)?)),
+            1 => Some(Qualified::Forbid(T::decode(input)?)),
+            _ => None,
+        }
+    }
+}
+
+impl<T: Cache> Cache for Meta<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Meta::All => tag(out, 0),
+            Meta::Only(item) => {
+                tag(out, 1);
+                item.encode(out);
+            }
+            Meta::Alias(name) => {
+                tag(out, 2);
+                name.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        match u8::decode(input)? {
+            0 => Some(Meta::All),
+            1 => Some(Meta::Only(T::decode(input)?)),
+            2 => Some(Meta::Alias(String::decode(input)?)),
+            _ => None,
+        }
+    }
+}
+
+impl Cache for Identifier {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Identifier::Name(name) => {
+                tag(out, 0);
+                name.encode(out);
+            }
+            Identifier::ID(id) => {
+                tag(out, 1);
+                id.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        match u8::decode(input)? {
+            0 => Some(Identifier::Name(String::decode(input)?)),
+            1 => Some(Identifier::ID(u32::decode(input)?)),
+            _ => None,
+        }
+    }
+}
+
+impl Cache for UserSpecifier {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let (variant, identifier) = match self {
+            UserSpecifier::User(identifier) => (0, identifier),
+            UserSpecifier::Group(identifier) => (1, identifier),
+            UserSpecifier::NonunixGroup(identifier) => (2, identifier),
+        };
+        tag(out, variant);
+        identifier.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        let variant = u8::decode(input)?;
+        let identifier = Identifier::decode(input)?;
+        match variant {
+            0 => Some(UserSpecifier::User(identifier)),
+            1 => Some(UserSpecifier::Group(identifier)),
+            2 => Some(UserSpecifier::NonunixGroup(identifier)),
+            _ => None,
+        }
+    }
+}
+
+impl Cache for Hostname {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out)
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(Hostname(String::decode(input)?))
+    }
+}
+
+/// A command path is stored as it was written, and resolved again when it is read back.
+impl Cache for CommandPath {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.written.encode(out)
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        CommandPath::new(String::decode(input)?).ok()
+    }
+}
+
+impl Cache for RunAs {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.users.encode(out);
+        self.groups.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(RunAs {
+            users: Vec::decode(input)?,
+            groups: Vec::decode(input)?,
+        })
+    }
+}
+
+impl Cache for Modifier {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Modifier::Authenticate(authenticate) => {
+                tag(out, 0);
+                tag(
+                    out,
+                    match authenticate {
+                        Authenticate::None => 0,
+                        Authenticate::Passwd => 1,
+                        Authenticate::Nopasswd => 2,
+                    },
+                );
+            }
+            Modifier::Cwd(ChDir::Any) => tag(out, 1),
+            Modifier::Cwd(ChDir::Path(path)) => {
+                tag(out, 2);
+                path.encode(out);
+            }
+            Modifier::Caps(caps) => {
+                tag(out, 3);
+                caps.bits().encode(out);
+            }
+            Modifier::Sandbox(name) => {
+                tag(out, 4);
+                name.encode(out);
+            }
+            Modifier::Mail(mail) => {
+                tag(out, 5);
+                mail.encode(out);
+            }
+            Modifier::LogInput(log) => {
+                tag(out, 6);
+                log.encode(out);
+            }
+            Modifier::LogOutput(log) => {
+                tag(out, 7);
+                log.encode(out);
+            }
+            Modifier::Follow(follow) => {
+                tag(out, 8);
+                follow.encode(out);
+            }
+            Modifier::Setenv(setenv) => {
+                tag(out, 9);
+                setenv.encode(out);
+            }
+            Modifier::Noexec(noexec) => {
+                tag(out, 10);
+                noexec.encode(out);
+            }
+            Modifier::Env(name, value) => {
+                tag(out, 11);
+                name.encode(out);
+                value.encode(out);
+            }
+            Modifier::Intercept(intercept) => {
+                tag(out, 12);
+                intercept.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        match u8::decode(input)? {
+            0 => Some(Modifier::Authenticate(match u8::decode(input)? {
+                0 => Authenticate::None,
+                1 => Authenticate::Passwd,
+                2 => Authenticate::Nopasswd,
+                _ => return None,
+            })),
+            1 => Some(Modifier::Cwd(ChDir::Any)),
+            2 => Some(Modifier::Cwd(ChDir::Path(PathBuf::decode(input)?))),
+            3 => Some(Modifier::Caps(CapabilitySet::from_bits(u64::decode(
+                input,
+            )?))),
+            4 => Some(Modifier::Sandbox(String::decode(input)?)),
+            5 => Some(Modifier::Mail(bool::decode(input)?)),
+            6 => Some(Modifier::LogInput(bool::decode(input)?)),
+            7 => Some(Modifier::LogOutput(bool::decode(input)?)),
+            8 => Some(Modifier::Follow(bool::decode(input)?)),
+            9 => Some(Modifier::Setenv(bool::decode(input)?)),
+            10 => Some(Modifier::Noexec(bool::decode(input)?)),
+            11 => Some(Modifier::Env(
+                String::decode(input)?,
+                String::decode(input)?,
+            )),
+            12 => Some(Modifier::Intercept(bool::decode(input)?)),
+            _ => None,
+        }
+    }
+}
+
+impl Cache for CommandSpec {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(CommandSpec(Vec::decode(input)?, Spec::decode(input)?))
+    }
+}
+
+impl Cache for PermissionSpec {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.users.encode(out);
+        self.permissions.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(PermissionSpec {
+            users: Vec::decode(input)?,
+            permissions: Vec::decode(input)?,
+        })
+    }
+}
+
+impl<T: Cache> Cache for Def<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(Def(String::decode(input)?, Vec::decode(input)?))
+    }
+}
+
+/// An alias definition along with the order in which the definitions are processed, which must
+/// refer to each of the definitions.
+fn encode_ordered<T: Cache>((order, defs): &(Vec<usize>, Vec<T>), out: &mut Vec<u8>) {
+    order.encode(out);
+    defs.encode(out);
+}
+
+fn decode_ordered<T: Cache>(input: &mut &[u8]) -> Option<(Vec<usize>, Vec<T>)> {
+    let order = Vec::<usize>::decode(input)?;
+    let defs = Vec::<T>::decode(input)?;
+    if order.iter().any(|&index| index >= defs.len()) {
+        return None;
+    }
+
+    Some((order, defs))
+}
+
+impl Cache for AliasTable {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_ordered(&self.user, out);
+        encode_ordered(&self.host, out);
+        encode_ordered(&self.cmnd, out);
+        encode_ordered(&self.runas, out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(AliasTable {
+            user: decode_ordered(input)?,
+            host: decode_ordered(input)?,
+            cmnd: decode_ordered(input)?,
+            runas: decode_ordered(input)?,
+        })
+    }
+}
+
+impl Cache for Settings {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.flags.encode(out);
+        self.str_value.encode(out);
+        self.enum_value.len().encode(out);
+        for (name, value) in &self.enum_value {
+            name.encode(out);
+            bytes(out, value.get().as_bytes());
+        }
+        self.int_value.encode(out);
+        self.list.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        let flags = HashSet::decode(input)?;
+        let str_value = HashMap::decode(input)?;
+        // the possible values of a setting are not stored, but are those that it has now
+        let enum_value = Vec::<(String, String)>::decode(input)?
+            .into_iter()
+            .map(|(name, value)| {
+                let Some(SudoDefault::Enum(choices)) = sudo_default(&name) else {
+                    return None;
+                };
+                let value = choices.default.alt(&value)?;
+                Some((name, value))
+            })
+            .collect::<Option<_>>()?;
+
+        Some(Settings {
+            flags,
+            str_value,
+            enum_value,
+            int_value: HashMap::decode(input)?,
+            list: HashMap::decode(input)?,
+        })
+    }
+}
+
+impl Cache for Mode {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Mode::Add => tag(out, 0),
+            Mode::Set => tag(out, 1),
+            Mode::Del => tag(out, 2),
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        match u8::decode(input)? {
+            0 => Some(Mode::Add),
+            1 => Some(Mode::Set),
+            2 => Some(Mode::Del),
+            _ => None,
+        }
+    }
+}
+
+/// Write a setting of a `Defaults` line; the value of an enum is read back as one of the values
+/// that the setting has now, like in [Settings].
+fn encode_param((name, value): &(String, ConfigValue), out: &mut Vec<u8>) {
+    name.encode(out);
+    match value {
+        ConfigValue::Flag(value) => {
+            tag(out, 0);
+            value.encode(out);
+        }
+        ConfigValue::Text(text) => {
+            tag(out, 1);
+            text.encode(out);
+        }
+        ConfigValue::Num(value) => {
+            tag(out, 2);
+            value.encode(out);
+        }
+        ConfigValue::List(mode, items) => {
+            tag(out, 3);
+            mode.encode(out);
+            items.encode(out);
+        }
+        ConfigValue::Enum(value) => {
+            tag(out, 4);
+            bytes(out, value.get().as_bytes());
+        }
+    }
+}
+
+fn decode_param(input: &mut &[u8]) -> Option<(String, ConfigValue)> {
+    let name = String::decode(input)?;
+    let value = match u8::decode(input)? {
+        0 => ConfigValue::Flag(bool::decode(input)?),
+        1 => ConfigValue::Text(Option::decode(input)?),
+        2 => ConfigValue::Num(i64::decode(input)?),
+        3 => ConfigValue::List(Mode::decode(input)?, Vec::decode(input)?),
+        4 => {
+            let Some(SudoDefault::Enum(choices)) = sudo_default(&name) else {
+                return None;
+            };
+            ConfigValue::Enum(choices.default.alt(&String::decode(input)?)?)
+        }
+        _ => return None,
+    };
+
+    Some((name, value))
+}
+
+fn encode_params(params: &[(String, ConfigValue)], out: &mut Vec<u8>) {
+    params.len().encode(out);
+    for param in params {
+        encode_param(param, out);
+    }
+}
+
+fn decode_params(input: &mut &[u8]) -> Option<Vec<(String, ConfigValue)>> {
+    let len = usize::decode(input)?;
+    (0..len).map(|_| decode_param(input)).collect()
+}
+
+impl Cache for Origin {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.path.encode(out);
+        self.line.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(Origin {
+            path: PathBuf::decode(input)?,
+            line: usize::decode(input)?,
+        })
+    }
+}
+
+impl Cache for DefaultsLine {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.origin.encode(out);
+        encode_params(&self.params, out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(DefaultsLine {
+            origin: Origin::decode(input)?,
+            params: decode_params(input)?,
+        })
+    }
+}
+
+impl Cache for HostSettings {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.origin.encode(out);
+        self.hosts.encode(out);
+        encode_params(&self.params, out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(HostSettings {
+            origin: Origin::decode(input)?,
+            hosts: Vec::decode(input)?,
+            params: decode_params(input)?,
+        })
+    }
+}
+
+impl Cache for CommandSettings {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.origin.encode(out);
+        self.commands.encode(out);
+        encode_params(&self.params, out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(CommandSettings {
+            origin: Origin::decode(input)?,
+            commands: Vec::decode(input)?,
+            params: decode_params(input)?,
+        })
+    }
+}
+
+impl Cache for Sudoers {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.rules.encode(out);
+        self.rule_origins.encode(out);
+        self.aliases.encode(out);
+        self.settings.encode(out);
+        self.defaults.encode(out);
+        self.host_defaults.encode(out);
+        self.cmnd_defaults.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Option<Self> {
+        Some(Sudoers {
+            rules: Vec::decode(input)?,
+            rule_origins: Vec::decode(input)?,
+            aliases: AliasTable::decode(input)?,
+            settings: Settings::decode(input)?,
+            defaults: Vec::decode(input)?,
+            host_defaults: Vec::decode(input)?,
+            cmnd_defaults: Vec::decode(input)?,
+            sources: Vec::new(),
+        })
+    }
+}
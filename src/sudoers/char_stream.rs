@@ -1,44 +1,140 @@
+use std::io::{self, BufRead};
+
 pub trait CharStream {
     fn advance(&mut self);
     fn peek(&mut self) -> Option<char>;
     fn get_pos(&self) -> (usize, usize);
+
+    /// The problem that ended the stream before the end of the input, if any; it is reported
+    /// only once.
+    fn take_error(&mut self) -> Option<String> {
+        None
+    }
 }
 
-pub struct PeekableWithPos<Iter: Iterator> {
-    iter: std::iter::Peekable<Iter>,
+impl<Stream: CharStream + ?Sized> CharStream for &mut Stream {
+    fn advance(&mut self) {
+        (**self).advance()
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        (**self).peek()
+    }
+
+    fn get_pos(&self) -> (usize, usize) {
+        (**self).get_pos()
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        (**self).take_error()
+    }
+}
+
+/// The characters of a file, decoded as UTF-8 while it is read, so that a file never has to be
+/// held in memory in full. The stream ends early if the file cannot be read, is not valid UTF-8,
+/// or is larger than `limit` bytes.
+pub struct ReaderStream<R> {
+    reader: R,
+    next: Option<char>,
     line: usize,
     col: usize,
+    size: u64,
+    limit: u64,
+    failed: bool,
+    error: Option<String>,
 }
 
-impl<Iter: Iterator<Item = char>> PeekableWithPos<Iter> {
-    pub fn new(src: Iter) -> Self {
-        PeekableWithPos {
-            iter: src.peekable(),
+impl<R: BufRead> ReaderStream<R> {
+    pub fn new(reader: R, limit: u64) -> Self {
+        ReaderStream {
+            reader,
+            next: None,
             line: 1,
             col: 1,
+            size: 0,
+            limit,
+            failed: false,
+            error: None,
+        }
+    }
+
+    fn byte(&mut self) -> io::Result<Option<u8>> {
+        let byte = loop {
+            match self.reader.fill_buf() {
+                Ok(buffer) => break buffer.first().copied(),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        };
+        if byte.is_some() {
+            self.reader.consume(1);
+            self.size += 1;
+            if self.size > self.limit {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("file is larger than {} bytes", self.limit),
+                ));
+            }
         }
+
+        Ok(byte)
+    }
+
+    fn decode(&mut self) -> io::Result<Option<char>> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid UTF-8");
+
+        let Some(first) = self.byte()? else {
+            return Ok(None);
+        };
+        let len = match first.leading_ones() {
+            0 => 1,
+            2..=4 => first.leading_ones() as usize,
+            _ => return Err(invalid()),
+        };
+        let mut bytes = [first, 0, 0, 0];
+        for byte in &mut bytes[1..len] {
+            *byte = self.byte()?.ok_or_else(invalid)?;
+        }
+
+        let text = std::str::from_utf8(&bytes[..len]).map_err(|_| invalid())?;
+        Ok(text.chars().next())
     }
 }
 
-impl<Iter: Iterator<Item = char>> CharStream for PeekableWithPos<Iter> {
+impl<R: BufRead> CharStream for ReaderStream<R> {
     fn advance(&mut self) {
-        match self.iter.next() {
+        match self.peek() {
             Some('\n') => {
                 self.line += 1;
                 self.col = 1;
             }
             Some(_) => self.col += 1,
-            _ => {}
+            None => {}
         }
+        self.next = None;
     }
 
     fn peek(&mut self) -> Option<char> {
-        self.iter.peek().cloned()
+        if self.next.is_none() && !self.failed {
+            match self.decode() {
+                Ok(next) => self.next = next,
+                Err(err) => {
+                    self.failed = true;
+                    self.error = Some(err.to_string());
+                }
+            }
+        }
+
+        self.next
     }
 
     fn get_pos(&self) -> (usize, usize) {
         (self.line, self.col)
     }
+
+    fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
 }
 
 #[cfg(test)]
@@ -62,7 +158,7 @@ mod test {
 
     #[test]
     fn test_iter() {
-        let mut stream = PeekableWithPos::<std::str::Chars>::new("12\n3\n".chars());
+        let mut stream = ReaderStream::new("12\n3\n".as_bytes(), 100);
         assert_eq!(stream.peek(), Some('1'));
         stream.advance();
         assert_eq!(stream.peek(), Some('2'));
@@ -72,4 +168,41 @@ mod test {
         stream.advance();
         assert_eq!(stream.get_pos(), (2, 2));
     }
+
+    #[test]
+    fn decodes_utf8() {
+        let text = "a\u{e9}\u{20ac}\u{1f980}\n";
+        let mut stream = ReaderStream::new(text.as_bytes(), 100);
+        let mut chars = String::new();
+        while let Some(c) = stream.peek() {
+            chars.push(c);
+            stream.advance();
+        }
+        assert_eq!(chars, text);
+        assert_eq!(stream.get_pos(), (2, 1));
+        assert_eq!(stream.take_error(), None);
+    }
+
+    #[test]
+    fn stops_at_errors() {
+        let mut stream = ReaderStream::new(&b"a\xff"[..], 100);
+        assert_eq!(stream.peek(), Some('a'));
+        stream.advance();
+        assert_eq!(stream.peek(), None);
+        assert_eq!(stream.get_pos(), (1, 2));
+        assert_eq!(stream.take_error().as_deref(), Some("invalid UTF-8"));
+        assert_eq!(stream.take_error(), None);
+        assert_eq!(stream.peek(), None);
+
+        let mut stream = ReaderStream::new("abcd".as_bytes(), 3);
+        for c in "abc".chars() {
+            assert_eq!(stream.peek(), Some(c));
+            stream.advance();
+        }
+        assert_eq!(stream.peek(), None);
+        assert_eq!(
+            stream.take_error().as_deref(),
+            Some("file is larger than 3 bytes")
+        );
+    }
 }
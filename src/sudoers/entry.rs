@@ -16,17 +16,23 @@ mod verbose;
 
 pub struct Entry<'a> {
     run_as: &'a RunAs,
+    runas_default: &'a str,
     cmd_specs: Vec<(Tag, Qualified<&'a Meta<Command>>)>,
 }
 
 impl<'a> Entry<'a> {
     pub(super) fn new(
         run_as: &'a RunAs,
+        runas_default: &'a str,
         cmd_specs: Vec<(Tag, Qualified<&'a Meta<Command>>)>,
     ) -> Self {
         debug_assert!(!cmd_specs.is_empty());
 
-        Self { run_as, cmd_specs }
+        Self {
+            run_as,
+            runas_default,
+            cmd_specs,
+        }
     }
 
     pub fn verbose(self) -> impl fmt::Display + 'a {
@@ -36,10 +42,14 @@ impl<'a> Entry<'a> {
 
 impl fmt::Display for Entry<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let Self { run_as, cmd_specs } = self;
+        let Self {
+            run_as,
+            runas_default,
+            cmd_specs,
+        } = self;
 
         f.write_str("    (")?;
-        write_users(run_as, f)?;
+        write_users(run_as, runas_default, f)?;
         if !run_as.groups.is_empty() {
             f.write_str(" : ")?;
         }
@@ -63,10 +73,13 @@ impl fmt::Display for Entry<'_> {
     }
 }
 
-fn write_users(run_as: &RunAs, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+fn write_users(
+    run_as: &RunAs,
+    runas_default: &str,
+    f: &mut fmt::Formatter<'_>,
+) -> Result<(), fmt::Error> {
     if run_as.users.is_empty() {
-        // XXX assumes that the superuser is called "root"
-        f.write_str("root")?;
+        f.write_str(runas_default)?;
     }
 
     let mut is_first_user = true;
@@ -141,22 +154,39 @@ fn write_groups(run_as: &RunAs, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::E
 }
 
 fn write_tag(f: &mut fmt::Formatter, tag: &Tag, last_tag: Option<&Tag>) -> fmt::Result {
-    let (cwd, auth) = if let Some(last_tag) = last_tag {
+    let (cwd, caps, sandbox, auth) = if let Some(last_tag) = last_tag {
         let cwd = if last_tag.cwd == tag.cwd {
             None
         } else {
             tag.cwd.as_ref()
         };
 
+        let caps = if last_tag.caps == tag.caps {
+            None
+        } else {
+            tag.caps
+        };
+
+        let sandbox = if last_tag.sandbox == tag.sandbox {
+            None
+        } else {
+            tag.sandbox.as_ref()
+        };
+
         let auth = if last_tag.authenticate == tag.authenticate {
             None
         } else {
             Some(tag.authenticate)
         };
 
-        (cwd, auth)
+        (cwd, caps, sandbox, auth)
     } else {
-        (tag.cwd.as_ref(), Some(tag.authenticate))
+        (
+            tag.cwd.as_ref(),
+            tag.caps,
+            tag.sandbox.as_ref(),
+            Some(tag.authenticate),
+        )
     };
 
     if let Some(cwd) = cwd {
@@ -168,6 +198,66 @@ fn write_tag(f: &mut fmt::Formatter, tag: &Tag, last_tag: Option<&Tag>) -> fmt::
         f.write_str(" ")?;
     }
 
+    if let Some(caps) = caps {
+        write!(f, "CAPS={caps} ")?;
+    }
+
+    if let Some(sandbox) = sandbox {
+        write!(f, "SANDBOX={sandbox} ")?;
+    }
+
+    for assignment in &tag.env {
+        if last_tag.is_some_and(|last_tag| last_tag.env.contains(assignment)) {
+            continue;
+        }
+        let (name, value) = assignment;
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        write!(f, "ENV=\"{name}={value}\" ")?;
+    }
+
+    // `EXEC:` is the negation of `NOEXEC:`
+    let exec = |tag: &Tag| tag.noexec.map(|noexec| !noexec);
+    let switches = [
+        ("EXEC", exec(tag), last_tag.map(exec)),
+        (
+            "FOLLOW",
+            tag.follow,
+            last_tag.map(|last_tag| last_tag.follow),
+        ),
+        (
+            "INTERCEPT",
+            tag.intercept,
+            last_tag.map(|last_tag| last_tag.intercept),
+        ),
+        (
+            "LOG_INPUT",
+            tag.log_input,
+            last_tag.map(|last_tag| last_tag.log_input),
+        ),
+        (
+            "LOG_OUTPUT",
+            tag.log_output,
+            last_tag.map(|last_tag| last_tag.log_output),
+        ),
+        ("MAIL", tag.mail, last_tag.map(|last_tag| last_tag.mail)),
+        (
+            "SETENV",
+            tag.setenv,
+            last_tag.map(|last_tag| last_tag.setenv),
+        ),
+    ];
+    for (name, switch, last_switch) in switches {
+        if last_switch == Some(switch) {
+            continue;
+        }
+        if let Some(enabled) = switch {
+            if !enabled {
+                f.write_str("NO")?;
+            }
+            write!(f, "{name}: ")?;
+        }
+    }
+
     if let Some(auth) = auth {
         if auth != Authenticate::None {
             let tag = if auth == Authenticate::Passwd {
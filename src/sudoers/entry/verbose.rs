@@ -11,7 +11,11 @@ pub struct Verbose<'a>(pub Entry<'a>);
 
 impl fmt::Display for Verbose<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let Self(Entry { run_as, cmd_specs }) = self;
+        let Self(Entry {
+            run_as,
+            runas_default,
+            cmd_specs,
+        }) = self;
 
         let mut last_tag = None;
         for (tag, cmd_spec) in cmd_specs {
@@ -21,7 +25,7 @@ impl fmt::Display for Verbose<'_> {
                     f.write_str("\n")?;
                 }
 
-                write_entry_header(run_as, f)?;
+                write_entry_header(run_as, runas_default, f)?;
                 write_tag(f, tag)?;
                 f.write_str("\n    Commands:")?;
             }
@@ -35,16 +39,20 @@ impl fmt::Display for Verbose<'_> {
     }
 }
 
-fn write_entry_header(run_as: &RunAs, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+fn write_entry_header(
+    run_as: &RunAs,
+    runas_default: &str,
+    f: &mut fmt::Formatter<'_>,
+) -> fmt::Result {
     f.write_str("\nSudoers entry:")?;
 
-    write_users(run_as, f)?;
+    write_users(run_as, runas_default, f)?;
     write_groups(run_as, f)
 }
 
-fn write_users(run_as: &RunAs, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+fn write_users(run_as: &RunAs, runas_default: &str, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     f.write_str("\n    RunAsUsers: ")?;
-    super::write_users(run_as, f)
+    super::write_users(run_as, runas_default, f)
 }
 
 fn write_groups(run_as: &RunAs, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -57,12 +65,29 @@ fn write_groups(run_as: &RunAs, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 }
 
 fn write_tag(f: &mut fmt::Formatter, tag: &Tag) -> fmt::Result {
+    let mut options = Vec::new();
     if tag.authenticate != Authenticate::None {
-        f.write_str("\n    Options: ")?;
-        if tag.authenticate != Authenticate::Passwd {
+        options.push((tag.authenticate == Authenticate::Passwd, "authenticate"));
+    }
+    let switches = [
+        (tag.follow, "sudoedit_follow"),
+        (tag.log_input, "log_input"),
+        (tag.log_output, "log_output"),
+        (tag.mail, "mail_all_cmnds"),
+        (tag.setenv, "setenv"),
+    ];
+    for (switch, name) in switches {
+        if let Some(enabled) = switch {
+            options.push((enabled, name));
+        }
+    }
+
+    for (i, (enabled, name)) in options.into_iter().enumerate() {
+        f.write_str(if i == 0 { "\n    Options: " } else { ", " })?;
+        if !enabled {
             f.write_str("!")?;
         }
-        f.write_str("authenticate")?;
+        f.write_str(name)?;
     }
 
     if let Some(cwd) = &tag.cwd {
@@ -73,5 +98,13 @@ fn write_tag(f: &mut fmt::Formatter, tag: &Tag) -> fmt::Result {
         }
     }
 
+    if let Some(caps) = &tag.caps {
+        write!(f, "\n    Capabilities: {caps}")?;
+    }
+
+    if let Some(sandbox) = &tag.sandbox {
+        write!(f, "\n    Sandbox: {sandbox}")?;
+    }
+
     Ok(())
 }
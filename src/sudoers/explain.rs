@@ -0,0 +1,265 @@
+//! Explaining how the sudoers files decide on a request: what each rule says about it, which
+//! rule decides, and which `Defaults` lines are in effect, along with where each of them is.
+use std::fmt;
+use std::path::PathBuf;
+
+use super::*;
+
+/// A line in one of the sudoers files.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Origin {
+    pub path: PathBuf,
+    pub line: usize,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.path.display(), self.line)
+    }
+}
+
+/// What a rule says about a request.
+#[derive(PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+pub(super) enum Verdict {
+    /// The rule is not for the invoking user.
+    OtherUser,
+    /// The rule is not for this host.
+    OtherHost,
+    /// The rule does not allow running commands as the target user and group.
+    OtherRunas,
+    /// The rule does not mention the command.
+    OtherCommand,
+    /// The rule forbids the command.
+    Forbids,
+    /// The rule allows the command, with these tags.
+    Allows(Tag),
+}
+
+/// How the sudoers files decide on a request, see [Sudoers::explain].
+pub struct Explanation<'a> {
+    pub(super) rules: Vec<(&'a Origin, Verdict)>,
+    /// The `Defaults` lines that apply, in the order they are applied.
+    pub(super) defaults: Vec<AppliedDefaults<'a>>,
+}
+
+/// A `Defaults` line, with what it is restricted to.
+type AppliedDefaults<'a> = (&'a Origin, DefaultsScope<'a>, &'a [(String, ConfigValue)]);
+
+/// The hosts of a `Defaults@host` line, or the commands of a `Defaults!command` line.
+pub(super) enum DefaultsScope<'a> {
+    Everywhere,
+    Hosts(&'a SpecList<Hostname>),
+    Commands(&'a SpecList<Command>),
+}
+
+impl Explanation<'_> {
+    /// The rule that decides on the request: the last one that allows or forbids the command.
+    pub fn deciding_rule(&self) -> Option<(&Origin, bool)> {
+        self.rules
+            .iter()
+            .rev()
+            .find_map(|(origin, verdict)| match verdict {
+                Verdict::Forbids => Some((*origin, false)),
+                Verdict::Allows(_) => Some((*origin, true)),
+                _ => None,
+            })
+    }
+}
+
+impl Sudoers {
+    /// Explain how `am_user` running `request` on `on_host` is decided on, by going through the
+    /// same steps as [Sudoers::check] one rule at a time.
+    pub fn explain<User: UnixUser + PartialEq<User>, Group: UnixGroup>(
+        &self,
+        am_user: &User,
+        on_host: &str,
+        request: Request<User, Group>,
+    ) -> Explanation<'_> {
+        let cmdline = (request.command, request.arguments);
+        let user_aliases = get_aliases(&self.aliases.user, &match_user(am_user));
+        let host_aliases = get_aliases(&self.aliases.host, &match_host(on_host));
+        let cmnd_aliases = get_aliases(&self.aliases.cmnd, &match_command(cmdline));
+        let runas_matches = match_runas(self, am_user, on_host, &request);
+
+        let mut rules = Vec::with_capacity(self.rules.len());
+        for (rule, origin) in self.rules.iter().zip(&self.rule_origins) {
+            let verdict = 'verdict: {
+                if find_item(&rule.users, &match_user(am_user), &user_aliases).is_none() {
+                    break 'verdict Verdict::OtherUser;
+                }
+
+                let cmd_specs = rule
+                    .permissions
+                    .iter()
+                    .filter(|(hosts, _)| {
+                        find_item(hosts, &match_host(on_host), &host_aliases).is_some()
+                    })
+                    .flat_map(|(_, runas_cmds)| distribute_tags(runas_cmds))
+                    .collect::<Vec<_>>();
+                if cmd_specs.is_empty() {
+                    break 'verdict Verdict::OtherHost;
+                }
+
+                let commands = cmd_specs
+                    .into_iter()
+                    .filter(|(runas, _)| runas_matches(*runas))
+                    .map(|(_, cmdspec)| imply_tags(cmdspec))
+                    .collect::<Vec<_>>();
+                if commands.is_empty() {
+                    break 'verdict Verdict::OtherRunas;
+                }
+
+                match find_match(commands, &match_command(cmdline), &cmnd_aliases) {
+                    None => Verdict::OtherCommand,
+                    Some((false, _)) => Verdict::Forbids,
+                    Some((true, tag)) => Verdict::Allows(tag),
+                }
+            };
+            rules.push((origin, verdict));
+        }
+
+        let mut defaults = self
+            .defaults
+            .iter()
+            .map(|line| (&line.origin, DefaultsScope::Everywhere, &line.params[..]))
+            .collect::<Vec<_>>();
+        defaults.extend(
+            self.host_defaults
+                .iter()
+                .filter(|line| {
+                    find_item(&line.hosts, &match_host(on_host), &host_aliases).is_some()
+                })
+                .map(|line| {
+                    (
+                        &line.origin,
+                        DefaultsScope::Hosts(&line.hosts),
+                        &line.params[..],
+                    )
+                }),
+        );
+        defaults.extend(
+            self.cmnd_defaults
+                .iter()
+                .filter(|line| {
+                    find_item(&line.commands, &match_command(cmdline), &cmnd_aliases).is_some()
+                })
+                .map(|line| {
+                    (
+                        &line.origin,
+                        DefaultsScope::Commands(&line.commands),
+                        &line.params[..],
+                    )
+                }),
+        );
+
+        Explanation { rules, defaults }
+    }
+}
+
+impl fmt::Display for Explanation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let deciding_rule = self.deciding_rule().map(|(origin, _)| origin);
+
+        writeln!(f, "Rules:")?;
+        if self.rules.is_empty() {
+            writeln!(f, "    (none)")?;
+        }
+        for (origin, verdict) in &self.rules {
+            let text = match verdict {
+                Verdict::OtherUser => "does not apply to the user",
+                Verdict::OtherHost => "does not apply on this host",
+                Verdict::OtherRunas => "does not allow running commands as the target user",
+                Verdict::OtherCommand => "does not match the command",
+                Verdict::Forbids => "forbids the command",
+                Verdict::Allows(tag) if tag.authenticate == Authenticate::Nopasswd => {
+                    "allows the command without a password"
+                }
+                Verdict::Allows(_) => "allows the command",
+            };
+            write!(f, "    {origin}: {text}")?;
+            if matches!(verdict, Verdict::Forbids | Verdict::Allows(_))
+                && deciding_rule != Some(origin)
+            {
+                write!(f, ", but a later rule decides")?;
+            }
+            writeln!(f)?;
+        }
+
+        match self.deciding_rule() {
+            Some((origin, true)) => writeln!(f, "The command is allowed by {origin}.")?,
+            Some((origin, false)) => writeln!(f, "The command is forbidden by {origin}.")?,
+            None => writeln!(f, "No rule allows the command.")?,
+        }
+
+        write!(f, "\nDefaults:")?;
+        if self.defaults.is_empty() {
+            write!(f, "\n    (none)")?;
+        }
+        for (origin, scope, params) in &self.defaults {
+            write!(f, "\n    {origin}: Defaults")?;
+            match scope {
+                DefaultsScope::Everywhere => {}
+                DefaultsScope::Hosts(hosts) => {
+                    f.write_str("@")?;
+                    write_list(f, hosts, |f, host| f.write_str(host.as_str()))?;
+                }
+                DefaultsScope::Commands(commands) => {
+                    f.write_str("!")?;
+                    write_list(f, commands, |f, (pattern, _)| f.write_str(pattern.as_str()))?;
+                }
+            }
+            for (i, param) in params.iter().enumerate() {
+                f.write_str(if i == 0 { " " } else { ", " })?;
+                write_param(f, param)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn write_list<T>(
+    f: &mut fmt::Formatter,
+    list: &SpecList<T>,
+    write_item: impl Fn(&mut fmt::Formatter, &T) -> fmt::Result,
+) -> fmt::Result {
+    for (i, spec) in list.iter().enumerate() {
+        if i > 0 {
+            f.write_str(",")?;
+        }
+        let meta = match spec {
+            Qualified::Allow(meta) => meta,
+            Qualified::Forbid(meta) => {
+                f.write_str("!")?;
+                meta
+            }
+        };
+        match meta {
+            Meta::All => f.write_str("ALL")?,
+            Meta::Only(item) => write_item(f, item)?,
+            Meta::Alias(alias) => f.write_str(alias)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Write a setting the way it is written on a `Defaults` line.
+fn write_param(f: &mut fmt::Formatter, (name, value): &(String, ConfigValue)) -> fmt::Result {
+    match value {
+        ConfigValue::Flag(true) => write!(f, "{name}"),
+        ConfigValue::Flag(false) | ConfigValue::Text(None) => write!(f, "!{name}"),
+        ConfigValue::Text(Some(text)) => write!(f, "{name}=\"{text}\""),
+        ConfigValue::Num(value) => write!(f, "{name}={value}"),
+        ConfigValue::Enum(value) => write!(f, "{name}={}", value.get()),
+        ConfigValue::List(mode, values) => {
+            let operator = match mode {
+                Mode::Set => "=",
+                Mode::Add => "+=",
+                Mode::Del => "-=",
+            };
+            write!(f, "{name}{operator}\"{}\"", values.join(" "))
+        }
+    }
+}
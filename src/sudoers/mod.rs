@@ -5,33 +5,91 @@
 mod ast;
 mod ast_names;
 mod basic_parser;
+mod cache;
 mod char_stream;
 mod entry;
+mod explain;
 mod tokens;
 
 use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, Metadata};
+use std::io::BufReader;
 use std::path::{Path, PathBuf};
 use std::{io, mem};
 
-use crate::common::resolve::resolve_path;
-use crate::log::auth_warn;
-use crate::system::can_execute;
+use crate::common::resolve::resolve_editor;
+use crate::log::{auth_warn, dev_warn};
 use crate::system::interface::{UnixGroup, UnixUser};
 use ast::*;
+use cache::{Source, SourceKind, Stamp};
+use char_stream::ReaderStream;
 use tokens::*;
 
 /// How many nested include files do we allow?
-const INCLUDE_LIMIT: u8 = 128;
+const INCLUDE_LIMIT: usize = 128;
+
+/// How many files do we read in total, including the same file more than once?
+const INCLUDE_FILES_LIMIT: usize = 65536;
+
+/// How large may a single sudoers file be, in bytes?
+const MAX_FILE_SIZE: u64 = 16 * 1024 * 1024;
 
 /// Export some necessary symbols from modules
 pub use ast::TextEnum;
-pub struct Error(pub Option<basic_parser::Position>, pub String);
+/// A problem found while reading a sudoers file.
+pub struct Error {
+    /// The file that has the problem, if it is in a particular file.
+    pub source: Option<PathBuf>,
+    pub location: Option<basic_parser::Position>,
+    pub message: String,
+}
+
+impl Error {
+    /// A problem that is not at a particular place in a file.
+    fn general(message: String) -> Self {
+        Error {
+            source: None,
+            location: None,
+            message,
+        }
+    }
+}
 
 #[derive(Default)]
 pub struct Sudoers {
     rules: Vec<PermissionSpec>,
+    /// Where each of the rules was read from, in the same order.
+    rule_origins: Vec<Origin>,
     aliases: AliasTable,
     settings: Settings,
+    /// The `Defaults` lines that make up `settings`, in the order they were applied.
+    defaults: Vec<DefaultsLine>,
+    /// The `Defaults@host` lines, which are applied after the other `Defaults` on those hosts.
+    host_defaults: Vec<HostSettings>,
+    /// The `Defaults!command` lines, which are applied last, when one of their commands is run.
+    cmnd_defaults: Vec<CommandSettings>,
+    /// The files and directories the rules were read from, starting with the sudoers file.
+    sources: Vec<Source>,
+}
+
+/// The settings of a `Defaults` line.
+pub(super) struct DefaultsLine {
+    origin: Origin,
+    params: Vec<(String, ConfigValue)>,
+}
+
+/// The settings of a `Defaults@host_list` line.
+pub(super) struct HostSettings {
+    origin: Origin,
+    hosts: SpecList<Hostname>,
+    params: Vec<(String, ConfigValue)>,
+}
+
+/// The settings of a `Defaults!cmnd_list` line.
+pub(super) struct CommandSettings {
+    origin: Origin,
+    commands: SpecList<Command>,
+    params: Vec<(String, ConfigValue)>,
 }
 
 /// A structure that represents what the user wants to do
@@ -55,23 +113,56 @@ pub struct Judgement {
 
 mod policy;
 
-pub use policy::{Authorization, AuthorizationAllowed, DirChange, Policy, PreJudgementPolicy};
+pub use policy::{
+    Authorization, AuthorizationAllowed, DirChange, NestedSudo, Policy, PreJudgementPolicy,
+};
 
 pub use self::entry::Entry;
+use self::explain::Origin;
 
 /// This function takes a file argument for a sudoers file and processes it.
 impl Sudoers {
     pub fn open(path: impl AsRef<Path>) -> Result<(Sudoers, Vec<Error>), io::Error> {
-        let sudoers = open_sudoers(path.as_ref())?;
-        Ok(analyze(path.as_ref(), sudoers))
+        let (sudoers, stamp) = open_sudoers(path.as_ref())?;
+        Ok(analyze_file(path.as_ref(), sudoers, stamp))
     }
 
     pub fn read<R: io::Read, P: AsRef<Path>>(
         reader: R,
         path: P,
     ) -> Result<(Sudoers, Vec<Error>), io::Error> {
-        let sudoers = read_sudoers(reader)?;
-        Ok(analyze(path.as_ref(), sudoers))
+        // rules that were not read from a file never match a cache
+        Ok(analyze_file(
+            path.as_ref(),
+            read_sudoers(reader),
+            Stamp::default(),
+        ))
+    }
+
+    /// Like [Sudoers::open], but take the rules from the cache at `cache` if none of the files
+    /// they were read from have changed. Otherwise the cache is brought up to date, unless the
+    /// sudoers file has errors.
+    pub fn open_cached(
+        path: impl AsRef<Path>,
+        cache: &Path,
+    ) -> Result<(Sudoers, Vec<Error>), io::Error> {
+        if let Some(sudoers) = cache::load(cache, path.as_ref()) {
+            return Ok((sudoers, Vec::new()));
+        }
+
+        let (sudoers, errors) = Self::open(path)?;
+        if errors.is_empty() {
+            sudoers.write_cache(cache);
+        }
+
+        Ok((sudoers, errors))
+    }
+
+    /// Store these rules in the cache at `cache`, for [Sudoers::open_cached].
+    pub fn write_cache(&self, cache: &Path) {
+        if let Err(err) = cache::store(cache, self) {
+            dev_warn!("unable to update {}: {err}", cache.display());
+        }
     }
 
     pub fn check<User: UnixUser + PartialEq<User>, Group: UnixGroup>(
@@ -84,6 +175,7 @@ impl Sudoers {
         let skip_passwd =
             am_user.is_root() || (request.user == am_user && in_group(am_user, request.group));
 
+        let settings = self.settings_for(on_host, (request.command, request.arguments));
         let mut flags = check_permission(self, am_user, on_host, request);
         if let Some(Tag { authenticate, .. }) = flags.as_mut() {
             if skip_passwd {
@@ -91,10 +183,7 @@ impl Sudoers {
             }
         }
 
-        Judgement {
-            flags,
-            settings: self.settings.clone(), // this is wasteful, but in the future this will not be a simple clone and it avoids a lifetime
-        }
+        Judgement { flags, settings }
     }
 
     pub fn check_list_permission<User: UnixUser + PartialEq<User>, Group: UnixGroup>(
@@ -129,10 +218,151 @@ impl Sudoers {
 
         Judgement {
             flags,
-            settings: self.settings.clone(), // this is wasteful, but in the future this will not be a simple clone and it avoids a lifetime
+            settings: self.settings_on(hostname),
         }
     }
 
+    /// Check whether `invoking_user` may refresh their session record with `sudo -v`, which any
+    /// rule on `hostname` allows; `verifypw` decides whether that takes a password: `all` unless
+    /// every rule has `NOPASSWD`, `any` unless one of them has it, and `always` or `never`.
+    pub fn check_validate_permission<User: UnixUser + PartialEq<User>>(
+        &self,
+        invoking_user: &User,
+        hostname: &str,
+    ) -> Judgement {
+        let settings = self.settings_on(hostname);
+        let tags = self
+            .matching_user_specs(invoking_user, hostname)
+            .flatten()
+            .map(|(_, (tag, _))| tag)
+            .collect::<Vec<_>>();
+        if tags.is_empty() {
+            return Judgement {
+                flags: None,
+                settings,
+            };
+        }
+
+        let needs_passwd = !invoking_user.is_root()
+            && match settings.enum_value["verifypw"].get() {
+                "never" => false,
+                "any" => tags.iter().all(Tag::needs_passwd),
+                "all" => tags.iter().any(Tag::needs_passwd),
+                _ => true,
+            };
+        let authenticate = if needs_passwd {
+            Authenticate::Passwd
+        } else {
+            Authenticate::Nopasswd
+        };
+
+        Judgement {
+            flags: Some(Tag {
+                authenticate,
+                ..Default::default()
+            }),
+            settings,
+        }
+    }
+
+    /// Check whether `invoking_user` may list the privileges of `other_user` with `sudo -l -U`:
+    /// this takes a rule that allows the `list` pseudo-command (or `ALL`) to be run as that
+    /// user, of which the tags decide whether a password is needed.
+    pub fn check_list_other_permission<User: UnixUser + PartialEq<User>, Group: UnixGroup>(
+        &self,
+        invoking_user: &User,
+        hostname: &str,
+        (other_user, other_group): (&User, &Group),
+        request: ListRequest<User, Group>,
+    ) -> Judgement {
+        // exception: if user is root or does not switch users, NOPASSWD is implied
+        let skip_passwd = invoking_user.is_root()
+            || (request.target_user == invoking_user
+                && in_group(invoking_user, request.target_group));
+
+        let list_request = Request {
+            user: other_user,
+            group: other_group,
+            command: Path::new(LIST_COMMAND),
+            arguments: &[],
+        };
+        let mut flags = check_permission(self, invoking_user, hostname, list_request);
+        if let Some(Tag { authenticate, .. }) = flags.as_mut() {
+            if skip_passwd {
+                *authenticate = Authenticate::Nopasswd;
+            }
+        }
+
+        Judgement {
+            flags,
+            settings: self.settings_on(hostname),
+        }
+    }
+
+    /// The `Defaults@host` settings that apply on `hostname`, in the order they appear in.
+    fn host_params<'a: 'h, 'h>(
+        &'a self,
+        hostname: &'h str,
+    ) -> impl Iterator<Item = &'a (String, ConfigValue)> + 'h {
+        let host_aliases = get_aliases(&self.aliases.host, &match_host(hostname));
+
+        self.host_defaults
+            .iter()
+            .filter(move |defaults| {
+                find_item(&defaults.hosts, &match_host(hostname), &host_aliases).is_some()
+            })
+            .flat_map(|defaults| &defaults.params)
+    }
+
+    /// The settings on `hostname`: those of the `Defaults` lines, followed by those of the
+    /// `Defaults@host` lines that apply to it.
+    fn settings_on(&self, hostname: &str) -> Settings {
+        let mut settings = self.settings.clone();
+        for (name, value) in self.host_params(hostname) {
+            settings.apply(name.clone(), value.clone());
+        }
+
+        settings
+    }
+
+    /// The `Defaults!command` settings that apply to running `cmdline`, in the order they appear in.
+    fn command_params<'a: 'c, 'c>(
+        &'a self,
+        cmdline: (&'c Path, &'c [String]),
+    ) -> impl Iterator<Item = &'a (String, ConfigValue)> + 'c {
+        let cmnd_aliases = get_aliases(&self.aliases.cmnd, &match_command(cmdline));
+
+        self.cmnd_defaults
+            .iter()
+            .filter(move |defaults| {
+                find_item(&defaults.commands, &match_command(cmdline), &cmnd_aliases).is_some()
+            })
+            .flat_map(|defaults| &defaults.params)
+    }
+
+    /// The settings for running `cmdline` on `hostname`: those of [Sudoers::settings_on],
+    /// followed by those of the `Defaults!command` lines that apply to it.
+    fn settings_for(&self, hostname: &str, cmdline: (&Path, &[String])) -> Settings {
+        let mut settings = self.settings_on(hostname);
+        for (name, value) in self.command_params(cmdline) {
+            settings.apply(name.clone(), value.clone());
+        }
+
+        settings
+    }
+
+    /// The `runas_default` on `hostname`, like [Sudoers::settings_on] would give it.
+    fn runas_default_on(&self, hostname: &str) -> &str {
+        self.host_params(hostname)
+            .filter(|(name, _)| name == "runas_default")
+            .filter_map(|(_, value)| match value {
+                ConfigValue::Text(text) => Some(text.as_deref().unwrap_or("root")),
+                _ => None,
+            })
+            .last()
+            .unwrap_or_else(|| self.settings.runas_default())
+    }
+
     /// returns `User_Spec`s that match `invoking_user` and `hostname`
     ///
     /// it also distributes `Tag_Spec`s across the `Cmnd_Spec` list of each `User_Spec`
@@ -147,7 +377,7 @@ impl Sudoers {
            + 'c {
         let Self { rules, aliases, .. } = self;
         let user_aliases = get_aliases(&aliases.user, &match_user(invoking_user));
-        let host_aliases = get_aliases(&aliases.host, &match_token(hostname));
+        let host_aliases = get_aliases(&aliases.host, &match_host(hostname));
 
         rules
             .iter()
@@ -157,11 +387,17 @@ impl Sudoers {
             })
             .flatten()
             .filter_map(move |(hosts, runas_cmds)| {
-                find_item(hosts, &match_token(hostname), &host_aliases)?;
+                find_item(hosts, &match_host(hostname), &host_aliases)?;
                 Some(distribute_tags(runas_cmds))
             })
     }
 
+    /// The settings after applying the `Defaults` directives.
+    #[cfg(feature = "policy-api")]
+    pub(crate) fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
     /// returns `User_Spec`s that match `invoking_user` and `hostname` in a print-able format
     pub fn matching_entries<'a, User: UnixUser + PartialEq<User>>(
         &'a self,
@@ -174,33 +410,17 @@ impl Sudoers {
 
         let cmnd_aliases = unfold_alias_table(&self.aliases.cmnd);
         let mut entries = vec![];
+        let runas_default = self.runas_default_on(hostname);
         for cmd_specs in user_specs {
-            group_cmd_specs_per_runas(cmd_specs, &mut entries, &cmnd_aliases);
+            group_cmd_specs_per_runas(cmd_specs, &mut entries, &cmnd_aliases, runas_default);
         }
 
         entries
     }
 
+    /// The editor that visudo and sudoedit should use, see [Settings::editor].
     pub(crate) fn solve_editor_path(&self) -> Option<PathBuf> {
-        if self.settings.flags.contains("env_editor") {
-            for key in ["SUDO_EDITOR", "VISUAL", "EDITOR"] {
-                if let Some(var) = std::env::var_os(key) {
-                    let path = Path::new(&var);
-                    if can_execute(path) {
-                        return Some(path.to_owned());
-                    }
-                    let path = resolve_path(
-                        path,
-                        &std::env::var("PATH").unwrap_or(env!("DEFAULT_PATH").to_string()),
-                    );
-                    if let Some(path) = path {
-                        return Some(path);
-                    }
-                }
-            }
-        }
-
-        None
+        self.settings.editor()
     }
 }
 
@@ -208,6 +428,7 @@ fn group_cmd_specs_per_runas<'a>(
     cmnd_specs: impl Iterator<Item = (Option<&'a RunAs>, (Tag, &'a Spec<Command>))>,
     entries: &mut Vec<Entry<'a>>,
     cmnd_aliases: &HashMap<&String, &'a Vec<Spec<Command>>>,
+    runas_default: &'a str,
 ) {
     static EMPTY_RUNAS: RunAs = RunAs {
         users: Vec::new(),
@@ -222,6 +443,7 @@ fn group_cmd_specs_per_runas<'a>(
             if !collected_specs.is_empty() {
                 entries.push(Entry::new(
                     runas.take().unwrap_or(&EMPTY_RUNAS),
+                    runas_default,
                     mem::take(&mut collected_specs),
                 ));
             }
@@ -249,28 +471,68 @@ fn group_cmd_specs_per_runas<'a>(
     }
 
     if !collected_specs.is_empty() {
-        entries.push(Entry::new(runas.unwrap_or(&EMPTY_RUNAS), collected_specs));
+        entries.push(Entry::new(
+            runas.unwrap_or(&EMPTY_RUNAS),
+            runas_default,
+            collected_specs,
+        ));
     }
 }
 
-fn read_sudoers<R: io::Read>(mut reader: R) -> io::Result<Vec<basic_parser::Parsed<Sudo>>> {
-    // it's a bit frustrating that BufReader.chars() does not exist
-    let mut buffer = String::new();
-    reader.read_to_string(&mut buffer)?;
+/// The lines of a sudoers file, which are parsed while the file is read.
+type SudoersLines<R> = basic_parser::Lines<Sudo, ReaderStream<BufReader<R>>>;
+
+fn read_sudoers<R: io::Read>(reader: R) -> SudoersLines<R> {
+    basic_parser::Lines::new(ReaderStream::new(BufReader::new(reader), MAX_FILE_SIZE))
+}
+
+/// Open a sudoers file, along with the [Stamp] that it has before it is read.
+fn open_sudoers(path: &Path) -> io::Result<(SudoersLines<File>, Stamp)> {
+    let source = crate::system::secure_open_no_symlinks(path, false)?;
+    let metadata = source.metadata()?;
+    check_size(path, &metadata)?;
+    Ok((read_sudoers(source), Stamp::of(&metadata)))
+}
 
-    use basic_parser::parse_lines;
-    use char_stream::*;
-    Ok(parse_lines(&mut PeekableWithPos::new(buffer.chars())))
+fn open_subsudoers(path: &Path) -> io::Result<(SudoersLines<File>, Stamp)> {
+    let source = crate::system::secure_open_no_symlinks(path, true)?;
+    let metadata = source.metadata()?;
+    check_size(path, &metadata)?;
+    Ok((read_sudoers(source), Stamp::of(&metadata)))
 }
 
-fn open_sudoers(path: &Path) -> io::Result<Vec<basic_parser::Parsed<Sudo>>> {
-    let source = crate::system::secure_open(path, false)?;
-    read_sudoers(source)
+/// Refuse files that are too large before reading them; the size of other kinds of files is
+/// limited while they are read.
+fn check_size(path: &Path, metadata: &Metadata) -> io::Result<()> {
+    if metadata.len() > MAX_FILE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{}: file is larger than {MAX_FILE_SIZE} bytes",
+                path.display()
+            ),
+        ));
+    }
+
+    Ok(())
 }
 
-fn open_subsudoers(path: &Path) -> io::Result<Vec<basic_parser::Parsed<Sudo>>> {
-    let source = crate::system::secure_open(path, true)?;
-    read_sudoers(source)
+/// The files in an `@includedir` directory that are read, in the order they are read in.
+fn includedir_files(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut safe_files = std::fs::read_dir(path)?
+        .filter_map(|direntry| {
+            let path = direntry.ok()?.path();
+            let text = path.file_name()?.to_str()?;
+            if text.ends_with('~') || text.contains('.') {
+                None
+            } else {
+                Some(path)
+            }
+        })
+        .collect::<Vec<_>>();
+    safe_files.sort();
+
+    Ok(safe_files)
 }
 
 #[derive(Default)]
@@ -303,33 +565,58 @@ fn check_permission<User: UnixUser + PartialEq<User>, Group: UnixGroup>(
     request: Request<User, Group>,
 ) -> Option<Tag> {
     let cmdline = (request.command, request.arguments);
-
-    let aliases = &sudoers.aliases;
-    let cmnd_aliases = get_aliases(&aliases.cmnd, &match_command(cmdline));
-    let runas_user_aliases = get_aliases(&aliases.runas, &match_user(request.user));
-    let runas_group_aliases = get_aliases(&aliases.runas, &match_group_alias(request.group));
+    let cmnd_aliases = get_aliases(&sudoers.aliases.cmnd, &match_command(cmdline));
+    let runas_matches = match_runas(sudoers, am_user, on_host, &request);
 
     // NOTE to ensure `sudo $command` and `sudo --list` behave the same, both this function and
     // `Sudoers::matching_entries` must call this `matching_user_specs` method
     let matching_user_specs = sudoers.matching_user_specs(am_user, on_host).flatten();
 
-    let allowed_commands = matching_user_specs.filter_map(|(runas, cmdspec)| {
+    let allowed_commands = matching_user_specs
+        .filter(|(runas, _)| runas_matches(*runas))
+        .map(|(_, cmdspec)| imply_tags(cmdspec));
+
+    find_item(allowed_commands, &match_command(cmdline), &cmnd_aliases)
+}
+
+/// Returns whether the commands of a rule with a runas specification may be run by `am_user` as
+/// the user and group of `request`.
+fn match_runas<'a, User: UnixUser + PartialEq<User>, Group: UnixGroup>(
+    sudoers: &'a Sudoers,
+    am_user: &'a User,
+    on_host: &str,
+    request: &'a Request<User, Group>,
+) -> impl Fn(Option<&RunAs>) -> bool + 'a {
+    let aliases = &sudoers.aliases;
+    let runas_user_aliases = get_aliases(&aliases.runas, &match_user(request.user));
+    let runas_group_aliases = get_aliases(&aliases.runas, &match_group_alias(request.group));
+    let runas_default = runas_identifier(sudoers.runas_default_on(on_host));
+
+    move |runas| {
+        let stays_in_group = in_group(request.user, request.group);
         if let Some(RunAs { users, groups }) = runas {
-            let stays_in_group = in_group(request.user, request.group);
-            if request.user != am_user || (stays_in_group && !users.is_empty()) {
-                find_item(users, &match_user(request.user), &runas_user_aliases)?
-            }
-            if !stays_in_group {
-                find_item(groups, &match_group(request.group), &runas_group_aliases)?
+            if (request.user != am_user || (stays_in_group && !users.is_empty()))
+                && find_item(users, &match_user(request.user), &runas_user_aliases).is_none()
+            {
+                return false;
             }
-        } else if !(request.user.is_root() && in_group(request.user, request.group)) {
-            None?;
+
+            stays_in_group
+                || find_item(groups, &match_group(request.group), &runas_group_aliases).is_some()
+        } else {
+            // without a runas specification, commands may only be run as the runas_default user
+            match_identifier(request.user, &runas_default) && stays_in_group
         }
+    }
+}
 
-        Some(cmdspec)
-    });
+/// The command ALL implies SETENV, unless the rule says otherwise.
+fn imply_tags((mut tag, spec): (Tag, &Spec<Command>)) -> (Tag, &Spec<Command>) {
+    if matches!(spec, Qualified::Allow(Meta::All)) {
+        tag.setenv.get_or_insert(true);
+    }
 
-    find_item(allowed_commands, &match_command(cmdline), &cmnd_aliases)
+    (tag, spec)
 }
 
 /// Process a raw parsed AST bit of RunAs + Command specifications:
@@ -343,8 +630,8 @@ fn distribute_tags(
         (None, Default::default()),
         |(mut last_runas, tag), (runas, CommandSpec(mods, cmd))| {
             last_runas = runas.as_ref().or(last_runas);
-            for f in mods {
-                f(tag);
+            for modifier in mods {
+                modifier.apply(tag);
             }
 
             Some((last_runas, (tag.clone(), cmd)))
@@ -366,6 +653,22 @@ fn find_item<'a, Predicate, Iter, T: 'a>(
     matches: &Predicate,
     aliases: &FoundAliases,
 ) -> Option<<Iter::Item as WithInfo>::Info>
+where
+    Predicate: Fn(&T) -> bool,
+    Iter: IntoIterator,
+    Iter::Item: WithInfo<Item = &'a Spec<T>>,
+{
+    let (allowed, info) = find_match(items, matches, aliases)?;
+    allowed.then_some(info)
+}
+
+/// Like [find_item], but returns the last item that matches whether it allows or forbids, along
+/// with which of the two it does.
+fn find_match<'a, Predicate, Iter, T: 'a>(
+    items: Iter,
+    matches: &Predicate,
+    aliases: &FoundAliases,
+) -> Option<(bool, <Iter::Item as WithInfo>::Info)>
 where
     Predicate: Fn(&T) -> bool,
     Iter: IntoIterator,
@@ -377,23 +680,18 @@ where
             Qualified::Forbid(x) => (false, x),
             Qualified::Allow(x) => (true, x),
         };
-        let info = || item.to_info();
         match who {
-            Meta::All => result = judgement.then(info),
-            Meta::Only(ident) if matches(ident) => result = judgement.then(info),
+            Meta::All => result = Some((judgement, item)),
+            Meta::Only(ident) if matches(ident) => result = Some((judgement, item)),
             Meta::Alias(id) if aliases.contains_key(id) => {
-                result = if aliases[id] {
-                    judgement.then(info)
-                } else {
-                    // in this case, an explicit negation in the alias applies
-                    (!judgement).then(info)
-                }
+                // if the alias excludes the item, an explicit negation in it applies
+                result = Some((judgement == aliases[id], item))
             }
             _ => {}
         };
     }
 
-    result
+    result.map(|(judgement, item)| (judgement, item.to_info()))
 }
 
 /// A interface to access optional "satellite data"
@@ -460,10 +758,11 @@ fn match_group_alias(group: &impl UnixGroup) -> impl Fn(&UserSpecifier) -> bool
     }
 }
 
-fn match_token<T: basic_parser::Token + std::ops::Deref<Target = String>>(
-    text: &str,
-) -> (impl Fn(&T) -> bool + '_) {
-    move |token| token.as_str() == text
+/// A host name matches `hostname` as it is, or if it has no domain, the first part of a fully
+/// qualified `hostname`, like the "short host name" of ogsudo.
+fn match_host(hostname: &str) -> impl Fn(&Hostname) -> bool + '_ {
+    let short = hostname.split('.').next().unwrap_or(hostname);
+    move |token| token.as_str() == hostname || (!token.contains('.') && token.as_str() == short)
 }
 
 fn match_command<'a>((cmd, args): (&'a Path, &'a [String])) -> (impl Fn(&Command) -> bool + 'a) {
@@ -472,7 +771,7 @@ fn match_command<'a>((cmd, args): (&'a Path, &'a [String])) -> (impl Fn(&Command
         ..glob::MatchOptions::new()
     };
     move |(cmdpat, argpat)| {
-        cmdpat.matches_path_with(cmd, opts)
+        cmdpat.pattern.matches_path_with(cmd, opts)
             && argpat.as_ref().map_or(true, |vec| args == vec.as_ref())
     }
 }
@@ -505,6 +804,14 @@ where
     set
 }
 
+/// A user as given by a setting such as `runas_default`, which may be a name or a `#uid`
+fn runas_identifier(user: &str) -> Identifier {
+    match user.strip_prefix('#').and_then(|uid| uid.parse().ok()) {
+        Some(uid) => Identifier::ID(uid),
+        None => Identifier::Name(user.to_string()),
+    }
+}
+
 /// Code to map an ast::Identifier to the UnixUser trait
 
 fn match_identifier(user: &impl UnixUser, ident: &ast::Identifier) -> bool {
@@ -523,6 +830,57 @@ pub struct Settings {
     pub list: HashMap<String, HashSet<String>>,
 }
 
+impl Settings {
+    /// The user that commands are run as if no target user is specified
+    pub(crate) fn runas_default(&self) -> &str {
+        self.str_value["runas_default"].as_deref().unwrap_or("root")
+    }
+
+    /// Apply a setting of a `Defaults` line.
+    fn apply(&mut self, name: String, value: ConfigValue) {
+        use ConfigValue::*;
+        match value {
+            Flag(value) => {
+                if value {
+                    self.flags.insert(name);
+                } else {
+                    self.flags.remove(&name);
+                }
+            }
+            List(mode, values) => {
+                let slot: &mut _ = self.list.entry(name).or_default();
+                match mode {
+                    Mode::Set => *slot = values.into_iter().collect(),
+                    Mode::Add => slot.extend(values),
+                    Mode::Del => {
+                        for key in values {
+                            slot.remove(&key);
+                        }
+                    }
+                }
+            }
+            Text(value) => {
+                self.str_value.insert(name, value);
+            }
+            Enum(value) => {
+                self.enum_value.insert(name, value);
+            }
+            Num(value) => {
+                self.int_value.insert(name, value);
+            }
+        }
+    }
+
+    /// The editor from the `editor` list, or that of the user if `env_editor` allows it
+    pub(crate) fn editor(&self) -> Option<PathBuf> {
+        resolve_editor(
+            self.str_value["editor"].as_deref().unwrap_or_default(),
+            self.flags.contains("env_editor"),
+            |key| std::env::var_os(key),
+        )
+    }
+}
+
 impl Default for Settings {
     fn default() -> Self {
         let mut this = Settings {
@@ -564,12 +922,30 @@ impl Default for Settings {
     }
 }
 
-/// Process a sudoers-parsing file into a workable AST
+/// Like [analyze], but also record the sudoers file itself as a source of the rules.
+fn analyze_file<R: io::Read>(
+    path: &Path,
+    mut sudoers: SudoersLines<R>,
+    stamp: Stamp,
+) -> (Sudoers, Vec<Error>) {
+    let (mut result, errors) = analyze(path, sudoers.numbered());
+    result.sources.insert(
+        0,
+        Source {
+            path: path.to_path_buf(),
+            kind: SourceKind::Sudoers,
+            stamp,
+        },
+    );
+
+    (result, errors)
+}
+
+/// Process a sudoers-parsing file, of which the lines are numbered, into a workable AST
 fn analyze(
     path: &Path,
-    sudoers: impl IntoIterator<Item = basic_parser::Parsed<Sudo>>,
+    sudoers: impl IntoIterator<Item = (usize, basic_parser::Parsed<Sudo>)>,
 ) -> (Sudoers, Vec<Error>) {
-    use ConfigValue::*;
     use Directive::*;
 
     let mut result: Sudoers = Default::default();
@@ -586,20 +962,44 @@ fn analyze(
         }
     }
 
+    /// The files that are being read, to detect include loops and limit how many files are read.
+    struct Includes {
+        open: Vec<PathBuf>,
+        total: usize,
+    }
+
     impl Sudoers {
-        fn include(&mut self, path: &Path, diagnostics: &mut Vec<Error>, count: &mut u8) {
-            if *count >= INCLUDE_LIMIT {
-                diagnostics.push(Error(
-                    None,
-                    format!("include file limit reached opening '{}'", path.display()),
-                ))
+        fn include(&mut self, path: &Path, diagnostics: &mut Vec<Error>, includes: &mut Includes) {
+            if includes.open.iter().any(|open| open == path) {
+                diagnostics.push(Error::general(format!(
+                    "include loop: '{}' includes itself",
+                    path.display()
+                )))
+            } else if includes.open.len() > INCLUDE_LIMIT {
+                diagnostics.push(Error::general(format!(
+                    "includes are nested more than {INCLUDE_LIMIT} levels deep opening '{}'",
+                    path.display()
+                )))
+            } else if includes.total >= INCLUDE_FILES_LIMIT {
+                diagnostics.push(Error::general(format!(
+                    "more than {INCLUDE_FILES_LIMIT} files are included opening '{}'",
+                    path.display()
+                )))
             // FIXME: this will cause an error in `visudo` if we open a non-privileged sudoers file
             // that includes another non-privileged sudoer files.
             } else {
                 match open_subsudoers(path) {
-                    Ok(subsudoer) => {
-                        *count += 1;
-                        self.process(path, subsudoer, diagnostics, count)
+                    Ok((mut subsudoer, stamp)) => {
+                        includes.total += 1;
+                        includes.open.push(path.to_path_buf());
+                        self.process(path, subsudoer.numbered(), diagnostics, includes);
+                        includes.open.pop();
+
+                        self.sources.push(Source {
+                            path: path.to_path_buf(),
+                            kind: SourceKind::Include,
+                            stamp,
+                        });
                     }
                     Err(e) => {
                         let message = if e.kind() == io::ErrorKind::NotFound {
@@ -609,7 +1009,7 @@ fn analyze(
                             e.to_string()
                         };
 
-                        diagnostics.push(Error(None, message))
+                        diagnostics.push(Error::general(message))
                     }
                 }
             }
@@ -618,16 +1018,23 @@ fn analyze(
         fn process(
             &mut self,
             cur_path: &Path,
-            sudoers: impl IntoIterator<Item = basic_parser::Parsed<Sudo>>,
+            sudoers: impl IntoIterator<Item = (usize, basic_parser::Parsed<Sudo>)>,
             diagnostics: &mut Vec<Error>,
-            safety_count: &mut u8,
+            includes: &mut Includes,
         ) {
-            for item in sudoers {
+            for (line_number, item) in sudoers {
+                let origin = || Origin {
+                    path: cur_path.to_path_buf(),
+                    line: line_number,
+                };
                 match item {
                     Ok(line) => match line {
                         Sudo::LineComment => {}
 
-                        Sudo::Spec(permission) => self.rules.push(permission),
+                        Sudo::Spec(permission) => {
+                            self.rules.push(permission);
+                            self.rule_origins.push(origin());
+                        }
 
                         Sudo::Decl(UserAlias(mut def)) => self.aliases.user.1.append(&mut def),
                         Sudo::Decl(HostAlias(mut def)) => self.aliases.host.1.append(&mut def),
@@ -635,95 +1042,83 @@ fn analyze(
                         Sudo::Decl(RunasAlias(mut def)) => self.aliases.runas.1.append(&mut def),
 
                         Sudo::Decl(Defaults(params)) => {
-                            for (name, value) in params {
-                                self.set_default(name, value)
+                            for (name, value) in params.iter().cloned() {
+                                self.settings.apply(name, value)
                             }
+                            self.defaults.push(DefaultsLine {
+                                origin: origin(),
+                                params,
+                            })
+                        }
+
+                        Sudo::Decl(HostDefaults(hosts, params)) => {
+                            self.host_defaults.push(HostSettings {
+                                origin: origin(),
+                                hosts,
+                                params,
+                            })
                         }
 
-                        Sudo::Include(path) => self.include(
-                            &resolve_relative(cur_path, path),
-                            diagnostics,
-                            safety_count,
-                        ),
+                        Sudo::Decl(CmndDefaults(commands, params)) => {
+                            self.cmnd_defaults.push(CommandSettings {
+                                origin: origin(),
+                                commands,
+                                params,
+                            })
+                        }
+
+                        Sudo::Include(path) => {
+                            self.include(&resolve_relative(cur_path, path), diagnostics, includes)
+                        }
 
                         Sudo::IncludeDir(path) => {
                             if path.contains("%h") {
-                                diagnostics.push(Error(
-                                    None,
-                                    format!("cannot open sudoers file {path}: percent escape %h in includedir is unsupported")));
+                                diagnostics.push(Error::general(format!(
+                                    "cannot open sudoers file {path}: percent escape %h in includedir is unsupported"
+                                )));
                                 continue;
                             }
 
                             let path = resolve_relative(cur_path, path);
-                            let Ok(files) = std::fs::read_dir(&path) else {
-                                diagnostics.push(Error(
-                                    None,
-                                    format!("cannot open sudoers file {}", path.display()),
-                                ));
+                            // the directory is looked at before it is read, like a file
+                            let listing = fs::metadata(&path).and_then(|metadata| {
+                                Ok((Stamp::of(&metadata), includedir_files(&path)?))
+                            });
+                            let Ok((stamp, safe_files)) = listing else {
+                                diagnostics.push(Error::general(format!(
+                                    "cannot open sudoers file {}",
+                                    path.display()
+                                )));
                                 continue;
                             };
-                            let mut safe_files = files
-                                .filter_map(|direntry| {
-                                    let path = direntry.ok()?.path();
-                                    let text = path.file_name()?.to_str()?;
-                                    if text.ends_with('~') || text.contains('.') {
-                                        None
-                                    } else {
-                                        Some(path)
-                                    }
-                                })
-                                .collect::<Vec<_>>();
-                            safe_files.sort();
+                            self.sources.push(Source {
+                                path,
+                                stamp,
+                                kind: SourceKind::Directory,
+                            });
                             for file in safe_files {
-                                self.include(file.as_ref(), diagnostics, safety_count)
+                                self.include(file.as_ref(), diagnostics, includes)
                             }
                         }
                     },
 
-                    Err(basic_parser::Status::Fatal(pos, error)) => {
-                        diagnostics.push(Error(Some(pos), error))
-                    }
+                    Err(basic_parser::Status::Fatal(pos, error)) => diagnostics.push(Error {
+                        source: Some(cur_path.to_path_buf()),
+                        location: Some(pos),
+                        message: error,
+                    }),
                     Err(_) => panic!("internal parser error"),
                 }
             }
         }
-
-        fn set_default(&mut self, name: String, value: ConfigValue) {
-            match value {
-                Flag(value) => {
-                    if value {
-                        self.settings.flags.insert(name);
-                    } else {
-                        self.settings.flags.remove(&name);
-                    }
-                }
-                List(mode, values) => {
-                    let slot: &mut _ = self.settings.list.entry(name).or_default();
-                    match mode {
-                        Mode::Set => *slot = values.into_iter().collect(),
-                        Mode::Add => slot.extend(values),
-                        Mode::Del => {
-                            for key in values {
-                                slot.remove(&key);
-                            }
-                        }
-                    }
-                }
-                Text(value) => {
-                    self.settings.str_value.insert(name, value);
-                }
-                Enum(value) => {
-                    self.settings.enum_value.insert(name, value);
-                }
-                Num(value) => {
-                    self.settings.int_value.insert(name, value);
-                }
-            }
-        }
     }
 
     let mut diagnostics = vec![];
-    result.process(path, sudoers, &mut diagnostics, &mut 0);
+    let mut includes = Includes {
+        open: vec![path.to_path_buf()],
+        total: 0,
+    };
+    result.process(path, sudoers, &mut diagnostics, &mut includes);
 
     let alias = &mut result.aliases;
     alias.user.0 = sanitize_alias_table(&alias.user.1, &mut diagnostics);
@@ -756,7 +1151,7 @@ fn sanitize_alias_table<T>(table: &Vec<Def<T>>, diagnostics: &mut Vec<Error>) ->
 
     impl<T> Visitor<'_, T> {
         fn complain(&mut self, text: String) {
-            self.diagnostics.push(Error(None, text))
+            self.diagnostics.push(Error::general(text))
         }
 
         fn visit(&mut self, pos: usize) {
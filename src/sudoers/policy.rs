@@ -1,14 +1,18 @@
 use super::Sudoers;
 
 use super::Judgement;
-use crate::system::time::Duration;
+use crate::exec::OutputQuota;
+use crate::iolog::{IoLogOptions, MAX_SEQ};
+use crate::log::{SyslogProtocol, SyslogServer};
+use crate::plugin::ExternalApproval;
+use crate::system::{capabilities::CapabilitySet, time::Duration, User};
 /// Data types and traits that represent what the "terms and conditions" are after a succesful
 /// permission check.
 ///
 /// The trait definitions can be part of some global crate in the future, if we support more
 /// than just the sudoers file.
 use std::collections::HashSet;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub trait Policy {
     fn authorization(&self) -> Authorization {
@@ -19,12 +23,171 @@ pub trait Policy {
         DirChange::Strict(None)
     }
 
+    fn capabilities(&self) -> Option<CapabilitySet> {
+        None
+    }
+
+    fn sandbox(&self) -> Option<&str> {
+        None
+    }
+
+    /// The cgroup below which the command gets a cgroup of its own.
+    fn cgroup(&self) -> Option<&str> {
+        None
+    }
+
+    /// Whether what is typed into the command should be logged (`LOG_INPUT`).
+    fn log_input(&self) -> bool {
+        false
+    }
+
+    /// Whether the output of the command should be logged (`LOG_OUTPUT`).
+    fn log_output(&self) -> bool {
+        false
+    }
+
+    /// Whether mail should be sent when the command is run (`MAIL`).
+    fn mail(&self) -> bool {
+        false
+    }
+
+    /// Where and how the input and output of the command are logged, if they are.
+    fn iolog(&self) -> Option<IoLogOptions> {
+        None
+    }
+
+    /// The file or FIFO that gets a copy of the output of the command.
+    fn tee_output(&self) -> Option<&str> {
+        None
+    }
+
+    /// How much of the output of the command is logged (`output_quota`).
+    fn output_quota(&self) -> Option<OutputQuota> {
+        None
+    }
+
+    /// The names of the signals that are forwarded to the command, if not all of them are.
+    fn forward_signals(&self) -> Option<&HashSet<String>> {
+        None
+    }
+
+    /// Whether the exit status and resource usage of the command are logged once it exited.
+    fn log_exit_status(&self) -> bool {
+        false
+    }
+
+    /// Whether the command is logged when it is run.
+    fn log_command(&self) -> bool {
+        true
+    }
+
+    /// Whether sudoedit may follow symbolic links (`FOLLOW`).
+    fn sudoedit_follow(&self) -> bool {
+        false
+    }
+
+    /// Whether the command may not run other programs (`NOEXEC`).
+    fn noexec(&self) -> bool {
+        false
+    }
+
+    /// Whether the programs that the command runs have to be allowed as well (`INTERCEPT`).
+    fn intercept(&self) -> bool {
+        false
+    }
+
+    /// Whether the command is executed through a file descriptor, so that the file that is run is
+    /// the one that was opened and checked (`fdexec`).
+    fn fdexec(&self) -> bool {
+        false
+    }
+
+    /// What to do when sudo is run by a command that another sudo runs (`nested_sudo`).
+    fn nested_sudo(&self) -> NestedSudo {
+        NestedSudo::Ignore
+    }
+
+    /// The variables that are set for the command (`ENV="NAME=value"`), whatever the other
+    /// environment settings say.
+    fn env_assignments(&self) -> &[(String, String)] {
+        &[]
+    }
+
+    /// Whether the invoking user may keep their whole environment with `-E`, and set any
+    /// variable (`SETENV`).
+    fn setenv(&self) -> bool {
+        false
+    }
+
+    /// Whether the environment of the command only has the variables of the invoking user that
+    /// are in env_keep or env_check; otherwise, it has all of them except those in env_delete.
+    fn env_reset(&self) -> bool {
+        true
+    }
+
+    /// The group that may write to the pty of the command.
+    fn pty_group(&self) -> Option<&str> {
+        Some("tty")
+    }
+
     fn env_keep(&self) -> &HashSet<String>;
     fn env_check(&self) -> &HashSet<String>;
+    fn env_delete(&self) -> &HashSet<String>;
 
     fn secure_path(&self) -> Option<String>;
 
     fn use_pty(&self) -> bool;
+
+    fn tty_signals(&self) -> bool;
+
+    /// Whether the user's terminal stops and starts output for Ctrl-S and Ctrl-Q while it is in
+    /// raw mode, instead of passing these keys to the pty (`tty_flow_control`).
+    fn tty_flow_control(&self) -> bool {
+        false
+    }
+
+    /// Whether to open a PAM session for the command, which has to be closed after it exited.
+    fn pam_session(&self) -> bool;
+
+    /// How long to keep relaying the output of the pty after the command exited, while other
+    /// processes still have it open.
+    fn pty_wait_for_eof(&self) -> std::time::Duration;
+
+    /// For how long a message that is the same as the previous message in the system log is
+    /// only counted, instead of logged again.
+    fn syslog_dedup_window(&self) -> std::time::Duration {
+        std::time::Duration::ZERO
+    }
+
+    /// The syslog collector that messages for the system log are sent to instead of the local
+    /// system log (`syslog_server`).
+    fn syslog_server(&self) -> Option<SyslogServer> {
+        None
+    }
+
+    /// How often the invoking user has to authenticate again while the command runs in a pty
+    /// (`session_reauth`).
+    fn session_reauth(&self) -> Option<std::time::Duration> {
+        None
+    }
+
+    /// How long the command may run in a pty without any input or output before it is hung up on
+    /// (`idle_timeout`).
+    fn idle_timeout(&self) -> Option<std::time::Duration> {
+        None
+    }
+
+    /// The socket or program that has to approve the command before it is run (`approval`).
+    fn approval(&self) -> Option<ExternalApproval> {
+        None
+    }
+
+    fn runas_check_shell(&self) -> bool;
+
+    fn stay_setuid(&self) -> bool;
+
+    fn set_home(&self) -> bool;
+    fn always_set_home(&self) -> bool;
 }
 
 #[must_use]
@@ -39,6 +202,7 @@ pub struct AuthorizationAllowed {
     pub must_authenticate: bool,
     pub allowed_attempts: u16,
     pub prior_validity: Duration,
+    pub insults: bool,
 }
 
 #[must_use]
@@ -48,6 +212,16 @@ pub enum DirChange<'a> {
     Any,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NestedSudo {
+    Ignore,
+    /// Record in the log how many invocations of sudo this one runs under.
+    Log,
+    /// Also tell the user.
+    Warn,
+    Deny,
+}
+
 impl Policy for Judgement {
     fn authorization(&self) -> Authorization {
         if let Some(tag) = &self.flags {
@@ -57,6 +231,7 @@ impl Policy for Judgement {
                 must_authenticate: tag.needs_passwd(),
                 allowed_attempts,
                 prior_validity: Duration::seconds(valid_seconds),
+                insults: self.settings.flags.contains("insults"),
             })
         } else {
             Authorization::Forbidden
@@ -71,6 +246,14 @@ impl Policy for Judgement {
         &self.settings.list["env_check"]
     }
 
+    fn env_delete(&self) -> &HashSet<String> {
+        &self.settings.list["env_delete"]
+    }
+
+    fn env_reset(&self) -> bool {
+        self.settings.flags.contains("env_reset")
+    }
+
     fn chdir(&self) -> DirChange {
         match self.flags.as_ref().expect("not authorized").cwd.as_ref() {
             None => DirChange::Strict(None),
@@ -79,20 +262,230 @@ impl Policy for Judgement {
         }
     }
 
+    fn capabilities(&self) -> Option<CapabilitySet> {
+        self.flags.as_ref().expect("not authorized").caps
+    }
+
+    fn sandbox(&self) -> Option<&str> {
+        self.flags
+            .as_ref()
+            .expect("not authorized")
+            .sandbox
+            .as_deref()
+    }
+
+    fn log_input(&self) -> bool {
+        self.tag_or_default(|tag| tag.log_input, "log_input")
+    }
+
+    fn log_output(&self) -> bool {
+        self.tag_or_default(|tag| tag.log_output, "log_output")
+    }
+
+    fn mail(&self) -> bool {
+        self.tag_or_default(|tag| tag.mail, "mail_all_cmnds")
+    }
+
+    fn sudoedit_follow(&self) -> bool {
+        self.tag_or_default(|tag| tag.follow, "sudoedit_follow")
+    }
+
+    fn noexec(&self) -> bool {
+        self.tag_or_default(|tag| tag.noexec, "noexec")
+    }
+
+    fn intercept(&self) -> bool {
+        self.tag_or_default(|tag| tag.intercept, "intercept")
+    }
+
+    fn fdexec(&self) -> bool {
+        // digests of commands are not supported, so `digest_only` never applies
+        self.settings.enum_value["fdexec"].get() == "always"
+    }
+
+    fn nested_sudo(&self) -> NestedSudo {
+        match self.settings.enum_value["nested_sudo"].get() {
+            "log" => NestedSudo::Log,
+            "warn" => NestedSudo::Warn,
+            "deny" => NestedSudo::Deny,
+            _ => NestedSudo::Ignore,
+        }
+    }
+
+    fn env_assignments(&self) -> &[(String, String)] {
+        self.flags.as_ref().map_or(&[], |tag| &tag.env)
+    }
+
+    fn setenv(&self) -> bool {
+        self.tag_or_default(|tag| tag.setenv, "setenv")
+    }
+
+    fn iolog(&self) -> Option<IoLogOptions> {
+        let (input, output) = (self.log_input(), self.log_output());
+        if !input && !output {
+            return None;
+        }
+
+        Some(IoLogOptions {
+            dir: self.settings.str_value["iolog_dir"].as_deref()?.into(),
+            maxseq: self.settings.int_value["maxseq"]
+                .try_into()
+                .unwrap_or(MAX_SEQ),
+            compress: self.settings.flags.contains("compress_io"),
+            flush: self.settings.flags.contains("iolog_flush"),
+            input,
+            output,
+            user: self.settings.str_value["iolog_user"]
+                .as_deref()
+                .unwrap_or("root")
+                .to_string(),
+            group: self.settings.str_value["iolog_group"]
+                .as_deref()
+                .map(str::to_string),
+            mode: self.settings.int_value["iolog_mode"]
+                .try_into()
+                .unwrap_or(0o600),
+            recipient: self.settings.str_value["iolog_recipient"]
+                .as_deref()
+                .map(str::to_string),
+        })
+    }
+
     fn secure_path(&self) -> Option<String> {
         self.settings.str_value["secure_path"]
             .as_ref()
             .map(|s| s.to_string())
     }
 
+    fn cgroup(&self) -> Option<&str> {
+        self.settings.str_value["cgroup"].as_deref()
+    }
+
+    fn pty_group(&self) -> Option<&str> {
+        self.settings.str_value["pty_group"].as_deref()
+    }
+
+    fn tee_output(&self) -> Option<&str> {
+        self.settings.str_value["tee_output"].as_deref()
+    }
+
+    fn log_exit_status(&self) -> bool {
+        self.settings.flags.contains("log_exit_status")
+    }
+
+    fn forward_signals(&self) -> Option<&HashSet<String>> {
+        let names = &self.settings.list["forward_signals"];
+        (!names.contains("ALL")).then_some(names)
+    }
+
     fn use_pty(&self) -> bool {
         self.settings.flags.contains("use_pty")
     }
+
+    fn tty_signals(&self) -> bool {
+        self.settings.flags.contains("tty_signals")
+    }
+
+    fn tty_flow_control(&self) -> bool {
+        self.settings.flags.contains("tty_flow_control")
+    }
+
+    fn pam_session(&self) -> bool {
+        self.settings.flags.contains("pam_session")
+    }
+
+    fn pty_wait_for_eof(&self) -> std::time::Duration {
+        let seconds = self.settings.int_value["pty_wait_for_eof"];
+        std::time::Duration::from_secs(seconds.try_into().unwrap_or_default())
+    }
+
+    fn approval(&self) -> Option<ExternalApproval> {
+        let target = self.settings.str_value["approval"].as_deref()?;
+        let seconds = self.settings.int_value["approval_timeout"];
+        Some(ExternalApproval::new(
+            target.into(),
+            std::time::Duration::from_secs(seconds.try_into().unwrap_or_default()),
+        ))
+    }
+
+    fn session_reauth(&self) -> Option<std::time::Duration> {
+        let seconds = self.settings.int_value["session_reauth"];
+        (seconds > 0).then(|| std::time::Duration::from_secs(seconds as u64))
+    }
+
+    fn idle_timeout(&self) -> Option<std::time::Duration> {
+        let seconds = self.settings.int_value["idle_timeout"];
+        (seconds > 0).then(|| std::time::Duration::from_secs(seconds as u64))
+    }
+
+    fn output_quota(&self) -> Option<OutputQuota> {
+        let bytes = self.settings.int_value["output_quota"];
+        (bytes > 0).then(|| OutputQuota {
+            bytes: bytes as u64,
+            terminate: self.settings.enum_value["output_quota_action"].get() == "terminate",
+        })
+    }
+
+    fn syslog_dedup_window(&self) -> std::time::Duration {
+        let seconds = self.settings.int_value["syslog_dedup_window"];
+        std::time::Duration::from_secs(seconds.try_into().unwrap_or_default())
+    }
+
+    fn syslog_server(&self) -> Option<SyslogServer> {
+        let host = self.settings.str_value["syslog_server"].as_deref()?;
+        Some(SyslogServer {
+            host: host.to_string(),
+            port: self.settings.int_value["syslog_port"].try_into().ok()?,
+            protocol: match self.settings.enum_value["syslog_protocol"].get() {
+                "tcp" => SyslogProtocol::Tcp,
+                "tls" => SyslogProtocol::Tls,
+                _ => SyslogProtocol::Udp,
+            },
+            cabundle: self.settings.str_value["syslog_cabundle"]
+                .as_deref()
+                .map(PathBuf::from),
+        })
+    }
+
+    fn runas_check_shell(&self) -> bool {
+        self.settings.flags.contains("runas_check_shell")
+    }
+
+    fn stay_setuid(&self) -> bool {
+        self.settings.flags.contains("stay_setuid")
+    }
+
+    fn set_home(&self) -> bool {
+        self.settings.flags.contains("set_home")
+    }
+
+    fn always_set_home(&self) -> bool {
+        self.settings.flags.contains("always_set_home")
+    }
+}
+
+impl Judgement {
+    /// A tag of the rule that allowed the command, or the `Defaults` if the rule does not set it.
+    fn tag_or_default(&self, tag: impl Fn(&super::Tag) -> Option<bool>, setting: &str) -> bool {
+        tag(self.flags.as_ref().expect("not authorized"))
+            .unwrap_or_else(|| self.settings.flags.contains(setting))
+    }
 }
 
 pub trait PreJudgementPolicy {
     fn secure_path(&self) -> Option<String>;
-    fn validate_authorization(&self) -> Authorization;
+    fn runas_default(&self) -> String;
+    fn runaspw(&self) -> bool;
+    /// Whether `invoking_user` may refresh their session record on `hostname` with `sudo -v`.
+    fn validate_authorization(&self, invoking_user: &User, hostname: &str) -> Authorization;
+    /// Whether error messages and headings are shown in color (`Defaults color`).
+    fn color(&self) -> bool;
+    /// Whether the policy is checked for the fully qualified name of the host, which is looked up
+    /// in DNS (`Defaults fqdn`).
+    fn fqdn(&self) -> bool;
+    /// Whether commands may be run as a user or group that is only known by its id, with
+    /// `-u #uid` or `-g #gid` (`Defaults runas_allow_unknown_id`).
+    fn runas_allow_unknown_id(&self) -> bool;
 }
 
 impl PreJudgementPolicy for Sudoers {
@@ -102,12 +495,29 @@ impl PreJudgementPolicy for Sudoers {
             .map(|s| s.to_string())
     }
 
-    fn validate_authorization(&self) -> Authorization {
-        Authorization::Allowed(AuthorizationAllowed {
-            must_authenticate: true,
-            allowed_attempts: self.settings.int_value["passwd_tries"].try_into().unwrap(),
-            prior_validity: Duration::seconds(self.settings.int_value["timestamp_timeout"]),
-        })
+    fn runas_default(&self) -> String {
+        self.settings.runas_default().to_string()
+    }
+
+    fn runaspw(&self) -> bool {
+        self.settings.flags.contains("runaspw")
+    }
+
+    fn validate_authorization(&self, invoking_user: &User, hostname: &str) -> Authorization {
+        self.check_validate_permission(invoking_user, hostname)
+            .authorization()
+    }
+
+    fn color(&self) -> bool {
+        self.settings.flags.contains("color")
+    }
+
+    fn fqdn(&self) -> bool {
+        self.settings.flags.contains("fqdn")
+    }
+
+    fn runas_allow_unknown_id(&self) -> bool {
+        self.settings.flags.contains("runas_allow_unknown_id")
     }
 }
 
@@ -139,6 +549,7 @@ mod test {
                 must_authenticate: true,
                 allowed_attempts: 3,
                 prior_validity: Duration::minutes(15),
+                insults: false,
             })
         );
         judge.mod_flag(|tag| tag.authenticate = Authenticate::Nopasswd);
@@ -148,6 +559,7 @@ mod test {
                 must_authenticate: false,
                 allowed_attempts: 3,
                 prior_validity: Duration::minutes(15),
+                insults: false,
             })
         );
     }
@@ -166,4 +578,19 @@ mod test {
         judge.mod_flag(|tag| tag.cwd = Some(ChDir::Path("/bin".into())));
         assert_eq!(judge.chdir(), (DirChange::Strict(Some(Path::new("/bin")))));
     }
+
+    #[test]
+    fn exec_tags_override_defaults() {
+        let mut judge = Judgement {
+            flags: Some(Tag::default()),
+            ..Default::default()
+        };
+        assert!(!judge.noexec() && !judge.intercept());
+        judge.settings.flags.insert("noexec".to_string());
+        judge.settings.flags.insert("intercept".to_string());
+        assert!(judge.noexec() && judge.intercept());
+        judge.mod_flag(|tag| tag.noexec = Some(false));
+        judge.mod_flag(|tag| tag.intercept = Some(false));
+        assert!(!judge.noexec() && !judge.intercept());
+    }
 }
@@ -58,12 +58,14 @@ macro_rules! sudoer {
         parse_lines(&mut [$($e),*, ""].join("\n").chars().peekable())
             .into_iter()
             .map(|x| Ok::<_,basic_parser::Status>(x.unwrap()))
+            .enumerate()
+            .map(|(i, x)| (i + 1, x))
     }
 }
 
 // alternative to parse_eval, but goes through sudoer! directly
 fn parse_line(s: &str) -> Sudo {
-    sudoer![s].next().unwrap().unwrap()
+    sudoer![s].next().unwrap().1.unwrap()
 }
 
 #[test]
@@ -82,19 +84,19 @@ fn permission_test() {
 
     macro_rules! FAIL {
         ([$($sudo:expr),*], $user:expr => $req:expr, $server:expr; $command:expr) => {
-            let (Sudoers { rules,aliases,settings }, _) = analyze(Path::new("/etc/fakesudoers"), sudoer![$($sudo),*]);
+            let (sudoers, _) = analyze(Path::new("/etc/fakesudoers"), sudoer![$($sudo),*]);
             let cmdvec = $command.split_whitespace().map(String::from).collect::<Vec<_>>();
             let req = Request { user: $req.0, group: $req.1, command: &realpath(cmdvec[0].as_ref()), arguments: &cmdvec[1..].to_vec() };
-            assert_eq!(Sudoers { rules, aliases, settings }.check(&Named($user), $server, req).flags, None);
+            assert_eq!(sudoers.check(&Named($user), $server, req).flags, None);
         }
     }
 
     macro_rules! pass {
         ([$($sudo:expr),*], $user:expr => $req:expr, $server:expr; $command:expr $(=> [$($key:ident : $val:expr),*])?) => {
-            let (Sudoers { rules,aliases,settings }, _) = analyze(Path::new("/etc/fakesudoers"), sudoer![$($sudo),*]);
+            let (sudoers, _) = analyze(Path::new("/etc/fakesudoers"), sudoer![$($sudo),*]);
             let cmdvec = $command.split_whitespace().map(String::from).collect::<Vec<_>>();
             let req = Request { user: $req.0, group: $req.1, command: &realpath(cmdvec[0].as_ref()), arguments: &cmdvec[1..].to_vec() };
-            let result = Sudoers { rules, aliases, settings }.check(&Named($user), $server, req).flags;
+            let result = sudoers.check(&Named($user), $server, req).flags;
             assert!(!result.is_none());
             $(
                 let result = result.unwrap();
@@ -125,10 +127,44 @@ fn permission_test() {
     pass!(["user ALL=(ALL:ALL) CWD=/usr/bin NOPASSWD: /bin/foo"], "user" => root(), "server"; "/bin/foo" => [authenticate: Authenticate::Nopasswd, cwd: Some(ChDir::Path("/usr/bin".into()))]);
     //note: original sudo does not allow the below
     pass!(["user ALL=(ALL:ALL) NOPASSWD: CWD=/usr/bin /bin/foo"], "user" => root(), "server"; "/bin/foo" => [authenticate: Authenticate::Nopasswd, cwd: Some(ChDir::Path("/usr/bin".into()))]);
+    pass!(["user ALL=(ALL:ALL) CAPS=cap_net_admin /bin/foo, /bin/bar"], "user" => root(), "server"; "/bin/bar" => [caps: CapabilitySet::from_names("cap_net_admin").ok()]);
+    pass!(["user ALL=(ALL:ALL) CAPS=net_admin,net_raw NOPASSWD: /bin/foo"], "user" => root(), "server"; "/bin/foo" => [authenticate: Authenticate::Nopasswd, caps: CapabilitySet::from_names("cap_net_admin,cap_net_raw").ok()]);
+    pass!(["user ALL=(ALL:ALL) /bin/foo"], "user" => root(), "server"; "/bin/foo" => [caps: None]);
+    SYNTAX!(["user ALL=(ALL:ALL) CAPS=cap_world_domination /bin/foo"]);
+    pass!(["user ALL=(ALL:ALL) SANDBOX=net-admin /bin/foo, /bin/bar"], "user" => root(), "server"; "/bin/bar" => [sandbox: Some("net-admin".to_string())]);
+    pass!(["user ALL=(ALL:ALL) SANDBOX=one SANDBOX=two /bin/foo"], "user" => root(), "server"; "/bin/foo" => [sandbox: Some("two".to_string())]);
+    SYNTAX!(["user ALL=(ALL:ALL) SANDBOX=../etc/shadow /bin/foo"]);
+    pass!(["user ALL=(ALL:ALL) /bin/foo"], "user" => root(), "server"; "/bin/foo" => [mail: None, log_input: None, log_output: None, follow: None]);
+    pass!(["user ALL=(ALL:ALL) LOG_INPUT: NOLOG_OUTPUT: /bin/foo, /bin/bar"], "user" => root(), "server"; "/bin/bar" => [log_input: Some(true), log_output: Some(false)]);
+    pass!(["user ALL=(ALL:ALL) MAIL: NOPASSWD: NOMAIL: /bin/foo"], "user" => root(), "server"; "/bin/foo" => [mail: Some(false), authenticate: Authenticate::Nopasswd]);
+    pass!(["user ALL=(ALL:ALL) FOLLOW: /bin/foo, NOFOLLOW: /bin/bar"], "user" => root(), "server"; "/bin/bar" => [follow: Some(false)]);
+    pass!(["user ALL=(ALL:ALL) SETENV: /bin/foo"], "user" => root(), "server"; "/bin/foo" => [setenv: Some(true)]);
+    pass!(["user ALL=(ALL:ALL) /bin/foo"], "user" => root(), "server"; "/bin/foo" => [setenv: None]);
+    pass!(["user ALL=(ALL:ALL) ALL"], "user" => root(), "server"; "/bin/foo" => [setenv: Some(true)]);
+    pass!(["user ALL=(ALL:ALL) NOSETENV: ALL"], "user" => root(), "server"; "/bin/foo" => [setenv: Some(false)]);
+    pass!(["user ALL=(ALL:ALL) NOEXEC: /bin/foo, EXEC: /bin/bar"], "user" => root(), "server"; "/bin/foo" => [noexec: Some(true)]);
+    pass!(["user ALL=(ALL:ALL) NOEXEC: /bin/foo, EXEC: /bin/bar"], "user" => root(), "server"; "/bin/bar" => [noexec: Some(false)]);
+    pass!(["user ALL=(ALL:ALL) /bin/foo"], "user" => root(), "server"; "/bin/foo" => [noexec: None]);
+    pass!(["Defaults intercept", "user ALL=(ALL:ALL) NOINTERCEPT: /bin/foo, /bin/bar"], "user" => root(), "server"; "/bin/bar" => [intercept: Some(false)]);
+    pass!(["user ALL=(ALL:ALL) INTERCEPT: /bin/foo, NOEXEC: /bin/bar"], "user" => root(), "server"; "/bin/bar" => [intercept: Some(true), noexec: Some(true)]);
+    pass!(["user ALL=(ALL:ALL) ENV=\"http_proxy=http://proxy:3128\" /bin/foo"], "user" => root(), "server"; "/bin/foo" => [env: vec![("http_proxy".to_string(), "http://proxy:3128".to_string())]]);
+    pass!(["user ALL=(ALL:ALL) ENV=\"A=1\" ENV=\"B=x, \\\"y\\\"\" ENV=\"A=2\" /bin/foo"], "user" => root(), "server"; "/bin/foo" => [env: vec![("B".to_string(), "x, \"y\"".to_string()), ("A".to_string(), "2".to_string())]]);
+    pass!(["user ALL=(ALL:ALL) ENV=\"EMPTY=\" /bin/foo, /bin/bar"], "user" => root(), "server"; "/bin/bar" => [env: vec![("EMPTY".to_string(), String::new())]]);
+    SYNTAX!(["user ALL=(ALL:ALL) ENV=FOO=bar /bin/foo"]);
+    SYNTAX!(["user ALL=(ALL:ALL) ENV=\"1FOO=bar\" /bin/foo"]);
+    SYNTAX!(["user ALL=(ALL:ALL) ENV=\"FOO\" /bin/foo"]);
+    SYNTAX!(["user ALL=(ALL:ALL) LOG_INPUT /bin/foo"]);
 
     pass!(["user ALL=/bin/e##o"], "user" => root(), "vm"; "/bin/e");
     SYNTAX!(["ALL ALL=(ALL) /bin/\n/echo"]);
 
+    pass!(["user ALL=/bin/hello"], "user" => root(), "server"; "/bin/hello");
+    FAIL!(["user ALL=/bin/hello"], "user" => request! { operator }, "server"; "/bin/hello");
+    pass!(["Defaults runas_default=operator", "user ALL=/bin/hello"], "user" => request! { operator }, "server"; "/bin/hello");
+    FAIL!(["Defaults runas_default=operator", "user ALL=/bin/hello"], "user" => root(), "server"; "/bin/hello");
+    pass!(["Defaults runas_default=\"#2060\"", "user ALL=/bin/hello"], "user" => request! { operator }, "server"; "/bin/hello");
+    FAIL!(["Defaults runas_default=\"#2060\"", "user ALL=/bin/hello"], "user" => root(), "server"; "/bin/hello");
+
     pass!(["user server=(ALL:ALL) ALL"], "user" => root(), "server"; "/bin/hello");
     FAIL!(["user laptop=(ALL:ALL) ALL"], "user" => root(), "server"; "/bin/hello");
 
@@ -215,6 +251,10 @@ fn permission_test() {
     pass!(["Host_Alias MACHINE=laptop,server","user MACHINE=ALL"], "user" => root(), "server"; "/bin/bash");
     pass!(["Host_Alias MACHINE=laptop,server","user MACHINE=ALL"], "user" => root(), "laptop"; "/bin/bash");
     FAIL!(["Host_Alias MACHINE=laptop,server","user MACHINE=ALL"], "user" => root(), "desktop"; "/bin/bash");
+    pass!(["user server=ALL"], "user" => root(), "server.example.com"; "/bin/bash");
+    pass!(["user server.example.com=ALL"], "user" => root(), "server.example.com"; "/bin/bash");
+    FAIL!(["user server.example.org=ALL"], "user" => root(), "server.example.com"; "/bin/bash");
+    FAIL!(["user server.example.com=ALL"], "user" => root(), "server"; "/bin/bash");
     pass!(["Cmnd_Alias WHAT=/bin/dd, /bin/rm","user ALL=WHAT"], "user" => root(), "server"; "/bin/rm");
     pass!(["Cmd_Alias WHAT=/bin/dd,/bin/rm","user ALL=WHAT"], "user" => root(), "laptop"; "/bin/dd");
     FAIL!(["Cmnd_Alias WHAT=/bin/dd,/bin/rm","user ALL=WHAT"], "user" => root(), "desktop"; "/bin/bash");
@@ -266,7 +306,8 @@ fn default_set_test() {
             "Defaults env_check += \"FOO\"",
             "Defaults env_check += \"XYZZY\"",
             "Defaults passwd_tries = 5",
-            "Defaults secure_path = /etc"
+            "Defaults secure_path = /etc",
+            "Defaults editor = \"/usr/bin/vim:/usr/bin/nano\""
         ],
     );
     assert_eq!(
@@ -281,6 +322,10 @@ fn default_set_test() {
             .collect()
     );
     assert_eq!(settings.str_value["secure_path"].as_deref(), Some("/etc"));
+    assert_eq!(
+        settings.str_value["editor"].as_deref(),
+        Some("/usr/bin/vim:/usr/bin/nano")
+    );
     assert_eq!(settings.int_value["passwd_tries"], 5);
 
     assert!(parse_string::<Sudo>("Defaults verifypw = \"sometimes\"").is_err());
@@ -407,11 +452,456 @@ fn gh676_percent_h_escape_unsupported() {
     );
     assert_eq!(errs.len(), 1);
     assert_eq!(
-        errs[0].1,
+        errs[0].message,
         "cannot open sudoers file /etc/%h: percent escape %h in includedir is unsupported"
     )
 }
 
+#[test]
+fn reports_every_error() {
+    let text = "Defaults env_kep += FOO\n\
+                root ALL=(ALL:ALL) ALL\n\
+                ferris ALL=(root /bin/true\n";
+    let (_, errs) = Sudoers::read(text.as_bytes(), "/etc/fakesudoers").unwrap();
+    assert_eq!(errs.len(), 2);
+
+    let locations = errs
+        .iter()
+        .map(|err| {
+            (
+                err.source.as_deref(),
+                err.location.clone().map(|loc| loc.start),
+            )
+        })
+        .collect::<Vec<_>>();
+    let path = Some(Path::new("/etc/fakesudoers"));
+    assert_eq!(locations, [(path, Some((1, 10))), (path, Some((3, 18)))]);
+    assert_eq!(
+        errs[0].message,
+        "unknown setting: 'env_kep', did you mean 'env_keep'?"
+    );
+}
+
+#[test]
+fn explains_accepted_syntax() {
+    let message = |text: &str| {
+        let (_, errs) = Sudoers::read(text.as_bytes(), "/etc/fakesudoers").unwrap();
+        assert_eq!(errs.len(), 1, "{text}");
+        errs[0].message.clone()
+    };
+
+    assert_eq!(
+        message("Defaults passwd_tries = 2000\n"),
+        "'2000' is not a valid value for passwd_tries; expected: Defaults passwd_tries = <number from 0 to 1000>"
+    );
+    assert_eq!(
+        message("Defaults use_pty = yes\n"),
+        "can't assign to boolean setting 'use_pty'; expected: Defaults use_pty, Defaults !use_pty"
+    );
+    assert_eq!(
+        message("Defaults verifypw = sometimes\n"),
+        "'sometimes' is not a valid value for verifypw; expected: Defaults verifypw = all|always|any|never, Defaults !verifypw"
+    );
+    assert_eq!(
+        message("Defaults editor\n"),
+        "'editor' is not a boolean setting; expected: Defaults editor = \"<text>\""
+    );
+}
+
+#[test]
+fn stops_at_invalid_utf8() {
+    let text =
+        b"root ALL=(ALL:ALL) ALL\nuser ALL=(ALL:ALL) ALL, !/bin/b\xffsh\nuser ALL=(ALL:ALL) ALL\n";
+    let (sudoers, errs) = Sudoers::read(&text[..], "/etc/fakesudoers").unwrap();
+
+    // the line that is cut short is not used, nor anything after it
+    assert_eq!(sudoers.rules.len(), 1);
+    assert_eq!(errs.len(), 1);
+    assert_eq!(errs[0].message, "invalid UTF-8");
+    assert_eq!(errs[0].location.clone().map(|loc| loc.start), Some((2, 32)));
+}
+
+#[test]
+fn list_pseudo_command() {
+    let may_list = |text: &str, user: &'static str, other: &'static str| {
+        let (sudoers, errs) = Sudoers::read(text.as_bytes(), "/etc/fakesudoers").unwrap();
+        assert!(errs.is_empty());
+        let request = ListRequest {
+            target_user: &Named("root"),
+            target_group: &Named("root"),
+        };
+        let other = (&Named(other), &Named(other));
+        sudoers
+            .check_list_other_permission(&Named(user), "server", other, request)
+            .flags
+            .map(|tag| tag.authenticate)
+    };
+
+    let text = "user ALL=(bob) NOPASSWD: list\nuser ALL=(ALL) /bin/ls\n";
+    assert_eq!(may_list(text, "user", "bob"), Some(Authenticate::Nopasswd));
+    assert_eq!(may_list(text, "user", "alice"), None);
+    assert_eq!(may_list(text, "other", "bob"), None);
+
+    // the password requirement is that of the rule for `list`
+    let text = "user ALL=(ALL) list, NOPASSWD: /bin/ls\n";
+    assert_eq!(may_list(text, "user", "alice"), Some(Authenticate::None));
+
+    // ALL includes listing other users, and only `ALL` or `list` do
+    assert!(may_list("user ALL=(ALL) ALL\n", "user", "alice").is_some());
+    assert!(may_list("user ALL=(ALL) /bin/ls\n", "user", "alice").is_none());
+
+    // `list` is not a command that can be run
+    let (sudoers, _) =
+        Sudoers::read("user ALL=(ALL) list\n".as_bytes(), "/etc/fakesudoers").unwrap();
+    let request = Request {
+        user: &Named("root"),
+        group: &Named("root"),
+        command: Path::new("/usr/bin/list"),
+        arguments: &[],
+    };
+    assert!(sudoers
+        .check(&Named("user"), "server", request)
+        .flags
+        .is_none());
+}
+
+#[test]
+fn validate_with_verifypw() {
+    let may_validate = |text: &str, user: &'static str| {
+        let (sudoers, errs) = Sudoers::read(text.as_bytes(), "/etc/fakesudoers").unwrap();
+        assert!(errs.is_empty());
+        sudoers
+            .check_validate_permission(&Named(user), "server")
+            .flags
+            .map(|tag| tag.authenticate)
+    };
+
+    let rules = "user ALL=(ALL) /bin/ls, NOPASSWD: /bin/cat\n";
+    assert_eq!(may_validate(rules, "user"), Some(Authenticate::Passwd));
+    assert_eq!(may_validate(rules, "other"), None);
+    assert_eq!(may_validate(rules, "root"), None);
+
+    let with = |verifypw: &str| format!("Defaults verifypw={verifypw}\n{rules}");
+    assert_eq!(
+        may_validate(&with("any"), "user"),
+        Some(Authenticate::Nopasswd)
+    );
+    assert_eq!(
+        may_validate(&with("all"), "user"),
+        Some(Authenticate::Passwd)
+    );
+    assert_eq!(
+        may_validate(&with("never"), "user"),
+        Some(Authenticate::Nopasswd)
+    );
+    assert_eq!(
+        may_validate(&format!("Defaults !verifypw\n{rules}"), "user"),
+        Some(Authenticate::Nopasswd)
+    );
+
+    let rules = "user ALL=(ALL) NOPASSWD: ALL\n";
+    assert_eq!(may_validate(rules, "user"), Some(Authenticate::Nopasswd));
+    assert_eq!(
+        may_validate(&format!("Defaults verifypw=always\n{rules}"), "user"),
+        Some(Authenticate::Passwd)
+    );
+}
+
+#[test]
+fn host_defaults() {
+    let text = "Host_Alias SERVERS = server, backup\n\
+                Defaults passwd_tries=5\n\
+                Defaults@SERVERS !use_pty, passwd_tries=1, runas_default=ferris\n\
+                Defaults@desktop env_keep += FOO\n\
+                user ALL = ALL\n";
+    let (sudoers, errs) = Sudoers::read(text.as_bytes(), "/etc/fakesudoers").unwrap();
+    assert!(errs.is_empty());
+
+    let check = |host, runas| {
+        let request = Request {
+            user: &Named(runas),
+            group: &Named(runas),
+            command: Path::new("/bin/ls"),
+            arguments: &[],
+        };
+        sudoers.check(&Named("user"), host, request)
+    };
+
+    let judgement = check("server", "ferris");
+    assert!(judgement.flags.is_some());
+    assert!(!judgement.settings.flags.contains("use_pty"));
+    assert_eq!(judgement.settings.int_value["passwd_tries"], 1);
+    assert!(check("server", "root").flags.is_none());
+
+    let judgement = check("desktop", "root");
+    assert!(judgement.flags.is_some());
+    assert!(judgement.settings.flags.contains("use_pty"));
+    assert_eq!(judgement.settings.int_value["passwd_tries"], 5);
+    assert!(judgement.settings.list["env_keep"].contains("FOO"));
+    assert!(!check("backup", "root").settings.list["env_keep"].contains("FOO"));
+
+    // these apply after the other `Defaults`, wherever they are in the file
+    let text = "Defaults@server passwd_tries=1\nDefaults passwd_tries=5\n";
+    let (sudoers, _) = Sudoers::read(text.as_bytes(), "/etc/fakesudoers").unwrap();
+    let request = Request {
+        user: &Named("root"),
+        group: &Named("root"),
+        command: Path::new("/bin/ls"),
+        arguments: &[],
+    };
+    let judgement = sudoers.check(&Named("user"), "server", request);
+    assert_eq!(judgement.settings.int_value["passwd_tries"], 1);
+}
+
+#[test]
+fn command_defaults() {
+    let text = "Cmnd_Alias GUI = /usr/bin/virt-manager, /usr/bin/gparted\n\
+                Defaults!/usr/bin/ssh, /usr/bin/scp env_keep += SSH_AUTH_SOCK\n\
+                Defaults!GUI env_keep += \"DISPLAY XAUTHORITY\", !use_pty\n\
+                Defaults !use_pty, passwd_tries=5, env_keep -= \"DISPLAY XAUTHORITY\"\n\
+                user ALL = ALL\n";
+    let (sudoers, errs) = Sudoers::read(text.as_bytes(), "/etc/fakesudoers").unwrap();
+    assert!(errs.is_empty());
+
+    let settings = |command| {
+        let request = Request {
+            user: &Named("root"),
+            group: &Named("root"),
+            command: Path::new(command),
+            arguments: &[],
+        };
+        sudoers.check(&Named("user"), "server", request).settings
+    };
+
+    let ssh = settings("/usr/bin/ssh");
+    assert!(ssh.list["env_keep"].contains("SSH_AUTH_SOCK"));
+    assert!(!ssh.list["env_keep"].contains("DISPLAY"));
+    assert_eq!(ssh.int_value["passwd_tries"], 5);
+
+    let gui = settings("/usr/bin/gparted");
+    assert!(gui.list["env_keep"].contains("DISPLAY"));
+    assert!(gui.list["env_keep"].contains("XAUTHORITY"));
+    assert!(!gui.list["env_keep"].contains("SSH_AUTH_SOCK"));
+
+    let other = settings("/bin/ls");
+    assert!(!other.list["env_keep"].contains("SSH_AUTH_SOCK"));
+    assert!(!other.flags.contains("use_pty"));
+
+    // `Defaults !setting` is not a `Defaults!command` line
+    let (sudoers, errs) =
+        Sudoers::read("Defaults !use_pty\n".as_bytes(), "/etc/fakesudoers").unwrap();
+    assert!(errs.is_empty());
+    assert!(sudoers.cmnd_defaults.is_empty());
+    assert!(!sudoers.settings.flags.contains("use_pty"));
+}
+
+#[test]
+fn explain() {
+    use explain::Verdict::*;
+
+    let text = "Defaults env_reset, passwd_tries=5\n\
+                Defaults@server env_keep += \"FOO BAR\"\n\
+                Defaults@desktop !use_pty\n\
+                \n\
+                other ALL = ALL\n\
+                user desktop = ALL\n\
+                user ALL = (ferris) /bin/ls\n\
+                user ALL = /bin/true\n\
+                user ALL = NOPASSWD: ALL\n\
+                user ALL = !/bin/ls, /bin/cat\n";
+    let (sudoers, errs) = Sudoers::read(text.as_bytes(), "/etc/fakesudoers").unwrap();
+    assert!(errs.is_empty());
+
+    let explain = |command: &str| {
+        let command = crate::common::resolve::canonicalize(command).unwrap();
+        let request = Request {
+            user: &Named("root"),
+            group: &Named("root"),
+            command: &command,
+            arguments: &[],
+        };
+        let explanation = sudoers.explain(&Named("user"), "server", request);
+        let verdicts = explanation
+            .rules
+            .iter()
+            .map(|(origin, verdict)| (origin.line, verdict))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            verdicts[..4],
+            [
+                (5, &OtherUser),
+                (6, &OtherHost),
+                (7, &OtherRunas),
+                (8, &OtherCommand)
+            ]
+        );
+        (
+            explanation.deciding_rule().map(|(o, a)| (o.line, a)),
+            explanation.to_string(),
+        )
+    };
+
+    let (decision, text) = explain("/bin/ls");
+    assert_eq!(decision, Some((10, false)));
+    assert!(text.contains(
+        "/etc/fakesudoers:9: allows the command without a password, but a later rule decides\n"
+    ));
+    assert!(text.contains("The command is forbidden by /etc/fakesudoers:10."));
+    assert!(text.ends_with(
+        "Defaults:\n    /etc/fakesudoers:1: Defaults env_reset, passwd_tries=5\n    \
+         /etc/fakesudoers:2: Defaults@server env_keep+=\"FOO BAR\""
+    ));
+
+    let (decision, text) = explain("/bin/cat");
+    assert_eq!(decision, Some((10, true)));
+    assert!(text.contains("/etc/fakesudoers:9: allows the command without a password, but"));
+
+    // the explanation agrees with the check
+    let (decision, _) = explain("/usr/bin/apt");
+    assert_eq!(decision, Some((9, true)));
+    let request = Request {
+        user: &Named("root"),
+        group: &Named("root"),
+        command: Path::new("/usr/bin/apt"),
+        arguments: &[],
+    };
+    let judgement = sudoers.check(&Named("user"), "server", request);
+    assert_eq!(
+        judgement.flags.unwrap().authenticate,
+        Authenticate::Nopasswd
+    );
+}
+
+#[test]
+fn cache_round_trip() {
+    let text = "Defaults !env_reset, passwd_tries=5, verifypw=any, secure_path=/bin\n\
+                Defaults env_keep += \"FOO BAR\"\n\
+                Defaults@SERVERS !use_pty, verifypw=never, env_keep -= FOO\n\
+                User_Alias ADMINS = %wheel, !#1234, ferris\n\
+                Host_Alias SERVERS = server, 10.0.0.1\n\
+                Cmnd_Alias SHELLS = /bin/sh, /bin/bash -c \"\"\n\
+                ADMINS SERVERS=(root:ALL) CWD=* NOPASSWD: ALL, !SHELLS\n\
+                user ALL=(ALL) CAPS=net_admin SANDBOX=web PASSWD: /usr/bin/*, CWD=/tmp /bin/true\n\
+                user ALL=(ALL) LOG_INPUT: NOLOG_OUTPUT: MAIL: FOLLOW: SETENV: NOEXEC: NOINTERCEPT: ENV=\"LANG=C\" /bin/ls\n";
+    let (sudoers, errs) = Sudoers::read(text.as_bytes(), "/etc/fakesudoers").unwrap();
+    assert!(errs.is_empty());
+
+    let contents = cache::encode(&sudoers);
+    let cached = cache::decode(&contents).unwrap();
+    assert_eq!(cached.sources, sudoers.sources);
+    assert_eq!(cached.sources[0].path, Path::new("/etc/fakesudoers"));
+    assert_eq!(cached.rule_origins, sudoers.rule_origins);
+    assert_eq!(cached.rule_origins[2].line, 9);
+
+    let requests = [
+        ("ferris", "server", "/bin/ls"),
+        ("ferris", "server", "/bin/sh"),
+        ("ferris", "desktop", "/bin/ls"),
+        ("user", "server", "/usr/bin/apt"),
+        ("user", "server", "/bin/true"),
+        ("user", "server", "/bin/ls"),
+    ];
+    for (user, host, command) in requests {
+        let check = |sudoers: &Sudoers| {
+            let request = Request {
+                user: &Named("root"),
+                group: &Named("root"),
+                command: Path::new(command),
+                arguments: &[],
+            };
+            sudoers.check(&Named(user), host, request)
+        };
+        let (expected, actual) = (check(&sudoers), check(&cached));
+        assert_eq!(expected.flags, actual.flags, "{user} {host} {command}");
+        let explain = |sudoers: &Sudoers| {
+            let request = Request {
+                user: &Named("root"),
+                group: &Named("root"),
+                command: Path::new(command),
+                arguments: &[],
+            };
+            sudoers.explain(&Named(user), host, request).to_string()
+        };
+        assert_eq!(explain(&sudoers), explain(&cached));
+        let (expected, actual) = (&expected.settings, &actual.settings);
+        assert_eq!(expected.flags, actual.flags, "{host}");
+        assert_eq!(expected.list, actual.list, "{host}");
+        assert_eq!(
+            expected.enum_value["verifypw"].get(),
+            actual.enum_value["verifypw"].get()
+        );
+    }
+
+    let (expected, actual) = (&sudoers.settings, &cached.settings);
+    assert_eq!(expected.flags, actual.flags);
+    assert_eq!(expected.str_value, actual.str_value);
+    assert_eq!(expected.int_value, actual.int_value);
+    assert_eq!(expected.list, actual.list);
+    assert_eq!(actual.enum_value["verifypw"].get(), "any");
+
+    assert!(cache::decode(&contents[..contents.len() - 1]).is_none());
+    assert!(cache::decode(&[contents.as_slice(), b"\0"].concat()).is_none());
+    assert!(cache::decode(b"sudo-rs sudoers cache 0.0.0\n").is_none());
+}
+
+#[test]
+fn cache_resolves_commands_again() {
+    let dir = std::env::temp_dir().join(format!("sudo-rs-cache-links-{}", std::process::id()));
+    for target in ["old", "new"] {
+        std::fs::create_dir_all(dir.join(target)).unwrap();
+        std::fs::write(dir.join(target).join("tool"), "").unwrap();
+    }
+    let link = dir.join("bin");
+    let point_to = |target: &str| {
+        let _ = std::fs::remove_file(&link);
+        std::os::unix::fs::symlink(dir.join(target), &link).unwrap();
+    };
+    point_to("old");
+
+    let text = format!("user ALL=(ALL) {}/tool\n", link.display());
+    let (sudoers, errs) = Sudoers::read(text.as_bytes(), "/etc/fakesudoers").unwrap();
+    assert!(errs.is_empty());
+    let contents = cache::encode(&sudoers);
+    point_to("new");
+    let cached = cache::decode(&contents).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let allows = |sudoers: &Sudoers, target: &str| {
+        let request = Request {
+            user: &Named("root"),
+            group: &Named("root"),
+            command: &dir.join(target).join("tool"),
+            arguments: &[],
+        };
+        sudoers
+            .check(&Named("user"), "server", request)
+            .flags
+            .is_some()
+    };
+    assert!(allows(&sudoers, "old") && !allows(&sudoers, "new"));
+    assert!(!allows(&cached, "old") && allows(&cached, "new"));
+}
+
+#[test]
+fn cache_notices_a_replaced_directory() {
+    let dir = std::env::temp_dir().join(format!("sudo-rs-cache-dir-{}", std::process::id()));
+    let included = dir.join("sudoers.d");
+    std::fs::create_dir_all(&included).unwrap();
+    let source = cache::Source {
+        path: included.clone(),
+        kind: cache::SourceKind::Directory,
+        stamp: cache::Stamp::of(&std::fs::metadata(&included).unwrap()),
+    };
+    assert!(source.is_unchanged());
+
+    // the old directory is kept, so that the new one cannot get its inode
+    std::fs::rename(&included, dir.join("old")).unwrap();
+    std::fs::create_dir(&included).unwrap();
+    let unchanged = source.is_unchanged();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(!unchanged);
+}
+
 #[test]
 #[should_panic]
 fn hashsign_error() {
@@ -1,6 +1,7 @@
 //! Various tokens
 
 use super::basic_parser::{Many, Token};
+pub use crate::system::capabilities::CapabilitySet;
 
 #[cfg_attr(test, derive(Clone, PartialEq, Eq))]
 pub struct Username(pub String);
@@ -46,7 +47,8 @@ impl Token for Numeric {
     }
 
     fn accept(c: char) -> bool {
-        c.is_ascii_hexdigit() || c == '.'
+        // with the units of a length of time or a size
+        c.is_ascii_hexdigit() || c == '.' || matches!(c, 'h' | 'm' | 's' | 'K' | 'k' | 'M' | 'G')
     }
 }
 
@@ -139,31 +141,20 @@ impl Token for AliasName {
 
 /// A struct that represents valid command strings; this can contain escape sequences and are
 /// limited to 1024 characters.
-pub type Command = (glob::Pattern, Option<Box<[String]>>);
-
-impl Token for Command {
-    const MAX_LEN: usize = 1024;
-
-    fn construct(s: String) -> Result<Self, String> {
-        let cvt_err = |pat: Result<_, glob::PatternError>| {
-            pat.map_err(|err| format!("wildcard pattern error {err}"))
-        };
+pub type Command = (CommandPath, Option<Box<[String]>>);
+
+/// The path of a command, as a pattern in which the symbolic links in the directories leading up
+/// to the command have been resolved. The path as it was written is kept as well, so that they
+/// can be resolved again when the rules are read back from a cache.
+#[cfg_attr(test, derive(Clone, Debug, PartialEq, Eq))]
+pub struct CommandPath {
+    pub written: String,
+    pub pattern: glob::Pattern,
+}
 
-        // the tokenizer should not give us a token that consists of only whitespace
-        let mut cmd_iter = s.split_whitespace();
-        let mut cmd = cmd_iter.next().unwrap().to_string();
-        let mut args = cmd_iter.map(String::from).collect::<Vec<String>>();
-
-        let argpat = if args.is_empty() {
-            // if no arguments are mentioned, anything is allowed
-            None
-        } else {
-            if args.last().map(|x| -> &str { x }) == Some("\"\"") {
-                // if the magic "" appears, no (further) arguments are allowed
-                args.pop();
-            }
-            Some(args.into_boxed_slice())
-        };
+impl CommandPath {
+    pub fn new(written: String) -> Result<Self, String> {
+        let mut cmd = written.clone();
 
         // record if the cmd ends in a slash and remove it if it does
         let is_dir = cmd.ends_with('/') && {
@@ -184,7 +175,44 @@ impl Token for Command {
             cmd.push_str("/*");
         }
 
-        Ok((cvt_err(glob::Pattern::new(&cmd))?, argpat))
+        let pattern =
+            glob::Pattern::new(&cmd).map_err(|err| format!("wildcard pattern error {err}"))?;
+
+        Ok(CommandPath { written, pattern })
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.pattern.as_str()
+    }
+}
+
+impl std::fmt::Display for CommandPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Token for Command {
+    const MAX_LEN: usize = 1024;
+
+    fn construct(s: String) -> Result<Self, String> {
+        // the tokenizer should not give us a token that consists of only whitespace
+        let mut cmd_iter = s.split_whitespace();
+        let cmd = cmd_iter.next().unwrap().to_string();
+        let mut args = cmd_iter.map(String::from).collect::<Vec<String>>();
+
+        let argpat = if args.is_empty() {
+            // if no arguments are mentioned, anything is allowed
+            None
+        } else {
+            if args.last().map(|x| -> &str { x }) == Some("\"\"") {
+                // if the magic "" appears, no (further) arguments are allowed
+                args.pop();
+            }
+            Some(args.into_boxed_slice())
+        };
+
+        Ok((CommandPath::new(cmd)?, argpat))
     }
 
     // all commands start with "/" except "sudoedit"
@@ -204,6 +232,33 @@ impl Token for Command {
 
 impl Many for Command {}
 
+/// A command on a `Defaults!` line, which takes no arguments, so that whitespace ends it and the
+/// settings can follow.
+pub struct DefaultsCommand(pub Command);
+
+impl Token for DefaultsCommand {
+    const MAX_LEN: usize = Command::MAX_LEN;
+
+    fn construct(s: String) -> Result<Self, String> {
+        Command::construct(s).map(DefaultsCommand)
+    }
+
+    fn accept_1st(c: char) -> bool {
+        Command::accept_1st(c)
+    }
+
+    fn accept(c: char) -> bool {
+        Command::accept(c) && !c.is_whitespace()
+    }
+
+    const ALLOW_ESCAPE: bool = Command::ALLOW_ESCAPE;
+    fn escaped(c: char) -> bool {
+        Command::escaped(c)
+    }
+}
+
+impl Many for DefaultsCommand {}
+
 pub struct DefaultName(pub String);
 
 impl Token for DefaultName {
@@ -312,6 +367,70 @@ impl Token for StringParameter {
     }
 }
 
+// a variable and its value used in ENV specs: `ENV="NAME=value"`
+//                                                   ^^^^^^^^^^
+pub struct EnvAssignment(pub String, pub String);
+
+impl Token for EnvAssignment {
+    const MAX_LEN: usize = QuotedText::MAX_LEN;
+
+    fn construct(s: String) -> Result<Self, String> {
+        let valid_name = |name: &str| {
+            name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        };
+        match s.split_once('=') {
+            Some((name, value)) if valid_name(name) => {
+                Ok(EnvAssignment(name.to_string(), value.to_string()))
+            }
+            _ => Err(format!("expected NAME=value, found '{s}'")),
+        }
+    }
+
+    fn accept(c: char) -> bool {
+        !Self::escaped(c)
+    }
+
+    const ALLOW_ESCAPE: bool = true;
+    fn escaped(c: char) -> bool {
+        QuotedText::escaped(c)
+    }
+}
+
+// the name of a sandbox profile used in SANDBOX specs
+#[derive(Clone, PartialEq)]
+#[cfg_attr(test, derive(Debug, Eq))]
+pub struct ProfileName(pub String);
+
+impl Token for ProfileName {
+    fn construct(s: String) -> Result<Self, String> {
+        Ok(ProfileName(s))
+    }
+
+    fn accept(c: char) -> bool {
+        c.is_ascii_alphanumeric() || "_-".contains(c)
+    }
+
+    fn accept_1st(c: char) -> bool {
+        c.is_ascii_alphanumeric()
+    }
+}
+
+// a set of capabilities used in CAPS specs
+impl Token for CapabilitySet {
+    fn construct(s: String) -> Result<Self, String> {
+        CapabilitySet::from_names(&s)
+    }
+
+    fn accept(c: char) -> bool {
+        c.is_ascii_alphanumeric() || "_,".contains(c)
+    }
+
+    fn accept_1st(c: char) -> bool {
+        c.is_ascii_alphabetic()
+    }
+}
+
 // a path used for in CWD and CHROOT specs
 #[derive(Clone, PartialEq)]
 #[cfg_attr(test, derive(Debug, Eq))]
@@ -1,8 +1,17 @@
-use std::fs::{DirBuilder, File, Metadata, OpenOptions};
+use std::ffi::{CStr, CString};
+use std::fs::{DirBuilder, File, Metadata, OpenOptions, Permissions};
 use std::io::{self, Error, ErrorKind};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
 use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
 use std::os::unix::prelude::OpenOptionsExt;
-use std::path::Path;
+use std::path::{Component, Path};
+
+use crate::cutils::cerr;
+
+use super::file::Chown;
+use super::interface::UserId;
+use super::userns::{overflow_uid, UidMap};
 
 // of course we can also write "file & 0o040 != 0", but this makes the intent explicit
 enum Op {
@@ -26,21 +35,177 @@ pub fn secure_open(path: impl AsRef<Path>, check_parent_dir: bool) -> io::Result
     secure_open_impl(path.as_ref(), &mut open_options, check_parent_dir, false)
 }
 
+/// Open a file that only root may read or write, such as the session records of a user. The file
+/// and the directories leading to it are created if needed; the file is owned by root:root and
+/// has mode 0600 afterwards, whichever group and umask sudo was started with.
 pub fn secure_open_cookie_file(path: impl AsRef<Path>) -> io::Result<File> {
+    let private = mode(Category::Owner, Op::Write) | mode(Category::Owner, Op::Read);
     let mut open_options = OpenOptions::new();
     open_options
         .read(true)
         .write(true)
         .create(true)
-        .mode(mode(Category::Owner, Op::Write) | mode(Category::Owner, Op::Read));
-    secure_open_impl(path.as_ref(), &mut open_options, true, true)
+        .mode(private);
+    let file = secure_open_impl(path.as_ref(), &mut open_options, true, true)?;
+
+    let meta = file.metadata()?;
+    if meta.gid() != 0 {
+        file.chown(0, 0)?;
+    }
+    if meta.permissions().mode() & 0o777 != private {
+        file.set_permissions(Permissions::from_mode(private))?;
+    }
+
+    Ok(file)
+}
+
+/// Like [`secure_open`], but without following symbolic links: neither `path` itself nor any of
+/// the directories leading to it may be a symbolic link, and those directories may only be
+/// writable by root (see [`open_no_symlinks`]).
+pub fn secure_open_no_symlinks(path: impl AsRef<Path>, check_parent_dir: bool) -> io::Result<File> {
+    let path = path.as_ref();
+    let (dir, name) = open_parent_dir(path, &[0])?;
+    if check_parent_dir {
+        checks(path.parent().unwrap_or(path), dir.metadata()?)?;
+    }
+
+    let file = open_at(path, &dir, &name, libc::O_RDONLY, 0)?;
+    checks(path, file.metadata()?)?;
+
+    Ok(file)
+}
+
+/// Open `path` for appending, creating it with `mode` if it does not exist, for a log that root
+/// writes to. As with [`secure_open_no_symlinks`], no symbolic links are followed, and both the
+/// directory that holds the file and the file itself have to be owned by root and may not be
+/// writable by others, so another user cannot choose which file is written to.
+pub fn secure_open_for_append(path: impl AsRef<Path>, mode: u32) -> io::Result<File> {
+    let path = path.as_ref();
+    let (dir, name) = open_parent_dir(path, &[0])?;
+    checks(path.parent().unwrap_or(path), dir.metadata()?)?;
+
+    let flags = libc::O_WRONLY | libc::O_APPEND | libc::O_CREAT;
+    let file = open_at(path, &dir, &name, flags, mode)?;
+    checks(path, file.metadata()?)?;
+
+    Ok(file)
+}
+
+/// Open `path` with the `flags` of `open`, creating it with `mode` if indicated, without
+/// following symbolic links in `path` or in any of the directories leading to it.
+///
+/// Every one of these directories has to be owned by root or by one of the `trusted` users, and
+/// may only be writable by everyone if it is sticky; otherwise another user could replace the
+/// file that is opened. Each directory is opened in turn, relative to the one before, so that
+/// none of them can be swapped for a symbolic link while this happens either. This does what
+/// `openat2` with `RESOLVE_NO_SYMLINKS` does on Linux, but on every system, and it lets each
+/// directory be checked on the way.
+pub fn open_no_symlinks(
+    path: &Path,
+    flags: libc::c_int,
+    mode: u32,
+    trusted: &[UserId],
+) -> io::Result<File> {
+    let (dir, name) = open_parent_dir(path, trusted)?;
+    open_at(path, &dir, &name, flags, mode)
+}
+
+/// Open the directory that contains `path` as [`open_no_symlinks`] describes, and return it with
+/// the name of `path` in it.
+fn open_parent_dir(path: &Path, trusted: &[UserId]) -> io::Result<(File, CString)> {
+    let error = |msg| Error::new(ErrorKind::PermissionDenied, msg);
+    let invalid = || {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} is not a valid path", path.display()),
+        )
+    };
+
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return Err(invalid());
+    };
+    if !path.is_absolute() {
+        return Err(invalid());
+    }
+
+    let mut dir = File::open("/")?;
+    let mut current = Path::new("/").to_path_buf();
+    loop {
+        let meta = dir.metadata()?;
+        let sticky = meta.mode() & 0o1000 != 0;
+        if meta.uid() != 0 && !trusted.contains(&meta.uid()) {
+            return Err(error(format!("{} is not owned by root", current.display())));
+        } else if meta.mode() & mode(Category::World, Op::Write) != 0 && !sticky {
+            return Err(error(format!(
+                "{} cannot be world-writable",
+                current.display()
+            )));
+        }
+
+        let Some(component) = parent.components().nth(current.components().count()) else {
+            break;
+        };
+        let Component::Normal(component) = component else {
+            return Err(invalid());
+        };
+        current.push(component);
+        let component = CString::new(component.as_bytes()).map_err(|_| invalid())?;
+        // not `O_DIRECTORY`, as Linux then reports a symbolic link as not being a directory
+        dir = open_at(&current, &dir, &component, libc::O_RDONLY, 0)?;
+        if !dir.metadata()?.is_dir() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} is not a directory", current.display()),
+            ));
+        }
+    }
+
+    let name = CString::new(name.as_bytes()).map_err(|_| invalid())?;
+    Ok((dir, name))
+}
+
+/// Open `name` in the directory `dir`, unless it is a symbolic link; `path` is its full path.
+fn open_at(
+    path: &Path,
+    dir: &File,
+    name: &CStr,
+    flags: libc::c_int,
+    mode: u32,
+) -> io::Result<File> {
+    // SAFETY: `dir` is a valid file descriptor and `name` is a null-terminated string.
+    let fd = unsafe {
+        libc::openat(
+            dir.as_raw_fd(),
+            name.as_ptr(),
+            flags | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+            mode as libc::c_uint,
+        )
+    };
+    match cerr(fd) {
+        // SAFETY: the file descriptor was just opened, and is not owned by anything else.
+        Ok(fd) => Ok(File::from(unsafe { OwnedFd::from_raw_fd(fd) })),
+        // this is how `O_NOFOLLOW` refuses symbolic links (FreeBSD uses `EMLINK`)
+        Err(err) if matches!(err.raw_os_error(), Some(libc::ELOOP | libc::EMLINK)) => {
+            Err(Error::new(
+                ErrorKind::PermissionDenied,
+                format!("{} is a symbolic link", path.display()),
+            ))
+        }
+        Err(err) => Err(err),
+    }
 }
 
 fn checks(path: &Path, meta: Metadata) -> io::Result<()> {
     let error = |msg| Error::new(ErrorKind::PermissionDenied, msg);
 
     let path_mode = meta.permissions().mode();
-    if meta.uid() != 0 {
+    if meta.uid() != 0 && meta.uid() == overflow_uid() && UidMap::current().is_some() {
+        Err(error(format!(
+            "{} must be owned by root, but is owned by a user that is not mapped into this user \
+             namespace",
+            path.display()
+        )))
+    } else if meta.uid() != 0 {
         Err(error(format!("{} must be owned by root", path.display())))
     } else if meta.gid() != 0 && (path_mode & mode(Category::Group, Op::Write) != 0) {
         Err(error(format!(
@@ -77,9 +242,7 @@ fn secure_open_impl(
                     .mode(
                         mode(Category::Owner, Op::Write)
                             | mode(Category::Owner, Op::Read)
-                            | mode(Category::Owner, Op::Exec)
-                            | mode(Category::Group, Op::Exec)
-                            | mode(Category::World, Op::Exec),
+                            | mode(Category::Owner, Op::Exec),
                     )
                     .create(parent_dir)?;
             }
@@ -120,6 +283,30 @@ mod test {
         assert!(secure_open("/etc/shadow", false).is_err());
     }
 
+    #[test]
+    fn refuses_symbolic_links() {
+        let link = std::env::temp_dir().join(format!("sudo-rs-link-{}", std::process::id()));
+        std::os::unix::fs::symlink("/etc", &link).unwrap();
+        // SAFETY: `geteuid` cannot fail.
+        let me = [unsafe { libc::geteuid() }];
+        let result = open_no_symlinks(&link.join("hosts"), libc::O_RDONLY, 0, &me);
+        std::fs::remove_file(&link).unwrap();
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::PermissionDenied);
+
+        assert!(open_no_symlinks(Path::new("/etc/hosts"), libc::O_RDONLY, 0, &[]).is_ok());
+        assert!(open_no_symlinks(Path::new("etc/hosts"), libc::O_RDONLY, 0, &[]).is_err());
+        assert!(open_no_symlinks(Path::new("/etc/../etc/hosts"), libc::O_RDONLY, 0, &[]).is_err());
+    }
+
+    #[test]
+    fn appends_only_in_private_directories() {
+        // the temporary directory is writable by everyone, or owned by the user running the tests
+        let path = std::env::temp_dir().join(format!("sudo-rs-debug-{}", std::process::id()));
+        let result = secure_open_for_append(&path, 0o600);
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::PermissionDenied);
+        assert!(!path.exists());
+    }
+
     #[test]
     fn test_secure_open_cookie_file() {
         assert!(secure_open_cookie_file("/etc/hosts").is_err());
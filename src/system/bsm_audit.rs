@@ -0,0 +1,190 @@
+//! Writing records to the BSM audit trail of macOS and FreeBSD (e.g. that of auditd), with the
+//! `libbsm` of OpenBSM, like the Linux audit support does for the Linux audit subsystem.
+//!
+//! Like ogsudo, a record is only written if auditing is enabled and the audit mask of the user
+//! selects the `AUE_sudo` event.
+use std::ffi::{c_char, c_int, c_short, c_uint, c_void, CString, OsStr};
+use std::io;
+use std::os::unix::prelude::OsStrExt;
+use std::ptr;
+
+use crate::cutils::cerr;
+
+/// The event of ogsudo in `audit_uevents.h`.
+const AUE_SUDO: c_short = 6300;
+
+const A_GETCOND: c_int = 37;
+const AUC_NOAUDIT: c_int = 2;
+
+const AU_TO_NO_WRITE: c_int = 0;
+const AU_TO_WRITE: c_int = 1;
+
+const AU_PRS_SUCCESS: c_int = 1;
+const AU_PRS_FAILURE: c_int = 2;
+const AU_PRS_REREAD: c_int = 1;
+
+/// A token of a record, which is owned by the record once it is written to it.
+#[repr(C)]
+struct Token {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct AuditMask {
+    success: c_uint,
+    failure: c_uint,
+}
+
+/// `auditinfo_addr_t`, of which only the mask is used.
+#[repr(C)]
+struct AuditInfo {
+    auid: libc::uid_t,
+    mask: AuditMask,
+    /// The terminal: the port is a 64-bit `dev_t` in the kernel of FreeBSD.
+    #[cfg(target_os = "freebsd")]
+    port: u64,
+    #[cfg(not(target_os = "freebsd"))]
+    port: i32,
+    addr_type: u32,
+    addr: [u32; 4],
+    asid: libc::pid_t,
+    flags: u64,
+}
+
+#[link(name = "bsm")]
+extern "C" {
+    fn au_open() -> c_int;
+    fn au_write(d: c_int, token: *mut Token) -> c_int;
+    fn au_close(d: c_int, keep: c_int, event: c_short) -> c_int;
+    fn au_to_me() -> *mut Token;
+    fn au_to_exec_args(argv: *mut *mut c_char) -> *mut Token;
+    fn au_to_text(text: *const c_char) -> *mut Token;
+    fn au_to_return32(status: c_char, ret: u32) -> *mut Token;
+    fn au_to_exit(retval: c_int, err: c_int) -> *mut Token;
+    fn au_preselect(event: c_short, mask: *mut AuditMask, sorf: c_int, flag: c_int) -> c_int;
+}
+
+extern "C" {
+    fn auditon(cmd: c_int, data: *mut c_void, length: c_int) -> c_int;
+    fn getaudit_addr(info: *mut AuditInfo, length: c_int) -> c_int;
+}
+
+/// What became of a command.
+pub enum Outcome<'a> {
+    Accepted,
+    Rejected(&'a str),
+    /// The exit status of the command, or 128 plus the signal that terminated it.
+    Exited(i32),
+}
+
+/// Whether a record should be written at all, which is not the case if the kernel was built
+/// without audit support, auditing is disabled, or the user is not audited for this event.
+fn is_selected(success: bool) -> io::Result<bool> {
+    let mut condition: c_int = 0;
+    // SAFETY: `A_GETCOND` writes an `int` to `condition`.
+    let result = cerr(unsafe {
+        auditon(
+            A_GETCOND,
+            (&mut condition as *mut c_int).cast(),
+            std::mem::size_of::<c_int>() as c_int,
+        )
+    });
+    match result {
+        Err(err) if err.raw_os_error() == Some(libc::ENOSYS) => return Ok(false),
+        Err(err) => return Err(err),
+        Ok(_) if condition == AUC_NOAUDIT => return Ok(false),
+        Ok(_) => {}
+    }
+
+    // SAFETY: all fields of `AuditInfo` are integers.
+    let mut info: AuditInfo = unsafe { std::mem::zeroed() };
+    // SAFETY: `info` is valid for the length that is passed.
+    cerr(unsafe { getaudit_addr(&mut info, std::mem::size_of::<AuditInfo>() as c_int) })?;
+
+    let sorf = if success {
+        AU_PRS_SUCCESS
+    } else {
+        AU_PRS_FAILURE
+    };
+    // SAFETY: `info.mask` is a valid mask.
+    let selected = cerr(unsafe { au_preselect(AUE_SUDO, &mut info.mask, sorf, AU_PRS_REREAD) })?;
+
+    Ok(selected == 1)
+}
+
+/// A record that is discarded, unless it is written with [Record::commit].
+struct Record(c_int);
+
+impl Record {
+    fn open() -> io::Result<Self> {
+        // SAFETY: `au_open` takes no arguments.
+        cerr(unsafe { au_open() }).map(Record)
+    }
+
+    /// Add a token that was just created, which fails if it could not be created.
+    fn write(&mut self, token: *mut Token) -> io::Result<()> {
+        if token.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the token is valid, and is owned by the record from now on.
+        cerr(unsafe { au_write(self.0, token) }).map(|_| ())
+    }
+
+    fn commit(self) -> io::Result<()> {
+        let d = self.0;
+        std::mem::forget(self);
+        // SAFETY: the record was opened, and is not used afterwards.
+        cerr(unsafe { au_close(d, AU_TO_WRITE, AUE_SUDO) }).map(|_| ())
+    }
+}
+
+impl Drop for Record {
+    fn drop(&mut self) {
+        // SAFETY: the record was opened, and is not used afterwards.
+        unsafe { au_close(self.0, AU_TO_NO_WRITE, AUE_SUDO) };
+    }
+}
+
+/// Write a record about the command `argv` to the audit trail.
+pub fn write_record(argv: &[&OsStr], outcome: Outcome) -> io::Result<()> {
+    let success = !matches!(outcome, Outcome::Rejected(_));
+    if !is_selected(success)? {
+        return Ok(());
+    }
+
+    let to_c_string = |bytes: &[u8]| {
+        CString::new(bytes).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))
+    };
+    let args = argv
+        .iter()
+        .map(|arg| to_c_string(arg.as_bytes()))
+        .collect::<io::Result<Vec<_>>>()?;
+    // `au_to_exec_args` does not change the arguments, despite its signature
+    let mut arg_ptrs = args
+        .iter()
+        .map(|arg| arg.as_ptr() as *mut c_char)
+        .chain([ptr::null_mut()])
+        .collect::<Vec<_>>();
+
+    let mut record = Record::open()?;
+    // SAFETY: these functions only read from the pointers they are given, which are valid and
+    // NUL terminated; `arg_ptrs` ends with a null pointer.
+    unsafe {
+        record.write(au_to_me())?;
+        record.write(au_to_exec_args(arg_ptrs.as_mut_ptr()))?;
+        match outcome {
+            Outcome::Accepted => record.write(au_to_return32(0, 0))?,
+            Outcome::Rejected(reason) => {
+                let reason = to_c_string(reason.as_bytes())?;
+                record.write(au_to_text(reason.as_ptr()))?;
+                record.write(au_to_return32(-1i8 as c_char, libc::EPERM as u32))?;
+            }
+            Outcome::Exited(status) => {
+                record.write(au_to_exit(status, 0))?;
+                record.write(au_to_return32(0, status as u32))?;
+            }
+        }
+    }
+
+    record.commit()
+}
@@ -0,0 +1,384 @@
+//! Linux capabilities that can be granted to a command instead of full root privileges.
+//!
+//! Other systems have no capabilities, so there a command can only be granted none of them.
+use std::path::Path;
+#[cfg(target_os = "linux")]
+use std::{ffi::CString, os::unix::ffi::OsStrExt};
+use std::{fmt, io};
+
+#[cfg(target_os = "linux")]
+use crate::cutils::cerr;
+
+/// The names of all capabilities known to us, indexed by their number.
+const CAPABILITY_NAMES: [&str; 41] = [
+    "cap_chown",
+    "cap_dac_override",
+    "cap_dac_read_search",
+    "cap_fowner",
+    "cap_fsetid",
+    "cap_kill",
+    "cap_setgid",
+    "cap_setuid",
+    "cap_setpcap",
+    "cap_linux_immutable",
+    "cap_net_bind_service",
+    "cap_net_broadcast",
+    "cap_net_admin",
+    "cap_net_raw",
+    "cap_ipc_lock",
+    "cap_ipc_owner",
+    "cap_sys_module",
+    "cap_sys_rawio",
+    "cap_sys_chroot",
+    "cap_sys_ptrace",
+    "cap_sys_pacct",
+    "cap_sys_admin",
+    "cap_sys_boot",
+    "cap_sys_nice",
+    "cap_sys_resource",
+    "cap_sys_time",
+    "cap_sys_tty_config",
+    "cap_mknod",
+    "cap_lease",
+    "cap_audit_write",
+    "cap_audit_control",
+    "cap_setfcap",
+    "cap_mac_override",
+    "cap_mac_admin",
+    "cap_syslog",
+    "cap_wake_alarm",
+    "cap_block_suspend",
+    "cap_audit_read",
+    "cap_perfmon",
+    "cap_bpf",
+    "cap_checkpoint_restore",
+];
+
+/// A set of capabilities, as specified using e.g. `CAPS=cap_net_admin,cap_net_raw` in sudoers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CapabilitySet(u64);
+
+impl CapabilitySet {
+    /// Parse a comma separated list of capability names; the `cap_` prefix is optional and
+    /// names are case insensitive.
+    pub fn from_names(names: &str) -> Result<Self, String> {
+        let mut set = 0;
+        for name in names.split(',') {
+            let name = name.to_ascii_lowercase();
+            let name = name.strip_prefix("cap_").unwrap_or(&name);
+            let Some(number) = CAPABILITY_NAMES
+                .iter()
+                .position(|known| known["cap_".len()..] == *name)
+            else {
+                return Err(format!("unknown capability: {name}"));
+            };
+            set |= 1 << number;
+        }
+
+        Ok(Self(set))
+    }
+
+    /// The set as a bit mask, in which bit `n` stands for capability number `n`.
+    pub fn bits(&self) -> u64 {
+        self.0
+    }
+
+    pub fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    /// The capabilities that sudo can be given with `setcap` instead of being installed setuid
+    /// root: to switch to other users, to give terminals to them, to keep commands from typing
+    /// into terminals, to signal commands that run as another user, and to read and write the
+    /// files of root that root can access regardless of their mode.
+    pub const INSTEAD_OF_SETUID: Self =
+        Self(1 << 0 | 1 << 1 | 1 << 3 | 1 << 5 | 1 << 6 | 1 << 7 | 1 << 21);
+
+    /// Whether every capability in `other` is in this set.
+    pub fn includes(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    fn contains(&self, number: usize) -> bool {
+        self.0 & (1 << number) != 0
+    }
+
+    /// The permitted capabilities in the `security.capability` attribute of a file, which is
+    /// where `setcap` stores them; see `struct vfs_cap_data` in `linux/capability.h`.
+    fn from_file_attribute(value: &[u8]) -> Option<Self> {
+        const VFS_CAP_REVISION_MASK: u32 = 0xff000000;
+        const VFS_CAP_REVISION_2: u32 = 0x02000000;
+        const VFS_CAP_REVISION_3: u32 = 0x03000000;
+
+        let word = |index: usize| -> Option<u32> {
+            let bytes = value.get(4 * index..4 * index + 4)?;
+            Some(u32::from_le_bytes(bytes.try_into().ok()?))
+        };
+
+        let (low, high) = match word(0)? & VFS_CAP_REVISION_MASK {
+            // the magic word is followed by the permitted and inheritable capabilities, first of
+            // the lower and then of the upper 32 capabilities
+            VFS_CAP_REVISION_2 if value.len() == 20 => (word(1)?, word(3)?),
+            // the same, followed by the user id of root in the user namespace of the file
+            VFS_CAP_REVISION_3 if value.len() == 24 => (word(1)?, word(3)?),
+            _ => return None,
+        };
+
+        Some(Self(u64::from(low) | u64::from(high) << 32))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl CapabilitySet {
+    fn unsupported() -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            "capabilities are only supported on Linux",
+        )
+    }
+
+    pub fn permitted() -> io::Result<Self> {
+        Ok(Self::default())
+    }
+
+    pub fn of_file(_path: &Path) -> io::Result<Self> {
+        Ok(Self::default())
+    }
+
+    pub fn restrict_bounding_set(&self) -> io::Result<()> {
+        Err(Self::unsupported())
+    }
+
+    pub fn keep_across_setuid() -> io::Result<()> {
+        Err(Self::unsupported())
+    }
+
+    pub fn become_root() -> io::Result<()> {
+        Err(Self::unsupported())
+    }
+
+    pub fn apply(&self) -> io::Result<()> {
+        Err(Self::unsupported())
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct CapUserHeader {
+    version: u32,
+    pid: libc::c_int,
+}
+
+#[cfg(target_os = "linux")]
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct CapUserData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+#[cfg(target_os = "linux")]
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x20080522;
+
+#[cfg(target_os = "linux")]
+impl CapabilitySet {
+    /// The capabilities that the current process is permitted to use, e.g. because they were
+    /// given to its program with `setcap`.
+    pub fn permitted() -> io::Result<Self> {
+        let header = CapUserHeader {
+            version: LINUX_CAPABILITY_VERSION_3,
+            pid: 0,
+        };
+        let mut data = [CapUserData::default(); 2];
+
+        // SAFETY: both pointers point to valid structures of the layout expected by version 3
+        // of the capabilities interface, which fills in two of them.
+        cerr(unsafe {
+            libc::syscall(
+                libc::SYS_capget,
+                &header as *const CapUserHeader,
+                data.as_mut_ptr(),
+            )
+        })?;
+
+        Ok(Self(
+            u64::from(data[0].permitted) | u64::from(data[1].permitted) << 32,
+        ))
+    }
+
+    /// The capabilities that the program at `path` was given with `setcap`, which it is
+    /// permitted to use when it is executed (unless e.g. `no_new_privs` is set).
+    pub fn of_file(path: &Path) -> io::Result<Self> {
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+        let mut value = [0u8; 24];
+
+        // SAFETY: both strings are NUL terminated, and `value` can hold `value.len()` bytes.
+        let result = unsafe {
+            libc::getxattr(
+                c_path.as_ptr(),
+                b"security.capability\0".as_ptr().cast(),
+                value.as_mut_ptr().cast(),
+                value.len(),
+            )
+        };
+        match cerr(result) {
+            Ok(len) => Self::from_file_attribute(&value[..len as usize]).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: unknown format of file capabilities", path.display()),
+                )
+            }),
+            // a file without capabilities has no such attribute
+            Err(err) if err.raw_os_error() == Some(libc::ENODATA) => Ok(Self::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Remove every capability that is not in this set from the bounding set of the current
+    /// process, so the command cannot acquire them again (e.g. by executing a setuid binary).
+    ///
+    /// This requires `CAP_SETPCAP`, so it has to happen before switching to the target user.
+    pub fn restrict_bounding_set(&self) -> io::Result<()> {
+        for number in 0..u64::BITS as usize {
+            if self.contains(number) {
+                continue;
+            }
+            // SAFETY: `PR_CAPBSET_DROP` only takes a capability number as its argument.
+            if let Err(err) = cerr(unsafe { libc::prctl(libc::PR_CAPBSET_DROP, number, 0, 0, 0) }) {
+                // capabilities beyond the last one supported by the kernel are rejected
+                if err.raw_os_error() == Some(libc::EINVAL) {
+                    break;
+                }
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Keep the permitted capabilities when the user ids change from root to a regular user.
+    pub fn keep_across_setuid() -> io::Result<()> {
+        // SAFETY: `PR_SET_KEEPCAPS` only takes a boolean as its argument.
+        cerr(unsafe { libc::prctl(libc::PR_SET_KEEPCAPS, 1, 0, 0, 0) })?;
+
+        Ok(())
+    }
+
+    /// Change the effective and saved user ids to root, keeping the invoking user as the real user
+    /// id, as a program that is setuid root starts out with; this requires `CAP_SETUID`. The
+    /// permitted capabilities become the effective ones, but no others are gained.
+    pub fn become_root() -> io::Result<()> {
+        // SAFETY: `setresuid` only takes numbers; -1 leaves the real user id as it is
+        cerr(unsafe { libc::setresuid(libc::uid_t::MAX, 0, 0) })?;
+
+        Ok(())
+    }
+
+    /// Make this set the permitted, effective, inheritable and ambient capabilities of the
+    /// current process, so they are retained when a command is executed as a regular user.
+    pub fn apply(&self) -> io::Result<()> {
+        let header = CapUserHeader {
+            version: LINUX_CAPABILITY_VERSION_3,
+            pid: 0,
+        };
+        let data = [self.0 as u32, (self.0 >> 32) as u32].map(|bits| CapUserData {
+            effective: bits,
+            permitted: bits,
+            inheritable: bits,
+        });
+
+        // SAFETY: both pointers point to valid structures of the layout expected by version 3
+        // of the capabilities interface.
+        cerr(unsafe {
+            libc::syscall(
+                libc::SYS_capset,
+                &header as *const CapUserHeader,
+                data.as_ptr(),
+            )
+        })?;
+
+        for number in (0..u64::BITS as usize).filter(|&number| self.contains(number)) {
+            // SAFETY: `PR_CAP_AMBIENT_RAISE` only takes a capability number as its argument.
+            cerr(unsafe {
+                libc::prctl(
+                    libc::PR_CAP_AMBIENT,
+                    libc::PR_CAP_AMBIENT_RAISE,
+                    number,
+                    0,
+                    0,
+                )
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for CapabilitySet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = CAPABILITY_NAMES
+            .iter()
+            .enumerate()
+            .filter(|(number, _)| self.contains(*number))
+            .map(|(_, name)| name);
+
+        if let Some(first) = names.next() {
+            f.write_str(first)?;
+        }
+        for name in names {
+            write!(f, ",{name}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CapabilitySet;
+
+    #[test]
+    fn parses_capability_names() {
+        let caps = CapabilitySet::from_names("cap_net_admin,NET_RAW").unwrap();
+
+        assert_eq!(caps, CapabilitySet(1 << 12 | 1 << 13));
+        assert_eq!(caps.to_string(), "cap_net_admin,cap_net_raw");
+
+        let instead_of_setuid = CapabilitySet::INSTEAD_OF_SETUID;
+        assert_eq!(
+            instead_of_setuid.to_string(),
+            "cap_chown,cap_dac_override,cap_fowner,cap_kill,cap_setgid,cap_setuid,cap_sys_admin"
+        );
+        assert!(CapabilitySet::from_names(
+            "chown,dac_override,fowner,kill,setuid,setgid,sys_admin,net_admin"
+        )
+        .unwrap()
+        .includes(instead_of_setuid));
+        assert!(!caps.includes(instead_of_setuid));
+    }
+
+    #[test]
+    fn reads_file_capabilities() {
+        // `setcap cap_kill,cap_setuid,cap_sys_admin+ep`, as written by Linux 4.14 and later and
+        // by earlier versions; the inheritable capabilities are not part of the set
+        let caps = CapabilitySet::from_names("kill,setuid,sys_admin").unwrap();
+        let permitted = 1 << 5 | 1 << 7 | 1 << 21;
+        let attribute =
+            |words: &[u32]| -> Vec<u8> { words.iter().flat_map(|w| w.to_le_bytes()).collect() };
+        let revision_3 = attribute(&[0x03000001, permitted, 1 << 12, 0, 0, 0]);
+        let revision_2 = attribute(&[0x02000001, permitted, 1 << 12, 0, 0]);
+
+        assert_eq!(CapabilitySet::from_file_attribute(&revision_3), Some(caps));
+        assert_eq!(CapabilitySet::from_file_attribute(&revision_2), Some(caps));
+        assert_eq!(CapabilitySet::from_file_attribute(&revision_3[..20]), None);
+        assert_eq!(CapabilitySet::from_file_attribute(&[]), None);
+    }
+
+    #[test]
+    fn rejects_unknown_capabilities() {
+        assert!(CapabilitySet::from_names("cap_net_admin,cap_world_domination").is_err());
+        assert!(CapabilitySet::from_names("").is_err());
+    }
+}
@@ -0,0 +1,105 @@
+//! Running a command in a cgroup of its own, so that resource controls can be applied to the
+//! commands that are started with sudo, and tools like `systemd-cgls` show them separately.
+//!
+//! With `Defaults cgroup=sudo.slice`, a command is moved to a new cgroup `sudo-<pid>.scope` below
+//! `sudo.slice` in the unified (v2) cgroup hierarchy, where `<pid>` is the PID of sudo. The cgroup
+//! is removed when the command has exited, unless other processes are still running in it.
+//!
+//! The cgroup is created directly in the cgroup file system, so systemd does not manage it as a
+//! unit; resource controls are set up by configuring the parent cgroup, e.g. as a slice.
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+use super::interface::ProcessId;
+
+/// The places where the unified cgroup hierarchy is mounted: on systems that only use cgroups
+/// v2, or with the hybrid layout of systemd.
+const UNIFIED_ROOTS: &[&str] = &["/sys/fs/cgroup", "/sys/fs/cgroup/unified"];
+
+/// A cgroup that was created for a command.
+#[derive(Debug)]
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    /// Create the cgroup for the command of the sudo process `pid`, below `parent`, which is a
+    /// path relative to the root of the cgroup hierarchy. The parent is created if needed.
+    pub fn create(parent: &str, pid: ProcessId) -> io::Result<Self> {
+        let root = UNIFIED_ROOTS
+            .iter()
+            .map(Path::new)
+            .find(|root| root.join("cgroup.procs").exists())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "the unified cgroup hierarchy is not mounted",
+                )
+            })?;
+        let path = Self::path_below(root, parent, pid)?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::create_dir(&path)?;
+
+        Ok(Self { path })
+    }
+
+    fn path_below(root: &Path, parent: &str, pid: ProcessId) -> io::Result<PathBuf> {
+        let parent = Path::new(parent);
+        // the parent must not refer to a directory outside of the cgroup hierarchy
+        if !parent
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not a path relative to the root of the cgroup hierarchy",
+            ));
+        }
+
+        Ok(root.join(parent).join(format!("sudo-{pid}.scope")))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The file that a process writes `0` to in order to move itself to this cgroup.
+    pub fn procs(&self) -> PathBuf {
+        self.path.join("cgroup.procs")
+    }
+
+    /// Remove the cgroup, which fails if processes are still running in it.
+    pub fn remove(self) -> io::Result<()> {
+        fs::remove_dir(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::Cgroup;
+
+    #[test]
+    fn names_cgroups() {
+        let root = Path::new("/sys/fs/cgroup");
+        assert_eq!(
+            Cgroup::path_below(root, "sudo.slice", 42).unwrap(),
+            PathBuf::from("/sys/fs/cgroup/sudo.slice/sudo-42.scope")
+        );
+        assert_eq!(
+            Cgroup::path_below(root, "system.slice/admin.slice", 7).unwrap(),
+            PathBuf::from("/sys/fs/cgroup/system.slice/admin.slice/sudo-7.scope")
+        );
+
+        assert!(Cgroup::path_below(root, "/sudo.slice", 42).is_err());
+        assert!(Cgroup::path_below(root, "../sudo.slice", 42).is_err());
+        assert!(Cgroup::path_below(root, "sudo.slice/../..", 42).is_err());
+        assert!(Cgroup::path_below(root, "./sudo.slice", 42).is_err());
+    }
+}
@@ -0,0 +1,81 @@
+//! Looking up the fully qualified name of a host, for `Defaults fqdn`; without it, sudo does not
+//! use DNS at all.
+//!
+//! A resolver that is misconfigured can take a long time to give up, so the name is looked up on
+//! a thread of its own, and sudo carries on with the name it has when that takes too long. What
+//! was found, or not, is kept for the rest of the invocation.
+use std::ffi::{CStr, CString};
+use std::sync::{mpsc, Mutex};
+use std::time::Duration;
+use std::{mem, ptr, thread};
+
+use crate::cutils::string_from_ptr;
+
+/// How long a lookup may take before sudo gives up on it.
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The names that were looked up, with their fully qualified names if they were found.
+static CACHE: Mutex<Vec<(String, Option<String>)>> = Mutex::new(Vec::new());
+
+/// The fully qualified name of `host`, or `host` itself if it cannot be found in time.
+pub fn fully_qualified(host: &str) -> String {
+    fully_qualified_within(host, RESOLVE_TIMEOUT).unwrap_or_else(|| host.to_string())
+}
+
+fn fully_qualified_within(host: &str, timeout: Duration) -> Option<String> {
+    let mut cache = CACHE.lock().unwrap_or_else(|err| err.into_inner());
+    if let Some((_, found)) = cache.iter().find(|(name, _)| name == host) {
+        return found.clone();
+    }
+
+    let found = CString::new(host).ok().and_then(|name| {
+        let (sender, receiver) = mpsc::channel();
+        // a lookup that hangs is left behind, its result is never looked at
+        thread::Builder::new()
+            .name("resolver".to_string())
+            .spawn(move || sender.send(canonical_name(&name)))
+            .ok()?;
+        receiver.recv_timeout(timeout).ok().flatten()
+    });
+    cache.push((host.to_string(), found.clone()));
+
+    found
+}
+
+/// The canonical name of `host` according to `getaddrinfo`.
+fn canonical_name(host: &CStr) -> Option<String> {
+    // SAFETY: an all-zero `addrinfo` is valid, it only holds numbers and null pointers
+    let mut hints: libc::addrinfo = unsafe { mem::zeroed() };
+    hints.ai_family = libc::AF_UNSPEC;
+    hints.ai_socktype = libc::SOCK_STREAM;
+    hints.ai_flags = libc::AI_CANONNAME;
+
+    let mut found = ptr::null_mut();
+    // SAFETY: `host` is a C string and `hints` lives through the call; `found` is freed below
+    if unsafe { libc::getaddrinfo(host.as_ptr(), ptr::null(), &hints, &mut found) } != 0 {
+        return None;
+    }
+
+    // SAFETY: `getaddrinfo` succeeded, so `found` points to at least one result, of which the
+    // first has the canonical name if there is one
+    let name = unsafe { string_from_ptr((*found).ai_canonname) };
+    unsafe { libc::freeaddrinfo(found) };
+
+    (!name.is_empty()).then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_to_the_name() {
+        assert_eq!(fully_qualified("bad\0name"), "bad\0name");
+
+        // a lookup that was given up on is not tried again
+        let name = "unresolvable.invalid";
+        CACHE.lock().unwrap().push((name.to_string(), None));
+        assert_eq!(fully_qualified_within(name, Duration::ZERO), None);
+        assert_eq!(fully_qualified(name), name);
+    }
+}
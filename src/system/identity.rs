@@ -0,0 +1,278 @@
+//! Switching the credentials of a process over to those of the target user.
+//!
+//! All of the functions that run after `fork` only perform system calls on data that was prepared
+//! beforehand, so they do not allocate.
+use std::io;
+
+use crate::cutils::cerr;
+
+use super::{
+    capabilities::CapabilitySet,
+    interface::{GroupId, UserId},
+    Group, User,
+};
+
+/// The credentials a command will be run with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    real_uid: UserId,
+    uid: UserId,
+    gid: GroupId,
+    groups: Vec<GroupId>,
+    capabilities: Option<CapabilitySet>,
+}
+
+impl Identity {
+    /// The identity of `target_user` with `target_group` as its primary group.
+    ///
+    /// The supplementary groups are the groups of the target user as reported by `getgrouplist`
+    /// (i.e. what `initgroups` would set), unless `preserved_groups` is given, in which case
+    /// those are used instead (e.g. the groups of the invoking user when `sudo -P` is used).
+    /// The primary group is always part of the supplementary groups.
+    pub fn new(
+        target_user: &User,
+        target_group: &Group,
+        preserved_groups: Option<&[GroupId]>,
+    ) -> Self {
+        let mut groups = preserved_groups.unwrap_or(&target_user.groups).to_vec();
+
+        if !groups.contains(&target_group.gid) {
+            groups.insert(0, target_group.gid);
+        }
+
+        Self {
+            real_uid: target_user.uid,
+            uid: target_user.uid,
+            gid: target_group.gid,
+            groups,
+            capabilities: None,
+        }
+    }
+
+    /// Only change the effective (and saved) user id, keeping `real_user` as the real user id.
+    ///
+    /// This is what `Defaults stay_setuid` asks for, so that e.g. resource accounting remains
+    /// attributed to the invoking user.
+    pub fn keep_real_user(self, real_user: &User) -> Self {
+        Self {
+            real_uid: real_user.uid,
+            ..self
+        }
+    }
+
+    /// Only grant `capabilities` to the command, instead of all privileges of the target user.
+    ///
+    /// A command that runs as root keeps owning every file of root, whatever its capabilities,
+    /// so when the target user is root, the command is run as `invoking_user` instead, with
+    /// that user's own groups; it then holds no privileges beyond the listed capabilities.
+    pub fn with_capabilities(
+        self,
+        capabilities: Option<CapabilitySet>,
+        invoking_user: &User,
+    ) -> Self {
+        if capabilities.is_none() || self.uid != 0 {
+            return Self {
+                capabilities,
+                ..self
+            };
+        }
+
+        let mut groups = invoking_user.groups.clone();
+        if !groups.contains(&invoking_user.gid) {
+            groups.insert(0, invoking_user.gid);
+        }
+
+        Self {
+            real_uid: invoking_user.uid,
+            uid: invoking_user.uid,
+            gid: invoking_user.gid,
+            groups,
+            capabilities,
+        }
+    }
+
+    /// Permanently switch the current process over to this identity, together with
+    /// [Identity::switch_user], which has to be called right after this.
+    ///
+    /// The supplementary groups and the group ids are set first, as this is no longer possible
+    /// once the user ids have been changed. The real, effective and saved user ids are all set,
+    /// after which we verify that the process cannot regain its previous privileges. Finally, the
+    /// capabilities that were asked for are granted.
+    ///
+    /// These are separate steps so that it can be told which of them failed: this one sets the
+    /// supplementary groups.
+    pub fn switch_groups(&self) -> io::Result<()> {
+        if let Some(capabilities) = self.capabilities {
+            capabilities.restrict_bounding_set()?;
+            CapabilitySet::keep_across_setuid()?;
+        }
+
+        // SAFETY: `setgroups` reads `self.groups.len()` elements from a valid pointer.
+        cerr(unsafe { libc::setgroups(self.groups.len() as _, self.groups.as_ptr()) })?;
+
+        Ok(())
+    }
+
+    /// The second part of switching to this identity, after [Identity::switch_groups], which sets
+    /// the user and group ids and grants the capabilities.
+    pub fn switch_user(&self) -> io::Result<()> {
+        self.set_ids()?;
+        self.verify()?;
+
+        if let Some(capabilities) = self.capabilities {
+            capabilities.apply()?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn set_ids(&self) -> io::Result<()> {
+        cerr(unsafe { libc::setresgid(self.gid, self.gid, self.gid) })?;
+        cerr(unsafe { libc::setresuid(self.real_uid, self.uid, self.uid) })?;
+
+        Ok(())
+    }
+
+    /// macOS has no `setresuid`: as root, `setgid` and `setuid` set all three ids, and `setreuid`
+    /// sets the saved user id to the new effective user id when it changes the real user id.
+    #[cfg(target_os = "macos")]
+    fn set_ids(&self) -> io::Result<()> {
+        cerr(unsafe { libc::setgid(self.gid) })?;
+        if self.real_uid == self.uid {
+            cerr(unsafe { libc::setuid(self.uid) })?;
+        } else {
+            cerr(unsafe { libc::setreuid(self.real_uid, self.uid) })?;
+        }
+
+        Ok(())
+    }
+
+    /// Check that the switch to this identity happened and cannot be undone.
+    fn verify(&self) -> io::Result<()> {
+        #[cfg(not(target_os = "macos"))]
+        {
+            let (mut ruid, mut euid, mut suid) = (0, 0, 0);
+            let (mut rgid, mut egid, mut sgid) = (0, 0, 0);
+            // SAFETY: all pointers point to valid, writable memory.
+            cerr(unsafe { libc::getresuid(&mut ruid, &mut euid, &mut suid) })?;
+            cerr(unsafe { libc::getresgid(&mut rgid, &mut egid, &mut sgid) })?;
+
+            if [ruid, euid, suid] != [self.real_uid, self.uid, self.uid]
+                || [rgid, egid, sgid] != [self.gid; 3]
+            {
+                return Err(io::Error::from_raw_os_error(libc::EPERM));
+            }
+        }
+
+        // the saved ids cannot be queried here, the `setuid(0)` below still covers them
+        #[cfg(target_os = "macos")]
+        {
+            // SAFETY: these functions cannot fail.
+            let ids = unsafe { [libc::getuid(), libc::geteuid()] };
+            let group_ids = unsafe { [libc::getgid(), libc::getegid()] };
+            if ids != [self.real_uid, self.uid] || group_ids != [self.gid; 2] {
+                return Err(io::Error::from_raw_os_error(libc::EPERM));
+            }
+        }
+
+        // an unprivileged process must not be able to become root again
+        if self.real_uid != 0 && self.uid != 0 && unsafe { libc::setuid(0) } != -1 {
+            return Err(io::Error::from_raw_os_error(libc::EPERM));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::system::{Group, User};
+
+    use crate::system::capabilities::CapabilitySet;
+
+    use super::Identity;
+
+    fn user(uid: u32, gid: u32, groups: Vec<u32>) -> User {
+        User {
+            uid,
+            gid,
+            name: "ferris".to_string(),
+            gecos: String::new(),
+            home: "/home/ferris".into(),
+            shell: "/bin/sh".into(),
+            passwd: String::new(),
+            groups,
+        }
+    }
+
+    fn group(gid: u32) -> Group {
+        Group {
+            gid,
+            name: "crabs".to_string(),
+            passwd: String::new(),
+            members: vec![],
+        }
+    }
+
+    #[test]
+    fn uses_groups_of_target_user() {
+        let identity = Identity::new(&user(1000, 1000, vec![1000, 27]), &group(1000), None);
+
+        assert_eq!(identity.uid, 1000);
+        assert_eq!(identity.gid, 1000);
+        assert_eq!(identity.groups, [1000, 27]);
+    }
+
+    #[test]
+    fn adds_primary_group_to_supplementary_groups() {
+        let identity = Identity::new(&user(1000, 1000, vec![1000, 27]), &group(42), None);
+
+        assert_eq!(identity.gid, 42);
+        assert_eq!(identity.groups, [42, 1000, 27]);
+    }
+
+    #[test]
+    fn preserves_groups_when_asked() {
+        let identity = Identity::new(&user(0, 0, vec![0]), &group(0), Some(&[1000, 27]));
+
+        assert_eq!(identity.uid, 0);
+        assert_eq!(identity.groups, [0, 1000, 27]);
+    }
+
+    #[test]
+    fn keeps_real_user_when_asked() {
+        let identity = Identity::new(&user(0, 0, vec![0]), &group(0), None).keep_real_user(&user(
+            1000,
+            1000,
+            vec![1000],
+        ));
+
+        assert_eq!(identity.real_uid, 1000);
+        assert_eq!(identity.uid, 0);
+        assert_eq!(identity.gid, 0);
+    }
+
+    #[test]
+    fn runs_as_invoking_user_with_capabilities() {
+        let caps = CapabilitySet::from_names("cap_net_admin").ok();
+        let invoking_user = user(1000, 1000, vec![27]);
+
+        let identity = Identity::new(&user(0, 0, vec![0]), &group(0), None)
+            .with_capabilities(caps, &invoking_user);
+        assert_eq!(identity.real_uid, 1000);
+        assert_eq!(identity.uid, 1000);
+        assert_eq!(identity.gid, 1000);
+        assert_eq!(identity.groups, [1000, 27]);
+        assert_eq!(identity.capabilities, caps);
+
+        let identity = Identity::new(&user(1001, 1001, vec![1001]), &group(1001), None)
+            .with_capabilities(caps, &invoking_user);
+        assert_eq!(identity.uid, 1001);
+        assert_eq!(identity.gid, 1001);
+
+        let identity = Identity::new(&user(0, 0, vec![0]), &group(0), None)
+            .with_capabilities(None, &invoking_user);
+        assert_eq!(identity.uid, 0);
+    }
+}
@@ -63,6 +63,7 @@ impl UnixGroup for super::Group {
 
 #[cfg(test)]
 mod test {
+    use crate::system::nss::{use_database, Database};
     use crate::system::{Group, User};
 
     use super::*;
@@ -81,6 +82,11 @@ mod test {
 
     #[test]
     fn test_unix_user() {
+        use_database(
+            Database::default()
+                .with_user("root", 0)
+                .with_user("daemon", 1),
+        );
         let user = |name| User::from_name(name).unwrap().unwrap();
         test_user(user("root"), "root", 0);
         test_user(user("daemon"), "daemon", 1);
@@ -88,6 +94,11 @@ mod test {
 
     #[test]
     fn test_unix_group() {
+        use_database(
+            Database::default()
+                .with_user("root", 0)
+                .with_user("daemon", 1),
+        );
         let group = |name| Group::from_name(name).unwrap().unwrap();
         test_group(group("root"), "root", 0);
         test_group(group("daemon"), "daemon", 1);
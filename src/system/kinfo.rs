@@ -0,0 +1,212 @@
+//! Inspecting processes on systems without a `/proc` file system, which have `sysctl` (FreeBSD and
+//! OpenBSD) or `libproc` (macOS) instead.
+use std::{io, mem::size_of};
+
+use libc::{c_char, c_int};
+
+use crate::cutils::cerr;
+
+use super::interface::{DeviceId, ProcessId};
+
+/// What we need to know about a process.
+pub(super) struct ProcessInfo {
+    /// The controlling terminal, if there is one.
+    pub(super) tty: Option<DeviceId>,
+    /// The wall-clock time at which the process was started, in seconds and microseconds.
+    pub(super) start: (i64, i64),
+    /// The process ID of the parent.
+    pub(super) parent: ProcessId,
+    /// The name of the program that the process runs, as the kernel truncates it.
+    pub(super) name: String,
+}
+
+/// The terminal of a process without one.
+const NODEV: DeviceId = !0;
+
+fn tty(device: DeviceId) -> Option<DeviceId> {
+    (device != 0 && device != NODEV).then_some(device)
+}
+
+/// The name in a buffer that ends with a NUL byte, unless the name fills it.
+fn name(buffer: &[c_char]) -> String {
+    let bytes = buffer
+        .iter()
+        .take_while(|&&c| c != 0)
+        .map(|&c| c as u8)
+        .collect::<Vec<_>>();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Read the value of a `sysctl` into `buffer`, returning how many bytes were written.
+fn sysctl<T>(mib: &mut [c_int], buffer: *mut T, len: usize) -> io::Result<usize> {
+    let mut len = len;
+    // SAFETY: `buffer` is valid for `len` bytes, or null when asking for the size.
+    cerr(unsafe {
+        libc::sysctl(
+            mib.as_mut_ptr(),
+            mib.len() as _,
+            buffer.cast(),
+            &mut len,
+            std::ptr::null_mut(),
+            0,
+        )
+    })?;
+
+    Ok(len)
+}
+
+/// The wall-clock time at which the system was started, in seconds and microseconds.
+pub(super) fn boot_time() -> io::Result<(i64, i64)> {
+    // SAFETY: `timeval` only contains integers.
+    let mut time: libc::timeval = unsafe { std::mem::zeroed() };
+    let mut mib = [libc::CTL_KERN, libc::KERN_BOOTTIME];
+    sysctl(&mut mib, &mut time, size_of::<libc::timeval>())?;
+
+    Ok((time.tv_sec as i64, time.tv_usec as i64))
+}
+
+fn no_such_process() -> io::Error {
+    io::Error::from_raw_os_error(libc::ESRCH)
+}
+
+#[cfg(target_os = "freebsd")]
+pub(super) fn process_info(pid: ProcessId) -> io::Result<ProcessInfo> {
+    // SAFETY: `kinfo_proc` only contains integers, arrays and pointers.
+    let mut info: libc::kinfo_proc = unsafe { std::mem::zeroed() };
+    let mut mib = [libc::CTL_KERN, libc::KERN_PROC, libc::KERN_PROC_PID, pid];
+    if sysctl(&mut mib, &mut info, size_of::<libc::kinfo_proc>())? == 0 {
+        return Err(no_such_process());
+    }
+
+    Ok(ProcessInfo {
+        tty: tty(info.ki_tdev),
+        start: (info.ki_start.tv_sec as i64, info.ki_start.tv_usec as i64),
+        parent: info.ki_ppid,
+        name: name(&info.ki_comm),
+    })
+}
+
+#[cfg(target_os = "freebsd")]
+pub(super) fn group_members(pgrp: ProcessId) -> io::Result<Vec<ProcessId>> {
+    let mut mib = [libc::CTL_KERN, libc::KERN_PROC, libc::KERN_PROC_PGRP, pgrp];
+    loop {
+        let len = sysctl(&mut mib, std::ptr::null_mut::<libc::kinfo_proc>(), 0)?;
+        // leave room for processes that are started in the meantime
+        let mut procs =
+            Vec::<libc::kinfo_proc>::with_capacity(len / size_of::<libc::kinfo_proc>() + 4);
+        let capacity = procs.capacity() * size_of::<libc::kinfo_proc>();
+        match sysctl(&mut mib, procs.as_mut_ptr(), capacity) {
+            Ok(len) => {
+                // SAFETY: the kernel wrote this many entries.
+                unsafe { procs.set_len(len / size_of::<libc::kinfo_proc>()) };
+                return Ok(procs.iter().map(|info| info.ki_pid).collect());
+            }
+            Err(err) if err.raw_os_error() == Some(libc::ENOMEM) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// OpenBSD also wants to know the size of an entry and how many entries fit in the buffer.
+#[cfg(target_os = "openbsd")]
+fn kinfo_mib(op: c_int, arg: c_int, count: usize) -> [c_int; 6] {
+    [
+        libc::CTL_KERN,
+        libc::KERN_PROC,
+        op,
+        arg,
+        size_of::<libc::kinfo_proc>() as c_int,
+        count as c_int,
+    ]
+}
+
+#[cfg(target_os = "openbsd")]
+pub(super) fn process_info(pid: ProcessId) -> io::Result<ProcessInfo> {
+    // SAFETY: `kinfo_proc` only contains integers and arrays.
+    let mut info: libc::kinfo_proc = unsafe { std::mem::zeroed() };
+    let mut mib = kinfo_mib(libc::KERN_PROC_PID, pid, 1);
+    if sysctl(&mut mib, &mut info, size_of::<libc::kinfo_proc>())? == 0 {
+        return Err(no_such_process());
+    }
+
+    Ok(ProcessInfo {
+        tty: tty(info.p_tdev as DeviceId),
+        start: (info.p_ustart_sec as i64, info.p_ustart_usec.into()),
+        parent: info.p_ppid,
+        name: name(&info.p_comm),
+    })
+}
+
+#[cfg(target_os = "openbsd")]
+pub(super) fn group_members(pgrp: ProcessId) -> io::Result<Vec<ProcessId>> {
+    loop {
+        let mut mib = kinfo_mib(libc::KERN_PROC_PGRP, pgrp, 0);
+        let len = sysctl(&mut mib, std::ptr::null_mut::<libc::kinfo_proc>(), 0)?;
+        // leave room for processes that are started in the meantime
+        let count = len / size_of::<libc::kinfo_proc>() + 4;
+        let mut procs = Vec::<libc::kinfo_proc>::with_capacity(count);
+        let mut mib = kinfo_mib(libc::KERN_PROC_PGRP, pgrp, count);
+        match sysctl(
+            &mut mib,
+            procs.as_mut_ptr(),
+            count * size_of::<libc::kinfo_proc>(),
+        ) {
+            Ok(len) => {
+                // SAFETY: the kernel wrote this many entries.
+                unsafe { procs.set_len(len / size_of::<libc::kinfo_proc>()) };
+                return Ok(procs.iter().map(|info| info.p_pid).collect());
+            }
+            Err(err) if err.raw_os_error() == Some(libc::ENOMEM) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub(super) fn process_info(pid: ProcessId) -> io::Result<ProcessInfo> {
+    // SAFETY: `proc_bsdinfo` only contains integers and arrays.
+    let mut info: libc::proc_bsdinfo = unsafe { std::mem::zeroed() };
+    let size = size_of::<libc::proc_bsdinfo>() as c_int;
+    // SAFETY: `info` is valid for `size` bytes.
+    let written = cerr(unsafe {
+        libc::proc_pidinfo(
+            pid,
+            libc::PROC_PIDTBSDINFO,
+            0,
+            (&mut info as *mut libc::proc_bsdinfo).cast(),
+            size,
+        )
+    })?;
+    if written < size {
+        return Err(no_such_process());
+    }
+
+    Ok(ProcessInfo {
+        tty: tty(info.e_tdev as DeviceId),
+        start: (info.pbi_start_tvsec as i64, info.pbi_start_tvusec as i64),
+        parent: info.pbi_ppid as ProcessId,
+        name: name(&info.pbi_comm),
+    })
+}
+
+#[cfg(target_os = "macos")]
+pub(super) fn group_members(pgrp: ProcessId) -> io::Result<Vec<ProcessId>> {
+    let mut capacity = 64;
+    loop {
+        let mut pids = vec![0 as ProcessId; capacity];
+        // SAFETY: `pids` is valid for the size that is passed.
+        let count = cerr(unsafe {
+            libc::proc_listpgrppids(
+                pgrp,
+                pids.as_mut_ptr().cast(),
+                (capacity * size_of::<ProcessId>()) as c_int,
+            )
+        })? as usize;
+        // the buffer may have been too small if it is full
+        if count < capacity {
+            pids.truncate(count);
+            return Ok(pids);
+        }
+        capacity *= 2;
+    }
+}
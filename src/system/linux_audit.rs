@@ -0,0 +1,154 @@
+//! Sending records to the Linux audit subsystem, so that sudo events end up in the audit log
+//! (e.g. that of auditd) instead of only in syslog.
+//!
+//! Records are sent over an audit netlink socket, in the same format as libaudit uses; the kernel
+//! adds the process credentials (pid, uid, auid, session) to every record itself.
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::io;
+use std::mem::size_of;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::prelude::OsStrExt;
+
+use crate::cutils::cerr;
+
+/// The kind of record; the numbers are those of `linux/audit.h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditRecord {
+    /// The result of authenticating a user.
+    UserAuth = 1100,
+    /// A command that was run (or refused) on behalf of a user.
+    UserCmd = 1123,
+    /// A change to the configuration of the system, such as the sudoers file.
+    UsysConfig = 1127,
+}
+
+/// Encode a field value the way the audit tools expect: values that only consist of printable
+/// characters are quoted, all others are hex encoded.
+fn encode_value(value: &OsStr) -> String {
+    let bytes = value.as_bytes();
+    if bytes.iter().all(|&b| b.is_ascii_graphic() && b != b'"') {
+        format!("\"{}\"", String::from_utf8_lossy(bytes))
+    } else {
+        bytes.iter().fold(String::new(), |mut hex, b| {
+            let _ = write!(hex, "{b:02X}");
+            hex
+        })
+    }
+}
+
+/// Build the message of a record; `fields` are encoded, and the result is appended.
+fn format_message(fields: &[(&str, &OsStr)], terminal: Option<&OsStr>, success: bool) -> String {
+    let mut message = String::new();
+    for (name, value) in fields {
+        let _ = write!(message, "{name}={} ", encode_value(value));
+    }
+
+    let terminal = terminal.map(|tty| {
+        let tty = tty.as_bytes();
+        String::from_utf8_lossy(tty.strip_prefix(b"/dev/").unwrap_or(tty)).into_owned()
+    });
+    let _ = write!(
+        message,
+        "terminal={} res={}",
+        terminal.as_deref().unwrap_or("?"),
+        if success { "success" } else { "failed" }
+    );
+
+    message
+}
+
+/// Send a record to the kernel. If the kernel was built without audit support, or no audit
+/// daemon is listening, the record is silently dropped, just like libaudit does.
+pub fn send_record(
+    record: AuditRecord,
+    fields: &[(&str, &OsStr)],
+    terminal: Option<&OsStr>,
+    success: bool,
+) -> io::Result<()> {
+    let message = format_message(fields, terminal, success);
+
+    // SAFETY: `socket` does not access memory; a returned file descriptor is owned by us.
+    let socket = match cerr(unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+            libc::NETLINK_AUDIT,
+        )
+    }) {
+        Ok(fd) => unsafe { OwnedFd::from_raw_fd(fd) },
+        Err(err)
+            if matches!(
+                err.raw_os_error(),
+                Some(libc::EINVAL | libc::EPROTONOSUPPORT | libc::EAFNOSUPPORT)
+            ) =>
+        {
+            return Ok(())
+        }
+        Err(err) => return Err(err),
+    };
+
+    // a netlink header followed by the NUL terminated message, padded to a multiple of 4 bytes
+    let header_len = size_of::<libc::nlmsghdr>();
+    let len = header_len + message.len() + 1;
+    let mut buffer = vec![0u8; (len + 3) & !3];
+    // the fields of `nlmsghdr`: length, type, flags, sequence number and port id (0)
+    buffer[..4].copy_from_slice(&(len as u32).to_ne_bytes());
+    buffer[4..6].copy_from_slice(&(record as u16).to_ne_bytes());
+    buffer[6..8].copy_from_slice(&(libc::NLM_F_REQUEST as u16).to_ne_bytes());
+    buffer[8..12].copy_from_slice(&1u32.to_ne_bytes());
+    buffer[header_len..header_len + message.len()].copy_from_slice(message.as_bytes());
+
+    // the kernel is the destination
+    // SAFETY: `sockaddr_nl` is valid when zeroed.
+    let mut address: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+    address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+
+    // SAFETY: both the buffer and the address are valid for the lengths that are passed.
+    let result = cerr(unsafe {
+        libc::sendto(
+            socket.as_raw_fd(),
+            buffer.as_ptr().cast(),
+            buffer.len(),
+            0,
+            (&address as *const libc::sockaddr_nl).cast(),
+            size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        )
+    });
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(err) if err.raw_os_error() == Some(libc::ECONNREFUSED) => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use super::{encode_value, format_message};
+
+    #[test]
+    fn encodes_values() {
+        assert_eq!(encode_value(OsStr::new("/usr/bin/id")), "\"/usr/bin/id\"");
+        assert_eq!(encode_value(OsStr::new("ls -l")), "6C73202D6C");
+        assert_eq!(encode_value(OsStr::new("a\"b")), "612262");
+    }
+
+    #[test]
+    fn formats_messages() {
+        assert_eq!(
+            format_message(
+                &[("cwd", OsStr::new("/root")), ("cmd", OsStr::new("id -u"))],
+                Some(OsStr::new("/dev/pts/1")),
+                true
+            ),
+            "cwd=\"/root\" cmd=6964202D75 terminal=pts/1 res=success"
+        );
+        assert_eq!(
+            format_message(&[("acct", OsStr::new("ferris"))], None, false),
+            "acct=\"ferris\" terminal=? res=failed"
+        );
+    }
+}
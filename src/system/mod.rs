@@ -3,17 +3,18 @@ use std::{
     collections::BTreeSet,
     ffi::{c_uint, CStr, CString},
     io,
-    mem::MaybeUninit,
     os::{
-        fd::AsRawFd,
+        fd::{AsRawFd, FromRawFd, OwnedFd},
         unix::{self, prelude::OsStrExt},
     },
     path::{Path, PathBuf},
-    str::FromStr,
 };
 
 use crate::cutils::*;
-pub use audit::secure_open;
+pub use audit::{
+    open_no_symlinks, secure_open, secure_open_cookie_file, secure_open_for_append,
+    secure_open_no_symlinks,
+};
 use interface::{DeviceId, GroupId, ProcessId, UserId};
 pub use libc::PATH_MAX;
 use libc::STDERR_FILENO;
@@ -22,15 +23,44 @@ use time::SystemTime;
 use self::signal::SignalNumber;
 
 mod audit;
+
+#[cfg(all(feature = "bsm-audit", any(target_os = "macos", target_os = "freebsd")))]
+pub mod bsm_audit;
 // generalized traits for when we want to hide implementations
 pub mod interface;
 
+pub mod capabilities;
+
+pub mod cgroup;
+
+pub mod dns;
+
 pub mod file;
 
+pub mod identity;
+
+pub mod nss;
+
+mod nss_cache;
+
+#[cfg(not(target_os = "linux"))]
+mod kinfo;
+
+#[cfg(feature = "linux-audit")]
+pub mod linux_audit;
+
+pub mod poll;
+
+pub mod sandbox;
+
 pub mod time;
 
+pub mod timer;
+
 pub mod timestamp;
 
+pub mod userns;
+
 pub mod signal;
 
 pub mod term;
@@ -68,8 +98,10 @@ impl FileCloser {
 
     /// Close every file descriptor that is not one of the IO streams or one of the file
     /// descriptors passed via [`FileCloser::except`].
-    pub(crate) fn close_the_universe(self) -> io::Result<()> {
-        let mut fds = self.fds.into_iter();
+    ///
+    /// This neither allocates nor frees memory, so it can be used after `fork`.
+    pub(crate) fn close_the_universe(&self) -> io::Result<()> {
+        let mut fds = self.fds.iter().copied();
 
         let Some(mut curr_fd) = fds.next() else {
             return close_range(STDERR_FILENO as c_uint + 1, c_uint::MAX);
@@ -97,6 +129,7 @@ impl FileCloser {
     }
 }
 
+#[cfg(target_os = "linux")]
 fn close_range(min_fd: c_uint, max_fd: c_uint) -> io::Result<()> {
     if min_fd <= max_fd {
         cerr(unsafe { libc::syscall(libc::SYS_close_range, min_fd, max_fd, 0 as c_uint) })?;
@@ -105,6 +138,22 @@ fn close_range(min_fd: c_uint, max_fd: c_uint) -> io::Result<()> {
     Ok(())
 }
 
+/// Without a `close_range` system call, every file descriptor up to the limit of the process is
+/// closed, whether it is open or not.
+#[cfg(not(target_os = "linux"))]
+fn close_range(min_fd: c_uint, max_fd: c_uint) -> io::Result<()> {
+    let open_max = sysconf(libc::_SC_OPEN_MAX).unwrap_or(1024);
+    let max_fd = max_fd.min(c_uint::try_from(open_max).unwrap_or(c_uint::MAX));
+    for fd in min_fd..=max_fd {
+        match cerr(unsafe { libc::close(fd as libc::c_int) }) {
+            Err(err) if err.raw_os_error() != Some(libc::EBADF) => return Err(err),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
 pub(crate) enum ForkResult {
     // Parent process branch with the child process' PID.
     Parent(ProcessId),
@@ -112,7 +161,15 @@ pub(crate) enum ForkResult {
     Child,
 }
 
-unsafe fn inner_fork() -> io::Result<ForkResult> {
+/// Create a new process.
+///
+/// # Safety
+///
+/// In a multithreaded program, only async-signal-safe functions are guaranteed to work in the
+/// child process until a call to `execve` or a similar function is done: another thread may have
+/// held a lock (e.g. of the memory allocator) at the time of the fork, which is never released in
+/// the child.
+pub(crate) unsafe fn fork() -> io::Result<ForkResult> {
     let pid = cerr(unsafe { libc::fork() })?;
     if pid == 0 {
         Ok(ForkResult::Child)
@@ -121,27 +178,48 @@ unsafe fn inner_fork() -> io::Result<ForkResult> {
     }
 }
 
-#[cfg(target_os = "linux")]
-/// Create a new process.
-pub(crate) fn fork() -> io::Result<ForkResult> {
-    // SAFETY: `fork` is implemented using `clone` in linux so we don't need to worry about signal
-    // safety.
-    unsafe { inner_fork() }
+pub fn setsid() -> io::Result<ProcessId> {
+    cerr(unsafe { libc::setsid() })
 }
 
-#[cfg(not(target_os = "linux"))]
-/// Create a new process.
-///
-/// # Safety
-///
-/// In a multithreaded program, only async-signal-safe functions are guaranteed to work in the
-/// child process until a call to `execve` or a similar function is done.
-pub(crate) unsafe fn fork() -> io::Result<ForkResult> {
-    inner_fork()
+/// Create a pipe, returning its read and write ends.
+pub fn pipe() -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    // SAFETY: `fds` has room for the two file descriptors that are written to it
+    cerr(unsafe { libc::pipe(fds.as_mut_ptr()) })?;
+    // SAFETY: both file descriptors were just created, and are not owned by anything else
+    Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
 }
 
-pub fn setsid() -> io::Result<ProcessId> {
-    cerr(unsafe { libc::setsid() })
+/// Random bytes from the kernel, for keys and unique IDs.
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+pub fn random_bytes<const N: usize>() -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    let mut filled = 0;
+    while filled < N {
+        // SAFETY: the pointer and length describe the part of `bytes` that is not filled yet
+        let read =
+            cerr(unsafe { libc::getrandom(bytes[filled..].as_mut_ptr().cast(), N - filled, 0) });
+        match read {
+            Ok(read) => filled += read as usize,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Random bytes from the kernel, for keys and unique IDs; `getrandom` is not available
+/// everywhere, but `/dev/urandom` is.
+#[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+pub fn random_bytes<const N: usize>() -> io::Result<[u8; N]> {
+    use std::io::Read;
+
+    let mut bytes = [0; N];
+    std::fs::File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+
+    Ok(bytes)
 }
 
 pub fn hostname() -> String {
@@ -180,36 +258,6 @@ pub fn syslog(priority: libc::c_int, facility: libc::c_int, message: &str) {
     }
 }
 
-/// set target user and groups (uid, gid, additional groups) for a command
-pub fn set_target_user(
-    cmd: &mut std::process::Command,
-    mut target_user: User,
-    target_group: Group,
-) {
-    use std::os::unix::process::CommandExt;
-
-    // add target group to list of additional groups if not present
-    if !target_user.groups.contains(&target_group.gid) {
-        target_user.groups.push(target_group.gid);
-    }
-
-    // we need to do this in a `pre_exec` call since the `groups` method in `process::Command` is unstable
-    // see https://github.com/rust-lang/rust/blob/a01b4cc9f375f1b95fa8195daeea938d3d9c4c34/library/std/src/sys/unix/process/process_unix.rs#L329-L352
-    // for the std implementation of the libc calls to `setgroups`, `setgid` and `setuid`
-    unsafe {
-        cmd.pre_exec(move || {
-            cerr(libc::setgroups(
-                target_user.groups.len(),
-                target_user.groups.as_ptr(),
-            ))?;
-            cerr(libc::setgid(target_group.gid))?;
-            cerr(libc::setuid(target_user.uid))?;
-
-            Ok(())
-        });
-    }
-}
-
 /// Send a signal to a process with the specified ID.
 pub fn kill(pid: ProcessId, signal: SignalNumber) -> io::Result<()> {
     // SAFETY: This function cannot cause UB even if `pid` is not a valid process ID or if
@@ -240,16 +288,56 @@ pub fn setpgid(pid: ProcessId, pgid: ProcessId) -> io::Result<()> {
     cerr(unsafe { libc::setpgid(pid, pgid) }).map(|_| ())
 }
 
-pub fn chown<S: AsRef<CStr>>(
-    path: &S,
-    uid: impl Into<Option<UserId>>,
-    gid: impl Into<Option<GroupId>>,
-) -> io::Result<()> {
-    let path = path.as_ref().as_ptr();
-    let uid = uid.into().unwrap_or(UserId::MAX);
-    let gid = gid.into().unwrap_or(GroupId::MAX);
+/// Get the supplementary groups of the current process.
+pub fn getgroups() -> io::Result<Vec<GroupId>> {
+    // SAFETY: with a size of 0, only the number of groups is returned
+    let len = cerr(unsafe { libc::getgroups(0, std::ptr::null_mut()) })?;
+    let mut groups = vec![0; len as usize];
+    // SAFETY: `groups` has room for `len` elements
+    let len = cerr(unsafe { libc::getgroups(len, groups.as_mut_ptr()) })?;
+    groups.truncate(len as usize);
 
-    cerr(unsafe { libc::chown(path, uid, gid) }).map(|_| ())
+    Ok(groups)
+}
+
+/// The maximum number of supplementary groups a process can have.
+pub fn max_groups() -> usize {
+    // the limit of Linux since version 2.6.4
+    const NGROUPS_MAX: libc::c_long = 65536;
+
+    sysconf(libc::_SC_NGROUPS_MAX).unwrap_or(NGROUPS_MAX) as usize
+}
+
+/// Prevent the current process from dumping core, so that e.g. password hashes or the contents
+/// of sudoers do not end up in a core file. This is reset when a command is executed.
+#[cfg(target_os = "linux")]
+pub fn disable_core_dumps() -> io::Result<()> {
+    // SAFETY: `PR_SET_DUMPABLE` only takes a boolean as its argument.
+    cerr(unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) }).map(|_| ())
+}
+
+#[cfg(target_os = "freebsd")]
+pub fn disable_core_dumps() -> io::Result<()> {
+    // this also prevents core dumps, and is reset when a command is executed, like on Linux
+    let mut data: libc::c_int = libc::PROC_TRACE_CTL_DISABLE;
+    // SAFETY: `PROC_TRACE_CTL` reads an `int` from `data`.
+    cerr(unsafe {
+        libc::procctl(
+            libc::P_PID,
+            0,
+            libc::PROC_TRACE_CTL,
+            (&mut data as *mut libc::c_int).cast(),
+        )
+    })
+    .map(|_| ())
+}
+
+/// A process that changed its credentials, such as a setuid binary, never dumps core on these
+/// platforms, unless this was enabled system wide with `kern.sugid_coredump` (macOS) or
+/// `kern.nosuidcoredump` (OpenBSD).
+#[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+pub fn disable_core_dumps() -> io::Result<()> {
+    Ok(())
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -265,6 +353,22 @@ pub struct User {
 }
 
 impl User {
+    /// A user that is not in the user database, as `sudo -u #uid` runs commands as with
+    /// `Defaults runas_allow_unknown_id`; like ogsudo, its name is `#uid`, it has `gid` as its
+    /// group, `/` as its home directory and `/bin/sh` as its shell.
+    pub fn unknown(uid: UserId, gid: GroupId) -> User {
+        User {
+            uid,
+            gid,
+            name: format!("#{uid}"),
+            gecos: String::new(),
+            home: PathBuf::from("/"),
+            shell: PathBuf::from("/bin/sh"),
+            passwd: String::new(),
+            groups: vec![gid],
+        }
+    }
+
     /// # Safety
     /// This function expects `pwd` to be a result from a succesful call to `getpwXXX_r`.
     /// (It can cause UB if any of `pwd`'s pointed-to strings does not have a null-terminator.)
@@ -277,8 +381,9 @@ impl User {
             let result = unsafe {
                 libc::getgrouplist(
                     pwd.pw_name,
-                    pwd.pw_gid,
-                    groups_buffer.as_mut_ptr(),
+                    // macOS uses `c_int` for the group ids here
+                    pwd.pw_gid as _,
+                    groups_buffer.as_mut_ptr().cast(),
                     &mut buf_len,
                 )
             };
@@ -309,25 +414,7 @@ impl User {
     }
 
     pub fn from_uid(uid: UserId) -> std::io::Result<Option<User>> {
-        let max_pw_size = sysconf(libc::_SC_GETPW_R_SIZE_MAX).unwrap_or(16_384);
-        let mut buf = vec![0; max_pw_size as usize];
-        let mut pwd = MaybeUninit::uninit();
-        let mut pwd_ptr = std::ptr::null_mut();
-        cerr(unsafe {
-            libc::getpwuid_r(
-                uid,
-                pwd.as_mut_ptr(),
-                buf.as_mut_ptr(),
-                buf.len(),
-                &mut pwd_ptr,
-            )
-        })?;
-        if pwd_ptr.is_null() {
-            Ok(None)
-        } else {
-            let pwd = unsafe { pwd.assume_init() };
-            Ok(Some(unsafe { Self::from_libc(&pwd) }))
-        }
+        nss_cache::user_by_uid(uid, || nss::with_system(|system| system.user_by_uid(uid)))
     }
 
     pub fn effective_uid() -> UserId {
@@ -347,26 +434,9 @@ impl User {
     }
 
     pub fn from_name(name: &str) -> std::io::Result<Option<User>> {
-        let max_pw_size = sysconf(libc::_SC_GETPW_R_SIZE_MAX).unwrap_or(16_384);
-        let mut buf = vec![0; max_pw_size as usize];
-        let mut pwd = MaybeUninit::uninit();
-        let mut pwd_ptr = std::ptr::null_mut();
-        let name_c = CString::new(name).expect("String contained null bytes");
-        cerr(unsafe {
-            libc::getpwnam_r(
-                name_c.as_ptr(),
-                pwd.as_mut_ptr(),
-                buf.as_mut_ptr(),
-                buf.len(),
-                &mut pwd_ptr,
-            )
-        })?;
-        if pwd_ptr.is_null() {
-            Ok(None)
-        } else {
-            let pwd = unsafe { pwd.assume_init() };
-            Ok(Some(unsafe { Self::from_libc(&pwd) }))
-        }
+        nss_cache::user_by_name(name, || {
+            nss::with_system(|system| system.user_by_name(name))
+        })
     }
 }
 
@@ -380,6 +450,16 @@ pub struct Group {
 }
 
 impl Group {
+    /// A group that is not in the group database, named `#gid`, see [User::unknown].
+    pub fn unknown(gid: GroupId) -> Group {
+        Group {
+            gid,
+            name: format!("#{gid}"),
+            passwd: String::new(),
+            members: Vec::new(),
+        }
+    }
+
     /// # Safety
     /// This function expects `grp` to be a result from a succesful call to `getgrXXX_r`.
     /// In particular the grp.gr_mem pointer is assumed to be non-null, and pointing to a
@@ -407,48 +487,13 @@ impl Group {
     }
 
     pub fn from_gid(gid: GroupId) -> std::io::Result<Option<Group>> {
-        let max_gr_size = sysconf(libc::_SC_GETGR_R_SIZE_MAX).unwrap_or(16_384);
-        let mut buf = vec![0; max_gr_size as usize];
-        let mut grp = MaybeUninit::uninit();
-        let mut grp_ptr = std::ptr::null_mut();
-        cerr(unsafe {
-            libc::getgrgid_r(
-                gid,
-                grp.as_mut_ptr(),
-                buf.as_mut_ptr(),
-                buf.len(),
-                &mut grp_ptr,
-            )
-        })?;
-        if grp_ptr.is_null() {
-            Ok(None)
-        } else {
-            let grp = unsafe { grp.assume_init() };
-            Ok(Some(unsafe { Group::from_libc(&grp) }))
-        }
+        nss_cache::group_by_gid(gid, || nss::with_system(|system| system.group_by_gid(gid)))
     }
 
     pub fn from_name(name: &str) -> std::io::Result<Option<Group>> {
-        let max_gr_size = sysconf(libc::_SC_GETGR_R_SIZE_MAX).unwrap_or(16_384);
-        let mut buf = vec![0; max_gr_size as usize];
-        let mut grp = MaybeUninit::uninit();
-        let mut grp_ptr = std::ptr::null_mut();
-        let name_c = CString::new(name).expect("String contained null bytes");
-        cerr(unsafe {
-            libc::getgrnam_r(
-                name_c.as_ptr(),
-                grp.as_mut_ptr(),
-                buf.as_mut_ptr(),
-                buf.len(),
-                &mut grp_ptr,
-            )
-        })?;
-        if grp_ptr.is_null() {
-            Ok(None)
-        } else {
-            let grp = unsafe { grp.assume_init() };
-            Ok(Some(unsafe { Group::from_libc(&grp) }))
-        }
+        nss_cache::group_by_name(name, || {
+            nss::with_system(|system| system.group_by_name(name))
+        })
     }
 }
 
@@ -458,12 +503,21 @@ pub enum WithProcess {
 }
 
 impl WithProcess {
+    #[cfg(target_os = "linux")]
     fn to_proc_string(&self) -> String {
         match self {
             WithProcess::Current => "self".into(),
             WithProcess::Other(pid) => pid.to_string(),
         }
     }
+
+    #[cfg(not(target_os = "linux"))]
+    fn pid(&self) -> ProcessId {
+        match self {
+            WithProcess::Current => Process::process_id(),
+            WithProcess::Other(pid) => *pid,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -525,8 +579,29 @@ impl Process {
         unsafe { libc::getsid(0) }
     }
 
+    /// The user that logged in to the session of the current process, as recorded by the audit
+    /// subsystem; this does not change when the process switches users.
+    #[cfg(target_os = "linux")]
+    pub fn login_uid() -> Option<UserId> {
+        let loginuid = std::fs::read_to_string("/proc/self/loginuid").ok()?;
+        // an unset login uid is shown as -1
+        loginuid
+            .trim()
+            .parse()
+            .ok()
+            .filter(|&uid| uid != UserId::MAX)
+    }
+
+    /// The user that logged in to the session of the current process, which is only known on
+    /// Linux.
+    #[cfg(not(target_os = "linux"))]
+    pub fn login_uid() -> Option<UserId> {
+        None
+    }
+
     /// Returns the device identifier of the TTY device that is currently
     /// attached to the given process
+    #[cfg(target_os = "linux")]
     pub fn tty_device_id(pid: WithProcess) -> std::io::Result<Option<DeviceId>> {
         // device id of tty is displayed as a signed integer of 32 bits
         let data: i32 = read_proc_stat(pid, 6)?;
@@ -542,7 +617,74 @@ impl Process {
         }
     }
 
+    /// Returns the device identifier of the TTY device that is currently
+    /// attached to the given process
+    #[cfg(not(target_os = "linux"))]
+    pub fn tty_device_id(pid: WithProcess) -> std::io::Result<Option<DeviceId>> {
+        Ok(kinfo::process_info(pid.pid())?.tty)
+    }
+
+    /// Return the process ID of the parent of a process, which is 0 for the first process.
+    #[cfg(target_os = "linux")]
+    pub fn parent_of(pid: WithProcess) -> io::Result<ProcessId> {
+        read_proc_stat(pid, 3)
+    }
+
+    /// Return the process ID of the parent of a process, which is 0 for the first process.
+    #[cfg(not(target_os = "linux"))]
+    pub fn parent_of(pid: WithProcess) -> io::Result<ProcessId> {
+        Ok(kinfo::process_info(pid.pid())?.parent)
+    }
+
+    /// Return the name of the program that a process runs, which the kernel truncates to 15
+    /// bytes or so.
+    #[cfg(target_os = "linux")]
+    pub fn name_of(pid: WithProcess) -> io::Result<String> {
+        let path = PathBuf::from_iter(&["/proc", &pid.to_proc_string(), "comm"]);
+        let name = std::fs::read(path)?;
+        Ok(String::from_utf8_lossy(name.strip_suffix(b"\n").unwrap_or(&name)).into_owned())
+    }
+
+    /// Return the name of the program that a process runs, which the kernel truncates to 15
+    /// bytes or so.
+    #[cfg(not(target_os = "linux"))]
+    pub fn name_of(pid: WithProcess) -> io::Result<String> {
+        Ok(kinfo::process_info(pid.pid())?.name)
+    }
+
+    /// Return the IDs of the processes in the process group `pgrp`.
+    #[cfg(target_os = "linux")]
+    pub fn group_members(pgrp: ProcessId) -> io::Result<Vec<ProcessId>> {
+        let mut pids = Vec::new();
+        for entry in std::fs::read_dir("/proc")? {
+            let Some(pid) = entry?
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse::<ProcessId>().ok())
+            else {
+                continue;
+            };
+            // the process may have exited since the directory was read
+            if read_proc_stat::<ProcessId>(WithProcess::Other(pid), 4).is_ok_and(|id| id == pgrp) {
+                pids.push(pid);
+            }
+        }
+        pids.sort_unstable();
+
+        Ok(pids)
+    }
+
+    /// Return the IDs of the processes in the process group `pgrp`.
+    #[cfg(not(target_os = "linux"))]
+    pub fn group_members(pgrp: ProcessId) -> io::Result<Vec<ProcessId>> {
+        let mut pids = kinfo::group_members(pgrp)?;
+        pids.sort_unstable();
+
+        Ok(pids)
+    }
+
     /// Get the process starting time of a specific process
+    #[cfg(target_os = "linux")]
     pub fn starting_time(pid: WithProcess) -> io::Result<SystemTime> {
         let process_start: u64 = read_proc_stat(pid, 21)?;
 
@@ -561,9 +703,72 @@ impl Process {
             ((process_start % ticks_per_second) * (1_000_000_000 / ticks_per_second)) as i64,
         ))
     }
+
+    /// Get the process starting time of a specific process, relative to the start of the system
+    /// like [SystemTime::now].
+    #[cfg(not(target_os = "linux"))]
+    pub fn starting_time(pid: WithProcess) -> io::Result<SystemTime> {
+        let (secs, usecs) = kinfo::process_info(pid.pid())?.start;
+        let (boot_secs, boot_usecs) = kinfo::boot_time()?;
+
+        Ok(SystemTime::new(
+            secs - boot_secs,
+            (usecs - boot_usecs) * 1000,
+        ))
+    }
 }
 
-fn read_proc_stat<T: FromStr>(pid: WithProcess, field_idx: isize) -> io::Result<T> {
+/// A process that sudo started, along with the time at which it started, so that a signal that is
+/// meant for it is not sent to another process that got the same ID after it exited and was
+/// waited for, e.g. the command, which the monitor waits for while the parent still refers to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChildProcess {
+    pub pid: ProcessId,
+    start: Option<SystemTime>,
+}
+
+impl ChildProcess {
+    /// Record the process with ID `pid`, which must not have been waited for yet.
+    pub fn new(pid: ProcessId) -> Self {
+        Self {
+            pid,
+            start: Process::starting_time(WithProcess::Other(pid)).ok(),
+        }
+    }
+
+    /// Whether the ID of the process now refers to a process that started at another time. If no
+    /// process has the ID, it was not reused; the process group of a process keeps its ID until
+    /// the last process in it exits.
+    pub fn is_replaced(&self) -> bool {
+        match (
+            self.start,
+            Process::starting_time(WithProcess::Other(self.pid)),
+        ) {
+            (Some(start), Ok(now)) => start != now,
+            _ => false,
+        }
+    }
+
+    /// Send `signal` to the process, unless its ID now refers to another process.
+    pub fn kill(&self, signal: SignalNumber) -> io::Result<()> {
+        if self.is_replaced() {
+            return Err(io::Error::from_raw_os_error(libc::ESRCH));
+        }
+        kill(self.pid, signal)
+    }
+
+    /// Send `signal` to the process group that the process leads, unless its ID now refers to
+    /// another process.
+    pub fn killpg(&self, signal: SignalNumber) -> io::Result<()> {
+        if self.is_replaced() {
+            return Err(io::Error::from_raw_os_error(libc::ESRCH));
+        }
+        killpg(self.pid, signal)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn read_proc_stat<T: std::str::FromStr>(pid: WithProcess, field_idx: isize) -> io::Result<T> {
     // read from a specific pid file, or use `self` to refer to our own process
     let pidref = pid.to_proc_string();
 
@@ -639,7 +844,9 @@ mod tests {
     use libc::SIGKILL;
 
     use super::{
-        fork, getpgrp, setpgid,
+        fork, getpgrp,
+        nss::{use_database, Database},
+        setpgid,
         wait::{Wait, WaitOptions},
         ForkResult, Group, User, WithProcess,
     };
@@ -662,6 +869,11 @@ mod tests {
 
     #[test]
     fn test_get_user_and_group_by_id() {
+        use_database(
+            Database::default()
+                .with_user("root", 0)
+                .with_user("daemon", 1),
+        );
         let fixed_users = &[(0, "root"), (1, "daemon")];
         for &(id, name) in fixed_users {
             let root = User::from_uid(id).unwrap().unwrap();
@@ -728,6 +940,14 @@ mod tests {
         assert!(time < now);
     }
 
+    #[test]
+    fn finds_the_parent_process() {
+        let parent = super::Process::parent_of(WithProcess::Current).unwrap();
+        assert_eq!(Some(parent), super::Process::parent_id());
+        let name = super::Process::name_of(WithProcess::Current).unwrap();
+        assert!(!name.is_empty() && !name.ends_with('\n'));
+    }
+
     #[test]
     fn pgid_test() {
         use super::{getpgid, setpgid};
@@ -736,7 +956,7 @@ mod tests {
         assert_eq!(getpgid(0).unwrap(), pgrp);
         assert_eq!(getpgid(std::process::id() as i32).unwrap(), pgrp);
 
-        match super::fork().unwrap() {
+        match unsafe { super::fork() }.unwrap() {
             ForkResult::Child => {
                 // wait for the parent.
                 std::thread::sleep(std::time::Duration::from_secs(1))
@@ -752,6 +972,27 @@ mod tests {
         }
     }
     #[test]
+    fn lists_process_group_members() {
+        use std::os::unix::process::CommandExt;
+        use std::process::{Command, Stdio};
+
+        let mut child = Command::new("/bin/sleep")
+            .arg("1")
+            .process_group(0)
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        let pgrp = child.id() as i32;
+        assert_eq!(super::Process::group_members(pgrp).unwrap(), [pgrp]);
+        assert!(super::Process::group_members(getpgrp())
+            .unwrap()
+            .contains(&(std::process::id() as i32)));
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(super::Process::group_members(pgrp).unwrap().is_empty());
+    }
+    #[test]
     fn kill_test() {
         let mut child = std::process::Command::new("/bin/sleep")
             .arg("1")
@@ -765,13 +1006,13 @@ mod tests {
         // Create a socket so the children write to it if they aren't terminated by `killpg`.
         let (mut rx, mut tx) = UnixStream::pair().unwrap();
 
-        let ForkResult::Parent(pid1) = fork().unwrap() else {
+        let ForkResult::Parent(pid1) = unsafe { fork() }.unwrap() else {
             std::thread::sleep(std::time::Duration::from_secs(1));
             tx.write_all(&[42]).unwrap();
             exit(0);
         };
 
-        let ForkResult::Parent(pid2) = fork().unwrap() else {
+        let ForkResult::Parent(pid2) = unsafe { fork() }.unwrap() else {
             std::thread::sleep(std::time::Duration::from_secs(1));
             tx.write_all(&[42]).unwrap();
             exit(0);
@@ -799,7 +1040,7 @@ mod tests {
 
     #[test]
     fn close_the_universe() {
-        let ForkResult::Parent(child_pid) = fork().unwrap() else {
+        let ForkResult::Parent(child_pid) = unsafe { fork() }.unwrap() else {
             let should_close =
                 std::fs::File::open(std::env::temp_dir().join("should_close.txt")).unwrap();
             assert!(!is_closed(&should_close));
@@ -830,7 +1071,7 @@ mod tests {
 
     #[test]
     fn except_stdio_is_fine() {
-        let ForkResult::Parent(child_pid) = fork().unwrap() else {
+        let ForkResult::Parent(child_pid) = unsafe { fork() }.unwrap() else {
             let mut closer = super::FileCloser::new();
 
             closer.except(&io::stdin());
@@ -849,4 +1090,29 @@ mod tests {
         let (_, status) = child_pid.wait(WaitOptions::new()).unwrap();
         assert_eq!(status.exit_status(), Some(0));
     }
+
+    #[test]
+    fn child_process_is_not_replaced() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+        let process = super::ChildProcess::new(child.id() as super::ProcessId);
+        assert!(!process.is_replaced());
+        process.kill(0).unwrap();
+
+        // a process with the same ID that started at another time
+        let other = super::ChildProcess {
+            start: Some(super::SystemTime::new(0, 0)),
+            ..process
+        };
+        assert!(other.is_replaced());
+        assert!(other.kill(0).is_err());
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        // a process that is gone is not replaced, but cannot be signaled either
+        assert!(!process.is_replaced());
+        assert!(process.kill(0).is_err());
+    }
 }
@@ -0,0 +1,241 @@
+//! The user and group databases of the system, which are read with the reentrant `getpw*_r` and
+//! `getgr*_r` functions of libc.
+//!
+//! Lookups go through the [System] trait, so that unit tests can replace these databases with an
+//! in-memory [Database] for the thread they run in, instead of depending on the users and groups
+//! of the machine they happen to run on.
+use std::ffi::{c_char, c_int, CString};
+use std::io;
+use std::mem::MaybeUninit;
+use std::ptr;
+
+use crate::cutils::sysconf;
+
+use super::interface::{GroupId, UserId};
+use super::{Group, User};
+
+/// A source of users and groups.
+pub trait System {
+    fn user_by_uid(&self, uid: UserId) -> io::Result<Option<User>>;
+    fn user_by_name(&self, name: &str) -> io::Result<Option<User>>;
+    fn group_by_gid(&self, gid: GroupId) -> io::Result<Option<Group>>;
+    fn group_by_name(&self, name: &str) -> io::Result<Option<Group>>;
+}
+
+/// The databases of the system, as configured in nsswitch.conf.
+pub struct Libc;
+
+/// The largest buffer that is tried when an entry does not fit in a smaller one.
+const MAX_BUFFER_SIZE: usize = 1 << 20;
+
+/// Call one of the reentrant lookup functions, which fills in `entry` and the strings it points
+/// to in a buffer; the buffer is grown for as long as it is too small.
+///
+/// # Safety
+///
+/// `convert` is given the entry that `call` filled in, while the buffer is still alive.
+unsafe fn lookup<E, T>(
+    size_hint: c_int,
+    call: impl Fn(*mut E, &mut [c_char], *mut *mut E) -> c_int,
+    convert: impl FnOnce(&E) -> T,
+) -> io::Result<Option<T>> {
+    let mut size = sysconf(size_hint)
+        .and_then(|size| usize::try_from(size).ok())
+        .filter(|&size| size > 0)
+        .unwrap_or(16_384);
+
+    loop {
+        let mut buffer = vec![0; size];
+        let mut entry = MaybeUninit::<E>::uninit();
+        let mut result = ptr::null_mut();
+        match call(entry.as_mut_ptr(), &mut buffer, &mut result) {
+            0 if result.is_null() => return Ok(None),
+            // SAFETY: the entry was filled in, and only points into `buffer`
+            0 => return Ok(Some(convert(unsafe { entry.assume_init_ref() }))),
+            libc::ERANGE if size < MAX_BUFFER_SIZE => size *= 2,
+            // some systems report an entry that does not exist as one of these errors
+            libc::ENOENT | libc::ESRCH | libc::EBADF | libc::EPERM => return Ok(None),
+            errno => return Err(io::Error::from_raw_os_error(errno)),
+        }
+    }
+}
+
+fn c_name(name: &str) -> io::Result<CString> {
+    CString::new(name).map_err(|_| io::ErrorKind::InvalidInput.into())
+}
+
+impl System for Libc {
+    fn user_by_uid(&self, uid: UserId) -> io::Result<Option<User>> {
+        // SAFETY: `getpwuid_r` fills in the entry with strings in the buffer of the given length
+        unsafe {
+            lookup(
+                libc::_SC_GETPW_R_SIZE_MAX,
+                |pwd, buf, result| libc::getpwuid_r(uid, pwd, buf.as_mut_ptr(), buf.len(), result),
+                |pwd| User::from_libc(pwd),
+            )
+        }
+    }
+
+    fn user_by_name(&self, name: &str) -> io::Result<Option<User>> {
+        let name = c_name(name)?;
+        // SAFETY: `getpwnam_r` fills in the entry with strings in the buffer of the given length
+        unsafe {
+            lookup(
+                libc::_SC_GETPW_R_SIZE_MAX,
+                |pwd, buf, result| {
+                    libc::getpwnam_r(name.as_ptr(), pwd, buf.as_mut_ptr(), buf.len(), result)
+                },
+                |pwd| User::from_libc(pwd),
+            )
+        }
+    }
+
+    fn group_by_gid(&self, gid: GroupId) -> io::Result<Option<Group>> {
+        // SAFETY: `getgrgid_r` fills in the entry with strings in the buffer of the given length
+        unsafe {
+            lookup(
+                libc::_SC_GETGR_R_SIZE_MAX,
+                |grp, buf, result| libc::getgrgid_r(gid, grp, buf.as_mut_ptr(), buf.len(), result),
+                |grp| Group::from_libc(grp),
+            )
+        }
+    }
+
+    fn group_by_name(&self, name: &str) -> io::Result<Option<Group>> {
+        let name = c_name(name)?;
+        // SAFETY: `getgrnam_r` fills in the entry with strings in the buffer of the given length
+        unsafe {
+            lookup(
+                libc::_SC_GETGR_R_SIZE_MAX,
+                |grp, buf, result| {
+                    libc::getgrnam_r(name.as_ptr(), grp, buf.as_mut_ptr(), buf.len(), result)
+                },
+                |grp| Group::from_libc(grp),
+            )
+        }
+    }
+}
+
+/// Use the databases of the system, or the ones that a test installed with [use_database].
+pub(super) fn with_system<T>(f: impl FnOnce(&dyn System) -> T) -> T {
+    #[cfg(test)]
+    return DATABASE.with(|database| match &*database.borrow() {
+        Some(database) => f(database),
+        None => f(&Libc),
+    });
+
+    #[cfg(not(test))]
+    f(&Libc)
+}
+
+/// Users and groups that are kept in memory, for tests.
+#[cfg(test)]
+#[derive(Default)]
+pub struct Database {
+    pub users: Vec<User>,
+    pub groups: Vec<Group>,
+}
+
+#[cfg(test)]
+impl Database {
+    /// Add a user, whose primary group has the same name and id.
+    pub fn with_user(mut self, name: &str, uid: UserId) -> Self {
+        self.users.push(User {
+            uid,
+            gid: uid,
+            name: name.to_string(),
+            gecos: String::new(),
+            home: format!("/home/{name}").into(),
+            shell: "/bin/sh".into(),
+            passwd: String::new(),
+            groups: vec![uid],
+        });
+        self.with_group(name, uid, &[])
+    }
+
+    /// Add a group with these members, which is also added to the groups of those members.
+    pub fn with_group(mut self, name: &str, gid: GroupId, members: &[&str]) -> Self {
+        for user in self.users.iter_mut() {
+            if members.contains(&user.name.as_str()) {
+                user.groups.push(gid);
+            }
+        }
+        self.groups.push(Group {
+            gid,
+            name: name.to_string(),
+            passwd: String::new(),
+            members: members.iter().map(|member| member.to_string()).collect(),
+        });
+        self
+    }
+}
+
+#[cfg(test)]
+impl System for Database {
+    fn user_by_uid(&self, uid: UserId) -> io::Result<Option<User>> {
+        Ok(self.users.iter().find(|user| user.uid == uid).cloned())
+    }
+
+    fn user_by_name(&self, name: &str) -> io::Result<Option<User>> {
+        Ok(self.users.iter().find(|user| user.name == name).cloned())
+    }
+
+    fn group_by_gid(&self, gid: GroupId) -> io::Result<Option<Group>> {
+        Ok(self.groups.iter().find(|group| group.gid == gid).cloned())
+    }
+
+    fn group_by_name(&self, name: &str) -> io::Result<Option<Group>> {
+        Ok(self.groups.iter().find(|group| group.name == name).cloned())
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    static DATABASE: std::cell::RefCell<Option<Database>> = Default::default();
+}
+
+/// Look up users and groups in `database` instead of in the databases of the system, for the rest
+/// of the current test. This has to happen before the first lookup, which is cached.
+#[cfg(test)]
+pub fn use_database(database: Database) {
+    DATABASE.with(|current| *current.borrow_mut() = Some(database));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::interface::UnixUser;
+
+    #[test]
+    fn reads_the_system_databases() {
+        let root = Libc.user_by_uid(0).unwrap().unwrap();
+        assert_eq!(root.name, "root");
+        assert_eq!(Libc.user_by_name("root").unwrap(), Some(root));
+        assert_eq!(Libc.group_by_gid(0).unwrap().unwrap().name, "root");
+        assert_eq!(Libc.group_by_name("root").unwrap().unwrap().gid, 0);
+
+        assert_eq!(Libc.user_by_name("no-such-user-exists").unwrap(), None);
+        assert_eq!(Libc.group_by_name("no-such-group-exists").unwrap(), None);
+        assert!(Libc.user_by_name("nul\0byte").is_err());
+    }
+
+    #[test]
+    fn uses_a_database_in_memory() {
+        use_database(
+            Database::default()
+                .with_user("ferris", 1000)
+                .with_user("crab", 1001)
+                .with_group("rustaceans", 2000, &["ferris"]),
+        );
+
+        let ferris = User::from_name("ferris").unwrap().unwrap();
+        assert_eq!(ferris.uid, 1000);
+        assert!(ferris.in_group_by_name("rustaceans"));
+        assert!(!User::from_uid(1001)
+            .unwrap()
+            .unwrap()
+            .in_group_by_name("rustaceans"));
+        assert_eq!(Group::from_gid(2000).unwrap().unwrap().members, ["ferris"]);
+        assert!(User::from_name("root").unwrap().is_none());
+    }
+}
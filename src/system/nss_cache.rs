@@ -0,0 +1,162 @@
+//! A cache of the users and groups that were looked up in the user and group databases, so that a
+//! policy that names many groups (e.g. dozens of `%group` entries, with an LDAP backed nsswitch)
+//! does not look up the same group again for every rule. Users and groups that do not exist are
+//! remembered as well; errors are not, so a failed lookup is tried again.
+//!
+//! The supplementary groups of a user are found with a single call to `getgrouplist` when the
+//! user is looked up, so they are cached along with the user.
+//!
+//! The cache lasts as long as the process, i.e. a single invocation of sudo.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io;
+use std::thread::LocalKey;
+
+use super::interface::{GroupId, UserId};
+use super::{Group, User};
+
+type Cache<K, V> = RefCell<HashMap<K, Option<V>>>;
+
+thread_local! {
+    static USERS_BY_UID: Cache<UserId, User> = RefCell::default();
+    static USERS_BY_NAME: Cache<String, User> = RefCell::default();
+    static GROUPS_BY_GID: Cache<GroupId, Group> = RefCell::default();
+    static GROUPS_BY_NAME: Cache<String, Group> = RefCell::default();
+}
+
+/// The result of an earlier `lookup` of `key`, or else that of `lookup` itself.
+fn cached<K: Eq + Hash, V: Clone>(
+    cache: &'static LocalKey<Cache<K, V>>,
+    key: K,
+    lookup: impl FnOnce() -> io::Result<Option<V>>,
+) -> io::Result<Option<V>> {
+    if let Some(found) = cache.with(|cache| cache.borrow().get(&key).cloned()) {
+        return Ok(found);
+    }
+
+    let found = lookup()?;
+    cache.with(|cache| cache.borrow_mut().insert(key, found.clone()));
+
+    Ok(found)
+}
+
+/// Remember a user that was found by its uid under its name too, and the other way around.
+fn remember_user(user: &Option<User>) {
+    if let Some(user) = user {
+        USERS_BY_UID.with(|cache| {
+            cache
+                .borrow_mut()
+                .entry(user.uid)
+                .or_insert_with(|| Some(user.clone()));
+        });
+        USERS_BY_NAME.with(|cache| {
+            cache
+                .borrow_mut()
+                .entry(user.name.clone())
+                .or_insert_with(|| Some(user.clone()));
+        });
+    }
+}
+
+fn remember_group(group: &Option<Group>) {
+    if let Some(group) = group {
+        GROUPS_BY_GID.with(|cache| {
+            cache
+                .borrow_mut()
+                .entry(group.gid)
+                .or_insert_with(|| Some(group.clone()));
+        });
+        GROUPS_BY_NAME.with(|cache| {
+            cache
+                .borrow_mut()
+                .entry(group.name.clone())
+                .or_insert_with(|| Some(group.clone()));
+        });
+    }
+}
+
+pub(super) fn user_by_uid(
+    uid: UserId,
+    lookup: impl FnOnce() -> io::Result<Option<User>>,
+) -> io::Result<Option<User>> {
+    let user = cached(&USERS_BY_UID, uid, lookup)?;
+    remember_user(&user);
+    Ok(user)
+}
+
+pub(super) fn user_by_name(
+    name: &str,
+    lookup: impl FnOnce() -> io::Result<Option<User>>,
+) -> io::Result<Option<User>> {
+    let user = cached(&USERS_BY_NAME, name.to_string(), lookup)?;
+    remember_user(&user);
+    Ok(user)
+}
+
+pub(super) fn group_by_gid(
+    gid: GroupId,
+    lookup: impl FnOnce() -> io::Result<Option<Group>>,
+) -> io::Result<Option<Group>> {
+    let group = cached(&GROUPS_BY_GID, gid, lookup)?;
+    remember_group(&group);
+    Ok(group)
+}
+
+pub(super) fn group_by_name(
+    name: &str,
+    lookup: impl FnOnce() -> io::Result<Option<Group>>,
+) -> io::Result<Option<Group>> {
+    let group = cached(&GROUPS_BY_NAME, name.to_string(), lookup)?;
+    remember_group(&group);
+    Ok(group)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::io;
+
+    use super::*;
+
+    fn group(gid: GroupId, name: &str) -> Group {
+        Group {
+            gid,
+            name: name.to_string(),
+            passwd: String::new(),
+            members: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn looks_up_once() {
+        let lookups = Cell::new(0);
+        let lookup = |found: Option<Group>| {
+            lookups.set(lookups.get() + 1);
+            Ok(found)
+        };
+
+        let found = group_by_name("cached-wheel", || lookup(Some(group(4242, "cached-wheel"))));
+        assert_eq!(found.unwrap().unwrap().gid, 4242);
+        let found = group_by_name("cached-wheel", || lookup(None));
+        assert_eq!(found.unwrap().unwrap().gid, 4242);
+        // found under its gid as well
+        let found = group_by_gid(4242, || lookup(None));
+        assert_eq!(found.unwrap().unwrap().name, "cached-wheel");
+        assert_eq!(lookups.get(), 1);
+
+        // groups that do not exist are remembered too
+        assert!(group_by_name("cached-nobody", || lookup(None))
+            .unwrap()
+            .is_none());
+        assert!(group_by_name("cached-nobody", || lookup(None))
+            .unwrap()
+            .is_none());
+        assert_eq!(lookups.get(), 2);
+
+        // but errors are not
+        assert!(group_by_gid(4343, || Err(io::ErrorKind::Other.into())).is_err());
+        assert!(group_by_gid(4343, || lookup(None)).unwrap().is_none());
+        assert_eq!(lookups.get(), 3);
+    }
+}
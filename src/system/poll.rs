@@ -0,0 +1,181 @@
+//! Waiting for file descriptors to become ready, with the same interface as `poll`.
+//!
+//! Linux uses `poll` itself. The BSDs use a kqueue, and macOS uses `select`, as neither `poll`
+//! nor kqueue support terminal devices there.
+use std::{io, time::Duration};
+
+use libc::pollfd;
+#[cfg(not(target_os = "linux"))]
+use libc::{POLLIN, POLLOUT};
+
+use crate::cutils::cerr;
+
+/// Wait until one of `fds` is ready for the events it asks for, or until `timeout` passed, and
+/// return how many of them are ready. The events that happened are stored in `revents`.
+#[cfg(target_os = "linux")]
+pub fn poll(fds: &mut [pollfd], timeout: Option<Duration>) -> io::Result<usize> {
+    let timeout = timeout.map_or(-1, |timeout| {
+        libc::c_int::try_from(timeout.as_millis()).unwrap_or(libc::c_int::MAX)
+    });
+    // SAFETY: `fds` is a valid slice of `pollfd` structs.
+    let ready = cerr(unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, timeout) })?;
+
+    Ok(ready as usize)
+}
+
+/// Wait until one of `fds` is ready for the events it asks for, or until `timeout` passed, and
+/// return how many of them are ready. The events that happened are stored in `revents`.
+#[cfg(any(target_os = "freebsd", target_os = "openbsd"))]
+pub fn poll(fds: &mut [pollfd], timeout: Option<Duration>) -> io::Result<usize> {
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    // SAFETY: `kqueue` takes no arguments.
+    let kqueue = cerr(unsafe { libc::kqueue() })?;
+    // SAFETY: the file descriptor was just created, and is not owned by anything else.
+    let kqueue = unsafe { OwnedFd::from_raw_fd(kqueue) };
+
+    let mut changes = Vec::with_capacity(2 * fds.len());
+    for (index, fd) in fds.iter_mut().enumerate() {
+        fd.revents = 0;
+        for (flag, filter) in [(POLLIN, libc::EVFILT_READ), (POLLOUT, libc::EVFILT_WRITE)] {
+            if fd.events & flag != 0 {
+                // SAFETY: all fields of `kevent` are integers or pointers, for which zero is valid.
+                let mut change: libc::kevent = unsafe { std::mem::zeroed() };
+                change.ident = fd.fd as _;
+                change.filter = filter;
+                change.flags = libc::EV_ADD | libc::EV_ONESHOT;
+                // to find the `pollfd` of an event
+                change.udata = index as _;
+                changes.push(change);
+            }
+        }
+    }
+
+    let timeout = timeout.map(|timeout| libc::timespec {
+        tv_sec: timeout.as_secs() as _,
+        tv_nsec: timeout.subsec_nanos() as _,
+    });
+    // SAFETY: see above.
+    let mut events = vec![unsafe { std::mem::zeroed::<libc::kevent>() }; changes.len()];
+    // SAFETY: the changes and events are valid for the lengths that are passed, and errors in the
+    // changes are returned as events as there is room for all of them.
+    let count = cerr(unsafe {
+        libc::kevent(
+            kqueue.as_raw_fd(),
+            changes.as_ptr(),
+            changes.len() as _,
+            events.as_mut_ptr(),
+            events.len() as _,
+            timeout.as_ref().map_or(std::ptr::null(), |timeout| timeout),
+        )
+    })?;
+
+    for event in &events[..count as usize] {
+        let fd = &mut fds[event.udata as usize];
+        if event.flags & libc::EV_ERROR != 0 {
+            fd.revents |= libc::POLLNVAL;
+        } else if event.filter == libc::EVFILT_READ {
+            fd.revents |= POLLIN;
+        } else {
+            fd.revents |= POLLOUT;
+        }
+        if event.flags & libc::EV_EOF != 0 {
+            fd.revents |= libc::POLLHUP;
+        }
+    }
+
+    Ok(fds.iter().filter(|fd| fd.revents != 0).count())
+}
+
+/// Wait until one of `fds` is ready for the events it asks for, or until `timeout` passed, and
+/// return how many of them are ready. The events that happened are stored in `revents`.
+#[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "openbsd")))]
+pub fn poll(fds: &mut [pollfd], timeout: Option<Duration>) -> io::Result<usize> {
+    // SAFETY: an `fd_set` only contains integers, and is emptied with `FD_ZERO` below.
+    let mut readable: libc::fd_set = unsafe { std::mem::zeroed() };
+    let mut writable: libc::fd_set = unsafe { std::mem::zeroed() };
+    unsafe {
+        libc::FD_ZERO(&mut readable);
+        libc::FD_ZERO(&mut writable);
+    }
+
+    let mut max_fd = -1;
+    for fd in fds.iter_mut() {
+        fd.revents = 0;
+        if fd.fd < 0 || fd.fd as usize >= libc::FD_SETSIZE {
+            return Err(io::Error::from_raw_os_error(libc::EINVAL));
+        }
+        // SAFETY: `fd` was just checked to fit in the sets.
+        if fd.events & POLLIN != 0 {
+            unsafe { libc::FD_SET(fd.fd, &mut readable) };
+        }
+        if fd.events & POLLOUT != 0 {
+            unsafe { libc::FD_SET(fd.fd, &mut writable) };
+        }
+        max_fd = max_fd.max(fd.fd);
+    }
+
+    let mut timeout = timeout.map(|timeout| libc::timeval {
+        tv_sec: timeout.as_secs() as _,
+        tv_usec: timeout.subsec_micros() as _,
+    });
+    // SAFETY: the sets are valid, and contain no file descriptor above `max_fd`.
+    cerr(unsafe {
+        libc::select(
+            max_fd + 1,
+            &mut readable,
+            &mut writable,
+            std::ptr::null_mut(),
+            timeout
+                .as_mut()
+                .map_or(std::ptr::null_mut(), |timeout| timeout),
+        )
+    })?;
+
+    for fd in fds.iter_mut() {
+        // SAFETY: `fd` fits in the sets, as was checked above.
+        if unsafe { libc::FD_ISSET(fd.fd, &readable) } {
+            fd.revents |= POLLIN;
+        }
+        if unsafe { libc::FD_ISSET(fd.fd, &writable) } {
+            fd.revents |= POLLOUT;
+        }
+    }
+
+    Ok(fds.iter().filter(|fd| fd.revents != 0).count())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, os::fd::AsRawFd, os::unix::net::UnixStream, time::Duration};
+
+    use super::poll;
+
+    #[test]
+    fn reports_ready_descriptors() {
+        let (mut tx, rx) = UnixStream::pair().unwrap();
+        let mut fds = [
+            libc::pollfd {
+                fd: rx.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+            libc::pollfd {
+                fd: tx.as_raw_fd(),
+                events: libc::POLLOUT,
+                revents: 0,
+            },
+        ];
+
+        assert_eq!(
+            poll(&mut fds[..1], Some(Duration::from_millis(10))).unwrap(),
+            0
+        );
+        assert_eq!(fds[0].revents, 0);
+
+        tx.write_all(b"ready").unwrap();
+        assert_eq!(poll(&mut fds, None).unwrap(), 2);
+        assert_ne!(fds[0].revents & libc::POLLIN, 0);
+        assert_ne!(fds[1].revents & libc::POLLOUT, 0);
+    }
+}
@@ -0,0 +1,565 @@
+//! Sandbox profiles that restrict a command using Landlock and seccomp.
+//!
+//! A profile is selected in sudoers with the `SANDBOX=name` tag, and read from the file `name` in
+//! [`PROFILE_DIR`]. Every non-empty line that is not a comment contains one directive:
+//!
+//! ```text
+//! # the command may read (and execute) everything below these paths
+//! read /usr
+//! read /etc
+//! # the command may also modify everything below these paths
+//! write /var/log/myapp
+//! # the command may not make these system calls
+//! deny mount
+//! deny ptrace
+//! ```
+//!
+//! If a profile contains `read` or `write` directives, all other file system access is denied.
+//!
+//! Landlock and seccomp only exist on Linux, so on other systems, commands cannot be sandboxed.
+//!
+//! Regardless of profiles, every command gets an [`InjectionFilter`] on Linux, which keeps it from
+//! typing into the terminal of the user.
+#[cfg(target_os = "linux")]
+use std::{
+    fs::File,
+    os::fd::{AsRawFd, FromRawFd},
+};
+use std::{
+    io::{self, Read},
+    os::fd::OwnedFd,
+    path::{Path, PathBuf},
+};
+
+#[cfg(target_os = "linux")]
+use crate::cutils::cerr;
+
+use super::secure_open;
+
+/// The directory containing the sandbox profiles.
+pub const PROFILE_DIR: &str = "/etc/sudo-sandbox.d";
+
+/// A parsed sandbox profile.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SandboxProfile {
+    read: Vec<PathBuf>,
+    write: Vec<PathBuf>,
+    deny: Vec<libc::c_long>,
+}
+
+impl SandboxProfile {
+    /// Read the profile called `name` from [`PROFILE_DIR`].
+    pub fn load(name: &str) -> io::Result<Self> {
+        let mut text = String::new();
+        secure_open(Path::new(PROFILE_DIR).join(name), true)?.read_to_string(&mut text)?;
+
+        Self::parse(&text)
+    }
+
+    fn parse(text: &str) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+        let mut profile = Self::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((directive, argument)) = line.split_once(char::is_whitespace) else {
+                return Err(invalid(format!("line {}: missing argument", number + 1)));
+            };
+            let argument = argument.trim();
+
+            match directive {
+                "read" | "write" => {
+                    let path = PathBuf::from(argument);
+                    if !path.is_absolute() {
+                        return Err(invalid(format!(
+                            "line {}: path must be absolute: {argument}",
+                            number + 1
+                        )));
+                    }
+                    if directive == "read" {
+                        profile.read.push(path)
+                    } else {
+                        profile.write.push(path)
+                    }
+                }
+                "deny" => {
+                    let Some(nr) = syscall_number(argument) else {
+                        return Err(invalid(format!(
+                            "line {}: unknown system call: {argument}",
+                            number + 1
+                        )));
+                    };
+                    profile.deny.push(nr);
+                }
+                _ => {
+                    return Err(invalid(format!(
+                        "line {}: unknown directive: {directive}",
+                        number + 1
+                    )))
+                }
+            }
+        }
+
+        Ok(profile)
+    }
+
+    /// Set up everything that requires allocations or opening files, so that the resulting
+    /// [`Sandbox`] can be applied after forking.
+    #[cfg(not(target_os = "linux"))]
+    pub fn prepare(&self) -> io::Result<Sandbox> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "sandboxes are only supported on Linux",
+        ))
+    }
+
+    /// Set up everything that requires allocations or opening files, so that the resulting
+    /// [`Sandbox`] can be applied after forking.
+    #[cfg(target_os = "linux")]
+    pub fn prepare(&self) -> io::Result<Sandbox> {
+        let ruleset = if self.read.is_empty() && self.write.is_empty() {
+            None
+        } else {
+            let ruleset = landlock::create_ruleset()?;
+            for path in &self.read {
+                landlock::allow(&ruleset, path, landlock::ACCESS_READ)?;
+            }
+            for path in &self.write {
+                landlock::allow(&ruleset, path, landlock::ACCESS_ALL)?;
+            }
+            Some(ruleset)
+        };
+
+        let filter = if self.deny.is_empty() {
+            Vec::new()
+        } else {
+            seccomp::deny_filter(&self.deny)?
+        };
+
+        Ok(Sandbox { ruleset, filter })
+    }
+}
+
+/// A sandbox that is ready to be applied to the current process.
+pub struct Sandbox {
+    ruleset: Option<OwnedFd>,
+    #[cfg(target_os = "linux")]
+    filter: Vec<libc::sock_filter>,
+}
+
+impl Sandbox {
+    /// The landlock ruleset, which must stay open until the sandbox is applied.
+    pub fn ruleset(&self) -> Option<&OwnedFd> {
+        self.ruleset.as_ref()
+    }
+
+    /// Restrict the current process (and everything it executes) to this sandbox.
+    #[cfg(not(target_os = "linux"))]
+    pub fn apply(&self) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// Restrict the current process (and everything it executes) to this sandbox.
+    #[cfg(target_os = "linux")]
+    pub fn apply(&self) -> io::Result<()> {
+        // SAFETY: `PR_SET_NO_NEW_PRIVS` only takes a boolean as its argument.
+        cerr(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) })?;
+
+        if let Some(ruleset) = &self.ruleset {
+            // SAFETY: `landlock_restrict_self` takes a file descriptor and (empty) flags.
+            cerr(unsafe {
+                libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0)
+            })?;
+        }
+
+        if !self.filter.is_empty() {
+            seccomp::install(&self.filter)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A seccomp filter that makes the `ioctl`s with which a process can make a terminal read input
+/// that was not typed fail with `EPERM`: `TIOCSTI`, which queues input, and `TIOCLINUX`, which
+/// can paste the selection of the Linux console. Running the command in a pty keeps it away from
+/// the terminal of the user, but a command that runs as root could still open it.
+pub struct InjectionFilter {
+    #[cfg(target_os = "linux")]
+    filter: Vec<libc::sock_filter>,
+}
+
+impl InjectionFilter {
+    /// Set up the filter, so that it can be applied after forking.
+    #[cfg(not(target_os = "linux"))]
+    pub fn new() -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "ioctls can only be filtered on Linux",
+        ))
+    }
+
+    /// Set up the filter, so that it can be applied after forking.
+    #[cfg(target_os = "linux")]
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            filter: seccomp::ioctl_filter(&[libc::TIOCSTI as u32, libc::TIOCLINUX as u32])?,
+        })
+    }
+
+    /// Restrict the current process (and everything it executes). Unlike [`Sandbox::apply`],
+    /// this does not keep the process from gaining privileges, so it has to be privileged itself.
+    #[cfg(not(target_os = "linux"))]
+    pub fn apply(&self) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// Restrict the current process (and everything it executes). Unlike [`Sandbox::apply`],
+    /// this does not keep the process from gaining privileges, so it has to be privileged itself.
+    #[cfg(target_os = "linux")]
+    pub fn apply(&self) -> io::Result<()> {
+        seccomp::install(&self.filter)
+    }
+}
+
+/// The system calls that can be denied in a profile.
+#[cfg(not(target_os = "linux"))]
+const SYSCALLS: &[(&str, libc::c_long)] = &[];
+
+/// The system calls that can be denied in a profile.
+#[cfg(target_os = "linux")]
+const SYSCALLS: &[(&str, libc::c_long)] = &[
+    ("acct", libc::SYS_acct),
+    ("add_key", libc::SYS_add_key),
+    ("adjtimex", libc::SYS_adjtimex),
+    ("bpf", libc::SYS_bpf),
+    ("chroot", libc::SYS_chroot),
+    ("clock_adjtime", libc::SYS_clock_adjtime),
+    ("clock_settime", libc::SYS_clock_settime),
+    ("delete_module", libc::SYS_delete_module),
+    ("finit_module", libc::SYS_finit_module),
+    ("init_module", libc::SYS_init_module),
+    ("kexec_file_load", libc::SYS_kexec_file_load),
+    ("kexec_load", libc::SYS_kexec_load),
+    ("keyctl", libc::SYS_keyctl),
+    ("mknodat", libc::SYS_mknodat),
+    ("mount", libc::SYS_mount),
+    ("open_by_handle_at", libc::SYS_open_by_handle_at),
+    ("perf_event_open", libc::SYS_perf_event_open),
+    ("personality", libc::SYS_personality),
+    ("pivot_root", libc::SYS_pivot_root),
+    ("process_vm_readv", libc::SYS_process_vm_readv),
+    ("process_vm_writev", libc::SYS_process_vm_writev),
+    ("ptrace", libc::SYS_ptrace),
+    ("quotactl", libc::SYS_quotactl),
+    ("reboot", libc::SYS_reboot),
+    ("request_key", libc::SYS_request_key),
+    ("setdomainname", libc::SYS_setdomainname),
+    ("sethostname", libc::SYS_sethostname),
+    ("setns", libc::SYS_setns),
+    ("settimeofday", libc::SYS_settimeofday),
+    ("swapoff", libc::SYS_swapoff),
+    ("swapon", libc::SYS_swapon),
+    ("syslog", libc::SYS_syslog),
+    ("umount2", libc::SYS_umount2),
+    ("unshare", libc::SYS_unshare),
+    ("userfaultfd", libc::SYS_userfaultfd),
+    ("vhangup", libc::SYS_vhangup),
+];
+
+fn syscall_number(name: &str) -> Option<libc::c_long> {
+    SYSCALLS
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, nr)| *nr)
+}
+
+#[cfg(target_os = "linux")]
+mod landlock {
+    use super::*;
+
+    const ACCESS_FS_EXECUTE: u64 = 1 << 0;
+    const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+    const ACCESS_FS_READ_DIR: u64 = 1 << 3;
+
+    /// All file system accesses known to the first version of Landlock.
+    pub(super) const ACCESS_ALL: u64 = (1 << 13) - 1;
+    pub(super) const ACCESS_READ: u64 =
+        ACCESS_FS_EXECUTE | ACCESS_FS_READ_FILE | ACCESS_FS_READ_DIR;
+    /// The accesses that can be granted on a file, instead of a directory.
+    const ACCESS_FILE: u64 = ACCESS_FS_EXECUTE | ACCESS_FS_WRITE_FILE | ACCESS_FS_READ_FILE;
+
+    const RULE_PATH_BENEATH: libc::c_int = 1;
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: libc::c_int,
+    }
+
+    pub(super) fn create_ruleset() -> io::Result<OwnedFd> {
+        let attr = RulesetAttr {
+            handled_access_fs: ACCESS_ALL,
+        };
+        // SAFETY: `attr` is a valid ruleset attribute of the given size.
+        let fd = cerr(unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &attr as *const RulesetAttr,
+                std::mem::size_of::<RulesetAttr>(),
+                0,
+            )
+        })?;
+
+        // SAFETY: the kernel just handed us this (close-on-exec) file descriptor.
+        Ok(unsafe { OwnedFd::from_raw_fd(fd as libc::c_int) })
+    }
+
+    pub(super) fn allow(ruleset: &OwnedFd, path: &Path, access: u64) -> io::Result<()> {
+        let file = File::open(path)?;
+        let access = if file.metadata()?.is_dir() {
+            access
+        } else {
+            access & ACCESS_FILE
+        };
+
+        let attr = PathBeneathAttr {
+            allowed_access: access,
+            parent_fd: file.as_raw_fd(),
+        };
+        // SAFETY: `attr` is a valid path beneath attribute, and both file descriptors are open.
+        cerr(unsafe {
+            libc::syscall(
+                libc::SYS_landlock_add_rule,
+                ruleset.as_raw_fd(),
+                RULE_PATH_BENEATH,
+                &attr as *const PathBeneathAttr,
+                0,
+            )
+        })?;
+
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod seccomp {
+    use super::*;
+    use libc::{sock_filter, BPF_ABS, BPF_JEQ, BPF_JGE, BPF_JMP, BPF_K, BPF_LD, BPF_RET, BPF_W};
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xC000_003E);
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xC000_00B7);
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    const AUDIT_ARCH: Option<u32> = None;
+
+    /// The architectures that a process can make system calls with, along with the numbers of
+    /// `ioctl` in each of them: its own ABI (and x32 on x86_64), and the 32-bit one.
+    #[cfg(target_arch = "x86_64")]
+    const IOCTL_ABIS: &[(u32, &[u32])] = &[
+        (
+            0xC000_003E,
+            &[libc::SYS_ioctl as u32, X32_SYSCALL_BIT | 514],
+        ),
+        (0x4000_0003, &[54]),
+    ];
+    #[cfg(target_arch = "aarch64")]
+    const IOCTL_ABIS: &[(u32, &[u32])] = &[
+        (0xC000_00B7, &[libc::SYS_ioctl as u32]),
+        (0x4000_0028, &[54]),
+    ];
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    const IOCTL_ABIS: &[(u32, &[u32])] = &[];
+
+    // offsets of the fields of `struct seccomp_data`
+    const OFFSET_NR: u32 = 0;
+    const OFFSET_ARCH: u32 = 4;
+    /// The lower half of the second argument; the upper half of the request of an `ioctl` is
+    /// ignored by the kernel.
+    const OFFSET_ARG1_LOW: u32 = 24;
+
+    /// System calls of the x32 ABI have this bit set on x86_64.
+    const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+    fn stmt(code: u32, k: u32) -> sock_filter {
+        sock_filter {
+            code: code as u16,
+            jt: 0,
+            jf: 0,
+            k,
+        }
+    }
+
+    fn jump(code: u32, k: u32, jt: u8, jf: u8) -> sock_filter {
+        sock_filter {
+            code: code as u16,
+            jt,
+            jf,
+            k,
+        }
+    }
+
+    /// A BPF program that makes the system calls in `deny` fail with `EPERM`. Any system call
+    /// made using a different architecture (or ABI) than our own kills the process.
+    pub(super) fn deny_filter(deny: &[libc::c_long]) -> io::Result<Vec<sock_filter>> {
+        let Some(arch) = AUDIT_ARCH else {
+            return Err(io::ErrorKind::Unsupported.into());
+        };
+
+        let mut filter = vec![
+            stmt(BPF_LD | BPF_W | BPF_ABS, OFFSET_ARCH),
+            jump(BPF_JMP | BPF_JEQ | BPF_K, arch, 1, 0),
+            stmt(BPF_RET | BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+            stmt(BPF_LD | BPF_W | BPF_ABS, OFFSET_NR),
+            jump(BPF_JMP | BPF_JGE | BPF_K, X32_SYSCALL_BIT, 0, 1),
+            stmt(BPF_RET | BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+        ];
+        for &nr in deny {
+            filter.push(jump(BPF_JMP | BPF_JEQ | BPF_K, nr as u32, 0, 1));
+            filter.push(stmt(
+                BPF_RET | BPF_K,
+                libc::SECCOMP_RET_ERRNO | libc::EPERM as u32,
+            ));
+        }
+        filter.push(stmt(BPF_RET | BPF_K, libc::SECCOMP_RET_ALLOW));
+
+        Ok(filter)
+    }
+
+    /// A BPF program that makes the `ioctl`s in `requests` fail with `EPERM`, whatever the
+    /// architecture that they are made with.
+    pub(super) fn ioctl_filter(requests: &[u32]) -> io::Result<Vec<sock_filter>> {
+        if IOCTL_ABIS.is_empty() {
+            return Err(io::ErrorKind::Unsupported.into());
+        }
+
+        // every architecture gets a block that jumps to the check of the request if the system
+        // call is an `ioctl`, which comes after all blocks and the end of the program
+        let check = 1
+            + IOCTL_ABIS
+                .iter()
+                .map(|(_, numbers)| 3 + numbers.len())
+                .sum::<usize>()
+            + 1;
+
+        let mut filter = vec![stmt(BPF_LD | BPF_W | BPF_ABS, OFFSET_ARCH)];
+        for (arch, numbers) in IOCTL_ABIS {
+            filter.push(jump(
+                BPF_JMP | BPF_JEQ | BPF_K,
+                *arch,
+                0,
+                2 + numbers.len() as u8,
+            ));
+            filter.push(stmt(BPF_LD | BPF_W | BPF_ABS, OFFSET_NR));
+            for &nr in *numbers {
+                let to_check = (check - filter.len() - 1) as u8;
+                filter.push(jump(BPF_JMP | BPF_JEQ | BPF_K, nr, to_check, 0));
+            }
+            filter.push(stmt(BPF_RET | BPF_K, libc::SECCOMP_RET_ALLOW));
+        }
+        filter.push(stmt(BPF_RET | BPF_K, libc::SECCOMP_RET_ALLOW));
+
+        debug_assert_eq!(filter.len(), check);
+        filter.push(stmt(BPF_LD | BPF_W | BPF_ABS, OFFSET_ARG1_LOW));
+        for &request in requests {
+            filter.push(jump(BPF_JMP | BPF_JEQ | BPF_K, request, 0, 1));
+            filter.push(stmt(
+                BPF_RET | BPF_K,
+                libc::SECCOMP_RET_ERRNO | libc::EPERM as u32,
+            ));
+        }
+        filter.push(stmt(BPF_RET | BPF_K, libc::SECCOMP_RET_ALLOW));
+
+        Ok(filter)
+    }
+
+    /// Apply `filter` to the current process.
+    pub(super) fn install(filter: &[sock_filter]) -> io::Result<()> {
+        let program = libc::sock_fprog {
+            len: filter.len() as _,
+            filter: filter.as_ptr() as *mut _,
+        };
+        // SAFETY: `program` points to a valid BPF program that outlives this call.
+        cerr(unsafe {
+            libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &program as *const libc::sock_fprog,
+                0,
+                0,
+            )
+        })?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn parses_profile() {
+        let profile = SandboxProfile::parse(
+            "# a comment\n\nread /usr\nwrite  /var/log\ndeny ptrace\ndeny mount\n",
+        )
+        .unwrap();
+
+        assert_eq!(profile.read, [PathBuf::from("/usr")]);
+        assert_eq!(profile.write, [PathBuf::from("/var/log")]);
+        assert_eq!(profile.deny, [libc::SYS_ptrace, libc::SYS_mount]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn keeps_commands_from_typing_into_terminals() {
+        use crate::system::{
+            fork,
+            term::Pty,
+            wait::{Wait, WaitOptions},
+            ForkResult,
+        };
+
+        let pty = Pty::open().unwrap();
+        let filter = InjectionFilter::new().unwrap();
+        let ForkResult::Parent(child_pid) = (unsafe { fork() }).unwrap() else {
+            // an unprivileged process may only install a filter if it cannot gain privileges
+            unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) };
+            filter.apply().unwrap();
+
+            let byte = b'x';
+            let result = unsafe { libc::ioctl(pty.follower.as_raw_fd(), libc::TIOCSTI, &byte) };
+            let injected =
+                result != -1 || io::Error::last_os_error().raw_os_error() != Some(libc::EPERM);
+            // the filter only applies to the requests that it names
+            let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+            let other =
+                unsafe { libc::ioctl(pty.follower.as_raw_fd(), libc::TIOCGWINSZ, &mut size) };
+
+            std::process::exit(if injected || other == -1 { 1 } else { 0 })
+        };
+
+        let (_, status) = child_pid.wait(WaitOptions::new()).unwrap();
+        assert_eq!(status.exit_status(), Some(0));
+    }
+
+    #[test]
+    fn rejects_invalid_profiles() {
+        assert!(SandboxProfile::parse("read usr").is_err());
+        assert!(SandboxProfile::parse("deny world_domination").is_err());
+        assert!(SandboxProfile::parse("execute /usr/bin").is_err());
+        assert!(SandboxProfile::parse("read").is_err());
+    }
+}
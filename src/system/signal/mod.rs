@@ -13,17 +13,29 @@ use std::borrow::Cow;
 pub(crate) type SignalNumber = libc::c_int;
 
 macro_rules! define_consts {
-    ($($signal:ident,)*) => {
+    ($($(#[$meta:meta])* $signal:ident,)*) => {
         pub(crate) mod consts {
-            pub(crate) use libc::{$($signal,)*};
+            $($(#[$meta])* pub(crate) use libc::$signal;)*
         }
 
         pub(crate) fn signal_name(signal: SignalNumber) -> Cow<'static, str> {
             match signal {
-                $(consts::$signal => stringify!($signal).into(),)*
+                $($(#[$meta])* consts::$signal => stringify!($signal).into(),)*
                 _ => format!("unknown signal ({signal})").into(),
             }
         }
+
+        /// The signal with the name `name`, which may leave out the "SIG" prefix.
+        pub(crate) fn signal_number(name: &str) -> Option<SignalNumber> {
+            let name = name.strip_prefix("SIG").unwrap_or(name);
+            $(
+                $(#[$meta])*
+                if name == &stringify!($signal)[3..] {
+                    return Some(consts::$signal);
+                }
+            )*
+            None
+        }
     };
 }
 
@@ -44,4 +56,7 @@ define_consts! {
     SIGTTOU,
     SIGKILL,
     SIGSTOP,
+    // the status request of the BSDs and macOS, which is sent with `^T`
+    #[cfg(not(target_os = "linux"))]
+    SIGINFO,
 }
@@ -35,6 +35,7 @@ impl SignalAction {
                 sa_sigaction,
                 sa_mask: sa_mask.raw,
                 sa_flags,
+                #[cfg(target_os = "linux")]
                 sa_restorer: None,
             },
         })
@@ -74,6 +75,15 @@ impl SignalSet {
         Ok(unsafe { set.assume_init() })
     }
 
+    /// Create a set containing only the given signal.
+    pub(crate) fn single(signal: SignalNumber) -> io::Result<Self> {
+        let mut set = Self::empty()?;
+
+        cerr(unsafe { libc::sigaddset(&mut set.raw, signal) })?;
+
+        Ok(set)
+    }
+
     fn sigprocmask(&self, how: libc::c_int) -> io::Result<Self> {
         let mut original_set = MaybeUninit::<Self>::zeroed();
 
@@ -90,6 +100,11 @@ impl SignalSet {
         self.sigprocmask(libc::SIG_BLOCK)
     }
 
+    /// Unblock all the signals in this set and return the previous set of blocked signals.
+    pub(crate) fn unblock(&self) -> io::Result<Self> {
+        self.sigprocmask(libc::SIG_UNBLOCK)
+    }
+
     /// Block only the signals that are in this set and return the previous set of blocked signals.
     ///
     /// After calling this function successfully, the set of blocked signals will be the exactly
@@ -6,6 +6,8 @@ use std::{
     fs::File,
     io,
     os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    os::unix::fs::{FileTypeExt, MetadataExt},
+    path::Path,
     ptr::null_mut,
 };
 
@@ -13,9 +15,9 @@ use libc::{ioctl, winsize, TIOCSWINSZ};
 
 use crate::cutils::{cerr, os_string_from_ptr, safe_isatty};
 
-use super::interface::ProcessId;
+use super::interface::{GroupId, ProcessId, UserId};
 
-pub(crate) use user_term::UserTerm;
+pub(crate) use user_term::{TermSettings, UserTerm};
 
 pub(crate) struct Pty {
     /// The file path of the leader side of the pty.
@@ -119,13 +121,72 @@ impl PtyFollower {
     }
 }
 
+/// The mode of a pty: its owner may read and write, and its group may write, so messages from
+/// `write` and `wall` reach the command.
+pub(crate) const PTY_MODE: libc::mode_t = 0o620;
+
+/// Setting up who may use a pty. Tests replace the system calls with a fake.
+pub(crate) trait PtyOwnership {
+    fn chown(&self, uid: UserId, gid: Option<GroupId>) -> io::Result<()>;
+    fn chmod(&self, mode: libc::mode_t) -> io::Result<()>;
+    /// The owner, group, and permission bits of the pty.
+    fn owner_and_mode(&self) -> io::Result<(UserId, GroupId, libc::mode_t)>;
+}
+
+impl PtyOwnership for PtyFollower {
+    fn chown(&self, uid: UserId, gid: Option<GroupId>) -> io::Result<()> {
+        let gid = gid.unwrap_or(GroupId::MAX);
+        cerr(unsafe { libc::fchown(self.file.as_raw_fd(), uid, gid) }).map(|_| ())
+    }
+
+    fn chmod(&self, mode: libc::mode_t) -> io::Result<()> {
+        cerr(unsafe { libc::fchmod(self.file.as_raw_fd(), mode) }).map(|_| ())
+    }
+
+    fn owner_and_mode(&self) -> io::Result<(UserId, GroupId, libc::mode_t)> {
+        let metadata = self.file.metadata()?;
+        Ok((
+            metadata.uid(),
+            metadata.gid(),
+            (metadata.mode() & 0o7777) as libc::mode_t,
+        ))
+    }
+}
+
+/// Give a pty to `uid` and, if there is one, to the group `gid`, with [PTY_MODE]; without a
+/// group, only the owner may use it. The result is checked, so a pty that others could still
+/// access is never used.
+pub(crate) fn set_pty_owner(
+    pty: &impl PtyOwnership,
+    uid: UserId,
+    gid: Option<GroupId>,
+) -> io::Result<()> {
+    let mode = match gid {
+        Some(_) => PTY_MODE,
+        None => PTY_MODE & 0o700,
+    };
+
+    pty.chown(uid, gid)?;
+    pty.chmod(mode)?;
+
+    let (owner, group, actual_mode) = pty.owner_and_mode()?;
+    if owner != uid || gid.is_some_and(|gid| gid != group) || actual_mode != mode {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("pty has owner {owner}, group {group} and mode {actual_mode:o}"),
+        ));
+    }
+
+    Ok(())
+}
+
 impl AsRawFd for PtyFollower {
     fn as_raw_fd(&self) -> std::os::fd::RawFd {
         self.file.as_raw_fd()
     }
 }
 
-impl From<PtyFollower> for std::process::Stdio {
+impl From<PtyFollower> for OwnedFd {
     fn from(follower: PtyFollower) -> Self {
         follower.file.into()
     }
@@ -159,7 +220,7 @@ impl<F: AsRawFd> Terminal for F {
 
     /// Make the given terminal the controlling terminal of the calling process.
     fn make_controlling_terminal(&self) -> io::Result<()> {
-        cerr(unsafe { libc::ioctl(self.as_raw_fd(), libc::TIOCSCTTY, 0) })?;
+        cerr(unsafe { libc::ioctl(self.as_raw_fd(), libc::TIOCSCTTY as _, 0) })?;
         Ok(())
     }
 
@@ -186,11 +247,47 @@ pub fn current_tty_name() -> io::Result<OsString> {
     std::io::stdin().ttyname()
 }
 
+/// Check that a terminal can be trusted to prompt the invoking user (with the given `uid`) for a
+/// password: it must be a terminal device under `/dev`, owned by that user or by root, so that a
+/// password cannot be captured through a crafted file descriptor or a terminal of another user.
+pub(crate) fn verify_tty(tty: &File, uid: UserId) -> io::Result<()> {
+    let metadata = tty.metadata()?;
+    let name = tty.ttyname().unwrap_or_default();
+    if !metadata.file_type().is_char_device() || !Path::new(&name).starts_with("/dev/") {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "input is not a terminal device",
+        ));
+    }
+
+    if metadata.uid() != uid && metadata.uid() != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} is not owned by the invoking user",
+                Path::new(&name).display()
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
 #[repr(transparent)]
 pub(crate) struct TermSize {
     raw: winsize,
 }
 
+impl TermSize {
+    pub(crate) fn rows(&self) -> u16 {
+        self.raw.ws_row
+    }
+
+    pub(crate) fn cols(&self) -> u16 {
+        self.raw.ws_col
+    }
+}
+
 impl PartialEq for TermSize {
     fn eq(&self, other: &Self) -> bool {
         self.raw.ws_col == other.raw.ws_col && self.raw.ws_row == other.raw.ws_row
@@ -226,12 +323,30 @@ mod tests {
         assert!(path.starts_with("/dev/pts/"));
     }
 
+    #[test]
+    fn verifies_ttys() {
+        use std::os::unix::fs::MetadataExt;
+
+        let pty = Pty::open().unwrap();
+        let owner = pty.follower.file.metadata().unwrap().uid();
+        assert!(verify_tty(&pty.follower.file, owner).is_ok());
+        // terminals of root are trusted by everyone
+        if owner != 0 {
+            assert!(verify_tty(&pty.follower.file, owner + 1).is_err());
+        }
+
+        let file = std::fs::File::open("/bin/sh").unwrap();
+        assert!(verify_tty(&file, file.metadata().unwrap().uid()).is_err());
+        let null = std::fs::File::open("/dev/null").unwrap();
+        assert!(verify_tty(&null, 0).is_err());
+    }
+
     #[test]
     fn tcsetpgrp_and_tcgetpgrp_are_consistent() {
         // Create a socket so the child can send us a byte if successful.
         let (mut rx, mut tx) = UnixStream::pair().unwrap();
 
-        let ForkResult::Parent(_) = fork().unwrap() else {
+        let ForkResult::Parent(_) = unsafe { fork() }.unwrap() else {
             // Open a new pseudoterminal.
             let leader = Pty::open().unwrap().leader;
             // The pty leader should not have a foreground process group yet.
@@ -258,4 +373,199 @@ mod tests {
         rx.read_exact(&mut buf).unwrap();
         assert_eq!(buf[0], 42);
     }
+
+    #[test]
+    fn raw_mode_with_and_without_signals() {
+        let pty = Pty::open().unwrap();
+        let follower = pty.follower.file.try_clone().unwrap();
+        let lflag = || {
+            let mut term = std::mem::MaybeUninit::<libc::termios>::uninit();
+            crate::cutils::cerr(unsafe {
+                libc::tcgetattr(follower.as_raw_fd(), term.as_mut_ptr())
+            })
+            .unwrap();
+            unsafe { term.assume_init() }.c_lflag
+        };
+        assert_ne!(lflag() & libc::ICANON, 0);
+
+        let mut user_term = UserTerm::from_file(pty.follower.file);
+        user_term.set_raw_mode(false, false).unwrap();
+        assert_eq!(lflag() & (libc::ICANON | libc::ISIG), 0);
+        user_term.set_raw_mode(true, false).unwrap();
+        assert_eq!(lflag() & (libc::ICANON | libc::ISIG), libc::ISIG);
+        user_term.restore(false).unwrap();
+        assert_ne!(lflag() & libc::ICANON, 0);
+    }
+
+    #[test]
+    fn raw_mode_with_and_without_flow_control() {
+        let pty = Pty::open().unwrap();
+        let follower = pty.follower.file.try_clone().unwrap();
+        let iflag = || {
+            let mut term = std::mem::MaybeUninit::<libc::termios>::uninit();
+            crate::cutils::cerr(unsafe {
+                libc::tcgetattr(follower.as_raw_fd(), term.as_mut_ptr())
+            })
+            .unwrap();
+            unsafe { term.assume_init() }.c_iflag
+        };
+        assert_ne!(iflag() & libc::IXON, 0);
+
+        // output that was stopped with Ctrl-S is started again, as Ctrl-Q is passed on now
+        unsafe { libc::tcflow(follower.as_raw_fd(), libc::TCOOFF) };
+        let mut user_term = UserTerm::from_file(pty.follower.file);
+        user_term.set_raw_mode(false, false).unwrap();
+        assert_eq!(iflag() & libc::IXON, 0);
+        // writing to a terminal that is stopped would block
+        unsafe { libc::fcntl(follower.as_raw_fd(), libc::F_SETFL, libc::O_NONBLOCK) };
+        user_term.write_all(b"x").unwrap();
+        let mut pollfd = libc::pollfd {
+            fd: pty.leader.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ready = crate::system::poll::poll(
+            std::slice::from_mut(&mut pollfd),
+            Some(std::time::Duration::from_secs(5)),
+        );
+        assert_eq!(ready.unwrap(), 1);
+
+        user_term.set_raw_mode(false, true).unwrap();
+        assert_ne!(iflag() & libc::IXON, 0);
+        user_term.restore(false).unwrap();
+    }
+
+    #[test]
+    fn copies_terminal_settings() {
+        let user_pty = Pty::open().unwrap();
+        let pty = Pty::open().unwrap();
+        let mut user_term = UserTerm::from_file(user_pty.follower.file);
+
+        user_term.set_raw_mode(false, false).unwrap();
+        let user_settings = TermSettings::of(&user_term).unwrap();
+        let differences = user_settings.differences(&TermSettings::of(&pty.follower).unwrap());
+        assert!(differences.contains(&"local flags"));
+        assert!(differences.contains(&"output flags"));
+
+        user_term.copy_to(&pty.follower).unwrap();
+        assert_eq!(TermSettings::of(&pty.follower).unwrap(), user_settings);
+    }
+
+    /// Type `key` on a terminal in raw mode and return what the process that has it as its
+    /// controlling terminal got: the number of the signal, or the byte that was read.
+    fn type_in_raw_mode(key: u8, with_signals: bool) -> i32 {
+        use crate::system::{
+            interface::ProcessId,
+            wait::{Wait, WaitOptions},
+        };
+
+        let (mut rx, mut tx) = UnixStream::pair().unwrap();
+        let pty = Pty::open().unwrap();
+
+        let ForkResult::Parent(child_pid) = unsafe { fork() }.unwrap() else {
+            // Block the signals so we can wait for them: the process group of this new session
+            // is orphaned, so the default action of SIGTSTP would be to ignore it.
+            let mut set = std::mem::MaybeUninit::<libc::sigset_t>::uninit();
+            let set = unsafe {
+                libc::sigemptyset(set.as_mut_ptr());
+                libc::sigaddset(set.as_mut_ptr(), libc::SIGINT);
+                libc::sigaddset(set.as_mut_ptr(), libc::SIGTSTP);
+                libc::sigprocmask(libc::SIG_BLOCK, set.as_ptr(), null_mut());
+                set.assume_init()
+            };
+            setsid().unwrap();
+            pty.follower.make_controlling_terminal().unwrap();
+            let mut user_term = UserTerm::from_file(pty.follower.file);
+            user_term.set_raw_mode(with_signals, false).unwrap();
+            tx.write_all(&[0]).unwrap();
+
+            let got = if with_signals {
+                let mut signal = 0;
+                unsafe { libc::sigwait(&set, &mut signal) };
+                signal
+            } else {
+                let mut buf = [0];
+                user_term.read_exact(&mut buf).unwrap();
+                buf[0].into()
+            };
+            exit(got);
+        };
+
+        drop(tx);
+        rx.read_exact(&mut [0]).unwrap();
+        let mut leader = pty.leader;
+        leader.write_all(&[key]).unwrap();
+
+        let (_, status) = (child_pid as ProcessId).wait(WaitOptions::new()).unwrap();
+        status.exit_status().unwrap()
+    }
+
+    #[test]
+    fn interrupt_and_suspend_keys() {
+        const CTRL_C: u8 = 0x03;
+        const CTRL_Z: u8 = 0x1a;
+
+        // by default the keys are passed on as they are, like any other input
+        assert_eq!(type_in_raw_mode(CTRL_C, false), CTRL_C.into());
+        assert_eq!(type_in_raw_mode(CTRL_Z, false), CTRL_Z.into());
+        // otherwise the terminal turns them into signals
+        assert_eq!(type_in_raw_mode(CTRL_C, true), libc::SIGINT);
+        assert_eq!(type_in_raw_mode(CTRL_Z, true), libc::SIGTSTP);
+    }
+
+    /// A pty that only records what is done to it; `broken_chown` makes changing the owner
+    /// appear to succeed without having an effect.
+    struct FakePty {
+        state: std::cell::Cell<(UserId, GroupId, libc::mode_t)>,
+        broken_chown: bool,
+    }
+
+    impl FakePty {
+        fn new(broken_chown: bool) -> Self {
+            Self {
+                state: (0, 0, 0o666).into(),
+                broken_chown,
+            }
+        }
+    }
+
+    impl PtyOwnership for FakePty {
+        fn chown(&self, uid: UserId, gid: Option<GroupId>) -> std::io::Result<()> {
+            if !self.broken_chown {
+                let (_, group, mode) = self.state.get();
+                self.state.set((uid, gid.unwrap_or(group), mode));
+            }
+            Ok(())
+        }
+
+        fn chmod(&self, mode: libc::mode_t) -> std::io::Result<()> {
+            let (owner, group, _) = self.state.get();
+            self.state.set((owner, group, mode));
+            Ok(())
+        }
+
+        fn owner_and_mode(&self) -> std::io::Result<(UserId, GroupId, libc::mode_t)> {
+            Ok(self.state.get())
+        }
+    }
+
+    #[test]
+    fn sets_pty_owner_and_mode() {
+        let pty = FakePty::new(false);
+        set_pty_owner(&pty, 1000, Some(5)).unwrap();
+        assert_eq!(pty.state.get(), (1000, 5, 0o620));
+
+        // without a group, nobody else may write to the pty
+        let pty = FakePty::new(false);
+        set_pty_owner(&pty, 1000, None).unwrap();
+        assert_eq!(pty.state.get(), (1000, 0, 0o600));
+
+        let pty = FakePty::new(true);
+        assert!(set_pty_owner(&pty, 1000, Some(5)).is_err());
+
+        let pty = Pty::open().unwrap();
+        let euid = unsafe { libc::geteuid() };
+        set_pty_owner(&pty.follower, euid, None).unwrap();
+        assert_eq!(pty.follower.owner_and_mode().unwrap().2, 0o600);
+    }
 }
@@ -11,17 +11,27 @@ use std::{
 };
 
 use libc::{
-    c_void, cfgetispeed, cfgetospeed, cfmakeraw, cfsetispeed, cfsetospeed, ioctl, sigaction,
-    sigemptyset, sighandler_t, siginfo_t, sigset_t, tcflag_t, tcgetattr, tcsetattr, termios,
-    winsize, CS7, CS8, ECHO, ECHOCTL, ECHOE, ECHOK, ECHOKE, ECHONL, ICANON, ICRNL, IEXTEN, IGNCR,
-    IGNPAR, IMAXBEL, INLCR, INPCK, ISIG, ISTRIP, IUTF8, IXANY, IXOFF, IXON, NOFLSH, OCRNL, OLCUC,
-    ONLCR, ONLRET, ONOCR, OPOST, PARENB, PARMRK, PARODD, PENDIN, SIGTTOU, TCSADRAIN, TCSAFLUSH,
-    TIOCGWINSZ, TIOCSWINSZ, TOSTOP,
+    c_void, cc_t, cfgetispeed, cfgetospeed, cfmakeraw, cfsetispeed, cfsetospeed, ioctl, sigaction,
+    sigemptyset, sighandler_t, siginfo_t, sigset_t, speed_t, tcflag_t, tcgetattr, tcsetattr,
+    termios, winsize, _POSIX_VDISABLE, CS7, CS8, ECHO, ECHOCTL, ECHOE, ECHOK, ECHOKE, ECHONL,
+    ICANON, ICRNL, IEXTEN, IGNCR, IGNPAR, IMAXBEL, INLCR, INPCK, ISIG, ISTRIP, IXANY, IXOFF, IXON,
+    NCCS, NOFLSH, OCRNL, ONLCR, ONLRET, ONOCR, OPOST, PARENB, PARMRK, PARODD, PENDIN, SIGTTOU,
+    TCSADRAIN, TCSAFLUSH, TIOCGWINSZ, TIOCSWINSZ, TOSTOP, VEOF,
 };
 
 use super::{TermSize, Terminal};
 use crate::{cutils::cerr, system::interface::ProcessId};
 
+// not every platform has these flags, in which case there is nothing to copy
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use libc::IUTF8;
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+const IUTF8: tcflag_t = 0;
+#[cfg(any(target_os = "linux", target_os = "openbsd"))]
+use libc::OLCUC;
+#[cfg(not(any(target_os = "linux", target_os = "openbsd")))]
+const OLCUC: tcflag_t = 0;
+
 const INPUT_FLAGS: tcflag_t = IGNPAR
     | PARMRK
     | INPCK
@@ -74,6 +84,7 @@ fn tcsetattr_nobg(fd: c_int, flags: c_int, tp: *const termios) -> io::Result<()>
             unsafe { sa_mask.assume_init() }
         },
         sa_flags: 0,
+        #[cfg(target_os = "linux")]
         sa_restorer: None,
     };
     // Reset `GOT_SIGTTOU`.
@@ -99,11 +110,80 @@ fn tcsetattr_nobg(fd: c_int, flags: c_int, tp: *const termios) -> io::Result<()>
     result
 }
 
+/// The settings of a terminal that [UserTerm::copy_to] copies to another terminal, so that it can
+/// be checked whether two terminals agree, or whether the settings of a terminal changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TermSettings {
+    input: tcflag_t,
+    output: tcflag_t,
+    control: tcflag_t,
+    local: tcflag_t,
+    special_chars: [cc_t; NCCS],
+    input_speed: speed_t,
+    output_speed: speed_t,
+}
+
+impl TermSettings {
+    /// Get the settings of the terminal `fd`.
+    pub fn of<F: AsRawFd>(fd: &F) -> io::Result<Self> {
+        let mut term = MaybeUninit::<termios>::uninit();
+        cerr(unsafe { tcgetattr(fd.as_raw_fd(), term.as_mut_ptr()) })?;
+
+        Ok(Self::from_termios(&unsafe { term.assume_init() }))
+    }
+
+    fn from_termios(term: &termios) -> Self {
+        let output_speed = match unsafe { cfgetospeed(term) } {
+            // `copy_to` does not copy a zero output speed, which would close the connection.
+            libc::B0 => libc::B38400,
+            speed => speed,
+        };
+
+        Self {
+            input: term.c_iflag & INPUT_FLAGS,
+            output: term.c_oflag & OUTPUT_FLAGS,
+            control: term.c_cflag & CONTROL_FLAGS,
+            local: term.c_lflag & LOCAL_FLAGS,
+            special_chars: term.c_cc,
+            input_speed: unsafe { cfgetispeed(term) },
+            output_speed,
+        }
+    }
+
+    /// The character that ends the input when it is typed at the start of a line, unless it is
+    /// disabled.
+    pub fn eof_char(&self) -> Option<u8> {
+        let eof = self.special_chars[VEOF];
+        (eof != _POSIX_VDISABLE).then_some(eof)
+    }
+
+    /// The names of the settings that differ from `other`.
+    pub fn differences(&self, other: &Self) -> Vec<&'static str> {
+        [
+            ("input flags", self.input != other.input),
+            ("output flags", self.output != other.output),
+            ("control flags", self.control != other.control),
+            ("local flags", self.local != other.local),
+            (
+                "special characters",
+                self.special_chars != other.special_chars,
+            ),
+            ("input speed", self.input_speed != other.input_speed),
+            ("output speed", self.output_speed != other.output_speed),
+        ]
+        .into_iter()
+        .filter_map(|(name, differs)| differs.then_some(name))
+        .collect()
+    }
+}
+
 /// Type to manipulate the settings of the user's terminal.
 pub struct UserTerm {
     tty: File,
     original_termios: MaybeUninit<termios>,
     changed: bool,
+    /// Whether the terminal was put in exclusive mode by [`UserTerm::set_exclusive`].
+    exclusive: bool,
 }
 
 impl UserTerm {
@@ -113,9 +193,21 @@ impl UserTerm {
             tty: OpenOptions::new().read(true).write(true).open("/dev/tty")?,
             original_termios: MaybeUninit::uninit(),
             changed: false,
+            exclusive: false,
         })
     }
 
+    /// Use an already opened terminal as the user's terminal.
+    #[cfg(test)]
+    pub(super) fn from_file(tty: File) -> Self {
+        Self {
+            tty,
+            original_termios: MaybeUninit::uninit(),
+            changed: false,
+            exclusive: false,
+        }
+    }
+
     pub(crate) fn get_size(&self) -> io::Result<TermSize> {
         let mut term_size = MaybeUninit::<TermSize>::uninit();
 
@@ -181,8 +273,11 @@ impl UserTerm {
     }
 
     /// Set the user's terminal to raw mode. Enable terminal signals if `with_signals` is set to
-    /// `true`.
-    pub fn set_raw_mode(&mut self, with_signals: bool) -> io::Result<()> {
+    /// `true`, and keep its software flow control if `with_flow_control` is.
+    ///
+    /// Without flow control, Ctrl-S and Ctrl-Q are passed on like any other input, so that they
+    /// stop and start the output of the command in the pty instead of the output of sudo.
+    pub fn set_raw_mode(&mut self, with_signals: bool, with_flow_control: bool) -> io::Result<()> {
         let fd = self.tty.as_raw_fd();
 
         if !self.changed {
@@ -194,12 +289,22 @@ impl UserTerm {
         unsafe { cfmakeraw(&mut term) };
         // Enable terminal signals.
         if with_signals {
-            term.c_cflag |= ISIG;
+            term.c_lflag |= ISIG;
+        }
+        // Keep flow control as it was.
+        let original = unsafe { self.original_termios.assume_init_ref() };
+        if with_flow_control {
+            term.c_iflag |= original.c_iflag & (IXON | IXOFF | IXANY);
         }
 
         tcsetattr_nobg(fd, TCSADRAIN, &term)?;
         self.changed = true;
 
+        // Output that was stopped with Ctrl-S could not be started with Ctrl-Q anymore.
+        if !with_flow_control {
+            unsafe { libc::tcflow(fd, libc::TCOON) };
+        }
+
         Ok(())
     }
 
@@ -218,6 +323,30 @@ impl UserTerm {
         Ok(())
     }
 
+    /// Put the terminal in exclusive mode, in which it cannot be opened again by processes without
+    /// `CAP_SYS_ADMIN`, or take it out of that mode. A terminal that was already in exclusive
+    /// mode is left alone, and the mode is taken away again when this is dropped.
+    pub fn set_exclusive(&mut self, exclusive: bool) -> io::Result<()> {
+        let fd = self.tty.as_raw_fd();
+        if exclusive && !self.exclusive {
+            #[cfg(target_os = "linux")]
+            {
+                let mut current: c_int = 0;
+                cerr(unsafe { ioctl(fd, libc::TIOCGEXCL, &mut current) })?;
+                if current != 0 {
+                    return Ok(());
+                }
+            }
+            cerr(unsafe { ioctl(fd, libc::TIOCEXCL as _) })?;
+            self.exclusive = true;
+        } else if !exclusive && self.exclusive {
+            cerr(unsafe { ioctl(fd, libc::TIOCNXCL as _) })?;
+            self.exclusive = false;
+        }
+
+        Ok(())
+    }
+
     /// This is like `tcsetpgrp` but it only suceeds if we are in the foreground process group.
     pub fn tcsetpgrp_nobg(&self, pgrp: ProcessId) -> io::Result<()> {
         // This function is based around the fact that we receive `SIGTTOU` if we call `tcsetpgrp` and
@@ -235,6 +364,7 @@ impl UserTerm {
                 unsafe { sa_mask.assume_init() }
             },
             sa_flags: 0,
+            #[cfg(target_os = "linux")]
             sa_restorer: None,
         };
         // Reset `GOT_SIGTTOU`.
@@ -261,6 +391,12 @@ impl UserTerm {
     }
 }
 
+impl Drop for UserTerm {
+    fn drop(&mut self) {
+        self.set_exclusive(false).ok();
+    }
+}
+
 impl AsRawFd for UserTerm {
     fn as_raw_fd(&self) -> RawFd {
         self.tty.as_raw_fd()
@@ -11,7 +11,7 @@ pub struct SystemTime {
 }
 
 impl SystemTime {
-    pub(super) fn new(secs: i64, nsecs: i64) -> SystemTime {
+    pub(crate) fn new(secs: i64, nsecs: i64) -> SystemTime {
         SystemTime {
             secs: secs + nsecs.div_euclid(1_000_000_000),
             nsecs: nsecs.rem_euclid(1_000_000_000),
@@ -19,12 +19,21 @@ impl SystemTime {
     }
 
     pub fn now() -> std::io::Result<SystemTime> {
-        let mut spec = MaybeUninit::<libc::timespec>::uninit();
-        crate::cutils::cerr(unsafe {
-            libc::clock_gettime(libc::CLOCK_BOOTTIME, spec.as_mut_ptr())
-        })?;
-        let spec = unsafe { spec.assume_init() };
-        Ok(spec.into())
+        // the monotonic clock of macOS keeps counting while the system is asleep
+        #[cfg(not(target_os = "macos"))]
+        const CLOCK: libc::clockid_t = libc::CLOCK_BOOTTIME;
+        #[cfg(target_os = "macos")]
+        const CLOCK: libc::clockid_t = libc::CLOCK_MONOTONIC;
+
+        read_clock(CLOCK)
+    }
+
+    pub fn seconds(&self) -> i64 {
+        self.secs
+    }
+
+    pub fn nanoseconds(&self) -> i64 {
+        self.nsecs
     }
 
     pub(super) fn encode(&self, target: &mut impl Write) -> std::io::Result<()> {
@@ -73,6 +82,36 @@ impl Sub<Duration> for SystemTime {
     }
 }
 
+fn read_clock(clock: libc::clockid_t) -> std::io::Result<SystemTime> {
+    let mut spec = MaybeUninit::<libc::timespec>::uninit();
+    crate::cutils::cerr(unsafe { libc::clock_gettime(clock, spec.as_mut_ptr()) })?;
+    let spec = unsafe { spec.assume_init() };
+    Ok(spec.into())
+}
+
+/// The time on each of the clocks of the system, read one right after the other: the wall clock,
+/// which can be adjusted at any moment, the monotonic clock, which cannot, and (on Linux) the
+/// boot clock, which also counts while the system is suspended and is the clock of the kernel log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamps {
+    pub realtime: SystemTime,
+    pub monotonic: SystemTime,
+    pub boottime: Option<SystemTime>,
+}
+
+impl Timestamps {
+    pub fn now() -> std::io::Result<Timestamps> {
+        Ok(Timestamps {
+            realtime: read_clock(libc::CLOCK_REALTIME)?,
+            monotonic: read_clock(libc::CLOCK_MONOTONIC)?,
+            #[cfg(target_os = "linux")]
+            boottime: Some(read_clock(libc::CLOCK_BOOTTIME)?),
+            #[cfg(not(target_os = "linux"))]
+            boottime: None,
+        })
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub struct Duration {
     secs: i64,
@@ -145,6 +184,16 @@ mod tests {
         );
     }
 
+    #[test]
+    fn reads_all_clocks() {
+        let before = Timestamps::now().unwrap();
+        let after = Timestamps::now().unwrap();
+        assert!(before.monotonic <= after.monotonic);
+        // the wall clock is somewhere after 2023
+        assert!(before.realtime.seconds() > 1_700_000_000);
+        assert_eq!(before.boottime.is_some(), cfg!(target_os = "linux"));
+    }
+
     #[test]
     fn test_time_ops() {
         assert_eq!(
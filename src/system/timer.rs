@@ -0,0 +1,146 @@
+//! A timer that can be polled like any other file descriptor, so that an event loop can do
+//! something periodically without a timeout of its own.
+//!
+//! This is a `timerfd` on Linux, and a kqueue with only a timer in it elsewhere, which is readable
+//! when the timer expired.
+use std::{
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    time::Duration,
+};
+
+use crate::cutils::cerr;
+
+pub struct IntervalTimer {
+    fd: OwnedFd,
+}
+
+impl IntervalTimer {
+    /// Create a timer that expires every `interval`, starting `interval` from now.
+    #[cfg(target_os = "linux")]
+    pub fn new(interval: Duration) -> io::Result<Self> {
+        // SAFETY: `timerfd_create` takes no pointers.
+        let fd = cerr(unsafe {
+            libc::timerfd_create(
+                libc::CLOCK_MONOTONIC,
+                libc::TFD_CLOEXEC | libc::TFD_NONBLOCK,
+            )
+        })?;
+        // SAFETY: the file descriptor was just created, and is not owned by anything else.
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let interval = libc::timespec {
+            tv_sec: interval.as_secs() as _,
+            tv_nsec: interval.subsec_nanos() as _,
+        };
+        let spec = libc::itimerspec {
+            it_interval: interval,
+            it_value: interval,
+        };
+        // SAFETY: `spec` is a valid `itimerspec`, and the old value is not asked for.
+        cerr(unsafe { libc::timerfd_settime(fd.as_raw_fd(), 0, &spec, std::ptr::null_mut()) })?;
+
+        Ok(Self { fd })
+    }
+
+    /// Create a timer that expires every `interval`, starting `interval` from now.
+    #[cfg(not(target_os = "linux"))]
+    pub fn new(interval: Duration) -> io::Result<Self> {
+        // SAFETY: `kqueue` takes no arguments.
+        let fd = cerr(unsafe { libc::kqueue() })?;
+        // SAFETY: the file descriptor was just created, and is not owned by anything else.
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        cerr(unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) })?;
+
+        // SAFETY: all fields of `kevent` are integers or pointers, for which zero is valid.
+        let mut event: libc::kevent = unsafe { std::mem::zeroed() };
+        event.filter = libc::EVFILT_TIMER;
+        event.flags = libc::EV_ADD;
+        // in milliseconds, which is the default unit of a timer
+        event.data = interval.as_millis().max(1) as _;
+        // SAFETY: one event is read from a valid pointer, and none are returned.
+        cerr(unsafe {
+            libc::kevent(
+                fd.as_raw_fd(),
+                &event,
+                1,
+                std::ptr::null_mut(),
+                0,
+                std::ptr::null(),
+            )
+        })?;
+
+        Ok(Self { fd })
+    }
+
+    /// Acknowledge that the timer expired, returning how often it did since the last time. This
+    /// fails with [io::ErrorKind::WouldBlock] if it has not expired since.
+    #[cfg(target_os = "linux")]
+    pub fn acknowledge(&self) -> io::Result<u64> {
+        let mut expirations = 0u64;
+        // SAFETY: the buffer is valid for the length that is passed.
+        cerr(unsafe {
+            libc::read(
+                self.fd.as_raw_fd(),
+                (&mut expirations as *mut u64).cast(),
+                std::mem::size_of::<u64>(),
+            )
+        })?;
+
+        Ok(expirations)
+    }
+
+    /// Acknowledge that the timer expired, returning how often it did since the last time. This
+    /// fails with [io::ErrorKind::WouldBlock] if it has not expired since.
+    #[cfg(not(target_os = "linux"))]
+    pub fn acknowledge(&self) -> io::Result<u64> {
+        // SAFETY: see `new`.
+        let mut event: libc::kevent = unsafe { std::mem::zeroed() };
+        let timeout = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: at most one event is written to a valid pointer, without waiting.
+        let count = cerr(unsafe {
+            libc::kevent(
+                self.fd.as_raw_fd(),
+                std::ptr::null(),
+                0,
+                &mut event,
+                1,
+                &timeout,
+            )
+        })?;
+        if count == 0 {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+
+        Ok(event.data as u64)
+    }
+}
+
+impl AsRawFd for IntervalTimer {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, time::Duration};
+
+    use super::IntervalTimer;
+
+    #[test]
+    fn expires_periodically() {
+        let timer = IntervalTimer::new(Duration::from_millis(10)).unwrap();
+        assert_eq!(
+            timer.acknowledge().unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+
+        std::thread::sleep(Duration::from_millis(35));
+        assert!(timer.acknowledge().unwrap() >= 3);
+        assert!(timer.acknowledge().is_err());
+    }
+}
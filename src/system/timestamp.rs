@@ -34,6 +34,49 @@ const SIZE_OF_TS: i64 = std::mem::size_of::<SystemTime>() as i64;
 const SIZE_OF_BOOL: i64 = std::mem::size_of::<BoolStorage>() as i64;
 const MOD_OFFSET: i64 = SIZE_OF_TS + SIZE_OF_BOOL;
 
+/// The random identifier that the kernel generates at every boot.
+type BootId = [u8; 16];
+
+#[cfg(target_os = "linux")]
+const BOOT_ID_PATH: &str = "/proc/sys/kernel/random/boot_id";
+
+/// The identifier of the current boot, or all zeroes if it is not known.
+#[cfg(target_os = "linux")]
+fn current_boot_id() -> BootId {
+    let mut boot_id = BootId::default();
+    let Ok(text) = std::fs::read_to_string(BOOT_ID_PATH) else {
+        return boot_id;
+    };
+
+    // the identifier is written as a UUID, e.g. `a6b1f2c4-0e5d-4c3b-9a8f-1d2e3f4a5b6c`
+    let digits: Vec<u8> = text
+        .trim()
+        .bytes()
+        .filter(|&byte| byte != b'-')
+        .map(|digit| (digit as char).to_digit(16).map(|value| value as u8))
+        .collect::<Option<_>>()
+        .unwrap_or_default();
+    if digits.len() == 2 * boot_id.len() {
+        for (byte, pair) in boot_id.iter_mut().zip(digits.chunks(2)) {
+            *byte = pair[0] << 4 | pair[1];
+        }
+    }
+
+    boot_id
+}
+
+/// Without a random identifier, the time at which the system was started identifies the boot.
+#[cfg(not(target_os = "linux"))]
+fn current_boot_id() -> BootId {
+    let mut boot_id = BootId::default();
+    if let Ok((secs, usecs)) = super::kinfo::boot_time() {
+        boot_id[..8].copy_from_slice(&secs.to_le_bytes());
+        boot_id[8..].copy_from_slice(&usecs.to_le_bytes());
+    }
+
+    boot_id
+}
+
 #[derive(Debug)]
 pub struct SessionRecordFile<'u> {
     file: File,
@@ -42,7 +85,7 @@ pub struct SessionRecordFile<'u> {
 }
 
 impl<'u> SessionRecordFile<'u> {
-    const BASE_PATH: &'static str = "/var/run/sudo-rs/ts";
+    const BASE_PATH: &'static str = "/run/sudo-rs/ts";
 
     pub fn open_for_user(user: &'u str, timeout: Duration) -> io::Result<Self> {
         let mut path = PathBuf::from(Self::BASE_PATH);
@@ -50,11 +93,11 @@ impl<'u> SessionRecordFile<'u> {
         SessionRecordFile::new(user, secure_open_cookie_file(&path)?, timeout)
     }
 
-    const FILE_VERSION: u16 = 1;
+    const FILE_VERSION: u16 = 2;
     const MAGIC_NUM: u16 = 0x50D0;
-    const VERSION_OFFSET: u64 = Self::MAGIC_NUM.to_le_bytes().len() as u64;
-    const FIRST_RECORD_OFFSET: u64 =
-        Self::VERSION_OFFSET + Self::FILE_VERSION.to_le_bytes().len() as u64;
+    const FIRST_RECORD_OFFSET: u64 = (Self::MAGIC_NUM.to_le_bytes().len()
+        + Self::FILE_VERSION.to_le_bytes().len()
+        + std::mem::size_of::<BootId>()) as u64;
 
     /// Create a new SessionRecordFile from the given i/o stream.
     /// Timestamps in this file are considered valid if they were created or
@@ -66,71 +109,85 @@ impl<'u> SessionRecordFile<'u> {
             for_user,
         };
 
+        // another sudo process could be resetting the file at the same time
+        let lock = FileLock::exclusive(&session_records.file, false)?;
+        session_records.file.rewind()?;
+        let header_is_valid = session_records.check_header()?;
+        if !header_is_valid {
+            session_records.write_header()?;
+        }
+        lock.unlock()?;
+
+        // we are ready to read records
+        Ok(session_records)
+    }
+
+    /// Check the magic number, the file version and the boot the records were written in.
+    fn check_header(&mut self) -> io::Result<bool> {
+        let for_user = self.for_user;
+
         // match the magic number, otherwise reset the file
-        match session_records.read_magic()? {
+        match self.read_u16()? {
             Some(magic) if magic == Self::MAGIC_NUM => (),
-            x => {
-                if let Some(_magic) = x {
-                    auth_info!("Session records file for user '{for_user}' is invalid, resetting");
-                }
-
-                session_records.init(Self::VERSION_OFFSET)?;
+            Some(_) => {
+                auth_info!("Session records file for user '{for_user}' is invalid, resetting");
+                return Ok(false);
             }
+            None => return Ok(false),
         }
 
         // match the file version
-        match session_records.read_version()? {
+        match self.read_u16()? {
             Some(v) if v == Self::FILE_VERSION => (),
-            x => {
-                if let Some(v) = x {
-                    auth_info!("Session records file for user '{for_user}' has invalid version {v}, only file version {} is supported, resetting", Self::FILE_VERSION);
-                } else {
-                    auth_info!(
-                        "Session records file did not contain file version information, resetting"
-                    );
-                }
-
-                session_records.init(Self::FIRST_RECORD_OFFSET)?;
+            Some(v) => {
+                auth_info!("Session records file for user '{for_user}' has invalid version {v}, only file version {} is supported, resetting", Self::FILE_VERSION);
+                return Ok(false);
+            }
+            None => {
+                auth_info!(
+                    "Session records file did not contain file version information, resetting"
+                );
+                return Ok(false);
             }
         }
 
-        // we are ready to read records
-        Ok(session_records)
-    }
-
-    /// Read the magic number from the input stream
-    fn read_magic(&mut self) -> io::Result<Option<u16>> {
-        let mut magic_bytes = [0; std::mem::size_of::<u16>()];
-        match self.file.read_exact(&mut magic_bytes) {
-            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
-            Err(e) => Err(e),
-            Ok(()) => Ok(Some(u16::from_le_bytes(magic_bytes))),
+        // the clock that timestamps are taken from starts at zero again after a reboot, so
+        // records of an earlier boot could otherwise be valid again
+        let mut boot_id = BootId::default();
+        match self.file.read_exact(&mut boot_id) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(e) => return Err(e),
+            Ok(()) if boot_id != current_boot_id() => {
+                auth_info!(
+                    "Session records file for user '{for_user}' is from an earlier boot, resetting"
+                );
+                return Ok(false);
+            }
+            Ok(()) => (),
         }
+
+        Ok(true)
     }
 
-    /// Read the version number from the input stream
-    fn read_version(&mut self) -> io::Result<Option<u16>> {
-        let mut version_bytes = [0; std::mem::size_of::<u16>()];
-        match self.file.read_exact(&mut version_bytes) {
+    /// Read a header field from the input stream
+    fn read_u16(&mut self) -> io::Result<Option<u16>> {
+        let mut bytes = [0; std::mem::size_of::<u16>()];
+        match self.file.read_exact(&mut bytes) {
             Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
             Err(e) => Err(e),
-            Ok(()) => Ok(Some(u16::from_le_bytes(version_bytes))),
+            Ok(()) => Ok(Some(u16::from_le_bytes(bytes))),
         }
     }
 
-    /// Initialize a new empty stream. If the stream/file was already filled
-    /// before it will be truncated.
-    fn init(&mut self, offset: u64) -> io::Result<()> {
-        // lock the file to indicate that we are currently writing to it
-        let lock = FileLock::exclusive(&self.file, false)?;
-
+    /// Truncate the file and write a header for the current boot.
+    ///
+    /// This method assumes that the file is already exclusively locked.
+    fn write_header(&mut self) -> io::Result<()> {
         self.file.set_len(0)?;
         self.file.rewind()?;
         self.file.write_all(&Self::MAGIC_NUM.to_le_bytes())?;
         self.file.write_all(&Self::FILE_VERSION.to_le_bytes())?;
-        self.file.seek(io::SeekFrom::Start(offset))?;
-
-        lock.unlock()?;
+        self.file.write_all(&current_boot_id())?;
 
         Ok(())
     }
@@ -190,6 +247,21 @@ impl<'u> SessionRecordFile<'u> {
     /// when one is not found. A record will only be updated if it is still
     /// valid at this time.
     pub fn touch(&mut self, scope: RecordScope, auth_user: UserId) -> io::Result<TouchResult> {
+        self.find_valid(scope, auth_user, true)
+    }
+
+    /// Like [SessionRecordFile::touch], but leave the file as it is: a valid record is reported
+    /// as updated with its current time.
+    pub fn peek(&mut self, scope: RecordScope, auth_user: UserId) -> io::Result<TouchResult> {
+        self.find_valid(scope, auth_user, false)
+    }
+
+    fn find_valid(
+        &mut self,
+        scope: RecordScope,
+        auth_user: UserId,
+        update: bool,
+    ) -> io::Result<TouchResult> {
         // lock the file to indicate that we are currently in a writing operation
         let lock = FileLock::exclusive(&self.file, false)?;
         self.seek_to_first_record()?;
@@ -197,27 +269,33 @@ impl<'u> SessionRecordFile<'u> {
             // only touch if record is enabled
             if record.enabled && record.matches(&scope, auth_user) {
                 let now = SystemTime::now()?;
-                if record.written_between(now - self.timeout, now) {
-                    // move back to where the timestamp is and overwrite with the latest time
+                if !record.written_between(now - self.timeout, now) {
+                    lock.unlock()?;
+                    return Ok(TouchResult::Outdated {
+                        time: record.timestamp,
+                    });
+                }
+
+                let new_time = if update {
+                    // move back to where the timestamp is and overwrite with the time it was
+                    // found valid at, which is not before the time it has
                     self.file.seek(io::SeekFrom::Current(-MOD_OFFSET))?;
-                    let new_time = SystemTime::now()?;
+                    let new_time = now;
                     new_time.encode(&mut self.file)?;
 
                     // make sure we can still go to the end of the record
                     self.file.seek(io::SeekFrom::Current(SIZE_OF_BOOL))?;
-
-                    // writing is done, unlock and return
-                    lock.unlock()?;
-                    return Ok(TouchResult::Updated {
-                        old_time: record.timestamp,
-                        new_time,
-                    });
+                    new_time
                 } else {
-                    lock.unlock()?;
-                    return Ok(TouchResult::Outdated {
-                        time: record.timestamp,
-                    });
-                }
+                    record.timestamp
+                };
+
+                // writing is done, unlock and return
+                lock.unlock()?;
+                return Ok(TouchResult::Updated {
+                    old_time: record.timestamp,
+                    new_time,
+                });
             }
         }
 
@@ -246,7 +324,7 @@ impl<'u> SessionRecordFile<'u> {
 
     /// Create a new record for the given scope and auth user id.
     /// If there is an existing record that matches the scope and auth user,
-    /// then that record will be updated.
+    /// then that record will be updated; its time never goes back.
     pub fn create(&mut self, scope: RecordScope, auth_user: UserId) -> io::Result<CreateResult> {
         // lock the file to indicate that we are currently writing to it
         let lock = FileLock::exclusive(&self.file, false)?;
@@ -254,7 +332,7 @@ impl<'u> SessionRecordFile<'u> {
         while let Some(record) = self.next_record()? {
             if record.matches(&scope, auth_user) {
                 self.file.seek(io::SeekFrom::Current(-MOD_OFFSET))?;
-                let new_time = SystemTime::now()?;
+                let new_time = SystemTime::now()?.max(record.timestamp);
                 new_time.encode(&mut self.file)?;
                 write_bool(true, &mut self.file)?;
                 lock.unlock()?;
@@ -281,7 +359,9 @@ impl<'u> SessionRecordFile<'u> {
 
     /// Completely resets the entire file and removes all records.
     pub fn reset(&mut self) -> io::Result<()> {
-        self.init(0)
+        let lock = FileLock::exclusive(&self.file, false)?;
+        self.write_header()?;
+        lock.unlock()
     }
 
     /// Write a new record at the current position in the file.
@@ -709,32 +789,73 @@ mod tests {
         Ok(v)
     }
 
+    fn header() -> Vec<u8> {
+        let mut header = vec![0xD0, 0x50, 0x02, 0x00];
+        header.extend(current_boot_id());
+        header
+    }
+
     #[test]
     fn session_record_file_header_checks() {
         // valid header should remain valid
-        let c = tempfile_with_data(&[0xD0, 0x50, 0x01, 0x00]).unwrap();
+        let c = tempfile_with_data(&header()).unwrap();
         let timeout = Duration::seconds(30);
         assert!(SessionRecordFile::new("test", c.try_clone().unwrap(), timeout).is_ok());
         let v = data_from_tempfile(c).unwrap();
-        assert_eq!(&v[..], &[0xD0, 0x50, 0x01, 0x00]);
+        assert_eq!(v, header());
 
         // invalid headers should be corrected
         let c = tempfile_with_data(&[0xAB, 0xBA]).unwrap();
         assert!(SessionRecordFile::new("test", c.try_clone().unwrap(), timeout).is_ok());
         let v = data_from_tempfile(c).unwrap();
-        assert_eq!(&v[..], &[0xD0, 0x50, 0x01, 0x00]);
+        assert_eq!(v, header());
 
         // empty header should be filled in
         let c = tempfile_with_data(&[]).unwrap();
         assert!(SessionRecordFile::new("test", c.try_clone().unwrap(), timeout).is_ok());
         let v = data_from_tempfile(c).unwrap();
-        assert_eq!(&v[..], &[0xD0, 0x50, 0x01, 0x00]);
+        assert_eq!(v, header());
 
         // invalid version should reset file
         let c = tempfile_with_data(&[0xD0, 0x50, 0xAB, 0xBA, 0x0, 0x0]).unwrap();
         assert!(SessionRecordFile::new("test", c.try_clone().unwrap(), timeout).is_ok());
         let v = data_from_tempfile(c).unwrap();
-        assert_eq!(&v[..], &[0xD0, 0x50, 0x01, 0x00]);
+        assert_eq!(v, header());
+
+        // files of the previous version, without a boot id, should be reset
+        let c = tempfile_with_data(&[0xD0, 0x50, 0x01, 0x00, 0x0, 0x0]).unwrap();
+        assert!(SessionRecordFile::new("test", c.try_clone().unwrap(), timeout).is_ok());
+        let v = data_from_tempfile(c).unwrap();
+        assert_eq!(v, header());
+    }
+
+    #[test]
+    fn records_of_an_earlier_boot_are_discarded() {
+        let timeout = Duration::seconds(30);
+        let c = tempfile_with_data(&[]).unwrap();
+        let mut srf = SessionRecordFile::new("test", c.try_clone().unwrap(), timeout).unwrap();
+        let scope = RecordScope::Ppid {
+            group_pid: 42,
+            init_time: SystemTime::new(0, 0),
+        };
+        srf.create(scope, 1000).unwrap();
+
+        // pretend that the records were written before the system was rebooted
+        let mut data = data_from_tempfile(c).unwrap();
+        data[4] ^= 0xFF;
+
+        let c = tempfile_with_data(&data).unwrap();
+        let mut srf = SessionRecordFile::new("test", c.try_clone().unwrap(), timeout).unwrap();
+        assert_eq!(srf.touch(scope, 1000).unwrap(), TouchResult::NotFound);
+        assert_eq!(data_from_tempfile(c).unwrap(), header());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn reads_the_boot_id() {
+        if std::path::Path::new(BOOT_ID_PATH).exists() {
+            assert_ne!(current_boot_id(), BootId::default());
+        }
     }
 
     #[test]
@@ -767,11 +888,206 @@ mod tests {
             panic!("Expected record to be updated");
         };
 
+        // peeking leaves the record as it is
+        let TouchResult::Updated { old_time, new_time } = srf.peek(tty_scope, auth_user).unwrap()
+        else {
+            panic!("Expected record to be found");
+        };
+        assert_eq!(old_time, new_time);
+        let TouchResult::Updated { old_time, .. } = srf.touch(tty_scope, auth_user).unwrap() else {
+            panic!("Expected record to be updated");
+        };
+        assert_eq!(old_time, new_time);
+
         // reset the file
         assert!(srf.reset().is_ok());
 
         // after all this the data should be just an empty header
         let data = data_from_tempfile(c).unwrap();
-        assert_eq!(&data, &[0xD0, 0x50, 0x01, 0x00]);
+        assert_eq!(data, header());
+    }
+
+    /// What the records in a file should be after some operations on it: the scopes and users
+    /// of the records, whether they are enabled, and their last time.
+    type Model = Vec<((RecordScope, UserId), bool, SystemTime)>;
+
+    #[derive(Clone, Copy, Debug)]
+    enum Operation {
+        Create(usize),
+        Touch(usize),
+        Peek(usize),
+        Disable(usize),
+        Reset,
+    }
+
+    /// Carry out `operation` on `srf` and on `model`, checking that they agree.
+    fn check_operation(srf: &mut SessionRecordFile, model: &mut Model, operation: Operation) {
+        let keys = [
+            (
+                RecordScope::Ppid {
+                    group_pid: 1,
+                    init_time: SystemTime::new(1, 0),
+                },
+                1000,
+            ),
+            (
+                RecordScope::Ppid {
+                    group_pid: 1,
+                    init_time: SystemTime::new(1, 0),
+                },
+                0,
+            ),
+            (
+                RecordScope::Ppid {
+                    group_pid: 2,
+                    init_time: SystemTime::new(1, 0),
+                },
+                1000,
+            ),
+            (
+                RecordScope::Ppid {
+                    group_pid: 1,
+                    init_time: SystemTime::new(2, 0),
+                },
+                1000,
+            ),
+        ];
+        let find = |model: &Model, key| model.iter().position(|(k, _, _)| *k == key);
+
+        match operation {
+            Operation::Create(i) => {
+                let (scope, user) = keys[i];
+                match (srf.create(scope, user).unwrap(), find(model, keys[i])) {
+                    (CreateResult::Created { time }, None) => model.push((keys[i], true, time)),
+                    (CreateResult::Updated { old_time, new_time }, Some(at)) => {
+                        assert_eq!(old_time, model[at].2);
+                        assert!(new_time >= old_time);
+                        model[at] = (keys[i], true, new_time);
+                    }
+                    _ => panic!("{operation:?} does not agree with {model:?}"),
+                }
+            }
+            Operation::Touch(i) | Operation::Peek(i) => {
+                let (scope, user) = keys[i];
+                let update = matches!(operation, Operation::Touch(_));
+                let result = if update {
+                    srf.touch(scope, user)
+                } else {
+                    srf.peek(scope, user)
+                };
+                match (result.unwrap(), find(model, keys[i])) {
+                    (TouchResult::Updated { old_time, new_time }, Some(at)) if model[at].1 => {
+                        assert_eq!(old_time, model[at].2);
+                        if update {
+                            assert!(new_time >= old_time);
+                        } else {
+                            assert_eq!(new_time, old_time);
+                        }
+                        model[at].2 = new_time;
+                    }
+                    (TouchResult::NotFound, None) => (),
+                    (TouchResult::NotFound, Some(at)) if !model[at].1 => (),
+                    (result, _) => panic!("{operation:?} gave {result:?}, not as in {model:?}"),
+                }
+            }
+            Operation::Disable(i) => {
+                let (scope, user) = keys[i];
+                srf.disable(scope, Some(user)).unwrap();
+                if let Some(at) = find(model, keys[i]) {
+                    model[at].1 = false;
+                }
+            }
+            Operation::Reset => {
+                srf.reset().unwrap();
+                model.clear();
+            }
+        }
+    }
+
+    #[test]
+    fn records_follow_their_model() {
+        let mut operations = vec![Operation::Reset];
+        for i in 0..4 {
+            operations.extend([
+                Operation::Create(i),
+                Operation::Touch(i),
+                Operation::Peek(i),
+                Operation::Disable(i),
+            ]);
+        }
+
+        let c = tempfile_with_data(&[]).unwrap();
+        let mut srf = SessionRecordFile::new("test", c, Duration::seconds(30)).unwrap();
+        // every sequence of three operations, after some records were made
+        for &first in &operations {
+            for &second in &operations {
+                for &third in &operations {
+                    srf.reset().unwrap();
+                    let mut model = Model::new();
+                    for operation in [
+                        Operation::Create(0),
+                        Operation::Create(2),
+                        Operation::Disable(2),
+                        first,
+                        second,
+                        third,
+                        Operation::Touch(0),
+                        Operation::Create(3),
+                    ] {
+                        check_operation(&mut srf, &mut model, operation);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn concurrent_processes_share_records() {
+        let path = std::env::temp_dir().join(format!(
+            "sudo_rs_test_concurrent_records_{}",
+            std::process::id()
+        ));
+        let open = || {
+            let file = File::options()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)
+                .unwrap();
+            SessionRecordFile::new("test", file, Duration::seconds(30)).unwrap()
+        };
+        let scope = |group_pid| RecordScope::Ppid {
+            group_pid,
+            init_time: SystemTime::new(1, 0),
+        };
+
+        // every thread has a file of its own, as every process would, which the others write to
+        std::thread::scope(|threads| {
+            for group_pid in 0..8 {
+                let mut srf = open();
+                threads.spawn(move || {
+                    for _ in 0..20 {
+                        srf.create(scope(group_pid), 1000).unwrap();
+                        srf.create(scope(0), 1000).unwrap();
+                        assert!(matches!(
+                            srf.touch(scope(group_pid), 1000).unwrap(),
+                            TouchResult::Updated { .. }
+                        ));
+                    }
+                });
+            }
+        });
+
+        // one record for every scope
+        let mut srf = open();
+        srf.seek_to_first_record().unwrap();
+        let mut scopes = Vec::new();
+        while let Some(record) = srf.next_record().unwrap() {
+            scopes.push(record.scope);
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(scopes.len(), 8);
+        assert!((0..8).all(|group_pid| scopes.contains(&scope(group_pid))));
     }
 }
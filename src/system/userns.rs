@@ -0,0 +1,105 @@
+//! Telling whether sudo runs in a user namespace other than the initial one, as it does in a
+//! rootless container, and why its setuid bit may not have taken effect there.
+//!
+//! In a user namespace, user ids that are not mapped into it show up as the overflow id (usually
+//! 65534), and being root only means being root within the namespace.
+use std::ffi::CString;
+use std::fs;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use super::interface::UserId;
+
+/// The ids that are reported for files owned by users that are not mapped into the namespace,
+/// unless `/proc/sys/kernel/overflowuid` says otherwise.
+const DEFAULT_OVERFLOW_UID: UserId = 65534;
+
+/// The user ids that are mapped into a user namespace, as in `/proc/self/uid_map`: ranges of ids
+/// inside the namespace, the ids that they are outside of it, and the length of each range.
+#[derive(Debug, PartialEq, Eq)]
+pub struct UidMap(Vec<(UserId, UserId, UserId)>);
+
+impl UidMap {
+    /// The mapping of the user namespace that sudo runs in, or `None` if that is the initial
+    /// namespace, or the system has no user namespaces.
+    pub fn current() -> Option<UidMap> {
+        let map = UidMap::parse(&fs::read_to_string("/proc/self/uid_map").ok()?)?;
+        (!map.is_initial()).then_some(map)
+    }
+
+    fn parse(text: &str) -> Option<UidMap> {
+        text.lines()
+            .map(|line| {
+                let mut fields = line.split_whitespace().map(str::parse);
+                match (fields.next(), fields.next(), fields.next()) {
+                    (Some(Ok(inside)), Some(Ok(outside)), Some(Ok(count))) => {
+                        Some((inside, outside, count))
+                    }
+                    _ => None,
+                }
+            })
+            .collect::<Option<_>>()
+            .map(UidMap)
+    }
+
+    /// Whether this is the mapping of the initial namespace, in which all ids map to themselves.
+    fn is_initial(&self) -> bool {
+        self.0 == [(0, 0, UserId::MAX)]
+    }
+}
+
+/// The id that files owned by users that are not mapped into the namespace appear to be owned by.
+pub fn overflow_uid() -> UserId {
+    fs::read_to_string("/proc/sys/kernel/overflowuid")
+        .ok()
+        .and_then(|text| text.trim().parse().ok())
+        .unwrap_or(DEFAULT_OVERFLOW_UID)
+}
+
+/// Whether sudo was started with the `no_new_privs` flag, which keeps setuid bits from taking
+/// effect; container runtimes set it with e.g. `--security-opt no-new-privileges`.
+#[cfg(target_os = "linux")]
+pub fn no_new_privs() -> bool {
+    // SAFETY: `PR_GET_NO_NEW_PRIVS` takes no arguments, and only returns the flag
+    unsafe { libc::prctl(libc::PR_GET_NO_NEW_PRIVS, 0, 0, 0, 0) == 1 }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn no_new_privs() -> bool {
+    false
+}
+
+/// Whether `path` is on a file system that is mounted with `nosuid`, so that setuid bits of the
+/// programs on it have no effect.
+pub fn is_on_nosuid_mount(path: &Path) -> bool {
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: an all-zero `statvfs` is valid, it only holds numbers
+    let mut info: libc::statvfs = unsafe { mem::zeroed() };
+    // SAFETY: `path` is a C string, and `info` lives through the call
+    if unsafe { libc::statvfs(path.as_ptr(), &mut info) } != 0 {
+        return false;
+    }
+
+    info.f_flag & libc::ST_NOSUID != 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_uid_maps() {
+        let initial = UidMap::parse("         0          0 4294967295\n").unwrap();
+        assert!(initial.is_initial());
+
+        // a rootless container: root is the invoking user, others are subordinate ids
+        let rootless = UidMap::parse("0 1000 1\n1 100000 65536\n").unwrap();
+        assert_eq!(rootless, UidMap(vec![(0, 1000, 1), (1, 100000, 65536)]));
+        assert!(!rootless.is_initial());
+
+        assert_eq!(UidMap::parse("0 1000\n"), None);
+    }
+}
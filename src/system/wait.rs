@@ -1,8 +1,8 @@
-use std::io;
+use std::{io, time::Duration};
 
 use libc::{
     c_int, WEXITSTATUS, WIFCONTINUED, WIFEXITED, WIFSIGNALED, WIFSTOPPED, WNOHANG, WSTOPSIG,
-    WTERMSIG, WUNTRACED, __WALL,
+    WTERMSIG, WUNTRACED,
 };
 
 use crate::cutils::cerr;
@@ -26,15 +26,23 @@ pub(crate) trait Wait: sealed::Sealed {
 impl Wait for ProcessId {
     fn wait(self, options: WaitOptions) -> Result<(ProcessId, WaitStatus), WaitError> {
         let mut status: c_int = 0;
+        // SAFETY: `rusage` only consists of integers, for which zero is a valid value
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
 
-        let pid = cerr(unsafe { libc::waitpid(self, &mut status, options.flags) })
+        let pid = cerr(unsafe { libc::wait4(self, &mut status, options.flags, &mut usage) })
             .map_err(WaitError::Io)?;
 
         if pid == 0 && options.flags & WNOHANG != 0 {
             return Err(WaitError::NotReady);
         }
 
-        Ok((pid, WaitStatus { status }))
+        Ok((
+            pid,
+            WaitStatus {
+                status,
+                usage: ResourceUsage::from(&usage),
+            },
+        ))
     }
 }
 
@@ -73,8 +81,15 @@ impl WaitOptions {
     }
 
     /// Wait for all children, regardless of being created using `clone` or not.
+    #[cfg(target_os = "linux")]
     pub const fn all(mut self) -> Self {
-        self.flags |= __WALL;
+        self.flags |= libc::__WALL;
+        self
+    }
+
+    /// Only Linux has children that are not created with `fork`, so there is nothing to add.
+    #[cfg(not(target_os = "linux"))]
+    pub const fn all(self) -> Self {
         self
     }
 }
@@ -82,6 +97,33 @@ impl WaitOptions {
 /// The status of the waited child.
 pub struct WaitStatus {
     status: c_int,
+    usage: ResourceUsage,
+}
+
+/// The resources that a child and the children that it waited for used, as reported by `wait4`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// The time spent executing in user mode.
+    pub user_time: Duration,
+    /// The time spent executing in kernel mode.
+    pub system_time: Duration,
+    /// The maximum resident set size, in kilobytes.
+    pub max_rss: u64,
+}
+
+impl From<&libc::rusage> for ResourceUsage {
+    fn from(usage: &libc::rusage) -> Self {
+        let duration = |time: libc::timeval| {
+            Duration::from_secs(time.tv_sec.try_into().unwrap_or_default())
+                + Duration::from_micros(time.tv_usec.try_into().unwrap_or_default())
+        };
+
+        Self {
+            user_time: duration(usage.ru_utime),
+            system_time: duration(usage.ru_stime),
+            max_rss: usage.ru_maxrss.try_into().unwrap_or_default(),
+        }
+    }
 }
 
 impl std::fmt::Debug for WaitStatus {
@@ -149,6 +191,11 @@ impl WaitStatus {
     pub const fn did_continue(&self) -> bool {
         WIFCONTINUED(self.status)
     }
+
+    /// Return the resources that the child used, which are only known once it terminated.
+    pub fn resource_usage(&self) -> Option<ResourceUsage> {
+        (self.did_exit() || self.was_signaled()).then_some(self.usage)
+    }
 }
 
 #[cfg(test)]
@@ -180,6 +227,7 @@ mod tests {
         assert!(!status.was_stopped());
         assert!(status.stop_signal().is_none());
         assert!(!status.did_continue());
+        assert!(status.resource_usage().unwrap().max_rss > 0);
 
         // Waiting when there are no children should fail.
         let WaitError::Io(err) = command_pid.wait(WaitOptions::new()).unwrap_err() else {
@@ -202,6 +250,7 @@ mod tests {
         let (pid, status) = command_pid.wait(WaitOptions::new().untraced()).unwrap();
         assert_eq!(command_pid, pid);
         assert_eq!(status.stop_signal(), Some(SIGSTOP));
+        assert!(status.resource_usage().is_none());
 
         kill(command_pid, SIGKILL).unwrap();
 
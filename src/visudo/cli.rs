@@ -29,6 +29,12 @@ pub(crate) enum VisudoAction {
     Version,
     Check,
     Run,
+    Schema,
+    /// Explain how the sudoers file decides on `user` (as `user@host`) running `command`.
+    Explain {
+        user: String,
+        command: Vec<String>,
+    },
 }
 
 type OptionSetter = fn(&mut VisudoOptions, Option<String>) -> Result<(), String>;
@@ -51,6 +57,18 @@ impl VisudoOptions {
                 Ok(())
             },
         },
+        VisudoOption {
+            short: 'E',
+            long: "explain",
+            takes_argument: true,
+            set: |options, argument| {
+                options.action = VisudoAction::Explain {
+                    user: argument.ok_or("option requires an argument -- 'E'")?,
+                    command: Vec::new(),
+                };
+                Ok(())
+            },
+        },
         VisudoOption {
             short: 'f',
             long: "file",
@@ -105,6 +123,21 @@ impl VisudoOptions {
                 Ok(())
             },
         },
+        VisudoOption {
+            short: 'x',
+            long: "export",
+            takes_argument: true,
+            set: |options, argument| {
+                match argument.as_deref() {
+                    Some("schema") => options.action = VisudoAction::Schema,
+                    Some(what) => Err(format!(
+                        "cannot export '{what}', only the schema of the settings can be exported"
+                    ))?,
+                    None => Err("option requires an argument -- 'x'")?,
+                }
+                Ok(())
+            },
+        },
         VisudoOption {
             short: 'O',
             long: "owner",
@@ -193,6 +226,10 @@ impl VisudoOptions {
                         Err(format!("unrecognized option '{}'", char))?;
                     }
                 }
+            } else if let VisudoAction::Explain { command, .. } = &mut options.action {
+                // the command to explain takes the rest of the arguments, along with its options
+                command.push(arg);
+                command.extend(arg_iter.by_ref());
             } else {
                 // If the arg doesn't start with a `-` it must be a file argument. However `-f`
                 // must take precedence
@@ -202,6 +239,12 @@ impl VisudoOptions {
             }
         }
 
+        if let VisudoAction::Explain { command, .. } = &options.action {
+            if command.is_empty() {
+                Err("'--explain' needs a command to explain")?;
+            }
+        }
+
         Ok(options)
     }
 }
@@ -1,15 +1,18 @@
-pub(crate) const USAGE_MSG: &str = "usage: visudo [-chqsV] [[-f] sudoers ]";
+pub(crate) const USAGE_MSG: &str = "usage: visudo [-chqsV] [-x schema] [[-f] sudoers ]\n       visudo [-f sudoers] -E user[@host] command [arg ...]";
 
 const DESCRIPTOR: &str = "visudo - safely edit the sudoers file";
 
 const HELP_MSG: &str = "Options:
   -c, --check              check-only mode
+  -E, --explain=user[@host]
+                           explain which rules and Defaults decide on the command
   -f, --file=sudoers       specify sudoers file location
   -h, --help               display help message and exit
   -I, --no-includes        do not edit include files
   -q, --quiet              less verbose (quiet) syntax error messages
   -s, --strict             strict syntax checking
   -V, --version            display version information and exit
+  -x, --export=schema      list the settings that Defaults accepts and exit
 ";
 
 pub(crate) fn long_help_message() -> String {
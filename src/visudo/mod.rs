@@ -5,16 +5,22 @@ use std::{
     ffi::{CStr, CString, OsString},
     fs::{File, Permissions},
     io::{self, Read, Seek, Write},
-    os::unix::prelude::{MetadataExt, OsStringExt, PermissionsExt},
+    os::unix::prelude::{MetadataExt, OpenOptionsExt, OsStringExt, PermissionsExt},
     path::{Path, PathBuf},
     process::Command,
 };
 
 use crate::{
-    sudoers::Sudoers,
+    common::resolve::{canonicalize, resolve_path, resolve_target_user_and_group},
+    log::{auth_info, auth_warn, SudoLogger},
+    sudo::{
+        conf::{SudoConf, SUDO_CONF_PATH},
+        diagnostic::cited_error,
+    },
+    sudoers::{self, PreJudgementPolicy, Request, Settings, Sudoers},
     system::{
-        can_execute,
         file::{Chown, FileLock},
+        hostname,
         signal::{consts::*, register_handlers, SignalStream},
         User,
     },
@@ -32,6 +38,8 @@ macro_rules! io_msg {
 }
 
 pub fn main() {
+    SudoLogger::new("visudo: ").into_global_logger();
+
     let options = match VisudoOptions::from_env() {
         Ok(options) => options,
         Err(error) => {
@@ -49,6 +57,19 @@ pub fn main() {
             println_ignore_io_error!("visudo version {VERSION}");
             std::process::exit(0);
         }
+        VisudoAction::Schema => {
+            print_schema();
+            std::process::exit(0);
+        }
+        VisudoAction::Explain { user, command } => {
+            match explain(options.file.as_deref(), &user, command) {
+                Ok(allowed) => std::process::exit(if allowed { 0 } else { 1 }),
+                Err(error) => {
+                    eprintln_ignore_io_error!("visudo: {error}");
+                    std::process::exit(1);
+                }
+            }
+        }
         VisudoAction::Check => check,
         VisudoAction::Run => run,
     };
@@ -62,6 +83,19 @@ pub fn main() {
     }
 }
 
+/// List every setting with its type, default value and the ways to write it.
+fn print_schema() {
+    for schema in crate::defaults::all_schemas() {
+        println_ignore_io_error!(
+            "{} ({}, default: {})\n    {}",
+            schema.name,
+            schema.type_name(),
+            schema.default_value(),
+            schema.syntax()
+        );
+    }
+}
+
 fn check(file_arg: Option<&str>, perms: bool, owner: bool) -> io::Result<()> {
     let sudoers_path = Path::new(file_arg.unwrap_or("/etc/sudoers"));
 
@@ -103,15 +137,97 @@ fn check(file_arg: Option<&str>, perms: bool, owner: bool) -> io::Result<()> {
 
     if errors.is_empty() {
         writeln!(io::stdout(), "{}: parsed OK", sudoers_path.display())?;
+        if let (None, Some(cache)) = (file_arg, load_sudo_conf().sudoers_cache) {
+            update_cache(sudoers_path, &cache);
+        }
         return Ok(());
     }
 
+    report_errors(errors)?;
+
+    Err(io::Error::new(io::ErrorKind::Other, "invalid sudoers file"))
+}
+
+/// Explain how the sudoers file decides on `user_at_host` (a user name, optionally followed by
+/// `@host`) running `command` as the `runas_default` user, and return whether it is allowed.
+fn explain(file_arg: Option<&str>, user_at_host: &str, command: Vec<String>) -> io::Result<bool> {
+    let sudoers_path = Path::new(file_arg.unwrap_or("/etc/sudoers"));
+    let sudoers_file = File::open(sudoers_path)
+        .map_err(|err| io_msg!(err, "unable to open {}", sudoers_path.display()))?;
+    let (sudoers, errors) = Sudoers::read(&sudoers_file, sudoers_path)?;
+    if !errors.is_empty() {
+        report_errors(errors)?;
+        return Err(io::Error::new(io::ErrorKind::Other, "invalid sudoers file"));
+    }
+
+    let (user_name, host) = match user_at_host.split_once('@') {
+        Some((user_name, host)) => (user_name, host.to_string()),
+        None => (user_at_host, hostname()),
+    };
+    let not_found = |what: String| io::Error::new(io::ErrorKind::NotFound, what);
+    let user = User::from_name(user_name)?
+        .ok_or_else(|| not_found(format!("unknown user: {user_name}")))?;
+    let (target_user, target_group) = resolve_target_user_and_group(
+        &Some(sudoers.runas_default()),
+        &None,
+        &user,
+        sudoers.runas_allow_unknown_id(),
+    )
+    .map_err(|err| not_found(err.to_string()))?;
+
+    let mut arguments = command;
+    let command = PathBuf::from(arguments.remove(0));
+    let path = sudoers
+        .secure_path()
+        .unwrap_or_else(|| std::env::var("PATH").unwrap_or_default());
+    let resolved = if command.to_string_lossy().contains('/') {
+        Some(command.clone())
+    } else {
+        resolve_path(&command, &path)
+    };
+    let command = resolved
+        .and_then(|command| canonicalize(command).ok())
+        .ok_or_else(|| not_found(format!("{}: command not found", command.display())))?;
+
+    let request = Request {
+        user: &target_user,
+        group: &target_group,
+        command: &command,
+        arguments: &arguments,
+    };
+    let explanation = sudoers.explain(&user, &host, request);
+    writeln!(
+        io::stdout(),
+        "{} on {host} running {} as {}:\n{explanation}",
+        user.name,
+        std::iter::once(command.display().to_string())
+            .chain(arguments)
+            .collect::<Vec<_>>()
+            .join(" "),
+        target_user.name,
+    )?;
+
+    Ok(explanation
+        .deciding_rule()
+        .is_some_and(|(_, allowed)| allowed))
+}
+
+/// Show every problem that was found, citing the line it is on if it is known.
+fn report_errors(errors: Vec<sudoers::Error>) -> io::Result<()> {
     let mut stderr = io::stderr();
-    for crate::sudoers::Error(_position, message) in errors {
-        writeln!(stderr, "syntax error: {message}")?;
+    for sudoers::Error {
+        source,
+        location,
+        message,
+    } in errors
+    {
+        match (source, location) {
+            (Some(path), Some(range)) => cited_error(&message, range, path),
+            _ => writeln!(stderr, "syntax error: {message}")?,
+        }
     }
 
-    Err(io::Error::new(io::ErrorKind::Other, "invalid sudoers file"))
+    Ok(())
 }
 
 fn run(file_arg: Option<&str>, perms: bool, owner: bool) -> io::Result<()> {
@@ -176,6 +292,8 @@ fn run(file_arg: Option<&str>, perms: bool, owner: bool) -> io::Result<()> {
 
     tmp_file.set_permissions(Permissions::from_mode(0o700))?;
 
+    let conf = load_sudo_conf();
+
     let result = edit_sudoers_file(
         existed,
         sudoers_file,
@@ -183,10 +301,15 @@ fn run(file_arg: Option<&str>, perms: bool, owner: bool) -> io::Result<()> {
         lock,
         tmp_file,
         &tmp_path,
+        conf.sudoers_backups,
     );
 
     std::fs::remove_dir_all(tmp_dir)?;
 
+    if let (Ok(()), None, Some(cache)) = (&result, file_arg, &conf.sudoers_cache) {
+        update_cache(sudoers_path, cache);
+    }
+
     result
 }
 
@@ -197,6 +320,7 @@ fn edit_sudoers_file(
     lock: FileLock,
     mut tmp_file: File,
     tmp_path: &Path,
+    backups: u32,
 ) -> io::Result<()> {
     let mut editor_path = None;
     let mut sudoers_contents = Vec::new();
@@ -215,9 +339,12 @@ fn edit_sudoers_file(
         }
     }
 
+    // without a valid sudoers file, the default `editor` and `env_editor` are used
     let editor_path = match editor_path {
         Some(path) => path,
-        None => editor_path_fallback()?,
+        None => Settings::default()
+            .editor()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "cannot find text editor"))?,
     };
 
     let mut stderr = io::stderr();
@@ -245,9 +372,7 @@ fn edit_sudoers_file(
 
         writeln!(stderr, "Come on... you can do better than that.\n")?;
 
-        for crate::sudoers::Error(_position, message) in errors {
-            writeln!(stderr, "syntax error: {message}")?;
-        }
+        report_errors(errors)?;
 
         writeln!(stderr)?;
 
@@ -281,7 +406,21 @@ fn edit_sudoers_file(
     if tmp_contents == sudoers_contents {
         writeln!(stderr, "visudo: {} unchanged", tmp_path.display())?;
     } else {
+        if existed && backups > 0 {
+            if let Err(err) = keep_backup(sudoers_path, &sudoers_contents, backups) {
+                auth_warn!(
+                    "unable to keep a backup of {}: {err}",
+                    sudoers_path.display()
+                );
+            }
+        }
         sudoers_file.write_all(&tmp_contents)?;
+        sudoers_file.set_len(tmp_contents.len() as u64)?;
+        record_change(
+            sudoers_path,
+            existed.then_some(&sudoers_contents),
+            &tmp_contents,
+        );
     }
 
     lock.unlock()?;
@@ -289,16 +428,101 @@ fn edit_sudoers_file(
     Ok(())
 }
 
-fn editor_path_fallback() -> io::Result<PathBuf> {
-    let path = Path::new("/usr/bin/editor");
-    if can_execute(path) {
-        return Ok(path.to_owned());
+/// The name of the `number`th backup of the sudoers file at `sudoers_path`, which the
+/// `@includedir` directive skips because of the dot in it.
+fn backup_path(sudoers_path: &Path, number: u32) -> PathBuf {
+    let mut path = sudoers_path.as_os_str().to_owned();
+    path.push(format!(".{number}"));
+    path.into()
+}
+
+/// Keep the `contents` that the sudoers file had as its first backup, after moving the earlier
+/// backups one place up; only `count` of them are kept.
+fn keep_backup(sudoers_path: &Path, contents: &[u8], count: u32) -> io::Result<()> {
+    for number in (1..count).rev() {
+        match std::fs::rename(
+            backup_path(sudoers_path, number),
+            backup_path(sudoers_path, number + 1),
+        ) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+            result => result?,
+        }
     }
 
-    Err(io::Error::new(
-        io::ErrorKind::NotFound,
-        "cannot find text editor",
-    ))
+    let mut backup = File::options()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o440)
+        .open(backup_path(sudoers_path, 1))?;
+    backup.set_permissions(Permissions::from_mode(0o440))?;
+    backup.write_all(contents)
+}
+
+/// What identifies `contents` in the log: its SHA-256 hash in hexadecimal.
+#[cfg(feature = "sudoers-digest")]
+fn digest(contents: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+
+    let hash = Sha256::digest(contents)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    format!("sha256:{hash}")
+}
+
+/// What identifies `contents` in the log: without the `sudoers-digest` feature, only its size.
+#[cfg(not(feature = "sudoers-digest"))]
+fn digest(contents: &[u8]) -> String {
+    format!("{} bytes", contents.len())
+}
+
+/// Log that the sudoers file at `sudoers_path` was changed from `before` to `after`, along with
+/// the user that did so.
+fn record_change(sudoers_path: &Path, before: Option<&Vec<u8>>, after: &[u8]) {
+    let uid = User::real_uid();
+    let user = match User::from_uid(uid) {
+        Ok(Some(user)) => user.name,
+        _ => uid.to_string(),
+    };
+    let before = before.map_or_else(|| "none".to_string(), |contents| digest(contents));
+    let after = digest(after);
+
+    auth_info!(
+        "user {user} installed {}; before: {before}, after: {after}",
+        sudoers_path.display()
+    );
+
+    #[cfg(feature = "linux-audit")]
+    {
+        use crate::system::linux_audit::{send_record, AuditRecord};
+
+        let fields = [
+            ("op", "change-sudoers".as_ref()),
+            ("file", sudoers_path.as_os_str()),
+            ("old", before.as_ref()),
+            ("new", after.as_ref()),
+        ];
+        if let Err(err) = send_record(AuditRecord::UsysConfig, &fields, None, true) {
+            auth_warn!("unable to send a record to the audit subsystem: {err}");
+        }
+    }
+}
+
+/// The settings in sudo.conf, for the cache of the parsed sudoers file and the backups of it.
+fn load_sudo_conf() -> SudoConf {
+    SudoConf::open(SUDO_CONF_PATH)
+        .map(|(conf, _)| conf)
+        .unwrap_or_default()
+}
+
+/// Bring the cache of the parsed sudoers file at `cache` up to date.
+fn update_cache(sudoers_path: &Path, cache: &Path) {
+    if let Ok((sudoers, errors)) = Sudoers::open(sudoers_path) {
+        if errors.is_empty() {
+            sudoers.write_cache(cache);
+        }
+    }
 }
 
 fn create_temporary_dir() -> io::Result<PathBuf> {
@@ -193,7 +193,7 @@ fn warning_is_printed_when_home_directory_does_not_exist() -> Result<()> {
             "su: warning: cannot change directory to /home/{USERNAME}: No such file or directory"
         )
     } else {
-        format!("su: unable to change directory to /home/{USERNAME}: No such file or directory (os error 2)")
+        format!("su: unable to change directory to /home/{USERNAME}: No such file or directory")
     };
 
     assert_contains!(output.stderr(), diagnostic);
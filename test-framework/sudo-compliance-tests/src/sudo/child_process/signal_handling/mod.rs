@@ -37,6 +37,7 @@ dup! {
     signal_is_forwarded_to_child,
     child_terminated_by_signal,
     sigtstp_works,
+    sigstop_and_sigcont_of_command_work,
     sigalrm_terminates_command,
     sigchld_is_ignored,
 }
@@ -154,6 +155,41 @@ fn sigtstp_works(tty: bool) -> Result<()> {
     Ok(())
 }
 
+// the command is stopped by a signal that is not sent through sudo, and continued the same way
+fn sigstop_and_sigcont_of_command_work(tty: bool) -> Result<()> {
+    const STOP_DELAY: u64 = 5;
+    const NUM_ITERATIONS: usize = 5;
+
+    let script_path = "/tmp/script.sh";
+    let env = Env([SUDOERS_ALL_ALL_NOPASSWD, SUDOERS_USE_PTY])
+        .file(script_path, include_str!("sigstop.bash"))
+        .build()?;
+
+    let output = Command::new("bash")
+        .arg(script_path)
+        .tty(tty)
+        .output(&env)?
+        .stdout()?;
+
+    let timestamps = output
+        .lines()
+        .filter_map(|line| line.trim_end().parse::<u64>().ok())
+        .collect::<Vec<_>>();
+
+    dbg!(&timestamps);
+
+    assert_eq!(NUM_ITERATIONS, timestamps.len());
+
+    let stopped_iterations = timestamps
+        .windows(2)
+        .filter(|window| window[1] - window[0] >= STOP_DELAY)
+        .count();
+
+    assert_eq!(1, stopped_iterations);
+
+    Ok(())
+}
+
 fn sigalrm_terminates_command(tty: bool) -> Result<()> {
     let expected = "got signal";
     let expects_signal = "/root/expects-signal.sh";
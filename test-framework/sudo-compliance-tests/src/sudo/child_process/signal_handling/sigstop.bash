@@ -0,0 +1,12 @@
+set -e
+# enable 'job control' to make `fg` work
+set -m
+
+sudo sh -c 'for i in $(seq 1 5); do date +%s; sleep 1; done' &
+sleep 2
+# stop the command instead of sudo; sudo is stopped in turn so the shell sees the job as stopped
+commandpid=$(pgrep -n -f '^sh -c for i')
+kill -STOP "$commandpid"
+sleep 5
+kill -CONT "$commandpid"
+fg
@@ -181,3 +181,75 @@ fn user_does_not_exist() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn sets_supplementary_groups_of_target_user() -> Result<()> {
+    let target_user = "ghost";
+    let env = Env(SUDOERS_ROOT_ALL_NOPASSWD)
+        .group(GROUPNAME)
+        .group("spooky")
+        .user(User(target_user).secondary_group("spooky"))
+        .user(User(USERNAME).secondary_group(GROUPNAME))
+        .build()?;
+
+    let groups = Command::new("sudo")
+        .args(["-u", target_user, "groups"])
+        .output(&env)?
+        .stdout()?;
+    let groups = groups.split_ascii_whitespace().collect::<Vec<_>>();
+
+    assert!(groups.contains(&"spooky"));
+    assert!(!groups.contains(&GROUPNAME));
+    assert!(!groups.contains(&"root"));
+
+    Ok(())
+}
+
+#[test]
+fn preserve_groups_keeps_supplementary_groups_of_invoking_user() -> Result<()> {
+    let target_user = "ghost";
+    let env = Env(SUDOERS_ALL_ALL_NOPASSWD)
+        .group(GROUPNAME)
+        .group("spooky")
+        .user(User(target_user).secondary_group("spooky"))
+        .user(User(USERNAME).secondary_group(GROUPNAME))
+        .build()?;
+
+    let groups = Command::new("sudo")
+        .args(["-P", "-u", target_user, "groups"])
+        .as_user(USERNAME)
+        .output(&env)?
+        .stdout()?;
+    let groups = groups.split_ascii_whitespace().collect::<Vec<_>>();
+
+    assert!(groups.contains(&GROUPNAME));
+    assert!(!groups.contains(&"spooky"));
+
+    Ok(())
+}
+
+#[test]
+fn cannot_regain_privileges() -> Result<()> {
+    let env = Env(SUDOERS_ROOT_ALL_NOPASSWD).user(USERNAME).build()?;
+
+    let ids = Command::new("sudo")
+        .args([
+            "-u",
+            USERNAME,
+            "sh",
+            "-c",
+            "cat /proc/self/status | grep -E '^(Uid|Gid):'",
+        ])
+        .output(&env)?
+        .stdout()?;
+
+    for line in ids.lines() {
+        let mut fields = line.split_ascii_whitespace().skip(1);
+        let real = fields.next().unwrap();
+        // effective, saved and filesystem ids must all match the real id
+        assert!(fields.all(|id| id == real), "{line}");
+        assert_ne!("0", real);
+    }
+
+    Ok(())
+}
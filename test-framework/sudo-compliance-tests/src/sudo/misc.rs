@@ -1,6 +1,6 @@
 use sudo_test::{Command, Env};
 
-use crate::{Result, PANIC_EXIT_CODE, SUDOERS_ALL_ALL_NOPASSWD, USERNAME};
+use crate::{Result, OTHER_USERNAME, PANIC_EXIT_CODE, SUDOERS_ALL_ALL_NOPASSWD, USERNAME};
 
 macro_rules! assert_snapshot {
     ($($tt:tt)*) => {
@@ -130,6 +130,40 @@ fn sudo_binary_is_not_owned_by_root() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn runs_commands_as_another_user_with_file_capabilities() -> Result<()> {
+    if sudo_test::is_original_sudo() {
+        // only sudo-rs can be installed with file capabilities instead of the setuid bit
+        return Ok(());
+    }
+
+    let env = Env(SUDOERS_ALL_ALL_NOPASSWD)
+        .user(USERNAME)
+        .user(OTHER_USERNAME)
+        .build()?;
+
+    Command::new("chmod")
+        .args(["0755", "/usr/bin/sudo"])
+        .output(&env)?
+        .assert_success()?;
+    Command::new("setcap")
+        .args([
+            "cap_chown,cap_dac_override,cap_fowner,cap_kill,cap_setgid,cap_setuid,cap_sys_admin+ep",
+            "/usr/bin/sudo",
+        ])
+        .output(&env)?
+        .assert_success()?;
+
+    let output = Command::new("sudo")
+        .args(["-u", OTHER_USERNAME, "id", "-un"])
+        .as_user(USERNAME)
+        .output(&env)?;
+
+    assert_eq!(OTHER_USERNAME, output.stdout()?);
+
+    Ok(())
+}
+
 #[test]
 fn works_when_invoked_through_a_symlink() -> Result<()> {
     let symlink_path = "/tmp/sudo";
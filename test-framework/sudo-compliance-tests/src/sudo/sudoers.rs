@@ -14,7 +14,11 @@ mod include;
 mod includedir;
 mod run_as;
 mod runas_alias;
+mod runas_check_shell;
+mod runas_default;
 mod secure_path;
+mod set_home;
+mod stay_setuid;
 mod timestamp_timeout;
 mod user_list;
 
@@ -1,6 +1,6 @@
-use sudo_test::{Command, Env};
+use sudo_test::{Command, Env, TextFile, User};
 
-use crate::{helpers, Result, SUDOERS_ALL_ALL_NOPASSWD, SUDO_ENV_DEFAULT_PATH};
+use crate::{helpers, Result, SUDOERS_ALL_ALL_NOPASSWD, SUDO_ENV_DEFAULT_PATH, USERNAME};
 
 const ENV_LIST: crate::EnvList = crate::EnvList::Keep;
 
@@ -248,3 +248,75 @@ fn checks_not_applied() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn xauthority_owned_by_invoking_user_is_preserved() -> Result<()> {
+    let xauthority = format!("/home/{USERNAME}/.Xauthority");
+    let env = Env(SUDOERS_ALL_ALL_NOPASSWD)
+        .user(User(USERNAME).create_home_directory())
+        .file(&xauthority, TextFile("").chown(USERNAME))
+        .build()?;
+
+    let stdout = Command::new("env")
+        .arg(format!("XAUTHORITY={xauthority}"))
+        .args(["sudo", "env"])
+        .as_user(USERNAME)
+        .output(&env)?
+        .stdout()?;
+    let sudo_env = helpers::parse_env_output(&stdout)?;
+
+    assert_eq!(
+        Some(xauthority.as_str()),
+        sudo_env.get("XAUTHORITY").copied()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn xauthority_not_owned_by_invoking_user_is_removed() -> Result<()> {
+    let env = Env(SUDOERS_ALL_ALL_NOPASSWD).user(USERNAME).build()?;
+
+    let stdout = Command::new("env")
+        .arg("XAUTHORITY=/etc/shadow")
+        .args(["sudo", "env"])
+        .as_user(USERNAME)
+        .output(&env)?
+        .stdout()?;
+    let sudo_env = helpers::parse_env_output(&stdout)?;
+
+    if sudo_test::is_original_sudo() {
+        assert_eq!(Some("/etc/shadow"), sudo_env.get("XAUTHORITY").copied());
+    } else {
+        assert_eq!(None, sudo_env.get("XAUTHORITY").copied());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn ssh_auth_sock_can_be_preserved() -> Result<()> {
+    let socket = format!("/home/{USERNAME}/agent.sock");
+    let env = Env([
+        SUDOERS_ALL_ALL_NOPASSWD,
+        "Defaults env_keep += SSH_AUTH_SOCK",
+    ])
+    .user(User(USERNAME).create_home_directory())
+    .file(&socket, TextFile("").chown(USERNAME))
+    .build()?;
+
+    let stdout = Command::new("env")
+        .arg(format!("SSH_AUTH_SOCK={socket}"))
+        .args(["sudo", "env"])
+        .as_user(USERNAME)
+        .output(&env)?
+        .stdout()?;
+    let sudo_env = helpers::parse_env_output(&stdout)?;
+
+    assert_eq!(
+        Some(socket.as_str()),
+        sudo_env.get("SSH_AUTH_SOCK").copied()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use sudo_test::{Command, Env, User};
+
+use crate::{Result, SUDOERS_ALL_ALL_NOPASSWD};
+
+const TARGET_USER: &str = "ghost";
+const INVALID_SHELL: &str = "/usr/sbin/nologin";
+
+#[test]
+fn if_unset_runs_command_as_user_with_shell_not_in_etc_shells() -> Result<()> {
+    let env = Env(SUDOERS_ALL_ALL_NOPASSWD)
+        .user(User(TARGET_USER).shell(INVALID_SHELL))
+        .build()?;
+
+    Command::new("sudo")
+        .args(["-u", TARGET_USER, "true"])
+        .output(&env)?
+        .assert_success()
+}
+
+#[test]
+fn if_set_refuses_to_run_command_as_user_with_shell_not_in_etc_shells() -> Result<()> {
+    let env = Env(format!(
+        "Defaults runas_check_shell\n{SUDOERS_ALL_ALL_NOPASSWD}"
+    ))
+    .user(User(TARGET_USER).shell(INVALID_SHELL))
+    .build()?;
+
+    let output = Command::new("sudo")
+        .args(["-u", TARGET_USER, "true"])
+        .output(&env)?;
+
+    assert!(!output.status().success());
+    assert_contains!(
+        output.stderr(),
+        format!("invalid shell for user {TARGET_USER}: {INVALID_SHELL}")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn if_set_runs_command_as_user_with_shell_in_etc_shells() -> Result<()> {
+    let env = Env(format!(
+        "Defaults runas_check_shell\n{SUDOERS_ALL_ALL_NOPASSWD}"
+    ))
+    .user(User(TARGET_USER).shell("/bin/sh"))
+    .build()?;
+
+    Command::new("sudo")
+        .args(["-u", TARGET_USER, "true"])
+        .output(&env)?
+        .assert_success()
+}
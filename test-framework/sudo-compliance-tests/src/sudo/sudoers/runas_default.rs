@@ -0,0 +1,68 @@
+use sudo_test::{Command, Env, User};
+
+use crate::{Result, USERNAME};
+
+const RUNAS_DEFAULT: &str = "operator";
+
+#[test]
+fn command_runs_as_runas_default_user_when_no_user_is_given() -> Result<()> {
+    let env = Env(format!(
+        "Defaults runas_default={RUNAS_DEFAULT}\nALL ALL=(ALL:ALL) NOPASSWD: ALL"
+    ))
+    .user(RUNAS_DEFAULT)
+    .build()?;
+
+    let stdout = Command::new("sudo")
+        .args(["whoami"])
+        .output(&env)?
+        .stdout()?;
+
+    assert_eq!(RUNAS_DEFAULT, stdout);
+
+    Ok(())
+}
+
+#[test]
+fn rule_without_runas_list_allows_runas_default_user() -> Result<()> {
+    let env = Env(format!(
+        "Defaults runas_default={RUNAS_DEFAULT}\n{USERNAME} ALL=NOPASSWD: /usr/bin/whoami"
+    ))
+    .user(RUNAS_DEFAULT)
+    .user(USERNAME)
+    .build()?;
+
+    let stdout = Command::new("sudo")
+        .arg("whoami")
+        .as_user(USERNAME)
+        .output(&env)?
+        .stdout()?;
+
+    assert_eq!(RUNAS_DEFAULT, stdout);
+
+    let output = Command::new("sudo")
+        .args(["-u", "root", "whoami"])
+        .as_user(USERNAME)
+        .output(&env)?;
+
+    assert!(!output.status().success());
+
+    Ok(())
+}
+
+#[test]
+fn runaspw_asks_for_the_password_of_the_runas_default_user() -> Result<()> {
+    let password = "operator-password";
+    let env = Env(format!(
+        "Defaults runas_default={RUNAS_DEFAULT}, runaspw\n{USERNAME} ALL=(ALL:ALL) ALL"
+    ))
+    .user(User(RUNAS_DEFAULT).password(password))
+    .user(USERNAME)
+    .build()?;
+
+    Command::new("sudo")
+        .args(["-S", "true"])
+        .as_user(USERNAME)
+        .stdin(password)
+        .output(&env)?
+        .assert_success()
+}
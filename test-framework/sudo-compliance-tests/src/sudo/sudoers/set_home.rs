@@ -0,0 +1,76 @@
+use sudo_test::{Command, Env, TextFile};
+
+use crate::{helpers, Result, SUDOERS_ALL_ALL_NOPASSWD};
+
+const CALLER_HOME: &str = "/tmp/caller";
+const KEEP_HOME: &str = "Defaults env_keep += HOME";
+
+fn sudo_home(env: &Env, sudo_args: &[&str]) -> Result<String> {
+    let sudo_abs_path = Command::new("which").arg("sudo").output(env)?.stdout()?;
+    let env_abs_path = Command::new("which").arg("env").output(env)?.stdout()?;
+
+    let stdout = Command::new("env")
+        .arg(format!("HOME={CALLER_HOME}"))
+        .arg(sudo_abs_path)
+        .args(sudo_args)
+        .arg(env_abs_path)
+        .output(env)?
+        .stdout()?;
+
+    let sudo_env = helpers::parse_env_output(&stdout)?;
+
+    Ok(sudo_env["HOME"].to_string())
+}
+
+#[test]
+fn home_in_env_keep_is_preserved() -> Result<()> {
+    let env = Env([SUDOERS_ALL_ALL_NOPASSWD, KEEP_HOME]).build()?;
+
+    assert_eq!(CALLER_HOME, sudo_home(&env, &[])?);
+
+    Ok(())
+}
+
+#[test]
+fn flag_set_home_overrides_env_keep() -> Result<()> {
+    let env = Env([SUDOERS_ALL_ALL_NOPASSWD, KEEP_HOME]).build()?;
+
+    assert_eq!("/root", sudo_home(&env, &["-H"])?);
+    assert_eq!("/root", sudo_home(&env, &["--set-home"])?);
+
+    Ok(())
+}
+
+#[test]
+fn always_set_home_overrides_env_keep() -> Result<()> {
+    let env = Env([
+        SUDOERS_ALL_ALL_NOPASSWD,
+        KEEP_HOME,
+        "Defaults always_set_home",
+    ])
+    .build()?;
+
+    assert_eq!("/root", sudo_home(&env, &[])?);
+
+    Ok(())
+}
+
+#[test]
+fn set_home_only_applies_to_shell() -> Result<()> {
+    let env = Env([SUDOERS_ALL_ALL_NOPASSWD, KEEP_HOME, "Defaults set_home"])
+        .file("/tmp/env.sh", TextFile("#!/bin/sh\nexec env").chmod("755"))
+        .build()?;
+
+    assert_eq!(CALLER_HOME, sudo_home(&env, &[])?);
+
+    let stdout = Command::new("env")
+        .arg(format!("HOME={CALLER_HOME}"))
+        .args(["SHELL=/tmp/env.sh", "sudo", "-s"])
+        .output(&env)?
+        .stdout()?;
+    let sudo_env = helpers::parse_env_output(&stdout)?;
+
+    assert_eq!(Some("/root"), sudo_env.get("HOME").copied());
+
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use sudo_test::{Command, Env};
+
+use crate::{Result, SUDOERS_ALL_ALL_NOPASSWD, USERNAME};
+
+#[test]
+fn if_unset_sets_real_and_effective_user_id() -> Result<()> {
+    let env = Env(SUDOERS_ALL_ALL_NOPASSWD).user(USERNAME).build()?;
+
+    let real_uid = Command::new("sudo")
+        .args(["id", "-r", "-u"])
+        .as_user(USERNAME)
+        .output(&env)?
+        .stdout()?;
+
+    assert_eq!("0", real_uid);
+
+    Ok(())
+}
+
+#[test]
+fn if_set_keeps_real_user_id_of_invoking_user() -> Result<()> {
+    let env = Env(format!("Defaults stay_setuid\n{SUDOERS_ALL_ALL_NOPASSWD}"))
+        .user(USERNAME)
+        .build()?;
+
+    let invoking_uid = Command::new("id")
+        .arg("-u")
+        .as_user(USERNAME)
+        .output(&env)?
+        .stdout()?;
+
+    let real_uid = Command::new("sudo")
+        .args(["id", "-r", "-u"])
+        .as_user(USERNAME)
+        .output(&env)?
+        .stdout()?;
+
+    let effective_uid = Command::new("sudo")
+        .args(["id", "-u"])
+        .as_user(USERNAME)
+        .output(&env)?
+        .stdout()?;
+
+    assert_eq!(invoking_uid, real_uid);
+    assert_eq!("0", effective_uid);
+
+    Ok(())
+}